@@ -13,7 +13,7 @@ pub trait Opcode: Sized {
     /// Returns the opcode byte for this instruction. The low bit is not set.
     fn opcode_byte(&self) -> u8;
     // Returns the name of the opcode.
-    fn opcode_name(&self) -> Cow<str>;
+    fn opcode_name(&self) -> Cow<'_, str>;
 }
 
 /// An instruction set is a collection of opcodes and their corresponding
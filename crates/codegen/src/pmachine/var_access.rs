@@ -96,7 +96,7 @@ impl Opcode for VarAccessOp {
         0x80 | (op_bits << 5) | (use_acc_bit << 4) | (other_type_bits << 3) | (var_type_bits << 1)
     }
 
-    fn opcode_name(&self) -> std::borrow::Cow<str> {
+    fn opcode_name(&self) -> std::borrow::Cow<'_, str> {
         // This uses the scheme from the ScummVM specification.
         let var_type_str = match self.var_type {
             VarType::Global => "g",
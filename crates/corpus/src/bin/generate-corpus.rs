@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use sci_corpus::{Compression, Corpus, CorpusConfig};
+
+/// Writes a synthetic SCI1.1 game directory, for benchmarking and testing.
+#[derive(Parser)]
+struct Args {
+    /// The directory to write the game files to.
+    output: PathBuf,
+    #[clap(long, default_value_t = 1)]
+    seed: u64,
+    #[clap(long, default_value_t = 50)]
+    rooms: u16,
+    #[clap(long, default_value_t = 100)]
+    resources_per_type: u16,
+    /// Store resources uncompressed instead of DCL-compressed.
+    #[clap(long)]
+    uncompressed: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = CorpusConfig {
+        seed: args.seed,
+        rooms: args.rooms,
+        resources_per_type: args.resources_per_type,
+        compression: if args.uncompressed {
+            Compression::None
+        } else {
+            Compression::Dcl
+        },
+        ..CorpusConfig::default()
+    };
+    let corpus = Corpus::generate(&config)?;
+    corpus.write_to(&args.output)?;
+    for (name, contents) in corpus.files() {
+        println!("{name}: {} bytes", contents.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Synthetic game data, for benchmarks and tests that need a realistically
+//! sized game without shipping copyrighted files.
+//!
+//! A [`Corpus`] holds the files of an SCI1.1 game directory: the resource map
+//! and volume (with script and text resources, optionally DCL-compressed),
+//! and the message map and volume. Generation is deterministic for a given
+//! [`CorpusConfig`], so benchmark runs can be compared against each other.
+
+use std::{collections::BTreeMap, io, path::Path};
+
+mod message;
+mod text;
+mod volume;
+
+pub use message::{MessageSpec, build_message_resource};
+pub use sci_utils::rng::Rng;
+pub use text::TextGenerator;
+pub use volume::{Compression, VolumeBuilder};
+
+/// Resource type IDs, as stored in maps and volume entry headers.
+pub mod type_ids {
+    pub const SCRIPT: u8 = 0x82;
+    pub const TEXT: u8 = 0x83;
+    pub const VOCAB: u8 = 0x86;
+    pub const MESSAGE: u8 = 0x8F;
+}
+
+#[derive(Debug, Clone)]
+pub struct CorpusConfig {
+    pub seed: u64,
+    /// The number of rooms, each with a message resource.
+    pub rooms: u16,
+    /// The number of nouns with conversations in each room.
+    pub nouns_per_room: u8,
+    /// The most lines in a conversation.
+    pub max_lines_per_conversation: u8,
+    /// The number of script and of text resources.
+    pub resources_per_type: u16,
+    /// The approximate size of each script and text resource, in bytes.
+    pub resource_size: usize,
+    pub compression: Compression,
+}
+
+impl Default for CorpusConfig {
+    fn default() -> Self {
+        CorpusConfig {
+            seed: 1,
+            rooms: 50,
+            nouns_per_room: 8,
+            max_lines_per_conversation: 4,
+            resources_per_type: 100,
+            resource_size: 4096,
+            compression: Compression::Dcl,
+        }
+    }
+}
+
+/// The files of a synthetic game directory.
+pub struct Corpus {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Corpus {
+    pub fn generate(config: &CorpusConfig) -> anyhow::Result<Self> {
+        let mut rng = Rng::new(config.seed);
+        let text = TextGenerator::new();
+
+        let mut resources = VolumeBuilder::new();
+        for num in 0..config.resources_per_type {
+            resources.add(
+                type_ids::SCRIPT,
+                num,
+                &script_like_data(&mut rng, config.resource_size),
+                config.compression,
+            )?;
+            let mut contents = Vec::new();
+            while contents.len() < config.resource_size {
+                contents.extend_from_slice(text.sentence(&mut rng).as_bytes());
+                contents.push(0);
+            }
+            resources.add(type_ids::TEXT, num, &contents, config.compression)?;
+        }
+        resources.add(
+            type_ids::VOCAB,
+            0,
+            &script_like_data(&mut rng, config.resource_size),
+            config.compression,
+        )?;
+
+        let mut messages = VolumeBuilder::new();
+        for room in 0..config.rooms {
+            let specs = room_messages(&mut rng, &text, config);
+            messages.add(
+                type_ids::MESSAGE,
+                room,
+                &build_message_resource(&specs)?,
+                Compression::None,
+            )?;
+        }
+
+        let (resource_map, resource_volume) = resources.finish()?;
+        let (message_map, message_volume) = messages.finish()?;
+        Ok(Corpus {
+            files: BTreeMap::from([
+                ("RESOURCE.MAP".to_string(), resource_map),
+                ("RESOURCE.000".to_string(), resource_volume),
+                ("MESSAGE.MAP".to_string(), message_map),
+                ("RESOURCE.MSG".to_string(), message_volume),
+            ]),
+        })
+    }
+
+    /// The contents of one of the game's files (e.g. `RESOURCE.MAP`).
+    pub fn file(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(Vec::as_slice)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+    }
+
+    /// Writes the files into a directory, which can then be opened as a game.
+    pub fn write_to(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (name, contents) in &self.files {
+            std::fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytecode-like data: short runs of random bytes mixed with repeats of
+/// earlier spans, so it compresses about as well as real scripts.
+pub fn script_like_data(rng: &mut Rng, size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        if data.len() > 16 && rng.below(3) > 0 {
+            let len = 3 + rng.below(12) as usize;
+            let start = rng.below((data.len() - len.min(data.len())) as u64) as usize;
+            for i in start..(start + len).min(data.len()) {
+                data.push(data[i]);
+            }
+        } else {
+            for _ in 0..1 + rng.below(6) {
+                data.push(rng.next_u64() as u8);
+            }
+        }
+    }
+    data.truncate(size);
+    data
+}
+
+/// The messages of one room: a few conversations for each noun.
+fn room_messages(rng: &mut Rng, text: &TextGenerator, config: &CorpusConfig) -> Vec<MessageSpec> {
+    let mut specs = Vec::new();
+    for noun in 1..=config.nouns_per_room {
+        // Look, talk, and a few others.
+        for verb in [1, 2, 4, 7] {
+            if verb != 1 && rng.below(2) == 0 {
+                continue;
+            }
+            let condition = if rng.below(4) == 0 {
+                rng.below(3) as u8 + 1
+            } else {
+                0
+            };
+            let lines = 1 + rng.below(u64::from(config.max_lines_per_conversation)) as u8;
+            for sequence in 1..=lines {
+                specs.push(MessageSpec {
+                    noun,
+                    verb,
+                    condition,
+                    sequence,
+                    talker: if verb == 1 { 99 } else { rng.below(8) as u8 },
+                    text: text.sentence(rng),
+                });
+            }
+        }
+    }
+    specs
+}
+
+#[cfg(test)]
+mod tests {
+    use sci_resources::{
+        ResourceId, ResourceType, file::open_game_resources, types::msg::parse_message_resource,
+    };
+
+    use super::*;
+
+    fn small_config(compression: Compression) -> CorpusConfig {
+        CorpusConfig {
+            rooms: 3,
+            resources_per_type: 4,
+            resource_size: 1000,
+            compression,
+            ..CorpusConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_generation_is_deterministic() -> anyhow::Result<()> {
+        let config = small_config(Compression::Dcl);
+        let first = Corpus::generate(&config)?;
+        let second = Corpus::generate(&config)?;
+        assert!(first.files().eq(second.files()));
+        let other = Corpus::generate(&CorpusConfig { seed: 2, ..config })?;
+        assert!(!first.files().eq(other.files()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_corpus_opens_as_game() -> anyhow::Result<()> {
+        for compression in [Compression::None, Compression::Dcl] {
+            let dir = tempfile::tempdir()?;
+            Corpus::generate(&small_config(compression))?.write_to(dir.path())?;
+            let resources = open_game_resources(dir.path())?;
+            assert_eq!(resources.resource_ids().count(), 4 + 4 + 1 + 3);
+
+            let script = resources
+                .get_resource(&ResourceId::new(ResourceType::Script, 2))
+                .unwrap();
+            assert_eq!(script.load_data()?.size(), 1000);
+            let messages = resources
+                .get_resource(&ResourceId::new(ResourceType::Message, 1))
+                .unwrap();
+            let messages = parse_message_resource(messages.load_data()?)?;
+            assert!(messages.messages().count() >= 8);
+            assert!(
+                messages
+                    .messages()
+                    .all(|(_, record)| record.text().ends_with('.'))
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+/// A message to put in a generated message resource.
+#[derive(Debug, Clone)]
+pub struct MessageSpec {
+    pub noun: u8,
+    pub verb: u8,
+    pub condition: u8,
+    pub sequence: u8,
+    pub talker: u8,
+    /// The text, which must be ASCII.
+    pub text: String,
+}
+
+/// The size of the version and header before the message entries.
+const HEADER_SIZE: usize = 4 + 6;
+const ENTRY_SIZE: usize = 11;
+
+/// Builds a version 4 (SCI1.1) message resource, little-endian.
+pub fn build_message_resource(messages: &[MessageSpec]) -> anyhow::Result<Vec<u8>> {
+    let mut messages: Vec<&MessageSpec> = messages.iter().collect();
+    messages.sort_by_key(|m| (m.noun, m.verb, m.condition, m.sequence));
+    let text_start = HEADER_SIZE + messages.len() * ENTRY_SIZE;
+    let mut data = Vec::with_capacity(text_start);
+    data.extend_from_slice(&4000u32.to_le_bytes());
+    // The end offset is filled in once the text is written.
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&u16::try_from(messages.len())?.to_le_bytes());
+
+    let mut text_offset = text_start;
+    for message in &messages {
+        anyhow::ensure!(message.text.is_ascii(), "Message text must be ASCII");
+        data.extend_from_slice(&[
+            message.noun,
+            message.verb,
+            message.condition,
+            message.sequence,
+            message.talker,
+        ]);
+        data.extend_from_slice(&u16::try_from(text_offset)?.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        text_offset += message.text.len() + 1;
+    }
+    for message in &messages {
+        data.extend_from_slice(message.text.as_bytes());
+        data.push(0);
+    }
+    // The end offset is relative to the start of the header fields.
+    let end_offset = u16::try_from(data.len() - 6)?;
+    data[4..6].copy_from_slice(&end_offset.to_le_bytes());
+    Ok(data)
+}
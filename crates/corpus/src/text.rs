@@ -0,0 +1,64 @@
+use sci_utils::rng::Rng;
+
+const SYLLABLES: &[&str] = &[
+    "ka", "ro", "the", "an", "mi", "sel", "dor", "ven", "ta", "li", "gor", "un", "es", "wy", "pa",
+    "ith",
+];
+
+const COMMON_WORDS: &[&str] = &[
+    "the", "a", "you", "I", "is", "of", "to", "and", "it", "that", "here", "there", "not", "can",
+    "see", "look", "this",
+];
+
+/// Generates text that looks enough like dialogue for parsers and the book
+/// builder: sentences of short words, with common words repeated often.
+pub struct TextGenerator {
+    vocabulary: Vec<String>,
+}
+
+impl TextGenerator {
+    pub fn new() -> Self {
+        let mut rng = Rng::new(0);
+        let mut vocabulary: Vec<String> = COMMON_WORDS.iter().map(|w| w.to_string()).collect();
+        for _ in 0..200 {
+            let syllables = 1 + rng.below(3);
+            vocabulary.push(
+                (0..syllables)
+                    .map(|_| *rng.choose(SYLLABLES))
+                    .collect::<String>(),
+            );
+        }
+        TextGenerator { vocabulary }
+    }
+
+    pub fn sentence(&self, rng: &mut Rng) -> String {
+        let words = 3 + rng.below(12);
+        let mut sentence = String::new();
+        for i in 0..words {
+            // Common words are used half the time, as in real dialogue.
+            let word = if rng.below(2) == 0 {
+                rng.choose(COMMON_WORDS).to_string()
+            } else {
+                rng.choose(&self.vocabulary).clone()
+            };
+            if i == 0 {
+                let mut chars = word.chars();
+                if let Some(first) = chars.next() {
+                    sentence.extend(first.to_uppercase());
+                    sentence.push_str(chars.as_str());
+                }
+            } else {
+                sentence.push(' ');
+                sentence.push_str(&word);
+            }
+        }
+        sentence.push('.');
+        sentence
+    }
+}
+
+impl Default for TextGenerator {
+    fn default() -> Self {
+        TextGenerator::new()
+    }
+}
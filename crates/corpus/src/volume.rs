@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+
+use sci_utils::compression::dcl::compress_dcl;
+
+/// How resource data is stored in a volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// DCL (PKWARE implode), compression type 18.
+    Dcl,
+}
+
+impl Compression {
+    fn type_id(self) -> u16 {
+        match self {
+            Compression::None => 0,
+            Compression::Dcl => 18,
+        }
+    }
+}
+
+/// Builds an SCI1.1 resource volume and the map that indexes it.
+pub struct VolumeBuilder {
+    volume: Vec<u8>,
+    /// The offset of each resource in the volume, by type and number.
+    locations: BTreeMap<u8, BTreeMap<u16, u32>>,
+}
+
+impl VolumeBuilder {
+    pub fn new() -> Self {
+        VolumeBuilder {
+            volume: Vec::new(),
+            locations: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        type_id: u8,
+        num: u16,
+        data: &[u8],
+        compression: Compression,
+    ) -> anyhow::Result<()> {
+        let packed = match compression {
+            Compression::None => data.to_vec(),
+            Compression::Dcl => compress_dcl(data),
+        };
+        // Offsets are stored halved in the map, so entries must be aligned.
+        if self.volume.len() % 2 == 1 {
+            self.volume.push(0);
+        }
+        let offset = u32::try_from(self.volume.len())?;
+        anyhow::ensure!(offset >> 1 < 0xFF_FFFF, "The volume is too large");
+        self.volume.push(type_id);
+        self.volume.extend_from_slice(&num.to_le_bytes());
+        self.volume
+            .extend_from_slice(&u16::try_from(packed.len())?.to_le_bytes());
+        self.volume
+            .extend_from_slice(&u16::try_from(data.len())?.to_le_bytes());
+        self.volume
+            .extend_from_slice(&compression.type_id().to_le_bytes());
+        self.volume.extend_from_slice(&packed);
+        self.locations
+            .entry(type_id)
+            .or_default()
+            .insert(num, offset);
+        Ok(())
+    }
+
+    /// Returns the map and the volume.
+    pub fn finish(self) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        // The index has an entry per type, then a terminator giving the end
+        // of the last type's entries.
+        let mut index = Vec::new();
+        let mut entries = Vec::new();
+        let entries_start = (self.locations.len() + 1) * 3;
+        for (type_id, locations) in &self.locations {
+            index.push(*type_id);
+            index.extend_from_slice(&u16::try_from(entries_start + entries.len())?.to_le_bytes());
+            for (num, offset) in locations {
+                entries.extend_from_slice(&num.to_le_bytes());
+                entries.extend_from_slice(&(offset >> 1).to_le_bytes()[..3]);
+            }
+        }
+        index.push(0xFF);
+        index.extend_from_slice(&u16::try_from(entries_start + entries.len())?.to_le_bytes());
+        index.extend_from_slice(&entries);
+        Ok((index, self.volume))
+    }
+}
+
+impl Default for VolumeBuilder {
+    fn default() -> Self {
+        VolumeBuilder::new()
+    }
+}
@@ -0,0 +1,149 @@
+//! Lines the dub adds to the game, such as accessibility narration.
+//!
+//! Added lines are defined in the book config, and listed in a message export
+//! (`scitool msg export --config`) with `"added": true`. The build adds them
+//! to the game's message resources and writes those as patches next to the
+//! audio, so the game has text for the new lines.
+
+use std::{collections::BTreeMap, path::Path};
+
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::{Resource, ResourceSet},
+    types::msg::{MessageId, MessageRecord, parse_message_resource},
+};
+use sci_utils::block::{LazyBlock, MemBlock};
+use serde::Deserialize;
+
+use crate::resources::{GameLine, sample_key};
+
+/// A line that isn't in the original game.
+#[derive(Debug, Clone)]
+pub struct AddedLine {
+    pub room: u16,
+    pub message_id: MessageId,
+    pub talker: u8,
+    pub text: String,
+}
+
+impl AddedLine {
+    /// The line as if it were one of the game's, for placeholders and
+    /// coverage.
+    pub fn to_game_line(&self) -> GameLine {
+        GameLine {
+            room: self.room,
+            message_id: self.message_id,
+            text: self.text.clone(),
+        }
+    }
+}
+
+// The parts of a message export that are needed here.
+
+#[derive(Deserialize)]
+struct ExportedId {
+    room: u16,
+    noun: u8,
+    verb: u8,
+    condition: u8,
+    sequence: u8,
+}
+
+#[derive(Deserialize)]
+struct ExportedMessage {
+    id: ExportedId,
+    talker: u8,
+    text: String,
+    #[serde(default)]
+    added: bool,
+}
+
+#[derive(Deserialize)]
+struct ExportedFile {
+    messages: Vec<ExportedMessage>,
+}
+
+/// Reads the added lines from a message export, skipping the game's own
+/// lines.
+pub fn read_added_lines(path: &Path) -> anyhow::Result<Vec<AddedLine>> {
+    parse_added_lines(&std::fs::read(path)?)
+}
+
+fn parse_added_lines(contents: &[u8]) -> anyhow::Result<Vec<AddedLine>> {
+    let file: ExportedFile = serde_json::from_slice(contents)?;
+    Ok(file
+        .messages
+        .into_iter()
+        .filter(|message| message.added)
+        .map(|message| AddedLine {
+            room: message.id.room,
+            message_id: MessageId::new(
+                message.id.noun,
+                message.id.verb,
+                message.id.condition,
+                message.id.sequence,
+            ),
+            talker: message.talker,
+            text: message.text,
+        })
+        .collect())
+}
+
+/// Adds the lines to the game's message resources, returning a patch for
+/// each room with added lines.
+pub fn message_patches(
+    resources: &ResourceSet,
+    lines: &[AddedLine],
+) -> anyhow::Result<Vec<Resource>> {
+    let mut rooms: BTreeMap<u16, Vec<&AddedLine>> = BTreeMap::new();
+    for line in lines {
+        rooms.entry(line.room).or_default().push(line);
+    }
+    let mut patches = Vec::new();
+    for (room, lines) in rooms {
+        let id = ResourceId::new(ResourceType::Message, room);
+        let resource = resources.get_resource(&id).ok_or_else(|| {
+            anyhow::anyhow!("Room {room} has no message resource to add lines to")
+        })?;
+        let mut messages = parse_message_resource(resource.load_data()?)?;
+        for line in lines {
+            anyhow::ensure!(
+                messages.get(&line.message_id).is_none(),
+                "Added line {} is already in the game; export the messages again",
+                sample_key(room, &line.message_id)
+            );
+            messages.insert(
+                line.message_id,
+                MessageRecord::new(line.talker, line.text.clone()),
+            );
+        }
+        patches.push(Resource::new(
+            id,
+            LazyBlock::from_mem_block(MemBlock::from_vec(messages.to_bytes(None)?)),
+        ));
+    }
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_added_lines() -> anyhow::Result<()> {
+        let lines = parse_added_lines(
+            br#"{"messages": [
+                {"id": {"room": 10, "noun": 1, "verb": 2, "condition": 0, "sequence": 1},
+                 "talker": 7, "text": "From the game."},
+                {"id": {"room": 10, "noun": 1, "verb": 2, "condition": 0, "sequence": 2},
+                 "talker": 99, "text": "Added.", "added": true}
+            ]}"#,
+        )?;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].room, 10);
+        assert_eq!(lines[0].message_id, MessageId::new(1, 2, 0, 2));
+        assert_eq!(lines[0].talker, 99);
+        assert_eq!(lines[0].text, "Added.");
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! An archive of the selected takes in a high quality format, kept alongside
+//! the lossy game-format build so the masters are preserved.
+//!
+//! The archive has one directory per room, with a file per line named by its
+//! message ID (`<noun>-<verb>-<condition>-<sequence>`), and a `manifest.json`
+//! recording where each file came from.
+
+use std::path::{Path, PathBuf};
+
+use sci_resources::types::msg::MessageId;
+use serde::Serialize;
+
+use crate::{
+    profile::{ConversionProfile, ProfileFormat},
+    resources::{Sample, SampleDir},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::ffmpeg::{self, FfmpegTool},
+};
+
+#[derive(Serialize, Debug)]
+struct ArchiveEntry {
+    room: u16,
+    message_id: MessageId,
+    /// The archived file, relative to the archive directory.
+    file: PathBuf,
+    /// The recording the take was cut from, relative to the sample directory.
+    source: PathBuf,
+    start_us: Option<u64>,
+    end_us: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+struct ArchiveManifest {
+    profile: String,
+    entries: Vec<ArchiveEntry>,
+}
+
+fn archive_file(sample: &Sample, profile: &ConversionProfile) -> PathBuf {
+    let id = &sample.message_id;
+    Path::new(&sample.room.to_string()).join(format!(
+        "{}-{}-{}-{}.{}",
+        id.noun(),
+        id.verb(),
+        id.condition(),
+        id.sequence(),
+        profile.extension()
+    ))
+}
+
+/// Checks that the profile can be used for the archive.
+pub fn check_archive_profile(
+    profile_name: &str,
+    profile: &ConversionProfile,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        profile.format != ProfileFormat::Sol,
+        "Profile {profile_name:?} uses SOL, which can't be used for the archive"
+    );
+    Ok(())
+}
+
+/// Writes the trimmed takes of all samples to the archive directory, converted
+/// with the given profile, along with a manifest.
+pub async fn write_archive(
+    sample_dir: &SampleDir,
+    archive_dir: &Path,
+    profile_name: &str,
+    profile: &ConversionProfile,
+    ffmpeg: &FfmpegTool,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<()> {
+    check_archive_profile(profile_name, profile)?;
+    let cancel = scheduler.cancellation();
+    let jobs = sample_dir.takes().map(|sample| {
+        let job = move || async move {
+            let file = archive_file(sample, profile);
+            let filters = [sample.clip.trim_filter(), profile.audio_filter()];
+            let audio_filter = filters.into_iter().flatten().collect::<Vec<_>>().join(",");
+            let data = ffmpeg
+                .convert_with_filter(
+                    sample.clip_path(sample_dir.base_path())?,
+                    ffmpeg::VecOutput,
+                    profile.output_format(),
+                    (!audio_filter.is_empty()).then_some(audio_filter.as_str()),
+                    &mut ffmpeg::NullProgressListener,
+                    cancel,
+                )
+                .await?;
+            let path = archive_dir.join(&file);
+            smol::fs::create_dir_all(path.parent().unwrap()).await?;
+            smol::fs::write(&path, data).await?;
+            Ok::<_, anyhow::Error>(ArchiveEntry {
+                room: sample.room,
+                message_id: sample.message_id,
+                file,
+                source: sample.clip.path.clone(),
+                start_us: sample.clip.start_us,
+                end_us: sample.clip.end_us,
+            })
+        };
+        (sample.key(), job)
+    });
+
+    let mut entries = Vec::new();
+    let report = scheduler
+        .run(jobs, async |entry: ArchiveEntry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    entries.sort_by_key(|entry| (entry.room, entry.message_id));
+    let manifest = ArchiveManifest {
+        profile: profile_name.to_string(),
+        entries,
+    };
+    std::fs::write(
+        archive_dir.join("manifest.json"),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    Ok(())
+}
@@ -1,20 +1,40 @@
-use std::path::PathBuf;
+use std::{collections::BTreeSet, path::PathBuf, time::Duration};
 
 use clap::Parser;
-use futures::FutureExt;
-use futures::stream::{FuturesUnordered, TryStreamExt};
-use scitool_fan_dub_cli::{path::LookupPath, tools::ffmpeg};
-
-async fn execute_all<F>(futures: impl IntoIterator<Item = F>) -> anyhow::Result<()>
-where
-    F: futures::Future<Output = anyhow::Result<()>> + Unpin,
-{
-    let mut fut_unordered = FuturesUnordered::from_iter(futures);
-    while let Some(()) = fut_unordered.try_next().await? {
-        // Do nothing
-    }
-    Ok(())
-}
+use sci_resources::{
+    ResourceType,
+    types::{
+        msg::MessageId,
+        sync36::{patch_file_name, read_sync36_patch, write_sync36_patch},
+    },
+};
+use sci_utils::fs;
+use scitool_fan_dub_cli::{
+    added::read_added_lines,
+    blind::{random_seed, take_pairs, tts_pairs, write_blind_test},
+    build::{BuildSettings, BuildTarget, build_audio},
+    cancel::{CancellationToken, cancel_on_ctrl_c},
+    cleanup::CleanupPresets,
+    config::FanDubConfig,
+    cues::export_cues,
+    daw::{ConversationFilter, export_session, import_session},
+    fingerprint::{BuildFingerprints, Drift, find_duplicates, fingerprint_samples},
+    gate::run_gate,
+    lipsync::{VisemeTable, read_phoneme_file, to_sync_cues},
+    one_take::{LineSource, SilenceOptions, import_one_take},
+    path::LookupPath,
+    placeholder::{CommandSpeech, SpeechBackend},
+    playtest::{PlaytestLog, parse_message_trace},
+    preview::{MouthLoop, render_sync_preview},
+    release::{ReleaseManifest, package_name, package_release, verify_install},
+    rename::{apply_renames, plan_renames, undo_renames},
+    render::render_room,
+    resources::{OriginalAudio, SampleDir, game_lines, parse_sample_key},
+    scheduler::BatchScheduler,
+    signing,
+    tone::analyze_tone,
+    tools::{ffmpeg, ffprobe::FfprobeTool, speech::SpeechTool},
+};
 
 #[derive(Parser)]
 struct Cli {
@@ -24,8 +44,37 @@ struct Cli {
 
 #[derive(clap::Subcommand)]
 enum Cmd {
-    #[clap(name = "compile-audio")]
+    #[clap(name = "blind-test")]
+    BlindTest(BlindTest),
+    #[clap(name = "compile-audio", alias = "build")]
     CompileAudio(CompileAudio),
+    #[clap(name = "export-cues")]
+    ExportCues(ExportCues),
+    #[clap(name = "export-daw")]
+    ExportDaw(ExportDaw),
+    Gate(Gate),
+    #[clap(name = "gen-signing-key")]
+    GenSigningKey(GenSigningKey),
+    #[clap(name = "import-daw")]
+    ImportDaw(ImportDaw),
+    #[clap(name = "import-one-take")]
+    ImportOneTake(ImportOneTake),
+    #[clap(name = "import-sync")]
+    ImportSync(ImportSync),
+    #[clap(name = "import-trace")]
+    ImportTrace(ImportTrace),
+    Lock(Lock),
+    Package(Package),
+    #[clap(name = "preview-sync")]
+    PreviewSync(PreviewSync),
+    Rename(Rename),
+    #[clap(name = "render-room")]
+    RenderRoom(RenderRoom),
+    Status(Status),
+    #[clap(name = "tone-report")]
+    ToneReport(ToneReport),
+    #[clap(name = "verify-install")]
+    VerifyInstall(VerifyInstall),
 }
 
 #[derive(Parser)]
@@ -35,48 +84,1224 @@ struct CompileAudio {
 
     #[clap(short = 'o', long)]
     output: PathBuf,
+
+    /// Resume an interrupted build, reusing the samples it already converted.
+    #[clap(long)]
+    resume: bool,
+
+    /// How many times to retry a sample whose conversion fails.
+    #[clap(long, default_value_t = 2)]
+    retries: u32,
+
+    /// Where to write output files that are locked (e.g. by a running game).
+    #[clap(long)]
+    staging_dir: Option<PathBuf>,
+
+    /// The conversion profile to use. Besides the built-in profiles
+    /// (game-ogg, sci11-speech, hq-archive, match-game), profiles can be
+    /// defined in the sample directory's fan-dub.json. Defaults to
+    /// match-game, which matches the format of the game's clips, if the game
+    /// directory is given, and to game-ogg otherwise.
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// Also write the trimmed takes to this directory, in a high quality
+    /// format, so the masters are kept independent of the game format.
+    #[clap(long)]
+    archive: Option<PathBuf>,
+
+    /// The conversion profile for the archive.
+    #[clap(long, default_value = "hq-archive")]
+    archive_profile: String,
+
+    /// The original game directory. Required for samples that match the
+    /// loudness of the original recording.
+    #[clap(long)]
+    game_dir: Option<PathBuf>,
+
+    /// Fill lines that haven't been recorded with generated speech, so the
+    /// dub can be played through. Needs the game directory.
+    #[clap(long, requires = "game_dir")]
+    placeholders: bool,
+
+    /// Only override recorded lines, keeping the original audio for the
+    /// rest, and write a checklist for testing the build in the game. Needs
+    /// the game directory.
+    #[clap(long, requires = "game_dir", conflicts_with = "placeholders")]
+    partial: bool,
+
+    /// A message export (`scitool msg export --config`) listing lines the
+    /// dub adds to the game. Their text is written as message patches.
+    /// Needs the game directory.
+    #[clap(long, requires = "game_dir")]
+    added_lines: Option<PathBuf>,
+
+    /// Make the build reproducible: the same samples give bit-identical
+    /// files, with a fixed modification time (`SOURCE_DATE_EPOCH`, or the
+    /// Unix epoch).
+    #[clap(long)]
+    deterministic: bool,
+
+    /// Give speech to a game that shipped without it (e.g. a floppy
+    /// release): write a complete set of audio maps keyed by the game's
+    /// messages, and `speech-setup.md`, listing the interpreter changes
+    /// needed. Needs the game directory.
+    #[clap(long, requires = "game_dir", conflicts_with = "partial")]
+    new_speech: bool,
+
+    /// The interpreter the build is for.
+    #[clap(long, value_enum, default_value_t = BuildTarget::Sierra)]
+    target: BuildTarget,
+
+    /// For `--target scummvm`: rescale the lip sync cues of lines whose
+    /// duration changed, so their subtitles in "text and speech" mode stay up
+    /// as long as the new clips play, and list those lines in
+    /// `subtitle-timing.json`. Needs the game directory.
+    #[clap(long, requires = "game_dir", conflicts_with = "new_speech")]
+    subtitle_timing: bool,
 }
 
 impl CompileAudio {
     pub async fn run(&self) -> anyhow::Result<()> {
         let system_path = LookupPath::from_env();
         eprintln!("System PATH: {:?}", system_path.find_binary("ffmpeg"));
+        let ffmpeg_path = system_path
+            .find_binary("ffmpeg")
+            .expect("ffmpeg not found in PATH")
+            .to_path_buf();
+        let settings = BuildSettings {
+            resume: self.resume,
+            retries: self.retries,
+            staging_dir: self.staging_dir.clone(),
+            profile: self.profile.clone(),
+            archive: self.archive.clone(),
+            archive_profile: self.archive_profile.clone(),
+            game_dir: self.game_dir.clone(),
+            placeholders: self.placeholders,
+            partial: self.partial,
+            added_lines: self
+                .added_lines
+                .as_deref()
+                .map(read_added_lines)
+                .transpose()?
+                .unwrap_or_default(),
+            deterministic: self.deterministic,
+            new_speech: self.new_speech,
+            target: self.target,
+            subtitle_timing: self.subtitle_timing,
+            ..BuildSettings::new(self.sample_dir.clone(), self.output.clone())
+        };
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        build_audio(&settings, ffmpeg_path, cancel, &|message| {
+            eprintln!("{message}")
+        })
+        .await
+    }
+}
+
+/// Locks lines whose takes are approved, so importers don't add takes for
+/// them or change their selection without `--force`. Without lines or a room,
+/// lists the locked lines.
+#[derive(Parser)]
+struct Lock {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The lines to lock, by key (e.g. `10-1-2-0-1`).
+    lines: Vec<String>,
+
+    /// Lock every line in the room that has a take.
+    #[clap(long)]
+    room: Option<u16>,
+
+    /// Unlock the lines instead.
+    #[clap(long)]
+    unlock: bool,
+}
+
+impl Lock {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let mut lines = self
+            .lines
+            .iter()
+            .map(|key| {
+                parse_sample_key(key).ok_or_else(|| anyhow::anyhow!("Not a line key: {key}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if let Some(room) = self.room {
+            lines.extend(
+                sample_dir
+                    .takes()
+                    .filter(|sample| sample.room == room)
+                    .map(|sample| (sample.room, sample.message_id)),
+            );
+        }
+        lines.sort();
+        lines.dedup();
+        if lines.is_empty() {
+            for sample in sample_dir.takes().filter(|sample| sample.locked) {
+                println!("{}", sample.key());
+            }
+            return Ok(());
+        }
+        for (room, message_id) in &lines {
+            sample_dir.set_locked(*room, message_id, !self.unlock)?;
+        }
+        sample_dir.save().await?;
+        eprintln!(
+            "{} {} lines",
+            if self.unlock { "Unlocked" } else { "Locked" },
+            lines.len()
+        );
+        Ok(())
+    }
+}
+
+/// Moves takes into a directory per line, named `<key>/<key>_take<n>.<ext>`,
+/// updating `samples.json` to match. Without `--apply`, only lists the moves.
+#[derive(Parser)]
+struct Rename {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// Make the moves, recording them so they can be undone.
+    #[clap(long)]
+    apply: bool,
+
+    /// Undo the latest applied rename.
+    #[clap(long, conflicts_with = "apply")]
+    undo: bool,
+}
+
+impl Rename {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        if self.undo {
+            match undo_renames(&mut sample_dir).await? {
+                Some(undone) => eprintln!("Moved {} takes back", undone.len()),
+                None => eprintln!("No renames to undo"),
+            }
+            return Ok(());
+        }
+        let moves = plan_renames(&sample_dir)?;
+        if moves.is_empty() {
+            eprintln!("All takes are already in place");
+            return Ok(());
+        }
+        for take_move in &moves {
+            println!("{} -> {}", take_move.from.display(), take_move.to.display());
+        }
+        if self.apply {
+            let count = moves.len();
+            let journal = apply_renames(&mut sample_dir, moves).await?;
+            eprintln!(
+                "Moved {count} takes; undo with --undo (journal: {})",
+                journal.display()
+            );
+        } else {
+            eprintln!(
+                "{} takes to move; run with --apply to move them",
+                moves.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Renders all dubbed lines in a room into one audio file, so the room can be
+/// reviewed end-to-end.
+#[derive(Parser)]
+struct RenderRoom {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The room number to render.
+    room: u16,
+
+    /// The output file. Defaults to `room-<room>.ogg`.
+    #[clap(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+impl RenderRoom {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
         let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
             system_path
                 .find_binary("ffmpeg")
-                .expect("ffmpeg not found in PATH")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
                 .to_path_buf(),
         );
-        let sample_dir =
-            scitool_fan_dub_cli::resources::SampleDir::load_dir(&self.sample_dir).await?;
-        let resources = sample_dir.to_audio_resources(&ffmpeg_tool, 4).await?;
-
-        let output_dir = &self.output;
-
-        futures::try_join!(
-            async {
-                let resource_aud_file =
-                    smol::fs::File::create(output_dir.join("resource.aud")).await?;
-                resources
-                    .audio_volume()
-                    .write_to_async(resource_aud_file)
-                    .await?;
-                Ok::<_, anyhow::Error>(())
-            },
-            execute_all(resources.map_resources().iter().map(|res| {
-                async move {
-                    let file = PathBuf::from(format!(
-                        "{}.{}",
-                        res.id().resource_num(),
-                        res.id().type_id().to_file_ext()
-                    ));
-                    let open_file = smol::fs::File::create(output_dir.join(&file)).await?;
-                    res.write_patch(open_file).await?;
-                    Ok::<_, anyhow::Error>(())
-                }
-                .boxed()
-            }))
+        let speech_tool = SpeechTool::find(&system_path);
+        if speech_tool.is_none() {
+            eprintln!("No text-to-speech program found; conversations will be marked by a beep.");
+        }
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("room-{}.ogg", self.room)));
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        render_room(
+            &ffmpeg_tool,
+            speech_tool.as_ref(),
+            &sample_dir,
+            self.room,
+            &output,
+            &cancel,
+        )
+        .await?;
+        eprintln!("Wrote {}", output.display());
+        Ok(())
+    }
+}
+
+/// Writes a blind A/B listening test: shuffled, anonymized pairs of takes
+/// of the same line (or of a line's selected take and a text-to-speech
+/// reading), with a ballot and an answer key.
+#[derive(Parser)]
+struct BlindTest {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// Only compare lines in these rooms.
+    #[clap(long)]
+    room: Vec<u16>,
+
+    /// Only compare these lines, by key (e.g. 10-1-2-0-1).
+    #[clap(long)]
+    line: Vec<String>,
+
+    /// Compare each selected take with a text-to-speech reading of its line,
+    /// rather than takes with each other.
+    #[clap(long, requires = "game_dir")]
+    tts: bool,
+
+    /// The original game directory, for the lines' text.
+    #[clap(long)]
+    game_dir: Option<PathBuf>,
+
+    /// The seed to shuffle the pairs with. Defaults to a random one, which
+    /// is recorded in the answer key.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// The directory to write to.
+    #[clap(short = 'o', long, default_value = "blind-test")]
+    output: PathBuf,
+}
+
+impl BlindTest {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        for key in &self.line {
+            anyhow::ensure!(
+                parse_sample_key(key).is_some(),
+                "Invalid line key {key:?}; expected room-noun-verb-condition-sequence"
+            );
+        }
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let rooms: BTreeSet<u16> = self.room.iter().copied().collect();
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let scratch_dir = tempfile::tempdir()?;
+        let pairs = match &self.game_dir {
+            Some(game_dir) if self.tts => {
+                let config = FanDubConfig::load(&self.sample_dir)?;
+                let backend: Box<dyn SpeechBackend> = match &config.placeholders {
+                    Some(placeholder_config) => {
+                        Box::new(CommandSpeech::from_config(placeholder_config)?)
+                    }
+                    None => Box::new(SpeechTool::find(&system_path).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "No text-to-speech program found; configure one in fan-dub.json"
+                        )
+                    })?),
+                };
+                tts_pairs(
+                    &sample_dir,
+                    &game_lines(game_dir)?,
+                    &rooms,
+                    &self.line,
+                    backend.as_ref(),
+                    scratch_dir.path(),
+                    &cancel,
+                )
+                .await?
+            }
+            _ => take_pairs(&sample_dir, &rooms, &self.line)?,
+        };
+        anyhow::ensure!(
+            !pairs.is_empty(),
+            if self.tts {
+                "No selected takes to compare"
+            } else {
+                "No lines with two takes to compare"
+            }
+        );
+        let seed = match self.seed {
+            Some(seed) => seed,
+            None => random_seed()?,
+        };
+        let answer_key = write_blind_test(pairs, seed, &self.output, &ffmpeg_tool, &cancel).await?;
+        eprintln!(
+            "Wrote {} pairs to {}. Keep answer-key.json from the listeners.",
+            answer_key.pairs.len(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
+
+/// Exports each dubbed conversation as a WAV file, with a CUE sheet and a
+/// CMX 3600 EDL placing its lines, for video editors. The gaps between lines
+/// are set in `fan-dub.json`.
+#[derive(Parser)]
+struct ExportCues {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// Only export the conversations in this room.
+    #[clap(long)]
+    room: Option<u16>,
+
+    /// The directory to write to.
+    #[clap(short = 'o', long, default_value = "cues")]
+    output: PathBuf,
+}
+
+impl ExportCues {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let config = FanDubConfig::load(&self.sample_dir)?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let sheets = export_cues(
+            &sample_dir,
+            self.room,
+            &config.cues,
+            &self.output,
+            &ffmpeg_tool,
+            &cancel,
+        )
+        .await?;
+        eprintln!(
+            "Wrote cue sheets for {} conversations to {}",
+            sheets.len(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
+
+/// Exports a conversation's original clips and selected takes as a Reaper
+/// project, with a region per line, for fine editing in a DAW.
+#[derive(Parser)]
+struct ExportDaw {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The original game directory.
+    #[clap(long)]
+    game_dir: PathBuf,
+
+    /// The room of the conversation.
+    room: u16,
+
+    /// Only export lines with this noun.
+    #[clap(long)]
+    noun: Option<u8>,
+
+    /// Only export lines with this verb.
+    #[clap(long)]
+    verb: Option<u8>,
+
+    /// Only export lines with this condition.
+    #[clap(long)]
+    condition: Option<u8>,
+
+    /// The project directory. Defaults to the conversation's name (e.g.
+    /// `room-10-n1`).
+    #[clap(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+impl ExportDaw {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let original_audio = OriginalAudio::open(&self.game_dir)?;
+        let filter = ConversationFilter {
+            room: self.room,
+            noun: self.noun,
+            verb: self.verb,
+            condition: self.condition,
+        };
+        let project_dir = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(filter.name()));
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let scheduler = BatchScheduler::new(4, cancel);
+        let session = export_session(
+            &sample_dir,
+            &original_audio,
+            &filter,
+            &project_dir,
+            &ffmpeg_tool,
+            &scheduler,
+        )
+        .await?;
+        let dubbed = session
+            .lines
+            .iter()
+            .filter(|line| line.dub.is_some())
+            .count();
+        eprintln!(
+            "Wrote {} ({} lines, {} dubbed)",
+            project_dir.join(session.rpp_file_name()).display(),
+            session.lines.len(),
+            dubbed
+        );
+        Ok(())
+    }
+}
+
+/// Checks every selected take against the quality thresholds in
+/// `fan-dub.json`, exiting with an error if any take fails. Meant to be run
+/// in CI before packaging.
+#[derive(Parser)]
+struct Gate {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The original game directory. Without it, lengths aren't compared to
+    /// the original lines.
+    #[clap(long)]
+    game_dir: Option<PathBuf>,
+
+    /// Also write the full report to this file, as JSON.
+    #[clap(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+impl Gate {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let ffprobe_tool = FfprobeTool::find(&system_path)
+            .ok_or_else(|| anyhow::anyhow!("ffprobe not found in PATH"))?;
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let config = FanDubConfig::load(&self.sample_dir)?;
+        let original_audio = self
+            .game_dir
+            .as_deref()
+            .map(OriginalAudio::open)
+            .transpose()?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let scheduler = BatchScheduler::new(4, cancel);
+        let report = run_gate(
+            &config.gate,
+            &sample_dir,
+            original_audio.as_ref(),
+            &ffmpeg_tool,
+            &ffprobe_tool,
+            &scheduler,
+        )
+        .await?;
+
+        for result in report.failures() {
+            let id = &result.message_id;
+            let violations = result
+                .violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            println!(
+                "room {} line {}-{}-{}-{} ({}): {}",
+                result.room,
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence(),
+                result.source.display(),
+                violations.join("; ")
+            );
+        }
+        if let Some(output) = &self.output {
+            std::fs::write(output, serde_json::to_vec_pretty(&report)?)?;
+            eprintln!("Wrote {}", output.display());
+        }
+        let failed = report.failures().count();
+        anyhow::ensure!(
+            failed == 0,
+            "{failed} of {} takes failed the quality gate",
+            report.results.len()
+        );
+        println!("All {} takes passed the quality gate", report.results.len());
+        Ok(())
+    }
+}
+
+/// Cuts a session rendered from an exported project back into a take per
+/// line, using the project's regions.
+#[derive(Parser)]
+struct ImportDaw {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The directory the project was exported to.
+    project_dir: PathBuf,
+
+    /// The rendered session.
+    rendered: PathBuf,
+
+    /// Also select the new takes for their lines.
+    #[clap(long)]
+    select: bool,
+
+    /// Import lines that are locked, too.
+    #[clap(long)]
+    force: bool,
+}
+
+impl ImportDaw {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let mut sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let imported = import_session(
+            &mut sample_dir,
+            &self.project_dir,
+            &self.rendered,
+            self.select,
+            self.force,
+            &ffmpeg_tool,
+            &cancel,
+        )
+        .await?;
+        for take in &imported {
+            println!("{}", take.take.display());
+        }
+        eprintln!(
+            "Imported {} takes{}",
+            imported.len(),
+            if self.select {
+                " and selected them"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+}
+
+/// Splits a conversation recorded in one take into a take per line, by an
+/// Audacity label file or by the pauses between lines, after checking the
+/// lines found against the conversation's lines in the game.
+#[derive(Parser)]
+struct ImportOneTake {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The original game directory, for the conversation's lines.
+    #[clap(long)]
+    game_dir: PathBuf,
+
+    /// The recording.
+    recording: PathBuf,
+
+    /// The room of the conversation.
+    #[clap(long)]
+    room: u16,
+
+    #[clap(long)]
+    noun: Option<u8>,
+
+    #[clap(long)]
+    verb: Option<u8>,
+
+    #[clap(long)]
+    condition: Option<u8>,
+
+    /// An Audacity label file with a region per line, named by the line's
+    /// key (e.g. from `scitool gen export --format audacity-labels`).
+    /// Without one, lines are found by the pauses between them, and must
+    /// have been recorded in order.
+    #[clap(long)]
+    labels: Option<PathBuf>,
+
+    /// Sound quieter than this (in dBFS) counts as silence.
+    #[clap(long, default_value_t = -45.0, allow_hyphen_values = true)]
+    silence_db: f64,
+
+    /// The shortest pause between lines, in seconds. Shorter pauses are
+    /// taken to be within a line.
+    #[clap(long, default_value_t = 0.6)]
+    min_silence: f64,
+
+    /// Also select the new takes for their lines.
+    #[clap(long)]
+    select: bool,
+
+    /// Import lines that are locked, too.
+    #[clap(long)]
+    force: bool,
+}
+
+impl ImportOneTake {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let mut sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let filter = ConversationFilter {
+            room: self.room,
+            noun: self.noun,
+            verb: self.verb,
+            condition: self.condition,
+        };
+        let source = match &self.labels {
+            Some(labels) => LineSource::Labels(labels),
+            None => LineSource::Silence(SilenceOptions {
+                threshold_db: self.silence_db,
+                min_silence: Duration::try_from_secs_f64(self.min_silence)?,
+                ..SilenceOptions::default()
+            }),
+        };
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let imported = import_one_take(
+            &mut sample_dir,
+            &self.game_dir,
+            &filter,
+            &self.recording,
+            source,
+            self.select,
+            self.force,
+            &ffmpeg_tool,
+            &cancel,
+        )
+        .await?;
+        for take in &imported {
+            println!("{}", take.take.display());
+        }
+        eprintln!(
+            "Split {} takes from {}{}",
+            imported.len(),
+            self.recording.display(),
+            if self.select {
+                " and selected them"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+}
+
+/// Converts a line's lip sync timing from Papagayo (`.dat`) or JSON into a
+/// Sync36 patch, mapping phonemes to mouth cels with the project's viseme
+/// table.
+#[derive(Parser)]
+struct ImportSync {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The phoneme file.
+    input: PathBuf,
+
+    /// The room of the line.
+    #[clap(long)]
+    room: u16,
+
+    #[clap(long)]
+    noun: u8,
+
+    #[clap(long)]
+    verb: u8,
+
+    #[clap(long)]
+    condition: u8,
+
+    #[clap(long)]
+    sequence: u8,
+
+    /// The frame rate of Papagayo files.
+    #[clap(long, default_value_t = 24.0)]
+    fps: f64,
+
+    /// The directory to write the patch to.
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl ImportSync {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let config = FanDubConfig::load(&self.sample_dir)?;
+        let visemes = config
+            .visemes
+            .as_ref()
+            .map(VisemeTable::new)
+            .unwrap_or_default();
+        let cues = to_sync_cues(&read_phoneme_file(&self.input, self.fps)?, &visemes)?;
+        let message_id = MessageId::new(self.noun, self.verb, self.condition, self.sequence);
+        let patch_path = self.output.join(patch_file_name(
+            ResourceType::Sync36,
+            self.room,
+            &message_id,
+        )?);
+        std::fs::create_dir_all(&self.output)?;
+        std::fs::write(&patch_path, write_sync36_patch(&cues)?)?;
+        println!("{}", patch_path.display());
+        eprintln!("Wrote {} mouth changes", cues.len());
+        Ok(())
+    }
+}
+
+/// Marks the lines that interpreter debug logs show being displayed as heard
+/// in playtest, for `status` to report coverage from.
+#[derive(Parser)]
+struct ImportTrace {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The debug logs.
+    #[clap(required = true)]
+    logs: Vec<PathBuf>,
+}
+
+impl ImportTrace {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let mut playtest = PlaytestLog::load(&self.sample_dir)?.unwrap_or_default();
+        for log in &self.logs {
+            let lines = parse_message_trace(&std::fs::read_to_string(log)?);
+            if lines.is_empty() {
+                eprintln!("Warning: no messages found in {}", log.display());
+                continue;
+            }
+            let log_name = log.file_name().map_or_else(
+                || log.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            let newly_heard = playtest.record(&log_name, &lines);
+            println!(
+                "{}: {} messages, {newly_heard} lines heard for the first time",
+                log.display(),
+                lines.len()
+            );
+        }
+        playtest.save(&self.sample_dir)
+    }
+}
+
+/// Renders a talker's mouth loop, animated by a Sync36 patch, with the
+/// dubbed line to a video, to check the sync without running the game.
+#[derive(Parser)]
+struct PreviewSync {
+    /// The original game directory.
+    #[clap(long)]
+    game_dir: PathBuf,
+
+    /// The talker's mouth view.
+    #[clap(long)]
+    view: u16,
+
+    /// The loop of the view with the mouth cels.
+    #[clap(long, default_value_t = 0)]
+    loop_num: u8,
+
+    /// The Sync36 patch, as written by `import-sync`.
+    #[clap(long)]
+    sync: PathBuf,
+
+    /// The dubbed audio for the line.
+    #[clap(long)]
+    audio: PathBuf,
+
+    /// How many times larger than the game's pixels to draw the video.
+    #[clap(long, default_value_t = 4)]
+    scale: u32,
+
+    /// The video file to write (e.g. `preview.mp4`).
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl PreviewSync {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.scale > 0, "The scale must be at least 1");
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let mouth = MouthLoop::load(&self.game_dir, self.view, self.loop_num)?;
+        let cues = read_sync36_patch(&std::fs::read(&self.sync)?)?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        render_sync_preview(
+            &ffmpeg_tool,
+            &mouth,
+            &cues,
+            &self.audio,
+            self.scale,
+            &self.output,
+            &cancel,
+        )
+        .await?;
+        eprintln!("Wrote {}", self.output.display());
+        Ok(())
+    }
+}
+
+/// Packages a build as a release, with a manifest and a changelog of the
+/// lines changed since the previous release.
+#[derive(Parser)]
+struct Package {
+    /// The output directory of the build.
+    build_dir: PathBuf,
+
+    /// The release's version (e.g. `1.2.0`).
+    #[clap(long)]
+    version: semver::Version,
+
+    /// The previous release's manifest (or package directory), for the
+    /// changelog.
+    #[clap(long)]
+    previous: Option<PathBuf>,
+
+    /// Build a delta package with only the files changed since this release
+    /// (a manifest, or a package directory).
+    #[clap(long, conflicts_with = "previous")]
+    diff_since: Option<PathBuf>,
+
+    /// The directory to create the package in.
+    #[clap(short = 'o', long, default_value = "releases")]
+    output: PathBuf,
+
+    /// Give the package's files a fixed modification time
+    /// (`SOURCE_DATE_EPOCH`, or the Unix epoch), so packaging the same build
+    /// gives identical files.
+    #[clap(long)]
+    deterministic: bool,
+
+    /// Sign the manifest with this key (from `gen-signing-key`).
+    #[clap(long)]
+    sign_key: Option<PathBuf>,
+}
+
+impl Package {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let previous = self
+            .previous
+            .as_ref()
+            .or(self.diff_since.as_ref())
+            .map(|path| ReleaseManifest::load(path))
+            .transpose()?;
+        let delta = self.diff_since.is_some();
+        let signing_key = self
+            .sign_key
+            .as_deref()
+            .map(signing::read_signing_key)
+            .transpose()?;
+        let package_dir = self.output.join(package_name(
+            &self.version,
+            previous
+                .as_ref()
+                .filter(|_| delta)
+                .map(|previous| &previous.version),
+        ));
+        let manifest = package_release(
+            &self.build_dir,
+            &package_dir,
+            self.version.clone(),
+            previous.as_ref(),
+            delta,
+            self.deterministic.then(fs::reproducible_mtime),
+            signing_key.as_ref(),
         )?;
+        let changelog = &manifest.changelog;
+        eprintln!(
+            "Wrote {} ({} of {} files; {} lines added, {} changed, {} removed)",
+            package_dir.display(),
+            manifest.included.len(),
+            manifest.files.len(),
+            changelog.added.len(),
+            changelog.changed.len(),
+            changelog.removed.len()
+        );
+        Ok(())
+    }
+}
+
+/// Reports takes that duplicate each other, and takes that changed since the
+/// last build.
+#[derive(Parser)]
+struct Status {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The output directory of the last build.
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl Status {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let scheduler = BatchScheduler::new(4, cancel);
+        let current = fingerprint_samples(&sample_dir, &ffmpeg_tool, &scheduler).await?;
+
+        let duplicates = find_duplicates(&current);
+        for keys in &duplicates {
+            println!("Identical takes: {}", keys.join(", "));
+        }
+
+        if let Some(playtest) = PlaytestLog::load(&self.sample_dir)? {
+            let coverage = playtest.coverage(&sample_dir);
+            for key in &coverage.heard_without_sample {
+                println!("{key}: heard in playtest, but not dubbed");
+            }
+            println!(
+                "{} of {} dubbed lines heard in playtest",
+                coverage.dubbed_heard, coverage.dubbed
+            );
+        }
+
+        let Some(built) = BuildFingerprints::load(&self.output)? else {
+            println!("No build found in {}", self.output.display());
+            return Ok(());
+        };
+        let drift = built.drift(&current);
+        for (key, drift) in &drift {
+            let status = match drift {
+                Drift::Changed => "take changed since the last build",
+                Drift::NotBuilt => "not in the last build",
+                Drift::Removed => "removed since the last build",
+            };
+            println!("{key}: {status}");
+        }
+        println!(
+            "{} samples, {} duplicate groups, {} out of date",
+            current.len(),
+            duplicates.len(),
+            drift.len()
+        );
+        Ok(())
+    }
+}
+
+/// Compares the background tone (noise floor and EQ balance) of dubbed lines
+/// against the original speech in each room, listing the lines that deviate
+/// most.
+#[derive(Parser)]
+struct ToneReport {
+    #[clap(short = 's')]
+    sample_dir: PathBuf,
+
+    /// The original game directory.
+    #[clap(long)]
+    game_dir: PathBuf,
+
+    /// Also write the full report to this file, as JSON.
+    #[clap(short = 'o', long)]
+    output: Option<PathBuf>,
+
+    /// How many lines to list.
+    #[clap(long, default_value_t = 20)]
+    limit: usize,
+}
+
+impl ToneReport {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let system_path = LookupPath::from_env();
+        let ffmpeg_tool = ffmpeg::FfmpegTool::from_path(
+            system_path
+                .find_binary("ffmpeg")
+                .ok_or_else(|| anyhow::anyhow!("ffmpeg not found in PATH"))?
+                .to_path_buf(),
+        );
+        let sample_dir = SampleDir::load_dir(&self.sample_dir).await?;
+        let config = FanDubConfig::load(&self.sample_dir)?;
+        let cleanup = CleanupPresets::from_config(&config.cleanup)?;
+        let original_audio = OriginalAudio::open(&self.game_dir)?;
+        let cancel = CancellationToken::new();
+        let _signal_task = cancel_on_ctrl_c(cancel.clone())?;
+        let scheduler = BatchScheduler::new(4, cancel);
+        let report = analyze_tone(
+            &sample_dir,
+            &original_audio,
+            &cleanup,
+            &ffmpeg_tool,
+            &scheduler,
+        )
+        .await?;
+
+        for room in &report.rooms {
+            match &room.reference {
+                Some(reference) => println!(
+                    "Room {}: noise floor {:.1} dB, low {:.1} dB, high {:.1} dB ({} original clips)",
+                    room.room,
+                    reference.noise_floor_db,
+                    reference.low_db,
+                    reference.high_db,
+                    room.original_clips
+                ),
+                None => println!("Room {}: no original clips to compare against", room.room),
+            }
+        }
+        println!();
+        for line in report.lines.iter().take(self.limit) {
+            let id = &line.message_id;
+            println!(
+                "{:6.1} dB  room {} line {}-{}-{}-{}: noise floor {:.1} dB, low {:.1} dB, high {:.1} dB ({})",
+                line.deviation,
+                line.room,
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence(),
+                line.tone.noise_floor_db,
+                line.tone.low_db,
+                line.tone.high_db,
+                line.source.display()
+            );
+        }
+        if let Some(output) = &self.output {
+            std::fs::write(output, serde_json::to_vec_pretty(&report)?)?;
+            eprintln!("Wrote {}", output.display());
+        }
+        Ok(())
+    }
+}
+
+/// Generates a key for signing packages, and prints its public key for users
+/// to check packages with.
+#[derive(Parser)]
+struct GenSigningKey {
+    /// The file to write the secret key to. Keep it private.
+    output: PathBuf,
+}
+
+impl GenSigningKey {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let key = signing::generate_key()?;
+        signing::write_signing_key(&self.output, &key)?;
+        eprintln!("Wrote the signing key to {}", self.output.display());
+        println!("{}", signing::public_key_hex(&key.verifying_key()));
+        Ok(())
+    }
+}
+
+/// Checks an installed dub against a release's manifest, and the manifest's
+/// signature.
+#[derive(Parser)]
+struct VerifyInstall {
+    /// The game directory the dub is installed in.
+    game_dir: PathBuf,
+
+    /// The release's manifest, or its package directory.
+    #[clap(long)]
+    package: PathBuf,
+
+    /// The project's public key, to check the manifest's signature with.
+    #[clap(long)]
+    public_key: Option<String>,
+
+    /// Fail unless the manifest is signed with the public key.
+    #[clap(long, requires = "public_key")]
+    require_signature: bool,
+}
+
+impl VerifyInstall {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let manifest_path = ReleaseManifest::path(&self.package);
+        let manifest_data = std::fs::read(&manifest_path)?;
+        match &self.public_key {
+            Some(public_key) => {
+                let public_key = signing::parse_public_key(public_key)?;
+                if self.require_signature || signing::is_signed(&manifest_path) {
+                    signing::verify_manifest(&manifest_path, &manifest_data, &public_key)?;
+                    eprintln!("The manifest's signature is valid");
+                } else {
+                    eprintln!("Warning: the manifest is not signed");
+                }
+            }
+            None if signing::is_signed(&manifest_path) => {
+                eprintln!(
+                    "Warning: the manifest is signed, but no public key was given to check it"
+                );
+            }
+            None => {}
+        }
+        let manifest = ReleaseManifest::parse(&manifest_data)?;
+        let problems = verify_install(&self.game_dir, &manifest)?;
+        for problem in &problems {
+            eprintln!("{problem}");
+        }
+        anyhow::ensure!(
+            problems.is_empty(),
+            "The install doesn't match release {}: {} problems",
+            manifest.version,
+            problems.len()
+        );
+        eprintln!(
+            "The install matches release {} ({} files)",
+            manifest.version,
+            manifest.files.len()
+        );
         Ok(())
     }
 }
@@ -84,7 +1309,24 @@ impl CompileAudio {
 async fn async_main() -> anyhow::Result<()> {
     let args = Cli::parse();
     match &args.command {
+        Cmd::BlindTest(blind_test) => blind_test.run().await?,
         Cmd::CompileAudio(compile_audio) => compile_audio.run().await?,
+        Cmd::ExportCues(export_cues) => export_cues.run().await?,
+        Cmd::ExportDaw(export_daw) => export_daw.run().await?,
+        Cmd::Gate(gate) => gate.run().await?,
+        Cmd::GenSigningKey(gen_signing_key) => gen_signing_key.run()?,
+        Cmd::ImportDaw(import_daw) => import_daw.run().await?,
+        Cmd::ImportOneTake(import_one_take) => import_one_take.run().await?,
+        Cmd::ImportSync(import_sync) => import_sync.run()?,
+        Cmd::ImportTrace(import_trace) => import_trace.run()?,
+        Cmd::Package(package) => package.run()?,
+        Cmd::PreviewSync(preview_sync) => preview_sync.run().await?,
+        Cmd::Lock(lock) => lock.run().await?,
+        Cmd::Rename(rename) => rename.run().await?,
+        Cmd::RenderRoom(render_room) => render_room.run().await?,
+        Cmd::Status(status) => status.run().await?,
+        Cmd::ToneReport(tone_report) => tone_report.run().await?,
+        Cmd::VerifyInstall(verify_install) => verify_install.run()?,
     }
     Ok(())
 }
@@ -0,0 +1,288 @@
+//! Blind A/B listening tests, for choosing takes for key scenes without
+//! knowing which is which.
+//!
+//! Each pair compares two takes of a line, or a line's selected take with a
+//! text-to-speech reading of it. The pairs are shuffled, and which side is A
+//! is random. Both sides are rendered the same way (silence trimmed and
+//! loudness normalized), so nothing but the performance tells them apart.
+//! The bundle holds:
+//!
+//! - `pair-01-A.wav`, `pair-01-B.wav`, ... for the listeners,
+//! - `ballot.md`, listing the pairs with their lines, to mark choices on, and
+//! - `answer-key.json`, saying which take each side is, for whoever runs the
+//!   test.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use sci_resources::types::msg::MessageId;
+use sci_utils::rng::Rng;
+use serde::Serialize;
+
+use crate::{
+    cancel::CancellationToken,
+    placeholder::{SpeechBackend, spoken_text},
+    rename::find_all_takes,
+    resources::{GameLine, SampleDir, normalize_path, sample_key},
+    tools::ffmpeg::{self, FfmpegTool, OutputFormat},
+};
+
+/// Renders both sides of a pair alike: leading and trailing silence
+/// removed, and loudness normalized, so neither stands out for its level.
+const RENDER_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB,areverse,\
+silenceremove=start_periods=1:start_threshold=-50dB,areverse,\
+loudnorm=I=-20:TP=-2,aresample=44100";
+
+/// What the listeners hear on one side of a pair.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BlindSource {
+    /// The take, relative to the sample directory, or `tts` for the
+    /// text-to-speech reference.
+    pub label: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub trim_filter: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BlindPair {
+    /// The line's key (`<room>-<noun>-<verb>-<condition>-<sequence>`).
+    pub line: String,
+    pub a: BlindSource,
+    pub b: BlindSource,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnswerKey {
+    /// The seed the pairs were shuffled with, to make the same bundle again.
+    pub seed: u64,
+    /// The pairs, in the order they are numbered from 1.
+    pub pairs: Vec<BlindPair>,
+}
+
+/// A random seed, for bundles that don't ask for one.
+pub fn random_seed() -> anyhow::Result<u64> {
+    getrandom::u64().map_err(|e| anyhow::anyhow!("No random source: {e}"))
+}
+
+/// Shuffles the pairs, and swaps the sides of about half of them.
+pub fn shuffle_pairs(mut pairs: Vec<BlindPair>, seed: u64) -> Vec<BlindPair> {
+    let mut rng = Rng::new(seed);
+    for i in (1..pairs.len()).rev() {
+        pairs.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+    for pair in &mut pairs {
+        if rng.below(2) == 1 {
+            std::mem::swap(&mut pair.a, &mut pair.b);
+        }
+    }
+    pairs
+}
+
+fn in_scope(room: u16, message_id: &MessageId, rooms: &BTreeSet<u16>, keys: &[String]) -> bool {
+    (rooms.is_empty() || rooms.contains(&room))
+        && (keys.is_empty() || keys.contains(&sample_key(room, message_id)))
+}
+
+/// The selected take of a line, as a source.
+fn selected_source(
+    sample_dir: &SampleDir,
+    room: u16,
+    message_id: &MessageId,
+) -> Option<BlindSource> {
+    let sample = sample_dir.sample(room, message_id)?;
+    if sample.stand_in.is_some() {
+        return None;
+    }
+    Some(BlindSource {
+        label: normalize_path(&sample.clip.path).display().to_string(),
+        path: sample.clip_path(sample_dir.base_path()).ok()?,
+        trim_filter: sample.clip.trim_filter(),
+    })
+}
+
+/// Pairs every two takes of each line in scope. The selected take is played
+/// as trimmed, and the others whole. Lines are in scope if they are in one
+/// of the rooms and have one of the keys, when either is given.
+pub fn take_pairs(
+    sample_dir: &SampleDir,
+    rooms: &BTreeSet<u16>,
+    keys: &[String],
+) -> anyhow::Result<Vec<BlindPair>> {
+    let mut pairs = Vec::new();
+    for ((room, message_id), paths) in find_all_takes(sample_dir)? {
+        if !in_scope(room, &message_id, rooms, keys) {
+            continue;
+        }
+        let selected = selected_source(sample_dir, room, &message_id);
+        let sources: Vec<BlindSource> = paths
+            .into_iter()
+            .map(|path| {
+                let label = path.display().to_string();
+                match &selected {
+                    Some(selected) if selected.label == label => selected.clone(),
+                    _ => BlindSource {
+                        label,
+                        path: sample_dir.base_path().join(path),
+                        trim_filter: None,
+                    },
+                }
+            })
+            .collect();
+        for (i, a) in sources.iter().enumerate() {
+            for b in &sources[i + 1..] {
+                pairs.push(BlindPair {
+                    line: sample_key(room, &message_id),
+                    a: a.clone(),
+                    b: b.clone(),
+                });
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Pairs the selected take of each line in scope with a text-to-speech
+/// reading of its text, spoken into `scratch_dir`.
+pub async fn tts_pairs(
+    sample_dir: &SampleDir,
+    lines: &[GameLine],
+    rooms: &BTreeSet<u16>,
+    keys: &[String],
+    backend: &dyn SpeechBackend,
+    scratch_dir: &Path,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<BlindPair>> {
+    let mut pairs = Vec::new();
+    for line in lines {
+        if !in_scope(line.room, &line.message_id, rooms, keys) {
+            continue;
+        }
+        let Some(selected) = selected_source(sample_dir, line.room, &line.message_id) else {
+            continue;
+        };
+        let text = spoken_text(&line.text);
+        if text.is_empty() {
+            continue;
+        }
+        let key = sample_key(line.room, &line.message_id);
+        let path = scratch_dir.join(format!("{key}.wav"));
+        backend.speak(&key, &text, &path, cancel).await?;
+        pairs.push(BlindPair {
+            line: key,
+            a: selected,
+            b: BlindSource {
+                label: "tts".to_string(),
+                path,
+                trim_filter: None,
+            },
+        });
+    }
+    Ok(pairs)
+}
+
+async fn render_source(
+    ffmpeg: &FfmpegTool,
+    source: &BlindSource,
+    output: &Path,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let filter = match &source.trim_filter {
+        Some(trim_filter) => format!("{trim_filter},{RENDER_FILTER}"),
+        None => RENDER_FILTER.to_string(),
+    };
+    let wav = ffmpeg
+        .convert_with_filter(
+            source.path.as_path(),
+            ffmpeg::VecOutput,
+            OutputFormat::Wav,
+            Some(&filter),
+            &mut ffmpeg::NullProgressListener,
+            cancel,
+        )
+        .await?;
+    smol::fs::write(output, wav).await?;
+    Ok(())
+}
+
+fn ballot(pairs: &[BlindPair]) -> String {
+    let mut ballot = String::from("# Blind listening test\n\n");
+    ballot.push_str("For each pair, listen to A and B, and mark the one you prefer.\n\n");
+    ballot.push_str("| Pair | Line | Prefer A | Prefer B | Notes |\n");
+    ballot.push_str("| --- | --- | --- | --- | --- |\n");
+    for (i, pair) in pairs.iter().enumerate() {
+        writeln!(ballot, "| {:02} | {} | [ ] | [ ] | |", i + 1, pair.line).unwrap();
+    }
+    ballot
+}
+
+/// Shuffles the pairs with the seed, and writes the bundle to `output_dir`.
+pub async fn write_blind_test(
+    pairs: Vec<BlindPair>,
+    seed: u64,
+    output_dir: &Path,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<AnswerKey> {
+    smol::fs::create_dir_all(output_dir).await?;
+    let pairs = shuffle_pairs(pairs, seed);
+    for (i, pair) in pairs.iter().enumerate() {
+        for (side, source) in [("A", &pair.a), ("B", &pair.b)] {
+            let output = output_dir.join(format!("pair-{:02}-{side}.wav", i + 1));
+            render_source(ffmpeg, source, &output, cancel).await?;
+        }
+    }
+    smol::fs::write(output_dir.join("ballot.md"), ballot(&pairs)).await?;
+    let answer_key = AnswerKey { seed, pairs };
+    smol::fs::write(
+        output_dir.join("answer-key.json"),
+        serde_json::to_vec_pretty(&answer_key)?,
+    )
+    .await?;
+    Ok(answer_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(label: &str) -> BlindSource {
+        BlindSource {
+            label: label.to_string(),
+            path: PathBuf::from(label),
+            trim_filter: None,
+        }
+    }
+
+    fn pairs() -> Vec<BlindPair> {
+        (1..=6)
+            .map(|n| BlindPair {
+                line: format!("10-1-2-0-{n}"),
+                a: source("first.wav"),
+                b: source("second.wav"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shuffle_is_seeded() {
+        let shuffled = shuffle_pairs(pairs(), 7);
+        assert_eq!(shuffled, shuffle_pairs(pairs(), 7));
+        assert_ne!(shuffled, pairs());
+        let lines: BTreeSet<_> = shuffled.iter().map(|pair| pair.line.clone()).collect();
+        assert_eq!(lines.len(), 6);
+        for pair in &shuffled {
+            assert_ne!(pair.a, pair.b);
+        }
+    }
+
+    #[test]
+    fn test_ballot() {
+        let ballot = ballot(&pairs()[..1]);
+        assert!(ballot.ends_with("| 01 | 10-1-2-0-1 | [ ] | [ ] | |\n"));
+    }
+}
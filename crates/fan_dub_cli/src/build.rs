@@ -0,0 +1,568 @@
+//! The audio build: converts every sample and packs the results into an
+//! audio volume and map patches. Shared by the command line and the GUI.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use futures::stream::{FuturesUnordered, TryStreamExt};
+use futures::{AsyncWriteExt, FutureExt};
+use sci_resources::{
+    file::open_game_resources,
+    types::{audio36::empty_audio_index, sync36::write_sync36_patch},
+};
+use sci_utils::fs;
+
+use crate::{
+    added::{AddedLine, message_patches},
+    archive::{check_archive_profile, write_archive},
+    cancel::{CancellationToken, Cancelled},
+    cleanup::CleanupPresets,
+    config::FanDubConfig,
+    effects::EffectPresets,
+    fingerprint::{BuildFingerprints, fingerprint_samples},
+    new_speech::{SETUP_FILE, has_speech, setup_guide, unmatched_samples},
+    overrides::LineOverrides,
+    partial::{PartialReport, original_stand_ins},
+    path::LookupPath,
+    placeholder::{CommandSpeech, CoverageReport, SpeechBackend, generate_placeholders},
+    profile::{ConversionProfile, DEFAULT_PROFILE, MATCH_GAME_PROFILE, ProfileSet},
+    remap::TupleRemap,
+    report::BuildReport,
+    resources::{
+        BuildCheckpoint, BuildOptions, OriginalAudio, SampleDir, game_lines, parse_sample_key,
+    },
+    scheduler::{BatchFailed, BatchScheduler, RetryPolicy},
+    stage::{StageRegistry, match_duration_factory},
+    subtitles::{TIMING_FILE, subtitle_timing},
+    tools::{ffmpeg, speech::SpeechTool},
+};
+
+async fn execute_all<F>(futures: impl IntoIterator<Item = F>) -> anyhow::Result<()>
+where
+    F: futures::Future<Output = anyhow::Result<()>> + Unpin,
+{
+    let mut fut_unordered = FuturesUnordered::from_iter(futures);
+    while let Some(()) = fut_unordered.try_next().await? {
+        // Do nothing
+    }
+    Ok(())
+}
+
+/// The report files a build may write to the output directory, besides the
+/// game files.
+const REPORT_FILES: &[&str] = &[
+    "qa-report.json",
+    "qa-report.html",
+    "coverage.json",
+    "partial-report.json",
+    "partial-report.md",
+    "fingerprints.json",
+    SETUP_FILE,
+    TIMING_FILE,
+];
+
+/// Writes an output file, moving it into place once it is complete. If the
+/// target stays locked and a staging directory is given, the file is written
+/// there instead.
+async fn write_output(
+    path: PathBuf,
+    staging_dir: Option<&Path>,
+    modified: Option<SystemTime>,
+    log: &dyn Fn(String),
+    write: impl AsyncFnOnce(&mut smol::fs::File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut pending = fs::PendingFile::create(&path)?;
+    let mut file = smol::fs::File::from(pending.file().try_clone()?);
+    write(&mut file).await?;
+    file.flush().await?;
+    let options = fs::WriteOptions {
+        staging_dir: staging_dir.map(Path::to_path_buf),
+        modified,
+        ..fs::WriteOptions::default()
+    };
+    let written = smol::unblock(move || pending.commit(&options)).await?;
+    if let fs::Written::Staged(staged) = written {
+        log(format!(
+            "{} is locked; wrote {} instead. Copy it over once the game is closed.",
+            path.display(),
+            staged.display()
+        ));
+    }
+    Ok(())
+}
+
+/// The interpreter a build is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BuildTarget {
+    /// Sierra's own interpreter.
+    #[default]
+    Sierra,
+    /// ScummVM.
+    #[clap(name = "scummvm")]
+    ScummVm,
+}
+
+/// What to build, and how.
+#[derive(Debug, Clone)]
+pub struct BuildSettings {
+    pub sample_dir: PathBuf,
+    pub output: PathBuf,
+    /// Resume an interrupted build, reusing the samples it already converted.
+    pub resume: bool,
+    /// How many times to retry a sample whose conversion fails.
+    pub retries: u32,
+    /// Where to write output files that are locked (e.g. by a running game).
+    pub staging_dir: Option<PathBuf>,
+    /// The conversion profile to use. If not set, the build matches the
+    /// format of the game's clips when the game directory is given, and uses
+    /// the default profile otherwise.
+    pub profile: Option<String>,
+    /// Also write the trimmed takes to this directory, in a high quality
+    /// format.
+    pub archive: Option<PathBuf>,
+    /// The conversion profile for the archive.
+    pub archive_profile: String,
+    /// The original game directory, for samples that are matched to the
+    /// original recording.
+    pub game_dir: Option<PathBuf>,
+    /// Fill lines that haven't been recorded with generated speech. Needs
+    /// the game directory, for the lines' text.
+    pub placeholders: bool,
+    /// Repack the original recordings of lines that haven't been recorded,
+    /// so only recorded lines are overridden. Needs the game directory.
+    pub partial: bool,
+    /// Lines the dub adds to the game. Their text is written as message
+    /// patches alongside the audio. Needs the game directory.
+    pub added_lines: Vec<AddedLine>,
+    /// Make the output reproducible: encode without encoder tags or random
+    /// stream serials, and give every written file a fixed modification
+    /// time (`SOURCE_DATE_EPOCH`, or the Unix epoch), so the same inputs
+    /// give bit-identical files.
+    pub deterministic: bool,
+    /// Give speech to a game that shipped without it: write a complete set
+    /// of audio maps, and a guide to the interpreter changes it needs.
+    /// Needs the game directory, for the lines' messages.
+    pub new_speech: bool,
+    /// The interpreter the build is for.
+    pub target: BuildTarget,
+    /// For ScummVM, rescale the Sync36 cues of lines whose duration changed,
+    /// so their subtitles stay up as long as the new clips play. Needs the
+    /// game directory.
+    pub subtitle_timing: bool,
+}
+
+impl BuildSettings {
+    pub fn new(sample_dir: PathBuf, output: PathBuf) -> Self {
+        BuildSettings {
+            sample_dir,
+            output,
+            resume: false,
+            retries: 2,
+            staging_dir: None,
+            profile: None,
+            archive: None,
+            archive_profile: "hq-archive".to_string(),
+            game_dir: None,
+            placeholders: false,
+            partial: false,
+            added_lines: Vec::new(),
+            deterministic: false,
+            new_speech: false,
+            target: BuildTarget::default(),
+            subtitle_timing: false,
+        }
+    }
+}
+
+/// Runs a build. Progress and problems worth telling the user about are
+/// passed to `log`.
+pub async fn build_audio(
+    settings: &BuildSettings,
+    ffmpeg_path: PathBuf,
+    cancel: CancellationToken,
+    log: &dyn Fn(String),
+) -> anyhow::Result<()> {
+    let ffmpeg_tool =
+        ffmpeg::FfmpegTool::from_path(ffmpeg_path.clone()).with_bitexact(settings.deterministic);
+    let modified = settings.deterministic.then(fs::reproducible_mtime);
+    let mut sample_dir = SampleDir::load_dir(&settings.sample_dir).await?;
+    let config = FanDubConfig::load(&settings.sample_dir)?;
+    let profiles = ProfileSet::from_config(&config)?;
+    let cleanup = CleanupPresets::from_config(&config.cleanup)?;
+    let effects = EffectPresets::from_config(&config.effects)?;
+    let overrides = LineOverrides::from_config(&config.lines)?;
+    let remap = TupleRemap::from_config(&config.remap)?;
+    if settings.new_speech {
+        let game_dir = settings
+            .game_dir
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("A new-speech build needs the game directory"))?;
+        anyhow::ensure!(
+            !has_speech(game_dir),
+            "The game already has speech; build without new speech to replace it"
+        );
+        anyhow::ensure!(
+            !settings.partial,
+            "A new-speech build has no original audio to keep for unrecorded lines"
+        );
+    }
+    if settings.subtitle_timing {
+        anyhow::ensure!(
+            settings.target == BuildTarget::ScummVm,
+            "Subtitle timing is only written for the scummvm target"
+        );
+        anyhow::ensure!(
+            settings.game_dir.is_some() && !settings.new_speech,
+            "Subtitle timing needs the game directory, with its original clips"
+        );
+    }
+    let original_audio = settings
+        .game_dir
+        .as_deref()
+        .filter(|_| !settings.new_speech)
+        .map(OriginalAudio::open)
+        .transpose()?
+        .map(Rc::new);
+    let mut stage_registry = StageRegistry::with_builtins();
+    stage_registry.register(
+        "match-duration",
+        match_duration_factory(ffmpeg_path, original_audio.clone()),
+    );
+    let stages = stage_registry.create_all(&config.stages)?;
+    let detected_format = original_audio
+        .as_deref()
+        .map(OriginalAudio::detect_format)
+        .transpose()?
+        .flatten();
+    if let Some(detected) = &detected_format
+        && detected.clips < detected.total
+    {
+        log(format!(
+            "The game's clips use mixed formats; {} of {} are {}",
+            detected.clips, detected.total, detected.format
+        ));
+    }
+    let profile_name = match (&settings.profile, &detected_format) {
+        (Some(name), _) => name.as_str(),
+        (None, Some(_)) => MATCH_GAME_PROFILE,
+        (None, None) => DEFAULT_PROFILE,
+    };
+    let profile = if profile_name == MATCH_GAME_PROFILE {
+        let Some(detected) = &detected_format else {
+            anyhow::bail!(
+                "The {MATCH_GAME_PROFILE} profile needs the game directory, with its original clips"
+            );
+        };
+        log(format!("Matching the game's clips: {}", detected.format));
+        &ConversionProfile::matching(&detected.format)
+    } else {
+        profiles.get(profile_name)?
+    };
+    if let Some(detected) = &detected_format {
+        for difference in profile.differences(&detected.format) {
+            log(format!(
+                "Warning: profile {profile_name:?} {difference}; the interpreter will have to convert the new clips"
+            ));
+        }
+    }
+    let archive_profile = profiles.get(&settings.archive_profile)?;
+    if settings.archive.is_some() {
+        check_archive_profile(&settings.archive_profile, archive_profile)?;
+    }
+    let output_dir = &settings.output;
+    let staging_dir = settings.staging_dir.as_deref();
+
+    let mut checkpoint = BuildCheckpoint::open(output_dir, settings.resume)?;
+    if checkpoint.num_complete() > 0 {
+        log(format!(
+            "Resuming build: {} samples already converted",
+            checkpoint.num_complete()
+        ));
+    }
+    let scheduler = BatchScheduler::new(4, cancel).with_retry(RetryPolicy {
+        max_retries: settings.retries,
+        ..RetryPolicy::default()
+    });
+    anyhow::ensure!(
+        !(settings.placeholders && settings.partial),
+        "A build can't use both placeholders and the original audio for unrecorded lines"
+    );
+    let coverage = if settings.placeholders {
+        let game_dir = settings
+            .game_dir
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Placeholders need the game directory"))?;
+        let backend: Box<dyn SpeechBackend> = match &config.placeholders {
+            Some(placeholder_config) => Box::new(CommandSpeech::from_config(placeholder_config)?),
+            None => Box::new(SpeechTool::find(&LookupPath::from_env()).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No text-to-speech program found for placeholders; configure one in fan-dub.json"
+                )
+            })?),
+        };
+        let mut lines = game_lines(game_dir)?;
+        lines.extend(settings.added_lines.iter().map(AddedLine::to_game_line));
+        let placeholders =
+            generate_placeholders(&sample_dir, &lines, backend.as_ref(), &scheduler).await?;
+        sample_dir.add_stand_ins(placeholders);
+        let coverage = CoverageReport::new(&lines, &sample_dir);
+        log(format!(
+            "{} of {} lines recorded ({:.1}%); {} placeholders",
+            coverage.total.recorded,
+            coverage.total.lines,
+            coverage.total.percent_recorded(),
+            coverage.total.placeholders
+        ));
+        Some(coverage)
+    } else {
+        None
+    };
+    let partial_report = if settings.partial {
+        let (Some(game_dir), Some(original_audio)) =
+            (settings.game_dir.as_deref(), original_audio.as_deref())
+        else {
+            anyhow::bail!("A partial build needs the game directory");
+        };
+        sample_dir.add_stand_ins(original_stand_ins(&sample_dir, original_audio)?);
+        let partial_report = PartialReport::new(sample_dir.samples(), &game_lines(game_dir)?);
+        log(format!(
+            "Partial build: {} lines dubbed, {} using the original audio",
+            partial_report.dubbed, partial_report.original
+        ));
+        Some(partial_report)
+    } else {
+        None
+    };
+    let message_patches = if settings.added_lines.is_empty() {
+        Vec::new()
+    } else {
+        let game_dir = settings
+            .game_dir
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Added lines need the game directory"))?;
+        log(format!(
+            "Adding {} lines to the game's messages",
+            settings.added_lines.len()
+        ));
+        message_patches(&open_game_resources(game_dir)?, &settings.added_lines)?
+    };
+    let new_speech_guide = if settings.new_speech {
+        let game_dir = settings
+            .game_dir
+            .as_deref()
+            .expect("checked at the start of the build");
+        let mut lines = game_lines(game_dir)?;
+        lines.extend(settings.added_lines.iter().map(AddedLine::to_game_line));
+        let unmatched = unmatched_samples(&sample_dir, &lines);
+        for key in &unmatched {
+            log(format!(
+                "Warning: sample {key} isn't in the game's messages, so it will never play"
+            ));
+        }
+        let mut room_lines = BTreeMap::new();
+        for sample in sample_dir.samples() {
+            *room_lines.entry(sample.room).or_insert(0) += 1;
+        }
+        Some(setup_guide(&room_lines, &unmatched))
+    } else {
+        None
+    };
+    let audio_index = settings.new_speech.then(empty_audio_index);
+    let mut report = BuildReport::new(profile_name, "resource.aud");
+    let resources = match sample_dir
+        .to_audio_resources(
+            &ffmpeg_tool,
+            &BuildOptions {
+                profile,
+                original: original_audio.as_deref(),
+                stages: &stages,
+                cleanup: &cleanup,
+                effects: &effects,
+                overrides: &overrides,
+                max_downmix_loss_db: config.gate.max_downmix_loss_db,
+                declip: &config.declip,
+                remap: &remap,
+                measure_durations: settings.subtitle_timing,
+            },
+            &scheduler,
+            Some(&mut checkpoint),
+            Some(&mut report),
+        )
+        .await
+    {
+        Ok(resources) => resources,
+        Err(e) if e.is::<Cancelled>() => {
+            log(format!(
+                "Build cancelled after converting {} samples. Resume the build to continue.",
+                checkpoint.num_complete()
+            ));
+            return Err(e);
+        }
+        Err(e) => {
+            if let Some(BatchFailed(report)) = e.downcast_ref::<BatchFailed>() {
+                let report_path = output_dir.join("build-report.json");
+                std::fs::write(&report_path, serde_json::to_vec_pretty(report)?)?;
+                for failure in &report.failures {
+                    log(format!(
+                        "Sample {} failed after {} attempts: {}",
+                        failure.key, failure.attempts, failure.error
+                    ));
+                }
+                log(format!(
+                    "Build report written to {}. Resume the build to retry the failed samples.",
+                    report_path.display()
+                ));
+            }
+            return Err(e);
+        }
+    };
+
+    futures::try_join!(
+        write_output(
+            output_dir.join("resource.aud"),
+            staging_dir,
+            modified,
+            log,
+            async |file| resources.audio_volume().write_to_async(file).await
+        )
+        .boxed_local(),
+        execute_all(
+            resources
+                .map_resources()
+                .iter()
+                .chain(&message_patches)
+                .chain(&audio_index)
+                .map(|res| {
+                    async move {
+                        let file = PathBuf::from(format!(
+                            "{}.{}",
+                            res.id().resource_num(),
+                            res.id().type_id().to_file_ext()
+                        ));
+                        write_output(
+                            output_dir.join(&file),
+                            staging_dir,
+                            modified,
+                            log,
+                            async |open_file| res.write_patch(open_file).await,
+                        )
+                        .await
+                    }
+                    .boxed_local()
+                })
+        )
+    )?;
+    checkpoint.finish()?;
+    let timing = if settings.subtitle_timing {
+        let (Some(game_dir), Some(original_audio)) =
+            (settings.game_dir.as_deref(), original_audio.as_deref())
+        else {
+            unreachable!("checked at the start of the build");
+        };
+        let lines: Vec<_> = report
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let (room, message_id) = line
+                    .packed_as
+                    .as_deref()
+                    .and_then(parse_sample_key)
+                    .unwrap_or((line.room, line.message_id));
+                let duration = std::time::Duration::from_millis(line.duration_ms?);
+                Some((room, message_id, duration))
+            })
+            .collect();
+        let timing = subtitle_timing(
+            &lines,
+            original_audio,
+            game_dir,
+            &ffmpeg_tool,
+            scheduler.cancellation(),
+        )
+        .await?;
+        execute_all(timing.patches.iter().map(|patch| {
+            write_output(
+                output_dir.join(&patch.file_name),
+                staging_dir,
+                modified,
+                log,
+                async |file| Ok(file.write_all(&write_sync36_patch(&patch.cues)?).await?),
+            )
+            .boxed_local()
+        }))
+        .await?;
+        log(format!(
+            "Subtitle timing: {} lines changed duration; rescaled the lip sync of {}",
+            timing.lines.len(),
+            timing.patches.len()
+        ));
+        Some(timing)
+    } else {
+        None
+    };
+    let num_warnings = report.num_warnings();
+    report.write(output_dir)?;
+    if let Some(coverage) = &coverage {
+        coverage.write(output_dir)?;
+    }
+    if let Some(timing) = &timing {
+        timing.write(output_dir)?;
+    }
+    if let Some(partial_report) = &partial_report {
+        partial_report.write(output_dir)?;
+        log(format!(
+            "In-game checklist written to {}",
+            output_dir.join("partial-report.md").display()
+        ));
+    }
+    if let Some(guide) = &new_speech_guide {
+        std::fs::write(output_dir.join(SETUP_FILE), guide)?;
+        log(format!(
+            "New speech: see {} for the interpreter changes it needs",
+            output_dir.join(SETUP_FILE).display()
+        ));
+    }
+    if num_warnings > 0 {
+        log(format!(
+            "{} warnings; see {}",
+            num_warnings,
+            output_dir.join("qa-report.html").display()
+        ));
+    }
+    // Record what was packed, so `status` can tell when takes change.
+    BuildFingerprints::new(fingerprint_samples(&sample_dir, &ffmpeg_tool, &scheduler).await?)
+        .save(output_dir)?;
+    if let Some(archive_dir) = &settings.archive {
+        write_archive(
+            &sample_dir,
+            archive_dir,
+            &settings.archive_profile,
+            archive_profile,
+            &ffmpeg_tool,
+            &scheduler,
+        )
+        .await?;
+    }
+    // Don't leave a report from an earlier failed run behind.
+    match std::fs::remove_file(output_dir.join("build-report.json")) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    if let Some(modified) = modified {
+        // The game files were given the time as they were written.
+        for name in REPORT_FILES {
+            let path = output_dir.join(name);
+            if path.exists() {
+                fs::set_modified(&path, modified)?;
+            }
+        }
+        if let Some(archive_dir) = &settings.archive {
+            fs::set_modified_recursive(archive_dir, modified)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Cooperative cancellation for long-running pipelines.
+//!
+//! A [`CancellationToken`] is shared between the code that decides to stop
+//! (e.g. a Ctrl-C handler) and the code doing the work. Workers either poll
+//! [`CancellationToken::check`] between steps, or race their work against
+//! [`CancellationToken::cancelled`] so they can clean up (e.g. kill child
+//! processes) as soon as cancellation is requested.
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use event_listener::Event;
+use futures::StreamExt;
+
+/// The error returned when an operation stops because it was cancelled.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Operation was cancelled")]
+pub struct Cancelled;
+
+struct Inner {
+    cancelled: AtomicBool,
+    event: Event,
+}
+
+#[derive(Clone)]
+pub struct CancellationToken(Arc<Inner>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken(Arc::new(Inner {
+            cancelled: AtomicBool::new(false),
+            event: Event::new(),
+        }))
+    }
+
+    /// Requests cancellation. All current and future waiters are woken.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.event.notify(usize::MAX);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns an error if cancellation has been requested.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resolves once cancellation has been requested.
+    pub async fn cancelled(&self) {
+        loop {
+            if self.is_cancelled() {
+                return;
+            }
+            let listener = self.0.event.listen();
+            // Check again, in case we were cancelled between the check above
+            // and registering the listener.
+            if self.is_cancelled() {
+                return;
+            }
+            listener.await;
+        }
+    }
+
+    /// Runs the future to completion, unless cancellation is requested first,
+    /// in which case the future is dropped.
+    pub async fn run_until_cancelled<F>(&self, fut: F) -> Result<F::Output, Cancelled>
+    where
+        F: Future,
+    {
+        smol::future::or(async { Ok(fut.await) }, async {
+            self.cancelled().await;
+            Err(Cancelled)
+        })
+        .await
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+/// Cancels the token when the user presses Ctrl-C.
+///
+/// A second Ctrl-C exits the process immediately, for when cleanup itself
+/// gets stuck.
+pub fn cancel_on_ctrl_c(token: CancellationToken) -> anyhow::Result<smol::Task<()>> {
+    let mut signals = async_signal::Signals::new([async_signal::Signal::Int])?;
+    Ok(smol::spawn(async move {
+        let mut seen_interrupt = false;
+        while let Some(signal) = signals.next().await {
+            if signal.is_err() {
+                continue;
+            }
+            if seen_interrupt {
+                eprintln!("Interrupted again; exiting without cleanup.");
+                std::process::exit(130);
+            }
+            seen_interrupt = true;
+            eprintln!("Interrupted; stopping after cleanup. Press Ctrl-C again to force quit.");
+            token.cancel();
+        }
+    }))
+}
@@ -0,0 +1,146 @@
+//! Cleanup filter presets (e.g. a noise gate), applied during conversion so
+//! home recordings from different actors get consistent treatment.
+//!
+//! Presets are chosen per line in `samples.json` (`"cleanup": ["gate"]`), or
+//! per role in the project's `fan-dub.json`:
+//!
+//! ```json
+//! { "cleanup": { "roles": { "narrator": ["highpass", "de-esser"] } } }
+//! ```
+//!
+//! A line's own list replaces its role's. Projects can also define presets of
+//! their own as ffmpeg filter graphs, under `"presets"`.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::resources::Sample;
+
+const BUILTIN_PRESETS: &[(&str, &str)] = &[
+    // Removes rumble and handling noise below the range of speech.
+    ("highpass", "highpass=f=80"),
+    // Silences room noise between phrases.
+    ("gate", "agate=threshold=0.01:ratio=4:attack=5:release=150"),
+    // Tames harsh sibilance.
+    ("de-esser", "deesser=i=0.4"),
+    // Removes clicks, e.g. from mouth noise.
+    ("de-click", "adeclick"),
+];
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CleanupConfig {
+    /// Presets defined by the project, as ffmpeg filter graphs.
+    #[serde(default)]
+    pub presets: BTreeMap<String, String>,
+    /// The presets applied to each role's lines.
+    #[serde(default)]
+    pub roles: BTreeMap<String, Vec<String>>,
+}
+
+/// Resolves the cleanup presets for each sample.
+pub struct CleanupPresets {
+    presets: BTreeMap<String, String>,
+    roles: BTreeMap<String, Vec<String>>,
+}
+
+impl CleanupPresets {
+    pub fn from_config(config: &CleanupConfig) -> anyhow::Result<Self> {
+        let mut presets: BTreeMap<String, String> = BUILTIN_PRESETS
+            .iter()
+            .map(|(name, filter)| (name.to_string(), filter.to_string()))
+            .collect();
+        presets.extend(config.presets.clone());
+        let cleanup = CleanupPresets {
+            presets,
+            roles: config.roles.clone(),
+        };
+        for names in cleanup.roles.values() {
+            cleanup.filter_graph(names)?;
+        }
+        Ok(cleanup)
+    }
+
+    fn filter_graph(&self, names: &[String]) -> anyhow::Result<Option<String>> {
+        let filters = names
+            .iter()
+            .map(|name| {
+                self.presets.get(name).map(String::as_str).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown cleanup preset {name:?}. Available presets: {}",
+                        self.presets
+                            .keys()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((!filters.is_empty()).then(|| filters.join(",")))
+    }
+
+    /// The cleanup filter graph for the sample, if any presets apply.
+    pub fn filter_for(&self, sample: &Sample) -> anyhow::Result<Option<String>> {
+        if !sample.cleanup.is_empty() {
+            return self.filter_graph(&sample.cleanup);
+        }
+        match sample.role.as_ref().and_then(|role| self.roles.get(role)) {
+            Some(names) => self.filter_graph(names),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(json: &str) -> Sample {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn config() -> CleanupConfig {
+        serde_json::from_str(
+            r#"{
+                "presets": { "loud-gate": "agate=threshold=0.05" },
+                "roles": { "narrator": ["highpass", "loud-gate"] }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_role_presets() -> anyhow::Result<()> {
+        let cleanup = CleanupPresets::from_config(&config())?;
+        let narrator = sample(
+            r#"{ "room": 1, "message_id": { "noun": 1, "verb": 1, "condition": 0, "sequence": 1 },
+                 "clip": { "path": "a.wav" }, "role": "narrator" }"#,
+        );
+        assert_eq!(
+            cleanup.filter_for(&narrator)?.as_deref(),
+            Some("highpass=f=80,agate=threshold=0.05")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_presets_replace_role_presets() -> anyhow::Result<()> {
+        let cleanup = CleanupPresets::from_config(&config())?;
+        let line = sample(
+            r#"{ "room": 1, "message_id": { "noun": 1, "verb": 1, "condition": 0, "sequence": 1 },
+                 "clip": { "path": "a.wav" }, "role": "narrator", "cleanup": ["de-click"] }"#,
+        );
+        assert_eq!(cleanup.filter_for(&line)?.as_deref(), Some("adeclick"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_preset() {
+        let config = CleanupConfig {
+            presets: BTreeMap::new(),
+            roles: BTreeMap::from([("narrator".to_string(), vec!["mystery".to_string()])]),
+        };
+        assert!(CleanupPresets::from_config(&config).is_err());
+    }
+}
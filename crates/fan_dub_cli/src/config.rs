@@ -0,0 +1,67 @@
+//! Per-project configuration, read from `fan-dub.json` in the sample
+//! directory.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cleanup::CleanupConfig, cues::CueConfig, declip::DeclipConfig, effects::EffectConfig,
+    gate::GateConfig, overrides::LineOverride, placeholder::PlaceholderConfig,
+    profile::ConversionProfile, remap::RemapRule, stage::StageConfig,
+};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FanDubConfig {
+    /// Conversion profiles, in addition to the built-in ones.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ConversionProfile>,
+    /// Custom processing stages, run in order between conversion and
+    /// packing.
+    #[serde(default)]
+    pub stages: Vec<StageConfig>,
+    /// Cleanup presets, and which roles they apply to.
+    #[serde(default)]
+    pub cleanup: CleanupConfig,
+    /// Effect presets, and which roles and lines they apply to.
+    #[serde(default)]
+    pub effects: EffectConfig,
+    /// The thresholds checked by the quality gate.
+    #[serde(default)]
+    pub gate: GateConfig,
+    /// Whether the build repairs clipped takes.
+    #[serde(default)]
+    pub declip: DeclipConfig,
+    /// The text-to-speech command for placeholder lines. Defaults to eSpeak
+    /// or `say`.
+    #[serde(default)]
+    pub placeholders: Option<PlaceholderConfig>,
+    /// The mouth cel for each phoneme name, for imported lip sync. Defaults
+    /// to Papagayo's Preston Blair set.
+    #[serde(default)]
+    pub visemes: Option<BTreeMap<String, u16>>,
+    /// The gaps and frame rate of exported cue sheets.
+    #[serde(default)]
+    pub cues: CueConfig,
+    /// Changes to the processing of particular lines, by line key.
+    #[serde(default)]
+    pub lines: BTreeMap<String, LineOverride>,
+    /// Rules moving takes to other lines when they are packed, for games
+    /// whose message tuples changed between versions.
+    #[serde(default)]
+    pub remap: Vec<RemapRule>,
+}
+
+impl FanDubConfig {
+    const FILE_NAME: &str = "fan-dub.json";
+
+    /// Loads the config in the directory. A missing file is treated as an
+    /// empty config.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        match std::fs::read(dir.join(Self::FILE_NAME)) {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FanDubConfig::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Exporting conversations as cue sheets and edit decision lists, for video
+//! editors cutting trailers and other footage to the dub.
+//!
+//! Each conversation (the lines of a room with the same noun, verb and
+//! condition) gets three files, named like `room-10-n1-v2-c0`:
+//!
+//! - a WAV file of its selected takes, one after another with a gap between
+//!   them,
+//! - a CUE sheet for that file, with a track per line, and
+//! - a CMX 3600 EDL placing each take on a timeline, for editors that
+//!   conform from the takes themselves.
+//!
+//! Lines are placed by the lengths of their trimmed takes. The gaps, and the
+//! EDL's frame rate, are set in `fan-dub.json` under `cues`.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    daw::ConversationFilter,
+    render::room_conversations,
+    resources::{SampleDir, sample_key},
+    tools::ffmpeg::{FfmpegTool, OutputFormat, SequencePart},
+};
+
+/// CUE sheets count time in CD frames.
+const CUE_FRAMES_PER_SECOND: f64 = 75.0;
+
+/// A CUE sheet holds at most 99 tracks.
+const MAX_CUE_TRACKS: usize = 99;
+
+/// Where an EDL's record timecode starts, by convention.
+const EDL_RECORD_START_SECS: f64 = 3600.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CueConfig {
+    /// The silence before the first line, in seconds.
+    pub lead_in_secs: f64,
+    /// The silence between lines, in seconds.
+    pub line_gap_secs: f64,
+    /// The frame rate of the EDL's timecodes.
+    pub frame_rate: u32,
+}
+
+impl Default for CueConfig {
+    fn default() -> Self {
+        CueConfig {
+            lead_in_secs: 0.0,
+            line_gap_secs: 0.3,
+            frame_rate: 30,
+        }
+    }
+}
+
+/// A line's take, and where it is placed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueLine {
+    /// The line's key (`<room>-<noun>-<verb>-<condition>-<sequence>`).
+    pub key: String,
+    pub role: Option<String>,
+    /// The take's file.
+    pub take: PathBuf,
+    /// Where the take is trimmed to start.
+    pub source_in_secs: f64,
+    pub length_secs: f64,
+    /// Where the line starts in the conversation.
+    pub start_secs: f64,
+}
+
+/// The placement of a conversation's lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueSheet {
+    pub name: String,
+    pub lines: Vec<CueLine>,
+}
+
+/// A take to place, before its position is known.
+pub struct CueTake {
+    pub key: String,
+    pub role: Option<String>,
+    pub take: PathBuf,
+    pub source_in_secs: f64,
+    pub length_secs: f64,
+}
+
+impl CueSheet {
+    /// Places the takes one after another, in order, with the configured
+    /// gaps.
+    pub fn layout(name: String, takes: Vec<CueTake>, config: &CueConfig) -> Self {
+        let mut start_secs = config.lead_in_secs;
+        let lines = takes
+            .into_iter()
+            .map(|take| {
+                let line = CueLine {
+                    key: take.key,
+                    role: take.role,
+                    take: take.take,
+                    source_in_secs: take.source_in_secs,
+                    length_secs: take.length_secs,
+                    start_secs,
+                };
+                start_secs += take.length_secs + config.line_gap_secs;
+                line
+            })
+            .collect();
+        CueSheet { name, lines }
+    }
+
+    pub fn audio_file_name(&self) -> String {
+        format!("{}.wav", self.name)
+    }
+
+    /// Writes the sheet as a CUE sheet for the conversation's audio file.
+    pub fn to_cue(&self) -> anyhow::Result<String> {
+        anyhow::ensure!(
+            self.lines.len() <= MAX_CUE_TRACKS,
+            "{} has {} lines, but a CUE sheet holds at most {MAX_CUE_TRACKS}",
+            self.name,
+            self.lines.len()
+        );
+        let mut cue = String::new();
+        writeln!(cue, "TITLE \"{}\"", cue_text(&self.name)).unwrap();
+        writeln!(cue, "FILE \"{}\" WAVE", cue_text(&self.audio_file_name())).unwrap();
+        for (i, line) in self.lines.iter().enumerate() {
+            writeln!(cue, "  TRACK {:02} AUDIO", i + 1).unwrap();
+            writeln!(cue, "    TITLE \"{}\"", cue_text(&line.key)).unwrap();
+            if let Some(role) = &line.role {
+                writeln!(cue, "    PERFORMER \"{}\"", cue_text(role)).unwrap();
+            }
+            writeln!(cue, "    INDEX 01 {}", cue_time(line.start_secs)).unwrap();
+        }
+        Ok(cue)
+    }
+
+    /// Writes the sheet as a CMX 3600 EDL, with an event per take.
+    pub fn to_edl(&self, frame_rate: u32) -> String {
+        let mut edl = String::new();
+        writeln!(edl, "TITLE: {}", self.name).unwrap();
+        writeln!(edl, "FCM: NON-DROP FRAME").unwrap();
+        for (i, line) in self.lines.iter().enumerate() {
+            let record_in = EDL_RECORD_START_SECS + line.start_secs;
+            writeln!(edl).unwrap();
+            writeln!(
+                edl,
+                "{:03}  AX       A     C        {} {} {} {}",
+                i + 1,
+                timecode(line.source_in_secs, frame_rate),
+                timecode(line.source_in_secs + line.length_secs, frame_rate),
+                timecode(record_in, frame_rate),
+                timecode(record_in + line.length_secs, frame_rate),
+            )
+            .unwrap();
+            let clip_name = line
+                .take
+                .file_name()
+                .map_or_else(|| line.key.clone(), |name| name.to_string_lossy().into());
+            writeln!(edl, "* FROM CLIP NAME: {clip_name}").unwrap();
+            writeln!(edl, "* COMMENT: {}", line.key).unwrap();
+        }
+        edl
+    }
+}
+
+/// Quotes can't be escaped in CUE sheets.
+fn cue_text(text: &str) -> String {
+    text.replace('"', "'")
+}
+
+/// Formats a time as CUE's `mm:ss:ff`, rounded to the nearest frame.
+fn cue_time(secs: f64) -> String {
+    let frames = (secs * CUE_FRAMES_PER_SECOND).round() as u64;
+    let per_second = CUE_FRAMES_PER_SECOND as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / per_second / 60,
+        frames / per_second % 60,
+        frames % per_second
+    )
+}
+
+/// Formats a time as a non-drop-frame `hh:mm:ss:ff` timecode, rounded to
+/// the nearest frame.
+fn timecode(secs: f64, frame_rate: u32) -> String {
+    let frame_rate = u64::from(frame_rate.max(1));
+    let frames = (secs * frame_rate as f64).round() as u64;
+    let seconds = frames / frame_rate;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frames % frame_rate
+    )
+}
+
+fn secs(us: u64) -> f64 {
+    us as f64 / 1_000_000.0
+}
+
+/// Renders each dubbed conversation to a WAV file in `output_dir`, with a
+/// CUE sheet and an EDL beside it. Only the conversations in `room` are
+/// exported, if it is given.
+pub async fn export_cues(
+    sample_dir: &SampleDir,
+    room: Option<u16>,
+    config: &CueConfig,
+    output_dir: &Path,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<CueSheet>> {
+    let rooms: BTreeSet<u16> = sample_dir
+        .samples()
+        .map(|sample| sample.room)
+        .filter(|sample_room| room.is_none_or(|room| room == *sample_room))
+        .collect();
+    anyhow::ensure!(!rooms.is_empty(), "No dubbed lines found");
+    std::fs::create_dir_all(output_dir)?;
+
+    let mut sheets = Vec::new();
+    for room in rooms {
+        for conversation in room_conversations(sample_dir, room) {
+            let name = ConversationFilter {
+                room,
+                noun: Some(conversation.noun),
+                verb: Some(conversation.verb),
+                condition: Some(conversation.condition),
+            }
+            .name();
+            let mut takes = Vec::new();
+            let mut parts = Vec::new();
+            for sample in &conversation.samples {
+                let path = sample.clip_path(sample_dir.base_path())?;
+                let file_secs = ffmpeg.measure_duration(&path, cancel).await?.as_secs_f64();
+                let source_in_secs = sample.clip.start_us.map_or(0.0, secs);
+                let source_out_secs = sample.clip.end_us.map_or(file_secs, secs).min(file_secs);
+                let gap_secs = if takes.is_empty() {
+                    config.lead_in_secs
+                } else {
+                    config.line_gap_secs
+                };
+                if gap_secs > 0.0 {
+                    parts.push(SequencePart::Silence(Duration::from_secs_f64(gap_secs)));
+                }
+                takes.push(CueTake {
+                    key: sample_key(room, &sample.message_id),
+                    role: sample.role.clone(),
+                    take: sample.clip.path.clone(),
+                    source_in_secs,
+                    length_secs: (source_out_secs - source_in_secs).max(0.0),
+                });
+                parts.push(SequencePart::Clip {
+                    path,
+                    start_us: sample.clip.start_us,
+                    end_us: sample.clip.end_us,
+                });
+            }
+            let sheet = CueSheet::layout(name, takes, config);
+            ffmpeg
+                .render_sequence(
+                    &parts,
+                    &output_dir.join(sheet.audio_file_name()),
+                    OutputFormat::Wav,
+                    cancel,
+                )
+                .await?;
+            std::fs::write(
+                output_dir.join(format!("{}.cue", sheet.name)),
+                sheet.to_cue()?,
+            )?;
+            std::fs::write(
+                output_dir.join(format!("{}.edl", sheet.name)),
+                sheet.to_edl(config.frame_rate),
+            )?;
+            sheets.push(sheet);
+        }
+    }
+    Ok(sheets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(key: &str, source_in_secs: f64, length_secs: f64) -> CueTake {
+        CueTake {
+            key: key.to_string(),
+            role: Some("Narrator".to_string()),
+            take: PathBuf::from(format!("takes/{key}.wav")),
+            source_in_secs,
+            length_secs,
+        }
+    }
+
+    fn test_sheet() -> CueSheet {
+        CueSheet::layout(
+            "room-10-n1-v2-c0".to_string(),
+            vec![take("10-1-2-0-1", 0.5, 2.0), take("10-1-2-0-2", 0.0, 1.5)],
+            &CueConfig {
+                lead_in_secs: 1.0,
+                line_gap_secs: 0.5,
+                frame_rate: 25,
+            },
+        )
+    }
+
+    #[test]
+    fn test_layout() {
+        let sheet = test_sheet();
+        assert_eq!(sheet.lines[0].start_secs, 1.0);
+        assert_eq!(sheet.lines[1].start_secs, 3.5);
+    }
+
+    #[test]
+    fn test_cue() {
+        let cue = test_sheet().to_cue().unwrap();
+        assert!(
+            cue.starts_with("TITLE \"room-10-n1-v2-c0\"\nFILE \"room-10-n1-v2-c0.wav\" WAVE\n")
+        );
+        assert!(cue.contains(
+            "  TRACK 02 AUDIO\n    TITLE \"10-1-2-0-2\"\n    PERFORMER \"Narrator\"\n    INDEX 01 00:03:38\n"
+        ));
+    }
+
+    #[test]
+    fn test_edl() {
+        let edl = test_sheet().to_edl(25);
+        assert!(edl.contains(
+            "001  AX       A     C        00:00:00:13 00:00:02:13 01:00:01:00 01:00:03:00\n* FROM CLIP NAME: 10-1-2-0-1.wav\n"
+        ));
+        assert!(edl.contains(
+            "002  AX       A     C        00:00:00:00 00:00:01:13 01:00:03:13 01:00:05:00\n"
+        ));
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(cue_time(61.5), "01:01:38");
+        assert_eq!(timecode(3725.5, 30), "01:02:05:15");
+    }
+}
@@ -0,0 +1,585 @@
+//! Exporting conversations as DAW projects, so engineers can fine-edit the
+//! dubbed lines against the original ones.
+//!
+//! An export is a directory holding a Reaper project (`.rpp`), the audio it
+//! uses under `media/`, and a `session.json` recording where each line was
+//! placed. The project has an "Original" and a "Dub" track, and a region per
+//! line named by the line's key (`<room>-<noun>-<verb>-<condition>-<sequence>`).
+//!
+//! Once the session has been edited and rendered to one file, the render is
+//! cut back into a take per line using the project's regions, so regions
+//! moved or resized in the DAW are honored.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    resources::{OriginalAudio, SampleDir, sample_key},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::ffmpeg::{self, FfmpegTool, OutputFormat},
+};
+
+/// The silence left between lines.
+const LINE_GAP: Duration = Duration::from_secs(1);
+
+/// Slices quieter than this (in dBFS) are taken to have nothing recorded.
+const SILENCE_DB: f64 = -60.0;
+
+/// The sample rate of the project. Sources at other rates are resampled by
+/// the DAW.
+const PROJECT_SAMPLE_RATE: u32 = 44100;
+
+/// Selects the lines of a conversation: all lines in a room, optionally
+/// narrowed by noun, verb and condition.
+#[derive(Debug, Clone, Copy)]
+pub struct ConversationFilter {
+    pub room: u16,
+    pub noun: Option<u8>,
+    pub verb: Option<u8>,
+    pub condition: Option<u8>,
+}
+
+impl ConversationFilter {
+    pub fn matches(&self, room: u16, message_id: &MessageId) -> bool {
+        room == self.room
+            && self.noun.is_none_or(|noun| noun == message_id.noun())
+            && self.verb.is_none_or(|verb| verb == message_id.verb())
+            && self
+                .condition
+                .is_none_or(|condition| condition == message_id.condition())
+    }
+
+    /// A name for the conversation, usable as a file name.
+    pub fn name(&self) -> String {
+        let mut name = format!("room-{}", self.room);
+        for (label, value) in [("n", self.noun), ("v", self.verb), ("c", self.condition)] {
+            if let Some(value) = value {
+                write!(name, "-{label}{value}").unwrap();
+            }
+        }
+        name
+    }
+}
+
+/// An audio file placed on a track.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrackClip {
+    /// The file, relative to the project directory.
+    pub file: PathBuf,
+    pub length_secs: f64,
+}
+
+/// A line's place in the project.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionLine {
+    pub room: u16,
+    pub message_id: MessageId,
+    pub position_secs: f64,
+    /// The length of the line's region: the longer of its clips.
+    pub length_secs: f64,
+    pub original: Option<TrackClip>,
+    pub dub: Option<TrackClip>,
+}
+
+impl SessionLine {
+    pub fn key(&self) -> String {
+        sample_key(self.room, &self.message_id)
+    }
+}
+
+/// The layout of an exported project.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub name: String,
+    pub lines: Vec<SessionLine>,
+}
+
+impl Session {
+    pub const FILE_NAME: &str = "session.json";
+
+    /// Places the lines one after another, in order, with a gap between
+    /// them.
+    fn layout(
+        name: String,
+        lines: Vec<(u16, MessageId, Option<TrackClip>, Option<TrackClip>)>,
+    ) -> Self {
+        let mut position_secs = 0.0;
+        let lines = lines
+            .into_iter()
+            .map(|(room, message_id, original, dub)| {
+                let length_secs = [&original, &dub]
+                    .into_iter()
+                    .flatten()
+                    .map(|clip| clip.length_secs)
+                    .fold(0.0, f64::max);
+                let line = SessionLine {
+                    room,
+                    message_id,
+                    position_secs,
+                    length_secs,
+                    original,
+                    dub,
+                };
+                position_secs += length_secs + LINE_GAP.as_secs_f64();
+                line
+            })
+            .collect();
+        Session { name, lines }
+    }
+
+    pub fn load(project_dir: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(
+            project_dir.join(Self::FILE_NAME),
+        )?)?)
+    }
+
+    pub fn save(&self, project_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            project_dir.join(Self::FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// The file name of the Reaper project.
+    pub fn rpp_file_name(&self) -> String {
+        format!("{}.rpp", self.name)
+    }
+
+    /// Writes the session as a Reaper project.
+    pub fn to_rpp(&self) -> String {
+        let mut rpp = String::new();
+        writeln!(rpp, "<REAPER_PROJECT 0.1 \"6.0\" 0").unwrap();
+        writeln!(rpp, "  SAMPLERATE {PROJECT_SAMPLE_RATE} 0 0").unwrap();
+        for (i, line) in self.lines.iter().enumerate() {
+            // A region is a pair of markers with the same index.
+            let index = i + 1;
+            writeln!(
+                rpp,
+                "  MARKER {index} {:.6} \"{}\" 1",
+                line.position_secs,
+                line.key()
+            )
+            .unwrap();
+            writeln!(
+                rpp,
+                "  MARKER {index} {:.6} \"\" 1",
+                line.position_secs + line.length_secs
+            )
+            .unwrap();
+        }
+        let originals = self.lines.iter().map(|line| (line, line.original.as_ref()));
+        write_rpp_track(&mut rpp, "Original", originals);
+        let dubs = self.lines.iter().map(|line| (line, line.dub.as_ref()));
+        write_rpp_track(&mut rpp, "Dub", dubs);
+        writeln!(rpp, ">").unwrap();
+        rpp
+    }
+}
+
+fn write_rpp_track<'a>(
+    rpp: &mut String,
+    name: &str,
+    clips: impl Iterator<Item = (&'a SessionLine, Option<&'a TrackClip>)>,
+) {
+    writeln!(rpp, "  <TRACK").unwrap();
+    writeln!(rpp, "    NAME \"{name}\"").unwrap();
+    for (line, clip) in clips {
+        let Some(clip) = clip else {
+            continue;
+        };
+        writeln!(rpp, "    <ITEM").unwrap();
+        writeln!(rpp, "      POSITION {:.6}", line.position_secs).unwrap();
+        writeln!(rpp, "      LENGTH {:.6}", clip.length_secs).unwrap();
+        writeln!(rpp, "      NAME \"{}\"", line.key()).unwrap();
+        writeln!(rpp, "      <SOURCE WAVE").unwrap();
+        // Reaper resolves relative paths against the project file.
+        writeln!(rpp, "        FILE \"{}\"", rpp_path(&clip.file)).unwrap();
+        writeln!(rpp, "      >").unwrap();
+        writeln!(rpp, "    >").unwrap();
+    }
+    writeln!(rpp, "  >").unwrap();
+}
+
+/// Formats a relative path with forward slashes, which Reaper accepts on all
+/// platforms.
+fn rpp_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A line's audio, converted to WAV in the project's media directory.
+struct PreparedLine {
+    room: u16,
+    message_id: MessageId,
+    original: Option<TrackClip>,
+    dub: Option<TrackClip>,
+}
+
+/// Exports the conversation's original clips and selected takes to a Reaper
+/// project in `project_dir`. The takes are trimmed, but otherwise left as
+/// recorded.
+pub async fn export_session(
+    sample_dir: &SampleDir,
+    original_audio: &OriginalAudio,
+    filter: &ConversationFilter,
+    project_dir: &Path,
+    ffmpeg: &FfmpegTool,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<Session> {
+    let mut message_ids = original_audio
+        .room_clips(filter.room)?
+        .into_iter()
+        .map(|(message_id, _)| message_id)
+        .filter(|message_id| filter.matches(filter.room, message_id))
+        .collect::<Vec<_>>();
+    message_ids.extend(
+        sample_dir
+            .samples()
+            .filter(|sample| filter.matches(sample.room, &sample.message_id))
+            .map(|sample| sample.message_id),
+    );
+    message_ids.sort();
+    message_ids.dedup();
+    anyhow::ensure!(
+        !message_ids.is_empty(),
+        "No lines found for {}",
+        filter.name()
+    );
+
+    for dir in ["original", "dub"] {
+        smol::fs::create_dir_all(project_dir.join("media").join(dir)).await?;
+    }
+    let cancel = scheduler.cancellation();
+    let room = filter.room;
+    let jobs = message_ids.iter().map(|message_id| {
+        let key = sample_key(room, message_id);
+        let job = {
+            let key = key.clone();
+            move || {
+                let key = key.clone();
+                async move {
+                    let original = match original_audio.clip(room, message_id)? {
+                        Some(clip) => {
+                            let file = Path::new("media/original").join(format!("{key}.wav"));
+                            let path = project_dir.join(&file);
+                            ffmpeg
+                                .convert(
+                                    ffmpeg::BytesInput::new(clip),
+                                    &path,
+                                    OutputFormat::Wav,
+                                    &mut ffmpeg::NullProgressListener,
+                                    cancel,
+                                )
+                                .await?;
+                            let length = ffmpeg.measure_duration(&path, cancel).await?;
+                            Some(TrackClip {
+                                file,
+                                length_secs: length.as_secs_f64(),
+                            })
+                        }
+                        None => None,
+                    };
+                    let dub = match sample_dir.sample(room, message_id) {
+                        Some(sample) => {
+                            let file = Path::new("media/dub").join(format!("{key}.wav"));
+                            let path = project_dir.join(&file);
+                            ffmpeg
+                                .convert_with_filter(
+                                    sample.clip_path(sample_dir.base_path())?,
+                                    &path,
+                                    OutputFormat::Wav,
+                                    sample.clip.trim_filter().as_deref(),
+                                    &mut ffmpeg::NullProgressListener,
+                                    cancel,
+                                )
+                                .await?;
+                            let length = ffmpeg.measure_duration(&path, cancel).await?;
+                            Some(TrackClip {
+                                file,
+                                length_secs: length.as_secs_f64(),
+                            })
+                        }
+                        None => None,
+                    };
+                    Ok::<_, anyhow::Error>(PreparedLine {
+                        room,
+                        message_id: *message_id,
+                        original,
+                        dub,
+                    })
+                }
+            }
+        };
+        (key, job)
+    });
+
+    let mut prepared = Vec::new();
+    let report = scheduler
+        .run(jobs, async |line: PreparedLine| {
+            prepared.push(line);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    prepared.sort_by_key(|line| line.message_id);
+
+    let session = Session::layout(
+        filter.name(),
+        prepared
+            .into_iter()
+            .map(|line| (line.room, line.message_id, line.original, line.dub))
+            .collect(),
+    );
+    session.save(project_dir)?;
+    std::fs::write(project_dir.join(session.rpp_file_name()), session.to_rpp())?;
+    Ok(session)
+}
+
+/// Reads the regions of a Reaper project, as the start and end time of each
+/// region by name.
+pub fn parse_rpp_regions(rpp: &str) -> anyhow::Result<BTreeMap<String, (f64, f64)>> {
+    // Regions are written as a pair of markers sharing an index: the start,
+    // which carries the name, then the end.
+    let mut starts: BTreeMap<u32, (String, f64)> = BTreeMap::new();
+    let mut regions = BTreeMap::new();
+    for line in rpp.lines() {
+        let Some(rest) = line.trim_start().strip_prefix("MARKER ") else {
+            continue;
+        };
+        let fields = split_rpp_fields(rest);
+        let [index, position, name, flags, ..] = fields.as_slice() else {
+            anyhow::bail!("Malformed marker: {}", line.trim());
+        };
+        let is_region = flags.parse::<u32>().is_ok_and(|flags| flags & 1 != 0);
+        if !is_region {
+            continue;
+        }
+        let index: u32 = index.parse()?;
+        let position: f64 = position.parse()?;
+        match starts.remove(&index) {
+            Some((name, start)) => {
+                regions.insert(name, (start, position));
+            }
+            None => {
+                starts.insert(index, (name.clone(), position));
+            }
+        }
+    }
+    if let Some((name, _)) = starts.into_values().next() {
+        anyhow::bail!("Region {name:?} has no end");
+    }
+    Ok(regions)
+}
+
+/// Splits a line of a Reaper project into fields. Fields containing spaces
+/// are quoted with `"`, `'` or `` ` ``.
+fn split_rpp_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|ch| matches!(ch, '"' | '\'' | '`'));
+        let (field, remainder) = match quote {
+            Some(quote) => {
+                let inner = &rest[1..];
+                match inner.find(quote) {
+                    Some(end) => (&inner[..end], &inner[end + 1..]),
+                    None => (inner, ""),
+                }
+            }
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        fields.push(field.to_string());
+        rest = remainder.trim_start();
+    }
+    fields
+}
+
+/// A take cut from a rendered session.
+#[derive(Debug)]
+pub struct ImportedTake {
+    pub room: u16,
+    pub message_id: MessageId,
+    /// The take, relative to the sample directory.
+    pub take: PathBuf,
+}
+
+/// Cuts a rendered session into a take per line, saved under
+/// `daw/<session name>/` in the sample directory. Lines are cut at the
+/// project's regions, or where they were exported if the project has no
+/// region for them. Silent lines are skipped. If `select` is set, the new
+/// takes are also selected.
+///
+/// Sessions with locked lines are refused, unless `force` is set.
+pub async fn import_session(
+    sample_dir: &mut SampleDir,
+    project_dir: &Path,
+    rendered: &Path,
+    select: bool,
+    force: bool,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<ImportedTake>> {
+    let session = Session::load(project_dir)?;
+    let locked = session
+        .lines
+        .iter()
+        .filter(|line| sample_dir.is_locked(line.room, &line.message_id))
+        .map(SessionLine::key)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(
+        force || locked.is_empty(),
+        "The session has locked lines ({}); use --force to import them anyway",
+        locked.join(", ")
+    );
+    let rpp_path = project_dir.join(session.rpp_file_name());
+    let regions = if rpp_path.exists() {
+        parse_rpp_regions(&std::fs::read_to_string(&rpp_path)?)?
+    } else {
+        BTreeMap::new()
+    };
+    let take_dir = Path::new("daw").join(&session.name);
+    smol::fs::create_dir_all(sample_dir.base_path().join(&take_dir)).await?;
+
+    let mut imported = Vec::new();
+    for line in &session.lines {
+        let key = line.key();
+        let (start, end) = regions
+            .get(&key)
+            .copied()
+            .unwrap_or((line.position_secs, line.position_secs + line.length_secs));
+        anyhow::ensure!(end > start, "The region for {key} is empty");
+        let take = sample_dir.new_take_path(&take_dir, line.room, &line.message_id, "wav")?;
+        let path = sample_dir.base_path().join(&take);
+        let audio_filter = format!("atrim=start={start:.6}:end={end:.6},asetpts=PTS-STARTPTS");
+        ffmpeg
+            .convert_with_filter(
+                rendered,
+                &path,
+                OutputFormat::Wav,
+                Some(&audio_filter),
+                &mut ffmpeg::NullProgressListener,
+                cancel,
+            )
+            .await?;
+        if ffmpeg.measure_loudness(&path, cancel).await? < SILENCE_DB {
+            smol::fs::remove_file(&path).await?;
+            continue;
+        }
+        imported.push(ImportedTake {
+            room: line.room,
+            message_id: line.message_id,
+            take,
+        });
+    }
+
+    if select {
+        for take in &imported {
+            sample_dir.select_take(take.room, take.message_id, take.take.clone());
+        }
+        sample_dir.save().await?;
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(file: &str, length_secs: f64) -> Option<TrackClip> {
+        Some(TrackClip {
+            file: PathBuf::from(file),
+            length_secs,
+        })
+    }
+
+    fn test_session() -> Session {
+        Session::layout(
+            "room-10".to_string(),
+            vec![
+                (
+                    10,
+                    MessageId::new(1, 2, 0, 1),
+                    clip("media/original/10-1-2-0-1.wav", 2.0),
+                    clip("media/dub/10-1-2-0-1.wav", 2.5),
+                ),
+                (
+                    10,
+                    MessageId::new(1, 2, 0, 2),
+                    clip("media/original/10-1-2-0-2.wav", 1.5),
+                    None,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_layout() {
+        let session = test_session();
+        assert_eq!(session.lines[0].position_secs, 0.0);
+        assert_eq!(session.lines[0].length_secs, 2.5);
+        assert_eq!(session.lines[1].position_secs, 3.5);
+        assert_eq!(session.lines[1].length_secs, 1.5);
+    }
+
+    #[test]
+    fn test_rpp() {
+        let rpp = test_session().to_rpp();
+        assert!(rpp.contains("  MARKER 1 0.000000 \"10-1-2-0-1\" 1\n  MARKER 1 2.500000 \"\" 1\n"));
+        assert!(rpp.contains("  MARKER 2 3.500000 \"10-1-2-0-2\" 1\n  MARKER 2 5.000000 \"\" 1\n"));
+        assert!(rpp.contains("FILE \"media/dub/10-1-2-0-1.wav\""));
+        assert!(!rpp.contains("media/dub/10-1-2-0-2.wav"));
+        assert_eq!(rpp.matches("<TRACK").count(), 2);
+        assert_eq!(rpp.matches("<ITEM").count(), 3);
+        assert!(rpp.ends_with(">\n"));
+    }
+
+    #[test]
+    fn test_rpp_regions_round_trip() {
+        let session = test_session();
+        let regions = parse_rpp_regions(&session.to_rpp()).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions["10-1-2-0-1"], (0.0, 2.5));
+        assert_eq!(regions["10-1-2-0-2"], (3.5, 5.0));
+    }
+
+    #[test]
+    fn test_rpp_regions_edited() {
+        // As saved by Reaper after the engineer moved a region and added a
+        // plain marker.
+        let rpp = "<REAPER_PROJECT 0.1 \"7.0\" 0\n  MARKER 1 0.25 10-1-2-0-1 1 0 1 B {ABC}\n  MARKER 1 2.75 \"\" 1\n  MARKER 2 3 \"breath here\" 0\n>\n";
+        let regions = parse_rpp_regions(rpp).unwrap();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions["10-1-2-0-1"], (0.25, 2.75));
+        assert!(parse_rpp_regions("  MARKER 1 0.25 \"x\" 1\n").is_err());
+    }
+
+    #[test]
+    fn test_filter_name() {
+        let filter = ConversationFilter {
+            room: 10,
+            noun: Some(1),
+            verb: None,
+            condition: Some(0),
+        };
+        assert_eq!(filter.name(), "room-10-n1-c0");
+        assert!(filter.matches(10, &MessageId::new(1, 2, 0, 1)));
+        assert!(!filter.matches(10, &MessageId::new(1, 2, 3, 1)));
+        assert!(!filter.matches(11, &MessageId::new(1, 2, 0, 1)));
+    }
+}
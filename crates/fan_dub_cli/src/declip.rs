@@ -0,0 +1,107 @@
+//! Finding clipped takes during a build, and optionally repairing them with
+//! ffmpeg's `adeclip` filter. Clipped takes are flagged in the build report
+//! either way, with the clipped samples counted before and after any repair.
+//! Repair is turned on in `fan-dub.json`:
+//!
+//! ```json
+//! { "declip": { "repair": true, "min_clipped_samples": 1 } }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    gate::measure_pcm,
+    tools::ffmpeg::{self, FfmpegTool, OutputFormat},
+};
+
+/// The filter that repairs clipped takes.
+pub const DECLIP_FILTER: &str = "adeclip";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DeclipConfig {
+    /// Repair clipped takes, instead of only flagging them.
+    pub repair: bool,
+    /// The fewest clipped samples that flag (or repair) a take.
+    pub min_clipped_samples: usize,
+}
+
+impl Default for DeclipConfig {
+    fn default() -> Self {
+        DeclipConfig {
+            repair: false,
+            min_clipped_samples: 1,
+        }
+    }
+}
+
+/// The clipping found in a take, and what repairing it did.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ClippingRepair {
+    /// The samples at full scale in the take, as recorded.
+    pub clipped_before: usize,
+    /// The samples at full scale after declipping, if it was repaired.
+    pub clipped_after: Option<usize>,
+}
+
+impl ClippingRepair {
+    pub fn repaired(&self) -> bool {
+        self.clipped_after.is_some()
+    }
+}
+
+/// Counts the samples at full scale in the file, after the filter. The file
+/// is decoded at its own rate, so resampling can't hide clipping.
+async fn count_clipped(
+    ffmpeg: &FfmpegTool,
+    path: &Path,
+    audio_filter: Option<&str>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<usize> {
+    let pcm = ffmpeg
+        .convert_with_filter(
+            path,
+            ffmpeg::VecOutput,
+            OutputFormat::RawS16Le,
+            audio_filter,
+            &mut ffmpeg::NullProgressListener,
+            cancel,
+        )
+        .await?;
+    let pcm: Vec<i16> = pcm
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+    Ok(measure_pcm(&pcm).0)
+}
+
+/// Checks a take for clipping, after the trim filter, and repairs it if the
+/// config says to. Returns `None` if the take isn't clipped.
+pub async fn check_clipping(
+    config: &DeclipConfig,
+    ffmpeg: &FfmpegTool,
+    path: &Path,
+    trim_filter: Option<&str>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Option<ClippingRepair>> {
+    let clipped_before = count_clipped(ffmpeg, path, trim_filter, cancel).await?;
+    if clipped_before == 0 || clipped_before < config.min_clipped_samples {
+        return Ok(None);
+    }
+    let clipped_after = if config.repair {
+        let filter = match trim_filter {
+            Some(trim_filter) => format!("{trim_filter},{DECLIP_FILTER}"),
+            None => DECLIP_FILTER.to_string(),
+        };
+        Some(count_clipped(ffmpeg, path, Some(&filter), cancel).await?)
+    } else {
+        None
+    };
+    Ok(Some(ClippingRepair {
+        clipped_before,
+        clipped_after,
+    }))
+}
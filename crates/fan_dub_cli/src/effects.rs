@@ -0,0 +1,182 @@
+//! Effect presets (e.g. a radio or a robot voice), applied during conversion
+//! so a character's lines all get the same treatment.
+//!
+//! Presets are chosen per role or per line (by line key) in the project's
+//! `fan-dub.json`:
+//!
+//! ```json
+//! { "effects": { "roles": { "robot": ["robot"] }, "lines": { "10-1-2-0-1": ["telephone"] } } }
+//! ```
+//!
+//! A line's own list replaces its role's. Projects can also define presets of
+//! their own as ffmpeg filter graphs, under `"presets"`. Original recordings
+//! standing in for unrecorded lines already have their effects, and get none.
+
+use std::collections::BTreeMap;
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::{Sample, StandIn, parse_sample_key};
+
+const BUILTIN_PRESETS: &[(&str, &str)] = &[
+    // A two-way radio: a narrow band, squashed and driven.
+    (
+        "radio",
+        "highpass=f=500,lowpass=f=3500,acompressor=threshold=0.1:ratio=8,volume=4dB",
+    ),
+    // A telephone line: the classic 300-3400 Hz band, with a little grit.
+    (
+        "telephone",
+        "highpass=f=300,lowpass=f=3400,acrusher=bits=10:mode=log:aa=1",
+    ),
+    // A robot: flattened phases (ffmpeg's "robotize"), with a metallic
+    // presence boost.
+    (
+        "robot",
+        "afftfilt=real='hypot(re,im)*sin(0)':imag='hypot(re,im)*cos(0)':win_size=512:overlap=0.75,equalizer=f=1800:t=q:w=1.5:g=6",
+    ),
+];
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EffectConfig {
+    /// Presets defined by the project, as ffmpeg filter graphs.
+    #[serde(default)]
+    pub presets: BTreeMap<String, String>,
+    /// The presets applied to each role's lines.
+    #[serde(default)]
+    pub roles: BTreeMap<String, Vec<String>>,
+    /// The presets applied to particular lines, by line key, replacing those
+    /// of their roles.
+    #[serde(default)]
+    pub lines: BTreeMap<String, Vec<String>>,
+}
+
+/// Resolves the effect presets for each sample.
+pub struct EffectPresets {
+    presets: BTreeMap<String, String>,
+    roles: BTreeMap<String, Vec<String>>,
+    lines: BTreeMap<(u16, MessageId), Vec<String>>,
+}
+
+impl EffectPresets {
+    pub fn from_config(config: &EffectConfig) -> anyhow::Result<Self> {
+        let mut presets: BTreeMap<String, String> = BUILTIN_PRESETS
+            .iter()
+            .map(|(name, filter)| (name.to_string(), filter.to_string()))
+            .collect();
+        presets.extend(config.presets.clone());
+        let lines = config
+            .lines
+            .iter()
+            .map(|(key, names)| {
+                let line_id = parse_sample_key(key).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid line key {key:?} in the effects; expected room-noun-verb-condition-sequence"
+                    )
+                })?;
+                Ok((line_id, names.clone()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let effects = EffectPresets {
+            presets,
+            roles: config.roles.clone(),
+            lines,
+        };
+        for names in effects.roles.values().chain(effects.lines.values()) {
+            effects.filter_graph(names)?;
+        }
+        Ok(effects)
+    }
+
+    fn filter_graph(&self, names: &[String]) -> anyhow::Result<Option<String>> {
+        let filters = names
+            .iter()
+            .map(|name| {
+                self.presets.get(name).map(String::as_str).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown effect preset {name:?}. Available presets: {}",
+                        self.presets
+                            .keys()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((!filters.is_empty()).then(|| filters.join(",")))
+    }
+
+    /// The effect filter graph for the sample, if any presets apply.
+    pub fn filter_for(&self, sample: &Sample) -> anyhow::Result<Option<String>> {
+        if sample.stand_in == Some(StandIn::Original) {
+            return Ok(None);
+        }
+        if let Some(names) = self.lines.get(&(sample.room, sample.message_id)) {
+            return self.filter_graph(names);
+        }
+        match sample.role.as_ref().and_then(|role| self.roles.get(role)) {
+            Some(names) => self.filter_graph(names),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sequence: u8, extra: &str) -> Sample {
+        serde_json::from_str(&format!(
+            r#"{{ "room": 10, "message_id": {{ "noun": 1, "verb": 2, "condition": 0, "sequence": {sequence} }},
+                 "clip": {{ "path": "a.wav" }}, "role": "robot"{extra} }}"#
+        ))
+        .unwrap()
+    }
+
+    fn config() -> EffectConfig {
+        serde_json::from_str(
+            r#"{
+                "presets": { "muffled": "lowpass=f=800" },
+                "roles": { "robot": ["robot"] },
+                "lines": { "10-1-2-0-2": ["telephone", "muffled"] }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_role_and_line_presets() -> anyhow::Result<()> {
+        let effects = EffectPresets::from_config(&config())?;
+        assert!(
+            effects
+                .filter_for(&sample(1, ""))?
+                .unwrap()
+                .starts_with("afftfilt=")
+        );
+        assert_eq!(
+            effects.filter_for(&sample(2, ""))?.as_deref(),
+            Some("highpass=f=300,lowpass=f=3400,acrusher=bits=10:mode=log:aa=1,lowpass=f=800")
+        );
+        assert_eq!(
+            effects.filter_for(&sample(1, r#", "stand_in": "original""#))?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = EffectConfig {
+            roles: BTreeMap::from([("robot".to_string(), vec!["mystery".to_string()])]),
+            ..EffectConfig::default()
+        };
+        assert!(EffectPresets::from_config(&config).is_err());
+        let config = EffectConfig {
+            lines: BTreeMap::from([("10-1-2".to_string(), vec!["radio".to_string()])]),
+            ..EffectConfig::default()
+        };
+        assert!(EffectPresets::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Content fingerprints of takes, used to spot duplicate recordings, and
+//! builds that no longer match the selected takes.
+//!
+//! A fingerprint is a hash of the take's decoded audio, after trimming and
+//! conversion to a fixed sample format, so it doesn't depend on the file name
+//! or container the take was delivered in.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cancel::CancellationToken,
+    resources::{Sample, SampleDir},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::ffmpeg::{self, FfmpegTool, OutputFormat},
+};
+
+/// Fingerprints one sample's take.
+async fn fingerprint_take(
+    sample: &Sample,
+    base_path: &Path,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<String> {
+    let filters = [
+        sample.clip.trim_filter(),
+        Some("aresample=22050,aformat=channel_layouts=mono".to_string()),
+    ];
+    let audio_filter = filters.into_iter().flatten().collect::<Vec<_>>().join(",");
+    let pcm = ffmpeg
+        .convert_with_filter(
+            sample.clip_path(base_path)?,
+            ffmpeg::VecOutput,
+            OutputFormat::RawS16Le,
+            Some(&audio_filter),
+            &mut ffmpeg::NullProgressListener,
+            cancel,
+        )
+        .await?;
+    Ok(Sha256::digest(&pcm)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Fingerprints the takes of all samples, keyed by sample key.
+pub async fn fingerprint_samples(
+    sample_dir: &SampleDir,
+    ffmpeg: &FfmpegTool,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let cancel = scheduler.cancellation();
+    let jobs = sample_dir.takes().map(|sample| {
+        let job = move || async move {
+            let fingerprint =
+                fingerprint_take(sample, sample_dir.base_path(), ffmpeg, cancel).await?;
+            Ok::<_, anyhow::Error>((sample.key(), fingerprint))
+        };
+        (sample.key(), job)
+    });
+    let mut fingerprints = BTreeMap::new();
+    let report = scheduler
+        .run(jobs, async |(key, fingerprint)| {
+            fingerprints.insert(key, fingerprint);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    Ok(fingerprints)
+}
+
+/// Groups samples whose takes have identical audio. Only groups with more
+/// than one sample are returned.
+pub fn find_duplicates(fingerprints: &BTreeMap<String, String>) -> Vec<Vec<&str>> {
+    let mut by_fingerprint: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (key, fingerprint) in fingerprints {
+        by_fingerprint
+            .entry(fingerprint.as_str())
+            .or_default()
+            .push(key.as_str());
+    }
+    by_fingerprint
+        .into_values()
+        .filter(|keys| keys.len() > 1)
+        .collect()
+}
+
+/// How a sample's current take compares to the one packed in the last build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drift {
+    /// The take has changed since it was built.
+    Changed,
+    /// The sample wasn't part of the last build.
+    NotBuilt,
+    /// The sample was built, but has since been removed.
+    Removed,
+}
+
+/// The fingerprints of the takes packed by a build, stored in the output
+/// directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildFingerprints {
+    takes: BTreeMap<String, String>,
+}
+
+impl BuildFingerprints {
+    const FILE_NAME: &str = "fingerprints.json";
+
+    pub fn new(takes: BTreeMap<String, String>) -> Self {
+        BuildFingerprints { takes }
+    }
+
+    /// The fingerprint of each packed take, by sample key.
+    pub fn takes(&self) -> &BTreeMap<String, String> {
+        &self.takes
+    }
+
+    fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(Self::FILE_NAME)
+    }
+
+    /// Loads the fingerprints of the last build, if there was one.
+    pub fn load(output_dir: &Path) -> anyhow::Result<Option<Self>> {
+        match std::fs::read(Self::path(output_dir)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, output_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(Self::path(output_dir), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Compares the current takes against the built ones, returning the
+    /// samples that differ.
+    pub fn drift<'a>(&'a self, current: &'a BTreeMap<String, String>) -> Vec<(&'a str, Drift)> {
+        let mut drift = Vec::new();
+        for (key, fingerprint) in current {
+            match self.takes.get(key) {
+                Some(built) if built == fingerprint => {}
+                Some(_) => drift.push((key.as_str(), Drift::Changed)),
+                None => drift.push((key.as_str(), Drift::NotBuilt)),
+            }
+        }
+        for key in self.takes.keys() {
+            if !current.contains_key(key) {
+                drift.push((key.as_str(), Drift::Removed));
+            }
+        }
+        drift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprints(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, fingerprint)| (key.to_string(), fingerprint.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let current = fingerprints(&[
+            ("1-1-1-0-1", "aa"),
+            ("1-1-1-0-2", "bb"),
+            ("2-3-1-0-1", "aa"),
+        ]);
+        assert_eq!(
+            find_duplicates(&current),
+            vec![vec!["1-1-1-0-1", "2-3-1-0-1"]]
+        );
+    }
+
+    #[test]
+    fn test_drift() {
+        let built = BuildFingerprints::new(fingerprints(&[
+            ("1-1-1-0-1", "aa"),
+            ("1-1-1-0-2", "bb"),
+            ("1-1-1-0-3", "cc"),
+        ]));
+        let current = fingerprints(&[
+            ("1-1-1-0-1", "aa"),
+            ("1-1-1-0-2", "b2"),
+            ("1-1-1-0-4", "dd"),
+        ]);
+        assert_eq!(
+            built.drift(&current),
+            vec![
+                ("1-1-1-0-2", Drift::Changed),
+                ("1-1-1-0-4", Drift::NotBuilt),
+                ("1-1-1-0-3", Drift::Removed),
+            ]
+        );
+    }
+}
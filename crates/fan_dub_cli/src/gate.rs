@@ -0,0 +1,456 @@
+//! A quality gate for the selected takes, meant to run in CI before
+//! packaging. The thresholds are configured in `fan-dub.json`:
+//!
+//! ```json
+//! { "gate": { "max_clipped_samples": 0, "max_duration_delta": 0.5,
+//!             "min_loudness_db": -35.0, "max_loudness_db": -10.0,
+//!             "min_sample_rate": 22050, "max_downmix_loss_db": 6.0 } }
+//! ```
+//!
+//! Any threshold can be set to `null` to skip that check.
+//!
+//! The downmix check catches pseudo-stereo takes whose channels are out of
+//! phase, so they cancel out when mixed to mono. Builds with a mono profile
+//! make such takes from their loudest channel alone instead.
+
+use std::{fmt, path::PathBuf};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resources::{OriginalAudio, SampleDir},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::{
+        ffmpeg::{self, FfmpegTool, OutputFormat},
+        ffprobe::FfprobeTool,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct GateConfig {
+    /// The most samples a take may have at full scale.
+    pub max_clipped_samples: Option<usize>,
+    /// The largest difference in length from the original line, as a
+    /// fraction of the original's length.
+    pub max_duration_delta: Option<f64>,
+    /// The quietest mean volume allowed, in dBFS.
+    pub min_loudness_db: Option<f64>,
+    /// The loudest mean volume allowed, in dBFS.
+    pub max_loudness_db: Option<f64>,
+    /// The lowest sample rate allowed for recordings.
+    pub min_sample_rate: Option<u32>,
+    /// The most a take may lose when downmixed to mono, in dB.
+    pub max_downmix_loss_db: Option<f64>,
+}
+
+impl Default for GateConfig {
+    fn default() -> Self {
+        GateConfig {
+            max_clipped_samples: Some(0),
+            max_duration_delta: Some(0.5),
+            min_loudness_db: Some(-35.0),
+            max_loudness_db: Some(-10.0),
+            min_sample_rate: Some(22050),
+            max_downmix_loss_db: Some(6.0),
+        }
+    }
+}
+
+/// What was measured of a take.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TakeMeasurement {
+    pub clipped_samples: usize,
+    pub duration_secs: f64,
+    pub loudness_db: f64,
+    pub sample_rate: u32,
+    /// The length of the game's original line, if known.
+    pub original_duration_secs: Option<f64>,
+    /// How the take holds up when downmixed, if it has more than one
+    /// channel.
+    pub downmix: Option<DownmixCheck>,
+}
+
+/// The most a downmix is reported to lose, so complete cancellation stays a
+/// finite number.
+const MAX_DOWNMIX_LOSS_DB: f64 = 96.0;
+
+/// How a take with several channels holds up when downmixed to mono.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DownmixCheck {
+    /// How much quieter the downmix is than the channels, in dB. Identical
+    /// channels lose nothing and unrelated ones about 3 dB, but channels out
+    /// of phase cancel out.
+    pub loss_db: f64,
+    /// The loudest channel, to use alone if the downmix cancels.
+    pub loudest_channel: usize,
+}
+
+impl DownmixCheck {
+    /// The filter that downmixes a stereo take by keeping only its loudest
+    /// channel.
+    pub fn safe_downmix_filter(&self) -> String {
+        format!(
+            "aformat=channel_layouts=stereo,pan=mono|c0=c{}",
+            self.loudest_channel.min(1)
+        )
+    }
+}
+
+/// Compares the power of the interleaved samples' mono downmix against
+/// that of their channels. Returns `None` for mono samples.
+pub fn check_downmix(pcm: &[i16], channels: usize) -> Option<DownmixCheck> {
+    if channels < 2 {
+        return None;
+    }
+    let mut channel_power = vec![0.0; channels];
+    let mut downmix_power = 0.0;
+    for frame in pcm.chunks_exact(channels) {
+        let mut sum = 0.0;
+        for (power, &sample) in channel_power.iter_mut().zip(frame) {
+            let sample = f64::from(sample) / 32768.0;
+            *power += sample * sample;
+            sum += sample;
+        }
+        downmix_power += (sum / channels as f64).powi(2);
+    }
+    let power = channel_power.iter().sum::<f64>() / channels as f64;
+    let loss_db = if power == 0.0 {
+        0.0
+    } else if downmix_power == 0.0 {
+        MAX_DOWNMIX_LOSS_DB
+    } else {
+        (10.0 * (power / downmix_power).log10()).min(MAX_DOWNMIX_LOSS_DB)
+    };
+    let loudest_channel = channel_power
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(channel, _)| channel);
+    Some(DownmixCheck {
+        loss_db,
+        loudest_channel,
+    })
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum Violation {
+    Clipping {
+        samples: usize,
+    },
+    DurationDelta {
+        duration_secs: f64,
+        original_duration_secs: f64,
+    },
+    TooQuiet {
+        loudness_db: f64,
+    },
+    TooLoud {
+        loudness_db: f64,
+    },
+    SampleRate {
+        sample_rate: u32,
+    },
+    PhaseCancellation {
+        loss_db: f64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Clipping { samples } => write!(f, "{samples} clipped samples"),
+            Violation::DurationDelta {
+                duration_secs,
+                original_duration_secs,
+            } => write!(
+                f,
+                "{duration_secs:.2}s long, the original is {original_duration_secs:.2}s"
+            ),
+            Violation::TooQuiet { loudness_db } => {
+                write!(f, "too quiet ({loudness_db:.1} dBFS)")
+            }
+            Violation::TooLoud { loudness_db } => write!(f, "too loud ({loudness_db:.1} dBFS)"),
+            Violation::SampleRate { sample_rate } => {
+                write!(f, "sample rate too low ({sample_rate} Hz)")
+            }
+            Violation::PhaseCancellation { loss_db } => write!(
+                f,
+                "channels cancel when downmixed to mono ({loss_db:.1} dB lost)"
+            ),
+        }
+    }
+}
+
+impl GateConfig {
+    /// Checks a take's measurements against the thresholds.
+    pub fn check(&self, take: &TakeMeasurement) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_clipped_samples
+            && take.clipped_samples > max
+        {
+            violations.push(Violation::Clipping {
+                samples: take.clipped_samples,
+            });
+        }
+        if let (Some(max), Some(original)) = (self.max_duration_delta, take.original_duration_secs)
+            && original > 0.0
+            && (take.duration_secs - original).abs() / original > max
+        {
+            violations.push(Violation::DurationDelta {
+                duration_secs: take.duration_secs,
+                original_duration_secs: original,
+            });
+        }
+        if let Some(min) = self.min_loudness_db
+            && take.loudness_db < min
+        {
+            violations.push(Violation::TooQuiet {
+                loudness_db: take.loudness_db,
+            });
+        }
+        if let Some(max) = self.max_loudness_db
+            && take.loudness_db > max
+        {
+            violations.push(Violation::TooLoud {
+                loudness_db: take.loudness_db,
+            });
+        }
+        if let Some(min) = self.min_sample_rate
+            && take.sample_rate < min
+        {
+            violations.push(Violation::SampleRate {
+                sample_rate: take.sample_rate,
+            });
+        }
+        if let (Some(max), Some(downmix)) = (self.max_downmix_loss_db, &take.downmix)
+            && downmix.loss_db > max
+        {
+            violations.push(Violation::PhaseCancellation {
+                loss_db: downmix.loss_db,
+            });
+        }
+        violations
+    }
+}
+
+/// The gate's verdict on one take.
+#[derive(Serialize, Debug)]
+pub struct GateResult {
+    pub room: u16,
+    pub message_id: MessageId,
+    pub source: PathBuf,
+    pub measurement: TakeMeasurement,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GateReport {
+    pub config: GateConfig,
+    /// All checked takes, failing ones first.
+    pub results: Vec<GateResult>,
+}
+
+impl GateReport {
+    pub fn failures(&self) -> impl Iterator<Item = &GateResult> {
+        self.results
+            .iter()
+            .filter(|result| !result.violations.is_empty())
+    }
+}
+
+/// Counts the samples at full scale, and measures the mean volume in dBFS.
+pub(crate) fn measure_pcm(pcm: &[i16]) -> (usize, f64) {
+    let clipped = pcm
+        .iter()
+        .filter(|&&sample| sample == i16::MAX || sample == i16::MIN)
+        .count();
+    let power = pcm
+        .iter()
+        .map(|&sample| (f64::from(sample) / 32768.0).powi(2))
+        .sum::<f64>()
+        / pcm.len().max(1) as f64;
+    let loudness_db = if power > 0.0 {
+        10.0 * power.log10()
+    } else {
+        f64::NEG_INFINITY
+    };
+    (clipped, loudness_db)
+}
+
+/// Measures every selected take (trimmed) and checks it against the gate.
+/// Durations are only compared if the original audio is given.
+pub async fn run_gate(
+    config: &GateConfig,
+    sample_dir: &SampleDir,
+    original_audio: Option<&OriginalAudio>,
+    ffmpeg: &FfmpegTool,
+    ffprobe: &FfprobeTool,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<GateReport> {
+    let cancel = scheduler.cancellation();
+    let jobs = sample_dir.samples().map(|sample| {
+        let job = move || async move {
+            let path = sample.clip_path(sample_dir.base_path())?;
+            let info = ffprobe.probe_audio(&path, cancel).await?;
+            // Decoded at the recording's own rate, so resampling can't hide
+            // clipping.
+            let pcm = ffmpeg
+                .convert_with_filter(
+                    &path,
+                    ffmpeg::VecOutput,
+                    OutputFormat::RawS16Le,
+                    sample.clip.trim_filter().as_deref(),
+                    &mut ffmpeg::NullProgressListener,
+                    cancel,
+                )
+                .await?;
+            let pcm = pcm
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect::<Vec<_>>();
+            let (clipped_samples, loudness_db) = measure_pcm(&pcm);
+            let downmix = check_downmix(&pcm, info.channels as usize);
+            let frames = pcm.len() / info.channels.max(1) as usize;
+            let original_duration_secs = match original_audio {
+                Some(original_audio) => {
+                    match original_audio.clip(sample.room, &sample.message_id)? {
+                        Some(clip) => Some(
+                            ffmpeg
+                                .measure_duration(ffmpeg::BytesInput::new(clip), cancel)
+                                .await?
+                                .as_secs_f64(),
+                        ),
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+            let measurement = TakeMeasurement {
+                clipped_samples,
+                duration_secs: frames as f64 / f64::from(info.sample_rate.max(1)),
+                loudness_db,
+                sample_rate: info.sample_rate,
+                original_duration_secs,
+                downmix,
+            };
+            Ok::<_, anyhow::Error>(GateResult {
+                room: sample.room,
+                message_id: sample.message_id,
+                source: sample.clip.path.clone(),
+                violations: config.check(&measurement),
+                measurement,
+            })
+        };
+        (sample.key(), job)
+    });
+
+    let mut results = Vec::new();
+    let report = scheduler
+        .run(jobs, async |result: GateResult| {
+            results.push(result);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    results.sort_by_key(|result| (result.violations.is_empty(), result.room, result.message_id));
+    Ok(GateReport {
+        config: config.clone(),
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement() -> TakeMeasurement {
+        TakeMeasurement {
+            clipped_samples: 0,
+            duration_secs: 2.0,
+            loudness_db: -20.0,
+            sample_rate: 44100,
+            original_duration_secs: Some(1.8),
+            downmix: Some(DownmixCheck {
+                loss_db: 3.0,
+                loudest_channel: 0,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_good_take_passes() {
+        assert!(GateConfig::default().check(&measurement()).is_empty());
+    }
+
+    #[test]
+    fn test_violations() {
+        let take = TakeMeasurement {
+            clipped_samples: 12,
+            duration_secs: 4.0,
+            loudness_db: -5.0,
+            sample_rate: 11025,
+            original_duration_secs: Some(2.0),
+            downmix: Some(DownmixCheck {
+                loss_db: 40.0,
+                loudest_channel: 1,
+            }),
+        };
+        let violations = GateConfig::default().check(&take);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::Clipping { samples: 12 },
+                Violation::DurationDelta {
+                    duration_secs: 4.0,
+                    original_duration_secs: 2.0
+                },
+                Violation::TooLoud { loudness_db: -5.0 },
+                Violation::SampleRate { sample_rate: 11025 },
+                Violation::PhaseCancellation { loss_db: 40.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_checks() {
+        let config: GateConfig =
+            serde_json::from_str(r#"{"max_loudness_db": null, "min_sample_rate": 8000}"#).unwrap();
+        assert_eq!(config.max_clipped_samples, Some(0));
+        let take = TakeMeasurement {
+            loudness_db: -3.0,
+            sample_rate: 11025,
+            original_duration_secs: None,
+            ..measurement()
+        };
+        assert!(config.check(&take).is_empty());
+    }
+
+    #[test]
+    fn test_measure_pcm() {
+        let (clipped, loudness) = measure_pcm(&[i16::MAX, i16::MIN, 0, 0]);
+        assert_eq!(clipped, 2);
+        assert!((loudness - -3.01).abs() < 0.01);
+        assert_eq!(measure_pcm(&[0, 0]).1, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_check_downmix() {
+        assert_eq!(check_downmix(&[100, 200], 1), None);
+        let same = check_downmix(&[100, 100, -300, -300], 2).unwrap();
+        assert_eq!(same.loss_db, 0.0);
+        let inverted = check_downmix(&[100, -100, -300, 300], 2).unwrap();
+        assert_eq!(inverted.loss_db, MAX_DOWNMIX_LOSS_DB);
+        let one_sided = check_downmix(&[0, 1000, 0, -1000], 2).unwrap();
+        assert!((one_sided.loss_db - 3.01).abs() < 0.01);
+        assert_eq!(one_sided.loudest_channel, 1);
+        assert_eq!(
+            one_sided.safe_downmix_filter(),
+            "aformat=channel_layouts=stereo,pan=mono|c0=c1"
+        );
+    }
+}
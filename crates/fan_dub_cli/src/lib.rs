@@ -1,3 +1,36 @@
+pub mod added;
+pub mod archive;
+pub mod blind;
+pub mod build;
+pub mod cancel;
+pub mod cleanup;
+pub mod config;
+pub mod cues;
+pub mod daw;
+pub mod declip;
+pub mod effects;
+pub mod fingerprint;
+pub mod gate;
+pub mod lipsync;
+pub mod new_speech;
+pub mod one_take;
+pub mod overrides;
+pub mod partial;
 pub mod path;
-pub mod tools;
+pub mod placeholder;
+pub mod playtest;
+pub mod preview;
+pub mod profile;
+pub mod release;
+pub mod remap;
+pub mod rename;
+pub mod render;
+pub mod report;
 pub mod resources;
+pub mod scheduler;
+pub mod signing;
+pub mod stage;
+pub mod subtitles;
+pub mod tone;
+pub mod tools;
+pub mod transcript;
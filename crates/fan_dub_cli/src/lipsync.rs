@@ -0,0 +1,222 @@
+//! Importing lip sync timing from phoneme tools, as Sync36 resources.
+//!
+//! Two formats are read:
+//!
+//! - Papagayo's Moho switch export (`.dat`): a `MohoSwitch1` line, then a
+//!   line per mouth change with the frame number and the phoneme name.
+//!   Frames are numbered from 1, at the frame rate set in Papagayo.
+//! - JSON (`.json`), with the time of each change in seconds:
+//!
+//!   ```json
+//!   { "cues": [ { "start": 0.0, "phoneme": "rest" }, { "start": 0.12, "phoneme": "AI" } ] }
+//!   ```
+//!
+//!   Rhubarb Lip Sync's JSON output (`mouthCues`, with `value` for the
+//!   phoneme) is read as well.
+//!
+//! Phoneme names are mapped to cels of the talker's mouth loop with the
+//! project's viseme table, which defaults to Papagayo's Preston Blair set.
+
+use std::{collections::BTreeMap, path::Path};
+
+use sci_resources::types::sync36::{SyncCue, TICKS_PER_SECOND};
+use serde::Deserialize;
+
+/// The cel for each phoneme of Papagayo's default (Preston Blair) set.
+const DEFAULT_VISEMES: &[(&str, u16)] = &[
+    ("rest", 0),
+    ("MBP", 1),
+    ("AI", 2),
+    ("E", 3),
+    ("O", 4),
+    ("U", 5),
+    ("WQ", 6),
+    ("FV", 7),
+    ("L", 8),
+    ("etc", 9),
+];
+
+/// Maps phoneme names to mouth cels. Names are matched without regard to
+/// case.
+pub struct VisemeTable {
+    cels: BTreeMap<String, u16>,
+}
+
+impl VisemeTable {
+    pub fn new(cels: &BTreeMap<String, u16>) -> Self {
+        VisemeTable {
+            cels: cels
+                .iter()
+                .map(|(name, cel)| (name.to_lowercase(), *cel))
+                .collect(),
+        }
+    }
+
+    pub fn cel(&self, phoneme: &str) -> Option<u16> {
+        self.cels.get(&phoneme.to_lowercase()).copied()
+    }
+}
+
+impl Default for VisemeTable {
+    fn default() -> Self {
+        VisemeTable::new(
+            &DEFAULT_VISEMES
+                .iter()
+                .map(|(name, cel)| (name.to_string(), *cel))
+                .collect(),
+        )
+    }
+}
+
+/// A mouth change read from a phoneme tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PhonemeCue {
+    /// The time of the change from the start of the line, in seconds.
+    pub start: f64,
+    #[serde(alias = "value")]
+    pub phoneme: String,
+}
+
+#[derive(Deserialize)]
+struct PhonemeFile {
+    #[serde(alias = "mouthCues")]
+    cues: Vec<PhonemeCue>,
+}
+
+/// Parses a Papagayo Moho switch file, with frames at `fps` per second.
+pub fn parse_papagayo_dat(text: &str, fps: f64) -> anyhow::Result<Vec<PhonemeCue>> {
+    anyhow::ensure!(fps > 0.0, "The frame rate must be positive");
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    anyhow::ensure!(
+        lines.next() == Some("MohoSwitch1"),
+        "Not a Papagayo Moho switch file"
+    );
+    lines
+        .map(|line| {
+            let (frame, phoneme) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow::anyhow!("Expected a frame and a phoneme: {line:?}"))?;
+            let frame: u32 = frame
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid frame number: {frame:?}"))?;
+            anyhow::ensure!(frame >= 1, "Frames are numbered from 1: {line:?}");
+            Ok(PhonemeCue {
+                start: f64::from(frame - 1) / fps,
+                phoneme: phoneme.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn parse_phoneme_json(text: &str) -> anyhow::Result<Vec<PhonemeCue>> {
+    Ok(serde_json::from_str::<PhonemeFile>(text)?.cues)
+}
+
+/// Reads a phoneme file, picking the format by its extension. The frame
+/// rate is only used for Papagayo files.
+pub fn read_phoneme_file(path: &Path, fps: f64) -> anyhow::Result<Vec<PhonemeCue>> {
+    let text = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("dat") => parse_papagayo_dat(&text, fps),
+        Some(ext) if ext.eq_ignore_ascii_case("json") => parse_phoneme_json(&text),
+        _ => anyhow::bail!(
+            "Unknown phoneme file format for {}; expected .dat or .json",
+            path.display()
+        ),
+    }
+}
+
+/// Converts phoneme cues to Sync36 cues. Cues are sorted by time; a cue
+/// that doesn't change the cel is dropped, and of cues falling on the same
+/// tick, the last wins.
+pub fn to_sync_cues(cues: &[PhonemeCue], visemes: &VisemeTable) -> anyhow::Result<Vec<SyncCue>> {
+    let unknown: Vec<&str> = cues
+        .iter()
+        .filter(|cue| visemes.cel(&cue.phoneme).is_none())
+        .map(|cue| cue.phoneme.as_str())
+        .collect();
+    anyhow::ensure!(
+        unknown.is_empty(),
+        "Phonemes missing from the viseme table (add them to the config): {}",
+        unknown.join(", ")
+    );
+    let mut cues = cues.to_vec();
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    let mut sync_cues: Vec<SyncCue> = Vec::new();
+    for cue in &cues {
+        anyhow::ensure!(
+            cue.start >= 0.0,
+            "Cue for {:?} starts before the line",
+            cue.phoneme
+        );
+        let ticks = (cue.start * f64::from(TICKS_PER_SECOND)).round();
+        anyhow::ensure!(
+            ticks < f64::from(u16::MAX),
+            "Cue for {:?} at {:.2}s is too late for the line",
+            cue.phoneme,
+            cue.start
+        );
+        let cue = SyncCue {
+            ticks: ticks as u16,
+            cel: visemes.cel(&cue.phoneme).expect("Checked above"),
+        };
+        if let Some(last) = sync_cues.last()
+            && last.ticks == cue.ticks
+        {
+            sync_cues.pop();
+        }
+        if sync_cues.last().map(|last| last.cel) != Some(cue.cel) {
+            sync_cues.push(cue);
+        }
+    }
+    Ok(sync_cues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_papagayo_import() -> anyhow::Result<()> {
+        let dat = "MohoSwitch1\n1 rest\n7 AI\n8 ai\n13 MBP\n13 E\n25 rest\n";
+        let cues = parse_papagayo_dat(dat, 24.0)?;
+        assert_eq!(cues.len(), 6);
+        assert_eq!(cues[1].start, 0.25);
+        let sync = to_sync_cues(&cues, &VisemeTable::default())?;
+        assert_eq!(
+            sync,
+            [
+                SyncCue { ticks: 0, cel: 0 },
+                SyncCue { ticks: 15, cel: 2 },
+                SyncCue { ticks: 30, cel: 3 },
+                SyncCue { ticks: 60, cel: 0 },
+            ]
+        );
+        assert!(parse_papagayo_dat("1 rest\n", 24.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_import() -> anyhow::Result<()> {
+        let rhubarb = r#"{ "metadata": {}, "mouthCues": [
+            { "start": 0.0, "end": 0.1, "value": "X" },
+            { "start": 0.1, "end": 0.3, "value": "B" } ] }"#;
+        let cues = parse_phoneme_json(rhubarb)?;
+        assert!(to_sync_cues(&cues, &VisemeTable::default()).is_err());
+        let visemes = VisemeTable::new(&BTreeMap::from([
+            ("X".to_string(), 0),
+            ("B".to_string(), 4),
+        ]));
+        assert_eq!(
+            to_sync_cues(&cues, &visemes)?,
+            [SyncCue { ticks: 0, cel: 0 }, SyncCue { ticks: 6, cel: 4 }]
+        );
+
+        let generic = r#"{ "cues": [ { "start": 0.5, "phoneme": "O" } ] }"#;
+        assert_eq!(
+            to_sync_cues(&parse_phoneme_json(generic)?, &VisemeTable::default())?,
+            [SyncCue { ticks: 30, cel: 4 }]
+        );
+        Ok(())
+    }
+}
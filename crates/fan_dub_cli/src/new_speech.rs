@@ -0,0 +1,133 @@
+//! Adding speech to games that shipped without any, such as the floppy
+//! releases of SCI1.1 games.
+//!
+//! Such a game has no audio volume and no audio maps, so a new-speech build
+//! writes all of them from scratch: `RESOURCE.AUD`, a map patch for every
+//! room with lines, keyed by the lines' message tuples, and an empty audio
+//! index (map 65535). The audio alone doesn't make the game speak, though,
+//! so the build also writes `speech-setup.md`, listing the changes each
+//! interpreter needs.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    path::Path,
+};
+
+use sci_resources::types::msg::MessageId;
+
+use crate::resources::{GameLine, SampleDir, sample_key};
+
+/// The guide to the interpreter-side changes, written to the output
+/// directory.
+pub const SETUP_FILE: &str = "speech-setup.md";
+
+/// Whether the game already has speech, in an audio volume of its own.
+pub fn has_speech(game_dir: &Path) -> bool {
+    game_dir.join("RESOURCE.AUD").exists() || game_dir.join("resource.aud").exists()
+}
+
+/// The keys of the samples whose lines aren't among the game's messages.
+/// Scripts only ask for speech for lines they show, so these are never
+/// heard.
+pub fn unmatched_samples(sample_dir: &SampleDir, lines: &[GameLine]) -> Vec<String> {
+    let known: BTreeSet<(u16, MessageId)> = lines
+        .iter()
+        .map(|line| (line.room, line.message_id))
+        .collect();
+    sample_dir
+        .samples()
+        .filter(|sample| !known.contains(&(sample.room, sample.message_id)))
+        .map(|sample| sample_key(sample.room, &sample.message_id))
+        .collect()
+}
+
+/// A place the new speech is played.
+struct Target {
+    name: &'static str,
+    steps: &'static [&'static str],
+}
+
+const TARGETS: &[Target] = &[
+    Target {
+        name: "ScummVM",
+        steps: &[
+            "Copy `RESOURCE.AUD` and the `.map` patches into the game directory. \
+             ScummVM reads patches from the game directory, and pairs each audio \
+             map with `RESOURCE.AUD`.",
+            "ScummVM still detects the game as a floppy release, so it doesn't \
+             offer the speech and subtitle options. The game speaks only once its \
+             scripts set the message mode to speech (see \"Scripts\").",
+        ],
+    },
+    Target {
+        name: "Sierra's DOS interpreter",
+        steps: &[
+            "Floppy interpreters may not play audio36 speech. Use `SIERRA.EXE` \
+             from a CD release built on the same SCI1.1 interpreter version, \
+             with its audio driver (e.g. `AUDBLAST.DRV`).",
+            "Add the audio driver to `RESOURCE.CFG`, e.g. `audioDrv = AUDBLAST.DRV` \
+             and `audioSize = 63k`.",
+            "Copy `RESOURCE.AUD` and the `.map` patches into the game directory \
+             (or the `patchDir` named in `RESOURCE.CFG`). The empty audio index, \
+             `65535.map`, is loaded at startup.",
+        ],
+    },
+    Target {
+        name: "Scripts",
+        steps: &[
+            "Floppy releases set the message mode to text. It must be set to \
+             speech or to both, in the game's message-mode global (global 90 in \
+             most SCI1.1 games), from the game's main script or its control panel.",
+            "The narrator (script 928 in most SCI1.1 games) must play audio. If \
+             the floppy release's system scripts lack the audio code, patch in the \
+             narrator of a CD release built on the same system scripts.",
+        ],
+    },
+];
+
+/// The setup guide: the rooms given speech, the changes each target needs,
+/// and the samples that are never heard.
+pub fn setup_guide(room_lines: &BTreeMap<u16, usize>, unmatched: &[String]) -> String {
+    let mut guide = String::from("# Adding speech to the game\n\n");
+    guide.push_str(
+        "This build gives speech to a game that shipped without it. Besides the \
+         files, the interpreter needs the changes below.\n\n",
+    );
+    guide.push_str("| Room | Lines |\n| --- | --- |\n");
+    for (room, lines) in room_lines {
+        writeln!(guide, "| {room} | {lines} |").unwrap();
+    }
+    for target in TARGETS {
+        write!(guide, "\n## {}\n\n", target.name).unwrap();
+        for step in target.steps {
+            writeln!(guide, "- [ ] {step}").unwrap();
+        }
+    }
+    if !unmatched.is_empty() {
+        guide.push_str("\n## Samples without messages\n\n");
+        guide.push_str("No script asks for these lines, which aren't in the game's messages:\n\n");
+        for key in unmatched {
+            writeln!(guide, "- {key}").unwrap();
+        }
+    }
+    guide
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_guide() {
+        let guide = setup_guide(
+            &BTreeMap::from([(10, 3), (20, 1)]),
+            &["30-1-2-0-1".to_string()],
+        );
+        assert!(guide.contains("| 10 | 3 |\n| 20 | 1 |\n"));
+        for target in TARGETS {
+            assert!(guide.contains(&format!("## {}\n", target.name)));
+        }
+        assert!(guide.ends_with("- 30-1-2-0-1\n"));
+    }
+}
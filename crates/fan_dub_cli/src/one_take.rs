@@ -0,0 +1,404 @@
+//! Splitting a conversation recorded in one take into a take per line.
+//!
+//! Where each line is comes either from an Audacity label file (as written
+//! by `scitool gen export --format audacity-labels`, and adjusted on the
+//! recording), with a region label per line named by its key, or from the
+//! pauses between lines. Without labels, the lines must have been recorded in
+//! order (by noun, verb, condition and sequence).
+//!
+//! Either way, the lines found are checked against the conversation's lines
+//! in the game before anything is written. Reference lines, which reuse
+//! other conversations' audio, aren't expected in the recording.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use sci_resources::{
+    ResourceType,
+    file::open_game_resources,
+    types::msg::{MessageId, parse_message_resource},
+};
+
+use crate::{
+    cancel::CancellationToken,
+    daw::{ConversationFilter, ImportedTake},
+    resources::{SampleDir, parse_sample_key, sample_key},
+    tools::ffmpeg::{self, ANALYSIS_RATE, FfmpegTool, OutputFormat},
+};
+
+/// The length of the windows the recording's level is measured over.
+const WINDOW: Duration = Duration::from_millis(10);
+
+/// A stretch of the recording, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub start_secs: f64,
+    pub end_secs: f64,
+}
+
+/// How the pauses between lines are found.
+#[derive(Debug, Clone, Copy)]
+pub struct SilenceOptions {
+    /// Windows quieter than this (in dBFS) are silent.
+    pub threshold_db: f64,
+    /// Silences at least this long separate lines; shorter ones are pauses
+    /// within a line.
+    pub min_silence: Duration,
+    /// Sounds shorter than this (e.g. clicks) aren't lines.
+    pub min_speech: Duration,
+    /// Silence kept before and after each line.
+    pub padding: Duration,
+}
+
+impl Default for SilenceOptions {
+    fn default() -> Self {
+        SilenceOptions {
+            threshold_db: -45.0,
+            min_silence: Duration::from_millis(600),
+            min_speech: Duration::from_millis(150),
+            padding: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A region label from an Audacity label file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub region: Region,
+    pub name: String,
+}
+
+/// Parses an Audacity label file: a label per line, with its start and end
+/// in seconds and its name, separated by tabs. The spectral selection lines
+/// Audacity may write after a label (starting with `\`) are skipped.
+pub fn parse_audacity_labels(text: &str) -> anyhow::Result<Vec<Label>> {
+    let mut labels = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('\\') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let (Some(start), Some(end)) = (fields.next(), fields.next()) else {
+            anyhow::bail!("Label line {} has no start and end", index + 1);
+        };
+        let parse = |field: &str| {
+            field
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| anyhow::anyhow!("Label line {}: bad time {field:?}: {e}", index + 1))
+        };
+        labels.push(Label {
+            region: Region {
+                start_secs: parse(start)?,
+                end_secs: parse(end)?,
+            },
+            name: fields.next().unwrap_or_default().trim().to_string(),
+        });
+    }
+    Ok(labels)
+}
+
+/// Finds the lines in a recording, as the stretches between silences, padded
+/// and kept within the recording.
+pub fn detect_lines(samples: &[i16], sample_rate: u32, options: &SilenceOptions) -> Vec<Region> {
+    let window = ((f64::from(sample_rate) * WINDOW.as_secs_f64()) as usize).max(1);
+    let window_secs = window as f64 / f64::from(sample_rate);
+    let threshold = 10f64.powf(options.threshold_db / 20.0) * f64::from(i16::MAX);
+    // The sounding stretches, in windows.
+    let mut sounds: Vec<(usize, usize)> = Vec::new();
+    for (index, chunk) in samples.chunks(window).enumerate() {
+        let power = chunk
+            .iter()
+            .map(|&sample| f64::from(sample) * f64::from(sample))
+            .sum::<f64>()
+            / chunk.len() as f64;
+        if power.sqrt() < threshold {
+            continue;
+        }
+        match sounds.last_mut() {
+            Some((_, end)) if index == *end => *end = index + 1,
+            _ => sounds.push((index, index + 1)),
+        }
+    }
+    // Join sounds separated by pauses too short to be between lines.
+    let min_silence = (options.min_silence.as_secs_f64() / window_secs).ceil() as usize;
+    let mut lines: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in sounds {
+        match lines.last_mut() {
+            Some((_, last_end)) if start - *last_end < min_silence => *last_end = end,
+            _ => lines.push((start, end)),
+        }
+    }
+    let total_secs = samples.len() as f64 / f64::from(sample_rate);
+    let padding = options.padding.as_secs_f64();
+    lines
+        .into_iter()
+        .map(|(start, end)| (start as f64 * window_secs, end as f64 * window_secs))
+        .filter(|(start, end)| end - start >= options.min_speech.as_secs_f64())
+        .map(|(start, end)| Region {
+            start_secs: (start - padding).max(0.0),
+            end_secs: (end + padding).min(total_secs),
+        })
+        .collect()
+}
+
+/// Pairs labels with the lines they name, checking that every expected line
+/// has exactly one label, and that no label names another line.
+pub fn match_labels(
+    room: u16,
+    expected: &[MessageId],
+    labels: &[Label],
+) -> anyhow::Result<Vec<(MessageId, Region)>> {
+    let mut regions = BTreeMap::new();
+    for label in labels {
+        let key = label.name.split_whitespace().next().unwrap_or_default();
+        let Some((label_room, message_id)) = parse_sample_key(key) else {
+            anyhow::bail!("Label {:?} doesn't start with a line key", label.name);
+        };
+        anyhow::ensure!(
+            label_room == room && expected.contains(&message_id),
+            "Label {key} isn't one of the conversation's lines"
+        );
+        anyhow::ensure!(
+            label.region.end_secs > label.region.start_secs,
+            "The label for {key} is empty"
+        );
+        anyhow::ensure!(
+            regions.insert(message_id, label.region).is_none(),
+            "Line {key} is labeled more than once"
+        );
+    }
+    let missing: Vec<_> = expected
+        .iter()
+        .filter(|message_id| !regions.contains_key(message_id))
+        .map(|message_id| sample_key(room, message_id))
+        .collect();
+    anyhow::ensure!(
+        missing.is_empty(),
+        "{} labels for the conversation's {} lines; missing {}",
+        regions.len(),
+        expected.len(),
+        missing.join(", ")
+    );
+    Ok(regions.into_iter().collect())
+}
+
+/// Pairs detected lines with the expected ones, in order, checking that
+/// there are as many of each.
+pub fn match_detected(
+    expected: &[MessageId],
+    detected: &[Region],
+) -> anyhow::Result<Vec<(MessageId, Region)>> {
+    anyhow::ensure!(
+        detected.len() == expected.len(),
+        "Found {} lines in the recording, but the conversation has {}; adjust the silence \
+         threshold or the shortest pause between lines, or use a label file",
+        detected.len(),
+        expected.len()
+    );
+    Ok(expected
+        .iter()
+        .copied()
+        .zip(detected.iter().copied())
+        .collect())
+}
+
+/// The lines of the conversation in the game, leaving out reference lines.
+pub fn conversation_lines(
+    game_dir: &Path,
+    filter: &ConversationFilter,
+) -> anyhow::Result<Vec<MessageId>> {
+    let resources = open_game_resources(game_dir)?;
+    let Some(res) = resources
+        .resources_of_type(ResourceType::Message)
+        .find(|res| res.id().resource_num() == filter.room)
+    else {
+        anyhow::bail!("Room {} has no messages", filter.room);
+    };
+    let messages = parse_message_resource(res.load_data()?)?;
+    let mut lines: Vec<_> = messages
+        .messages()
+        .filter(|(message_id, record)| {
+            record.ref_target().is_none() && filter.matches(filter.room, message_id)
+        })
+        .map(|(message_id, _)| *message_id)
+        .collect();
+    lines.sort();
+    anyhow::ensure!(!lines.is_empty(), "{} has no lines", filter.name());
+    Ok(lines)
+}
+
+/// Where the lines are in a one-take recording.
+pub enum LineSource<'a> {
+    Labels(&'a Path),
+    Silence(SilenceOptions),
+}
+
+/// Splits a recording of a conversation into a take per line, saved under
+/// `one-take/<conversation name>/` in the sample directory.
+#[expect(clippy::too_many_arguments)]
+pub async fn import_one_take(
+    sample_dir: &mut SampleDir,
+    game_dir: &Path,
+    filter: &ConversationFilter,
+    recording: &Path,
+    source: LineSource<'_>,
+    select: bool,
+    force: bool,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<ImportedTake>> {
+    let expected = conversation_lines(game_dir, filter)?;
+    let lines = match source {
+        LineSource::Labels(path) => match_labels(
+            filter.room,
+            &expected,
+            &parse_audacity_labels(&std::fs::read_to_string(path)?)?,
+        )?,
+        LineSource::Silence(options) => {
+            let samples = ffmpeg.decode_mono(recording, None, cancel).await?;
+            match_detected(&expected, &detect_lines(&samples, ANALYSIS_RATE, &options))?
+        }
+    };
+    let locked: Vec<_> = lines
+        .iter()
+        .filter(|(message_id, _)| sample_dir.is_locked(filter.room, message_id))
+        .map(|(message_id, _)| sample_key(filter.room, message_id))
+        .collect();
+    anyhow::ensure!(
+        force || locked.is_empty(),
+        "The conversation has locked lines ({}); use --force to import them anyway",
+        locked.join(", ")
+    );
+
+    let take_dir = Path::new("one-take").join(filter.name());
+    smol::fs::create_dir_all(sample_dir.base_path().join(&take_dir)).await?;
+    let mut imported = Vec::new();
+    for (message_id, region) in lines {
+        let take = sample_dir.new_take_path(&take_dir, filter.room, &message_id, "wav")?;
+        let audio_filter = format!(
+            "atrim=start={:.6}:end={:.6},asetpts=PTS-STARTPTS",
+            region.start_secs, region.end_secs
+        );
+        ffmpeg
+            .convert_with_filter(
+                recording,
+                &sample_dir.base_path().join(&take),
+                OutputFormat::Wav,
+                Some(&audio_filter),
+                &mut ffmpeg::NullProgressListener,
+                cancel,
+            )
+            .await?;
+        imported.push(ImportedTake {
+            room: filter.room,
+            message_id,
+            take,
+        });
+    }
+
+    if select {
+        for take in &imported {
+            sample_dir.select_take(take.room, take.message_id, take.take.clone());
+        }
+        sample_dir.save().await?;
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start_secs: f64, end_secs: f64) -> Region {
+        Region {
+            start_secs,
+            end_secs,
+        }
+    }
+
+    #[test]
+    fn test_parse_audacity_labels() -> anyhow::Result<()> {
+        let text = "0.000000\t2.800000\t10-1-2-0-1 Hello there.\n\\\t0.000000\t0.000000\n\
+                    3.5\t5\t10-1-2-0-2\n";
+        let labels = parse_audacity_labels(text)?;
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].region, region(0.0, 2.8));
+        assert_eq!(labels[0].name, "10-1-2-0-1 Hello there.");
+        assert_eq!(labels[1].name, "10-1-2-0-2");
+        assert!(parse_audacity_labels("1.0\n").is_err());
+        assert!(parse_audacity_labels("a\t2\tx\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_lines() {
+        let rate = 1000;
+        let tone = |ms: usize| (0..ms).map(|i| if i % 2 == 0 { 8000 } else { -8000 });
+        let silence = |ms: usize| std::iter::repeat_n(0i16, ms);
+        let samples: Vec<i16> = silence(500)
+            .chain(tone(1000))
+            // A pause within the line.
+            .chain(silence(200))
+            .chain(tone(300))
+            .chain(silence(1000))
+            .chain(tone(800))
+            // A click.
+            .chain(silence(1000))
+            .chain(tone(50))
+            .chain(silence(300))
+            .collect();
+        let lines: Vec<_> = detect_lines(&samples, rate, &SilenceOptions::default())
+            .iter()
+            .map(|line| {
+                let ms = |secs: f64| (secs * 1000.0).round() as u32;
+                (ms(line.start_secs), ms(line.end_secs))
+            })
+            .collect();
+        assert_eq!(lines, [(400, 2100), (2900, 3900)]);
+    }
+
+    #[test]
+    fn test_match_labels() -> anyhow::Result<()> {
+        let expected = [MessageId::new(1, 2, 0, 1), MessageId::new(1, 2, 0, 2)];
+        let label = |name: &str, start_secs| Label {
+            region: region(start_secs, start_secs + 1.0),
+            name: name.to_string(),
+        };
+        let lines = match_labels(
+            10,
+            &expected,
+            &[label("10-1-2-0-2", 2.0), label("10-1-2-0-1 Hi.", 0.0)],
+        )?;
+        assert_eq!(
+            lines,
+            [
+                (expected[0], region(0.0, 1.0)),
+                (expected[1], region(2.0, 3.0))
+            ]
+        );
+
+        let error = match_labels(10, &expected, &[label("10-1-2-0-1", 0.0)]).unwrap_err();
+        assert!(error.to_string().contains("missing 10-1-2-0-2"));
+        assert!(match_labels(10, &expected, &[label("10-1-2-0-3", 0.0)]).is_err());
+        assert!(match_labels(10, &expected, &[label("Hi.", 0.0)]).is_err());
+        assert!(
+            match_labels(
+                10,
+                &expected,
+                &[label("10-1-2-0-1", 0.0), label("10-1-2-0-1", 2.0)]
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_detected() {
+        let expected = [MessageId::new(1, 2, 0, 1), MessageId::new(1, 2, 0, 2)];
+        assert!(match_detected(&expected, &[region(0.0, 1.0)]).is_err());
+        assert_eq!(
+            match_detected(&expected, &[region(0.0, 1.0), region(2.0, 3.0)]).unwrap()[1],
+            (expected[1], region(2.0, 3.0))
+        );
+    }
+}
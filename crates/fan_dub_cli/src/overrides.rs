@@ -0,0 +1,138 @@
+//! Per-line changes to the build's processing chain, for the few lines that
+//! always need special handling (shouts, whispers, radio effects). They are
+//! set in `fan-dub.json`, by line key:
+//!
+//! ```json
+//! { "lines": { "10-1-2-0-1": { "skip_normalization": true, "gain_db": 6.0 } } }
+//! ```
+//!
+//! Overrides apply to recorded takes only; stand-ins are built as usual.
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::{AudioClip, Sample, parse_sample_key};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct LineOverride {
+    /// Don't match the original recording's loudness, even if the sample
+    /// asks to.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub skip_normalization: bool,
+    /// Don't run the custom stages on the line.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub skip_stages: bool,
+    /// Where to trim the take, replacing its trim points in `samples.json`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_us: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_us: Option<u64>,
+    /// A fixed gain, in dB, applied instead of matching the original
+    /// loudness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<f64>,
+}
+
+impl LineOverride {
+    /// Whether the line's loudness is matched to the original recording.
+    pub fn normalizes(&self) -> bool {
+        !self.skip_normalization && self.gain_db.is_none()
+    }
+
+    /// The gain filter, if the line has a fixed gain.
+    pub fn gain_filter(&self) -> Option<String> {
+        self.gain_db.map(|gain_db| format!("volume={gain_db:.2}dB"))
+    }
+
+    /// The sample's clip, with the override's trim points.
+    pub fn clip<'a>(&self, clip: &'a AudioClip) -> Cow<'a, AudioClip> {
+        if self.start_us.is_none() && self.end_us.is_none() {
+            return Cow::Borrowed(clip);
+        }
+        Cow::Owned(AudioClip {
+            start_us: self.start_us.or(clip.start_us),
+            end_us: self.end_us.or(clip.end_us),
+            path: clip.path.clone(),
+        })
+    }
+}
+
+/// The overrides of each line, by room and message.
+#[derive(Debug, Default)]
+pub struct LineOverrides {
+    lines: BTreeMap<(u16, MessageId), LineOverride>,
+}
+
+impl LineOverrides {
+    pub fn from_config(config: &BTreeMap<String, LineOverride>) -> anyhow::Result<Self> {
+        let lines = config
+            .iter()
+            .map(|(key, line)| {
+                let line_id = parse_sample_key(key).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid line key {key:?} in the line overrides; expected room-noun-verb-condition-sequence"
+                    )
+                })?;
+                if let (Some(start_us), Some(end_us)) = (line.start_us, line.end_us) {
+                    anyhow::ensure!(
+                        start_us < end_us,
+                        "The override for {key} ends before it starts"
+                    );
+                }
+                Ok((line_id, line.clone()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(LineOverrides { lines })
+    }
+
+    /// The override for the sample, if it is a recorded take with one.
+    pub fn for_sample(&self, sample: &Sample) -> Option<&LineOverride> {
+        if sample.stand_in.is_some() {
+            return None;
+        }
+        self.lines.get(&(sample.room, sample.message_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(stand_in: &str) -> Sample {
+        serde_json::from_str(&format!(
+            r#"{{ "room": 10, "message_id": {{ "noun": 1, "verb": 2, "condition": 0, "sequence": 1 }},
+                 "clip": {{ "path": "a.wav", "start_us": 100, "end_us": 900 }}{stand_in} }}"#
+        ))
+        .unwrap()
+    }
+
+    fn overrides(json: &str) -> anyhow::Result<LineOverrides> {
+        LineOverrides::from_config(&serde_json::from_str(json)?)
+    }
+
+    #[test]
+    fn test_override() -> anyhow::Result<()> {
+        let overrides = overrides(r#"{ "10-1-2-0-1": { "gain_db": -3.5, "end_us": 500 } }"#)?;
+        let take = sample("");
+        let line = overrides.for_sample(&take).unwrap();
+        assert!(!line.normalizes());
+        assert_eq!(line.gain_filter().as_deref(), Some("volume=-3.50dB"));
+        let clip = line.clip(&take.clip);
+        assert_eq!((clip.start_us, clip.end_us), (Some(100), Some(500)));
+        assert!(
+            overrides
+                .for_sample(&sample(r#", "stand_in": "original""#))
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        assert!(overrides(r#"{ "10-1-2-0": {} }"#).is_err());
+        assert!(overrides(r#"{ "10-1-2-0-1": { "start_us": 5, "end_us": 5 } }"#).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Partial packaging, so beta releases can go out while recording is still
+//! under way.
+//!
+//! A build replaces the game's audio volume, so in a partial build the
+//! original recordings of the lines that haven't been recorded are repacked
+//! alongside the takes, and only recorded lines are overridden. The build
+//! then lists the lines still using original audio, with a checklist per room
+//! for testing the dubbed lines in the game (`partial-report.md`, and
+//! `partial-report.json`).
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path, path::PathBuf};
+
+use sci_resources::types::msg::MessageId;
+use serde::Serialize;
+
+use crate::resources::{AudioClip, GameLine, OriginalAudio, Sample, SampleDir, StandIn};
+
+/// Creates samples that repack the original recording of every line without
+/// a take.
+pub fn original_stand_ins(
+    sample_dir: &SampleDir,
+    original_audio: &OriginalAudio,
+) -> anyhow::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for room in original_audio.rooms() {
+        for message_id in original_audio.room_lines(room)? {
+            if sample_dir.sample(room, &message_id).is_some() {
+                continue;
+            }
+            samples.push(Sample {
+                room,
+                message_id,
+                clip: AudioClip {
+                    start_us: None,
+                    end_us: None,
+                    path: PathBuf::from("RESOURCE.AUD"),
+                },
+                match_original_loudness: false,
+                role: None,
+                cleanup: Vec::new(),
+                store_uncompressed: false,
+                stand_in: Some(StandIn::Original),
+                locked: false,
+            });
+        }
+    }
+    Ok(samples)
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChecklistLine {
+    pub message_id: MessageId,
+    /// The line's text, if the game has it.
+    pub text: Option<String>,
+}
+
+impl ChecklistLine {
+    fn to_markdown(&self) -> String {
+        let id = &self.message_id;
+        let mut line = format!(
+            "{}-{}-{}-{}",
+            id.noun(),
+            id.verb(),
+            id.condition(),
+            id.sequence()
+        );
+        if let Some(text) = &self.text {
+            write!(line, ": {}", text.replace('\n', " ")).unwrap();
+        }
+        line
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RoomChecklist {
+    pub room: u16,
+    /// The lines with a take, to check in the game.
+    pub dubbed: Vec<ChecklistLine>,
+    /// The lines still using the original audio.
+    pub original: Vec<ChecklistLine>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PartialReport {
+    pub dubbed: usize,
+    pub original: usize,
+    pub rooms: Vec<RoomChecklist>,
+}
+
+impl PartialReport {
+    /// Builds the report from the samples of a partial build.
+    pub fn new<'a>(samples: impl IntoIterator<Item = &'a Sample>, lines: &[GameLine]) -> Self {
+        let texts: BTreeMap<_, _> = lines
+            .iter()
+            .map(|line| ((line.room, line.message_id), line.text.as_str()))
+            .collect();
+        let mut rooms: BTreeMap<u16, RoomChecklist> = BTreeMap::new();
+        for sample in samples {
+            let room = rooms.entry(sample.room).or_insert_with(|| RoomChecklist {
+                room: sample.room,
+                dubbed: Vec::new(),
+                original: Vec::new(),
+            });
+            let line = ChecklistLine {
+                message_id: sample.message_id,
+                text: texts
+                    .get(&(sample.room, sample.message_id))
+                    .map(|text| text.to_string()),
+            };
+            match sample.stand_in {
+                Some(StandIn::Original) => room.original.push(line),
+                _ => room.dubbed.push(line),
+            }
+        }
+        let mut rooms: Vec<RoomChecklist> = rooms.into_values().collect();
+        for room in &mut rooms {
+            room.dubbed.sort_by_key(|line| line.message_id);
+            room.original.sort_by_key(|line| line.message_id);
+        }
+        PartialReport {
+            dubbed: rooms.iter().map(|room| room.dubbed.len()).sum(),
+            original: rooms.iter().map(|room| room.original.len()).sum(),
+            rooms,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        md.push_str("# Partial dub checklist\n\n");
+        writeln!(
+            md,
+            "{} of {} lines dubbed; {} still use the original audio.",
+            self.dubbed,
+            self.dubbed + self.original,
+            self.original
+        )
+        .unwrap();
+        for room in self.rooms.iter().filter(|room| !room.dubbed.is_empty()) {
+            writeln!(
+                md,
+                "\n## Room {} ({} of {} dubbed)\n",
+                room.room,
+                room.dubbed.len(),
+                room.dubbed.len() + room.original.len()
+            )
+            .unwrap();
+            md.push_str(
+                "Play each dubbed line in the game, and check the new recording is heard:\n\n",
+            );
+            for line in &room.dubbed {
+                writeln!(md, "- [ ] {}", line.to_markdown()).unwrap();
+            }
+            if !room.original.is_empty() {
+                md.push_str("\nStill using the original audio:\n\n");
+                for line in &room.original {
+                    writeln!(md, "- {}", line.to_markdown()).unwrap();
+                }
+            }
+        }
+        let undubbed = self
+            .rooms
+            .iter()
+            .filter(|room| room.dubbed.is_empty())
+            .map(|room| room.room.to_string())
+            .collect::<Vec<_>>();
+        if !undubbed.is_empty() {
+            writeln!(
+                md,
+                "\n## Rooms with only original audio\n\n{}",
+                undubbed.join(", ")
+            )
+            .unwrap();
+        }
+        md
+    }
+
+    pub fn write(&self, output_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            output_dir.join("partial-report.json"),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        std::fs::write(output_dir.join("partial-report.md"), self.to_markdown())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(json: &str) -> Sample {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_checklist() {
+        let samples = [
+            sample(
+                r#"{"room": 10, "message_id": {"noun": 1, "verb": 2, "condition": 0, "sequence": 2},
+                    "clip": {"path": "10-1-2-0-2.wav"}}"#,
+            ),
+            sample(
+                r#"{"room": 10, "message_id": {"noun": 1, "verb": 2, "condition": 0, "sequence": 1},
+                    "clip": {"path": "RESOURCE.AUD"}, "stand_in": "original"}"#,
+            ),
+            sample(
+                r#"{"room": 20, "message_id": {"noun": 3, "verb": 4, "condition": 0, "sequence": 1},
+                    "clip": {"path": "RESOURCE.AUD"}, "stand_in": "original"}"#,
+            ),
+        ];
+        let lines = [GameLine {
+            room: 10,
+            message_id: MessageId::new(1, 2, 0, 2),
+            text: "Hello there.".to_string(),
+        }];
+        let report = PartialReport::new(&samples, &lines);
+        assert_eq!((report.dubbed, report.original), (1, 2));
+        assert_eq!(report.rooms.len(), 2);
+
+        let md = report.to_markdown();
+        assert!(md.contains("1 of 3 lines dubbed; 2 still use the original audio."));
+        assert!(md.contains("## Room 10 (1 of 2 dubbed)"));
+        assert!(md.contains("- [ ] 1-2-0-2: Hello there.\n"));
+        assert!(md.contains("- 1-2-0-1\n"));
+        assert!(md.contains("## Rooms with only original audio\n\n20\n"));
+    }
+}
@@ -0,0 +1,304 @@
+//! Placeholder audio for lines that haven't been recorded yet, so a build is
+//! playable end-to-end before recording finishes.
+//!
+//! Placeholders are spoken by a text-to-speech backend. By default this is
+//! eSpeak or `say`; a project can plug in another program in `fan-dub.json`,
+//! where `{text}` and `{output}` are replaced with the line's text and the
+//! WAV file to write, and `{key}` with the line's key:
+//!
+//! ```json
+//! { "placeholders": { "command": ["piper", "--output_file", "{output}", "--text", "{text}"] } }
+//! ```
+//!
+//! Generated audio is cached in `.placeholders/` in the sample directory.
+//! Placeholder lines are marked in the build's QA report, and a coverage
+//! summary is written to `coverage.json` in the output directory.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    resources::{AudioClip, GameLine, Sample, SampleDir, StandIn, sample_key},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::{CommandTemplate, ExternalTool, speech::SpeechTool},
+};
+
+/// The directory in the sample directory that generated audio is cached in.
+const CACHE_DIR: &str = ".placeholders";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlaceholderConfig {
+    /// The text-to-speech command, followed by its arguments.
+    pub command: Vec<String>,
+}
+
+/// Speaks text into a WAV file.
+pub trait SpeechBackend {
+    fn speak<'a>(
+        &'a self,
+        key: &'a str,
+        text: &'a str,
+        output: &'a Path,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+}
+
+impl SpeechBackend for SpeechTool {
+    fn speak<'a>(
+        &'a self,
+        _key: &'a str,
+        text: &'a str,
+        output: &'a Path,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.speak_to_file(text, output, cancel))
+    }
+}
+
+/// Runs a configured external program.
+pub struct CommandSpeech {
+    tool: ExternalTool,
+    args: CommandTemplate,
+}
+
+impl CommandSpeech {
+    pub fn from_config(config: &PlaceholderConfig) -> anyhow::Result<Self> {
+        let Some((program, args)) = config.command.split_first() else {
+            anyhow::bail!("The placeholder command is empty");
+        };
+        Ok(CommandSpeech {
+            tool: ExternalTool::from_path(program.into()),
+            args: CommandTemplate::new(args.iter().cloned()),
+        })
+    }
+}
+
+impl SpeechBackend for CommandSpeech {
+    fn speak<'a>(
+        &'a self,
+        key: &'a str,
+        text: &'a str,
+        output: &'a Path,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        let args = self.args.render(&BTreeMap::from([
+            ("text", OsStr::new(text)),
+            ("output", output.as_os_str()),
+            ("key", OsStr::new(key)),
+        ]));
+        Box::pin(
+            self.tool
+                .run(args, None, futures::future::ready(()), cancel),
+        )
+    }
+}
+
+/// The part of a message that is spoken: without `|..|` control codes or
+/// parenthesized stage directions, and with whitespace collapsed.
+pub(crate) fn spoken_text(text: &str) -> String {
+    let mut spoken = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find(['|', '(']) {
+        spoken.push_str(&rest[..open]);
+        let close = if rest[open..].starts_with('|') {
+            '|'
+        } else {
+            ')'
+        };
+        match rest[open + 1..].find(close) {
+            Some(end) => rest = &rest[open + 1 + end + 1..],
+            None => {
+                rest = "";
+            }
+        }
+        spoken.push(' ');
+    }
+    spoken.push_str(rest);
+    spoken.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Generates placeholder samples for the lines that have no sample, reusing
+/// cached audio where it exists.
+pub async fn generate_placeholders(
+    sample_dir: &SampleDir,
+    lines: &[GameLine],
+    backend: &dyn SpeechBackend,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<Vec<Sample>> {
+    smol::fs::create_dir_all(sample_dir.base_path().join(CACHE_DIR)).await?;
+    let cancel = scheduler.cancellation();
+    let jobs = lines
+        .iter()
+        .filter(|line| sample_dir.sample(line.room, &line.message_id).is_none())
+        .filter(|line| !spoken_text(&line.text).is_empty())
+        .map(|line| {
+            let key = sample_key(line.room, &line.message_id);
+            let job = {
+                let key = key.clone();
+                move || {
+                    let key = key.clone();
+                    async move {
+                        let path = Path::new(CACHE_DIR).join(format!("{key}.wav"));
+                        let abs_path = sample_dir.base_path().join(&path);
+                        if !abs_path.exists() {
+                            // Write to a temporary name, so an interrupted
+                            // run doesn't leave a partial file in the cache.
+                            let partial = abs_path.with_extension("partial.wav");
+                            backend
+                                .speak(&key, &spoken_text(&line.text), &partial, cancel)
+                                .await?;
+                            smol::fs::rename(&partial, &abs_path).await?;
+                        }
+                        Ok::<_, anyhow::Error>(placeholder_sample(line, path))
+                    }
+                }
+            };
+            (key, job)
+        });
+
+    let mut samples = Vec::new();
+    let report = scheduler
+        .run(jobs, async |sample: Sample| {
+            samples.push(sample);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    samples.sort_by_key(|sample| (sample.room, sample.message_id));
+    Ok(samples)
+}
+
+fn placeholder_sample(line: &GameLine, path: PathBuf) -> Sample {
+    Sample {
+        room: line.room,
+        message_id: line.message_id,
+        clip: AudioClip {
+            start_us: None,
+            end_us: None,
+            path,
+        },
+        match_original_loudness: false,
+        role: None,
+        cleanup: Vec::new(),
+        store_uncompressed: false,
+        stand_in: Some(StandIn::Placeholder),
+        locked: false,
+    }
+}
+
+/// How many of a set of lines are recorded.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct LineCoverage {
+    pub lines: usize,
+    pub recorded: usize,
+    pub placeholders: usize,
+}
+
+impl LineCoverage {
+    fn add(&mut self, recorded: bool, placeholder: bool) {
+        self.lines += 1;
+        self.recorded += usize::from(recorded);
+        self.placeholders += usize::from(placeholder);
+    }
+
+    /// The recorded fraction of the lines, as a percentage.
+    pub fn percent_recorded(&self) -> f64 {
+        if self.lines == 0 {
+            100.0
+        } else {
+            100.0 * self.recorded as f64 / self.lines as f64
+        }
+    }
+}
+
+/// How much of the game is recorded, overall and by room.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CoverageReport {
+    pub total: LineCoverage,
+    pub rooms: BTreeMap<u16, LineCoverage>,
+}
+
+impl CoverageReport {
+    const FILE_NAME: &str = "coverage.json";
+
+    pub fn new(lines: &[GameLine], sample_dir: &SampleDir) -> Self {
+        let mut recorded = BTreeSet::new();
+        let mut placeholders = BTreeSet::new();
+        for sample in sample_dir.samples() {
+            let set = if sample.stand_in == Some(StandIn::Placeholder) {
+                &mut placeholders
+            } else {
+                &mut recorded
+            };
+            set.insert((sample.room, sample.message_id));
+        }
+        let mut report = CoverageReport {
+            total: LineCoverage::default(),
+            rooms: BTreeMap::new(),
+        };
+        for line in lines {
+            let id = (line.room, line.message_id);
+            let (is_recorded, is_placeholder) =
+                (recorded.contains(&id), placeholders.contains(&id));
+            report.total.add(is_recorded, is_placeholder);
+            report
+                .rooms
+                .entry(line.room)
+                .or_default()
+                .add(is_recorded, is_placeholder);
+        }
+        report
+    }
+
+    pub fn write(&self, output_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            output_dir.join(Self::FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spoken_text() {
+        assert_eq!(spoken_text("Hello there."), "Hello there.");
+        assert_eq!(
+            spoken_text("(Angrily) Get |c1|out|c| of   here!"),
+            "Get out of here!"
+        );
+        assert_eq!(spoken_text("(Sighs)"), "");
+        assert_eq!(spoken_text("Unclosed (direction"), "Unclosed");
+    }
+
+    #[test]
+    fn test_line_coverage() {
+        let mut coverage = LineCoverage::default();
+        coverage.add(true, false);
+        coverage.add(false, true);
+        coverage.add(false, false);
+        coverage.add(true, false);
+        assert_eq!(
+            coverage,
+            LineCoverage {
+                lines: 4,
+                recorded: 2,
+                placeholders: 1
+            }
+        );
+        assert_eq!(coverage.percent_recorded(), 50.0);
+        assert_eq!(LineCoverage::default().percent_recorded(), 100.0);
+    }
+}
@@ -0,0 +1,205 @@
+//! Tracking which lines have been heard in playtests, from the message
+//! traces in interpreter debug logs, so coverage reflects actual play.
+//!
+//! Two kinds of log line are recognized, with numbers in decimal or in hex
+//! with a `0x` prefix:
+//!
+//! - Keyed, as in ScummVM's debug output:
+//!   `module 100, noun 1, verb 2, cond 0, seq 1` (`room`, `condition` and
+//!   `sequence` are accepted too, and `=` or `:` may follow the keys).
+//! - A tuple after the word "message", as in SCI debug output:
+//!   `Message (100, 1, 2, 0, 1)`.
+//!
+//! Anything else in the log is skipped.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::{SampleDir, sample_key};
+
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn to_line(values: [u32; 5]) -> Option<(u16, MessageId)> {
+    let [room, noun, verb, condition, sequence] = values;
+    Some((
+        u16::try_from(room).ok()?,
+        MessageId::new(
+            u8::try_from(noun).ok()?,
+            u8::try_from(verb).ok()?,
+            u8::try_from(condition).ok()?,
+            u8::try_from(sequence).ok()?,
+        ),
+    ))
+}
+
+fn parse_keyed(line: &str) -> Option<(u16, MessageId)> {
+    let mut values: [Option<u32>; 5] = [None; 5];
+    let mut tokens = line
+        .split(|ch: char| !ch.is_ascii_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .peekable();
+    while let Some(token) = tokens.next() {
+        let field = match token.to_ascii_lowercase().as_str() {
+            "module" | "room" => 0,
+            "noun" => 1,
+            "verb" => 2,
+            "cond" | "condition" => 3,
+            "seq" | "sequence" => 4,
+            _ => continue,
+        };
+        if let Some(value) = tokens.peek().and_then(|next| parse_number(next)) {
+            values[field] = Some(value);
+            tokens.next();
+        }
+    }
+    let [
+        Some(room),
+        Some(noun),
+        Some(verb),
+        Some(condition),
+        Some(sequence),
+    ] = values
+    else {
+        return None;
+    };
+    to_line([room, noun, verb, condition, sequence])
+}
+
+fn parse_tuple(line: &str) -> Option<(u16, MessageId)> {
+    let at = line.to_ascii_lowercase().find("message")?;
+    let rest = &line[at..];
+    let open = rest.find('(')?;
+    let close = open + rest[open..].find(')')?;
+    let values = rest[open + 1..close]
+        .split(',')
+        .map(|value| parse_number(value.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    to_line(values.try_into().ok()?)
+}
+
+/// Reads the lines that a debug log shows being displayed, in order.
+pub fn parse_message_trace(log: &str) -> Vec<(u16, MessageId)> {
+    log.lines()
+        .filter_map(|line| parse_keyed(line).or_else(|| parse_tuple(line)))
+        .collect()
+}
+
+/// The lines heard in playtests, kept in the sample directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PlaytestLog {
+    /// The names of the logs each line was heard in, by sample key.
+    heard: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// How much of the dub the playtests have covered.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Coverage {
+    pub dubbed: usize,
+    pub dubbed_heard: usize,
+    /// Lines heard in playtests that have no sample yet, by key.
+    pub heard_without_sample: Vec<String>,
+}
+
+impl PlaytestLog {
+    const FILE_NAME: &str = "playtest.json";
+
+    fn path(sample_dir: &Path) -> PathBuf {
+        sample_dir.join(Self::FILE_NAME)
+    }
+
+    /// Loads the playtest log, if any have been imported.
+    pub fn load(sample_dir: &Path) -> anyhow::Result<Option<Self>> {
+        match std::fs::read(Self::path(sample_dir)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, sample_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(Self::path(sample_dir), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Marks the lines as heard in the named log. Importing the same log
+    /// again changes nothing. Returns the number of lines not heard before.
+    pub fn record(&mut self, log_name: &str, lines: &[(u16, MessageId)]) -> usize {
+        let mut newly_heard = 0;
+        for (room, message_id) in lines {
+            let logs = self.heard.entry(sample_key(*room, message_id)).or_default();
+            if logs.is_empty() {
+                newly_heard += 1;
+            }
+            logs.insert(log_name.to_string());
+        }
+        newly_heard
+    }
+
+    pub fn is_heard(&self, key: &str) -> bool {
+        self.heard.contains_key(key)
+    }
+
+    pub fn coverage(&self, sample_dir: &SampleDir) -> Coverage {
+        let keys: BTreeSet<String> = sample_dir.samples().map(|sample| sample.key()).collect();
+        Coverage {
+            dubbed: keys.len(),
+            dubbed_heard: keys.iter().filter(|key| self.is_heard(key)).count(),
+            heard_without_sample: self
+                .heard
+                .keys()
+                .filter(|key| !keys.contains(*key))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message_trace() {
+        let log = "\
+            Loading room 100\n\
+            kMessage: module 100, noun 1, verb 2, cond 0, seq 1\n\
+            room=0x65 noun=3 verb=0x2 condition=1 sequence=1\n\
+            Message (120, 4, 5, 0, 2)\n\
+            Message (120, 4, 5)\n\
+            noun 300 verb 1 cond 0 seq 1 module 100\n";
+        assert_eq!(
+            parse_message_trace(log),
+            [
+                (100, MessageId::new(1, 2, 0, 1)),
+                (101, MessageId::new(3, 2, 1, 1)),
+                (120, MessageId::new(4, 5, 0, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut log = PlaytestLog::default();
+        let lines = [
+            (100, MessageId::new(1, 2, 0, 1)),
+            (100, MessageId::new(1, 2, 0, 1)),
+            (101, MessageId::new(3, 2, 1, 1)),
+        ];
+        assert_eq!(log.record("run1.log", &lines), 2);
+        assert_eq!(log.record("run1.log", &lines), 0);
+        assert_eq!(log.record("run2.log", &lines[2..]), 0);
+        assert!(log.is_heard("100-1-2-0-1"));
+        assert!(!log.is_heard("100-1-2-0-2"));
+        assert_eq!(log.heard["101-3-2-1-1"].len(), 2);
+    }
+}
@@ -0,0 +1,244 @@
+//! Rendering a talker's mouth animation, driven by a Sync36 track, to a
+//! video with the dubbed line, so sync can be checked without the game.
+
+use std::path::Path;
+
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::open_game_resources,
+    types::{
+        palette::{Palette, Rgb},
+        sync36::{SyncCue, TICKS_PER_SECOND},
+        view::{Cel, View},
+    },
+};
+
+use crate::{
+    cancel::CancellationToken,
+    tools::ffmpeg::{FfmpegTool, RawVideo},
+};
+
+/// The frame rate of the preview. Each frame spans two ticks.
+pub const PREVIEW_FPS: u32 = 30;
+
+/// The game's global palette, which views' embedded palettes add to.
+const GLOBAL_PALETTE: u16 = 999;
+
+/// Drawn where the cels are transparent, and for colors missing from the
+/// palette.
+const BACKGROUND: Rgb = [0x40, 0x40, 0x40];
+
+/// How long the last mouth position is held after the final cue, if the
+/// audio doesn't run longer.
+const FINAL_HOLD_TICKS: u32 = 30;
+
+/// The cels of a talker's mouth loop, with the palette to draw them in.
+pub struct MouthLoop {
+    cels: Vec<Cel>,
+    palette: Palette,
+}
+
+impl MouthLoop {
+    /// Loads a loop of a view from the game, in the global palette plus the
+    /// view's own.
+    pub fn load(game_dir: &Path, view_num: u16, loop_num: u8) -> anyhow::Result<Self> {
+        let resources = open_game_resources(game_dir)?;
+        let view_data = resources
+            .get_resource(&ResourceId::new(ResourceType::View, view_num))
+            .ok_or_else(|| anyhow::anyhow!("The game has no view {view_num}"))?
+            .load_data()?;
+        let view = View::parse(&view_data)?;
+        let mut palette =
+            match resources.get_resource(&ResourceId::new(ResourceType::Palette, GLOBAL_PALETTE)) {
+                Some(resource) => Palette::parse(&resource.load_data()?)?,
+                None => Palette::empty(),
+            };
+        if let Some(view_palette) = view.palette()? {
+            palette.overlay(&view_palette);
+        }
+        let cels = view.cels(loop_num)?;
+        anyhow::ensure!(
+            !cels.is_empty(),
+            "Loop {loop_num} of view {view_num} has no cels"
+        );
+        Ok(MouthLoop { cels, palette })
+    }
+}
+
+/// The area covered by all of the loop's cels, as drawn at the origin:
+/// centered horizontally on it and resting on it, moved by their offsets.
+struct Bounds {
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+}
+
+fn cel_origin(cel: &Cel) -> (i32, i32) {
+    (
+        i32::from(cel.displace_x) - i32::from(cel.width / 2),
+        i32::from(cel.displace_y) - i32::from(cel.height) + 1,
+    )
+}
+
+fn bounds(cels: &[Cel]) -> Bounds {
+    let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+    for cel in cels {
+        let (x, y) = cel_origin(cel);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x + i32::from(cel.width));
+        bottom = bottom.max(y + i32::from(cel.height));
+    }
+    Bounds {
+        left,
+        top,
+        // Kept even, as video encoders need.
+        width: ((right - left).max(1) as usize).next_multiple_of(2),
+        height: ((bottom - top).max(1) as usize).next_multiple_of(2),
+    }
+}
+
+/// Renders the frames of the animation as raw RGB, with the cel of the last
+/// cue at or before each frame (cel 0 before the first cue). Returns the
+/// frame size and the frames.
+pub fn render_frames(
+    mouth: &MouthLoop,
+    cues: &[SyncCue],
+    frame_count: usize,
+) -> anyhow::Result<((usize, usize), Vec<u8>)> {
+    if let Some(cue) = cues
+        .iter()
+        .find(|cue| usize::from(cue.cel) >= mouth.cels.len())
+    {
+        anyhow::bail!(
+            "The sync track uses cel {}, but the loop only has {} cels",
+            cue.cel,
+            mouth.cels.len()
+        );
+    }
+    let bounds = bounds(&mouth.cels);
+    let drawn: Vec<Vec<u8>> = mouth
+        .cels
+        .iter()
+        .map(|cel| {
+            let mut frame: Vec<u8> = BACKGROUND.repeat(bounds.width * bounds.height);
+            let (x, y) = cel_origin(cel);
+            let (x, y) = ((x - bounds.left) as usize, (y - bounds.top) as usize);
+            let width = usize::from(cel.width);
+            for (row, pixels) in cel.pixels.chunks(width.max(1)).enumerate() {
+                for (col, &index) in pixels.iter().enumerate() {
+                    if index == cel.clear_key {
+                        continue;
+                    }
+                    let color = mouth.palette.color(index).unwrap_or(BACKGROUND);
+                    let at = ((y + row) * bounds.width + x + col) * 3;
+                    frame[at..at + 3].copy_from_slice(&color);
+                }
+            }
+            frame
+        })
+        .collect();
+    let ticks_per_frame = TICKS_PER_SECOND / PREVIEW_FPS;
+    let mut frames = Vec::with_capacity(frame_count * drawn[0].len());
+    let mut next_cue = 0;
+    let mut cel = 0;
+    for frame in 0..frame_count {
+        let ticks = frame as u64 * u64::from(ticks_per_frame);
+        while let Some(cue) = cues.get(next_cue)
+            && u64::from(cue.ticks) <= ticks
+        {
+            cel = usize::from(cue.cel);
+            next_cue += 1;
+        }
+        frames.extend_from_slice(&drawn[cel]);
+    }
+    Ok(((bounds.width, bounds.height), frames))
+}
+
+/// Renders the preview video, lasting as long as the audio, or until
+/// shortly after the last cue if that is later.
+pub async fn render_sync_preview(
+    ffmpeg: &FfmpegTool,
+    mouth: &MouthLoop,
+    cues: &[SyncCue],
+    audio_path: &Path,
+    scale: u32,
+    output_path: &Path,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let audio_duration = ffmpeg.measure_duration(audio_path, cancel).await?;
+    let last_cue_ticks = cues.last().map_or(0, |cue| u32::from(cue.ticks));
+    let sync_duration = f64::from(last_cue_ticks + FINAL_HOLD_TICKS) / f64::from(TICKS_PER_SECOND);
+    let duration = audio_duration.as_secs_f64().max(sync_duration);
+    let frame_count = (duration * f64::from(PREVIEW_FPS)).ceil() as usize;
+    let ((width, height), frames) = render_frames(mouth, cues, frame_count)?;
+    let frames_file = tempfile::NamedTempFile::new()?;
+    std::fs::write(frames_file.path(), frames)?;
+    ffmpeg
+        .render_video(
+            &RawVideo {
+                path: frames_file.path(),
+                width,
+                height,
+                fps: PREVIEW_FPS,
+            },
+            scale,
+            audio_path,
+            output_path,
+            cancel,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cel(width: u16, height: u16, color: u8) -> Cel {
+        Cel {
+            width,
+            height,
+            displace_x: 0,
+            displace_y: 0,
+            clear_key: 0xFF,
+            pixels: vec![color; usize::from(width) * usize::from(height)],
+        }
+    }
+
+    #[test]
+    fn test_render_frames() -> anyhow::Result<()> {
+        // Colors 1 and 2, in the SCI1.1 palette layout.
+        let mut palette_data = vec![0u8; 37];
+        palette_data[0] = 0x0E;
+        palette_data[25] = 1;
+        palette_data[29] = 2;
+        palette_data[32] = 1;
+        palette_data.extend_from_slice(&[10, 10, 10, 20, 20, 20]);
+        let mouth = MouthLoop {
+            // A closed mouth, and a taller open one.
+            cels: vec![cel(2, 1, 1), cel(2, 2, 2)],
+            palette: Palette::parse(&palette_data)?,
+        };
+        let cues = [SyncCue { ticks: 4, cel: 1 }, SyncCue { ticks: 6, cel: 0 }];
+        let ((width, height), frames) = render_frames(&mouth, &cues, 4)?;
+        assert_eq!((width, height), (2, 2));
+        let frame_size = width * height * 3;
+        assert_eq!(frames.len(), 4 * frame_size);
+        let pixel = |frame: usize, at: usize| {
+            let start = frame * frame_size + at * 3;
+            [frames[start], frames[start + 1], frames[start + 2]]
+        };
+        // Both cels rest on the bottom row, so the closed mouth leaves the
+        // top row empty.
+        assert_eq!(pixel(0, 0), BACKGROUND);
+        assert_eq!(pixel(0, 2), [10, 10, 10]);
+        assert_eq!(pixel(1, 2), [10, 10, 10]);
+        assert_eq!(pixel(2, 0), [20, 20, 20]);
+        assert_eq!(pixel(3, 2), [10, 10, 10]);
+
+        let too_far = [SyncCue { ticks: 0, cel: 2 }];
+        assert!(render_frames(&mouth, &too_far, 1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,631 @@
+//! Named conversion profiles, which set the format, sample rate and channels
+//! that samples are converted to.
+//!
+//! Besides the built-in profiles, a sample directory can define its own in
+//! its `fan-dub.json` config:
+//!
+//! ```json
+//! { "profiles": { "speech-22k": { "format": "sol", "sample_rate": 22050, "mono": true } } }
+//! ```
+//!
+//! The `match-game` profile is made to match the game's original clips, so
+//! the interpreter doesn't have to resample the new ones.
+//!
+//! 8-bit SOL profiles can DPCM-compress their samples, which halves their
+//! size but loses detail in loud, bright takes. Each compressed line's
+//! signal-to-noise ratio against the uncompressed samples is recorded in the
+//! build report, and lines below the profile's `min_snr_db` (or marked
+//! `store_uncompressed` in `samples.json`) are stored uncompressed instead.
+//!
+//! Profiles can master their output: a lookahead limiter keeps peaks under a
+//! ceiling, then SOL samples are dithered down to their bit depth, so
+//! quantizing adds noise rather than distortion. The defaults are tuned to
+//! the bit depth, and the built-in 8-bit profiles master with them:
+//!
+//! ```json
+//! { "profiles": { "speech-22k": { "format": "sol", "sample_rate": 22050, "mono": true,
+//!                                 "mastering": { "ceiling_db": -0.5, "dither": "triangular" } } } }
+//! ```
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use sci_resources::types::audio36::{
+    AudioFormat, SolFormat, decode_dpcm8, encode_dpcm8, write_sol_clip, write_sol_clip_16bit,
+    write_sol_clip_dpcm8,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::FanDubConfig,
+    report::CompressionQuality,
+    tools::ffmpeg::{FlacOutputOptions, OggVorbisOutputOptions, OutputFormat},
+};
+
+/// The profile used when none is selected. This matches what builds produced
+/// before profiles existed.
+pub const DEFAULT_PROFILE: &str = "game-ogg";
+
+/// The profile matching the format of the game's original clips. Builds use
+/// it by default when the game directory is given.
+pub const MATCH_GAME_PROFILE: &str = "match-game";
+
+/// The lowest signal-to-noise ratio, in dB, that the built-in compressed
+/// profile accepts before storing a line uncompressed. Below this, the
+/// DPCM noise is audible over quiet speech.
+const DEFAULT_MIN_SNR_DB: f64 = 20.0;
+
+/// The limiter's default ceiling for 8-bit output, in dBFS. This leaves room
+/// for the dither, which can add a step either way.
+const DEFAULT_8BIT_CEILING_DB: f64 = -0.3;
+
+/// The limiter's default ceiling for other output, in dBFS.
+const DEFAULT_CEILING_DB: f64 = -0.1;
+
+/// The signal-to-noise ratio reported for lines that compress without loss,
+/// as JSON can't hold an infinite one.
+const LOSSLESS_SNR_DB: f64 = 100.0;
+
+const BUILTIN_PROFILES: &[(&str, ConversionProfile)] = &[
+    (
+        "game-ogg",
+        ConversionProfile {
+            format: ProfileFormat::Ogg,
+            sample_rate: None,
+            mono: false,
+            bitrate: None,
+            sixteen_bit: false,
+            compressed: false,
+            min_snr_db: None,
+            mastering: None,
+        },
+    ),
+    (
+        "sci11-speech",
+        ConversionProfile {
+            format: ProfileFormat::Sol,
+            sample_rate: Some(11025),
+            mono: true,
+            bitrate: None,
+            sixteen_bit: false,
+            compressed: false,
+            min_snr_db: None,
+            mastering: Some(Mastering::TUNED),
+        },
+    ),
+    (
+        "sci11-speech-dpcm",
+        ConversionProfile {
+            format: ProfileFormat::Sol,
+            sample_rate: Some(11025),
+            mono: true,
+            bitrate: None,
+            sixteen_bit: false,
+            compressed: true,
+            min_snr_db: Some(DEFAULT_MIN_SNR_DB),
+            mastering: Some(Mastering::TUNED),
+        },
+    ),
+    (
+        "hq-archive",
+        ConversionProfile {
+            format: ProfileFormat::Flac,
+            sample_rate: Some(44100),
+            mono: false,
+            bitrate: None,
+            sixteen_bit: false,
+            compressed: false,
+            min_snr_db: None,
+            mastering: None,
+        },
+    ),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileFormat {
+    /// Uncompressed SOL, as used by the original SCI1.1 games. 8-bit unless
+    /// `sixteen_bit` is set.
+    Sol,
+    Flac,
+    Ogg,
+}
+
+/// The dither added when reducing samples to the profile's bit depth.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    None,
+    Rectangular,
+    Triangular,
+    /// Triangular, high-passed so more of the noise is above speech.
+    TriangularHp,
+}
+
+impl Dither {
+    fn ffmpeg_name(self) -> Option<&'static str> {
+        match self {
+            Dither::None => None,
+            Dither::Rectangular => Some("rectangular"),
+            Dither::Triangular => Some("triangular"),
+            Dither::TriangularHp => Some("triangular_hp"),
+        }
+    }
+}
+
+/// The limiter and dither that finish a profile's conversion. Settings left
+/// unset are tuned to the profile's bit depth.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Mastering {
+    /// The limiter's ceiling, in dBFS.
+    #[serde(default)]
+    pub ceiling_db: Option<f64>,
+    /// How far ahead the limiter looks for peaks, in milliseconds.
+    #[serde(default)]
+    pub lookahead_ms: Option<f64>,
+    /// How quickly the limiter lets go after a peak, in milliseconds.
+    #[serde(default)]
+    pub release_ms: Option<f64>,
+    /// The dither for SOL samples.
+    #[serde(default)]
+    pub dither: Option<Dither>,
+}
+
+impl Mastering {
+    /// Mastering with every setting tuned to the bit depth.
+    const TUNED: Mastering = Mastering {
+        ceiling_db: None,
+        lookahead_ms: None,
+        release_ms: None,
+        dither: None,
+    };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConversionProfile {
+    pub format: ProfileFormat,
+    /// The sample rate to convert to. If not set, the input's rate is kept.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// Mix down to a single channel.
+    #[serde(default)]
+    pub mono: bool,
+    /// The bitrate, for lossy formats.
+    #[serde(default)]
+    pub bitrate: Option<u32>,
+    /// Use 16-bit samples, rather than 8-bit. Only for SOL.
+    #[serde(default)]
+    pub sixteen_bit: bool,
+    /// DPCM-compress the samples. Only for 8-bit SOL.
+    #[serde(default)]
+    pub compressed: bool,
+    /// The lowest signal-to-noise ratio, in dB, accepted for a compressed
+    /// line. Lines that compress worse are stored uncompressed.
+    #[serde(default)]
+    pub min_snr_db: Option<f64>,
+    /// Limit and dither the output as the last step of conversion.
+    #[serde(default)]
+    pub mastering: Option<Mastering>,
+}
+
+/// A converted line, ready to be packed.
+pub struct FinishedSample {
+    pub data: Vec<u8>,
+    /// How well the line compressed, for profiles that compress.
+    pub compression: Option<CompressionQuality>,
+}
+
+/// The signal-to-noise ratio of decoded unsigned 8-bit samples against the
+/// samples they were encoded from, in dB.
+fn snr_db(source: &[u8], decoded: &[u8]) -> f64 {
+    let centered = |sample: u8| f64::from(sample) - 128.0;
+    let signal: f64 = source.iter().map(|&s| centered(s).powi(2)).sum();
+    let noise: f64 = source
+        .iter()
+        .zip(decoded)
+        .map(|(&s, &d)| (f64::from(s) - f64::from(d)).powi(2))
+        .sum();
+    if noise == 0.0 {
+        return LOSSLESS_SNR_DB;
+    }
+    (10.0 * (signal / noise).log10()).min(LOSSLESS_SNR_DB)
+}
+
+impl ConversionProfile {
+    fn validate(&self, name: &str) -> anyhow::Result<()> {
+        if self.format == ProfileFormat::Sol {
+            let Some(sample_rate) = self.sample_rate else {
+                anyhow::bail!("Profile {name:?} uses SOL, which needs a sample rate");
+            };
+            anyhow::ensure!(
+                u16::try_from(sample_rate).is_ok(),
+                "Profile {name:?} has a sample rate too high for SOL: {sample_rate}"
+            );
+            anyhow::ensure!(self.mono, "Profile {name:?} uses SOL, which must be mono");
+        }
+        anyhow::ensure!(
+            !self.sixteen_bit || self.format == ProfileFormat::Sol,
+            "Profile {name:?} is 16-bit, which is only supported for SOL"
+        );
+        anyhow::ensure!(
+            !self.compressed || (self.format == ProfileFormat::Sol && !self.sixteen_bit),
+            "Profile {name:?} is compressed, which is only supported for 8-bit SOL"
+        );
+        anyhow::ensure!(
+            self.min_snr_db.is_none() || self.compressed,
+            "Profile {name:?} sets a minimum SNR, but isn't compressed"
+        );
+        if let Some(mastering) = &self.mastering {
+            anyhow::ensure!(
+                mastering
+                    .ceiling_db
+                    .is_none_or(|ceiling_db| (-24.0..=0.0).contains(&ceiling_db)),
+                "Profile {name:?} has a limiter ceiling outside -24 to 0 dB"
+            );
+            anyhow::ensure!(
+                mastering
+                    .lookahead_ms
+                    .is_none_or(|lookahead_ms| (0.1..=80.0).contains(&lookahead_ms)),
+                "Profile {name:?} has a limiter lookahead outside 0.1 to 80 ms"
+            );
+            anyhow::ensure!(
+                mastering
+                    .release_ms
+                    .is_none_or(|release_ms| (1.0..=8000.0).contains(&release_ms)),
+                "Profile {name:?} has a limiter release outside 1 to 8000 ms"
+            );
+            anyhow::ensure!(
+                mastering.dither.is_none() || self.format == ProfileFormat::Sol,
+                "Profile {name:?} sets a dither, which is only supported for SOL"
+            );
+        }
+        Ok(())
+    }
+
+    /// A profile producing clips in the same format as the game's.
+    pub fn matching(format: &SolFormat) -> Self {
+        ConversionProfile {
+            format: ProfileFormat::Sol,
+            sample_rate: Some(u32::from(format.sample_rate)),
+            mono: true,
+            bitrate: None,
+            sixteen_bit: format.sixteen_bit,
+            // Only 8-bit DPCM can be encoded, so 16-bit games get
+            // uncompressed clips, which the interpreter also plays.
+            compressed: format.compressed && !format.sixteen_bit,
+            min_snr_db: None,
+            mastering: (!format.sixteen_bit).then_some(Mastering::TUNED),
+        }
+    }
+
+    /// Describes how the profile's output differs from the game's clips in
+    /// ways the interpreter has to convert, if it does.
+    pub fn differences(&self, format: &SolFormat) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let Some(sample_rate) = self.sample_rate
+            && sample_rate != u32::from(format.sample_rate)
+        {
+            differences.push(format!(
+                "converts to {sample_rate} Hz, but the game's clips are {} Hz",
+                format.sample_rate
+            ));
+        }
+        if self.format == ProfileFormat::Sol && self.sixteen_bit != format.sixteen_bit {
+            let bits = |sixteen_bit| if sixteen_bit { 16 } else { 8 };
+            differences.push(format!(
+                "converts to {}-bit, but the game's clips are {}-bit",
+                bits(self.sixteen_bit),
+                bits(format.sixteen_bit)
+            ));
+        }
+        differences
+    }
+
+    /// The audio filter that resamples and downmixes the input, if needed.
+    pub fn audio_filter(&self) -> Option<String> {
+        let mut filters = Vec::new();
+        if let Some(sample_rate) = self.sample_rate {
+            filters.push(format!("aresample={sample_rate}"));
+        }
+        if self.mono {
+            filters.push("aformat=channel_layouts=mono".to_string());
+        }
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+
+    /// The audio filter that masters the output, if the profile does: a
+    /// lookahead limiter, then for SOL, dithering to the bit depth.
+    pub fn mastering_filter(&self) -> Option<String> {
+        let mastering = self.mastering?;
+        let eight_bit = self.format == ProfileFormat::Sol && !self.sixteen_bit;
+        let ceiling_db = mastering.ceiling_db.unwrap_or(if eight_bit {
+            DEFAULT_8BIT_CEILING_DB
+        } else {
+            DEFAULT_CEILING_DB
+        });
+        let mut filter = format!(
+            "alimiter=limit={:.4}:attack={}:release={}:level=false",
+            10f64.powf(ceiling_db / 20.0).max(0.0625),
+            mastering.lookahead_ms.unwrap_or(5.0),
+            mastering.release_ms.unwrap_or(50.0)
+        );
+        if self.format == ProfileFormat::Sol {
+            let dither = mastering.dither.unwrap_or(if eight_bit {
+                Dither::TriangularHp
+            } else {
+                Dither::Triangular
+            });
+            if let Some(dither) = dither.ffmpeg_name() {
+                let sample_format = if eight_bit { "u8" } else { "s16" };
+                write!(
+                    filter,
+                    ",aresample=osf={sample_format}:dither_method={dither}"
+                )
+                .unwrap();
+            }
+        }
+        Some(filter)
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        match self.format {
+            ProfileFormat::Sol if self.sixteen_bit => OutputFormat::RawS16Le,
+            ProfileFormat::Sol => OutputFormat::RawU8,
+            ProfileFormat::Flac => FlacOutputOptions::default().into(),
+            ProfileFormat::Ogg => match self.bitrate {
+                Some(bitrate) => OggVorbisOutputOptions::new(bitrate).into(),
+                None => OggVorbisOutputOptions::default().into(),
+            },
+        }
+    }
+
+    /// The file extension for converted samples.
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            ProfileFormat::Sol => "sol",
+            ProfileFormat::Flac => "flac",
+            ProfileFormat::Ogg => "ogg",
+        }
+    }
+
+    /// The format of the converted samples in the audio volume.
+    pub fn audio_format(&self) -> AudioFormat {
+        match self.format {
+            ProfileFormat::Sol => AudioFormat::Sol,
+            ProfileFormat::Flac => AudioFormat::Flac,
+            ProfileFormat::Ogg => AudioFormat::Ogg,
+        }
+    }
+
+    /// Turns ffmpeg's output into the final sample data. For compressed
+    /// profiles, the line is stored uncompressed if `store_uncompressed` is
+    /// set, or if it compresses worse than the profile's minimum SNR.
+    pub fn finish(
+        &self,
+        data: Vec<u8>,
+        store_uncompressed: bool,
+    ) -> anyhow::Result<FinishedSample> {
+        let sample_rate = match self.format {
+            ProfileFormat::Sol => self
+                .sample_rate
+                .expect("SOL profiles are validated to have a sample rate"),
+            ProfileFormat::Flac | ProfileFormat::Ogg => {
+                return Ok(FinishedSample {
+                    data,
+                    compression: None,
+                });
+            }
+        };
+        let sample_rate = u16::try_from(sample_rate)?;
+        if self.sixteen_bit {
+            return Ok(FinishedSample {
+                data: write_sol_clip_16bit(sample_rate, &data)?,
+                compression: None,
+            });
+        }
+        if !self.compressed {
+            return Ok(FinishedSample {
+                data: write_sol_clip(sample_rate, &data)?,
+                compression: None,
+            });
+        }
+        let snr_db = snr_db(&data, &decode_dpcm8(&encode_dpcm8(&data)));
+        let below_minimum = self
+            .min_snr_db
+            .is_some_and(|min_snr_db| snr_db < min_snr_db);
+        let stored_uncompressed = store_uncompressed || below_minimum;
+        let data = if stored_uncompressed {
+            write_sol_clip(sample_rate, &data)?
+        } else {
+            write_sol_clip_dpcm8(sample_rate, &data)?
+        };
+        Ok(FinishedSample {
+            data,
+            compression: Some(CompressionQuality {
+                snr_db,
+                stored_uncompressed,
+            }),
+        })
+    }
+}
+
+/// The built-in profiles, plus any defined by a project's config.
+pub struct ProfileSet {
+    custom: BTreeMap<String, ConversionProfile>,
+}
+
+impl ProfileSet {
+    /// Collects the profiles defined in the config. Profiles in the config
+    /// take precedence over built-in profiles of the same name.
+    pub fn from_config(config: &FanDubConfig) -> anyhow::Result<Self> {
+        for (name, profile) in &config.profiles {
+            profile.validate(name)?;
+        }
+        Ok(ProfileSet {
+            custom: config.profiles.clone(),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&ConversionProfile> {
+        self.custom
+            .get(name)
+            .or_else(|| {
+                BUILTIN_PROFILES
+                    .iter()
+                    .find(|(builtin, _)| *builtin == name)
+                    .map(|(_, profile)| profile)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown conversion profile {name:?}. Available profiles: {}",
+                    self.names().collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let custom = self.custom.keys().map(String::as_str);
+        let builtin = BUILTIN_PROFILES
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| !self.custom.contains_key(*name));
+        custom.chain(builtin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_profiles_are_valid() -> anyhow::Result<()> {
+        for (name, profile) in BUILTIN_PROFILES {
+            profile.validate(name)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_profile_overrides_builtin() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("fan-dub.json"),
+            r#"{ "profiles": { "sci11-speech": { "format": "sol", "sample_rate": 22050, "mono": true } } }"#,
+        )?;
+        let profiles = ProfileSet::from_config(&FanDubConfig::load(dir.path())?)?;
+        assert_eq!(profiles.get("sci11-speech")?.sample_rate, Some(22050));
+        assert_eq!(profiles.get("hq-archive")?.format, ProfileFormat::Flac);
+        assert!(profiles.get("nonexistent").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sol_profile_needs_sample_rate() {
+        let profile = ConversionProfile {
+            format: ProfileFormat::Sol,
+            sample_rate: None,
+            mono: true,
+            bitrate: None,
+            sixteen_bit: false,
+            compressed: false,
+            min_snr_db: None,
+            mastering: None,
+        };
+        assert!(profile.validate("test").is_err());
+    }
+
+    #[test]
+    fn test_compressed_lines_below_min_snr_are_stored_uncompressed() -> anyhow::Result<()> {
+        let profiles = ProfileSet::from_config(&FanDubConfig::default())?;
+        let profile = profiles.get("sci11-speech-dpcm")?;
+
+        // A quiet, slow wave compresses almost perfectly.
+        let quiet: Vec<u8> = (0..1000)
+            .map(|i| (128.0 + 40.0 * (f64::from(i) / 20.0).sin()) as u8)
+            .collect();
+        let finished = profile.finish(quiet.clone(), false)?;
+        let quality = finished.compression.unwrap();
+        assert!(quality.snr_db > DEFAULT_MIN_SNR_DB);
+        assert!(!quality.stored_uncompressed);
+        assert_eq!(finished.data.len(), 13 + quiet.len() / 2);
+
+        // The line can still be stored uncompressed on request.
+        let finished = profile.finish(quiet.clone(), true)?;
+        assert!(finished.compression.unwrap().stored_uncompressed);
+        assert_eq!(finished.data.len(), 13 + quiet.len());
+
+        // A full-scale square wave changes faster than the steps can follow.
+        let harsh: Vec<u8> = (0..1000).map(|i| if i % 4 < 2 { 0 } else { 255 }).collect();
+        let finished = profile.finish(harsh.clone(), false)?;
+        let quality = finished.compression.unwrap();
+        assert!(quality.snr_db < DEFAULT_MIN_SNR_DB);
+        assert!(quality.stored_uncompressed);
+        assert_eq!(finished.data.len(), 13 + harsh.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_needs_8bit_sol() {
+        let profile = ConversionProfile {
+            format: ProfileFormat::Sol,
+            sample_rate: Some(22050),
+            mono: true,
+            bitrate: None,
+            sixteen_bit: true,
+            compressed: true,
+            min_snr_db: None,
+            mastering: None,
+        };
+        assert!(profile.validate("test").is_err());
+    }
+
+    #[test]
+    fn test_mastering_filter() -> anyhow::Result<()> {
+        let profiles = ProfileSet::from_config(&FanDubConfig::default())?;
+        assert_eq!(
+            profiles.get("sci11-speech")?.mastering_filter().as_deref(),
+            Some(
+                "alimiter=limit=0.9661:attack=5:release=50:level=false,aresample=osf=u8:dither_method=triangular_hp"
+            )
+        );
+        assert_eq!(profiles.get("game-ogg")?.mastering_filter(), None);
+
+        let profile: ConversionProfile = serde_json::from_str(
+            r#"{ "format": "ogg", "mastering": { "ceiling_db": -1.0, "lookahead_ms": 2.5 } }"#,
+        )?;
+        profile.validate("test")?;
+        assert_eq!(
+            profile.mastering_filter().as_deref(),
+            Some("alimiter=limit=0.8913:attack=2.5:release=50:level=false")
+        );
+        let dithered: ConversionProfile = serde_json::from_str(
+            r#"{ "format": "ogg", "mastering": { "dither": "triangular" } }"#,
+        )?;
+        assert!(dithered.validate("test").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_profile() -> anyhow::Result<()> {
+        let format = SolFormat {
+            sample_rate: 22050,
+            sixteen_bit: true,
+            compressed: false,
+        };
+        let profile = ConversionProfile::matching(&format);
+        profile.validate(MATCH_GAME_PROFILE)?;
+        assert!(profile.differences(&format).is_empty());
+        assert!(matches!(profile.output_format(), OutputFormat::RawS16Le));
+
+        let profiles = ProfileSet::from_config(&FanDubConfig::default())?;
+        assert_eq!(
+            profiles.get("sci11-speech")?.differences(&format),
+            [
+                "converts to 11025 Hz, but the game's clips are 22050 Hz",
+                "converts to 8-bit, but the game's clips are 16-bit"
+            ]
+        );
+        // Without a sample rate, the input's is kept, which can't be checked.
+        assert!(profiles.get("game-ogg")?.differences(&format).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+//! Release packages of a build.
+//!
+//! A package holds a build's game files (the audio volume, and map and
+//! message patches), a `release.json` manifest, and a `CHANGELOG.md` listing
+//! the lines added, changed or removed since the previous release. The
+//! manifest records the release's version, a hash of every game file, and the
+//! fingerprint of every packed take, so the next release can be compared
+//! against it.
+//!
+//! A delta package only holds the game files that changed since a previous
+//! release. Note that the audio volume holds every line, so it is included
+//! whenever any line changed.
+//!
+//! A package may be signed (see [`crate::signing`]), and an install can be
+//! checked against a package's manifest with [`verify_install`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sci_utils::fs;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use ed25519_dalek::SigningKey;
+
+use crate::{fingerprint::BuildFingerprints, signing};
+
+/// A game file in a release.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReleaseFile {
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// The lines that differ between two releases, by sample key.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Changelog {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Changelog {
+    /// Compares the fingerprints of the takes in two releases.
+    pub fn between(
+        previous: &BTreeMap<String, String>,
+        current: &BTreeMap<String, String>,
+    ) -> Self {
+        let mut changelog = Changelog::default();
+        for (key, fingerprint) in current {
+            match previous.get(key) {
+                None => changelog.added.push(key.clone()),
+                Some(previous) if previous != fingerprint => changelog.changed.push(key.clone()),
+                Some(_) => {}
+            }
+        }
+        changelog.removed = previous
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+        changelog
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseManifest {
+    pub version: Version,
+    /// The release the changelog (and a delta package) is relative to.
+    pub previous_version: Option<Version>,
+    /// Every game file of the release, including those left out of a delta
+    /// package.
+    pub files: BTreeMap<String, ReleaseFile>,
+    /// The game files in the package. For a full package, all of them.
+    pub included: Vec<String>,
+    /// Game files of the previous release that should be deleted.
+    pub removed_files: Vec<String>,
+    /// The fingerprint of each packed take, by sample key.
+    pub lines: BTreeMap<String, String>,
+    pub changelog: Changelog,
+}
+
+impl ReleaseManifest {
+    pub const FILE_NAME: &str = "release.json";
+
+    /// The path of a manifest, given either its path or the package
+    /// directory.
+    pub fn path(path: &Path) -> PathBuf {
+        if path.is_dir() {
+            path.join(Self::FILE_NAME)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Loads a manifest, given either its path or the package directory.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::parse(&std::fs::read(Self::path(path))?)
+    }
+
+    /// Parses a manifest from the contents of `release.json`.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.included.len() < self.files.len()
+    }
+
+    pub fn changelog_markdown(&self) -> String {
+        let mut md = format!("# Release {}\n", self.version);
+        match &self.previous_version {
+            Some(previous) => {
+                writeln!(md, "\nChanges since {previous}.").unwrap();
+                if self.is_delta() {
+                    md.push_str(
+                        "\nThis is a delta package: install it over the previous release.\n",
+                    );
+                }
+            }
+            None => md.push_str("\nFirst release.\n"),
+        }
+        if self.previous_version.is_some() && self.changelog.is_empty() {
+            md.push_str("\nNo lines changed.\n");
+        }
+        for (heading, keys) in [
+            ("Added lines", &self.changelog.added),
+            ("Changed lines", &self.changelog.changed),
+            ("Removed lines", &self.changelog.removed),
+            ("Files to delete", &self.removed_files),
+        ] {
+            if keys.is_empty() {
+                continue;
+            }
+            writeln!(md, "\n## {heading}\n").unwrap();
+            for key in keys {
+                writeln!(md, "- {key}").unwrap();
+            }
+        }
+        md
+    }
+}
+
+/// Whether a file in a build's output directory is a game file, rather than
+/// a report.
+fn is_game_file(name: &str) -> bool {
+    name.eq_ignore_ascii_case("resource.aud")
+        || Path::new(name)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("map") || ext.eq_ignore_ascii_case("msg"))
+}
+
+fn hash_file(path: &Path) -> anyhow::Result<ReleaseFile> {
+    let contents = std::fs::read(path)?;
+    Ok(ReleaseFile {
+        sha256: Sha256::digest(&contents)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+        size: contents.len() as u64,
+    })
+}
+
+/// Packages the build in `build_dir` as a release in `package_dir`. With a
+/// previous release, the changelog is relative to it, and if `delta` is set
+/// only the game files that changed since are included. If `modified` is
+/// set, every file in the package is given that modification time, so
+/// packages of the same build are identical. With a signing key, the manifest
+/// is signed.
+pub fn package_release(
+    build_dir: &Path,
+    package_dir: &Path,
+    version: Version,
+    previous: Option<&ReleaseManifest>,
+    delta: bool,
+    modified: Option<SystemTime>,
+    signing_key: Option<&SigningKey>,
+) -> anyhow::Result<ReleaseManifest> {
+    anyhow::ensure!(
+        !delta || previous.is_some(),
+        "A delta package needs a previous release"
+    );
+    if let Some(previous) = previous {
+        anyhow::ensure!(
+            version > previous.version,
+            "Version {version} must be newer than the previous release, {}",
+            previous.version
+        );
+    }
+    let fingerprints = BuildFingerprints::load(build_dir)?
+        .ok_or_else(|| anyhow::anyhow!("No completed build found in {}", build_dir.display()))?;
+
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(build_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && is_game_file(&name) {
+            files.insert(name, hash_file(&entry.path())?);
+        }
+    }
+    anyhow::ensure!(
+        !files.is_empty(),
+        "No game files found in {}",
+        build_dir.display()
+    );
+
+    let included: Vec<String> = files
+        .iter()
+        .filter(|(name, file)| {
+            !delta || previous.is_none_or(|previous| previous.files.get(*name) != Some(*file))
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    let removed_files = previous
+        .map(|previous| {
+            previous
+                .files
+                .keys()
+                .filter(|name| !files.contains_key(*name))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let lines = fingerprints.takes().clone();
+    let manifest = ReleaseManifest {
+        version,
+        previous_version: previous.map(|previous| previous.version.clone()),
+        changelog: previous
+            .map(|previous| Changelog::between(&previous.lines, &lines))
+            .unwrap_or_default(),
+        files,
+        included,
+        removed_files,
+        lines,
+    };
+
+    std::fs::create_dir_all(package_dir)?;
+    for name in &manifest.included {
+        std::fs::copy(build_dir.join(name), package_dir.join(name))?;
+    }
+    let manifest_path = package_dir.join(ReleaseManifest::FILE_NAME);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+    std::fs::write(
+        package_dir.join("CHANGELOG.md"),
+        manifest.changelog_markdown(),
+    )?;
+    let mut written = vec![manifest_path.clone(), package_dir.join("CHANGELOG.md")];
+    if let Some(signing_key) = signing_key {
+        signing::sign_manifest(&manifest_path, signing_key)?;
+        written.push(signing::signature_path(&manifest_path));
+    }
+    if let Some(modified) = modified {
+        let included = manifest.included.iter().map(|name| package_dir.join(name));
+        for path in included.chain(written) {
+            fs::set_modified(&path, modified)?;
+        }
+    }
+    Ok(manifest)
+}
+
+/// A way an installed game differs from a release.
+#[derive(Debug, PartialEq)]
+pub enum InstallProblem {
+    /// The file isn't installed.
+    Missing(String),
+    /// The file doesn't match the release's hash.
+    Modified(String),
+    /// A file the release removed is still installed.
+    NotRemoved(String),
+}
+
+impl std::fmt::Display for InstallProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallProblem::Missing(name) => write!(f, "{name} is missing"),
+            InstallProblem::Modified(name) => {
+                write!(f, "{name} doesn't match the release")
+            }
+            InstallProblem::NotRemoved(name) => {
+                write!(f, "{name} should have been deleted")
+            }
+        }
+    }
+}
+
+/// Checks the release's game files in `game_dir` against the manifest.
+pub fn verify_install(
+    game_dir: &Path,
+    manifest: &ReleaseManifest,
+) -> anyhow::Result<Vec<InstallProblem>> {
+    let mut problems = Vec::new();
+    for (name, file) in &manifest.files {
+        let path = game_dir.join(name);
+        if !path.exists() {
+            problems.push(InstallProblem::Missing(name.clone()));
+        } else if hash_file(&path)? != *file {
+            problems.push(InstallProblem::Modified(name.clone()));
+        }
+    }
+    for name in &manifest.removed_files {
+        if game_dir.join(name).exists() {
+            problems.push(InstallProblem::NotRemoved(name.clone()));
+        }
+    }
+    Ok(problems)
+}
+
+/// The default package directory name for a release.
+pub fn package_name(version: &Version, previous: Option<&Version>) -> PathBuf {
+    match previous {
+        Some(previous) => PathBuf::from(format!("fan-dub-{version}-delta-from-{previous}")),
+        None => PathBuf::from(format!("fan-dub-{version}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprints(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, fingerprint)| (key.to_string(), fingerprint.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_changelog() {
+        let previous = fingerprints(&[("10-1-2-0-1", "aa"), ("10-1-2-0-2", "bb")]);
+        let current = fingerprints(&[
+            ("10-1-2-0-1", "aa"),
+            ("10-1-2-0-2", "cc"),
+            ("11-1-1-0-1", "dd"),
+        ]);
+        let changelog = Changelog::between(&previous, &current);
+        assert_eq!(changelog.added, ["11-1-1-0-1"]);
+        assert_eq!(changelog.changed, ["10-1-2-0-2"]);
+        assert!(changelog.removed.is_empty());
+        assert_eq!(
+            Changelog::between(&current, &previous).removed,
+            ["11-1-1-0-1"]
+        );
+    }
+
+    fn write_build(dir: &Path, map_contents: &[u8], takes: &[(&str, &str)]) {
+        std::fs::write(dir.join("resource.aud"), b"volume").unwrap();
+        std::fs::write(dir.join("10.map"), map_contents).unwrap();
+        std::fs::write(dir.join("qa-report.json"), b"{}").unwrap();
+        BuildFingerprints::new(fingerprints(takes))
+            .save(dir)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_delta_package() -> anyhow::Result<()> {
+        let build = tempfile::tempdir()?;
+        let release = tempfile::tempdir()?;
+        write_build(build.path(), b"map 1", &[("10-1-2-0-1", "aa")]);
+        let first = package_release(
+            build.path(),
+            &release.path().join("1"),
+            Version::new(0, 1, 0),
+            None,
+            false,
+            None,
+            None,
+        )?;
+        assert_eq!(first.included, ["10.map", "resource.aud"]);
+        assert!(release.path().join("1/CHANGELOG.md").exists());
+
+        write_build(build.path(), b"map 2", &[("10-1-2-0-1", "ab")]);
+        let previous = ReleaseManifest::load(&release.path().join("1"))?;
+        assert!(
+            package_release(
+                build.path(),
+                &release.path().join("x"),
+                Version::new(0, 1, 0),
+                Some(&previous),
+                true,
+                None,
+                None
+            )
+            .is_err()
+        );
+        let second = package_release(
+            build.path(),
+            &release.path().join("2"),
+            Version::new(0, 2, 0),
+            Some(&previous),
+            true,
+            None,
+            None,
+        )?;
+        assert_eq!(second.included, ["10.map"]);
+        assert!(second.is_delta());
+        assert_eq!(second.changelog.changed, ["10-1-2-0-1"]);
+        assert!(!release.path().join("2/resource.aud").exists());
+        assert!(
+            second
+                .changelog_markdown()
+                .contains("## Changed lines\n\n- 10-1-2-0-1\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_install() -> anyhow::Result<()> {
+        let build = tempfile::tempdir()?;
+        let release = tempfile::tempdir()?;
+        write_build(build.path(), b"map 1", &[("10-1-2-0-1", "aa")]);
+        let manifest = package_release(
+            build.path(),
+            release.path(),
+            Version::new(0, 1, 0),
+            None,
+            false,
+            None,
+            None,
+        )?;
+        assert!(verify_install(build.path(), &manifest)?.is_empty());
+        std::fs::write(build.path().join("10.map"), b"map 2")?;
+        std::fs::remove_file(build.path().join("resource.aud"))?;
+        assert_eq!(
+            verify_install(build.path(), &manifest)?,
+            [
+                InstallProblem::Modified("10.map".to_string()),
+                InstallProblem::Missing("resource.aud".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_package() -> anyhow::Result<()> {
+        let build = tempfile::tempdir()?;
+        let release = tempfile::tempdir()?;
+        write_build(build.path(), b"map 1", &[("10-1-2-0-1", "aa")]);
+        let modified = SystemTime::UNIX_EPOCH;
+        package_release(
+            build.path(),
+            release.path(),
+            Version::new(0, 1, 0),
+            None,
+            false,
+            Some(modified),
+            None,
+        )?;
+        for entry in std::fs::read_dir(release.path())? {
+            assert_eq!(entry?.metadata()?.modified()?, modified);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Remapping lines between versions of a game. Some games changed their
+//! message tuples between releases (e.g. floppy and CD), so takes recorded
+//! against one version's book are packed under the other's tuples by rules
+//! in `fan-dub.json`:
+//!
+//! ```json
+//! { "remap": [ { "from": "10-1-2-0-1", "to": "10-1-3-0-1" }, { "from": "20-4-*-*-*", "to": "21-*-*-*-*" } ] }
+//! ```
+//!
+//! A `*` in `from` matches any value, and a `*` in `to` keeps the line's
+//! value. The first rule that matches a line applies. Rules only move
+//! recorded takes; stand-ins are made for the version being built already.
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::Sample;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemapRule {
+    /// The line keys the rule applies to, in the recorded version.
+    pub from: String,
+    /// The line keys to pack them under, in the version being built.
+    pub to: String,
+}
+
+/// A line key with wildcards: room, noun, verb, condition and sequence, each
+/// either a value or `*`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyPattern([Option<u16>; 5]);
+
+impl KeyPattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let mut fields = [None; 5];
+        let mut parts = pattern.split('-');
+        for (i, field) in fields.iter_mut().enumerate() {
+            let part = parts.next()?;
+            if part != "*" {
+                let value: u16 = part.parse().ok()?;
+                // Only the room is wider than a byte.
+                if i > 0 && value > u16::from(u8::MAX) {
+                    return None;
+                }
+                *field = Some(value);
+            }
+        }
+        parts.next().is_none().then_some(KeyPattern(fields))
+    }
+
+    fn fields(room: u16, message_id: &MessageId) -> [u16; 5] {
+        [
+            room,
+            message_id.noun().into(),
+            message_id.verb().into(),
+            message_id.condition().into(),
+            message_id.sequence().into(),
+        ]
+    }
+
+    fn matches(&self, room: u16, message_id: &MessageId) -> bool {
+        self.0
+            .iter()
+            .zip(Self::fields(room, message_id))
+            .all(|(pattern, value)| pattern.is_none_or(|pattern| pattern == value))
+    }
+
+    fn apply(&self, room: u16, message_id: &MessageId) -> (u16, MessageId) {
+        let mut fields = Self::fields(room, message_id);
+        for (field, pattern) in fields.iter_mut().zip(self.0) {
+            if let Some(pattern) = pattern {
+                *field = pattern;
+            }
+        }
+        // Patterns other than the room's are checked to fit in a byte.
+        let [room, noun, verb, condition, sequence] = fields;
+        let byte = |value: u16| value as u8;
+        (
+            room,
+            MessageId::new(byte(noun), byte(verb), byte(condition), byte(sequence)),
+        )
+    }
+}
+
+/// The remapping rules of a project, in order.
+#[derive(Debug, Default)]
+pub struct TupleRemap {
+    rules: Vec<(KeyPattern, KeyPattern)>,
+}
+
+impl TupleRemap {
+    pub fn from_config(config: &[RemapRule]) -> anyhow::Result<Self> {
+        let parse = |pattern: &str| {
+            KeyPattern::parse(pattern).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid line pattern {pattern:?} in the remap rules; expected room-noun-verb-condition-sequence, with * for any value"
+                )
+            })
+        };
+        let rules = config
+            .iter()
+            .map(|rule| Ok((parse(&rule.from)?, parse(&rule.to)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(TupleRemap { rules })
+    }
+
+    /// The line to pack the sample under, if a rule moves it.
+    pub fn target(&self, sample: &Sample) -> Option<(u16, MessageId)> {
+        if sample.stand_in.is_some() {
+            return None;
+        }
+        let (_, to) = self
+            .rules
+            .iter()
+            .find(|(from, _)| from.matches(sample.room, &sample.message_id))?;
+        let target = to.apply(sample.room, &sample.message_id);
+        (target != (sample.room, sample.message_id)).then_some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(key: &str, stand_in: &str) -> Sample {
+        let fields: Vec<&str> = key.split('-').collect();
+        serde_json::from_str(&format!(
+            r#"{{ "room": {}, "message_id": {{ "noun": {}, "verb": {}, "condition": {}, "sequence": {} }},
+                 "clip": {{ "path": "a.wav" }}{stand_in} }}"#,
+            fields[0], fields[1], fields[2], fields[3], fields[4]
+        ))
+        .unwrap()
+    }
+
+    fn remap(rules: &[(&str, &str)]) -> anyhow::Result<TupleRemap> {
+        TupleRemap::from_config(
+            &rules
+                .iter()
+                .map(|(from, to)| RemapRule {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_remap() -> anyhow::Result<()> {
+        let remap = remap(&[
+            ("10-1-2-0-1", "10-1-3-0-1"),
+            ("20-4-*-*-*", "21-*-*-*-*"),
+            ("30-*-*-*-*", "30-*-*-*-*"),
+        ])?;
+        assert_eq!(
+            remap.target(&sample("10-1-2-0-1", "")),
+            Some((10, MessageId::new(1, 3, 0, 1)))
+        );
+        assert_eq!(remap.target(&sample("10-1-2-0-2", "")), None);
+        assert_eq!(
+            remap.target(&sample("20-4-7-1-3", "")),
+            Some((21, MessageId::new(4, 7, 1, 3)))
+        );
+        assert_eq!(remap.target(&sample("30-1-2-0-1", "")), None);
+        assert_eq!(
+            remap.target(&sample("10-1-2-0-1", r#", "stand_in": "placeholder""#)),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(remap(&[("10-1-2-0", "10-1-2-0-1")]).is_err());
+        assert!(remap(&[("10-1-2-0-1", "10-1-2-0-1-1")]).is_err());
+        assert!(remap(&[("10-1-2-0-1", "10-256-2-0-1")]).is_err());
+        assert!(remap(&[("10-x-2-0-1", "10-1-2-0-1")]).is_err());
+    }
+}
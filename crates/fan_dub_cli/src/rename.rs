@@ -0,0 +1,301 @@
+//! Moving takes into the canonical layout: a directory per line, named by
+//! the line's key, holding `<key>_take<n>.<ext>` files
+//! (e.g. `10-1-2-0-1/10-1-2-0-1_take2.wav`).
+//!
+//! A file is taken to be a take of a line if its name starts with the line's
+//! key (see [`SampleDir::find_takes`]), or if `samples.json` selects it for
+//! the line. Selected takes that are moved are updated in `samples.json`.
+//!
+//! Each applied rename is recorded in a journal under `.renames/` in the
+//! sample directory, so the latest one can be undone.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::{SampleDir, normalize_path, sample_key, take_line};
+
+/// The directory of the journals, in the sample directory.
+const JOURNAL_DIR: &str = ".renames";
+
+/// A take to move. Paths are relative to the sample directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TakeMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// The number of the take, if the path is already a canonical take of the
+/// line with the key.
+fn canonical_take_number(path: &Path, key: &str) -> Option<u32> {
+    if path.parent() != Some(Path::new(key)) {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(key)?
+        .strip_prefix("_take")?
+        .parse()
+        .ok()
+}
+
+/// Plans the moves that put each line's takes in the canonical layout.
+/// Canonical takes keep their numbers; the others are numbered from the
+/// lowest free number, in order of their paths. `exists` says whether a
+/// path is in use.
+fn canonical_moves(
+    takes: &BTreeMap<(u16, MessageId), BTreeSet<PathBuf>>,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<TakeMove> {
+    let mut moves = Vec::new();
+    for ((room, message_id), paths) in takes {
+        let key = sample_key(*room, message_id);
+        let mut used: BTreeSet<u32> = paths
+            .iter()
+            .filter_map(|path| canonical_take_number(path, &key))
+            .collect();
+        for path in paths {
+            if canonical_take_number(path, &key).is_some() {
+                continue;
+            }
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let (number, to) = (1..)
+                .filter(|number| !used.contains(number))
+                .map(|number| {
+                    let to = Path::new(&key).join(format!("{key}_take{number}.{extension}"));
+                    (number, to)
+                })
+                .find(|(_, to)| !exists(to))
+                .expect("Take numbers are unbounded");
+            used.insert(number);
+            moves.push(TakeMove {
+                from: path.clone(),
+                to,
+            });
+        }
+    }
+    moves
+}
+
+/// Finds every take in the sample directory, by line.
+pub(crate) fn find_all_takes(
+    sample_dir: &SampleDir,
+) -> anyhow::Result<BTreeMap<(u16, MessageId), BTreeSet<PathBuf>>> {
+    let base_path = sample_dir.base_path();
+    let mut takes: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    let mut dirs = vec![base_path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                // Hidden directories hold generated files, not takes.
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if let Some(line) = take_line(&path) {
+                takes
+                    .entry(line)
+                    .or_default()
+                    .insert(path.strip_prefix(base_path)?.to_path_buf());
+            }
+        }
+    }
+    for sample in sample_dir.takes() {
+        let path = normalize_path(&sample.clip.path);
+        if path.is_relative() && base_path.join(&path).is_file() {
+            takes
+                .entry((sample.room, sample.message_id))
+                .or_default()
+                .insert(path);
+        }
+    }
+    Ok(takes)
+}
+
+/// Plans the moves that put the sample directory's takes in the canonical
+/// layout.
+pub fn plan_renames(sample_dir: &SampleDir) -> anyhow::Result<Vec<TakeMove>> {
+    let base_path = sample_dir.base_path();
+    let takes = find_all_takes(sample_dir)?;
+    // A file selected for one line but named for another can't be put in
+    // both lines' directories.
+    let mut lines_of_path: BTreeMap<&Path, Vec<String>> = BTreeMap::new();
+    for ((room, message_id), paths) in &takes {
+        for path in paths {
+            lines_of_path
+                .entry(path)
+                .or_default()
+                .push(sample_key(*room, message_id));
+        }
+    }
+    if let Some((path, keys)) = lines_of_path.iter().find(|(_, keys)| keys.len() > 1) {
+        anyhow::bail!(
+            "{} is a take of more than one line ({}); rename or deselect it first",
+            path.display(),
+            keys.join(", ")
+        );
+    }
+    Ok(canonical_moves(&takes, |path| {
+        base_path.join(path).exists()
+    }))
+}
+
+/// The moves of an applied rename.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RenameJournal {
+    pub moves: Vec<TakeMove>,
+}
+
+impl RenameJournal {
+    /// The journals in the sample directory, oldest first.
+    fn paths(base_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = match std::fs::read_dir(base_path.join(JOURNAL_DIR)) {
+            Ok(entries) => entries
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+async fn move_file(base_path: &Path, from: &Path, to: &Path) -> anyhow::Result<()> {
+    let to_path = base_path.join(to);
+    anyhow::ensure!(
+        !to_path.exists(),
+        "{} is in the way of {}",
+        to.display(),
+        from.display()
+    );
+    if let Some(parent) = to_path.parent() {
+        smol::fs::create_dir_all(parent).await?;
+    }
+    smol::fs::rename(base_path.join(from), to_path).await?;
+    Ok(())
+}
+
+/// Moves the takes, and updates `samples.json` to match. The journal is
+/// written before anything is moved, so an interrupted rename can be undone
+/// too. Returns the journal's path.
+pub async fn apply_renames(
+    sample_dir: &mut SampleDir,
+    moves: Vec<TakeMove>,
+) -> anyhow::Result<PathBuf> {
+    let base_path = sample_dir.base_path().to_path_buf();
+    let journal_dir = base_path.join(JOURNAL_DIR);
+    smol::fs::create_dir_all(&journal_dir).await?;
+    let number = RenameJournal::paths(&base_path)?
+        .last()
+        .and_then(|path| path.file_stem()?.to_str()?.parse::<u32>().ok())
+        .map_or(1, |number| number + 1);
+    let journal_path = journal_dir.join(format!("{number:04}.json"));
+    let journal = RenameJournal { moves };
+    smol::fs::write(&journal_path, serde_json::to_vec_pretty(&journal)?).await?;
+
+    let mut result = Ok(());
+    for take_move in &journal.moves {
+        result = move_file(&base_path, &take_move.from, &take_move.to).await;
+        if result.is_err() {
+            break;
+        }
+        sample_dir.move_take(&take_move.from, &take_move.to);
+    }
+    // Save the moves that were made, even if one failed.
+    sample_dir.save().await?;
+    result?;
+    Ok(journal_path)
+}
+
+/// Moves the takes of the latest rename back, and updates `samples.json` to
+/// match. Moves that weren't made (if the rename was interrupted) are
+/// skipped. Returns the moves undone, or `None` if there is nothing to undo.
+pub async fn undo_renames(sample_dir: &mut SampleDir) -> anyhow::Result<Option<Vec<TakeMove>>> {
+    let base_path = sample_dir.base_path().to_path_buf();
+    let Some(journal_path) = RenameJournal::paths(&base_path)?.pop() else {
+        return Ok(None);
+    };
+    let journal: RenameJournal = serde_json::from_slice(&smol::fs::read(&journal_path).await?)?;
+    let mut undone = Vec::new();
+    let mut result = Ok(());
+    for take_move in journal.moves.iter().rev() {
+        if !base_path.join(&take_move.to).exists() && base_path.join(&take_move.from).exists() {
+            continue;
+        }
+        result = move_file(&base_path, &take_move.to, &take_move.from).await;
+        if result.is_err() {
+            break;
+        }
+        sample_dir.move_take(&take_move.to, &take_move.from);
+        undone.push(take_move.clone());
+    }
+    sample_dir.save().await?;
+    result?;
+    smol::fs::remove_file(&journal_path).await?;
+    Ok(Some(undone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn takes(paths: &[&str]) -> BTreeMap<(u16, MessageId), BTreeSet<PathBuf>> {
+        BTreeMap::from([(
+            (10, MessageId::new(1, 2, 0, 1)),
+            paths.iter().map(PathBuf::from).collect(),
+        )])
+    }
+
+    fn take_move(from: &str, to: &str) -> TakeMove {
+        TakeMove {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        }
+    }
+
+    #[test]
+    fn test_canonical_moves() {
+        let moves = canonical_moves(
+            &takes(&[
+                "10-1-2-0-1/10-1-2-0-1_take2.wav",
+                "alice/10-1-2-0-1.WAV",
+                "bob/line.flac",
+                "10-1-2-0-1_take1.wav",
+            ]),
+            |_| false,
+        );
+        assert_eq!(
+            moves,
+            vec![
+                take_move("10-1-2-0-1_take1.wav", "10-1-2-0-1/10-1-2-0-1_take1.wav"),
+                take_move("alice/10-1-2-0-1.WAV", "10-1-2-0-1/10-1-2-0-1_take3.wav"),
+                take_move("bob/line.flac", "10-1-2-0-1/10-1-2-0-1_take4.flac"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonical_moves_skip_existing() {
+        let moves = canonical_moves(&takes(&["10-1-2-0-1.wav"]), |path| {
+            path == Path::new("10-1-2-0-1/10-1-2-0-1_take1.wav")
+        });
+        assert_eq!(
+            moves,
+            vec![take_move(
+                "10-1-2-0-1.wav",
+                "10-1-2-0-1/10-1-2-0-1_take2.wav"
+            )]
+        );
+        assert!(canonical_moves(&takes(&["10-1-2-0-1/10-1-2-0-1_take5.ogg"]), |_| true).is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! Rendering of a whole room's dubbed lines into one audio file for review.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use crate::{
+    cancel::CancellationToken,
+    resources::{Sample, SampleDir},
+    tools::{
+        ffmpeg::{FfmpegTool, OggVorbisOutputOptions, SequencePart},
+        speech::SpeechTool,
+    },
+};
+
+/// The pause after a spoken scene marker.
+const MARKER_GAP: Duration = Duration::from_millis(500);
+/// The pause between lines in a conversation.
+const LINE_GAP: Duration = Duration::from_millis(300);
+/// The pause between conversations.
+const CONVERSATION_GAP: Duration = Duration::from_millis(1500);
+
+/// The dubbed lines of one conversation, in the order the game plays them.
+pub(crate) struct Conversation<'a> {
+    pub(crate) noun: u8,
+    pub(crate) verb: u8,
+    pub(crate) condition: u8,
+    pub(crate) samples: Vec<&'a Sample>,
+}
+
+impl Conversation<'_> {
+    fn marker_text(&self) -> String {
+        let mut text = format!("Noun {}, verb {}", self.noun, self.verb);
+        if self.condition != 0 {
+            text.push_str(&format!(", condition {}", self.condition));
+        }
+        text
+    }
+}
+
+/// Groups the room's samples into conversations. Conversations are ordered by
+/// noun, then verb, then condition, and lines by their sequence number.
+pub(crate) fn room_conversations(sample_dir: &SampleDir, room: u16) -> Vec<Conversation<'_>> {
+    let mut conversations: BTreeMap<(u8, u8, u8), Vec<&Sample>> = BTreeMap::new();
+    for sample in sample_dir.samples().filter(|sample| sample.room == room) {
+        let id = &sample.message_id;
+        conversations
+            .entry((id.noun(), id.verb(), id.condition()))
+            .or_default()
+            .push(sample);
+    }
+    conversations
+        .into_iter()
+        .map(|((noun, verb, condition), mut samples)| {
+            samples.sort_by_key(|sample| sample.message_id.sequence());
+            Conversation {
+                noun,
+                verb,
+                condition,
+                samples,
+            }
+        })
+        .collect()
+}
+
+/// Renders every dubbed line in the room into a single Ogg Vorbis file. Each
+/// conversation is introduced by a spoken marker, or a beep if no
+/// text-to-speech program is available.
+pub async fn render_room(
+    ffmpeg: &FfmpegTool,
+    speech: Option<&SpeechTool>,
+    sample_dir: &SampleDir,
+    room: u16,
+    output_path: &Path,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let conversations = room_conversations(sample_dir, room);
+    anyhow::ensure!(
+        !conversations.is_empty(),
+        "No dubbed lines found for room {}",
+        room
+    );
+    let marker_dir = tempfile::tempdir()?;
+    let mut parts = Vec::new();
+    for (i, conversation) in conversations.iter().enumerate() {
+        if i > 0 {
+            parts.push(SequencePart::Silence(CONVERSATION_GAP));
+        }
+        match speech {
+            Some(speech) => {
+                let marker_path = marker_dir.path().join(format!("marker-{i}.wav"));
+                speech
+                    .speak_to_file(&conversation.marker_text(), &marker_path, cancel)
+                    .await?;
+                parts.push(SequencePart::Clip {
+                    path: marker_path,
+                    start_us: None,
+                    end_us: None,
+                });
+            }
+            None => parts.push(SequencePart::Beep),
+        }
+        parts.push(SequencePart::Silence(MARKER_GAP));
+        for (j, sample) in conversation.samples.iter().enumerate() {
+            if j > 0 {
+                parts.push(SequencePart::Silence(LINE_GAP));
+            }
+            parts.push(SequencePart::Clip {
+                path: sample.clip_path(sample_dir.base_path())?,
+                start_us: sample.clip.start_us,
+                end_us: sample.clip.end_us,
+            });
+        }
+    }
+    ffmpeg
+        .render_sequence(
+            &parts,
+            output_path,
+            OggVorbisOutputOptions::default(),
+            cancel,
+        )
+        .await
+}
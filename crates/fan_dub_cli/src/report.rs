@@ -0,0 +1,345 @@
+//! A report of every line processed by a build, for QA sign-off.
+//!
+//! The report is written to the output directory as JSON (`qa-report.json`)
+//! and as an HTML summary (`qa-report.html`).
+
+use std::{fmt::Write, path::Path, path::PathBuf};
+
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::{declip::ClippingRepair, gate::DownmixCheck, resources::StandIn};
+
+/// Gain adjustments larger than this (in dB) are flagged, as they usually
+/// mean the take or the original clip is unusual.
+const LARGE_GAIN_DB: f64 = 10.0;
+
+/// The loudness measurements used to match a take to the original clip.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct LoudnessMatch {
+    /// The mean volume of the original clip, in dBFS.
+    pub original_db: f64,
+    /// The mean volume of the take before adjustment, in dBFS.
+    pub take_db: f64,
+}
+
+impl LoudnessMatch {
+    pub fn gain_db(&self) -> f64 {
+        self.original_db - self.take_db
+    }
+
+    pub fn filter(&self) -> String {
+        format!("volume={:.2}dB", self.gain_db())
+    }
+}
+
+/// How well a line survived DPCM compression.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct CompressionQuality {
+    /// The signal-to-noise ratio of the compressed samples against the
+    /// uncompressed ones, in dB.
+    pub snr_db: f64,
+    /// Set if the line was stored uncompressed, because it was marked so or
+    /// compressed worse than the profile allows.
+    pub stored_uncompressed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LineReport {
+    pub room: u16,
+    pub message_id: MessageId,
+    /// The take's file, relative to the sample directory.
+    pub source: PathBuf,
+    pub start_us: Option<u64>,
+    pub end_us: Option<u64>,
+    /// The audio filters applied during conversion, followed by any custom
+    /// stages (as `stage:<name>`) and the profile's mastering.
+    pub filters: Vec<String>,
+    pub loudness: Option<LoudnessMatch>,
+    /// For compressed profiles, how well the line compressed.
+    #[serde(default)]
+    pub compression: Option<CompressionQuality>,
+    /// The size of the converted sample, in bytes.
+    pub output_size: usize,
+    /// The patch file that maps the line to its audio.
+    pub patch: String,
+    /// The line the take was packed under, if a remap rule moved it.
+    #[serde(default)]
+    pub packed_as: Option<String>,
+    /// Whether the conversion was reused from an interrupted build.
+    pub from_checkpoint: bool,
+    /// Set if the line hasn't been recorded, and other audio stands in for
+    /// it.
+    #[serde(default)]
+    pub stand_in: Option<StandIn>,
+    /// Set if the take's channels cancelled when downmixed, so only its
+    /// loudest channel was used.
+    #[serde(default)]
+    pub downmix: Option<DownmixCheck>,
+    /// Set if the take was clipped, with what repairing it did.
+    #[serde(default)]
+    pub clipping: Option<ClippingRepair>,
+    /// The duration of the converted sample, if the build measured it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    pub warnings: Vec<String>,
+}
+
+impl LineReport {
+    /// Adds warnings that follow from the line's other fields.
+    pub fn check(&mut self) {
+        if self.from_checkpoint {
+            self.warnings.push(
+                "Reused from an interrupted build; filters and loudness were not recorded"
+                    .to_string(),
+            );
+        }
+        if let Some(loudness) = &self.loudness
+            && loudness.gain_db().abs() > LARGE_GAIN_DB
+        {
+            self.warnings.push(format!(
+                "Large gain adjustment of {:.1} dB to match the original",
+                loudness.gain_db()
+            ));
+        }
+        if let Some(compression) = &self.compression
+            && compression.stored_uncompressed
+        {
+            self.warnings.push(format!(
+                "Stored uncompressed; compressing gave an SNR of {:.1} dB",
+                compression.snr_db
+            ));
+        }
+        if let Some(downmix) = &self.downmix {
+            self.warnings.push(format!(
+                "Channels cancel when downmixed ({:.1} dB lost); used the {} channel alone",
+                downmix.loss_db,
+                if downmix.loudest_channel == 0 {
+                    "left"
+                } else {
+                    "right"
+                }
+            ));
+        }
+        if let Some(clipping) = &self.clipping {
+            self.warnings.push(match clipping.clipped_after {
+                Some(clipped_after) => format!(
+                    "Declipped: {} clipped samples before, {clipped_after} after",
+                    clipping.clipped_before
+                ),
+                None => format!(
+                    "{} clipped samples; set declip.repair in fan-dub.json to repair them, or record it again",
+                    clipping.clipped_before
+                ),
+            });
+        }
+        if self.output_size == 0 {
+            self.warnings.push("Converted sample is empty".to_string());
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BuildReport {
+    /// The conversion profile used for the build.
+    pub profile: String,
+    /// The audio volume the samples were packed into.
+    pub audio_volume: String,
+    pub lines: Vec<LineReport>,
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+impl BuildReport {
+    const FILE_NAME: &str = "qa-report.json";
+
+    pub fn new(profile: &str, audio_volume: &str) -> Self {
+        BuildReport {
+            profile: profile.to_string(),
+            audio_volume: audio_volume.to_string(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn add_line(&mut self, mut line: LineReport) {
+        line.check();
+        self.lines.push(line);
+    }
+
+    pub fn num_warnings(&self) -> usize {
+        self.lines.iter().map(|line| line.warnings.len()).sum()
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Build report</title>\n<style>\n");
+        html.push_str("body { font-family: sans-serif; }\n");
+        html.push_str("table { border-collapse: collapse; }\n");
+        html.push_str("td, th { border: 1px solid #ccc; padding: 2px 6px; text-align: left; }\n");
+        html.push_str("tr.warning { background: #fff3c4; }\n");
+        html.push_str("tr.stand-in { color: #777; }\n");
+        html.push_str("</style>\n</head>\n<body>\n<h1>Build report</h1>\n");
+        writeln!(
+            html,
+            "<p>Profile: {}. {} lines packed into {}, with {} warnings.</p>",
+            escape_html(&self.profile),
+            self.lines.len(),
+            escape_html(&self.audio_volume),
+            self.num_warnings()
+        )
+        .unwrap();
+        html.push_str("<table>\n<tr><th>Room</th><th>Line</th><th>Source</th><th>Filters</th>");
+        html.push_str("<th>Gain (dB)</th><th>SNR (dB)</th><th>Size</th><th>Patch</th>");
+        html.push_str("<th>Warnings</th></tr>\n");
+        for line in &self.lines {
+            let id = &line.message_id;
+            let class = if !line.warnings.is_empty() {
+                " class=\"warning\""
+            } else if line.stand_in.is_some() {
+                " class=\"stand-in\""
+            } else {
+                ""
+            };
+            let gain = line
+                .loudness
+                .map(|loudness| format!("{:+.1}", loudness.gain_db()))
+                .unwrap_or_default();
+            let snr = line
+                .compression
+                .map(|compression| format!("{:.1}", compression.snr_db))
+                .unwrap_or_default();
+            writeln!(
+                html,
+                "<tr{class}><td>{}</td><td>{}-{}-{}-{}</td><td>{}</td><td>{}</td><td>{gain}</td><td>{snr}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                line.room,
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence(),
+                escape_html(&line.source.display().to_string())
+                    + match line.stand_in {
+                        Some(StandIn::Placeholder) => " (placeholder)",
+                        Some(StandIn::Original) => " (original)",
+                        None => "",
+                    },
+                escape_html(&line.filters.join(", ")),
+                line.output_size,
+                escape_html(&line.patch),
+                escape_html(&line.warnings.join("; ")),
+            )
+            .unwrap();
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Loads the report of the last build in the output directory, if there
+    /// was one.
+    pub fn load(output_dir: &Path) -> anyhow::Result<Option<Self>> {
+        match std::fs::read(output_dir.join(Self::FILE_NAME)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the report to the output directory, with lines in message
+    /// order.
+    pub fn write(mut self, output_dir: &Path) -> anyhow::Result<()> {
+        self.lines.sort_by_key(|line| (line.room, line.message_id));
+        std::fs::write(
+            output_dir.join(Self::FILE_NAME),
+            serde_json::to_vec_pretty(&self)?,
+        )?;
+        std::fs::write(output_dir.join("qa-report.html"), self.to_html())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(loudness: Option<LoudnessMatch>) -> LineReport {
+        LineReport {
+            room: 10,
+            message_id: MessageId::new(1, 2, 0, 1),
+            source: PathBuf::from("takes/<odd>.wav"),
+            start_us: None,
+            end_us: None,
+            filters: Vec::new(),
+            loudness,
+            compression: None,
+            output_size: 100,
+            patch: "10.map".to_string(),
+            packed_as: None,
+            from_checkpoint: false,
+            stand_in: None,
+            downmix: None,
+            clipping: None,
+            duration_ms: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_large_gain_is_flagged() {
+        let mut report = BuildReport::new("game-ogg", "resource.aud");
+        report.add_line(line(Some(LoudnessMatch {
+            original_db: -20.0,
+            take_db: -22.0,
+        })));
+        assert_eq!(report.num_warnings(), 0);
+        report.add_line(line(Some(LoudnessMatch {
+            original_db: -20.0,
+            take_db: -35.0,
+        })));
+        assert_eq!(report.num_warnings(), 1);
+    }
+
+    #[test]
+    fn test_clipping_is_flagged() {
+        let mut report = BuildReport::new("game-ogg", "resource.aud");
+        report.add_line(LineReport {
+            clipping: Some(ClippingRepair {
+                clipped_before: 40,
+                clipped_after: None,
+            }),
+            ..line(None)
+        });
+        report.add_line(LineReport {
+            clipping: Some(ClippingRepair {
+                clipped_before: 40,
+                clipped_after: Some(2),
+            }),
+            ..line(None)
+        });
+        assert!(report.lines[0].warnings[0].contains("declip.repair"));
+        assert_eq!(
+            report.lines[1].warnings,
+            vec!["Declipped: 40 clipped samples before, 2 after".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_html_escapes_paths() {
+        let mut report = BuildReport::new("game-ogg", "resource.aud");
+        report.add_line(line(None));
+        let html = report.to_html();
+        assert!(html.contains("takes/&lt;odd&gt;.wav"));
+        assert!(!html.contains("<odd>"));
+    }
+}
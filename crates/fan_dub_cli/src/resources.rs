@@ -1,16 +1,42 @@
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
 
-use futures::StreamExt;
-use sci_resources::types::{
-    audio36::{Audio36ResourceBuilder, AudioFormat, VoiceSample, VoiceSampleResources},
-    msg::MessageId,
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::{ResourceSet, open_game_resources},
+    types::{
+        audio36::{
+            AUDIO_INDEX_MAP_NUM, Audio36Map, Audio36ResourceBuilder, SolFormat, VoiceSample,
+            VoiceSampleResources, read_sol_clip,
+        },
+        msg::{MessageId, parse_message_resource},
+    },
+};
+use sci_utils::{
+    block::{MemBlock, temp_store::TempStore},
+    checkpoint::Checkpoint,
 };
-use sci_utils::block::temp_store::TempStore;
 use serde::{Deserialize, Serialize};
 
-use crate::tools::ffmpeg::{self, FfmpegTool};
+use crate::{
+    cancel::CancellationToken,
+    cleanup::CleanupPresets,
+    declip::{ClippingRepair, DECLIP_FILTER, DeclipConfig, check_clipping},
+    effects::EffectPresets,
+    gate::{DownmixCheck, check_downmix},
+    overrides::{LineOverride, LineOverrides},
+    profile::ConversionProfile,
+    remap::TupleRemap,
+    report::{BuildReport, CompressionQuality, LineReport, LoudnessMatch},
+    scheduler::{BatchFailed, BatchScheduler},
+    stage::{Stage, StageInput},
+    tools::ffmpeg::{self, FfmpegTool},
+};
 
-fn normalize_path(path: &Path) -> PathBuf {
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
     let mut result_buf = PathBuf::new();
     for component in path.components() {
         match component {
@@ -46,18 +72,423 @@ fn normalize_path(path: &Path) -> PathBuf {
     result_buf
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AudioClip {
     pub start_us: Option<u64>,
     pub end_us: Option<u64>,
     pub path: PathBuf,
 }
 
+impl AudioClip {
+    /// The audio filter that trims the file to the clip's time range, if it
+    /// has one.
+    pub fn trim_filter(&self) -> Option<String> {
+        fn seconds(us: u64) -> String {
+            format!("{}.{:06}", us / 1_000_000, us % 1_000_000)
+        }
+        let mut bounds = Vec::new();
+        if let Some(start_us) = self.start_us {
+            bounds.push(format!("start={}", seconds(start_us)));
+        }
+        if let Some(end_us) = self.end_us {
+            bounds.push(format!("end={}", seconds(end_us)));
+        }
+        (!bounds.is_empty()).then(|| format!("atrim={},asetpts=PTS-STARTPTS", bounds.join(":")))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Sample {
     pub room: u16,
     pub message_id: MessageId,
     pub clip: AudioClip,
+    /// Adjust the clip's volume to match the game's original recording of
+    /// the line, so it sits at the same level relative to music and effects.
+    #[serde(default)]
+    pub match_original_loudness: bool,
+    /// The character speaking the line, used to pick its cleanup presets.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Cleanup presets for this line, replacing those of its role.
+    #[serde(default)]
+    pub cleanup: Vec<String>,
+    /// Store the line uncompressed, even if the profile compresses.
+    #[serde(default)]
+    pub store_uncompressed: bool,
+    /// Set if the audio stands in for a line that hasn't been recorded.
+    /// Stand-ins are only added for a build, and never saved to
+    /// `samples.json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stand_in: Option<StandIn>,
+    /// Set once the line's take is approved and mastered. Importers won't add
+    /// takes for a locked line or change its selection unless forced.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+}
+
+/// Audio packed for a line that hasn't been recorded yet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StandIn {
+    /// Generated speech.
+    Placeholder,
+    /// The game's original recording, repacked in the build's format.
+    Original,
+}
+
+impl Sample {
+    /// The path to the sample's audio file, under the sample directory.
+    pub fn clip_path(&self, base_path: &Path) -> anyhow::Result<PathBuf> {
+        let clip_path = normalize_path(&self.clip.path);
+        anyhow::ensure!(
+            clip_path.is_relative(),
+            "A path for an audio clip must be relative to the root directory."
+        );
+        Ok(base_path.join(clip_path))
+    }
+
+    /// A key that uniquely identifies this sample in a build.
+    pub fn key(&self) -> String {
+        sample_key(self.room, &self.message_id)
+    }
+}
+
+/// A key that uniquely identifies a line in a build.
+pub fn sample_key(room: u16, message_id: &MessageId) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        room,
+        message_id.noun(),
+        message_id.verb(),
+        message_id.condition(),
+        message_id.sequence()
+    )
+}
+
+const TAKE_EXTENSIONS: &[&str] = &["wav", "flac", "ogg", "mp3", "aif", "aiff", "m4a"];
+
+/// Whether files with the extension are recognized as takes.
+pub fn is_take_extension(extension: &str) -> bool {
+    TAKE_EXTENSIONS
+        .iter()
+        .any(|take_ext| extension.eq_ignore_ascii_case(take_ext))
+}
+
+/// Whether the file is an audio file named for the line with the key.
+fn is_take_of(path: &Path, key: &str) -> bool {
+    let is_audio = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(is_take_extension);
+    let Some(rest) = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.strip_prefix(key))
+    else {
+        return false;
+    };
+    // Don't let line 1 pick up the takes of line 12.
+    is_audio && !rest.starts_with(|ch: char| ch.is_ascii_digit())
+}
+
+/// Parses the line key that the text starts with, returning the rest of the
+/// text after it.
+fn parse_key_prefix(text: &str) -> Option<(u16, MessageId, &str)> {
+    let mut fields = [0u16; 5];
+    let mut rest = text;
+    for (i, field) in fields.iter_mut().enumerate() {
+        if i > 0 {
+            rest = rest.strip_prefix('-')?;
+        }
+        let digits = rest.len()
+            - rest
+                .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                .len();
+        *field = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+    }
+    let [room, noun, verb, condition, sequence] = fields;
+    let message_id = MessageId::new(
+        noun.try_into().ok()?,
+        verb.try_into().ok()?,
+        condition.try_into().ok()?,
+        sequence.try_into().ok()?,
+    );
+    Some((room, message_id, rest))
+}
+
+/// Parses a line's key (e.g. `10-1-2-0-1`): the inverse of [`sample_key`].
+pub fn parse_sample_key(key: &str) -> Option<(u16, MessageId)> {
+    match parse_key_prefix(key)? {
+        (room, message_id, "") if sample_key(room, &message_id) == key => Some((room, message_id)),
+        _ => None,
+    }
+}
+
+/// The line an audio file is a take of, by its name: the inverse of
+/// [`is_take_of`].
+pub fn take_line(path: &Path) -> Option<(u16, MessageId)> {
+    let is_audio = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(is_take_extension);
+    if !is_audio {
+        return None;
+    }
+    let (room, message_id, _) = parse_key_prefix(path.file_stem()?.to_str()?)?;
+    // Names with leading zeros aren't found by the line's key.
+    is_take_of(path, &sample_key(room, &message_id)).then_some((room, message_id))
+}
+
+/// A line of the game, with its text.
+#[derive(Debug, Clone)]
+pub struct GameLine {
+    pub room: u16,
+    pub message_id: MessageId,
+    pub text: String,
+}
+
+/// Reads every line from the game's message resources.
+pub fn game_lines(game_dir: &Path) -> anyhow::Result<Vec<GameLine>> {
+    let resources = open_game_resources(game_dir)?;
+    let mut lines = Vec::new();
+    for res in resources.resources_of_type(ResourceType::Message) {
+        let messages = parse_message_resource(res.load_data()?)?;
+        for (message_id, record) in messages.messages() {
+            lines.push(GameLine {
+                room: res.id().resource_num(),
+                message_id: *message_id,
+                text: record.text().to_string(),
+            });
+        }
+    }
+    lines.sort_by_key(|line| (line.room, line.message_id));
+    Ok(lines)
+}
+
+/// The format used by most of a game's original clips.
+#[derive(Debug, Clone, Copy)]
+pub struct DetectedFormat {
+    pub format: SolFormat,
+    /// How many clips use the format.
+    pub clips: usize,
+    /// How many clips were read.
+    pub total: usize,
+}
+
+/// The original speech audio of a game, used as a reference when processing
+/// dubbed lines.
+pub struct OriginalAudio {
+    resources: ResourceSet,
+    volume: MemBlock,
+}
+
+impl OriginalAudio {
+    /// Opens the original audio in the game directory.
+    pub fn open(game_dir: &Path) -> anyhow::Result<Self> {
+        let resources = open_game_resources(game_dir)?;
+        let volume = MemBlock::from_reader(std::fs::File::open(game_dir.join("RESOURCE.AUD"))?)?;
+        Ok(Self { resources, volume })
+    }
+
+    /// Returns the original clip for a line as a SOL file, if the line has
+    /// one.
+    pub fn clip(&self, room: u16, message_id: &MessageId) -> anyhow::Result<Option<MemBlock>> {
+        let Some(map) = self.room_map(room)? else {
+            return Ok(None);
+        };
+        map.offset(message_id)
+            .map(|offset| read_sol_clip(&self.volume, offset))
+            .transpose()
+    }
+
+    /// Returns the rooms with original speech.
+    pub fn rooms(&self) -> Vec<u16> {
+        self.resources
+            .resource_ids()
+            .filter(|id| {
+                id.type_id() == ResourceType::Map && id.resource_num() != AUDIO_INDEX_MAP_NUM
+            })
+            .map(|id| id.resource_num())
+            .collect()
+    }
+
+    /// Returns the lines in a room that have an original clip.
+    pub fn room_lines(&self, room: u16) -> anyhow::Result<Vec<MessageId>> {
+        let Some(map) = self.room_map(room)? else {
+            return Ok(Vec::new());
+        };
+        Ok(map.entries().map(|(id, _)| *id).collect())
+    }
+
+    /// Returns all original clips in a room, as SOL files.
+    pub fn room_clips(&self, room: u16) -> anyhow::Result<Vec<(MessageId, MemBlock)>> {
+        let Some(map) = self.room_map(room)? else {
+            return Ok(Vec::new());
+        };
+        map.entries()
+            .map(|(id, offset)| Ok((*id, read_sol_clip(&self.volume, offset)?)))
+            .collect()
+    }
+
+    /// Finds the format most of the original clips use, by reading every
+    /// clip's header. Returns `None` if there are no SOL clips (e.g. if the
+    /// audio volume has already been replaced by a compressed one).
+    pub fn detect_format(&self) -> anyhow::Result<Option<DetectedFormat>> {
+        let mut counts: BTreeMap<SolFormat, usize> = BTreeMap::new();
+        for room in self.rooms() {
+            let Some(map) = self.room_map(room)? else {
+                continue;
+            };
+            for (_, offset) in map.entries() {
+                if let Ok(format) =
+                    read_sol_clip(&self.volume, offset).and_then(|clip| SolFormat::from_clip(&clip))
+                {
+                    *counts.entry(format).or_default() += 1;
+                }
+            }
+        }
+        let total = counts.values().sum();
+        Ok(counts
+            .into_iter()
+            .max_by_key(|(_, clips)| *clips)
+            .map(|(format, clips)| DetectedFormat {
+                format,
+                clips,
+                total,
+            }))
+    }
+
+    fn room_map(&self, room: u16) -> anyhow::Result<Option<Audio36Map>> {
+        let Some(map) = self
+            .resources
+            .get_resource(&ResourceId::new(ResourceType::Map, room))
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Audio36Map::from_block(&map.load_data()?)?))
+    }
+}
+
+/// Measures the loudness of the sample and of the original clip, if the sample
+/// asks to be matched to the original.
+async fn match_loudness(
+    sample: &Sample,
+    base_path: &Path,
+    original: Option<&OriginalAudio>,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Option<LoudnessMatch>> {
+    if !sample.match_original_loudness {
+        return Ok(None);
+    }
+    let original = original.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Sample {} matches the original loudness, but no game directory was given",
+            sample.key()
+        )
+    })?;
+    let original_clip = original
+        .clip(sample.room, &sample.message_id)?
+        .ok_or_else(|| anyhow::anyhow!("No original clip found for sample {}", sample.key()))?;
+    let original_db = ffmpeg
+        .measure_loudness(ffmpeg::BytesInput::new(original_clip), cancel)
+        .await?;
+    let take_db = ffmpeg
+        .measure_loudness(sample.clip_path(base_path)?, cancel)
+        .await?;
+    Ok(Some(LoudnessMatch {
+        original_db,
+        take_db,
+    }))
+}
+
+/// Checkpoint state for an audio build, so an interrupted build can be
+/// resumed without redoing conversions that already finished.
+///
+/// Converted samples are kept in a directory next to the journal until the
+/// build completes.
+pub struct BuildCheckpoint {
+    journal: Checkpoint,
+    store_dir: PathBuf,
+}
+
+impl BuildCheckpoint {
+    const DIR_NAME: &str = ".build-checkpoint";
+
+    /// Opens the checkpoint for a build writing to the given output directory.
+    pub fn open(output_dir: &Path, resume: bool) -> anyhow::Result<Self> {
+        let store_dir = output_dir.join(Self::DIR_NAME);
+        if !resume && store_dir.exists() {
+            std::fs::remove_dir_all(&store_dir)?;
+        }
+        std::fs::create_dir_all(&store_dir)?;
+        let journal = Checkpoint::open(&store_dir.join("journal"), resume)?;
+        Ok(Self { journal, store_dir })
+    }
+
+    /// Returns the number of samples that were completed in earlier runs.
+    pub fn num_complete(&self) -> usize {
+        self.journal.num_complete()
+    }
+
+    fn resume_state(&self) -> ResumeState {
+        ResumeState {
+            completed: self.journal.completed().clone(),
+            store_dir: self.store_dir.clone(),
+        }
+    }
+
+    async fn save(&mut self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        smol::fs::write(self.store_dir.join(key), data).await?;
+        self.journal.mark_complete(key)?;
+        Ok(())
+    }
+
+    /// Marks the build as finished, removing all checkpoint state.
+    pub fn finish(self) -> anyhow::Result<()> {
+        self.journal.finish()?;
+        std::fs::remove_dir_all(&self.store_dir)?;
+        Ok(())
+    }
+}
+
+struct ResumeState {
+    completed: BTreeSet<String>,
+    store_dir: PathBuf,
+}
+
+impl ResumeState {
+    async fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        if !self.completed.contains(key) {
+            return Ok(None);
+        }
+        Ok(Some(smol::fs::read(self.store_dir.join(key)).await?))
+    }
+}
+
+/// How samples are processed in a build.
+pub struct BuildOptions<'a> {
+    pub profile: &'a ConversionProfile,
+    /// The original game audio, for samples that match its loudness.
+    pub original: Option<&'a OriginalAudio>,
+    /// Custom stages to run on each sample before it is packed.
+    pub stages: &'a [Box<dyn Stage>],
+    /// Cleanup filters, applied to each take after trimming.
+    pub cleanup: &'a CleanupPresets,
+    /// Effects, applied to each take after cleanup.
+    pub effects: &'a EffectPresets,
+    /// Changes to the processing of particular lines.
+    pub overrides: &'a LineOverrides,
+    /// With a mono profile, stereo takes that lose more than this (in dB)
+    /// when downmixed are made from their loudest channel instead.
+    pub max_downmix_loss_db: Option<f64>,
+    /// Whether clipped takes are repaired, or only flagged.
+    pub declip: &'a DeclipConfig,
+    /// Rules moving takes to other lines, for another version of the game.
+    pub remap: &'a TupleRemap,
+    /// Measure the duration of each converted sample, for the report.
+    pub measure_durations: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -68,45 +499,289 @@ impl SampleSet {
         &self,
         base_path: &Path,
         ffmpeg: &FfmpegTool,
-        num_concurrent: usize,
+        options: &BuildOptions<'_>,
+        scheduler: &BatchScheduler,
+        mut checkpoint: Option<&mut BuildCheckpoint>,
+        mut report: Option<&mut BuildReport>,
     ) -> anyhow::Result<VoiceSampleResources> {
-        struct ProcessedSample {
+        struct ProcessedSample<'a> {
             room: u16,
             message_id: MessageId,
+            clip: Cow<'a, AudioClip>,
+            key: String,
             data: Vec<u8>,
+            from_checkpoint: bool,
+            stand_in: Option<StandIn>,
+            filters: Vec<String>,
+            loudness: Option<LoudnessMatch>,
+            compression: Option<CompressionQuality>,
+            downmix: Option<DownmixCheck>,
+            clipping: Option<ClippingRepair>,
+            packed_as: Option<(u16, MessageId)>,
+            duration: Option<std::time::Duration>,
         }
+        let BuildOptions {
+            profile,
+            original,
+            stages,
+            cleanup,
+            effects,
+            overrides,
+            max_downmix_loss_db,
+            declip,
+            remap,
+            measure_durations,
+        } = *options;
         let mut builder = Audio36ResourceBuilder::new();
-        let conversion_ops = self.0.iter().map(|sample| async {
-            let clip_path = normalize_path(&sample.clip.path);
-            anyhow::ensure!(
-                clip_path.is_relative(),
-                "A path for an audio clip must be relative to the root directory."
-            );
-            let sample_file = smol::fs::File::open(base_path.join(clip_path)).await?;
-            let result = ffmpeg
-                .convert(
-                    ffmpeg::ReaderInput::new(sample_file),
-                    ffmpeg::VecOutput,
-                    ffmpeg::OutputFormat::Ogg(Default::default()),
-                    &mut ffmpeg::NullProgressListener,
-                )
-                .await?;
-            Ok::<_, anyhow::Error>(ProcessedSample {
-                room: sample.room,
-                message_id: sample.message_id,
-                data: result,
-            })
+        let resume_state = checkpoint.as_ref().map(|c| c.resume_state());
+        let resume_state = &resume_state;
+        let cancel = scheduler.cancellation();
+        let measure = async |data: &[u8]| {
+            if measure_durations {
+                let input = ffmpeg::BytesInput::new(data.to_vec());
+                ffmpeg.measure_duration(input, cancel).await.map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let conversion_ops = self.0.iter().map(|sample| {
+            let job = move || async move {
+                let key = sample.key();
+                if let Some(state) = resume_state
+                    && let Some(data) = state.load(&key).await?
+                {
+                    let duration = measure(&data).await?;
+                    return Ok(ProcessedSample {
+                        room: sample.room,
+                        message_id: sample.message_id,
+                        clip: Cow::Borrowed(&sample.clip),
+                        key,
+                        data,
+                        from_checkpoint: true,
+                        stand_in: sample.stand_in,
+                        filters: Vec::new(),
+                        loudness: None,
+                        compression: None,
+                        downmix: None,
+                        clipping: None,
+                        packed_as: remap.target(sample),
+                        duration,
+                    });
+                }
+                let line_override = overrides.for_sample(sample);
+                let clip = line_override
+                    .map_or(Cow::Borrowed(&sample.clip), |line| line.clip(&sample.clip));
+                let loudness = if line_override.is_none_or(LineOverride::normalizes) {
+                    match_loudness(sample, base_path, original, ffmpeg, cancel).await?
+                } else {
+                    None
+                };
+                let downmix = match max_downmix_loss_db {
+                    Some(max_loss_db) if profile.mono && sample.stand_in.is_none() => {
+                        let pcm = ffmpeg
+                            .decode_stereo(
+                                sample.clip_path(base_path)?,
+                                clip.trim_filter().as_deref(),
+                                cancel,
+                            )
+                            .await?;
+                        check_downmix(&pcm, 2).filter(|downmix| downmix.loss_db > max_loss_db)
+                    }
+                    _ => None,
+                };
+                let clipping = if sample.stand_in.is_none() {
+                    check_clipping(
+                        declip,
+                        ffmpeg,
+                        &sample.clip_path(base_path)?,
+                        clip.trim_filter().as_deref(),
+                        cancel,
+                    )
+                    .await?
+                } else {
+                    None
+                };
+                // Original recordings are repacked as they are, only
+                // converted to the build's format.
+                let repack = sample.stand_in == Some(StandIn::Original);
+                let stages = if repack || line_override.is_some_and(|line| line.skip_stages) {
+                    &[]
+                } else {
+                    stages
+                };
+                // Mastering is the last step, after any stages.
+                let mastering = if repack {
+                    None
+                } else {
+                    profile.mastering_filter()
+                };
+                let filters = [
+                    clip.trim_filter(),
+                    clipping
+                        .filter(ClippingRepair::repaired)
+                        .map(|_| DECLIP_FILTER.to_string()),
+                    downmix.as_ref().map(DownmixCheck::safe_downmix_filter),
+                    cleanup.filter_for(sample)?,
+                    effects.filter_for(sample)?,
+                    loudness.as_ref().map(LoudnessMatch::filter),
+                    line_override.and_then(LineOverride::gain_filter),
+                    profile.audio_filter(),
+                ];
+                let mut filters: Vec<String> = filters.into_iter().flatten().collect();
+                if stages.is_empty() {
+                    filters.extend(mastering.clone());
+                }
+                let audio_filter = filters.join(",");
+                // With custom stages, convert to WAV first, so the stages get
+                // audio they can process, and encode afterwards.
+                let conversion_format = if stages.is_empty() {
+                    profile.output_format()
+                } else {
+                    ffmpeg::OutputFormat::Wav
+                };
+                let audio_filter = (!audio_filter.is_empty()).then_some(audio_filter.as_str());
+                let mut result = if repack {
+                    let clip = original
+                        .ok_or_else(|| anyhow::anyhow!("No game directory was given"))?
+                        .clip(sample.room, &sample.message_id)?
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("No original clip found for sample {key}")
+                        })?;
+                    ffmpeg
+                        .convert_with_filter(
+                            ffmpeg::BytesInput::new(clip),
+                            ffmpeg::VecOutput,
+                            conversion_format,
+                            audio_filter,
+                            &mut ffmpeg::NullProgressListener,
+                            cancel,
+                        )
+                        .await?
+                } else {
+                    let sample_file = smol::fs::File::open(sample.clip_path(base_path)?).await?;
+                    ffmpeg
+                        .convert_with_filter(
+                            ffmpeg::ReaderInput::new(sample_file),
+                            ffmpeg::VecOutput,
+                            conversion_format,
+                            audio_filter,
+                            &mut ffmpeg::NullProgressListener,
+                            cancel,
+                        )
+                        .await?
+                };
+                if !stages.is_empty() {
+                    let stage_input = StageInput {
+                        room: sample.room,
+                        message_id: sample.message_id,
+                        key: &key,
+                    };
+                    for stage in stages {
+                        result = stage.process(&stage_input, result, cancel).await?;
+                        filters.push(format!("stage:{}", stage.name()));
+                    }
+                    result = ffmpeg
+                        .convert_with_filter(
+                            ffmpeg::BytesInput::new(result),
+                            ffmpeg::VecOutput,
+                            profile.output_format(),
+                            mastering.as_deref(),
+                            &mut ffmpeg::NullProgressListener,
+                            cancel,
+                        )
+                        .await?;
+                    filters.extend(mastering);
+                }
+                let finished = profile.finish(result, sample.store_uncompressed)?;
+                let duration = measure(&finished.data).await?;
+                Ok::<_, anyhow::Error>(ProcessedSample {
+                    room: sample.room,
+                    message_id: sample.message_id,
+                    clip,
+                    key,
+                    data: finished.data,
+                    from_checkpoint: false,
+                    stand_in: sample.stand_in,
+                    filters,
+                    loudness,
+                    compression: finished.compression,
+                    downmix,
+                    clipping,
+                    packed_as: remap.target(sample),
+                    duration,
+                })
+            };
+            (sample.key(), job)
         });
 
-        let mut conversion_stream =
-            futures::stream::iter(conversion_ops).buffer_unordered(num_concurrent);
         let mut temp_store = TempStore::new()?;
-        while let Some(result) = conversion_stream.next().await {
-            let sample = result?;
-            // Only VecDeque implements Buffer.
-            let sample_source = temp_store.store_bytes(&sample.data[..]).await?;
-            let voice_sample = VoiceSample::new(AudioFormat::Ogg, sample_source);
-            builder.add_entry(sample.room, sample.message_id, voice_sample)?;
+        let mut voice_samples = Vec::new();
+        let batch_report = scheduler
+            .run(conversion_ops, async |sample: ProcessedSample| {
+                // Save each conversion as soon as it completes, so a cancelled
+                // build keeps everything finished so far.
+                if let Some(checkpoint) = checkpoint.as_deref_mut()
+                    && !sample.from_checkpoint
+                {
+                    checkpoint.save(&sample.key, &sample.data).await?;
+                }
+                let (room, message_id) =
+                    sample.packed_as.unwrap_or((sample.room, sample.message_id));
+                if let Some(report) = report.as_deref_mut() {
+                    report.add_line(LineReport {
+                        room: sample.room,
+                        message_id: sample.message_id,
+                        source: sample.clip.path.clone(),
+                        start_us: sample.clip.start_us,
+                        end_us: sample.clip.end_us,
+                        filters: sample.filters.clone(),
+                        loudness: sample.loudness,
+                        compression: sample.compression,
+                        output_size: sample.data.len(),
+                        patch: format!("{}.{}", room, ResourceType::Map.to_file_ext()),
+                        packed_as: sample
+                            .packed_as
+                            .map(|(room, message_id)| sample_key(room, &message_id)),
+                        from_checkpoint: sample.from_checkpoint,
+                        stand_in: sample.stand_in,
+                        downmix: sample.downmix,
+                        clipping: sample.clipping,
+                        duration_ms: sample.duration.map(|duration| duration.as_millis() as u64),
+                        warnings: Vec::new(),
+                    });
+                }
+                // Only VecDeque implements Buffer.
+                let sample_source = temp_store.store_bytes(&sample.data[..]).await?;
+                let voice_sample = VoiceSample::new(profile.audio_format(), sample_source);
+                voice_samples.push((room, message_id, sample.stand_in, sample.key, voice_sample));
+                Ok(())
+            })
+            .await?;
+        if !batch_report.is_success() {
+            return Err(BatchFailed(batch_report).into());
+        }
+        // Conversions finish in any order; pack them in message order so the
+        // same samples always give the same volume.
+        // Recorded takes sort before stand-ins of the same line.
+        voice_samples.sort_by_key(|(room, message_id, stand_in, ..)| {
+            (*room, *message_id, stand_in.is_some())
+        });
+        let mut packed: Option<(u16, MessageId, String)> = None;
+        for (room, message_id, stand_in, key, voice_sample) in voice_samples {
+            if let Some((packed_room, packed_id, packed_key)) = &packed
+                && (*packed_room, *packed_id) == (room, message_id)
+            {
+                // Only remapping packs two samples under one line. A take
+                // moved onto a line replaces its stand-in.
+                anyhow::ensure!(
+                    stand_in.is_some(),
+                    "Samples {packed_key} and {key} are both packed as {}; check the remap rules",
+                    sample_key(room, &message_id)
+                );
+                continue;
+            }
+            builder.add_entry(room, message_id, voice_sample)?;
+            packed = Some((room, message_id, key));
         }
         builder.build()
     }
@@ -129,13 +804,237 @@ impl SampleDir {
         })
     }
 
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> {
+        self.samples.0.iter()
+    }
+
+    /// Returns the sample for a line, if it has one.
+    pub fn sample(&self, room: u16, message_id: &MessageId) -> Option<&Sample> {
+        self.samples
+            .0
+            .iter()
+            .find(|sample| sample.room == room && sample.message_id == *message_id)
+    }
+
+    /// Finds the recorded takes of a line: audio files anywhere under the
+    /// sample directory whose names start with the line's key (e.g.
+    /// `10-1-2-0-1.wav` or `10-1-2-0-1_take2.flac`). Paths are relative to
+    /// the sample directory.
+    pub fn find_takes(&self, room: u16, message_id: &MessageId) -> anyhow::Result<Vec<PathBuf>> {
+        let key = sample_key(room, message_id);
+        let mut takes = Vec::new();
+        let mut dirs = vec![self.base_path.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    // Hidden directories hold generated files, not takes.
+                    if !entry.file_name().to_string_lossy().starts_with('.') {
+                        dirs.push(path);
+                    }
+                } else if is_take_of(&path, &key) {
+                    takes.push(path.strip_prefix(&self.base_path)?.to_path_buf());
+                }
+            }
+        }
+        takes.sort();
+        Ok(takes)
+    }
+
+    /// Picks an unused path for a new take of a line, in the directory
+    /// (relative to the sample directory), named so that
+    /// [`SampleDir::find_takes`] finds it (e.g. `10-1-2-0-1_take3.wav`).
+    pub fn new_take_path(
+        &self,
+        dir: &Path,
+        room: u16,
+        message_id: &MessageId,
+        extension: &str,
+    ) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(
+            is_take_extension(extension),
+            "Unsupported audio file type: {extension}"
+        );
+        let key = sample_key(room, message_id);
+        let first = self.find_takes(room, message_id)?.len() + 1;
+        (first..)
+            .map(|n| dir.join(format!("{key}_take{n}.{extension}")))
+            .find(|path| !self.base_path.join(path).exists())
+            .ok_or_else(|| anyhow::anyhow!("No free take name for {key}"))
+    }
+
+    /// Uses the take (relative to the sample directory) for a line, adding
+    /// a sample for the line if it doesn't have one yet. Any trim points of
+    /// the previous take are dropped.
+    pub fn select_take(&mut self, room: u16, message_id: MessageId, path: PathBuf) {
+        let clip = AudioClip {
+            start_us: None,
+            end_us: None,
+            path,
+        };
+        match self
+            .samples
+            .0
+            .iter_mut()
+            .find(|sample| sample.room == room && sample.message_id == message_id)
+        {
+            Some(sample) => sample.clip = clip,
+            None => self.samples.0.push(Sample {
+                room,
+                message_id,
+                clip,
+                match_original_loudness: false,
+                role: None,
+                cleanup: Vec::new(),
+                store_uncompressed: false,
+                stand_in: None,
+                locked: false,
+            }),
+        }
+    }
+
+    /// Removes the line's sample, leaving it unrecorded, and returns it.
+    pub fn remove_sample(&mut self, room: u16, message_id: &MessageId) -> Option<Sample> {
+        let index = self
+            .samples
+            .0
+            .iter()
+            .position(|sample| sample.room == room && sample.message_id == *message_id)?;
+        Some(self.samples.0.remove(index))
+    }
+
+    /// Whether the line's take is locked.
+    pub fn is_locked(&self, room: u16, message_id: &MessageId) -> bool {
+        self.sample(room, message_id)
+            .is_some_and(|sample| sample.locked)
+    }
+
+    /// Fails if the line's take is locked.
+    pub fn ensure_unlocked(&self, room: u16, message_id: &MessageId) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.is_locked(room, message_id),
+            "Line {} is locked",
+            sample_key(room, message_id)
+        );
+        Ok(())
+    }
+
+    /// Locks or unlocks the line's take. Only lines with a selected take can
+    /// be locked.
+    pub fn set_locked(
+        &mut self,
+        room: u16,
+        message_id: &MessageId,
+        locked: bool,
+    ) -> anyhow::Result<()> {
+        let sample = self
+            .samples
+            .0
+            .iter_mut()
+            .find(|sample| sample.room == room && sample.message_id == *message_id)
+            .ok_or_else(|| anyhow::anyhow!("Line {} has no take", sample_key(room, message_id)))?;
+        sample.locked = locked;
+        Ok(())
+    }
+
+    /// Points the samples using the take at `from` to `to` instead, keeping
+    /// their trim points. Paths are relative to the sample directory.
+    pub fn move_take(&mut self, from: &Path, to: &Path) {
+        for sample in &mut self.samples.0 {
+            if normalize_path(&sample.clip.path) == normalize_path(from) {
+                sample.clip.path = to.to_path_buf();
+            }
+        }
+    }
+
+    /// Adds samples standing in for unrecorded lines, for the current build
+    /// only.
+    pub fn add_stand_ins(&mut self, samples: impl IntoIterator<Item = Sample>) {
+        self.samples.0.extend(samples.into_iter().inspect(|sample| {
+            debug_assert!(sample.stand_in.is_some());
+        }));
+    }
+
+    /// The samples of recorded takes, leaving out stand-ins.
+    pub fn takes(&self) -> impl Iterator<Item = &Sample> {
+        self.samples().filter(|sample| sample.stand_in.is_none())
+    }
+
+    /// Writes the samples back to `samples.json`.
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let recorded = self
+            .samples
+            .0
+            .iter()
+            .filter(|sample| sample.stand_in.is_none())
+            .collect::<Vec<_>>();
+        smol::fs::write(
+            self.base_path.join("samples.json"),
+            serde_json::to_vec_pretty(&recorded)?,
+        )
+        .await?;
+        Ok(())
+    }
+
     pub async fn to_audio_resources(
         &self,
         ffmpeg: &FfmpegTool,
-        num_concurrent: usize,
+        options: &BuildOptions<'_>,
+        scheduler: &BatchScheduler,
+        checkpoint: Option<&mut BuildCheckpoint>,
+        report: Option<&mut BuildReport>,
     ) -> anyhow::Result<VoiceSampleResources> {
         self.samples
-            .to_audio_resources(&self.base_path, ffmpeg, num_concurrent)
+            .to_audio_resources(
+                &self.base_path,
+                ffmpeg,
+                options,
+                scheduler,
+                checkpoint,
+                report,
+            )
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_take_of() {
+        assert!(is_take_of(Path::new("takes/10-1-2-0-1.wav"), "10-1-2-0-1"));
+        assert!(is_take_of(Path::new("10-1-2-0-1_take2.FLAC"), "10-1-2-0-1"));
+        assert!(!is_take_of(Path::new("10-1-2-0-12.wav"), "10-1-2-0-1"));
+        assert!(!is_take_of(Path::new("10-1-2-0-1.txt"), "10-1-2-0-1"));
+        assert!(!is_take_of(Path::new("110-1-2-0-1.wav"), "10-1-2-0-1"));
+    }
+
+    #[test]
+    fn test_take_line() {
+        let line = Some((10, MessageId::new(1, 2, 0, 1)));
+        assert_eq!(take_line(Path::new("takes/10-1-2-0-1.wav")), line);
+        assert_eq!(take_line(Path::new("10-1-2-0-1_take2.FLAC")), line);
+        assert_eq!(take_line(Path::new("10-1-2-0-1-retake.wav")), line);
+        assert_eq!(take_line(Path::new("10-1-2-0-1.txt")), None);
+        assert_eq!(take_line(Path::new("010-1-2-0-1.wav")), None);
+        assert_eq!(take_line(Path::new("10-1-2-0.wav")), None);
+        assert_eq!(take_line(Path::new("10-1-2-0-300.wav")), None);
+    }
+
+    #[test]
+    fn test_parse_sample_key() {
+        let message_id = MessageId::new(1, 2, 0, 1);
+        assert_eq!(
+            parse_sample_key(&sample_key(10, &message_id)),
+            Some((10, message_id))
+        );
+        assert_eq!(parse_sample_key("10-1-2-0-1_take2"), None);
+        assert_eq!(parse_sample_key("10-1-2-0-01"), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Scheduling of batches of independent jobs, such as audio conversions.
+
+use std::{future::Future, time::Duration};
+
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::cancel::{CancellationToken, Cancelled};
+
+/// How failed jobs are retried.
+///
+/// External tools can fail transiently (e.g. an output file locked by another
+/// process, or running out of memory while many conversions run at once), so
+/// a failed job is retried with exponential backoff before it is reported as
+/// a failure.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of times a failed job is retried. Zero disables retries.
+    pub max_retries: u32,
+    /// The delay before the first retry. Each later retry doubles the delay.
+    pub initial_backoff: Duration,
+    /// The upper bound on the delay between retries.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    /// The delay before the given retry (starting at 1).
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A job that still failed after all retries.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFailure {
+    pub key: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// The outcome of a batch run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchReport {
+    pub succeeded: usize,
+    pub failures: Vec<JobFailure>,
+}
+
+impl BatchReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The error returned when some jobs in a batch failed.
+#[derive(Debug, thiserror::Error)]
+#[error("{} of {} jobs failed", .0.failures.len(), .0.failures.len() + .0.succeeded)]
+pub struct BatchFailed(pub BatchReport);
+
+pub struct BatchScheduler {
+    num_concurrent: usize,
+    retry: RetryPolicy,
+    cancel: CancellationToken,
+}
+
+impl BatchScheduler {
+    pub fn new(num_concurrent: usize, cancel: CancellationToken) -> Self {
+        assert!(num_concurrent > 0);
+        BatchScheduler {
+            num_concurrent,
+            retry: RetryPolicy::none(),
+            cancel,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Runs all jobs, with at most `num_concurrent` running at a time.
+    ///
+    /// Each job is identified by a key, and is a function that starts a new
+    /// attempt each time it is called. Failed attempts are retried according
+    /// to the retry policy; jobs that fail every attempt are recorded in the
+    /// returned report, and the rest of the batch continues.
+    ///
+    /// Results are passed to `on_complete` in completion order. An error from
+    /// `on_complete` stops the batch. If cancellation is requested, no new jobs
+    /// are started, in-flight jobs are dropped, and [`Cancelled`] is returned;
+    /// results already passed to `on_complete` are unaffected, so callers can
+    /// persist progress there.
+    pub async fn run<J, Fut, T, F>(
+        &self,
+        jobs: impl IntoIterator<Item = (String, J)>,
+        mut on_complete: F,
+    ) -> anyhow::Result<BatchReport>
+    where
+        J: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+        F: AsyncFnMut(T) -> anyhow::Result<()>,
+    {
+        let jobs = jobs
+            .into_iter()
+            .map(|(key, job)| async move { (key, self.run_with_retry(&job).await) });
+        let mut results = futures::stream::iter(jobs).buffer_unordered(self.num_concurrent);
+        let mut report = BatchReport::default();
+        loop {
+            let next = self.cancel.run_until_cancelled(results.next()).await?;
+            let Some((key, result)) = next else {
+                break;
+            };
+            match result {
+                Ok(value) => {
+                    on_complete(value).await?;
+                    report.succeeded += 1;
+                }
+                Err((attempts, err)) => {
+                    if err.is::<Cancelled>() {
+                        return Err(err);
+                    }
+                    report.failures.push(JobFailure {
+                        key,
+                        attempts,
+                        error: format!("{:#}", err),
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Runs a job until it succeeds or runs out of retries. On failure,
+    /// returns the number of attempts made, and the last error.
+    async fn run_with_retry<J, Fut, T>(&self, job: &J) -> Result<T, (u32, anyhow::Error)>
+    where
+        J: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut attempts = 0;
+        loop {
+            self.cancel.check().map_err(|e| (attempts, e.into()))?;
+            attempts += 1;
+            let err = match job().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            if err.is::<Cancelled>() || attempts > self.retry.max_retries {
+                return Err((attempts, err));
+            }
+            let delay = self.retry.backoff(attempts);
+            self.cancel
+                .run_until_cancelled(smol::Timer::after(delay))
+                .await
+                .map_err(|e| (attempts, e.into()))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn fast_retry(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_transient_failure_is_retried() -> anyhow::Result<()> {
+        let scheduler = BatchScheduler::new(2, CancellationToken::new()).with_retry(fast_retry(2));
+        let calls = Cell::new(0);
+        let jobs = [("flaky".to_string(), || async {
+            calls.set(calls.get() + 1);
+            anyhow::ensure!(calls.get() >= 2, "transient");
+            Ok(calls.get())
+        })];
+        let mut results = Vec::new();
+        let report = smol::block_on(scheduler.run(jobs, async |value| {
+            results.push(value);
+            Ok(())
+        }))?;
+        assert!(report.is_success());
+        assert_eq!(results, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failure_recorded_after_max_retries() -> anyhow::Result<()> {
+        let scheduler = BatchScheduler::new(2, CancellationToken::new()).with_retry(fast_retry(1));
+        let jobs = (0..3).map(|i| {
+            (i.to_string(), move || async move {
+                anyhow::ensure!(i != 1, "job {} failed", i);
+                Ok(i)
+            })
+        });
+        let report = smol::block_on(scheduler.run(jobs, async |_| Ok(())))?;
+        assert_eq!(report.succeeded, 2);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].key, "1");
+        assert_eq!(report.failures[0].attempts, 2);
+        assert_eq!(report.failures[0].error, "job 1 failed");
+        Ok(())
+    }
+}
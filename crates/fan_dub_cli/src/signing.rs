@@ -0,0 +1,151 @@
+//! Signatures of release manifests, so users can check that a package came
+//! from the project.
+//!
+//! A package is signed with the project's ed25519 key: the signature of the
+//! exact bytes of `release.json` is stored next to it, hex-encoded, in
+//! `release.json.sig`. Since the manifest holds a hash of every game file,
+//! checking the signature and then the files against the manifest covers the
+//! whole install.
+//!
+//! Keys are stored as hex text. The secret key file must be kept private; the
+//! public key is published so users can pass it to `verify-install`.
+
+use std::{io::Write as _, path::Path};
+
+use ed25519_dalek::{SIGNATURE_LENGTH, Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+/// Generates a new signing key.
+pub fn generate_key() -> anyhow::Result<SigningKey> {
+    let mut secret = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+    getrandom::fill(&mut secret).map_err(|e| anyhow::anyhow!("No random source: {e}"))?;
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+/// Writes a signing key to a new file, readable only by its owner where the
+/// platform supports it.
+pub fn write_signing_key(path: &Path, key: &SigningKey) -> anyhow::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", path.display()))?;
+    writeln!(file, "{}", hex::encode(key.to_bytes()))?;
+    Ok(())
+}
+
+pub fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    let bytes = decode_hex::<{ ed25519_dalek::SECRET_KEY_LENGTH }>(
+        &std::fs::read_to_string(path)?,
+        "signing key",
+    )?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Parses a hex-encoded public key.
+pub fn parse_public_key(text: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes = decode_hex::<{ ed25519_dalek::PUBLIC_KEY_LENGTH }>(text, "public key")?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+pub fn public_key_hex(key: &VerifyingKey) -> String {
+    hex::encode(key.to_bytes())
+}
+
+fn decode_hex<const N: usize>(text: &str, what: &str) -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    hex::decode_to_slice(text.trim(), &mut bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid {what}: {e}"))?;
+    Ok(bytes)
+}
+
+/// The path of the signature of a manifest.
+pub fn signature_path(manifest_path: &Path) -> std::path::PathBuf {
+    let mut path = manifest_path.as_os_str().to_owned();
+    path.push(".sig");
+    path.into()
+}
+
+/// Signs the manifest at `manifest_path`, writing the signature next to it.
+pub fn sign_manifest(manifest_path: &Path, key: &SigningKey) -> anyhow::Result<()> {
+    let signature = key.sign(&std::fs::read(manifest_path)?);
+    std::fs::write(
+        signature_path(manifest_path),
+        format!("{}\n", hex::encode(signature.to_bytes())),
+    )?;
+    Ok(())
+}
+
+/// Whether the manifest at `manifest_path` has a signature.
+pub fn is_signed(manifest_path: &Path) -> bool {
+    signature_path(manifest_path).exists()
+}
+
+/// Checks the signature of the manifest at `manifest_path`, whose contents
+/// are `manifest_data`, against the public key. Fails if the manifest isn't
+/// signed.
+///
+/// The caller reads the manifest once and parses the same bytes it passes
+/// here, so the manifest it trusts is the one that was verified.
+pub fn verify_manifest(
+    manifest_path: &Path,
+    manifest_data: &[u8],
+    key: &VerifyingKey,
+) -> anyhow::Result<()> {
+    let signature_path = signature_path(manifest_path);
+    let signature_text = match std::fs::read_to_string(&signature_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("{} is not signed", manifest_path.display())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let signature = Signature::from_bytes(&decode_hex::<SIGNATURE_LENGTH>(
+        &signature_text,
+        "signature",
+    )?);
+    key.verify(manifest_data, &signature)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "The signature of {} doesn't match the public key; the package may have been tampered with",
+                manifest_path.display()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join("release.json");
+        std::fs::write(&manifest, b"{\"version\": \"1.0.0\"}")?;
+        let key = generate_key()?;
+        let public_key = parse_public_key(&public_key_hex(&key.verifying_key()))?;
+        let data = std::fs::read(&manifest)?;
+        assert!(verify_manifest(&manifest, &data, &public_key).is_err());
+
+        sign_manifest(&manifest, &key)?;
+        verify_manifest(&manifest, &data, &public_key)?;
+        assert!(verify_manifest(&manifest, &data, &generate_key()?.verifying_key()).is_err());
+
+        // Only the bytes given are checked, whatever the file holds now.
+        assert!(verify_manifest(&manifest, b"{\"version\": \"6.6.6\"}", &public_key).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_round_trip() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("release.key");
+        let key = generate_key()?;
+        write_signing_key(&path, &key)?;
+        assert_eq!(read_signing_key(&path)?.to_bytes(), key.to_bytes());
+        // An existing key must not be overwritten.
+        assert!(write_signing_key(&path, &generate_key()?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! Custom processing stages, run on each sample between conversion and
+//! packing (e.g. a de-noiser).
+//!
+//! Stages receive and return the sample as a 16-bit PCM WAV file, at the
+//! sample rate and channel layout of the build's conversion profile. They are
+//! configured per project in `fan-dub.json`:
+//!
+//! ```json
+//! { "stages": [{ "type": "command", "command": ["denoise", "{input}", "{output}"] }] }
+//! ```
+//!
+//! The `command` stage runs an external program, and the `match-duration`
+//! stage time-stretches lines to the length of the original clip. Programs
+//! built on this crate can add their own stage types with
+//! [`StageRegistry::register`].
+
+use std::{collections::BTreeMap, ffi::OsStr};
+
+use futures::future::LocalBoxFuture;
+use sci_resources::types::msg::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    tools::{CommandTemplate, ExternalTool},
+};
+
+mod match_duration;
+
+pub use match_duration::match_duration_factory;
+
+/// The sample a stage is processing.
+pub struct StageInput<'a> {
+    pub room: u16,
+    pub message_id: MessageId,
+    /// The sample's key, as used in build reports.
+    pub key: &'a str,
+}
+
+pub trait Stage {
+    /// A short name for the stage, used in build reports.
+    fn name(&self) -> &str;
+
+    /// Processes a WAV file, returning the processed WAV file.
+    fn process<'a>(
+        &'a self,
+        input: &'a StageInput<'a>,
+        wav: Vec<u8>,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Vec<u8>>>;
+}
+
+/// The configuration of one stage: its type, and type-specific options.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StageConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub options: serde_json::Map<String, serde_json::Value>,
+}
+
+type StageFactory = Box<dyn Fn(&StageConfig) -> anyhow::Result<Box<dyn Stage>>>;
+
+/// Creates stages from their configuration, by type.
+pub struct StageRegistry {
+    factories: BTreeMap<String, StageFactory>,
+}
+
+impl StageRegistry {
+    /// A registry with the built-in stage types.
+    pub fn with_builtins() -> Self {
+        let mut registry = StageRegistry {
+            factories: BTreeMap::new(),
+        };
+        registry.register("command", |config| {
+            Ok(Box::new(CommandStage::from_config(config)?))
+        });
+        registry
+    }
+
+    /// Adds a stage type. A type with the same name is replaced.
+    pub fn register(
+        &mut self,
+        kind: &str,
+        factory: impl Fn(&StageConfig) -> anyhow::Result<Box<dyn Stage>> + 'static,
+    ) {
+        self.factories.insert(kind.to_string(), Box::new(factory));
+    }
+
+    /// Creates the configured stages, in order.
+    pub fn create_all(&self, configs: &[StageConfig]) -> anyhow::Result<Vec<Box<dyn Stage>>> {
+        configs
+            .iter()
+            .map(|config| {
+                let factory = self.factories.get(&config.kind).ok_or_else(|| {
+                    anyhow::anyhow!("Unknown processing stage type {:?}", config.kind)
+                })?;
+                factory(config)
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct CommandStageOptions {
+    command: Vec<String>,
+    name: Option<String>,
+}
+
+/// Runs an external program on each sample. In the command's arguments,
+/// `{input}` and `{output}` are replaced with the paths of the input and
+/// output WAV files, and `{key}` with the sample's key.
+struct CommandStage {
+    name: String,
+    tool: ExternalTool,
+    args: CommandTemplate,
+}
+
+impl CommandStage {
+    fn from_config(config: &StageConfig) -> anyhow::Result<Self> {
+        let options: CommandStageOptions =
+            serde_json::from_value(serde_json::Value::Object(config.options.clone()))?;
+        let Some((program, args)) = options.command.split_first() else {
+            anyhow::bail!("A command stage needs a command to run");
+        };
+        Ok(CommandStage {
+            name: options.name.unwrap_or_else(|| program.clone()),
+            tool: ExternalTool::from_path(program.into()),
+            args: CommandTemplate::new(args.iter().cloned()),
+        })
+    }
+
+    async fn run(
+        &self,
+        input: &StageInput<'_>,
+        wav: Vec<u8>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<u8>> {
+        let dir = tempfile::tempdir()?;
+        let input_path = dir.path().join("input.wav");
+        let output_path = dir.path().join("output.wav");
+        smol::fs::write(&input_path, wav).await?;
+        let args = self.args.render(&BTreeMap::from([
+            ("input", input_path.as_os_str()),
+            ("output", output_path.as_os_str()),
+            ("key", OsStr::new(input.key)),
+        ]));
+        self.tool
+            .run(args, None, futures::future::ready(()), cancel)
+            .await?;
+        Ok(smol::fs::read(&output_path).await?)
+    }
+}
+
+impl Stage for CommandStage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a StageInput<'a>,
+        wav: Vec<u8>,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(self.run(input, wav, cancel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> Vec<StageConfig> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_create_command_stage() -> anyhow::Result<()> {
+        let registry = StageRegistry::with_builtins();
+        let stages = registry.create_all(&config(
+            r#"[{ "type": "command", "command": ["denoise", "{input}", "{output}"] }]"#,
+        ))?;
+        assert_eq!(stages.len(), 1);
+        assert_eq!(stages[0].name(), "denoise");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_stage_type() {
+        let registry = StageRegistry::with_builtins();
+        assert!(
+            registry
+                .create_all(&config(r#"[{ "type": "mystery" }]"#))
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_stage_runs_program() -> anyhow::Result<()> {
+        let registry = StageRegistry::with_builtins();
+        let stages = registry.create_all(&config(
+            r#"[{ "type": "command", "command": ["cp", "{input}", "{output}"] }]"#,
+        ))?;
+        let input = StageInput {
+            room: 1,
+            message_id: MessageId::new(1, 1, 0, 1),
+            key: "1-1-1-0-1",
+        };
+        let output =
+            smol::block_on(stages[0].process(&input, b"RIFF".to_vec(), &CancellationToken::new()))?;
+        assert_eq!(output, b"RIFF");
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! A stage that time-stretches lines to the duration of the original clip,
+//! so cutscenes timed around the original audio stay in sync.
+
+use std::{path::PathBuf, rc::Rc};
+
+use futures::future::LocalBoxFuture;
+use serde::Deserialize;
+
+use super::{Stage, StageConfig, StageInput};
+use crate::{
+    cancel::CancellationToken,
+    path::LookupPath,
+    resources::OriginalAudio,
+    tools::{
+        ffmpeg::{BytesInput, FfmpegTool},
+        rubberband::{RubberbandTool, StretchOptions},
+    },
+};
+
+/// Stretches smaller than this are skipped, as they aren't audible.
+const MIN_CHANGE: f64 = 0.005;
+
+#[derive(Deserialize)]
+struct MatchDurationOptions {
+    /// The largest change in duration allowed, as a percentage. Lines that
+    /// would need more are stretched by this much.
+    #[serde(default = "default_max_change_percent")]
+    max_change_percent: f64,
+}
+
+fn default_max_change_percent() -> f64 {
+    10.0
+}
+
+/// Returns the time ratio (output length to input length) that brings the
+/// take to the target duration, limited to the maximum change. Returns `None`
+/// if no stretch is needed.
+fn stretch_ratio(take_secs: f64, target_secs: f64, max_change_percent: f64) -> Option<f64> {
+    if take_secs <= 0.0 || target_secs <= 0.0 {
+        return None;
+    }
+    let max_change = max_change_percent / 100.0;
+    let ratio = (target_secs / take_secs).clamp(1.0 - max_change, 1.0 + max_change);
+    ((ratio - 1.0).abs() >= MIN_CHANGE).then_some(ratio)
+}
+
+struct MatchDurationStage {
+    ffmpeg: FfmpegTool,
+    rubberband: RubberbandTool,
+    original: Rc<OriginalAudio>,
+    max_change_percent: f64,
+}
+
+impl MatchDurationStage {
+    async fn run(
+        &self,
+        input: &StageInput<'_>,
+        wav: Vec<u8>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<u8>> {
+        // Lines that are new in the dub have nothing to match.
+        let Some(original_clip) = self.original.clip(input.room, &input.message_id)? else {
+            return Ok(wav);
+        };
+        let target = self
+            .ffmpeg
+            .measure_duration(BytesInput::new(original_clip), cancel)
+            .await?;
+        let take = self
+            .ffmpeg
+            .measure_duration(BytesInput::new(wav.clone()), cancel)
+            .await?;
+        let Some(ratio) = stretch_ratio(
+            take.as_secs_f64(),
+            target.as_secs_f64(),
+            self.max_change_percent,
+        ) else {
+            return Ok(wav);
+        };
+        let dir = tempfile::tempdir()?;
+        let input_path = dir.path().join("input.wav");
+        let output_path = dir.path().join("output.wav");
+        smol::fs::write(&input_path, wav).await?;
+        self.rubberband
+            .stretch(
+                &input_path,
+                &output_path,
+                &StretchOptions {
+                    time_ratio: Some(ratio),
+                    pitch_semitones: None,
+                },
+                cancel,
+            )
+            .await?;
+        Ok(smol::fs::read(&output_path).await?)
+    }
+}
+
+impl Stage for MatchDurationStage {
+    fn name(&self) -> &str {
+        "match-duration"
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a StageInput<'a>,
+        wav: Vec<u8>,
+        cancel: &'a CancellationToken,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(self.run(input, wav, cancel))
+    }
+}
+
+/// Returns a factory for the `match-duration` stage type, which needs ffmpeg,
+/// Rubber Band, and the original game audio.
+pub fn match_duration_factory(
+    ffmpeg_path: PathBuf,
+    original: Option<Rc<OriginalAudio>>,
+) -> impl Fn(&StageConfig) -> anyhow::Result<Box<dyn Stage>> {
+    move |config| {
+        let options: MatchDurationOptions =
+            serde_json::from_value(serde_json::Value::Object(config.options.clone()))?;
+        anyhow::ensure!(
+            (0.0..100.0).contains(&options.max_change_percent),
+            "max_change_percent must be between 0 and 100"
+        );
+        let original = original.clone().ok_or_else(|| {
+            anyhow::anyhow!("The match-duration stage needs the original game directory")
+        })?;
+        let rubberband = RubberbandTool::find(&LookupPath::from_env())
+            .ok_or_else(|| anyhow::anyhow!("rubberband not found in PATH"))?;
+        Ok(Box::new(MatchDurationStage {
+            ffmpeg: FfmpegTool::from_path(ffmpeg_path.clone()),
+            rubberband,
+            original,
+            max_change_percent: options.max_change_percent,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stretch_ratio() {
+        assert_eq!(stretch_ratio(2.0, 2.2, 20.0), Some(1.1));
+        // Limited to the maximum change.
+        assert_eq!(stretch_ratio(2.0, 3.0, 10.0), Some(1.1));
+        assert_eq!(stretch_ratio(2.0, 1.0, 10.0), Some(0.9));
+        // Too small to matter.
+        assert_eq!(stretch_ratio(2.0, 2.005, 10.0), None);
+        assert_eq!(stretch_ratio(0.0, 2.0, 10.0), None);
+    }
+}
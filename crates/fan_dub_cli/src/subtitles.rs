@@ -0,0 +1,172 @@
+//! Subtitle timing for ScummVM's "text and speech" mode.
+//!
+//! In that mode ScummVM shows a line's text for as long as its clip plays, so
+//! most subtitles follow the new clips by themselves. Lines with Sync36 data
+//! are the exception: the talker, and its text, stays up until the last cue,
+//! so cues timed to the original clip cut a longer line's subtitle short or
+//! hold a shorter one on screen. For every line whose duration changed, the
+//! original cues are rescaled to the new clip and written as Sync36 patches,
+//! which ScummVM reads from the game directory, and `subtitle-timing.json`
+//! lists the line's original and new durations.
+
+use std::{path::Path, time::Duration};
+
+use sci_resources::{
+    ResourceType,
+    types::{
+        msg::MessageId,
+        sync36::{SyncCue, TICKS_PER_SECOND, patch_file_name, read_sync36_patch},
+    },
+};
+use serde::Serialize;
+
+use crate::{
+    cancel::CancellationToken,
+    resources::{OriginalAudio, sample_key},
+    tools::ffmpeg::{BytesInput, FfmpegTool},
+};
+
+/// The report of lines whose timing changed, written to the output
+/// directory.
+pub const TIMING_FILE: &str = "subtitle-timing.json";
+
+/// Durations that differ from the original's by no more than this are
+/// treated as unchanged.
+const TOLERANCE_TICKS: u32 = 1;
+
+/// A line whose new clip is longer or shorter than the original.
+#[derive(Serialize, Debug)]
+pub struct TimedLine {
+    pub key: String,
+    pub original_ticks: u32,
+    pub new_ticks: u32,
+    /// The Sync36 patch written with the rescaled cues, if the original line
+    /// had any.
+    pub sync_patch: Option<String>,
+}
+
+/// A Sync36 patch to write to the output directory.
+#[derive(Debug)]
+pub struct SyncPatch {
+    pub file_name: String,
+    pub cues: Vec<SyncCue>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SubtitleTiming {
+    pub lines: Vec<TimedLine>,
+    #[serde(skip)]
+    pub patches: Vec<SyncPatch>,
+}
+
+impl SubtitleTiming {
+    pub fn write(&self, output_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            output_dir.join(TIMING_FILE),
+            serde_json::to_vec_pretty(&self.lines)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Converts a duration to interpreter ticks.
+pub fn to_ticks(duration: Duration) -> u32 {
+    (duration.as_secs_f64() * f64::from(TICKS_PER_SECOND)).round() as u32
+}
+
+/// Moves the cues so they fall at the same points of a line that now lasts
+/// `new_ticks` instead of `original_ticks`.
+pub fn rescale_cues(cues: &[SyncCue], original_ticks: u32, new_ticks: u32) -> Vec<SyncCue> {
+    if original_ticks == 0 {
+        return cues.to_vec();
+    }
+    let scale = f64::from(new_ticks) / f64::from(original_ticks);
+    cues.iter()
+        .map(|cue| SyncCue {
+            // 0xFFFF ends the list, so the last usable tick is just before.
+            ticks: (f64::from(cue.ticks) * scale)
+                .round()
+                .min(f64::from(0xFFFEu16)) as u16,
+            cel: cue.cel,
+        })
+        .collect()
+}
+
+/// Compares the lines' new durations with their original clips, and
+/// rescales the Sync36 cues of those that changed. The original cues are
+/// read from the Sync36 patches in the game directory.
+pub async fn subtitle_timing(
+    lines: &[(u16, MessageId, Duration)],
+    original: &OriginalAudio,
+    game_dir: &Path,
+    ffmpeg: &FfmpegTool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<SubtitleTiming> {
+    let mut timing = SubtitleTiming::default();
+    for (room, message_id, duration) in lines {
+        // Lines that are new in the dub have no subtitle timing to fix.
+        let Some(clip) = original.clip(*room, message_id)? else {
+            continue;
+        };
+        let original_ticks = to_ticks(
+            ffmpeg
+                .measure_duration(BytesInput::new(clip), cancel)
+                .await?,
+        );
+        let new_ticks = to_ticks(*duration);
+        if original_ticks.abs_diff(new_ticks) <= TOLERANCE_TICKS {
+            continue;
+        }
+        let file_name = patch_file_name(ResourceType::Sync36, *room, message_id)?;
+        let sync_path = game_dir.join(&file_name);
+        let sync_patch = if sync_path.exists() {
+            let cues = read_sync36_patch(&std::fs::read(&sync_path)?)?;
+            timing.patches.push(SyncPatch {
+                file_name: file_name.clone(),
+                cues: rescale_cues(&cues, original_ticks, new_ticks),
+            });
+            Some(file_name)
+        } else {
+            None
+        };
+        timing.lines.push(TimedLine {
+            key: sample_key(*room, message_id),
+            original_ticks,
+            new_ticks,
+            sync_patch,
+        });
+    }
+    Ok(timing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ticks() {
+        assert_eq!(to_ticks(Duration::from_millis(1500)), 90);
+        assert_eq!(to_ticks(Duration::from_millis(8)), 0);
+        assert_eq!(to_ticks(Duration::from_millis(9)), 1);
+    }
+
+    #[test]
+    fn test_rescale_cues() {
+        let cues = [
+            SyncCue { ticks: 0, cel: 0 },
+            SyncCue { ticks: 30, cel: 2 },
+            SyncCue { ticks: 90, cel: 0 },
+        ];
+        let longer = rescale_cues(&cues, 90, 120);
+        assert_eq!(
+            longer.iter().map(|cue| cue.ticks).collect::<Vec<_>>(),
+            [0, 40, 120]
+        );
+        assert_eq!(
+            longer.iter().map(|cue| cue.cel).collect::<Vec<_>>(),
+            [0, 2, 0]
+        );
+        assert_eq!(rescale_cues(&cues, 0, 120), cues);
+        assert_eq!(rescale_cues(&cues[2..], 1, 10_000)[0].ticks, 0xFFFE);
+    }
+}
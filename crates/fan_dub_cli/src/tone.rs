@@ -0,0 +1,358 @@
+//! Compares the background tone of dubbed lines against the original speech
+//! in the same room.
+//!
+//! Each clip gets a [`ToneProfile`]: its noise floor, and how much of its
+//! energy sits in the low and high bands. The original clips of a room are
+//! averaged into a reference, and dubbed lines are ranked by how far they are
+//! from it, so the lines most in need of manual mastering come first.
+
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    f64::consts::PI,
+    path::PathBuf,
+};
+
+use sci_resources::types::msg::MessageId;
+use serde::Serialize;
+
+use crate::{
+    cleanup::CleanupPresets,
+    resources::{OriginalAudio, SampleDir},
+    scheduler::{BatchFailed, BatchScheduler},
+    tools::ffmpeg::{self, ANALYSIS_RATE, FfmpegTool},
+};
+
+/// The length of the windows that levels are measured over.
+const WINDOW_SECS: f64 = 0.05;
+
+/// Energy below this frequency counts towards the low band.
+const LOW_BAND_HZ: f64 = 300.0;
+
+/// Energy above this frequency counts towards the high band.
+const HIGH_BAND_HZ: f64 = 3000.0;
+
+/// The level used for silence, in dB.
+const SILENCE_DB: f64 = -96.0;
+
+fn to_db(power: f64) -> f64 {
+    if power > 0.0 {
+        (10.0 * power.log10()).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+/// A one-pole low-pass filter.
+fn low_pass(samples: &[f64], cutoff_hz: f64, rate: u32) -> Vec<f64> {
+    let alpha = 1.0 - (-2.0 * PI * cutoff_hz / f64::from(rate)).exp();
+    let mut state = 0.0;
+    samples
+        .iter()
+        .map(|sample| {
+            state += alpha * (sample - state);
+            state
+        })
+        .collect()
+}
+
+fn mean_power(samples: &[f64]) -> f64 {
+    samples.iter().map(|sample| sample * sample).sum::<f64>() / samples.len() as f64
+}
+
+/// The background tone of a clip.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ToneProfile {
+    /// The level of the quietest parts of the clip, relative to the loudest
+    /// parts, in dB. This doesn't depend on the clip's overall gain.
+    pub noise_floor_db: f64,
+    /// The share of the clip's energy below 300 Hz, in dB.
+    pub low_db: f64,
+    /// The share of the clip's energy above 3 kHz, in dB.
+    pub high_db: f64,
+}
+
+impl ToneProfile {
+    /// Analyzes mono samples. Returns `None` for clips that are silent, or
+    /// too short to measure.
+    pub fn analyze(samples: &[i16], rate: u32) -> Option<Self> {
+        let window = (f64::from(rate) * WINDOW_SECS) as usize;
+        if window == 0 || samples.len() < window {
+            return None;
+        }
+        let samples: Vec<f64> = samples
+            .iter()
+            .map(|&sample| f64::from(sample) / 32768.0)
+            .collect();
+        let total = mean_power(&samples);
+        if total == 0.0 {
+            return None;
+        }
+
+        let mut levels: Vec<f64> = samples
+            .chunks_exact(window)
+            .map(|chunk| to_db(mean_power(chunk)))
+            .collect();
+        levels.sort_by(f64::total_cmp);
+        let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+
+        let low = low_pass(&samples, LOW_BAND_HZ, rate);
+        let high: Vec<f64> = samples
+            .iter()
+            .zip(low_pass(&samples, HIGH_BAND_HZ, rate))
+            .map(|(sample, low)| sample - low)
+            .collect();
+        Some(ToneProfile {
+            noise_floor_db: percentile(0.1) - percentile(0.9),
+            low_db: to_db(mean_power(&low) / total),
+            high_db: to_db(mean_power(&high) / total),
+        })
+    }
+
+    /// The average of the profiles, or `None` if there are none.
+    pub fn mean(profiles: &[ToneProfile]) -> Option<Self> {
+        if profiles.is_empty() {
+            return None;
+        }
+        let count = profiles.len() as f64;
+        let mean = |field: fn(&ToneProfile) -> f64| profiles.iter().map(field).sum::<f64>() / count;
+        Some(ToneProfile {
+            noise_floor_db: mean(|profile| profile.noise_floor_db),
+            low_db: mean(|profile| profile.low_db),
+            high_db: mean(|profile| profile.high_db),
+        })
+    }
+
+    /// How far this profile is from the reference, in dB, summed over the
+    /// measurements.
+    pub fn deviation(&self, reference: &ToneProfile) -> f64 {
+        (self.noise_floor_db - reference.noise_floor_db).abs()
+            + (self.low_db - reference.low_db).abs()
+            + (self.high_db - reference.high_db).abs()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RoomTone {
+    pub room: u16,
+    /// The number of original clips that could be measured.
+    pub original_clips: usize,
+    /// The average profile of the original clips.
+    pub reference: Option<ToneProfile>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LineTone {
+    pub room: u16,
+    pub message_id: MessageId,
+    /// The take's file, relative to the sample directory.
+    pub source: PathBuf,
+    pub tone: ToneProfile,
+    /// The line's deviation from its room's reference.
+    pub deviation: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ToneReport {
+    pub rooms: Vec<RoomTone>,
+    /// The dubbed lines, most deviating first. Lines in rooms without a
+    /// reference are left out.
+    pub lines: Vec<LineTone>,
+}
+
+impl ToneReport {
+    fn new(
+        references: BTreeMap<u16, (usize, Option<ToneProfile>)>,
+        mut lines: Vec<LineTone>,
+    ) -> Self {
+        lines.sort_by(|a, b| {
+            b.deviation
+                .total_cmp(&a.deviation)
+                .then((a.room, a.message_id).cmp(&(b.room, b.message_id)))
+        });
+        ToneReport {
+            rooms: references
+                .into_iter()
+                .map(|(room, (original_clips, reference))| RoomTone {
+                    room,
+                    original_clips,
+                    reference,
+                })
+                .collect(),
+            lines,
+        }
+    }
+}
+
+/// Analyzes the original clips of each room that has dubbed lines, and the
+/// takes of those lines. Takes are analyzed after trimming and cleanup, as
+/// they would be packed.
+pub async fn analyze_tone(
+    sample_dir: &SampleDir,
+    original: &OriginalAudio,
+    cleanup: &CleanupPresets,
+    ffmpeg: &FfmpegTool,
+    scheduler: &BatchScheduler,
+) -> anyhow::Result<ToneReport> {
+    let cancel = scheduler.cancellation();
+    let mut room_clips = BTreeMap::new();
+    for sample in sample_dir.samples() {
+        if let Entry::Vacant(entry) = room_clips.entry(sample.room) {
+            entry.insert(original.room_clips(sample.room)?);
+        }
+    }
+
+    let original_jobs = room_clips.iter().flat_map(|(&room, clips)| {
+        clips.iter().map(move |(id, clip)| {
+            let key = format!(
+                "original {}-{}-{}-{}-{}",
+                room,
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence()
+            );
+            let job = move || async move {
+                let samples = ffmpeg
+                    .decode_mono(ffmpeg::BytesInput::new(clip.clone()), None, cancel)
+                    .await?;
+                Ok::<_, anyhow::Error>((room, ToneProfile::analyze(&samples, ANALYSIS_RATE)))
+            };
+            (key, job)
+        })
+    });
+    let mut room_profiles: BTreeMap<u16, Vec<ToneProfile>> = BTreeMap::new();
+    let report = scheduler
+        .run(original_jobs, async |(room, profile)| {
+            let profiles = room_profiles.entry(room).or_default();
+            profiles.extend(profile);
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    let references: BTreeMap<u16, (usize, Option<ToneProfile>)> = room_clips
+        .keys()
+        .map(|room| {
+            let profiles = room_profiles.get(room).map(Vec::as_slice).unwrap_or(&[]);
+            (*room, (profiles.len(), ToneProfile::mean(profiles)))
+        })
+        .collect();
+
+    let references = &references;
+    let take_jobs = sample_dir
+        .samples()
+        .filter(|sample| matches!(references.get(&sample.room), Some((_, Some(_)))))
+        .map(|sample| {
+            let job = move || async move {
+                let filters = [sample.clip.trim_filter(), cleanup.filter_for(sample)?];
+                let filters: Vec<String> = filters.into_iter().flatten().collect();
+                let audio_filter = (!filters.is_empty()).then(|| filters.join(","));
+                let samples = ffmpeg
+                    .decode_mono(
+                        sample.clip_path(sample_dir.base_path())?,
+                        audio_filter.as_deref(),
+                        cancel,
+                    )
+                    .await?;
+                Ok::<_, anyhow::Error>((sample, ToneProfile::analyze(&samples, ANALYSIS_RATE)))
+            };
+            (sample.key(), job)
+        });
+    let mut lines = Vec::new();
+    let report = scheduler
+        .run(take_jobs, async |(sample, tone)| {
+            if let Some(tone) = tone
+                && let Some((_, Some(reference))) = references.get(&sample.room)
+            {
+                lines.push(LineTone {
+                    room: sample.room,
+                    message_id: sample.message_id,
+                    source: sample.clip.path.clone(),
+                    tone,
+                    deviation: tone.deviation(reference),
+                });
+            }
+            Ok(())
+        })
+        .await?;
+    if !report.is_success() {
+        return Err(BatchFailed(report).into());
+    }
+    Ok(ToneReport::new(references.clone(), lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, amplitude: f64, secs: f64) -> Vec<f64> {
+        let len = (secs * f64::from(ANALYSIS_RATE)) as usize;
+        (0..len)
+            .map(|i| amplitude * (2.0 * PI * freq * i as f64 / f64::from(ANALYSIS_RATE)).sin())
+            .collect()
+    }
+
+    fn to_pcm(samples: &[f64]) -> Vec<i16> {
+        samples
+            .iter()
+            .map(|sample| (sample * 32767.0) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_band_balance() {
+        // The bands use one-pole filters, so they roll off gently.
+        let low = ToneProfile::analyze(&to_pcm(&sine(80.0, 0.5, 1.0)), ANALYSIS_RATE).unwrap();
+        let high = ToneProfile::analyze(&to_pcm(&sine(6000.0, 0.5, 1.0)), ANALYSIS_RATE).unwrap();
+        assert!(low.low_db > -3.0, "{low:?}");
+        assert!(low.high_db < -20.0, "{low:?}");
+        assert!(high.high_db > -6.0, "{high:?}");
+        assert!(high.low_db < -20.0, "{high:?}");
+    }
+
+    #[test]
+    fn test_noise_floor() {
+        // Speech-like tone with quiet gaps between phrases.
+        let mut clip = Vec::new();
+        for _ in 0..5 {
+            clip.extend(sine(440.0, 0.5, 0.2));
+            clip.extend(sine(440.0, 0.005, 0.2));
+        }
+        let profile = ToneProfile::analyze(&to_pcm(&clip), ANALYSIS_RATE).unwrap();
+        assert!(
+            (profile.noise_floor_db + 40.0).abs() < 1.0,
+            "{}",
+            profile.noise_floor_db
+        );
+    }
+
+    #[test]
+    fn test_silence_has_no_profile() {
+        assert_eq!(ToneProfile::analyze(&[0; 22050], ANALYSIS_RATE), None);
+        assert_eq!(ToneProfile::analyze(&[100; 10], ANALYSIS_RATE), None);
+    }
+
+    #[test]
+    fn test_deviation() {
+        let reference = ToneProfile {
+            noise_floor_db: -40.0,
+            low_db: -6.0,
+            high_db: -12.0,
+        };
+        let line = ToneProfile {
+            noise_floor_db: -30.0,
+            low_db: -8.0,
+            high_db: -12.0,
+        };
+        assert_eq!(line.deviation(&reference), 12.0);
+        assert_eq!(
+            ToneProfile::mean(&[reference, line]),
+            Some(ToneProfile {
+                noise_floor_db: -35.0,
+                low_db: -7.0,
+                high_db: -12.0,
+            })
+        );
+    }
+}
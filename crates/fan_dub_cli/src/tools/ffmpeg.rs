@@ -1,14 +1,18 @@
+use std::ffi::OsString;
+
 use input::InputState;
 use output::OutputState;
-use smol::{io::AsyncBufReadExt, stream::StreamExt};
+
+use super::{ExternalTool, ProgressParser, ProgressStream};
+use crate::cancel::CancellationToken;
 
 mod formats;
 mod input;
 mod output;
 mod tcp;
 
-pub use formats::{OggVorbisOutputOptions, OutputFormat};
-pub use input::{Input, ReaderInput};
+pub use formats::{FlacOutputOptions, OggVorbisOutputOptions, OutputFormat};
+pub use input::{BytesInput, Input, ReaderInput};
 pub use output::{Output, VecOutput};
 
 pub trait ProgressListener {
@@ -21,13 +25,112 @@ impl ProgressListener for NullProgressListener {
     fn on_progress(&mut self, _done: bool, _progress_info: Vec<(String, String)>) {}
 }
 
+/// Parses the `key=value` progress blocks written by `-progress`, passing each
+/// complete block to a listener.
+struct KeyValueProgressParser<'a> {
+    listener: &'a mut dyn ProgressListener,
+    progress_info: Vec<(String, String)>,
+}
+
+impl ProgressParser for KeyValueProgressParser<'_> {
+    fn parse_line(&mut self, line: &str) {
+        let Some((key, value)) = line.split_once('=') else {
+            return;
+        };
+        if key == "progress" {
+            let done = value.trim() == "end";
+            self.listener
+                .on_progress(done, std::mem::take(&mut self.progress_info));
+        } else {
+            self.progress_info
+                .push((key.to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+/// Picks the mean volume out of the `volumedetect` filter's log output.
+#[derive(Default)]
+struct MeanVolumeParser {
+    mean_volume: Option<f64>,
+}
+
+impl ProgressParser for MeanVolumeParser {
+    fn parse_line(&mut self, line: &str) {
+        if let Some((_, value)) = line.split_once("mean_volume:") {
+            self.mean_volume = value
+                .trim()
+                .trim_end_matches("dB")
+                .trim()
+                .parse::<f64>()
+                .ok();
+        }
+    }
+}
+
+/// A piece of a rendered audio sequence.
+pub enum SequencePart {
+    /// An audio file, optionally trimmed to a time range.
+    Clip {
+        path: std::path::PathBuf,
+        start_us: Option<u64>,
+        end_us: Option<u64>,
+    },
+    /// A stretch of silence.
+    Silence(std::time::Duration),
+    /// A short tone, used to mark boundaries when speech isn't available.
+    Beep,
+}
+
+/// A file of raw RGB frames, with no header.
+pub struct RawVideo<'a> {
+    pub path: &'a std::path::Path,
+    pub width: usize,
+    pub height: usize,
+    pub fps: u32,
+}
+
+/// The sample rate that sequence parts are resampled to before joining.
+const SEQUENCE_SAMPLE_RATE: u32 = 22050;
+
+/// The sample rate that audio is decoded at for analysis.
+pub const ANALYSIS_RATE: u32 = 22050;
+
 pub struct FfmpegTool {
-    binary_path: std::path::PathBuf,
+    tool: ExternalTool,
+    bitexact: bool,
 }
 
 impl FfmpegTool {
     pub fn from_path(path: std::path::PathBuf) -> Self {
-        FfmpegTool { binary_path: path }
+        FfmpegTool {
+            tool: ExternalTool::from_path(path),
+            bitexact: false,
+        }
+    }
+
+    /// Makes the encoded output depend only on the input, leaving out the
+    /// encoder version, input metadata and random stream serials, so that
+    /// repeated builds are identical.
+    pub fn with_bitexact(self, bitexact: bool) -> Self {
+        FfmpegTool { bitexact, ..self }
+    }
+
+    /// Flags that apply to every encoded output.
+    fn output_flags(&self) -> Vec<OsString> {
+        if !self.bitexact {
+            return Vec::new();
+        }
+        [
+            "-map_metadata",
+            "-1",
+            "-fflags",
+            "+bitexact",
+            "-flags:a",
+            "+bitexact",
+        ]
+        .into_iter()
+        .map(OsString::from)
+        .collect()
     }
 
     pub async fn convert<I, O>(
@@ -36,61 +139,293 @@ impl FfmpegTool {
         output: O,
         output_format: impl Into<formats::OutputFormat>,
         progress: &mut dyn ProgressListener,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<O::OutputType>
+    where
+        I: Input,
+        O: Output,
+    {
+        self.convert_with_filter(input, output, output_format, None, progress, cancel)
+            .await
+    }
+
+    /// Like [`FfmpegTool::convert`], but applies an audio filter graph (e.g.
+    /// `volume=3dB`) to the input.
+    pub async fn convert_with_filter<I, O>(
+        &self,
+        input: I,
+        output: O,
+        output_format: impl Into<formats::OutputFormat>,
+        audio_filter: Option<&str>,
+        progress: &mut dyn ProgressListener,
+        cancel: &CancellationToken,
     ) -> anyhow::Result<O::OutputType>
     where
         I: Input,
         O: Output,
     {
-        let mut command = smol::process::Command::new(&self.binary_path);
         let input_state = input.create_state().await?;
         let output_state = output.create_state().await?;
         let output_format = output_format.into();
-        let mut child = command
-            .arg("-nostdin")
-            .arg("-progress")
-            .arg("pipe:1")
-            .arg("-i")
-            .arg(input_state.url())
-            .arg("-f")
-            .arg(output_format.format_name())
-            .args(output_format.get_options().to_flags(Some("a:0")))
-            .arg(output_state.url())
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-        let stdout = smol::io::BufReader::new(child.stdout.take().expect("Failed to create pipe."));
-        let (status, output, _, _) = futures::join!(
-            child.status(),
-            output_state.wait(),
-            input_state.wait(),
-            async move {
-                let mut lines = stdout.lines();
-                let mut progress_info = Vec::new();
-                while let Some(line) = lines.next().await {
-                    let line = line.unwrap();
-                    if let Some(eq_index) = line.find('=') {
-                        let key = &line[..eq_index];
-                        let value = &line[eq_index + 1..];
-                        if key == "progress" {
-                            let done = value.trim() == "end";
-                            progress.on_progress(done, progress_info);
-                            progress_info = Vec::new();
-                        } else {
-                            let value = value.trim().to_string();
-                            progress_info.push((key.to_string(), value));
-                        }
+        let mut args: Vec<OsString> = vec![
+            "-nostdin".into(),
+            "-progress".into(),
+            "pipe:1".into(),
+            "-i".into(),
+            input_state.url(),
+        ];
+        if let Some(audio_filter) = audio_filter {
+            args.extend(["-af".into(), audio_filter.into()]);
+        }
+        args.extend(self.output_flags());
+        args.extend(["-f".into(), output_format.format_name().into()]);
+        args.extend(output_format.get_options().to_flags(Some("a:0")));
+        args.push(output_state.url());
+        let mut parser = KeyValueProgressParser {
+            listener: progress,
+            progress_info: Vec::new(),
+        };
+        // If the run is cancelled, dropping these futures stops the
+        // input/output tasks.
+        let (output, _) = self
+            .tool
+            .run(
+                args,
+                Some((ProgressStream::Stdout, &mut parser)),
+                futures::future::join(output_state.wait(), input_state.wait()),
+                cancel,
+            )
+            .await?;
+        output
+    }
+
+    /// Measures the mean volume of the input, in dBFS.
+    pub async fn measure_loudness<I>(
+        &self,
+        input: I,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<f64>
+    where
+        I: Input,
+    {
+        let input_state = input.create_state().await?;
+        let args: Vec<OsString> = vec![
+            "-nostdin".into(),
+            "-hide_banner".into(),
+            "-i".into(),
+            input_state.url(),
+            "-af".into(),
+            "volumedetect".into(),
+            "-f".into(),
+            "null".into(),
+            "-".into(),
+        ];
+        let mut parser = MeanVolumeParser::default();
+        self.tool
+            .run(
+                args,
+                Some((ProgressStream::Stderr, &mut parser)),
+                input_state.wait(),
+                cancel,
+            )
+            .await??;
+        parser
+            .mean_volume
+            .ok_or_else(|| anyhow::anyhow!("ffmpeg did not report the mean volume"))
+    }
+
+    /// Decodes the input to mono samples at [`ANALYSIS_RATE`], for analysis.
+    /// The audio filter, if given, is applied first.
+    pub async fn decode_mono<I>(
+        &self,
+        input: I,
+        audio_filter: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<i16>>
+    where
+        I: Input,
+    {
+        self.decode(input, audio_filter, "mono", cancel).await
+    }
+
+    /// Decodes the input to interleaved stereo samples at [`ANALYSIS_RATE`],
+    /// for analysis. Mono inputs have the same samples in both channels. The
+    /// audio filter, if given, is applied first.
+    pub async fn decode_stereo<I>(
+        &self,
+        input: I,
+        audio_filter: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<i16>>
+    where
+        I: Input,
+    {
+        self.decode(input, audio_filter, "stereo", cancel).await
+    }
+
+    async fn decode<I>(
+        &self,
+        input: I,
+        audio_filter: Option<&str>,
+        channel_layout: &str,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<i16>>
+    where
+        I: Input,
+    {
+        let resample =
+            format!("aresample={ANALYSIS_RATE},aformat=channel_layouts={channel_layout}");
+        let audio_filter = match audio_filter {
+            Some(filter) => format!("{filter},{resample}"),
+            None => resample,
+        };
+        let pcm = self
+            .convert_with_filter(
+                input,
+                VecOutput,
+                OutputFormat::RawS16Le,
+                Some(&audio_filter),
+                &mut NullProgressListener,
+                cancel,
+            )
+            .await?;
+        Ok(pcm
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect())
+    }
+
+    /// Measures the duration of the input, by decoding it.
+    pub async fn measure_duration<I>(
+        &self,
+        input: I,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<std::time::Duration>
+    where
+        I: Input,
+    {
+        let samples = self.decode_mono(input, None, cancel).await?;
+        Ok(std::time::Duration::from_secs_f64(
+            samples.len() as f64 / f64::from(ANALYSIS_RATE),
+        ))
+    }
+
+    /// Encodes a file of raw RGB frames, with an audio track, into a video.
+    /// The frames are scaled up by `scale` without smoothing, so pixel art
+    /// stays sharp. The container and codecs follow the output's extension.
+    pub async fn render_video(
+        &self,
+        frames: &RawVideo<'_>,
+        scale: u32,
+        audio_path: &std::path::Path,
+        output_path: &std::path::Path,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let mut args: Vec<OsString> = vec![
+            "-nostdin".into(),
+            "-y".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-f".into(),
+            "rawvideo".into(),
+            "-pix_fmt".into(),
+            "rgb24".into(),
+            "-video_size".into(),
+            format!("{}x{}", frames.width, frames.height).into(),
+            "-framerate".into(),
+            frames.fps.to_string().into(),
+            "-i".into(),
+            frames.path.into(),
+            "-i".into(),
+            audio_path.into(),
+            "-vf".into(),
+            format!("scale=iw*{scale}:ih*{scale}:flags=neighbor").into(),
+            // Most players only handle 4:2:0 video.
+            "-pix_fmt".into(),
+            "yuv420p".into(),
+            "-shortest".into(),
+        ];
+        args.extend(self.output_flags());
+        args.push(output_path.into());
+        self.tool
+            .run(args, None, futures::future::ready(()), cancel)
+            .await
+    }
+
+    /// Joins the parts, in order, into a single mono audio file.
+    pub async fn render_sequence(
+        &self,
+        parts: &[SequencePart],
+        output_path: &std::path::Path,
+        output_format: impl Into<formats::OutputFormat>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(!parts.is_empty(), "Nothing to render");
+        let output_format = output_format.into();
+        let mut args: Vec<OsString> = vec![
+            "-nostdin".into(),
+            "-y".into(),
+            "-loglevel".into(),
+            "error".into(),
+        ];
+        let mut filter = String::new();
+        for (i, part) in parts.iter().enumerate() {
+            match part {
+                SequencePart::Clip {
+                    path,
+                    start_us,
+                    end_us,
+                } => {
+                    if let Some(start_us) = start_us {
+                        args.extend(["-ss".into(), format!("{start_us}us").into()]);
+                    }
+                    if let Some(end_us) = end_us {
+                        args.extend(["-to".into(), format!("{end_us}us").into()]);
                     }
+                    args.extend(["-i".into(), path.into()]);
+                }
+                SequencePart::Silence(duration) => {
+                    args.extend([
+                        "-f".into(),
+                        "lavfi".into(),
+                        "-t".into(),
+                        format!("{}us", duration.as_micros()).into(),
+                        "-i".into(),
+                        format!("anullsrc=r={SEQUENCE_SAMPLE_RATE}:cl=mono").into(),
+                    ]);
+                }
+                SequencePart::Beep => {
+                    args.extend([
+                        "-f".into(),
+                        "lavfi".into(),
+                        "-i".into(),
+                        format!(
+                            "sine=frequency=880:duration=0.25:sample_rate={SEQUENCE_SAMPLE_RATE}"
+                        )
+                        .into(),
+                    ]);
                 }
             }
-        );
-        let status = status?;
-
-        anyhow::ensure!(
-            status.success(),
-            "ffmpeg process exited with non-zero status: {}",
-            status
-        );
-
-        output
+            filter.push_str(&format!(
+                "[{i}:a:0]aresample={SEQUENCE_SAMPLE_RATE},aformat=channel_layouts=mono[a{i}];"
+            ));
+        }
+        for i in 0..parts.len() {
+            filter.push_str(&format!("[a{i}]"));
+        }
+        filter.push_str(&format!("concat=n={}:v=0:a=1[out]", parts.len()));
+        args.extend([
+            "-filter_complex".into(),
+            filter.into(),
+            "-map".into(),
+            "[out]".into(),
+        ]);
+        args.extend(self.output_flags());
+        args.extend(["-f".into(), output_format.format_name().into()]);
+        args.extend(output_format.get_options().to_flags(Some("a:0")));
+        args.push(output_path.into());
+        self.tool
+            .run(args, None, futures::future::ready(()), cancel)
+            .await
     }
 }
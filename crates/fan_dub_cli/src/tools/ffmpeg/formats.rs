@@ -7,6 +7,10 @@ pub struct FlacOutputOptions {
 }
 
 impl FlacOutputOptions {
+    pub fn new(compression_level: u8) -> Self {
+        FlacOutputOptions { compression_level }
+    }
+
     pub fn get_options(&self) -> AVOptions {
         let mut options = HashMap::new();
         options.insert(
@@ -17,6 +21,12 @@ impl FlacOutputOptions {
     }
 }
 
+impl Default for FlacOutputOptions {
+    fn default() -> Self {
+        FlacOutputOptions::new(5)
+    }
+}
+
 pub struct Mp3OutputOptions {
     bitrate: u32,
 }
@@ -55,6 +65,12 @@ pub enum OutputFormat {
     Flac(FlacOutputOptions),
     Mp3(Mp3OutputOptions),
     Ogg(OggVorbisOutputOptions),
+    /// Raw unsigned 8-bit PCM samples, with no header.
+    RawU8,
+    /// Raw signed 16-bit little-endian PCM samples, with no header.
+    RawS16Le,
+    /// 16-bit PCM in a WAV file.
+    Wav,
 }
 
 impl OutputFormat {
@@ -63,6 +79,9 @@ impl OutputFormat {
             OutputFormat::Flac(_) => "flac",
             OutputFormat::Mp3(_) => "mp3",
             OutputFormat::Ogg(_) => "ogg",
+            OutputFormat::RawU8 => "u8",
+            OutputFormat::RawS16Le => "s16le",
+            OutputFormat::Wav => "wav",
         }
     }
     pub fn get_options(&self) -> AVOptions {
@@ -70,6 +89,9 @@ impl OutputFormat {
             OutputFormat::Flac(opts) => opts.get_options(),
             OutputFormat::Mp3(opts) => opts.get_options(),
             OutputFormat::Ogg(opts) => opts.get_options(),
+            OutputFormat::RawU8 | OutputFormat::RawS16Le | OutputFormat::Wav => {
+                AVOptions(HashMap::new())
+            }
         }
     }
 }
@@ -71,6 +71,15 @@ where
 
 pub struct BytesInput<S>(S);
 
+impl<S> BytesInput<S>
+where
+    S: AsRef<[u8]> + Send + Unpin + 'static,
+{
+    pub fn new(bytes: S) -> Self {
+        Self(bytes)
+    }
+}
+
 impl<S> Input for BytesInput<S>
 where
     S: AsRef<[u8]> + Send + Unpin + 'static,
@@ -0,0 +1,135 @@
+//! Inspecting audio files with ffprobe, e.g. to check uploaded takes before
+//! accepting them.
+
+use std::{ffi::OsStr, path::Path};
+
+use serde::Deserialize;
+
+use super::{ExternalTool, LineCollector, ProgressStream};
+use crate::{cancel::CancellationToken, path::LookupPath};
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+/// The properties of the first audio stream in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub duration: std::time::Duration,
+}
+
+impl AudioInfo {
+    fn from_probe_output(output: &str) -> anyhow::Result<Self> {
+        let output: ProbeOutput = serde_json::from_str(output)?;
+        let stream = output
+            .streams
+            .iter()
+            .find(|stream| stream.codec_type.as_deref() == Some("audio"))
+            .ok_or_else(|| anyhow::anyhow!("The file has no audio stream"))?;
+        let duration = output
+            .format
+            .and_then(|format| format.duration)
+            .and_then(|duration| duration.parse::<f64>().ok())
+            .filter(|duration| duration.is_finite() && *duration >= 0.0)
+            .ok_or_else(|| anyhow::anyhow!("ffprobe did not report the duration"))?;
+        Ok(AudioInfo {
+            codec: stream.codec_name.clone().unwrap_or_default(),
+            sample_rate: stream
+                .sample_rate
+                .as_deref()
+                .and_then(|rate| rate.parse().ok())
+                .unwrap_or(0),
+            channels: stream.channels.unwrap_or(0),
+            duration: std::time::Duration::from_secs_f64(duration),
+        })
+    }
+}
+
+pub struct FfprobeTool {
+    tool: ExternalTool,
+}
+
+impl FfprobeTool {
+    pub fn find(path: &LookupPath) -> Option<Self> {
+        ExternalTool::find(path, "ffprobe").map(|tool| FfprobeTool { tool })
+    }
+
+    /// Reads the properties of the audio in the file. Fails if the file
+    /// can't be decoded, or has no audio.
+    pub async fn probe_audio(
+        &self,
+        input: &Path,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<AudioInfo> {
+        let mut output = LineCollector::default();
+        self.tool
+            .run(
+                [
+                    OsStr::new("-v"),
+                    OsStr::new("error"),
+                    OsStr::new("-show_entries"),
+                    OsStr::new("stream=codec_type,codec_name,sample_rate,channels:format=duration"),
+                    OsStr::new("-of"),
+                    OsStr::new("json"),
+                    input.as_os_str(),
+                ],
+                Some((ProgressStream::Stdout, &mut output)),
+                futures::future::ready(()),
+                cancel,
+            )
+            .await?;
+        AudioInfo::from_probe_output(&output.0.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_output() -> anyhow::Result<()> {
+        let output = r#"{
+            "programs": [],
+            "streams": [
+                { "codec_type": "video", "codec_name": "png" },
+                { "codec_type": "audio", "codec_name": "pcm_s16le", "sample_rate": "44100", "channels": 1 }
+            ],
+            "format": { "duration": "2.500000" }
+        }"#;
+        assert_eq!(
+            AudioInfo::from_probe_output(output)?,
+            AudioInfo {
+                codec: "pcm_s16le".to_string(),
+                sample_rate: 44100,
+                channels: 1,
+                duration: std::time::Duration::from_millis(2500),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_audio_stream() {
+        let output = r#"{ "streams": [], "format": { "duration": "1.0" } }"#;
+        assert!(AudioInfo::from_probe_output(output).is_err());
+    }
+}
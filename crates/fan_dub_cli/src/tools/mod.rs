@@ -1,29 +1,253 @@
-use std::{ffi::OsStr, path::PathBuf};
+//! Wrappers around the external programs used to process audio.
+//!
+//! [`ExternalTool`] handles what all of them have in common: spawning the
+//! process, feeding its progress output to a [`ProgressParser`], and killing
+//! it on cancellation. The modules build tool-specific arguments on top.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use smol::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    path::LookupPath,
+};
 
 pub mod ffmpeg;
+pub mod ffprobe;
+pub mod rubberband;
+pub mod sox;
+pub mod speech;
+
+/// Receives the progress output of a tool, one line at a time.
+pub trait ProgressParser {
+    fn parse_line(&mut self, line: &str);
+}
 
-pub struct Tool {
+pub struct NullProgressParser;
+
+impl ProgressParser for NullProgressParser {
+    fn parse_line(&mut self, _line: &str) {}
+}
+
+/// Collects the lines of a tool's output, for tools whose output is the
+/// result rather than progress.
+#[derive(Debug, Default)]
+pub struct LineCollector(pub Vec<String>);
+
+impl ProgressParser for LineCollector {
+    fn parse_line(&mut self, line: &str) {
+        self.0.push(line.to_string());
+    }
+}
+
+/// Which of a tool's output streams carries its progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStream {
+    Stdout,
+    Stderr,
+}
+
+/// Arguments with `{name}` placeholders, filled in for each run.
+#[derive(Debug, Clone)]
+pub struct CommandTemplate {
+    args: Vec<String>,
+}
+
+impl CommandTemplate {
+    pub fn new(args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        CommandTemplate {
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Replaces each `{name}` in the arguments with its value. Unknown
+    /// placeholders are left as-is.
+    pub fn render(&self, values: &BTreeMap<&str, &OsStr>) -> Vec<OsString> {
+        self.args
+            .iter()
+            .map(|arg| {
+                let mut rendered = OsString::new();
+                let mut rest = arg.as_str();
+                while let Some(open) = rest.find('{') {
+                    let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+                        break;
+                    };
+                    match values.get(&rest[open + 1..close]) {
+                        Some(value) => {
+                            rendered.push(&rest[..open]);
+                            rendered.push(value);
+                        }
+                        None => rendered.push(&rest[..=close]),
+                    }
+                    rest = &rest[close + 1..];
+                }
+                rendered.push(rest);
+                rendered
+            })
+            .collect()
+    }
+}
+
+/// Feeds lines from the reader to the parser. Lines may end with either `\n`
+/// or `\r`, as tools that redraw a progress line use the latter.
+async fn read_progress_lines(
+    mut reader: impl AsyncRead + Unpin,
+    parser: &mut dyn ProgressParser,
+) -> std::io::Result<()> {
+    let mut buf = [0u8; 4096];
+    let mut line = Vec::new();
+    loop {
+        let len = reader.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        for &byte in &buf[..len] {
+            if byte == b'\n' || byte == b'\r' {
+                if !line.is_empty() {
+                    parser.parse_line(&String::from_utf8_lossy(&line));
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+    if !line.is_empty() {
+        parser.parse_line(&String::from_utf8_lossy(&line));
+    }
+    Ok(())
+}
+
+pub struct ExternalTool {
     binary_path: PathBuf,
 }
 
-impl Tool {
+impl ExternalTool {
     pub fn from_path(path: PathBuf) -> Self {
-        Tool { binary_path: path }
+        ExternalTool { binary_path: path }
+    }
+
+    /// Finds the tool's binary in the PATH.
+    pub fn find(path: &LookupPath, name: &str) -> Option<Self> {
+        path.find_binary(name)
+            .map(|binary_path| ExternalTool::from_path(binary_path.to_path_buf()))
+    }
+
+    pub fn binary_path(&self) -> &Path {
+        &self.binary_path
+    }
+
+    fn name(&self) -> String {
+        self.binary_path
+            .file_stem()
+            .unwrap_or(self.binary_path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
     }
 
-    pub fn run<'args>(
+    /// Runs the tool to completion.
+    ///
+    /// If `progress` is given, that stream is piped to the parser; other
+    /// output is passed through. `alongside` is polled while the tool runs
+    /// (e.g. to feed its input), and its result is returned. On cancellation
+    /// the process is killed and waited for, so it isn't left as a zombie.
+    pub async fn run<T>(
         &self,
-        args: impl IntoIterator<Item = &'args (impl AsRef<OsStr> + 'args)>,
-        envs: impl IntoIterator<
-            Item = (
-                &'args (impl AsRef<OsStr> + 'args),
-                &'args (impl AsRef<OsStr> + 'args),
-            ),
-        >,
-    ) -> anyhow::Result<()> {
-        let mut command = std::process::Command::new(&self.binary_path);
-        command.args(args);
-        command.envs(envs);
-        Ok(())
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+        progress: Option<(ProgressStream, &mut dyn ProgressParser)>,
+        alongside: impl Future<Output = T>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<T> {
+        let mut command = smol::process::Command::new(&self.binary_path);
+        command
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true);
+        match progress.as_ref().map(|(stream, _)| *stream) {
+            Some(ProgressStream::Stdout) => {
+                command.stdout(std::process::Stdio::piped());
+            }
+            Some(ProgressStream::Stderr) => {
+                command.stderr(std::process::Stdio::piped());
+            }
+            None => {}
+        }
+        let mut child = command.spawn()?;
+        let pipe: Option<Box<dyn AsyncRead + Unpin>> = match &progress {
+            Some((ProgressStream::Stdout, _)) => child
+                .stdout
+                .take()
+                .map(|pipe| Box::new(pipe) as Box<dyn AsyncRead + Unpin>),
+            Some((ProgressStream::Stderr, _)) => child
+                .stderr
+                .take()
+                .map(|pipe| Box::new(pipe) as Box<dyn AsyncRead + Unpin>),
+            None => None,
+        };
+        let read_progress = async move {
+            if let (Some(pipe), Some((_, parser))) = (pipe, progress) {
+                read_progress_lines(pipe, parser).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        };
+        let run = futures::future::join3(child.status(), alongside, read_progress);
+        let (status, result, progress_result) = match cancel.run_until_cancelled(run).await {
+            Ok(results) => results,
+            Err(Cancelled) => {
+                let _ = child.kill();
+                let _ = child.status().await;
+                return Err(Cancelled.into());
+            }
+        };
+        let status = status?;
+        progress_result?;
+        anyhow::ensure!(
+            status.success(),
+            "{} process exited with non-zero status: {}",
+            self.name(),
+            status
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template = CommandTemplate::new(["-i", "{input}", "--out={output}", "{unknown}", "{"]);
+        let values = BTreeMap::from([
+            ("input", OsStr::new("in.wav")),
+            ("output", OsStr::new("out.wav")),
+        ]);
+        assert_eq!(
+            template.render(&values),
+            vec!["-i", "in.wav", "--out=out.wav", "{unknown}", "{"]
+        );
+    }
+
+    #[test]
+    fn test_progress_lines_split_on_carriage_returns() {
+        struct Collect(Vec<String>);
+        impl ProgressParser for Collect {
+            fn parse_line(&mut self, line: &str) {
+                self.0.push(line.to_string());
+            }
+        }
+        let mut collect = Collect(Vec::new());
+        smol::block_on(read_progress_lines(
+            smol::io::Cursor::new(b"In:10%\rIn:50%\r\nDone\nlast".to_vec()),
+            &mut collect,
+        ))
+        .unwrap();
+        assert_eq!(collect.0, vec!["In:10%", "In:50%", "Done", "last"]);
     }
 }
@@ -0,0 +1,81 @@
+//! Time-stretching and pitch-shifting with the Rubber Band command-line
+//! utility.
+
+use std::{collections::BTreeMap, path::Path};
+
+use super::{CommandTemplate, ExternalTool};
+use crate::{cancel::CancellationToken, path::LookupPath};
+
+/// How to stretch a clip. With no options set, the clip is copied unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct StretchOptions {
+    /// The ratio of output length to input length (e.g. 1.1 makes the clip
+    /// 10% longer).
+    pub time_ratio: Option<f64>,
+    /// The pitch shift, in semitones.
+    pub pitch_semitones: Option<f64>,
+}
+
+impl StretchOptions {
+    fn to_template(&self) -> CommandTemplate {
+        let mut args = vec!["-q".to_string()];
+        if let Some(time_ratio) = self.time_ratio {
+            args.extend(["-t".to_string(), time_ratio.to_string()]);
+        }
+        if let Some(pitch_semitones) = self.pitch_semitones {
+            args.extend(["-p".to_string(), pitch_semitones.to_string()]);
+        }
+        args.extend(["{input}".to_string(), "{output}".to_string()]);
+        CommandTemplate::new(args)
+    }
+}
+
+pub struct RubberbandTool {
+    tool: ExternalTool,
+}
+
+impl RubberbandTool {
+    pub fn find(path: &LookupPath) -> Option<Self> {
+        ExternalTool::find(path, "rubberband").map(|tool| RubberbandTool { tool })
+    }
+
+    /// Stretches a WAV file into a new WAV file.
+    pub async fn stretch(
+        &self,
+        input: &Path,
+        output: &Path,
+        options: &StretchOptions,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let args = options.to_template().render(&BTreeMap::from([
+            ("input", input.as_os_str()),
+            ("output", output.as_os_str()),
+        ]));
+        self.tool
+            .run(args, None, futures::future::ready(()), cancel)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn test_stretch_args() {
+        let options = StretchOptions {
+            time_ratio: Some(1.25),
+            pitch_semitones: Some(-2.0),
+        };
+        let args = options.to_template().render(&BTreeMap::from([
+            ("input", OsStr::new("in.wav")),
+            ("output", OsStr::new("out.wav")),
+        ]));
+        assert_eq!(
+            args,
+            vec!["-q", "-t", "1.25", "-p", "-2", "in.wav", "out.wav"]
+        );
+    }
+}
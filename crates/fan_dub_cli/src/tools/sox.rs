@@ -0,0 +1,176 @@
+//! Effects processing (e.g. EQ) with SoX.
+
+use std::{collections::BTreeMap, path::Path};
+
+use super::{CommandTemplate, ExternalTool, ProgressParser, ProgressStream};
+use crate::{cancel::CancellationToken, path::LookupPath};
+
+/// An effect in a SoX effects chain.
+#[derive(Debug, Clone)]
+pub enum SoxEffect {
+    /// A peaking EQ band around a frequency, with the width given as a Q
+    /// factor.
+    Equalizer {
+        frequency_hz: f64,
+        width_q: f64,
+        gain_db: f64,
+    },
+    /// A low-shelf boost or cut.
+    Bass {
+        gain_db: f64,
+    },
+    /// A high-shelf boost or cut.
+    Treble {
+        gain_db: f64,
+    },
+    HighPass {
+        frequency_hz: f64,
+    },
+    LowPass {
+        frequency_hz: f64,
+    },
+    /// Changes the tempo without changing the pitch.
+    Tempo {
+        factor: f64,
+    },
+}
+
+impl SoxEffect {
+    fn args(&self) -> Vec<String> {
+        match self {
+            SoxEffect::Equalizer {
+                frequency_hz,
+                width_q,
+                gain_db,
+            } => vec![
+                "equalizer".to_string(),
+                frequency_hz.to_string(),
+                format!("{width_q}q"),
+                gain_db.to_string(),
+            ],
+            SoxEffect::Bass { gain_db } => vec!["bass".to_string(), gain_db.to_string()],
+            SoxEffect::Treble { gain_db } => vec!["treble".to_string(), gain_db.to_string()],
+            SoxEffect::HighPass { frequency_hz } => {
+                vec!["highpass".to_string(), frequency_hz.to_string()]
+            }
+            SoxEffect::LowPass { frequency_hz } => {
+                vec!["lowpass".to_string(), frequency_hz.to_string()]
+            }
+            SoxEffect::Tempo { factor } => vec!["tempo".to_string(), factor.to_string()],
+        }
+    }
+}
+
+fn effects_template(effects: &[SoxEffect]) -> CommandTemplate {
+    let mut args = vec![
+        "--show-progress".to_string(),
+        "{input}".to_string(),
+        "{output}".to_string(),
+    ];
+    args.extend(effects.iter().flat_map(SoxEffect::args));
+    CommandTemplate::new(args)
+}
+
+/// Parses the completion percentage from SoX's progress line (e.g.
+/// `In:42.5% 00:00:01.20 [00:00:01.63] Out:52.9k ...`).
+struct PercentParser<'a> {
+    on_progress: &'a mut dyn FnMut(f64),
+}
+
+impl ProgressParser for PercentParser<'_> {
+    fn parse_line(&mut self, line: &str) {
+        let Some(rest) = line.trim_start().strip_prefix("In:") else {
+            return;
+        };
+        if let Some((percent, _)) = rest.split_once('%')
+            && let Ok(percent) = percent.trim().parse::<f64>()
+        {
+            (self.on_progress)(percent);
+        }
+    }
+}
+
+pub struct SoxTool {
+    tool: ExternalTool,
+}
+
+impl SoxTool {
+    pub fn find(path: &LookupPath) -> Option<Self> {
+        ExternalTool::find(path, "sox").map(|tool| SoxTool { tool })
+    }
+
+    /// Applies the effects, in order, writing the result to the output file.
+    /// The output format is picked from the output file's extension.
+    /// `on_progress` is called with the completion percentage.
+    pub async fn apply_effects(
+        &self,
+        input: &Path,
+        output: &Path,
+        effects: &[SoxEffect],
+        on_progress: &mut dyn FnMut(f64),
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let args = effects_template(effects).render(&BTreeMap::from([
+            ("input", input.as_os_str()),
+            ("output", output.as_os_str()),
+        ]));
+        let mut parser = PercentParser { on_progress };
+        self.tool
+            .run(
+                args,
+                Some((ProgressStream::Stderr, &mut parser)),
+                futures::future::ready(()),
+                cancel,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn test_effects_args() {
+        let args = effects_template(&[
+            SoxEffect::HighPass { frequency_hz: 80.0 },
+            SoxEffect::Equalizer {
+                frequency_hz: 3000.0,
+                width_q: 1.5,
+                gain_db: -2.5,
+            },
+        ])
+        .render(&BTreeMap::from([
+            ("input", OsStr::new("in.wav")),
+            ("output", OsStr::new("out.wav")),
+        ]));
+        assert_eq!(
+            args,
+            vec![
+                "--show-progress",
+                "in.wav",
+                "out.wav",
+                "highpass",
+                "80",
+                "equalizer",
+                "3000",
+                "1.5q",
+                "-2.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_progress() {
+        let mut seen = Vec::new();
+        let mut on_progress = |percent| seen.push(percent);
+        let mut parser = PercentParser {
+            on_progress: &mut on_progress,
+        };
+        parser.parse_line("In:42.50% 00:00:01.20 [00:00:01.63] Out:52.9k [ -====|====- ]");
+        parser.parse_line("Done.");
+        assert_eq!(seen, vec![42.5]);
+    }
+}
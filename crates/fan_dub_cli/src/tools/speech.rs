@@ -0,0 +1,55 @@
+//! Text-to-speech, used to announce markers in rendered review audio.
+
+use std::{ffi::OsString, path::Path};
+
+use super::ExternalTool;
+use crate::{cancel::CancellationToken, path::LookupPath};
+
+enum SpeechEngine {
+    /// eSpeak or eSpeak NG, available on most platforms.
+    Espeak,
+    /// The `say` command on macOS.
+    Say,
+}
+
+pub struct SpeechTool {
+    engine: SpeechEngine,
+    tool: ExternalTool,
+}
+
+impl SpeechTool {
+    /// Finds a supported text-to-speech program in the PATH.
+    pub fn find(path: &LookupPath) -> Option<Self> {
+        [
+            ("espeak-ng", SpeechEngine::Espeak),
+            ("espeak", SpeechEngine::Espeak),
+            ("say", SpeechEngine::Say),
+        ]
+        .into_iter()
+        .find_map(|(name, engine)| {
+            ExternalTool::find(path, name).map(|tool| SpeechTool { engine, tool })
+        })
+    }
+
+    /// Speaks the text into a WAV file.
+    pub async fn speak_to_file(
+        &self,
+        text: &str,
+        output: &Path,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let args: Vec<OsString> = match self.engine {
+            SpeechEngine::Espeak => vec!["-w".into(), output.into(), text.into()],
+            SpeechEngine::Say => vec![
+                "--file-format=WAVE".into(),
+                "--data-format=LEI16@22050".into(),
+                "-o".into(),
+                output.into(),
+                text.into(),
+            ],
+        };
+        self.tool
+            .run(args, None, futures::future::ready(()), cancel)
+            .await
+    }
+}
@@ -0,0 +1,111 @@
+//! Checks that a take says the line it was recorded for, by transcribing it
+//! with an external speech-to-text program (e.g. whisper.cpp) and comparing
+//! the words.
+
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{
+    cancel::CancellationToken,
+    tools::{CommandTemplate, ExternalTool, LineCollector, ProgressStream},
+};
+
+/// Runs a speech-to-text program. The program is given as a command, where
+/// `{input}` in the arguments is replaced with the path of the audio file; it
+/// must print the transcript to stdout.
+pub struct Transcriber {
+    tool: ExternalTool,
+    args: CommandTemplate,
+}
+
+impl Transcriber {
+    pub fn new(command: &[String]) -> anyhow::Result<Self> {
+        let Some((program, args)) = command.split_first() else {
+            anyhow::bail!("A transcriber needs a command to run");
+        };
+        Ok(Transcriber {
+            tool: ExternalTool::from_path(program.into()),
+            args: CommandTemplate::new(args.iter().cloned()),
+        })
+    }
+
+    pub async fn transcribe(
+        &self,
+        input: &Path,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let args = self
+            .args
+            .render(&BTreeMap::from([("input", input.as_os_str())]));
+        let mut output = LineCollector::default();
+        self.tool
+            .run(
+                args,
+                Some((ProgressStream::Stdout, &mut output)),
+                futures::future::ready(()),
+                cancel,
+            )
+            .await?;
+        Ok(output.0.join(" "))
+    }
+}
+
+/// Splits text into lowercase words, ignoring punctuation.
+fn words(text: &str) -> Vec<String> {
+    text.split(|ch: char| !ch.is_alphanumeric() && ch != '\'')
+        .map(|word| word.trim_matches('\'').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// How closely the transcript matches the expected text, from 0 (nothing in
+/// common) to 1 (the same words in the same order). Based on the longest
+/// common subsequence of words, so a few misheard words only lower the score
+/// a little.
+pub fn transcript_similarity(expected: &str, transcript: &str) -> f64 {
+    let expected = words(expected);
+    let transcript = words(transcript);
+    if expected.is_empty() && transcript.is_empty() {
+        return 1.0;
+    }
+    let mut lengths = vec![0usize; transcript.len() + 1];
+    for expected_word in &expected {
+        let mut diagonal = 0;
+        for (i, transcript_word) in transcript.iter().enumerate() {
+            let above = lengths[i + 1];
+            lengths[i + 1] = if expected_word == transcript_word {
+                diagonal + 1
+            } else {
+                above.max(lengths[i])
+            };
+            diagonal = above;
+        }
+    }
+    let common = lengths[transcript.len()];
+    2.0 * common as f64 / (expected.len() + transcript.len()) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_text_ignoring_case_and_punctuation() {
+        assert_eq!(
+            transcript_similarity("Don't touch that!", "don't touch that"),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_misheard_word() {
+        let similarity =
+            transcript_similarity("The castle gate is locked.", "the cast gate is locked");
+        assert!((similarity - 0.8).abs() < 1e-9, "{similarity}");
+    }
+
+    #[test]
+    fn test_unrelated_text() {
+        assert_eq!(transcript_similarity("Hello there.", "goodbye"), 0.0);
+        assert_eq!(transcript_similarity("Hello there.", ""), 0.0);
+    }
+}
@@ -0,0 +1,96 @@
+//! Parser benchmarks over a synthetic game (see `sci-corpus`).
+//!
+//! Save a baseline with `cargo bench -p sci-resources -- --save-baseline main`
+//! and compare a change against it with `-- --baseline main`.
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use sci_corpus::{Corpus, CorpusConfig, Rng, script_like_data};
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::{map::ResourceLocations, open_game_resources},
+    types::msg::parse_message_resource,
+};
+use sci_utils::{
+    block::{BlockReader, MemBlock},
+    compression::dcl::{compress_dcl, decompress_dcl},
+};
+
+fn bench_map_parse(c: &mut Criterion) {
+    let corpus = Corpus::generate(&CorpusConfig {
+        resources_per_type: 1000,
+        resource_size: 64,
+        ..CorpusConfig::default()
+    })
+    .unwrap();
+    let map = MemBlock::from_vec(corpus.file("RESOURCE.MAP").unwrap().to_vec());
+    let mut group = c.benchmark_group("map");
+    group.throughput(Throughput::Bytes(map.size() as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| ResourceLocations::read_from(BlockReader::new(map.clone())).unwrap());
+    });
+    group.finish();
+}
+
+fn bench_dcl_decompress(c: &mut Criterion) {
+    let data = script_like_data(&mut Rng::new(1), 64 * 1024);
+    let compressed = MemBlock::from_vec(compress_dcl(&data));
+    let mut group = c.benchmark_group("dcl");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("decompress", |b| {
+        b.iter(|| decompress_dcl(&compressed).unwrap());
+    });
+    group.finish();
+}
+
+fn bench_message_parse(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    Corpus::generate(&CorpusConfig {
+        rooms: 1,
+        nouns_per_room: 200,
+        ..CorpusConfig::default()
+    })
+    .unwrap()
+    .write_to(dir.path())
+    .unwrap();
+    let data = open_game_resources(dir.path())
+        .unwrap()
+        .get_resource(&ResourceId::new(ResourceType::Message, 0))
+        .unwrap()
+        .load_data()
+        .unwrap();
+    let mut group = c.benchmark_group("message");
+    group.throughput(Throughput::Bytes(data.size() as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| parse_message_resource(data.clone()).unwrap());
+    });
+    group.finish();
+}
+
+fn bench_load_all(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    Corpus::generate(&CorpusConfig::default())
+        .unwrap()
+        .write_to(dir.path())
+        .unwrap();
+    c.bench_function("game/open_and_load_all", |b| {
+        b.iter_batched(
+            || (),
+            |()| {
+                let resources = open_game_resources(dir.path()).unwrap();
+                for id in resources.resource_ids() {
+                    resources.get_resource(&id).unwrap().load_data().unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        );
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_map_parse,
+    bench_dcl_decompress,
+    bench_message_parse,
+    bench_load_all
+);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sci_resources::types::audio36::Audio36Map;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Audio36Map::parse_from_bytes(data);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sci_resources::file::data::{Contents, RawContents};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = RawContents::parse_from_bytes(data)
+        && let Ok(contents) = Contents::try_from(raw)
+    {
+        let _ = contents.data().open();
+    }
+});
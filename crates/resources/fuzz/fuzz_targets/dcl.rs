@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sci_utils::{block::MemBlock, compression::dcl::decompress_dcl};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decompress_dcl(&MemBlock::from_vec(data.to_vec()));
+});
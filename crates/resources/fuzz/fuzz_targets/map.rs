@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sci_resources::file::map::ResourceLocations;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(locations) = ResourceLocations::parse_from_bytes(data) {
+        for location in locations.locations() {
+            let _ = locations.get_location(&location.id);
+        }
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sci_resources::types::msg::RoomMessageSet;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(messages) = RoomMessageSet::parse_from_bytes(data) {
+        let _ = messages.to_bytes(None);
+    }
+});
@@ -1,49 +1,211 @@
 use std::{
     collections::{BTreeMap, btree_map},
+    ffi::OsString,
     fs::File,
     io,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use data::DataFile;
+pub use data::RawContents;
+use map::MapFormat;
 
+pub(crate) use patch::patch_header;
 use patch::try_patch_from_file;
+#[cfg(feature = "audio")]
+pub(crate) use patch::{EXTENDED_HEADER_MARKER, EXTENDED_HEADER_SIZE};
 use sci_utils::block::{BlockReader, BlockSource, LazyBlock, MemBlock};
 
-use super::{ResourceId, ResourceType};
+use super::{ParseOptions, ResourceId, ResourceType};
 
 use futures::io::AsyncWriteExt;
 
-mod data;
-mod map;
+pub mod compact;
+pub mod data;
+pub mod mac;
+pub mod map;
 mod patch;
 
 pub fn read_resources(
     map_file: &Path,
     data_file: &Path,
     patches: &[Resource],
+) -> io::Result<ResourceSet> {
+    read_resources_with_options(map_file, data_file, patches, &ParseOptions::default())
+}
+
+pub fn read_resources_with_options(
+    map_file: &Path,
+    data_file: &Path,
+    patches: &[Resource],
+    options: &ParseOptions,
+) -> io::Result<ResourceSet> {
+    // Volumes after the first are beside it, numbered by their extensions.
+    let volume_path = |volume: u8| {
+        if volume == 0 {
+            return Some(data_file.to_path_buf());
+        }
+        let path = data_file.with_extension(format!("{volume:03}"));
+        path.exists().then_some(path)
+    };
+    read_resource_volumes(map_file, volume_path, patches, options)
+}
+
+/// Reads the resources of a map whose volumes are in the given files, by
+/// volume number.
+pub fn read_resources_from_volumes(
+    map_file: &Path,
+    volumes: &BTreeMap<u8, PathBuf>,
+    patches: &[Resource],
+) -> io::Result<ResourceSet> {
+    read_resource_volumes(
+        map_file,
+        |volume| volumes.get(&volume).cloned(),
+        patches,
+        &ParseOptions::default(),
+    )
+}
+
+/// The names of the files in a game's directory, with uppercase names
+/// sorting first.
+fn game_file_names(root_dir: &Path) -> io::Result<Vec<OsString>> {
+    let mut names = Vec::new();
+    for entry in root_dir.read_dir()? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            names.push(entry.file_name());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Finds a file of a game, such as `RESOURCE.MAP`, in its directory. The
+/// name is matched regardless of case, as with [`find_volume_files`].
+pub fn find_game_file(root_dir: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    Ok(game_file_names(root_dir)?
+        .into_iter()
+        .find(|file_name| {
+            file_name
+                .to_str()
+                .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+        })
+        .map(|file_name| root_dir.join(file_name)))
+}
+
+/// Finds the numbered volume files of a game (`RESOURCE.000`,
+/// `RESOURCE.001`, ...) in its directory, by volume number. Names are
+/// matched regardless of case, as copies of the game's discs are often
+/// lowercased; if two names differ only in case, the uppercase one is used.
+pub fn find_volume_files(root_dir: &Path) -> io::Result<BTreeMap<u8, PathBuf>> {
+    let mut volumes = BTreeMap::new();
+    for name in game_file_names(root_dir)? {
+        let Some(volume) = name.to_str().and_then(volume_number) else {
+            continue;
+        };
+        volumes
+            .entry(volume)
+            .or_insert_with(|| root_dir.join(&name));
+    }
+    Ok(volumes)
+}
+
+/// The number of a volume file named like `RESOURCE.001`, in any case.
+fn volume_number(name: &str) -> Option<u8> {
+    let (stem, extension) = name.split_once('.')?;
+    if !stem.eq_ignore_ascii_case("RESOURCE")
+        || extension.len() != 3
+        || !extension.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    extension.parse().ok()
+}
+
+/// Reads the resources of a map, finding each volume's file with
+/// `volume_path`. Resources in volumes without a file are reported as out of
+/// bounds.
+fn read_resource_volumes(
+    map_file: &Path,
+    volume_path: impl Fn(u8) -> Option<PathBuf>,
+    patches: &[Resource],
+    options: &ParseOptions,
 ) -> io::Result<ResourceSet> {
     let map_file = MemBlock::from_reader(File::open(map_file)?)?;
-    let data_file = DataFile::new(BlockSource::from_path(data_file.to_path_buf())?);
-    let resource_locations = map::ResourceLocations::read_from(BlockReader::new(map_file))?;
+    let resource_locations =
+        map::ResourceLocations::read_from_with_options(BlockReader::new(map_file), options)?;
+    let mut format = resource_locations.format();
+    // An SCI2 map reads as an SCI1 map in a single volume; the volume tells
+    // them apart.
+    if format == MapFormat::Sci1
+        && resource_locations
+            .live_locations()
+            .all(|location| location.volume == 0)
+        && let Some(first) = resource_locations.live_locations().next()
+        && let Some(data_file) = volume_path(0)
+    {
+        let data = BlockSource::from_path(data_file)?;
+        format = DataFile::detect_format(&data, &first);
+    }
 
+    // SCI0 and SCI1 games spread their resources over numbered volumes, opened as
+    // they're first needed.
+    let mut volumes: BTreeMap<u8, Option<(PathBuf, DataFile)>> = BTreeMap::new();
     let mut entries = BTreeMap::new();
 
-    for location in resource_locations.locations() {
-        let block = data_file.read_contents(&location)?;
+    for location in resource_locations.live_locations() {
+        let volume = match volumes.entry(location.volume) {
+            btree_map::Entry::Occupied(occ) => occ.into_mut(),
+            btree_map::Entry::Vacant(vac) => {
+                let volume = match volume_path(location.volume) {
+                    Some(path) => {
+                        let data = BlockSource::from_path(path.clone())?;
+                        Some((path, DataFile::new(data).with_format(format)))
+                    }
+                    None => None,
+                };
+                vac.insert(volume)
+            }
+        };
+        let Some((volume_path, data_file)) = volume else {
+            options.out_of_bounds(format!(
+                "{:?}: volume {} is missing",
+                location.id, location.volume
+            ))?;
+            continue;
+        };
+        let raw_contents = match data_file.read_raw_contents(&location) {
+            Ok(raw_contents) => raw_contents,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                options.out_of_bounds(format!("{:?}: {e}", location.id))?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let raw = RawResource {
+            id: location.id,
+            volume: volume_path.clone(),
+            offset: location.file_offset,
+            contents: raw_contents.clone(),
+        };
+        let block = match data::Contents::try_from(raw_contents) {
+            Ok(block) => block,
+            Err(e) => {
+                options.unknown(format!("{:?}: {e}", location.id))?;
+                continue;
+            }
+        };
         if block.id() != &location.id {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Resource ID mismatch: expected {:?}, got {:?}",
-                    location.id,
-                    block.id()
-                ),
-            ));
+            options.unknown(format!(
+                "Resource ID mismatch: expected {:?}, got {:?}",
+                location.id,
+                block.id()
+            ))?;
+            continue;
         }
         entries.insert(
             location.id,
-            ResourceBlocks::new_of_data(block.data().clone()),
+            ResourceBlocks::new_of_data(block.data().clone(), Some(raw)),
         );
     }
 
@@ -57,12 +219,52 @@ pub fn read_resources(
         }
     }
 
-    Ok(ResourceSet { entries })
+    Ok(ResourceSet {
+        entries,
+        map_format: Some(format),
+    })
+}
+
+/// Reads every entry of a resource map file, including those the game
+/// doesn't use.
+pub fn read_resource_map(map_file: &Path) -> io::Result<map::ResourceLocations> {
+    let map_file = MemBlock::from_reader(File::open(map_file)?)?;
+    map::ResourceLocations::read_from(BlockReader::new(map_file))
+}
+
+/// A resource as stored in a volume file, before decompression.
+#[derive(Clone)]
+pub struct RawResource {
+    id: ResourceId,
+    volume: PathBuf,
+    offset: u32,
+    contents: RawContents,
+}
+
+impl RawResource {
+    pub fn id(&self) -> &ResourceId {
+        &self.id
+    }
+
+    /// The path of the volume file containing the resource.
+    pub fn volume(&self) -> &Path {
+        &self.volume
+    }
+
+    /// The offset of the resource's entry header in the volume file.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn contents(&self) -> &RawContents {
+        &self.contents
+    }
 }
 
 #[derive(Clone)]
 struct ResourceBlocks {
     data_block: Option<LazyBlock>,
+    raw: Option<RawResource>,
     patch_block: Option<LazyBlock>,
 }
 
@@ -77,13 +279,15 @@ impl ResourceBlocks {
     pub fn new_of_patch(patch_block: LazyBlock) -> ResourceBlocks {
         ResourceBlocks {
             data_block: None,
+            raw: None,
             patch_block: Some(patch_block),
         }
     }
 
-    pub fn new_of_data(data_block: LazyBlock) -> ResourceBlocks {
+    pub fn new_of_data(data_block: LazyBlock, raw: Option<RawResource>) -> ResourceBlocks {
         ResourceBlocks {
             data_block: Some(data_block),
+            raw,
             patch_block: None,
         }
     }
@@ -99,9 +303,16 @@ impl ResourceBlocks {
 
 pub struct ResourceSet {
     entries: BTreeMap<ResourceId, ResourceBlocks>,
+    map_format: Option<MapFormat>,
 }
 
 impl ResourceSet {
+    /// The layout of the resource map and volumes the resources were read
+    /// from, or `None` if they weren't read from a map (e.g. a Mac port's).
+    pub fn map_format(&self) -> Option<MapFormat> {
+        self.map_format
+    }
+
     pub fn get_resource(&self, id: &ResourceId) -> Option<Resource> {
         self.entries.get(id).map(|b| Resource {
             id: *id,
@@ -109,6 +320,12 @@ impl ResourceSet {
         })
     }
 
+    /// Returns the resource as stored in its volume file, or None if the
+    /// resource is not in a volume (e.g. it only exists as a patch file).
+    pub fn get_raw_resource(&self, id: &ResourceId) -> Option<RawResource> {
+        self.entries.get(id).and_then(|b| b.raw.clone())
+    }
+
     pub fn resource_ids(&self) -> impl Iterator<Item = ResourceId> + '_ {
         self.entries.keys().copied()
     }
@@ -137,7 +354,10 @@ impl ResourceSet {
         for (id, block) in overlay.entries.iter() {
             entries.insert(*id, block.clone());
         }
-        ResourceSet { entries }
+        ResourceSet {
+            entries,
+            map_format: self.map_format.or(overlay.map_format),
+        }
     }
 
     pub fn merge(&self, other: &ResourceSet) -> io::Result<ResourceSet> {
@@ -155,31 +375,115 @@ impl ResourceSet {
                 }
             }
         }
-        Ok(ResourceSet { entries })
+        Ok(ResourceSet {
+            entries,
+            map_format: self.map_format.or(other.map_format),
+        })
     }
 }
 
-pub fn open_game_resources(root_dir: &Path) -> anyhow::Result<ResourceSet> {
+/// Reads the SCI resources in a Mac resource file. Resources with tags that
+/// SCI doesn't use are skipped.
+pub fn read_mac_resources(path: &Path) -> anyhow::Result<ResourceSet> {
+    let fork = mac::open_resource_fork(path)?
+        .ok_or_else(|| anyhow::anyhow!("No resource fork found in {:?}", path))?;
+    let mut entries = BTreeMap::new();
+    for resource in mac::read_resource_fork(&fork)? {
+        let Some(id) = resource.resource_id() else {
+            continue;
+        };
+        entries.insert(
+            id,
+            ResourceBlocks::new_of_data(resource.data().to_lazy_block(), None),
+        );
+    }
+    Ok(ResourceSet {
+        entries,
+        map_format: None,
+    })
+}
+
+fn read_patch_files(root_dir: &Path, options: &ParseOptions) -> anyhow::Result<Vec<Resource>> {
     let mut patches = Vec::new();
     for entry in root_dir.read_dir()? {
         let entry = entry?;
-        if entry.file_type()?.is_file() {
-            if let Some(patch_res) = try_patch_from_file(&entry.path())? {
-                patches.push(patch_res);
-            }
+        if entry.file_type()?.is_file()
+            && let Some(patch_res) = try_patch_from_file(&entry.path(), options)?
+        {
+            patches.push(patch_res);
+        }
+    }
+    Ok(patches)
+}
+
+/// Reads the resources of a Mac port from its resource files, in order.
+/// Resources in later files replace those in earlier ones, and patch files
+/// replace both.
+fn read_mac_game_resources(
+    resource_files: &[PathBuf],
+    patches: &[Resource],
+) -> anyhow::Result<ResourceSet> {
+    let mut resources = ResourceSet {
+        entries: BTreeMap::new(),
+        map_format: None,
+    };
+    for path in resource_files {
+        resources = resources.with_overlay(&read_mac_resources(path)?);
+    }
+    for patch in patches {
+        resources.entries.insert(
+            *patch.id(),
+            ResourceBlocks::new_of_patch(patch.source.clone()),
+        );
+    }
+    Ok(resources)
+}
+
+/// Opens the resources of a game. The path is usually the game directory
+/// (of either a PC or Mac release), but may also be a single Mac resource
+/// file.
+pub fn open_game_resources(root_dir: &Path) -> anyhow::Result<ResourceSet> {
+    open_game_resources_with_options(root_dir, &ParseOptions::default())
+}
+
+/// Opens the resources of a game, as [`open_game_resources`], handling
+/// malformed data as the options say.
+pub fn open_game_resources_with_options(
+    root_dir: &Path,
+    options: &ParseOptions,
+) -> anyhow::Result<ResourceSet> {
+    if root_dir.is_file() {
+        return read_mac_resources(root_dir);
+    }
+    let patches = read_patch_files(root_dir, options)?;
+
+    let map_file = find_game_file(root_dir, "RESOURCE.MAP")?;
+    if map_file.is_none() {
+        let mac_files = mac::find_game_resource_files(root_dir)?;
+        if !mac_files.is_empty() {
+            return read_mac_game_resources(&mac_files, &patches);
         }
     }
 
     let main_set = {
-        let map_file = root_dir.join("RESOURCE.MAP");
-        let data_file = root_dir.join("RESOURCE.000");
-        read_resources(&map_file, &data_file, &patches)?
+        let map_file = map_file.unwrap_or_else(|| root_dir.join("RESOURCE.MAP"));
+        let volumes = find_volume_files(root_dir)?;
+        read_resource_volumes(
+            &map_file,
+            |volume| volumes.get(&volume).cloned(),
+            &patches,
+            options,
+        )?
     };
 
+    // Games before SCI1.1 keep their text in the main volumes.
+    let Some(map_file) = find_game_file(root_dir, "MESSAGE.MAP")? else {
+        return Ok(main_set);
+    };
     let message_set = {
-        let map_file = root_dir.join("MESSAGE.MAP");
-        let data_file = root_dir.join("RESOURCE.MSG");
-        read_resources(&map_file, &data_file, &[])?
+        let data_file = find_game_file(root_dir, "RESOURCE.MSG")?
+            .unwrap_or_else(|| root_dir.join("RESOURCE.MSG"));
+        read_resources_with_options(&map_file, &data_file, &[], options)?
     };
     Ok(main_set.merge(&message_set)?)
 }
@@ -204,12 +508,129 @@ impl Resource {
         Ok(self.source.open()?)
     }
 
+    /// Returns the file name of a patch file for this resource, or None if
+    /// resources of this type can't be patched by number.
+    pub fn patch_file_name(&self) -> Option<String> {
+        let ext = self.id.type_id().to_file_ext();
+        if ext.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{}.{}",
+            self.id.resource_num(),
+            ext.to_ascii_uppercase()
+        ))
+    }
+
     pub async fn write_patch<W: futures::io::AsyncWrite + Unpin>(
         &self,
         mut writer: W,
     ) -> anyhow::Result<()> {
-        writer.write_all(&[self.id.type_id().into(), 0]).await?;
+        writer.write_all(&patch_header(self.id.type_id())).await?;
         writer.write_all(&self.source.open()?).await?;
         Ok(())
     }
+
+    pub fn write_patch_sync<W: io::Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        writer.write_all(&patch_header(self.id.type_id()))?;
+        writer.write_all(&self.source.open()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A SCI0 volume entry: the ID, the sizes and the uncompressed data.
+    fn sci0_entry(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = id.to_le_bytes().to_vec();
+        entry.extend_from_slice(&(data.len() as u16 + 4).to_le_bytes());
+        entry.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&[0, 0]);
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    #[test]
+    fn test_find_volume_files() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in [
+            "RESOURCE.000",
+            "resource.002",
+            "RESOURCE.MAP",
+            "RESOURCE.MSG",
+            "RESOURCE.1",
+            "RESOURCE.0001",
+        ] {
+            std::fs::write(dir.path().join(name), [])?;
+        }
+        let volumes = find_volume_files(dir.path())?;
+        assert_eq!(
+            volumes,
+            BTreeMap::from([
+                (0, dir.path().join("RESOURCE.000")),
+                (2, dir.path().join("resource.002")),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_game_file() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["resource.map", "Message.Map"] {
+            std::fs::write(dir.path().join(name), [])?;
+        }
+        assert_eq!(
+            find_game_file(dir.path(), "RESOURCE.MAP")?,
+            Some(dir.path().join("resource.map"))
+        );
+        assert_eq!(
+            find_game_file(dir.path(), "message.map")?,
+            Some(dir.path().join("Message.Map"))
+        );
+        assert_eq!(find_game_file(dir.path(), "RESOURCE.MSG")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_multiple_volumes() -> anyhow::Result<()> {
+        // A copy of the game with every name lowercased.
+        let dir = tempfile::tempdir()?;
+        // View 5 in volume 0, and script 3 in volume 1, at the same offset.
+        let mut map = Vec::new();
+        for (id, location) in [(0x0005u16, 0u32), (0x1003, 1 << 26)] {
+            map.extend_from_slice(&id.to_le_bytes());
+            map.extend_from_slice(&location.to_le_bytes());
+        }
+        map.extend_from_slice(&[0xFF; 6]);
+        std::fs::write(dir.path().join("resource.map"), map)?;
+        std::fs::write(dir.path().join("resource.000"), sci0_entry(0x0005, b"view"))?;
+        std::fs::write(
+            dir.path().join("resource.001"),
+            sci0_entry(0x1003, b"script"),
+        )?;
+
+        let resources = open_game_resources(dir.path())?;
+        let script = ResourceId::new(ResourceType::Script, 3);
+        let view = ResourceId::new(ResourceType::View, 5);
+        assert_eq!(
+            &resources.get_resource(&script).unwrap().load_data()?[..],
+            b"script"
+        );
+        assert_eq!(
+            &resources.get_resource(&view).unwrap().load_data()?[..],
+            b"view"
+        );
+        assert_eq!(
+            resources.get_raw_resource(&script).unwrap().volume(),
+            dir.path().join("resource.001")
+        );
+        assert_eq!(
+            resources.get_raw_resource(&view).unwrap().volume(),
+            dir.path().join("resource.000")
+        );
+        Ok(())
+    }
 }
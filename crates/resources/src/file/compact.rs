@@ -0,0 +1,494 @@
+//! Rewriting volumes without the space that their map no longer uses.
+//!
+//! When a resource is replaced by appending a new entry to a volume, the old
+//! entry stays behind, unused or shadowed by a later duplicate in the map.
+//! Compacting copies only the live entries, byte for byte, into new volumes,
+//! and writes a map that points at them.
+//!
+//! SCI0 and SCI1 maps can spread the resources over numbered volumes
+//! (`RESOURCE.000`, `RESOURCE.001`, ...), so they can also be repacked to fit
+//! a size limit, such as a floppy disk's. SCI1.1 maps have room for a single
+//! volume only.
+
+use std::{collections::BTreeMap, io};
+
+use sci_utils::block::BlockSource;
+
+use super::{
+    data::DataFile,
+    map::{EntryStatus, MapFormat, ResourceLocation, ResourceLocations},
+};
+use crate::ResourceId;
+
+/// Compacted volumes and their map.
+#[derive(Debug)]
+pub struct CompactedVolumes {
+    /// The layout of the map, which is the same as the original's.
+    pub format: MapFormat,
+    pub map: Vec<u8>,
+    /// The new volumes, by number.
+    pub volumes: BTreeMap<u8, Vec<u8>>,
+    /// The number of map entries dropped because the game didn't use them.
+    pub dropped_entries: usize,
+}
+
+impl CompactedVolumes {
+    /// The total size of the volumes.
+    pub fn size(&self) -> usize {
+        self.volumes.values().map(Vec::len).sum()
+    }
+}
+
+/// How far a map's entries can point: the largest offset and volume number,
+/// and the alignment of the offsets.
+struct Limits {
+    max_offset: usize,
+    max_volume: u8,
+    alignment: usize,
+}
+
+impl Limits {
+    fn of(format: MapFormat) -> io::Result<Limits> {
+        Ok(match format {
+            // The volume is in the top 6 bits of the location.
+            MapFormat::Sci0 => Limits {
+                max_offset: 0x03FF_FFFF,
+                max_volume: 0x3F,
+                alignment: 1,
+            },
+            // The volume is in the top 4 bits of the location.
+            MapFormat::Sci1 => Limits {
+                max_offset: 0x0FFF_FFFF,
+                max_volume: 0x0F,
+                alignment: 1,
+            },
+            // Offsets are stored halved, so entries start on even offsets,
+            // and must be within the 24 bits of the map entry.
+            MapFormat::Sci11 => Limits {
+                max_offset: 0xFF_FFFE << 1,
+                max_volume: 0,
+                alignment: 2,
+            },
+            MapFormat::Sci2 => {
+                return Err(invalid_data(format!(
+                    "Compacting {format} volumes is not supported"
+                )));
+            }
+        })
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A volume's bytes, with its entries read in the map's format.
+struct Volume<'a> {
+    bytes: &'a [u8],
+    file: DataFile,
+}
+
+impl<'a> Volume<'a> {
+    fn new(bytes: &'a [u8], format: MapFormat) -> Volume<'a> {
+        Volume {
+            bytes,
+            file: DataFile::new(BlockSource::from_reader(io::Cursor::new(bytes.to_vec())))
+                .with_format(format),
+        }
+    }
+
+    /// The bytes of the entry (header and stored data) at the location.
+    fn entry(&self, location: &ResourceLocation) -> io::Result<&'a [u8]> {
+        let id = location.id;
+        let contents = self
+            .file
+            .read_raw_contents(location)
+            .map_err(|e| invalid_data(format!("{id:?} can't be read: {e}")))?;
+        let start = location.file_offset as usize;
+        let len = contents.header_size() + contents.packed_size() as usize;
+        Ok(&self.bytes[start..start + len])
+    }
+}
+
+/// Reads each volume as the map's format says, telling SCI2 volumes (whose
+/// maps read as SCI1's) apart.
+fn read_volumes<'a>(
+    locations: &ResourceLocations,
+    volumes: &'a BTreeMap<u8, Vec<u8>>,
+) -> io::Result<(MapFormat, BTreeMap<u8, Volume<'a>>)> {
+    let mut format = locations.format();
+    if format == MapFormat::Sci1
+        && let Some(location) = locations.live_locations().next()
+        && let Some(volume) = volumes.get(&location.volume)
+    {
+        let source = BlockSource::from_reader(io::Cursor::new(volume.clone()));
+        format = DataFile::detect_format(&source, &location);
+    }
+    Limits::of(format)?;
+    let volumes = volumes
+        .iter()
+        .map(|(&num, bytes)| (num, Volume::new(bytes, format)))
+        .collect();
+    Ok((format, volumes))
+}
+
+/// Copies the live entries of a map's volumes into new ones, in map order,
+/// and writes a map for them. Checks that every resource reads back the same
+/// from the new volumes before returning them.
+///
+/// Without a size limit, each resource stays in the volume it was in. With
+/// one, SCI0 and SCI1 resources are packed into volumes from 0 up, starting
+/// a new volume whenever the next entry wouldn't fit. An SCI1.1 volume is
+/// left whole, whatever its size, as its map can't point anywhere else.
+pub fn compact_volumes(
+    map: &[u8],
+    volumes: &BTreeMap<u8, Vec<u8>>,
+    max_volume_size: Option<usize>,
+) -> io::Result<CompactedVolumes> {
+    let locations = ResourceLocations::parse_from_bytes(map)?;
+    let (format, old_volumes) = read_volumes(&locations, volumes)?;
+    let limits = Limits::of(format)?;
+    let max_volume_size = max_volume_size.filter(|_| format.has_volumes());
+    let dropped_entries = locations
+        .locations()
+        .filter(|location| location.status != EntryStatus::Live)
+        .count();
+
+    let mut new_volumes: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+    // Where each live resource went, in map order.
+    let mut placed = Vec::new();
+    // The volume being filled, when packing to a size limit.
+    let mut packing_volume = 0;
+    for location in locations.live_locations() {
+        let id = location.id;
+        let old_volume = old_volumes.get(&location.volume).ok_or_else(|| {
+            invalid_data(format!(
+                "{id:?} is in volume {}, which is missing",
+                location.volume
+            ))
+        })?;
+        let entry = old_volume.entry(&location)?;
+        let volume_num = match max_volume_size {
+            Some(max_size) => {
+                if entry.len() > max_size {
+                    return Err(invalid_data(format!(
+                        "{id:?} takes {} bytes, more than a whole volume of {max_size}",
+                        entry.len()
+                    )));
+                }
+                let used = new_volumes.get(&packing_volume).map_or(0, Vec::len);
+                if used + entry.len() > max_size {
+                    packing_volume += 1;
+                }
+                packing_volume
+            }
+            None => location.volume,
+        };
+        if volume_num > limits.max_volume {
+            return Err(invalid_data(format!(
+                "The resources need more than the {} volumes the map can point to",
+                usize::from(limits.max_volume) + 1
+            )));
+        }
+        let new_volume = new_volumes.entry(volume_num).or_default();
+        while !new_volume.len().is_multiple_of(limits.alignment) {
+            new_volume.push(0);
+        }
+        if new_volume.len() > limits.max_offset {
+            return Err(invalid_data(format!(
+                "Volume {volume_num} is too large for its map"
+            )));
+        }
+        placed.push((id, volume_num, new_volume.len() as u32));
+        new_volume.extend_from_slice(entry);
+    }
+
+    let new_map = write_map(format, &placed)?;
+    verify_compacted(format, &locations, &old_volumes, &new_map, &new_volumes)?;
+    Ok(CompactedVolumes {
+        format,
+        map: new_map,
+        volumes: new_volumes,
+        dropped_entries,
+    })
+}
+
+/// Writes a map in the given layout, for resources at the given volumes and
+/// offsets, in order.
+fn write_map(format: MapFormat, placed: &[(ResourceId, u8, u32)]) -> io::Result<Vec<u8>> {
+    if format == MapFormat::Sci0 {
+        // A list of entries, ending with one of all ones.
+        let mut map = Vec::new();
+        for &(id, volume, offset) in placed {
+            let packed_id = (u16::from(u8::from(id.type_id()) & 0x7F) << 11) | id.resource_num();
+            map.extend_from_slice(&packed_id.to_le_bytes());
+            map.extend_from_slice(&((u32::from(volume) << 26) | offset).to_le_bytes());
+        }
+        map.extend_from_slice(&[0xFF; 6]);
+        return Ok(map);
+    }
+
+    // Each type's entries, in the order of the types in the original map.
+    let mut types: Vec<(u8, Vec<u8>)> = Vec::new();
+    for &(id, volume, offset) in placed {
+        let type_id = u8::from(id.type_id());
+        let entries = match types.last_mut() {
+            Some((last_type, entries)) if *last_type == type_id => entries,
+            _ => {
+                types.push((type_id, Vec::new()));
+                &mut types.last_mut().expect("a type was just added").1
+            }
+        };
+        entries.extend_from_slice(&id.resource_num().to_le_bytes());
+        if format == MapFormat::Sci11 {
+            entries.extend_from_slice(&(offset >> 1).to_le_bytes()[..3]);
+        } else {
+            entries.extend_from_slice(&((u32::from(volume) << 28) | offset).to_le_bytes());
+        }
+    }
+
+    // The index has an entry per type, then a terminator giving the end of
+    // the last type's entries.
+    let mut map = Vec::new();
+    let mut entries = Vec::new();
+    let entries_start = (types.len() + 1) * 3;
+    let table_offset = |len: usize| {
+        u16::try_from(entries_start + len)
+            .map_err(|_| invalid_data("The compacted map is too large".to_string()))
+    };
+    for (type_id, type_entries) in &types {
+        map.push(*type_id);
+        map.extend_from_slice(&table_offset(entries.len())?.to_le_bytes());
+        entries.extend_from_slice(type_entries);
+    }
+    map.push(0xFF);
+    map.extend_from_slice(&table_offset(entries.len())?.to_le_bytes());
+    map.extend_from_slice(&entries);
+    Ok(map)
+}
+
+/// Checks that the new map has exactly the original's live resources, and
+/// that each one's entry is unchanged.
+fn verify_compacted(
+    format: MapFormat,
+    locations: &ResourceLocations,
+    old_volumes: &BTreeMap<u8, Volume>,
+    new_map: &[u8],
+    new_volumes: &BTreeMap<u8, Vec<u8>>,
+) -> io::Result<()> {
+    let new_locations = ResourceLocations::parse_from_bytes(new_map)?;
+    let old_ids: Vec<_> = locations.live_locations().map(|l| l.id).collect();
+    let new_ids: Vec<_> = new_locations.live_locations().map(|l| l.id).collect();
+    if new_locations.format() != locations.format() || old_ids != new_ids {
+        return Err(invalid_data(
+            "The compacted map doesn't have the same resources as the original".to_string(),
+        ));
+    }
+    let new_volumes: BTreeMap<_, _> = new_volumes
+        .iter()
+        .map(|(&num, bytes)| (num, Volume::new(bytes, format)))
+        .collect();
+    for location in locations.live_locations() {
+        let new_location = new_locations
+            .get_location(&location.id)
+            .expect("IDs were checked above");
+        let old = old_volumes[&location.volume].entry(&location)?;
+        let new = new_volumes
+            .get(&new_location.volume)
+            .ok_or_else(|| invalid_data(format!("{:?} is in a missing volume", location.id)))?
+            .entry(&new_location)?;
+        if old != new {
+            return Err(invalid_data(format!(
+                "{:?} reads differently from the compacted volume",
+                location.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ResourceType, file::RawContents};
+
+    /// An SCI1.1 volume entry.
+    fn entry(type_id: u8, num: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = vec![type_id];
+        entry.extend_from_slice(&num.to_le_bytes());
+        entry.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&0u16.to_le_bytes());
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    /// An SCI1 volume entry, whose packed size, as in SCI0's, counts the
+    /// four bytes after it.
+    fn sci1_entry(type_id: u8, num: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = entry(type_id, num, data);
+        entry[3..5].copy_from_slice(&(data.len() as u16 + 4).to_le_bytes());
+        entry
+    }
+
+    /// An SCI0 volume entry, whose packed size counts the four bytes after
+    /// it.
+    fn sci0_entry(type_id: u8, num: u16, data: &[u8]) -> Vec<u8> {
+        let id = (u16::from(type_id & 0x7F) << 11) | num;
+        let mut entry = id.to_le_bytes().to_vec();
+        entry.extend_from_slice(&(data.len() as u16 + 4).to_le_bytes());
+        entry.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&0u16.to_le_bytes());
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    /// The data of each resource in compacted volumes, by ID, with the volume
+    /// it is in.
+    fn read_back(compacted: &CompactedVolumes) -> io::Result<BTreeMap<ResourceId, (u8, Vec<u8>)>> {
+        let locations = ResourceLocations::parse_from_bytes(&compacted.map)?;
+        let mut resources = BTreeMap::new();
+        for location in locations.live_locations() {
+            let volume = Volume::new(&compacted.volumes[&location.volume], compacted.format);
+            let contents = volume.file.read_raw_contents(&location)?;
+            let data = contents.data().open()?.to_vec();
+            resources.insert(location.id, (location.volume, data));
+        }
+        Ok(resources)
+    }
+
+    #[test]
+    fn test_compact_volume() -> io::Result<()> {
+        let script = ResourceType::Script as u8;
+        // Script 1 was replaced by an entry appended at the end, leaving its
+        // first entry and some padding behind.
+        let mut volume = entry(script, 1, b"old one");
+        volume.extend_from_slice(&[0; 4]);
+        let second = volume.len() as u32;
+        volume.extend(entry(script, 2, b"two"));
+        volume.extend_from_slice(&[0; 2]);
+        let third = volume.len() as u32;
+        volume.extend(entry(script, 1, b"new one"));
+
+        let mut map = vec![script, 6, 0, 0xFF, 21, 0];
+        for (num, offset) in [(1u16, 0u32), (2, second), (1, third)] {
+            map.extend_from_slice(&num.to_le_bytes());
+            map.extend_from_slice(&(offset >> 1).to_le_bytes()[..3]);
+        }
+
+        let volumes = BTreeMap::from([(0, volume.clone())]);
+        let compacted = compact_volumes(&map, &volumes, None)?;
+        assert_eq!(compacted.format, MapFormat::Sci11);
+        assert_eq!(compacted.dropped_entries, 1);
+        assert!(compacted.size() < volume.len());
+        let locations = ResourceLocations::parse_from_bytes(&compacted.map)?;
+        assert_eq!(locations.locations().count(), 2);
+        let script_1 = locations
+            .get_location(&ResourceId::new(ResourceType::Script, 1))
+            .unwrap();
+        let contents =
+            RawContents::parse_from_bytes(&compacted.volumes[&0][script_1.file_offset as usize..])?;
+        assert_eq!(contents.res_number(), 1);
+        assert_eq!(contents.packed_size(), 7);
+
+        // Compacting again changes nothing, and a size limit doesn't split an
+        // SCI1.1 volume.
+        let again = compact_volumes(&compacted.map, &compacted.volumes, Some(8))?;
+        assert_eq!(again.dropped_entries, 0);
+        assert_eq!(again.volumes, compacted.volumes);
+        assert_eq!(again.map, compacted.map);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_sci0_volumes() -> io::Result<()> {
+        let view = ResourceType::View as u8;
+        let script = ResourceType::Script as u8;
+        let resources = [
+            (view, 1, &b"first view"[..]),
+            (script, 2, b"script"),
+            (view, 3, b"third view"),
+        ];
+        let mut volume = Vec::new();
+        let mut map = Vec::new();
+        for &(type_id, num, data) in &resources {
+            let id = (u16::from(type_id & 0x7F) << 11) | num;
+            map.extend_from_slice(&id.to_le_bytes());
+            map.extend_from_slice(&(volume.len() as u32).to_le_bytes());
+            volume.extend(sci0_entry(type_id, num, data));
+        }
+        map.extend_from_slice(&[0xFF; 6]);
+        let volumes = BTreeMap::from([(0, volume)]);
+
+        // Each view takes 18 bytes, and the script 14. The map lists them by
+        // type, so the views are packed first.
+        let compacted = compact_volumes(&map, &volumes, Some(32))?;
+        assert_eq!(compacted.format, MapFormat::Sci0);
+        assert_eq!(
+            compacted.volumes.values().map(Vec::len).collect::<Vec<_>>(),
+            [18, 32]
+        );
+        let read = read_back(&compacted)?;
+        for (type_id, num, data) in resources {
+            let id = ResourceId::new(ResourceType::try_from(type_id).unwrap(), num);
+            let volume = if num == 1 { 0 } else { 1 };
+            assert_eq!(read[&id], (volume, data.to_vec()));
+        }
+
+        // Without a limit, they stay in one volume.
+        let unsplit = compact_volumes(&map, &volumes, None)?;
+        assert_eq!(unsplit.volumes.keys().copied().collect::<Vec<_>>(), [0]);
+        assert_eq!(unsplit.size(), volumes[&0].len());
+
+        let err = compact_volumes(&map, &volumes, Some(16)).unwrap_err();
+        assert!(
+            err.to_string().contains("more than a whole volume"),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_sci1_volumes() -> io::Result<()> {
+        let view = ResourceType::View as u8;
+        let script = ResourceType::Script as u8;
+        // Two views in volume 1 with an unused entry between them, and a
+        // script in volume 2.
+        let mut volume_1 = sci1_entry(view, 1, b"view one");
+        volume_1.extend(sci1_entry(view, 2, b"unused"));
+        let third = volume_1.len() as u32;
+        volume_1.extend(sci1_entry(view, 3, b"view three"));
+        let volume_2 = sci1_entry(script, 4, b"script");
+
+        let view_entries = [(1u16, 1u32 << 28), (2, u32::MAX), (3, (1 << 28) | third)];
+        let mut map = vec![view, 9, 0, script, 27, 0, 0xFF, 33, 0];
+        for (num, body) in view_entries.into_iter().chain([(4, 2 << 28)]) {
+            map.extend_from_slice(&num.to_le_bytes());
+            map.extend_from_slice(&body.to_le_bytes());
+        }
+        let volumes = BTreeMap::from([(1, volume_1), (2, volume_2)]);
+
+        // Without a limit, the unused entry is dropped and the volumes keep
+        // their numbers.
+        let compacted = compact_volumes(&map, &volumes, None)?;
+        assert_eq!(compacted.format, MapFormat::Sci1);
+        assert_eq!(compacted.dropped_entries, 1);
+        assert_eq!(
+            compacted.volumes.keys().copied().collect::<Vec<_>>(),
+            [1, 2]
+        );
+
+        // With one, they are repacked from volume 0.
+        let split = compact_volumes(&map, &volumes, Some(35))?;
+        let read = read_back(&split)?;
+        let id = |type_id, num| ResourceId::new(type_id, num);
+        assert_eq!(read[&id(ResourceType::View, 1)], (0, b"view one".to_vec()));
+        assert_eq!(
+            read[&id(ResourceType::View, 3)],
+            (1, b"view three".to_vec())
+        );
+        assert_eq!(read[&id(ResourceType::Script, 4)], (1, b"script".to_vec()));
+        assert_eq!(read.len(), 3);
+        Ok(())
+    }
+}
@@ -3,52 +3,73 @@ use std::io;
 use crate::{ResourceId, ResourceType};
 use sci_utils::{
     block::{BlockSource, LazyBlock},
-    compression::dcl::decompress_dcl,
-    data_reader::{DataReader, FromBlockSource},
+    compression::{
+        dcl::decompress_dcl,
+        sci0::{decompress_huffman, decompress_lzw},
+    },
+    data_layout::DataField,
+    data_reader::FromBlockSource,
 };
 
-use super::map::ResourceLocation;
+use super::map::{MapFormat, ResourceLocation};
 
-/// A resource entry header in a data file.
-///
-/// This is based on the SCI1.1 data file format.
-#[derive(Debug)]
-pub struct RawEntryHeader {
-    res_type: u8,
-    res_number: u16,
-    packed_size: u16,
-    unpacked_size: u16,
-    compression_type: u16,
+sci_utils::data_layout! {
+    /// A resource entry header in an SCI1 or SCI1.1 data file.
+    ///
+    /// In SCI1 data files, as in SCI0's, the packed size counts the two
+    /// fields after it as well as the data. In SCI1.1's it counts only the
+    /// data.
+    #[derive(Debug)]
+    pub struct RawEntryHeader {
+        res_type: u8,
+        res_number: u16,
+        packed_size: u16,
+        unpacked_size: u16,
+        compression_type: u16,
+    }
 }
 
-impl FromBlockSource for RawEntryHeader {
-    fn read_size() -> usize {
-        9
-    }
-
-    fn parse<R>(mut reader: R) -> io::Result<Self>
-    where
-        R: DataReader,
-    {
-        let res_type = reader.read_u8()?;
-        let res_number = reader.read_u16_le()?;
-        let packed_size = reader.read_u16_le()?;
-        let unpacked_size = reader.read_u16_le()?;
-        let compression_type = reader.read_u16_le()?;
-        Ok(RawEntryHeader {
-            res_type,
-            res_number,
-            packed_size,
-            unpacked_size,
-            compression_type,
-        })
+sci_utils::data_layout! {
+    /// A resource entry header in an SCI0 data file.
+    ///
+    /// The type and number are packed as in the map. The packed size counts
+    /// the two fields after it as well as the data.
+    #[derive(Debug)]
+    pub struct RawSci0EntryHeader {
+        id: u16,
+        packed_size: u16,
+        unpacked_size: u16,
+        compression_method: u16,
+    }
+}
+
+sci_utils::data_layout! {
+    /// A resource entry header in an SCI2 data file, with 32-bit sizes.
+    #[derive(Debug)]
+    pub struct RawSci2EntryHeader {
+        res_type: u8,
+        res_number: u16,
+        packed_size: u32,
+        unpacked_size: u32,
+        compression_type: u16,
     }
 }
 
+/// An entry header of any format.
+struct EntryHeader {
+    res_type: u8,
+    res_number: u16,
+    packed_size: u32,
+    unpacked_size: u32,
+    compression_type: u16,
+}
+
+#[derive(Clone)]
 pub struct RawContents {
+    format: MapFormat,
     res_type: u8,
     res_number: u16,
-    unpacked_size: u16,
+    unpacked_size: u32,
     compression_type: u16,
     data: BlockSource,
 }
@@ -56,6 +77,7 @@ pub struct RawContents {
 impl std::fmt::Debug for RawContents {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RawContents")
+            .field("format", &self.format)
             .field("res_type", &self.res_type)
             .field("res_number", &self.res_number)
             .field("unpacked_size", &self.unpacked_size)
@@ -65,6 +87,54 @@ impl std::fmt::Debug for RawContents {
     }
 }
 
+impl RawContents {
+    /// Parses a data file entry (its header and stored data) at the start of
+    /// the bytes.
+    pub fn parse_from_bytes(data: &[u8]) -> io::Result<RawContents> {
+        DataFile::new(BlockSource::from_reader(io::Cursor::new(data.to_vec())))
+            .read_raw_contents_at(0)
+    }
+
+    /// The format of the data file the entry was read from.
+    pub fn format(&self) -> MapFormat {
+        self.format
+    }
+
+    /// The size of the entry's header in its data file.
+    pub fn header_size(&self) -> usize {
+        match self.format {
+            MapFormat::Sci0 => RawSci0EntryHeader::SIZE,
+            MapFormat::Sci1 | MapFormat::Sci11 => RawEntryHeader::SIZE,
+            MapFormat::Sci2 => RawSci2EntryHeader::SIZE,
+        }
+    }
+
+    pub fn res_type(&self) -> u8 {
+        self.res_type
+    }
+
+    pub fn res_number(&self) -> u16 {
+        self.res_number
+    }
+
+    pub fn packed_size(&self) -> u64 {
+        self.data.size()
+    }
+
+    pub fn unpacked_size(&self) -> u32 {
+        self.unpacked_size
+    }
+
+    pub fn compression_type(&self) -> u16 {
+        self.compression_type
+    }
+
+    /// The stored (possibly compressed) data, without the entry header.
+    pub fn data(&self) -> &BlockSource {
+        &self.data
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Contents {
     id: ResourceId,
@@ -84,9 +154,18 @@ impl TryFrom<RawContents> for Contents {
     type Error = io::Error;
 
     fn try_from(raw_contents: RawContents) -> Result<Self, Self::Error> {
-        let decompressed_data = match raw_contents.compression_type {
-            0 => raw_contents.data.to_lazy_block(),
-            18 => raw_contents
+        let unpacked_size = raw_contents.unpacked_size as usize;
+        let decompressed_data = match (raw_contents.format, raw_contents.compression_type) {
+            (_, 0) => raw_contents.data.to_lazy_block(),
+            (MapFormat::Sci0, 1) => raw_contents
+                .data
+                .to_lazy_block()
+                .map(move |block| Ok(decompress_lzw(&block, unpacked_size)?)),
+            (MapFormat::Sci0, 2) => raw_contents
+                .data
+                .to_lazy_block()
+                .map(move |block| Ok(decompress_huffman(&block, unpacked_size)?)),
+            (MapFormat::Sci1 | MapFormat::Sci11, 18) => raw_contents
                 .data
                 .to_lazy_block()
                 .map(move |block| Ok(decompress_dcl(&block)?)),
@@ -118,21 +197,107 @@ impl TryFrom<RawContents> for Contents {
     }
 }
 
+/// The size of an SCI0 or SCI1 entry's data, from its packed size, which
+/// counts the unpacked size and compression method read as part of the
+/// header.
+fn data_size_of_sci0_packed_size(offset: u64, packed_size: u16) -> io::Result<u16> {
+    packed_size.checked_sub(4).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Resource at offset {offset} has an invalid packed size of {packed_size}"),
+        )
+    })
+}
+
 pub struct DataFile {
+    format: MapFormat,
     data: BlockSource,
 }
 
 impl DataFile {
     pub fn new(data: BlockSource) -> DataFile {
-        DataFile { data }
+        DataFile {
+            format: MapFormat::Sci11,
+            data,
+        }
+    }
+
+    /// Reads the entries in the format of the map that points to them.
+    pub fn with_format(self, format: MapFormat) -> DataFile {
+        DataFile { format, ..self }
     }
 
     pub fn read_raw_contents(&self, location: &ResourceLocation) -> io::Result<RawContents> {
-        let (header, rest) =
-            RawEntryHeader::from_block_source(&self.data.subblock(location.file_offset as u64..))?;
+        self.read_raw_contents_at(location.file_offset as u64)
+    }
+
+    fn read_raw_contents_at(&self, offset: u64) -> io::Result<RawContents> {
+        if offset > self.data.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Resource offset {} is past the end of the data file ({} bytes)",
+                    offset,
+                    self.data.size()
+                ),
+            ));
+        }
+        let entry = self.data.subblock(offset..);
+        let (header, rest) = match self.format {
+            MapFormat::Sci0 => {
+                let (header, rest) = RawSci0EntryHeader::from_block_source(&entry)?;
+                let packed_size = data_size_of_sci0_packed_size(offset, header.packed_size)?;
+                let header = EntryHeader {
+                    res_type: 0x80 | (header.id >> 11) as u8,
+                    res_number: header.id & 0x7FF,
+                    packed_size: packed_size.into(),
+                    unpacked_size: header.unpacked_size.into(),
+                    compression_type: header.compression_method,
+                };
+                (header, rest)
+            }
+            MapFormat::Sci1 | MapFormat::Sci11 => {
+                let (header, rest) = RawEntryHeader::from_block_source(&entry)?;
+                let packed_size = if self.format == MapFormat::Sci1 {
+                    data_size_of_sci0_packed_size(offset, header.packed_size)?
+                } else {
+                    header.packed_size
+                };
+                let header = EntryHeader {
+                    res_type: header.res_type,
+                    res_number: header.res_number,
+                    packed_size: packed_size.into(),
+                    unpacked_size: header.unpacked_size.into(),
+                    compression_type: header.compression_type,
+                };
+                (header, rest)
+            }
+            MapFormat::Sci2 => {
+                let (header, rest) = RawSci2EntryHeader::from_block_source(&entry)?;
+                let header = EntryHeader {
+                    res_type: header.res_type,
+                    res_number: header.res_number,
+                    packed_size: header.packed_size,
+                    unpacked_size: header.unpacked_size,
+                    compression_type: header.compression_type,
+                };
+                (header, rest)
+            }
+        };
+        if rest.size() < header.packed_size as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Resource at offset {} has {} bytes of data, but only {} remain",
+                    offset,
+                    header.packed_size,
+                    rest.size()
+                ),
+            ));
+        }
         let resource_block = rest.subblock(..header.packed_size as u64);
-        assert_eq!(resource_block.size(), header.packed_size as u64);
         Ok(RawContents {
+            format: self.format,
             res_type: header.res_type,
             res_number: header.res_number,
             unpacked_size: header.unpacked_size,
@@ -141,8 +306,126 @@ impl DataFile {
         })
     }
 
-    pub fn read_contents(&self, location: &ResourceLocation) -> io::Result<Contents> {
-        let raw_contents = self.read_raw_contents(location)?;
-        raw_contents.try_into()
+    /// Tells an SCI2 volume from an SCI1 one, which share the map layout, by
+    /// reading the entry of a resource the map locates in it both ways. An
+    /// SCI2 header read as SCI1 takes the top half of the packed size as the
+    /// unpacked size, which is zero for all but the largest resources, and
+    /// the bottom half of the unpacked size as the compression type. Its
+    /// sizes then disagree with its compression type.
+    pub fn detect_format(data: &BlockSource, location: &ResourceLocation) -> MapFormat {
+        let reads_as = |format| {
+            DataFile::new(data.clone())
+                .with_format(format)
+                .read_raw_contents(location)
+                .ok()
+                .filter(|raw| {
+                    u8::from(location.id.type_id()) == raw.res_type
+                        && location.id.resource_num() == raw.res_number
+                        && match raw.compression_type {
+                            0 => u64::from(raw.unpacked_size) == raw.packed_size(),
+                            _ => raw.unpacked_size != 0,
+                        }
+                })
+                .is_some()
+        };
+        if !reads_as(MapFormat::Sci1) && reads_as(MapFormat::Sci2) {
+            MapFormat::Sci2
+        } else {
+            MapFormat::Sci1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::map::EntryStatus;
+
+    #[test]
+    fn test_truncated_entry_is_error() -> io::Result<()> {
+        let mut entry = vec![0x82, 1, 0, 4, 0, 4, 0, 0, 0];
+        entry.extend_from_slice(b"data");
+        let contents = RawContents::parse_from_bytes(&entry)?;
+        assert_eq!(contents.res_number(), 1);
+        assert_eq!(contents.packed_size(), 4);
+        for len in 0..entry.len() {
+            assert!(RawContents::parse_from_bytes(&entry[..len]).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_sci2_volume() {
+        let location = |file_offset| ResourceLocation {
+            id: ResourceId::new(ResourceType::Script, 1),
+            volume: 0,
+            file_offset,
+            status: EntryStatus::Live,
+        };
+        let mut volume = vec![0x82, 1, 0, 8, 0, 4, 0, 0, 0];
+        volume.extend_from_slice(b"data");
+        let sci2_offset = volume.len() as u32;
+        volume.extend_from_slice(&[0x82, 1, 0, 4, 0, 0, 0, 4, 0, 0, 0, 0, 0]);
+        volume.extend_from_slice(b"data");
+        let data = BlockSource::from_reader(io::Cursor::new(volume));
+        assert_eq!(
+            DataFile::detect_format(&data, &location(0)),
+            MapFormat::Sci1
+        );
+        assert_eq!(
+            DataFile::detect_format(&data, &location(sci2_offset)),
+            MapFormat::Sci2
+        );
+    }
+
+    #[test]
+    fn test_sci0_entry() -> anyhow::Result<()> {
+        // Script 3: type 2 in the top bits, and 4 bytes of sizes before the
+        // data.
+        let mut entry = vec![0x03, 0x10, 8, 0, 4, 0, 0, 0];
+        entry.extend_from_slice(b"data");
+        let data_file = DataFile::new(BlockSource::from_reader(io::Cursor::new(entry)))
+            .with_format(MapFormat::Sci0);
+        let raw = data_file.read_raw_contents_at(0)?;
+        assert_eq!(raw.res_type(), u8::from(ResourceType::Script));
+        assert_eq!(raw.res_number(), 3);
+        assert_eq!(raw.packed_size(), 4);
+        assert_eq!(raw.header_size(), 8);
+        let contents = Contents::try_from(raw)?;
+        assert_eq!(contents.id(), &ResourceId::new(ResourceType::Script, 3));
+        assert_eq!(&contents.data().open()?[..], b"data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sci1_entries() -> anyhow::Result<()> {
+        // Two uncompressed entries, laid out as in an SCI1 volume: the packed
+        // size counts the 4 bytes of unpacked size and compression type.
+        let mut volume = vec![0x82, 3, 0, 8, 0, 4, 0, 0, 0];
+        volume.extend_from_slice(b"data");
+        let second_offset = volume.len() as u64;
+        volume.extend_from_slice(&[0x80, 7, 0, 6, 0, 2, 0, 0, 0]);
+        volume.extend_from_slice(b"vw");
+        let data_file = DataFile::new(BlockSource::from_reader(io::Cursor::new(volume)))
+            .with_format(MapFormat::Sci1);
+
+        let raw = data_file.read_raw_contents_at(0)?;
+        assert_eq!(raw.packed_size(), 4);
+        assert_eq!(raw.header_size(), 9);
+        let contents = Contents::try_from(raw)?;
+        assert_eq!(contents.id(), &ResourceId::new(ResourceType::Script, 3));
+        assert_eq!(&contents.data().open()?[..], b"data");
+
+        // The last entry in the volume ends at the end of the file.
+        let contents = Contents::try_from(data_file.read_raw_contents_at(second_offset)?)?;
+        assert_eq!(contents.id(), &ResourceId::new(ResourceType::View, 7));
+        assert_eq!(&contents.data().open()?[..], b"vw");
+
+        let mut bad = vec![0x82, 3, 0, 3, 0, 0, 0, 0, 0];
+        bad.extend_from_slice(b"abc");
+        let data_file = DataFile::new(BlockSource::from_reader(io::Cursor::new(bad)))
+            .with_format(MapFormat::Sci1);
+        assert!(data_file.read_raw_contents_at(0).is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,511 @@
+//! Support for the resource files of Mac SCI ports.
+//!
+//! Mac ports store their resources in the resource fork of their data files,
+//! using the standard Resource Manager format (which is big-endian), with a
+//! four character tag for each resource type. Since most filesystems have no
+//! resource forks, the fork may be found in a few different containers: a
+//! MacBinary file, an AppleSingle/AppleDouble file (including a `._` sidecar
+//! file), the native fork on macOS, or a bare copy of the fork.
+
+use std::path::{Path, PathBuf};
+
+use sci_utils::{
+    block::{BlockReader, BlockSource, MemBlock},
+    data_layout::{DataField, DataLayout, U24},
+    data_reader::{DataReader, Endian},
+};
+
+use crate::{ResourceId, ResourceType};
+
+const MACBINARY_HEADER_SIZE: u64 = 128;
+const APPLE_SINGLE_MAGIC: u32 = 0x0005_1600;
+const APPLE_DOUBLE_MAGIC: u32 = 0x0005_1607;
+const APPLE_RESOURCE_FORK_ENTRY: u32 = 2;
+
+/// Maps the resource tags used by Mac ports to resource types. This follows
+/// the table in ScummVM.
+const TAG_TYPES: &[(&[u8; 4], ResourceType)] = &[
+    (b"V56 ", ResourceType::View),
+    (b"P56 ", ResourceType::Pic),
+    (b"SCR ", ResourceType::Script),
+    (b"TEX ", ResourceType::Text),
+    (b"SND ", ResourceType::Sound),
+    (b"VOC ", ResourceType::Vocab),
+    (b"FON ", ResourceType::Font),
+    (b"CURS", ResourceType::Cursor),
+    (b"crsr", ResourceType::Cursor),
+    (b"Pat ", ResourceType::Patch),
+    (b"PAL ", ResourceType::Palette),
+    (b"snd ", ResourceType::Audio),
+    (b"MSG ", ResourceType::Message),
+    (b"HEP ", ResourceType::Heap),
+    (b"SYN ", ResourceType::Sync),
+];
+
+/// Returns the resource type stored under the given tag, if it is one used
+/// by SCI.
+pub fn resource_type_for_tag(tag: &[u8; 4]) -> Option<ResourceType> {
+    TAG_TYPES
+        .iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, res_type)| *res_type)
+}
+
+sci_utils::data_layout! {
+    struct ForkHeader {
+        data_offset: u32,
+        map_offset: u32,
+        data_length: u32,
+        map_length: u32,
+    }
+}
+
+sci_utils::data_layout! {
+    struct MapHeader {
+        _header_copy: [u8; 16],
+        _next_map: u32,
+        _file_ref: u16,
+        _attributes: u16,
+        type_list_offset: u16,
+        name_list_offset: u16,
+    }
+}
+
+sci_utils::data_layout! {
+    struct TypeEntry {
+        tag: [u8; 4],
+        count_minus_one: u16,
+        ref_list_offset: u16,
+    }
+}
+
+sci_utils::data_layout! {
+    struct RefEntry {
+        id: u16,
+        name_offset: u16,
+        _attributes: u8,
+        data_offset: U24,
+        _handle: u32,
+    }
+}
+
+sci_utils::data_layout! {
+    struct AppleEntry {
+        entry_id: u32,
+        offset: u32,
+        length: u32,
+    }
+}
+
+/// A resource in a Mac resource fork.
+#[derive(Clone)]
+pub struct MacResource {
+    tag: [u8; 4],
+    id: u16,
+    name: Option<String>,
+    data: BlockSource,
+}
+
+impl MacResource {
+    pub fn tag(&self) -> &[u8; 4] {
+        &self.tag
+    }
+
+    /// The tag as a string, for display.
+    pub fn tag_str(&self) -> String {
+        String::from_utf8_lossy(&self.tag).into_owned()
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn data(&self) -> &BlockSource {
+        &self.data
+    }
+
+    /// The ID of this resource in the SCI resource model, if it is an SCI
+    /// resource.
+    pub fn resource_id(&self) -> Option<ResourceId> {
+        resource_type_for_tag(&self.tag).map(|res_type| ResourceId::new(res_type, self.id))
+    }
+}
+
+fn big_endian_reader(block: MemBlock) -> BlockReader {
+    BlockReader::with_endian(block, Endian::Big)
+}
+
+fn checked_subblock(source: &BlockSource, start: u64, len: u64) -> anyhow::Result<BlockSource> {
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= source.size())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Range {}..{} is outside of a block of size {}",
+                start,
+                start.saturating_add(len),
+                source.size()
+            )
+        })?;
+    Ok(source.subblock(start..end))
+}
+
+/// Parses a resource fork, returning all of its resources.
+pub fn read_resource_fork(fork: &BlockSource) -> anyhow::Result<Vec<MacResource>> {
+    let header_block = checked_subblock(fork, 0, ForkHeader::SIZE as u64)?.open()?;
+    let header = ForkHeader::read_from(big_endian_reader(header_block))?;
+    let data_area = checked_subblock(fork, header.data_offset.into(), header.data_length.into())?;
+    let map_area = checked_subblock(fork, header.map_offset.into(), header.map_length.into())?;
+    let map_data = map_area.open()?;
+
+    let mut map_reader = big_endian_reader(map_data.clone());
+    let map_header = MapHeader::read_from(&mut map_reader)?;
+    let type_list_start = u32::from(map_header.type_list_offset);
+    let name_list_start = u32::from(map_header.name_list_offset);
+
+    map_reader.seek_to(type_list_start)?;
+    // The count is stored minus one, so an empty map stores 0xFFFF.
+    let num_types = map_reader.read_u16()?.wrapping_add(1);
+    let mut type_entries = Vec::new();
+    for _ in 0..num_types {
+        type_entries.push(TypeEntry::read_from(&mut map_reader)?);
+    }
+
+    let mut resources = Vec::new();
+    for type_entry in type_entries {
+        map_reader.seek_to(type_list_start + u32::from(type_entry.ref_list_offset))?;
+        for _ in 0..=type_entry.count_minus_one {
+            let ref_entry = RefEntry::read_from(&mut map_reader)?;
+            let name = if ref_entry.name_offset == 0xFFFF {
+                None
+            } else {
+                let mut name_reader = big_endian_reader(map_data.clone());
+                name_reader.seek_to(name_list_start + u32::from(ref_entry.name_offset))?;
+                let len = name_reader.read_u8()?;
+                let mut name = vec![0; len.into()];
+                name_reader.read_exact(&mut name)?;
+                Some(String::from_utf8_lossy(&name).into_owned())
+            };
+
+            let data_offset = u64::from(ref_entry.data_offset.get());
+            let mut len_reader =
+                big_endian_reader(checked_subblock(&data_area, data_offset, 4)?.open()?);
+            let data_len = len_reader.read_u32()?;
+            let data = checked_subblock(&data_area, data_offset + 4, data_len.into())?;
+
+            resources.push(MacResource {
+                tag: type_entry.tag,
+                id: ref_entry.id,
+                name,
+                data,
+            });
+        }
+    }
+    Ok(resources)
+}
+
+/// Returns the resource fork in a MacBinary file, if the file is one.
+fn macbinary_resource_fork(file: &BlockSource) -> anyhow::Result<Option<BlockSource>> {
+    if file.size() < MACBINARY_HEADER_SIZE {
+        return Ok(None);
+    }
+    let header = file.subblock(..MACBINARY_HEADER_SIZE).open()?;
+    let mut bytes = [0; MACBINARY_HEADER_SIZE as usize];
+    header.read_at(0, &mut bytes)?;
+    let name_len = bytes[1];
+    if bytes[0] != 0 || bytes[74] != 0 || bytes[82] != 0 || !(1..=63).contains(&name_len) {
+        return Ok(None);
+    }
+    let data_len = u64::from(u32::from_be_bytes(bytes[83..87].try_into().unwrap()));
+    let rsrc_len = u64::from(u32::from_be_bytes(bytes[87..91].try_into().unwrap()));
+    // Each fork is padded to a multiple of 128 bytes.
+    let rsrc_start = MACBINARY_HEADER_SIZE + data_len.next_multiple_of(128);
+    if rsrc_len == 0 || rsrc_start + rsrc_len > file.size() {
+        return Ok(None);
+    }
+    Ok(Some(file.subblock(rsrc_start..rsrc_start + rsrc_len)))
+}
+
+/// Returns the resource fork in an AppleSingle or AppleDouble file, if the
+/// file is one.
+fn apple_double_resource_fork(file: &BlockSource) -> anyhow::Result<Option<BlockSource>> {
+    const HEADER_SIZE: u64 = 26;
+    if file.size() < HEADER_SIZE {
+        return Ok(None);
+    }
+    let mut reader = big_endian_reader(file.open()?);
+    let magic = reader.read_u32()?;
+    if magic != APPLE_SINGLE_MAGIC && magic != APPLE_DOUBLE_MAGIC {
+        return Ok(None);
+    }
+    // Skip the version and filler.
+    reader.seek_to(24)?;
+    let num_entries = reader.read_u16()?;
+    for _ in 0..num_entries {
+        let entry = AppleEntry::read_from(&mut reader)?;
+        if entry.entry_id == APPLE_RESOURCE_FORK_ENTRY {
+            return Ok(Some(checked_subblock(
+                file,
+                entry.offset.into(),
+                entry.length.into(),
+            )?));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns true if the block looks like a bare resource fork.
+fn is_resource_fork(block: &BlockSource) -> anyhow::Result<bool> {
+    if block.size() < ForkHeader::SIZE as u64 {
+        return Ok(false);
+    }
+    let mut reader = big_endian_reader(block.subblock(..ForkHeader::SIZE as u64).open()?);
+    let header = ForkHeader::read_from(&mut reader)?;
+    let fits = |offset: u32, len: u32| u64::from(offset) + u64::from(len) <= block.size();
+    Ok(header.data_offset as usize >= ForkHeader::SIZE
+        && header.map_offset as usize >= ForkHeader::SIZE
+        && fits(header.data_offset, header.data_length)
+        && fits(header.map_offset, header.map_length))
+}
+
+fn non_empty_file(path: &Path) -> anyhow::Result<Option<BlockSource>> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => {
+            Ok(Some(BlockSource::from_path(path.to_path_buf())?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Finds the resource fork of a Mac file, in whatever container it was
+/// preserved in. Returns None if the file has no resource fork.
+pub fn open_resource_fork(path: &Path) -> anyhow::Result<Option<BlockSource>> {
+    // The native resource fork, on macOS.
+    if let Some(fork) = non_empty_file(&path.join("..namedfork/rsrc"))? {
+        return Ok(Some(fork));
+    }
+
+    // An AppleDouble sidecar, as written when copying to a non-Mac
+    // filesystem.
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let mut sidecar_name = std::ffi::OsString::from("._");
+        sidecar_name.push(name);
+        let sidecar: PathBuf = parent.join(sidecar_name);
+        if let Some(sidecar) = non_empty_file(&sidecar)?
+            && let Some(fork) = apple_double_resource_fork(&sidecar)?
+        {
+            return Ok(Some(fork));
+        }
+    }
+
+    let Some(file) = non_empty_file(path)? else {
+        return Ok(None);
+    };
+    if let Some(fork) = apple_double_resource_fork(&file)? {
+        return Ok(Some(fork));
+    }
+    if let Some(fork) = macbinary_resource_fork(&file)?
+        && is_resource_fork(&fork)?
+    {
+        return Ok(Some(fork));
+    }
+    if is_resource_fork(&file)? {
+        return Ok(Some(file));
+    }
+    Ok(None)
+}
+
+/// Returns the number of a Mac data file (e.g. 2 for "Data2"), if the name is
+/// one. MacBinary copies may have a ".bin" extension.
+fn data_file_number(name: &str) -> Option<u32> {
+    let lower = name.to_ascii_lowercase();
+    let stem = lower.strip_suffix(".bin").unwrap_or(&lower);
+    let digits = stem.strip_prefix("data")?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Finds the resource files of a Mac port in a game directory, in the order
+/// their resources should be loaded: the numbered data files ("Data1",
+/// "Data2", ...), followed by the "Patches" file if present.
+pub fn find_game_resource_files(root_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut data_files = Vec::new();
+    let mut patches_file = None;
+    for entry in root_dir.read_dir()? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(number) = data_file_number(&name) {
+            data_files.push((number, entry.path()));
+        } else if name.eq_ignore_ascii_case("patches") {
+            patches_file = Some(entry.path());
+        }
+    }
+    data_files.sort();
+    Ok(data_files
+        .into_iter()
+        .map(|(_, path)| path)
+        .chain(patches_file)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A resource to put in a test fork: (tag, id, name, data).
+    type TestEntry<'a> = (&'a [u8; 4], u16, Option<&'a str>, &'a [u8]);
+
+    /// Builds a resource fork with the given entries. Entries with the same
+    /// tag must be adjacent.
+    fn build_fork(entries: &[TestEntry]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut names = Vec::new();
+        let mut data_offsets = Vec::new();
+        let mut name_offsets = Vec::new();
+        for (_, _, name, contents) in entries {
+            data_offsets.push(data.len() as u32);
+            data.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            data.extend_from_slice(contents);
+            name_offsets.push(match name {
+                Some(name) => {
+                    let offset = names.len() as u16;
+                    names.push(name.len() as u8);
+                    names.extend_from_slice(name.as_bytes());
+                    offset
+                }
+                None => 0xFFFF,
+            });
+        }
+
+        let mut tags: Vec<(&[u8; 4], Vec<usize>)> = Vec::new();
+        for (i, (tag, ..)) in entries.iter().enumerate() {
+            match tags.last_mut() {
+                Some((last, indices)) if last == tag => indices.push(i),
+                _ => tags.push((tag, vec![i])),
+            }
+        }
+
+        let type_list_len = 2 + 8 * tags.len();
+        let mut type_list = Vec::new();
+        type_list.extend_from_slice(&(tags.len() as u16).wrapping_sub(1).to_be_bytes());
+        let mut ref_list = Vec::new();
+        for (tag, indices) in &tags {
+            type_list.extend_from_slice(*tag);
+            type_list.extend_from_slice(&(indices.len() as u16 - 1).to_be_bytes());
+            type_list.extend_from_slice(&((type_list_len + ref_list.len()) as u16).to_be_bytes());
+            for &i in indices {
+                ref_list.extend_from_slice(&entries[i].1.to_be_bytes());
+                ref_list.extend_from_slice(&name_offsets[i].to_be_bytes());
+                ref_list.push(0);
+                ref_list.extend_from_slice(&data_offsets[i].to_be_bytes()[1..]);
+                ref_list.extend_from_slice(&[0; 4]);
+            }
+        }
+
+        let mut map = vec![0; 28];
+        map.extend_from_slice(&type_list);
+        map.extend_from_slice(&ref_list);
+        let name_list_offset = map.len() as u16;
+        map.extend_from_slice(&names);
+        map[24..26].copy_from_slice(&28u16.to_be_bytes());
+        map[26..28].copy_from_slice(&name_list_offset.to_be_bytes());
+
+        let data_offset = 256u32;
+        let map_offset = data_offset + data.len() as u32;
+        let mut fork = Vec::new();
+        for value in [data_offset, map_offset, data.len() as u32, map.len() as u32] {
+            fork.extend_from_slice(&value.to_be_bytes());
+        }
+        fork.resize(data_offset as usize, 0);
+        fork.extend_from_slice(&data);
+        fork.extend_from_slice(&map);
+        fork
+    }
+
+    fn source_of(data: Vec<u8>) -> BlockSource {
+        BlockSource::from_reader(std::io::Cursor::new(data))
+    }
+
+    #[test]
+    fn test_read_resource_fork() -> anyhow::Result<()> {
+        let fork = build_fork(&[
+            (b"SCR ", 100, None, b"script"),
+            (b"SCR ", 200, Some("Other"), b"other"),
+            (b"PICT", 1, None, b"pict"),
+        ]);
+        let resources = read_resource_fork(&source_of(fork))?;
+        assert_eq!(resources.len(), 3);
+        assert_eq!(
+            resources[0].resource_id(),
+            Some(ResourceId::new(ResourceType::Script, 100))
+        );
+        assert_eq!(&*resources[0].data().open()?.read_all()?, b"script");
+        assert_eq!(resources[1].name(), Some("Other"));
+        assert_eq!(&*resources[1].data().open()?.read_all()?, b"other");
+        assert_eq!(resources[2].tag_str(), "PICT");
+        assert_eq!(resources[2].resource_id(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_file_number() {
+        assert_eq!(data_file_number("Data1"), Some(1));
+        assert_eq!(data_file_number("DATA12.bin"), Some(12));
+        assert_eq!(data_file_number("Data"), None);
+        assert_eq!(data_file_number("._Data1"), None);
+        assert_eq!(data_file_number("Database"), None);
+    }
+
+    #[test]
+    fn test_macbinary_container() -> anyhow::Result<()> {
+        let fork = build_fork(&[(b"HEP ", 7, None, b"heap")]);
+        let data_fork = b"data fork";
+        let mut file = vec![0; MACBINARY_HEADER_SIZE as usize];
+        file[1] = 5;
+        file[2..7].copy_from_slice(b"Data1");
+        file[83..87].copy_from_slice(&(data_fork.len() as u32).to_be_bytes());
+        file[87..91].copy_from_slice(&(fork.len() as u32).to_be_bytes());
+        file.extend_from_slice(data_fork);
+        file.resize(256, 0);
+        file.extend_from_slice(&fork);
+
+        let found = macbinary_resource_fork(&source_of(file))?.expect("No fork found");
+        assert!(is_resource_fork(&found)?);
+        let resources = read_resource_fork(&found)?;
+        assert_eq!(
+            resources[0].resource_id(),
+            Some(ResourceId::new(ResourceType::Heap, 7))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apple_double_container() -> anyhow::Result<()> {
+        let fork = build_fork(&[(b"MSG ", 10, None, b"msg")]);
+        let mut file = Vec::new();
+        file.extend_from_slice(&APPLE_DOUBLE_MAGIC.to_be_bytes());
+        file.extend_from_slice(&0x0002_0000u32.to_be_bytes());
+        file.extend_from_slice(&[0; 16]);
+        file.extend_from_slice(&1u16.to_be_bytes());
+        let offset = file.len() as u32 + 12;
+        file.extend_from_slice(&APPLE_RESOURCE_FORK_ENTRY.to_be_bytes());
+        file.extend_from_slice(&offset.to_be_bytes());
+        file.extend_from_slice(&(fork.len() as u32).to_be_bytes());
+        file.extend_from_slice(&fork);
+
+        let found = apple_double_resource_fork(&source_of(file))?.expect("No fork found");
+        assert_eq!(read_resource_fork(&found)?.len(), 1);
+        Ok(())
+    }
+}
@@ -1,22 +1,32 @@
-use std::io;
+use std::{collections::BTreeMap, io};
 
-use crate::{ResourceId, ResourceType};
-use sci_utils::data_reader::DataReader;
+use crate::{ParseOptions, Quirk, ResourceId, ResourceType};
+use sci_utils::{
+    block::{BlockReader, MemBlock},
+    data_layout::{DataField, DataLayout, U24},
+    data_reader::DataReader,
+};
 
-#[derive(Debug)]
-pub struct ResourceIndexEntry {
-    pub type_id: u8,
-    pub file_offset: u16,
-}
-
-impl ResourceIndexEntry {
-    pub fn read_from<R: DataReader>(mut reader: R) -> io::Result<ResourceIndexEntry> {
-        let type_id = reader.read_u8()?;
-        let file_offset = reader.read_u16_le()?;
-        Ok(ResourceIndexEntry {
-            type_id,
-            file_offset,
-        })
+sci_utils::data_layout! {
+    #[derive(Debug)]
+    pub struct ResourceIndexEntry {
+        pub type_id: u8,
+        pub file_offset: u16,
+    }
+}
+
+sci_utils::data_layout! {
+    struct RawLocationEntry {
+        resource_num: u16,
+        body: U24,
+    }
+}
+
+sci_utils::data_layout! {
+    /// A 6-byte map entry, as in SCI1 and SCI2 maps.
+    struct RawWideLocationEntry {
+        resource_num: u16,
+        body: u32,
     }
 }
 
@@ -27,9 +37,32 @@ pub struct ResourceIndex {
 }
 
 impl ResourceIndex {
-    pub fn read_from<R: DataReader>(mut reader: R) -> io::Result<ResourceIndex> {
-        let mut entries = Vec::new();
+    pub fn read_from<R: DataReader>(reader: R) -> io::Result<ResourceIndex> {
+        Self::read_from_with_options(reader, &ParseOptions::default())
+    }
+
+    /// Reads the index, which ends with a `0xFF` entry, or with the
+    /// [`Quirk::UnterminatedMapIndex`] quirk, where the first entry table
+    /// starts.
+    pub fn read_from_with_options<R: DataReader>(
+        mut reader: R,
+        options: &ParseOptions,
+    ) -> io::Result<ResourceIndex> {
+        let unterminated = options.has_quirk(Quirk::UnterminatedMapIndex);
+        let mut entries: Vec<ResourceIndexEntry> = Vec::new();
         loop {
+            if unterminated
+                && let Some(first_table) = entries.iter().map(|entry| entry.file_offset).min()
+                && reader.tell()? >= u32::from(first_table)
+            {
+                let end = u16::try_from(reader.file_size()?).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Map is too large for an unterminated index",
+                    )
+                })?;
+                return Ok(ResourceIndex { entries, end });
+            }
             let entry = ResourceIndexEntry::read_from(&mut reader)?;
             if entry.type_id == 0xFF {
                 return Ok(ResourceIndex {
@@ -42,21 +75,158 @@ impl ResourceIndex {
     }
 }
 
+/// An offset of all ones marks an entry that is not in use.
+const UNUSED_ENTRY_BODY: u32 = 0xFF_FFFF;
+
+/// The size of an SCI0 map entry.
+const SCI0_ENTRY_SIZE: u32 = 6;
+
+/// The layout of a game's resource map and volumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapFormat {
+    /// SCI0: a list of 6-byte entries, each with the resource's type and
+    /// number packed in a word, and its volume and offset in the next two.
+    /// An entry of all ones ends the list.
+    Sci0,
+    /// SCI1: an index of the types, each pointing to a table of 6-byte
+    /// entries, with the volume in the top 4 bits of the offset.
+    Sci1,
+    /// SCI1.1: an index of the types, each pointing to a table of 5-byte
+    /// entries in a single volume.
+    #[default]
+    Sci11,
+    /// SCI2: a map laid out as SCI1's, but with plain 32-bit offsets, and
+    /// volume entries with 32-bit sizes. The map alone can't be told apart
+    /// from an SCI1 map in a single volume, so this is only detected from the
+    /// volume.
+    Sci2,
+}
+
+impl std::fmt::Display for MapFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MapFormat::Sci0 => "SCI0",
+            MapFormat::Sci1 => "SCI1",
+            MapFormat::Sci11 => "SCI1.1",
+            MapFormat::Sci2 => "SCI2",
+        })
+    }
+}
+
+impl MapFormat {
+    /// Whether resources may be spread over several volumes.
+    pub fn has_volumes(self) -> bool {
+        matches!(self, MapFormat::Sci0 | MapFormat::Sci1)
+    }
+
+    /// Tells SCI0 maps apart from those with an index of types. A map is read
+    /// as SCI0 if it is a whole number of 6-byte entries, ending with the
+    /// SCI0 terminator, and doesn't start with a well-formed index. Indexed
+    /// maps are reported as SCI1.1 here; the size of their entries decides
+    /// between SCI1 and SCI1.1 as they're read.
+    pub fn detect<R: DataReader>(reader: &mut R) -> io::Result<MapFormat> {
+        let size = reader.file_size()?;
+        if size < SCI0_ENTRY_SIZE || size % SCI0_ENTRY_SIZE != 0 {
+            return Ok(MapFormat::Sci11);
+        }
+        reader.seek_to(size - SCI0_ENTRY_SIZE)?;
+        let mut last_entry = [0; SCI0_ENTRY_SIZE as usize];
+        reader.read_exact(&mut last_entry)?;
+        let format = if last_entry.iter().all(|&byte| byte == 0xFF)
+            && !Self::has_sci11_index(reader, size)?
+        {
+            MapFormat::Sci0
+        } else {
+            MapFormat::Sci11
+        };
+        reader.seek_to(0)?;
+        Ok(format)
+    }
+
+    /// Whether the map starts with an index: known types with
+    /// ascending table offsets, ending with a terminator at the end of the
+    /// map.
+    fn has_sci11_index<R: DataReader>(reader: &mut R, size: u32) -> io::Result<bool> {
+        reader.seek_to(0)?;
+        let mut last_offset = 0;
+        while reader.tell()? + 3 <= size {
+            let ResourceIndexEntry {
+                type_id,
+                file_offset,
+            } = ResourceIndexEntry::read_from(&mut *reader)?;
+            if file_offset < last_offset || u32::from(file_offset) > size {
+                return Ok(false);
+            }
+            if type_id == 0xFF {
+                return Ok(u32::from(file_offset) == size);
+            }
+            if ResourceType::try_from(type_id).is_err() {
+                return Ok(false);
+            }
+            last_offset = file_offset;
+        }
+        Ok(false)
+    }
+}
+
+/// Whether a map entry is the one the game uses for its resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    Live,
+    /// The entry is marked as not in use.
+    Unused,
+    /// A later entry in the map is for the same resource, and replaces this
+    /// one.
+    Duplicate,
+}
+
 #[derive(Debug)]
 pub struct ResourceLocationEntry {
     pub resource_num: u16,
+    /// The number of the volume file (`RESOURCE.<volume>`) holding the
+    /// resource. SCI1.1 maps have a single volume, 0.
+    pub volume: u8,
     pub resource_file_offset: u32,
+    pub status: EntryStatus,
 }
 
 impl ResourceLocationEntry {
     pub fn read_from<R: DataReader>(reader: &mut R) -> io::Result<ResourceLocationEntry> {
-        let resource_num = reader.read_u16_le()?;
-        let body = reader.read_u24_le()?;
-        assert_eq!(body & 0xF000_0000, 0);
+        let RawLocationEntry { resource_num, body } = RawLocationEntry::read_from(reader)?;
+        let body = body.get();
+        if body & 0xF000_0000 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Map entry for resource {resource_num} has an invalid offset"),
+            ));
+        }
         let resource_file_offset = (body & 0x0FFF_FFFF) << 1;
         Ok(ResourceLocationEntry {
             resource_num,
+            volume: 0,
             resource_file_offset,
+            status: if body == UNUSED_ENTRY_BODY {
+                EntryStatus::Unused
+            } else {
+                EntryStatus::Live
+            },
+        })
+    }
+
+    /// Reads a 6-byte SCI1 entry, whose offset has the volume in its top 4
+    /// bits. In an SCI2 map, those bits are always clear, so the entry reads
+    /// the same.
+    fn read_wide_from<R: DataReader>(reader: &mut R) -> io::Result<ResourceLocationEntry> {
+        let RawWideLocationEntry { resource_num, body } = RawWideLocationEntry::read_from(reader)?;
+        Ok(ResourceLocationEntry {
+            resource_num,
+            volume: (body >> 28) as u8,
+            resource_file_offset: body & 0x0FFF_FFFF,
+            status: if body == u32::MAX {
+                EntryStatus::Unused
+            } else {
+                EntryStatus::Live
+            },
         })
     }
 }
@@ -68,33 +238,176 @@ pub struct ResourceTypeLocations {
 }
 
 impl ResourceTypeLocations {
+    /// Reads the entries for a type, from an SCI1 or SCI1.1 map, or returns
+    /// `None` if the entry table is malformed and the options allow skipping
+    /// it.
     pub fn read_from<R: DataReader>(
         reader: &mut R,
+        format: MapFormat,
         type_id: ResourceType,
         start: u16,
         end: u16,
-    ) -> io::Result<ResourceTypeLocations> {
+        options: &ParseOptions,
+    ) -> io::Result<Option<ResourceTypeLocations>> {
         // Despite documentation to the contrary, SCI11 uses 5 byte entries in the resource map
         // file.
-        assert_eq!((end - start) % 5, 0);
-        let count = (end - start) / 5;
+        let wide = format != MapFormat::Sci11;
+        let entry_size = if wide {
+            RawWideLocationEntry::SIZE
+        } else {
+            RawLocationEntry::SIZE
+        } as u16;
+        let Some(table_size) = end.checked_sub(start).filter(|size| size % entry_size == 0) else {
+            options.out_of_bounds(format!(
+                "Invalid {type_id:?} entry table in map: {start}..{end}"
+            ))?;
+            return Ok(None);
+        };
+        let count = table_size / entry_size;
         reader.seek_to(start as u32)?;
         let mut entries = Vec::new();
         for _ in 0..count {
-            entries.push(ResourceLocationEntry::read_from(reader)?);
+            entries.push(if wide {
+                ResourceLocationEntry::read_wide_from(reader)?
+            } else {
+                ResourceLocationEntry::read_from(reader)?
+            });
+        }
+        let mut locations = ResourceTypeLocations { type_id, entries };
+        locations.mark_duplicates();
+        Ok(Some(locations))
+    }
+
+    /// Marks the entries that later ones replace: the last live entry for a
+    /// resource is the one that is used.
+    fn mark_duplicates(&mut self) {
+        let mut live_entries = BTreeMap::new();
+        for i in 0..self.entries.len() {
+            if self.entries[i].status == EntryStatus::Unused {
+                continue;
+            }
+            self.entries[i].status = EntryStatus::Live;
+            if let Some(earlier) = live_entries.insert(self.entries[i].resource_num, i) {
+                self.entries[earlier].status = EntryStatus::Duplicate;
+            }
         }
-        Ok(ResourceTypeLocations { type_id, entries })
+    }
+
+    fn location(&self, entry: &ResourceLocationEntry) -> ResourceLocation {
+        ResourceLocation {
+            id: ResourceId::new(self.type_id, entry.resource_num),
+            volume: entry.volume,
+            file_offset: entry.resource_file_offset,
+            status: entry.status,
+        }
+    }
+
+    fn locations(&self) -> impl Iterator<Item = ResourceLocation> + '_ {
+        self.entries.iter().map(|entry| self.location(entry))
     }
 }
 
 #[derive(Debug)]
 pub struct ResourceLocations {
-    pub type_locations: Vec<ResourceTypeLocations>,
+    format: MapFormat,
+    type_locations: Vec<ResourceTypeLocations>,
+    /// The position of each resource's live entry, as indexes into
+    /// `type_locations` and its entries.
+    index: BTreeMap<ResourceId, (usize, usize)>,
 }
 
 impl ResourceLocations {
-    pub fn read_from<R: DataReader>(mut reader: R) -> io::Result<ResourceLocations> {
-        let index = ResourceIndex::read_from(&mut reader)?;
+    /// Parses the contents of a resource map file.
+    pub fn parse_from_bytes(data: &[u8]) -> io::Result<ResourceLocations> {
+        Self::read_from(BlockReader::new(MemBlock::from_vec(data.to_vec())))
+    }
+
+    pub fn read_from<R: DataReader>(reader: R) -> io::Result<ResourceLocations> {
+        Self::read_from_with_options(reader, &ParseOptions::default())
+    }
+
+    pub fn read_from_with_options<R: DataReader>(
+        mut reader: R,
+        options: &ParseOptions,
+    ) -> io::Result<ResourceLocations> {
+        let (format, type_locations) = match MapFormat::detect(&mut reader)? {
+            MapFormat::Sci0 => (
+                MapFormat::Sci0,
+                Self::read_sci0_types(&mut reader, options)?,
+            ),
+            _ => Self::read_indexed_types(&mut reader, options)?,
+        };
+        let mut index = BTreeMap::new();
+        for (type_index, locations) in type_locations.iter().enumerate() {
+            for (entry_index, entry) in locations.entries.iter().enumerate() {
+                if entry.status == EntryStatus::Live {
+                    index.insert(
+                        ResourceId::new(locations.type_id, entry.resource_num),
+                        (type_index, entry_index),
+                    );
+                }
+            }
+        }
+        Ok(ResourceLocations {
+            format,
+            type_locations,
+            index,
+        })
+    }
+
+    /// Reads an SCI0 map's entries, grouped by type in the order the types
+    /// first appear.
+    fn read_sci0_types<R: DataReader>(
+        reader: &mut R,
+        options: &ParseOptions,
+    ) -> io::Result<Vec<ResourceTypeLocations>> {
+        let mut type_locations: Vec<ResourceTypeLocations> = Vec::new();
+        loop {
+            let id = reader.read_u16_le()?;
+            let location = reader.read_u32_le()?;
+            if id == 0xFFFF && location == 0xFFFF_FFFF {
+                break;
+            }
+            // The type is in the top 5 bits, numbered from 0 rather than
+            // 0x80.
+            let Ok(type_id) = ResourceType::try_from(0x80 | (id >> 11) as u8) else {
+                options.unknown(format!(
+                    "Unknown resource type in map: {:#x}",
+                    0x80 | (id >> 11)
+                ))?;
+                continue;
+            };
+            let entry = ResourceLocationEntry {
+                resource_num: id & 0x7FF,
+                volume: (location >> 26) as u8,
+                resource_file_offset: location & 0x03FF_FFFF,
+                status: EntryStatus::Live,
+            };
+            match type_locations
+                .iter_mut()
+                .find(|locations| locations.type_id == type_id)
+            {
+                Some(locations) => locations.entries.push(entry),
+                None => type_locations.push(ResourceTypeLocations {
+                    type_id,
+                    entries: vec![entry],
+                }),
+            }
+        }
+        for locations in &mut type_locations {
+            locations.mark_duplicates();
+        }
+        Ok(type_locations)
+    }
+
+    /// Reads a map with an index of types, telling SCI1's 6-byte entries
+    /// from SCI1.1's 5-byte ones by the sizes of the entry tables. Tables
+    /// that could hold either are read as SCI1.1's.
+    fn read_indexed_types<R: DataReader>(
+        reader: &mut R,
+        options: &ParseOptions,
+    ) -> io::Result<(MapFormat, Vec<ResourceTypeLocations>)> {
+        let index = ResourceIndex::read_from_with_options(&mut *reader, options)?;
         let mut type_locations = Vec::new();
 
         let end_offsets = index
@@ -103,25 +416,93 @@ impl ResourceLocations {
             .map(|entry| entry.file_offset)
             .skip(1)
             .chain(std::iter::once(index.end));
-        for (entry, end_offset) in index.entries.iter().zip(end_offsets) {
-            let locations = ResourceTypeLocations::read_from(
-                &mut reader,
-                entry.type_id.try_into().unwrap(),
+        let tables: Vec<_> = index.entries.iter().zip(end_offsets).collect();
+        let fits = |entry_size: usize| {
+            tables.iter().all(|(entry, end_offset)| {
+                end_offset
+                    .checked_sub(entry.file_offset)
+                    .is_some_and(|size| usize::from(size) % entry_size == 0)
+            })
+        };
+        let format = if !fits(RawLocationEntry::SIZE) && fits(RawWideLocationEntry::SIZE) {
+            MapFormat::Sci1
+        } else {
+            MapFormat::Sci11
+        };
+        for (entry, end_offset) in tables {
+            let Ok(type_id) = ResourceType::try_from(entry.type_id) else {
+                options.unknown(format!(
+                    "Unknown resource type in map: {:#x}",
+                    entry.type_id
+                ))?;
+                continue;
+            };
+            let Some(locations) = ResourceTypeLocations::read_from(
+                reader,
+                format,
+                type_id,
                 entry.file_offset,
                 end_offset,
-            )?;
-            type_locations.push(locations);
+                options,
+            )?
+            else {
+                continue;
+            };
+            let earlier = type_locations
+                .iter_mut()
+                .find(|earlier: &&mut ResourceTypeLocations| earlier.type_id == type_id);
+            match earlier {
+                Some(earlier) if options.has_quirk(Quirk::DuplicateMapTypes) => {
+                    earlier.entries.extend(locations.entries);
+                    earlier.mark_duplicates();
+                }
+                Some(_) => {
+                    options.warn(format_args!(
+                        "{type_id:?} is listed more than once in the map's index"
+                    ));
+                    type_locations.push(locations);
+                }
+                None => type_locations.push(locations),
+            }
         }
-        Ok(ResourceLocations { type_locations })
+        Ok((format, type_locations))
+    }
+
+    /// The layout the map was read in. SCI2 maps are reported as SCI1, which
+    /// they share their layout with.
+    pub fn format(&self) -> MapFormat {
+        self.format
     }
 
     pub fn locations(&self) -> impl Iterator<Item = ResourceLocation> + '_ {
-        self.type_locations.iter().flat_map(|locations| {
-            locations.entries.iter().map(move |entry| ResourceLocation {
-                id: ResourceId::new(locations.type_id, entry.resource_num),
-                file_offset: entry.resource_file_offset,
-            })
-        })
+        self.type_locations
+            .iter()
+            .flat_map(ResourceTypeLocations::locations)
+    }
+
+    /// The entries for resources of one type, in map order.
+    pub fn locations_of_type(
+        &self,
+        type_id: ResourceType,
+    ) -> impl Iterator<Item = ResourceLocation> + '_ {
+        self.type_locations
+            .iter()
+            .filter(move |locations| locations.type_id == type_id)
+            .flat_map(ResourceTypeLocations::locations)
+    }
+
+    /// The location of a resource's live entry, if the map has one.
+    pub fn get_location(&self, id: &ResourceId) -> Option<ResourceLocation> {
+        let &(type_index, entry_index) = self.index.get(id)?;
+        let locations = &self.type_locations[type_index];
+        Some(locations.location(&locations.entries[entry_index]))
+    }
+
+    /// The locations of the resources the game uses, skipping entries that
+    /// are not in use or are replaced by later ones.
+    pub fn live_locations(&self) -> impl Iterator<Item = ResourceLocation> + '_ {
+        self.locations()
+            .filter(|location| location.status == EntryStatus::Live)
     }
 }
 
@@ -129,5 +510,247 @@ impl ResourceLocations {
 #[derive(Debug, Clone, Copy)]
 pub struct ResourceLocation {
     pub id: ResourceId,
+    /// The number of the volume file holding the resource.
+    pub volume: u8,
     pub file_offset: u32,
+    pub status: EntryStatus,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_status() -> io::Result<()> {
+        let mut map = vec![
+            // The index: one type, whose entries start at 6 and end at 21.
+            ResourceType::Script as u8,
+            6,
+            0,
+            0xFF,
+            21,
+            0,
+        ];
+        for (num, body) in [(1u16, 0x10u32), (2, UNUSED_ENTRY_BODY), (1, 0x20)] {
+            map.extend_from_slice(&num.to_le_bytes());
+            map.extend_from_slice(&body.to_le_bytes()[..3]);
+        }
+        let locations = ResourceLocations::read_from(BlockReader::new(MemBlock::from_vec(map)))?;
+        let statuses: Vec<_> = locations
+            .locations()
+            .map(|location| (location.id.resource_num(), location.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (1, EntryStatus::Duplicate),
+                (2, EntryStatus::Unused),
+                (1, EntryStatus::Live)
+            ]
+        );
+        let live: Vec<_> = locations.live_locations().collect();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].file_offset, 0x40);
+
+        let script_1 = ResourceId::new(ResourceType::Script, 1);
+        assert_eq!(
+            locations
+                .get_location(&script_1)
+                .map(|location| location.file_offset),
+            Some(0x40)
+        );
+        assert!(
+            locations
+                .get_location(&ResourceId::new(ResourceType::Script, 2))
+                .is_none()
+        );
+        assert_eq!(locations.locations_of_type(ResourceType::Script).count(), 3);
+        assert_eq!(locations.locations_of_type(ResourceType::View).count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_map_is_error() -> io::Result<()> {
+        let mut map = vec![ResourceType::Script as u8, 6, 0, 0xFF, 11, 0];
+        map.extend_from_slice(&[1, 0, 0x10, 0, 0]);
+        assert!(ResourceLocations::parse_from_bytes(&map).is_ok());
+        for len in 0..map.len() {
+            assert!(ResourceLocations::parse_from_bytes(&map[..len]).is_err());
+        }
+
+        let mut bad_type = map.clone();
+        bad_type[0] = 0x20;
+        assert!(ResourceLocations::parse_from_bytes(&bad_type).is_err());
+        let permissive = ParseOptions {
+            log_anomalies: false,
+            ..ParseOptions::permissive()
+        };
+        let locations = ResourceLocations::read_from_with_options(
+            BlockReader::new(MemBlock::from_vec(bad_type)),
+            &permissive,
+        )?;
+        assert_eq!(locations.locations().count(), 0);
+        // An entry table that ends before it starts, and one that isn't a
+        // whole number of entries.
+        for end in [4, 10] {
+            let mut bad_end = map.clone();
+            bad_end[4] = end;
+            assert!(ResourceLocations::parse_from_bytes(&bad_end).is_err());
+        }
+        Ok(())
+    }
+
+    fn read_with_quirks(map: &[u8], quirks: &[Quirk]) -> io::Result<ResourceLocations> {
+        let options = ParseOptions {
+            log_anomalies: false,
+            ..ParseOptions::strict()
+        }
+        .with_quirks(quirks.iter().copied().collect());
+        ResourceLocations::read_from_with_options(
+            BlockReader::new(MemBlock::from_vec(map.to_vec())),
+            &options,
+        )
+    }
+
+    #[test]
+    fn test_unterminated_map_index() -> io::Result<()> {
+        // An index with one type and no terminator, then its table.
+        let map = [ResourceType::Script as u8, 3, 0, 1, 0, 0x10, 0, 0];
+        assert!(ResourceLocations::parse_from_bytes(&map).is_err());
+        let locations = read_with_quirks(&map, &[Quirk::UnterminatedMapIndex])?;
+        let live: Vec<_> = locations.live_locations().collect();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].id, ResourceId::new(ResourceType::Script, 1));
+        assert_eq!(live[0].file_offset, 0x20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_map_types() -> io::Result<()> {
+        // Two tables for scripts, each with an entry for script 1.
+        let mut map = vec![
+            ResourceType::Script as u8,
+            9,
+            0,
+            ResourceType::Script as u8,
+            14,
+            0,
+            0xFF,
+            19,
+            0,
+        ];
+        for body in [0x10u32, 0x20] {
+            map.extend_from_slice(&1u16.to_le_bytes());
+            map.extend_from_slice(&body.to_le_bytes()[..3]);
+        }
+        let statuses = |locations: &ResourceLocations| -> Vec<_> {
+            locations
+                .locations()
+                .map(|location| location.status)
+                .collect()
+        };
+
+        let locations = read_with_quirks(&map, &[])?;
+        assert_eq!(statuses(&locations), [EntryStatus::Live, EntryStatus::Live]);
+
+        let locations = read_with_quirks(&map, &[Quirk::DuplicateMapTypes])?;
+        assert_eq!(
+            statuses(&locations),
+            [EntryStatus::Duplicate, EntryStatus::Live]
+        );
+        assert_eq!(locations.live_locations().count(), 1);
+        assert_eq!(
+            locations
+                .get_location(&ResourceId::new(ResourceType::Script, 1))
+                .map(|location| location.file_offset),
+            Some(0x40)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sci0_map() -> io::Result<()> {
+        let mut map = Vec::new();
+        // Script 3 in volume 1, view 5 in volume 0, then script 3 again.
+        for (id, location) in [
+            (0x1003u16, (1u32 << 26) | 0x20),
+            (0x0005, 0x10),
+            (0x1003, (2 << 26) | 0x30),
+        ] {
+            map.extend_from_slice(&id.to_le_bytes());
+            map.extend_from_slice(&location.to_le_bytes());
+        }
+        map.extend_from_slice(&[0xFF; 6]);
+        let locations = ResourceLocations::parse_from_bytes(&map)?;
+        assert_eq!(locations.format(), MapFormat::Sci0);
+        let entries: Vec<_> = locations
+            .locations()
+            .map(|location| {
+                (
+                    location.id,
+                    location.volume,
+                    location.file_offset,
+                    location.status,
+                )
+            })
+            .collect();
+        let script_3 = ResourceId::new(ResourceType::Script, 3);
+        let view_5 = ResourceId::new(ResourceType::View, 5);
+        assert_eq!(
+            entries,
+            [
+                (script_3, 1, 0x20, EntryStatus::Duplicate),
+                (script_3, 2, 0x30, EntryStatus::Live),
+                (view_5, 0, 0x10, EntryStatus::Live),
+            ]
+        );
+
+        // A SCI1.1 map is still read as one, even when it ends with bytes that
+        // look like the SCI0 terminator.
+        let mut map = vec![ResourceType::Script as u8, 6, 0, 0xFF, 36, 0];
+        for (num, body) in [(1u16, 0x10u32), (2, 0x20), (3, 0x30), (4, 0x40)] {
+            map.extend_from_slice(&num.to_le_bytes());
+            map.extend_from_slice(&body.to_le_bytes()[..3]);
+        }
+        map.extend_from_slice(&[5, 0, 0, 0, 0xFF]);
+        map.extend_from_slice(&[0xFF; 5]);
+        assert_eq!(map.len(), 36);
+        assert_eq!(
+            ResourceLocations::parse_from_bytes(&map)?.format(),
+            MapFormat::Sci11
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sci1_map() -> io::Result<()> {
+        // One type with two 6-byte entries: script 1 in volume 2, and an
+        // unused entry. Neither table size is a multiple of 5.
+        let mut map = vec![ResourceType::Script as u8, 6, 0, 0xFF, 18, 0];
+        map.extend_from_slice(&1u16.to_le_bytes());
+        map.extend_from_slice(&((2u32 << 28) | 0x1234).to_le_bytes());
+        map.extend_from_slice(&2u16.to_le_bytes());
+        map.extend_from_slice(&u32::MAX.to_le_bytes());
+        let locations = ResourceLocations::parse_from_bytes(&map)?;
+        assert_eq!(locations.format(), MapFormat::Sci1);
+        let entries: Vec<_> = locations
+            .locations()
+            .map(|location| {
+                (
+                    location.id.resource_num(),
+                    location.volume,
+                    location.file_offset,
+                    location.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (1, 2, 0x1234, EntryStatus::Live),
+                (2, 0xF, 0x0FFF_FFFF, EntryStatus::Unused),
+            ]
+        );
+        Ok(())
+    }
 }
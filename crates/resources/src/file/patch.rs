@@ -2,11 +2,44 @@ use std::{ffi::OsStr, path::Path};
 
 use sci_utils::block::BlockSource;
 
-use crate::{ResourceId, ResourceType};
+use crate::{ParseOptions, ResourceId, ResourceType};
 
 use super::Resource;
 
-pub fn try_patch_from_file(patch_file: &Path) -> anyhow::Result<Option<Resource>> {
+/// The header size byte that indicates an extended patch header.
+pub(crate) const EXTENDED_HEADER_MARKER: u8 = 0x80;
+
+/// The size of the extended header data that follows the base header.
+pub(crate) const EXTENDED_HEADER_SIZE: usize = 24;
+
+/// Returns true if patches of the given type use the extended header.
+fn uses_extended_header(res_type: ResourceType) -> bool {
+    matches!(
+        res_type,
+        ResourceType::Audio | ResourceType::Sync | ResourceType::Audio36 | ResourceType::Sync36
+    )
+}
+
+/// Returns the header that starts a patch file of the given type.
+///
+/// Most patches have a two byte header: the resource type, and the size of
+/// any extra header data (which we never write). Audio and sync patches
+/// instead mark the header as extended, followed by 24 bytes of header data,
+/// which the interpreter skips.
+pub(crate) fn patch_header(res_type: ResourceType) -> Vec<u8> {
+    if uses_extended_header(res_type) {
+        let mut header = vec![res_type.into(), EXTENDED_HEADER_MARKER];
+        header.resize(2 + EXTENDED_HEADER_SIZE, 0);
+        header
+    } else {
+        vec![res_type.into(), 0]
+    }
+}
+
+pub fn try_patch_from_file(
+    patch_file: &Path,
+    options: &ParseOptions,
+) -> anyhow::Result<Option<Resource>> {
     // Parse the filename to get the resource ID.
 
     // The stem of the file is the resource ID as an integer.
@@ -35,17 +68,29 @@ pub fn try_patch_from_file(patch_file: &Path) -> anyhow::Result<Option<Resource>
     };
 
     let source = BlockSource::from_path(patch_file.to_path_buf())?;
+    if source.size() < 2 {
+        options.out_of_bounds(format!("Patch file {patch_file:?} is too short"))?;
+        return Ok(None);
+    }
     let (base_header_block, rest) = source.split_at(2);
     let base_header = base_header_block.open()?;
     let id = base_header[0];
     let header_size = base_header[1];
-    let content_res_type: ResourceType = id.try_into()?;
-    anyhow::ensure!(
-        content_res_type == res_type,
-        "Resource type mismatch: expected {:?}, got {:?}",
-        res_type,
-        content_res_type,
-    );
+    if ResourceType::try_from(id).ok() != Some(res_type) {
+        options.unknown(format!(
+            "Resource type mismatch in {patch_file:?}: expected {res_type:?}, got {id:#x}"
+        ))?;
+        return Ok(None);
+    }
+    let header_end = if header_size == EXTENDED_HEADER_MARKER {
+        EXTENDED_HEADER_SIZE as u64
+    } else {
+        header_size as u64
+    };
+    if rest.size() < header_end {
+        options.out_of_bounds(format!("Patch file {patch_file:?} has a truncated header"))?;
+        return Ok(None);
+    }
 
     // Looking at the ScummVM source code, it
     // doesn't appear that the data is used during execution, so we can skip
@@ -54,15 +99,19 @@ pub fn try_patch_from_file(patch_file: &Path) -> anyhow::Result<Option<Resource>
     // It looks like there's a fairly simple scheme. If the byte after the type is
     // 128, then we use an extended header, including a two-byte length field,
     // and another 22 byte header data that we can skip.
-    let data = if header_size == 128 {
-        let (header_data, rest) = rest.split_at(24);
+    let data = if header_size == EXTENDED_HEADER_MARKER {
+        let (header_data, rest) = rest.split_at(EXTENDED_HEADER_SIZE as u64);
         let header_data = header_data.open()?;
         let real_header_size = header_data[1];
         if real_header_size != 0 {
-            eprintln!(
-                "Warning: patch file header size is not 0, got (size {}) {:?} ({:?}, {:?})",
+            options.warn(format_args!(
+                "patch file header size is not 0, got (size {}) {:?} ({:?}, {:?})",
                 real_header_size, header_data, patch_file, res_type,
-            );
+            ));
+        }
+        if rest.size() < real_header_size as u64 {
+            options.out_of_bounds(format!("Patch file {patch_file:?} has a truncated header"))?;
+            return Ok(None);
         }
         rest.subblock(real_header_size as u64..).to_lazy_block()
     } else {
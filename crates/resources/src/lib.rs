@@ -1,6 +1,11 @@
 pub mod file;
+mod parse_options;
+mod quirks;
 pub mod types;
 
+pub use parse_options::ParseOptions;
+pub use quirks::{Quirk, Quirks};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
 #[repr(u8)]
 pub enum ResourceType {
@@ -0,0 +1,89 @@
+use std::{fmt::Display, io};
+
+use crate::{Quirk, Quirks};
+
+/// How strictly resource files are parsed.
+///
+/// Games (and fan-made patches) sometimes contain data the parsers don't
+/// expect. When checking a game, any of it should be an error; when
+/// extracting what we can, it's better to skip it and carry on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Skip data that isn't recognized, like unknown resource types in a
+    /// map, instead of failing.
+    pub allow_unknown: bool,
+    /// Skip entries that point outside their file, like map entries past the
+    /// end of the volume or message text past the end of the resource,
+    /// instead of failing.
+    pub allow_out_of_bounds: bool,
+    /// Print a warning for each anomaly that is tolerated.
+    pub log_anomalies: bool,
+    /// The deviations from the usual formats that the game is known to have.
+    pub quirks: Quirks,
+}
+
+impl ParseOptions {
+    /// Fails on any anomaly.
+    pub fn strict() -> Self {
+        ParseOptions {
+            allow_unknown: false,
+            allow_out_of_bounds: false,
+            log_anomalies: true,
+            quirks: Quirks::none(),
+        }
+    }
+
+    /// Skips anything that can't be read, with a warning.
+    pub fn permissive() -> Self {
+        ParseOptions {
+            allow_unknown: true,
+            allow_out_of_bounds: true,
+            log_anomalies: true,
+            quirks: Quirks::none(),
+        }
+    }
+
+    /// These options, expecting the quirks.
+    pub fn with_quirks(self, quirks: Quirks) -> Self {
+        ParseOptions { quirks, ..self }
+    }
+
+    pub(crate) fn has_quirk(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(quirk)
+    }
+
+    /// Handles unrecognized data. Returns `Ok` if the caller should skip it.
+    pub(crate) fn unknown(&self, anomaly: impl Display) -> io::Result<()> {
+        self.tolerate(self.allow_unknown, anomaly)
+    }
+
+    /// Handles data outside its bounds. Returns `Ok` if the caller should
+    /// skip it.
+    pub(crate) fn out_of_bounds(&self, anomaly: impl Display) -> io::Result<()> {
+        self.tolerate(self.allow_out_of_bounds, anomaly)
+    }
+
+    /// Reports an anomaly that is always tolerated.
+    pub(crate) fn warn(&self, anomaly: impl Display) {
+        if self.log_anomalies {
+            eprintln!("Warning: {anomaly}");
+        }
+    }
+
+    fn tolerate(&self, allowed: bool, anomaly: impl Display) -> io::Result<()> {
+        if !allowed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                anomaly.to_string(),
+            ));
+        }
+        self.warn(format_args!("{anomaly}; skipping"));
+        Ok(())
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::strict()
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// A way that some releases' resource files deviate from the usual format.
+///
+/// Parsers check for the quirks in their [`ParseOptions`](crate::ParseOptions)
+/// rather than guessing, so data that is merely malformed is still reported.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quirk {
+    /// The resource map's index has no `0xFF` entry marking its end. It ends
+    /// where the first entry table starts, and the last table runs to the
+    /// end of the map.
+    UnterminatedMapIndex,
+    /// The resource map's index lists a resource type more than once. The
+    /// type's tables are read as one, so a later entry for a resource
+    /// replaces an earlier one in another table.
+    DuplicateMapTypes,
+}
+
+/// A set of [`Quirk`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks(u32);
+
+impl Quirks {
+    pub fn none() -> Self {
+        Quirks(0)
+    }
+
+    pub fn contains(&self, quirk: Quirk) -> bool {
+        self.0 & (1 << quirk as u32) != 0
+    }
+
+    pub fn insert(&mut self, quirk: Quirk) {
+        self.0 |= 1 << quirk as u32;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Quirk> + '_ {
+        <Quirk as clap::ValueEnum>::value_variants()
+            .iter()
+            .copied()
+            .filter(|quirk| self.contains(*quirk))
+    }
+}
+
+impl FromIterator<Quirk> for Quirks {
+    fn from_iter<I: IntoIterator<Item = Quirk>>(iter: I) -> Self {
+        let mut quirks = Quirks::none();
+        for quirk in iter {
+            quirks.insert(quirk);
+        }
+        quirks
+    }
+}
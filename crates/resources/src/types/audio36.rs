@@ -1,12 +1,13 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     io::{self, Cursor},
 };
 
 use anyhow::ensure;
 use bytes::BufMut;
 use sci_utils::{
-    block::{BlockSource, LazyBlock, MemBlock, output_block::OutputBlock},
+    block::{BlockReader, BlockSource, LazyBlock, MemBlock, output_block::OutputBlock},
+    buffer::BufferExt,
     data_reader::DataReader,
     data_writer::{DataWriter, IoDataWriter},
 };
@@ -71,7 +72,6 @@ impl RawMapResource {
         });
     }
 
-    #[expect(dead_code)]
     pub fn read_from<R: DataReader>(reader: &mut R) -> io::Result<RawMapResource> {
         let mut entries = Vec::new();
         loop {
@@ -95,12 +95,323 @@ impl RawMapResource {
     }
 }
 
+/// The audio36 map of a room, giving the offset of each line's clip in the
+/// audio volume (`RESOURCE.AUD`).
+pub struct Audio36Map {
+    offsets: BTreeMap<MessageId, u32>,
+}
+
+impl Audio36Map {
+    pub fn parse_from_bytes(data: &[u8]) -> io::Result<Self> {
+        Self::from_block(&MemBlock::from_vec(data.to_vec()))
+    }
+
+    pub fn from_block(block: &MemBlock) -> io::Result<Self> {
+        let raw = RawMapResource::read_from(&mut BlockReader::new(block.clone()))?;
+        Ok(Audio36Map {
+            offsets: raw
+                .entries
+                .into_iter()
+                .map(|entry| (entry.id, entry.offset))
+                .collect(),
+        })
+    }
+
+    /// Returns the offset of the clip for a line, if the line has one.
+    pub fn offset(&self, id: &MessageId) -> Option<u32> {
+        self.offsets.get(id).copied()
+    }
+
+    /// Returns the lines in the map, with the offsets of their clips.
+    pub fn entries(&self) -> impl Iterator<Item = (&MessageId, u32)> {
+        self.offsets.iter().map(|(id, offset)| (id, *offset))
+    }
+}
+
+/// A disagreement between a room's messages and its audio map. These are
+/// usually bugs in the original game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioMapMismatch {
+    /// A message with no clip in the map.
+    MissingAudio(MessageId),
+    /// A map entry with no message.
+    OrphanedAudio(MessageId),
+    /// Sequence numbers missing from a conversation's messages.
+    SequenceGap {
+        noun: u8,
+        verb: u8,
+        condition: u8,
+        missing: Vec<u8>,
+    },
+}
+
+impl std::fmt::Display for AudioMapMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fmt_id = |id: &MessageId| {
+            format!(
+                "{}-{}-{}-{}",
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence()
+            )
+        };
+        match self {
+            AudioMapMismatch::MissingAudio(id) => {
+                write!(f, "message {} has no audio", fmt_id(id))
+            }
+            AudioMapMismatch::OrphanedAudio(id) => {
+                write!(f, "audio {} has no message", fmt_id(id))
+            }
+            AudioMapMismatch::SequenceGap {
+                noun,
+                verb,
+                condition,
+                missing,
+            } => write!(
+                f,
+                "conversation {noun}-{verb}-{condition} is missing sequence {}",
+                missing
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Compares a room's messages with its audio map. Messages without audio,
+/// audio without messages, and gaps in each conversation's sequence numbers
+/// (which start at 1) are reported, in that order.
+pub fn check_audio_map(
+    messages: impl IntoIterator<Item = MessageId>,
+    map: &Audio36Map,
+) -> Vec<AudioMapMismatch> {
+    let messages: BTreeSet<MessageId> = messages.into_iter().collect();
+    let mut mismatches: Vec<_> = messages
+        .iter()
+        .filter(|id| map.offset(id).is_none())
+        .map(|id| AudioMapMismatch::MissingAudio(*id))
+        .collect();
+    mismatches.extend(
+        map.entries()
+            .filter(|(id, _)| !messages.contains(id))
+            .map(|(id, _)| AudioMapMismatch::OrphanedAudio(*id)),
+    );
+    let mut sequences: BTreeMap<(u8, u8, u8), BTreeSet<u8>> = BTreeMap::new();
+    for id in &messages {
+        sequences
+            .entry((id.noun(), id.verb(), id.condition()))
+            .or_default()
+            .insert(id.sequence());
+    }
+    for ((noun, verb, condition), present) in sequences {
+        let last = present.last().copied().unwrap_or(0);
+        let missing: Vec<u8> = (1..last).filter(|seq| !present.contains(seq)).collect();
+        if !missing.is_empty() {
+            mismatches.push(AudioMapMismatch::SequenceGap {
+                noun,
+                verb,
+                condition,
+                missing,
+            });
+        }
+    }
+    mismatches
+}
+
+/// DPCM-compressed samples.
+const SOL_FLAG_COMPRESSED: u8 = 0x01;
+/// 16-bit samples, rather than 8-bit.
+const SOL_FLAG_16BIT: u8 = 0x04;
+/// Signed samples.
+const SOL_FLAG_SIGNED: u8 = 0x08;
+
+/// The sample format of a SOL clip, from its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SolFormat {
+    pub sample_rate: u16,
+    /// 16-bit signed samples, rather than 8-bit unsigned.
+    pub sixteen_bit: bool,
+    /// DPCM-compressed samples.
+    pub compressed: bool,
+}
+
+impl SolFormat {
+    /// Reads the format from the header of a SOL clip.
+    pub fn from_clip(clip: &MemBlock) -> anyhow::Result<Self> {
+        Ok(SolHeader::read_from(clip.clone())?.format)
+    }
+}
+
+impl std::fmt::Display for SolFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} Hz {}-bit",
+            self.sample_rate,
+            if self.sixteen_bit { 16 } else { 8 }
+        )?;
+        if self.compressed {
+            write!(f, " (compressed)")?;
+        }
+        Ok(())
+    }
+}
+
+struct SolHeader {
+    header_size: u8,
+    format: SolFormat,
+    data_size: u32,
+}
+
+impl SolHeader {
+    fn read_from(clip: MemBlock) -> anyhow::Result<Self> {
+        let mut reader = BlockReader::new(clip);
+        let _resource_type = reader.read_u8()?;
+        let header_size = reader.read_u8()?;
+        let mut magic = [0u8; 4];
+        for byte in &mut magic {
+            *byte = reader.read_u8()?;
+        }
+        ensure!(&magic == b"SOL\0", "Not a SOL clip");
+        let sample_rate = reader.read_u16_le()?;
+        let flags = reader.read_u8()?;
+        let data_size = reader.read_u32_le()?;
+        Ok(SolHeader {
+            header_size,
+            format: SolFormat {
+                sample_rate,
+                sixteen_bit: flags & SOL_FLAG_16BIT != 0,
+                compressed: flags & SOL_FLAG_COMPRESSED != 0,
+            },
+            data_size,
+        })
+    }
+}
+
+/// Returns the clip at the offset in an uncompressed audio volume, as a
+/// complete SOL file.
+///
+/// Each clip starts with a SOL header, which includes the size of the sample
+/// data following it.
+pub fn read_sol_clip(volume: &MemBlock, offset: u32) -> anyhow::Result<MemBlock> {
+    let clip = volume.clone().sub_buffer(offset as usize..);
+    let header = SolHeader::read_from(clip.clone()).map_err(|e| {
+        anyhow::anyhow!("No SOL clip found at offset {offset} of the audio volume: {e}")
+    })?;
+    // The header size doesn't include the first two bytes.
+    let clip_size = 2 + header.header_size as usize + header.data_size as usize;
+    ensure!(
+        clip_size <= clip.size(),
+        "SOL clip at offset {offset} extends past the end of the audio volume"
+    );
+    Ok(clip.sub_buffer(..clip_size))
+}
+
+fn write_sol_clip_with_flags(
+    sample_rate: u16,
+    flags: u8,
+    samples: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let data_size = u32::try_from(samples.len())?;
+    let mut clip = Vec::with_capacity(13 + samples.len());
+    clip.push(ResourceType::Audio as u8);
+    clip.push(11);
+    clip.extend_from_slice(b"SOL\0");
+    clip.extend_from_slice(&sample_rate.to_le_bytes());
+    clip.push(flags);
+    clip.extend_from_slice(&data_size.to_le_bytes());
+    clip.extend_from_slice(samples);
+    Ok(clip)
+}
+
+/// Wraps unsigned 8-bit mono PCM samples in a SOL header, as used by the
+/// original SCI1.1 audio volumes.
+pub fn write_sol_clip(sample_rate: u16, samples: &[u8]) -> anyhow::Result<Vec<u8>> {
+    // Uncompressed, unsigned 8-bit samples, so none of the flags are set.
+    write_sol_clip_with_flags(sample_rate, 0, samples)
+}
+
+/// Wraps signed 16-bit little-endian mono PCM samples in a SOL header, as
+/// used by later SCI1.1 releases.
+pub fn write_sol_clip_16bit(sample_rate: u16, samples: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        samples.len().is_multiple_of(2),
+        "16-bit sample data has an odd number of bytes"
+    );
+    write_sol_clip_with_flags(sample_rate, SOL_FLAG_16BIT | SOL_FLAG_SIGNED, samples)
+}
+
+/// The step sizes of 8-bit DPCM. Each 4-bit code indexes the table with its
+/// low three bits, and its high bit makes the step negative.
+const DPCM8_STEPS: [u8; 8] = [0, 1, 2, 3, 6, 10, 15, 21];
+
+/// Decodes 8-bit DPCM data to unsigned 8-bit PCM samples. Each byte holds
+/// two codes, high nibble first, and decoding starts from silence.
+pub fn decode_dpcm8(data: &[u8]) -> Vec<u8> {
+    let mut sample = 0x80u8;
+    let mut samples = Vec::with_capacity(data.len() * 2);
+    for byte in data {
+        for code in [byte >> 4, byte & 0x0F] {
+            let step = DPCM8_STEPS[usize::from(code & 0x07)];
+            sample = if code & 0x08 != 0 {
+                sample.saturating_sub(step)
+            } else {
+                sample.saturating_add(step)
+            };
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// Encodes unsigned 8-bit PCM samples as 8-bit DPCM, picking for each sample
+/// the step that lands closest to it. Steps never leave the 8-bit range, so
+/// decoders that clip and decoders that wrap give the same samples. An odd
+/// number of samples is padded with a final zero step.
+pub fn encode_dpcm8(samples: &[u8]) -> Vec<u8> {
+    let mut sample = 0x80u8;
+    let mut codes = Vec::with_capacity(samples.len() + 1);
+    for &target in samples {
+        let (code, next) = (0u8..16)
+            .filter_map(|code| {
+                let step = DPCM8_STEPS[usize::from(code & 0x07)];
+                let next = if code & 0x08 != 0 {
+                    sample.checked_sub(step)
+                } else {
+                    sample.checked_add(step)
+                }?;
+                Some((code, next))
+            })
+            .min_by_key(|(_, next)| next.abs_diff(target))
+            .expect("A zero step is always possible");
+        codes.push(code);
+        sample = next;
+    }
+    if codes.len() % 2 == 1 {
+        codes.push(0);
+    }
+    codes
+        .chunks_exact(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect()
+}
+
+/// Compresses unsigned 8-bit mono PCM samples with DPCM, and wraps them in a
+/// SOL header.
+pub fn write_sol_clip_dpcm8(sample_rate: u16, samples: &[u8]) -> anyhow::Result<Vec<u8>> {
+    write_sol_clip_with_flags(sample_rate, SOL_FLAG_COMPRESSED, &encode_dpcm8(samples))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum AudioFormat {
     Mp3,
     Flac,
     Ogg,
     Wav,
+    Sol,
 }
 
 pub struct VoiceSample {
@@ -167,8 +478,8 @@ impl AudioVolumeBuilder {
         // The offset of the next entry depends on the audio format, aas well as the size of
         // the current entry.
         //
-        // WAV files are simply written directly to the file, as it contains all information about
-        // the length of the file.
+        // WAV and SOL files are simply written directly to the file, as their headers contain all
+        // information about the length of the file.
         //
         // For other compressed formats, we initial compressed map table will provide the lengths,
         // but also provides the mapping from the logical offset to the actual offset in the
@@ -215,9 +526,9 @@ impl AudioVolumeBuilder {
             Some(AudioFormat::Mp3) => self.to_raw_of_compressed_format(b"MP3 "),
             Some(AudioFormat::Flac) => self.to_raw_of_compressed_format(b"FLAC"),
             Some(AudioFormat::Ogg) => self.to_raw_of_compressed_format(b"OGG "),
-            Some(AudioFormat::Wav) => {
-                // WAV files are not treated as compressed, so we can just
-                // concatenate the entries together.
+            Some(AudioFormat::Wav | AudioFormat::Sol) => {
+                // WAV and SOL files are not treated as compressed, so we can
+                // just concatenate the entries together.
                 let mut volume_blocks = Vec::new();
                 for entry in &self.entries {
                     volume_blocks.push(OutputBlock::from_buffer(entry.data.clone()));
@@ -289,6 +600,23 @@ impl Default for Audio36ResourceBuilder {
     }
 }
 
+/// The number of the map that indexes a game's other audio (sound effects
+/// and music played as digital audio), rather than a room's speech.
+pub const AUDIO_INDEX_MAP_NUM: u16 = 65535;
+
+/// An audio index map with no entries, for games that shipped without any
+/// digital audio. Interpreters that load the index at startup expect it
+/// alongside the room maps.
+///
+/// Its one entry is the terminator: a resource number of `0xFFFF`, padded
+/// to the six bytes of an early SCI1.1 index entry.
+pub fn empty_audio_index() -> Resource {
+    Resource::new(
+        ResourceId::new(ResourceType::Map, AUDIO_INDEX_MAP_NUM),
+        LazyBlock::from_mem_block(MemBlock::from_vec(vec![0xFF; 6])),
+    )
+}
+
 pub struct VoiceSampleResources {
     map_resources: Vec<Resource>,
     audio_volume: OutputBlock,
@@ -303,3 +631,130 @@ impl VoiceSampleResources {
         &self.audio_volume
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_sol_clip() -> anyhow::Result<()> {
+        let first = write_sol_clip(11025, &[1, 2, 3])?;
+        let second = write_sol_clip(22050, &[4, 5, 6, 7])?;
+        let mut volume = first.clone();
+        volume.extend_from_slice(&second);
+        let volume = MemBlock::from_vec(volume);
+
+        assert_eq!(&read_sol_clip(&volume, 0)?[..], &first[..]);
+        assert_eq!(
+            &read_sol_clip(&volume, first.len() as u32)?[..],
+            &second[..]
+        );
+        assert!(read_sol_clip(&volume, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_audio_index() -> anyhow::Result<()> {
+        let index = empty_audio_index();
+        assert_eq!(
+            index.id(),
+            &ResourceId::new(ResourceType::Map, AUDIO_INDEX_MAP_NUM)
+        );
+        assert_eq!(&index.load_data()?[..], &[0xFF; 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_audio_map() -> anyhow::Result<()> {
+        let mut map = RawMapResource::new();
+        map.add_entry(MessageId::new(1, 2, 0, 1), 0);
+        map.add_entry(MessageId::new(1, 2, 0, 3), 100);
+        map.add_entry(MessageId::new(5, 2, 0, 1), 200);
+        let mut data = Vec::new();
+        map.write_to(&mut IoDataWriter::new(&mut Cursor::new(&mut data)))?;
+        let map = Audio36Map::from_block(&MemBlock::from_vec(data))?;
+
+        let messages = [
+            MessageId::new(1, 2, 0, 1),
+            MessageId::new(1, 2, 0, 3),
+            MessageId::new(1, 2, 0, 4),
+        ];
+        assert_eq!(
+            check_audio_map(messages, &map),
+            vec![
+                AudioMapMismatch::MissingAudio(MessageId::new(1, 2, 0, 4)),
+                AudioMapMismatch::OrphanedAudio(MessageId::new(5, 2, 0, 1)),
+                AudioMapMismatch::SequenceGap {
+                    noun: 1,
+                    verb: 2,
+                    condition: 0,
+                    missing: vec![2],
+                },
+            ]
+        );
+        assert_eq!(
+            AudioMapMismatch::MissingAudio(MessageId::new(1, 2, 0, 4)).to_string(),
+            "message 1-2-0-4 has no audio"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sol_format() -> anyhow::Result<()> {
+        let clip = MemBlock::from_vec(write_sol_clip(11025, &[1, 2, 3])?);
+        assert_eq!(
+            SolFormat::from_clip(&clip)?,
+            SolFormat {
+                sample_rate: 11025,
+                sixteen_bit: false,
+                compressed: false
+            }
+        );
+        let clip = MemBlock::from_vec(write_sol_clip_16bit(22050, &[1, 2, 3, 4])?);
+        let format = SolFormat::from_clip(&clip)?;
+        assert!(format.sixteen_bit);
+        assert_eq!(format.to_string(), "22050 Hz 16-bit");
+        assert_eq!(&read_sol_clip(&clip, 0)?[..], &clip[..]);
+        assert!(write_sol_clip_16bit(22050, &[1, 2, 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dpcm8_round_trip() -> anyhow::Result<()> {
+        // Slow changes are reproduced exactly, and fast ones are followed as
+        // closely as the steps allow.
+        let samples = [0x80, 0x81, 0x83, 0x86, 0x8C, 0xFF, 0x00, 0x80, 0x7F];
+        let encoded = encode_dpcm8(&samples);
+        assert_eq!(encoded.len(), 5);
+        let decoded = decode_dpcm8(&encoded);
+        assert_eq!(decoded.len(), 10);
+        assert_eq!(&decoded[..5], &samples[..5]);
+        assert_eq!(decoded[5..], [0xA1, 0x8C, 0x82, 0x7F, 0x7F]);
+
+        let clip = MemBlock::from_vec(write_sol_clip_dpcm8(11025, &samples)?);
+        assert_eq!(
+            SolFormat::from_clip(&clip)?,
+            SolFormat {
+                sample_rate: 11025,
+                sixteen_bit: false,
+                compressed: true
+            }
+        );
+        assert_eq!(&read_sol_clip(&clip, 0)?[13..], &encoded[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_map() -> anyhow::Result<()> {
+        let mut map = RawMapResource::new();
+        map.add_entry(MessageId::new(1, 2, 0, 1), 0);
+        map.add_entry(MessageId::new(1, 2, 0, 2), 100);
+        let mut data = Vec::new();
+        map.write_to(&mut IoDataWriter::new(&mut Cursor::new(&mut data)))?;
+
+        let map = Audio36Map::from_block(&MemBlock::from_vec(data))?;
+        assert_eq!(map.offset(&MessageId::new(1, 2, 0, 2)), Some(100));
+        assert_eq!(map.offset(&MessageId::new(1, 2, 0, 3)), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! Cursor resources: 16x16 pixels in two bit planes, giving four fixed
+//! colors (black, white, gray and transparent), and a hotspot.
+//!
+//! From SCI01 on, the hotspot is the point within the cursor that the mouse
+//! position refers to. Earlier interpreters only read the high byte of the
+//! hotspot's Y, as a flag centering the hotspot if it's set.
+
+use anyhow::ensure;
+
+use super::palette::Palette;
+
+/// The width and height of a cursor.
+pub const CURSOR_SIZE: u16 = 16;
+
+/// The pixel values, from a pixel's bit in each plane: the first plane adds
+/// 1 and the second 2.
+pub const BLACK: u8 = 0;
+pub const WHITE: u8 = 1;
+pub const TRANSPARENT: u8 = 2;
+pub const GRAY: u8 = 3;
+
+/// The size of a cursor resource: the hotspot, then each plane's rows.
+const DATA_SIZE: usize = 4 + 2 * 2 * CURSOR_SIZE as usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub hotspot_x: u16,
+    pub hotspot_y: u16,
+    /// Pixel values, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl Cursor {
+    /// The colors the interpreter draws cursors with, with the transparent
+    /// value unset.
+    pub fn palette() -> Palette {
+        let mut palette = Palette::empty();
+        palette.set_color(BLACK, [0x00, 0x00, 0x00]);
+        palette.set_color(WHITE, [0xFF, 0xFF, 0xFF]);
+        palette.set_color(GRAY, [0xAA, 0xAA, 0xAA]);
+        palette
+    }
+
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        ensure!(
+            data.len() >= DATA_SIZE,
+            "Cursor data is {} bytes, not {DATA_SIZE}",
+            data.len()
+        );
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let size = usize::from(CURSOR_SIZE);
+        let mut pixels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            let (first, second) = (word(4 + y * 2), word(4 + size * 2 + y * 2));
+            pixels.extend((0..size).map(|x| {
+                let bit = |plane: u16| u8::from(plane & (0x8000 >> x) != 0);
+                bit(first) | (bit(second) << 1)
+            }));
+        }
+        Ok(Cursor {
+            hotspot_x: word(0),
+            hotspot_y: word(2),
+            pixels,
+        })
+    }
+
+    /// Whether the hotspot is within the cursor.
+    pub fn hotspot_inside(&self) -> bool {
+        self.hotspot_x < CURSOR_SIZE && self.hotspot_y < CURSOR_SIZE
+    }
+}
+
+/// Builds a cursor resource.
+pub fn build_cursor(cursor: &Cursor) -> anyhow::Result<Vec<u8>> {
+    let size = usize::from(CURSOR_SIZE);
+    ensure!(
+        cursor.pixels.len() == size * size,
+        "The cursor has {} pixels, not {}",
+        cursor.pixels.len(),
+        size * size
+    );
+    if let Some(value) = cursor.pixels.iter().find(|&&value| value > GRAY) {
+        anyhow::bail!("Cursor pixels are 0 to 3, not {value}");
+    }
+    let mut data = Vec::with_capacity(DATA_SIZE);
+    data.extend(cursor.hotspot_x.to_le_bytes());
+    data.extend(cursor.hotspot_y.to_le_bytes());
+    for plane in 0..2 {
+        for row in cursor.pixels.chunks(size) {
+            let bits = row
+                .iter()
+                .enumerate()
+                .filter(|&(_, &value)| value & (1 << plane) != 0)
+                .fold(0u16, |bits, (x, _)| bits | (0x8000 >> x));
+            data.extend(bits.to_le_bytes());
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_and_parse() -> anyhow::Result<()> {
+        let mut pixels = vec![TRANSPARENT; 256];
+        pixels[0] = BLACK;
+        pixels[1] = WHITE;
+        pixels[17] = GRAY;
+        pixels[255] = WHITE;
+        let cursor = Cursor {
+            hotspot_x: 1,
+            hotspot_y: 1,
+            pixels,
+        };
+        let data = build_cursor(&cursor)?;
+        assert_eq!(data.len(), DATA_SIZE);
+        assert_eq!(data[..4], [1, 0, 1, 0]);
+        // The first plane's first row: white at x=1.
+        assert_eq!(data[4..6], 0x4000u16.to_le_bytes());
+        // The second plane's first row: transparent but for x=0 and x=1.
+        assert_eq!(data[36..38], 0x3FFFu16.to_le_bytes());
+        assert_eq!(Cursor::parse(&data)?, cursor);
+        assert!(cursor.hotspot_inside());
+        assert!(Cursor::parse(&data[..DATA_SIZE - 1]).is_err());
+
+        let mut bad = cursor.clone();
+        bad.pixels[3] = 4;
+        assert!(build_cursor(&bad).is_err());
+        bad.pixels.pop();
+        assert!(build_cursor(&bad).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+//! SCI fonts: a line height, and a one-bit bitmap for each character code.
+//! Fonts are decoded with [`Font::parse`], read from BDF fonts with
+//! [`Font::from_bdf`], and built (e.g. with characters added for a
+//! translation) with [`build_font`].
+
+use anyhow::ensure;
+use sci_utils::encoding::CodePage;
+
+/// The size of the header: an unused word, the character count and the line
+/// height. The glyph offsets follow it.
+const HEADER_SIZE: usize = 6;
+
+/// A character's bitmap.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Glyph {
+    /// How far the character advances the text.
+    pub width: u8,
+    pub height: u8,
+    /// Whether each pixel is drawn, row by row.
+    pub pixels: Vec<bool>,
+}
+
+impl Glyph {
+    fn row_bytes(&self) -> usize {
+        usize::from(self.width).div_ceil(8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    pub line_height: u16,
+    /// The glyphs, indexed by character code.
+    pub glyphs: Vec<Glyph>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow::anyhow!("Font data is truncated at {offset}"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// A BDF font's ascent and line height, from its properties or else its
+/// bounding box.
+fn bdf_line_metrics(
+    ascent: Option<i32>,
+    descent: Option<i32>,
+    bounding_box: Option<&[i32]>,
+) -> Option<(i32, i32)> {
+    match (ascent, descent, bounding_box) {
+        (Some(ascent), Some(descent), _) => Some((ascent, ascent + descent)),
+        (_, _, Some(&[_, height, _, y_offset])) => Some((height + y_offset, height)),
+        _ => None,
+    }
+}
+
+impl Font {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let count = usize::from(read_u16(data, 2)?);
+        let line_height = read_u16(data, 4)?;
+        let glyphs = (0..count)
+            .map(|code| {
+                let offset = usize::from(read_u16(data, HEADER_SIZE + code * 2)?);
+                let truncated = || anyhow::anyhow!("Glyph {code} is past the end of the font");
+                let [width, height] = *data
+                    .get(offset..offset + 2)
+                    .and_then(|size| size.first_chunk())
+                    .ok_or_else(truncated)?;
+                let mut glyph = Glyph {
+                    width,
+                    height,
+                    pixels: Vec::with_capacity(usize::from(width) * usize::from(height)),
+                };
+                let row_bytes = glyph.row_bytes();
+                let bitmap = data
+                    .get(offset + 2..offset + 2 + row_bytes * usize::from(height))
+                    .ok_or_else(truncated)?;
+                for row in bitmap.chunks(row_bytes.max(1)).take(usize::from(height)) {
+                    glyph.pixels.extend(
+                        (0..usize::from(width)).map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0),
+                    );
+                }
+                Ok(glyph)
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Font {
+            line_height,
+            glyphs,
+        })
+    }
+
+    /// Reads the glyphs of a BDF font, converting their encodings (taken as
+    /// Unicode) to the code page's. Each glyph is as tall as the font's
+    /// lines, with the font's baseline. Returns the font with the
+    /// characters the code page can't hold, which are left out.
+    pub fn from_bdf(text: &str, code_page: CodePage) -> anyhow::Result<(Self, Vec<char>)> {
+        let mut ascent = None;
+        let mut descent = None;
+        let mut bounding_box = None;
+        let mut glyphs: Vec<(u8, Glyph)> = Vec::new();
+        let mut unmapped = Vec::new();
+        let mut lines = text.lines().enumerate();
+        let numbers = |line_num: usize, fields: &[&str]| -> anyhow::Result<Vec<i32>> {
+            fields
+                .iter()
+                .map(|field| {
+                    field.parse().map_err(|_| {
+                        anyhow::anyhow!("Line {}: {field:?} isn't a number", line_num + 1)
+                    })
+                })
+                .collect()
+        };
+        while let Some((line_num, line)) = lines.next() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FONT_ASCENT", value] => ascent = Some(numbers(line_num, &[value])?[0]),
+                ["FONT_DESCENT", value] => descent = Some(numbers(line_num, &[value])?[0]),
+                ["FONTBOUNDINGBOX", rest @ ..] => bounding_box = Some(numbers(line_num, rest)?),
+                ["STARTCHAR", ..] => {
+                    let (ascent, line_height) =
+                        bdf_line_metrics(ascent, descent, bounding_box.as_deref()).ok_or_else(
+                            || {
+                                anyhow::anyhow!(
+                                    "Line {}: the font's ascent isn't given before its characters",
+                                    line_num + 1
+                                )
+                            },
+                        )?;
+                    let mut encoding = None;
+                    let mut advance = None;
+                    let mut bbx = None;
+                    let mut rows = Vec::new();
+                    let mut in_bitmap = false;
+                    for (line_num, line) in lines.by_ref() {
+                        let fields: Vec<&str> = line.split_whitespace().collect();
+                        match fields.as_slice() {
+                            ["ENDCHAR"] => break,
+                            ["ENCODING", code, ..] => {
+                                encoding = Some(numbers(line_num, &[code])?[0])
+                            }
+                            ["DWIDTH", x, ..] => advance = Some(numbers(line_num, &[x])?[0]),
+                            ["BBX", rest @ ..] => bbx = Some(numbers(line_num, rest)?),
+                            ["BITMAP"] => in_bitmap = true,
+                            [hex] if in_bitmap => rows.push(
+                                u64::from_str_radix(hex, 16)
+                                    .map(|bits| (bits, hex.len() * 4))
+                                    .map_err(|_| {
+                                        anyhow::anyhow!("Line {}: {hex:?} isn't hex", line_num + 1)
+                                    })?,
+                            ),
+                            _ => {}
+                        }
+                    }
+                    let Some(code) = encoding
+                        .and_then(|code| u32::try_from(code).ok())
+                        .and_then(char::from_u32)
+                    else {
+                        continue;
+                    };
+                    let Some(byte) = code_page
+                        .encode(&code.to_string())
+                        .ok()
+                        .and_then(|bytes| bytes.first().copied())
+                    else {
+                        unmapped.push(code);
+                        continue;
+                    };
+                    let [width, height, x_offset, y_offset] = bbx
+                        .as_deref()
+                        .and_then(|bbx| bbx.first_chunk().copied())
+                        .ok_or_else(|| anyhow::anyhow!("Character {code:?} has no BBX"))?;
+                    let advance = advance.unwrap_or(width + x_offset);
+                    let glyph_width = u8::try_from(advance)
+                        .map_err(|_| anyhow::anyhow!("Character {code:?} is too wide"))?;
+                    let glyph_height = u8::try_from(line_height)
+                        .map_err(|_| anyhow::anyhow!("The font's lines are too tall"))?;
+                    let mut pixels =
+                        vec![false; usize::from(glyph_width) * usize::from(glyph_height)];
+                    // The bitmap's top row, counting down from the top of the
+                    // line.
+                    let top = ascent - (y_offset + height);
+                    for (row, &(bits, row_width)) in rows.iter().enumerate().take(height as usize) {
+                        for col in 0..width.min(row_width as i32) {
+                            if bits & (1 << (row_width as i32 - 1 - col)) == 0 {
+                                continue;
+                            }
+                            let (x, y) = (x_offset + col, top + row as i32);
+                            if (0..i32::from(glyph_width)).contains(&x)
+                                && (0..i32::from(glyph_height)).contains(&y)
+                            {
+                                pixels[y as usize * usize::from(glyph_width) + x as usize] = true;
+                            }
+                        }
+                    }
+                    glyphs.push((
+                        byte,
+                        Glyph {
+                            width: glyph_width,
+                            height: glyph_height,
+                            pixels,
+                        },
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let (_, line_height) = bdf_line_metrics(ascent, descent, bounding_box.as_deref())
+            .ok_or_else(|| {
+                anyhow::anyhow!("The BDF font has no FONT_ASCENT, FONT_DESCENT or FONTBOUNDINGBOX")
+            })?;
+        let count = glyphs
+            .iter()
+            .map(|(byte, _)| usize::from(*byte) + 1)
+            .max()
+            .unwrap_or(0);
+        let mut font = Font {
+            line_height: u16::try_from(line_height)?,
+            glyphs: vec![Glyph::default(); count],
+        };
+        for (byte, glyph) in glyphs {
+            font.glyphs[usize::from(byte)] = glyph;
+        }
+        Ok((font, unmapped))
+    }
+
+    /// Compares the font's metrics with those of the font it replaces.
+    pub fn check_metrics(&self, original: &Font) -> MetricsCheck {
+        let mut check = MetricsCheck::default();
+        for (code, glyph) in self.glyphs.iter().enumerate() {
+            let code = code as u8;
+            match original.glyphs.get(usize::from(code)) {
+                Some(old) if old.width != glyph.width => {
+                    check.widths_changed.push((code, old.width, glyph.width));
+                }
+                None if glyph.width > 0 => check.added.push(code),
+                _ => {}
+            }
+            if u16::from(glyph.height) > self.line_height {
+                check.too_tall.push(code);
+            }
+        }
+        check.removed = original.glyphs.len().saturating_sub(self.glyphs.len());
+        check
+    }
+}
+
+/// The differences between a font's metrics and those of the font it
+/// replaces. Changed widths move text the game laid out by hand (e.g. in
+/// fixed-size windows), and glyphs taller than the line overlap the next.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MetricsCheck {
+    /// Character codes whose widths changed, with the old and new widths.
+    pub widths_changed: Vec<(u8, u8, u8)>,
+    /// Character codes the original font didn't have.
+    pub added: Vec<u8>,
+    /// Character codes whose glyphs are taller than the font's lines.
+    pub too_tall: Vec<u8>,
+    /// How many character codes at the end of the original font are gone.
+    pub removed: usize,
+}
+
+/// Builds a font resource.
+pub fn build_font(font: &Font) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        !font.glyphs.is_empty() && font.glyphs.len() <= 256,
+        "A font has 1 to 256 characters, not {}",
+        font.glyphs.len()
+    );
+    let mut data = vec![0u8; HEADER_SIZE + font.glyphs.len() * 2];
+    data[2..4].copy_from_slice(&(font.glyphs.len() as u16).to_le_bytes());
+    data[4..6].copy_from_slice(&font.line_height.to_le_bytes());
+    for (code, glyph) in font.glyphs.iter().enumerate() {
+        ensure!(
+            glyph.pixels.len() == usize::from(glyph.width) * usize::from(glyph.height),
+            "Glyph {code} has {} pixels, not {}x{}",
+            glyph.pixels.len(),
+            glyph.width,
+            glyph.height
+        );
+        let offset = u16::try_from(data.len())
+            .map_err(|_| anyhow::anyhow!("The font is too large; fonts are at most 64 KiB"))?;
+        data[HEADER_SIZE + code * 2..HEADER_SIZE + code * 2 + 2]
+            .copy_from_slice(&offset.to_le_bytes());
+        data.extend([glyph.width, glyph.height]);
+        for row in glyph.pixels.chunks(usize::from(glyph.width).max(1)) {
+            let mut bytes = vec![0u8; glyph.row_bytes()];
+            for (x, _) in row.iter().enumerate().filter(|(_, set)| **set) {
+                bytes[x / 8] |= 0x80 >> (x % 8);
+            }
+            data.extend(bytes);
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(width: u8, rows: &[&str]) -> Glyph {
+        Glyph {
+            width,
+            height: rows.len() as u8,
+            pixels: rows
+                .iter()
+                .flat_map(|row| row.chars().map(|c| c == '#'))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_and_parse() -> anyhow::Result<()> {
+        let font = Font {
+            line_height: 3,
+            glyphs: vec![
+                Glyph::default(),
+                glyph(2, &["#.", ".#", "##"]),
+                glyph(10, &["#........#", ".########."]),
+            ],
+        };
+        let data = build_font(&font)?;
+        assert_eq!(data[..6], [0, 0, 3, 0, 3, 0]);
+        // The wide glyph's rows take two bytes each.
+        assert_eq!(data[data.len() - 6..], [10, 2, 0x80, 0x40, 0x7F, 0x80]);
+        assert_eq!(Font::parse(&data)?, font);
+        assert!(Font::parse(&data[..data.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_bdf() -> anyhow::Result<()> {
+        let bdf = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 5 0 -1
+STARTPROPERTIES 2
+FONT_ASCENT 4
+FONT_DESCENT 1
+ENDPROPERTIES
+CHARS 3
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 4 0 0
+BITMAP
+40
+A0
+E0
+A0
+ENDCHAR
+STARTCHAR eacute
+ENCODING 233
+DWIDTH 3 0
+BBX 2 2 1 -1
+BITMAP
+C0
+80
+ENDCHAR
+STARTCHAR snowman
+ENCODING 9731
+DWIDTH 4 0
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+        let (font, unmapped) = Font::from_bdf(bdf, CodePage::Cp437)?;
+        assert_eq!(unmapped, ['☃']);
+        assert_eq!(font.line_height, 5);
+        // é is 0x82 in CP437.
+        assert_eq!(font.glyphs.len(), 0x83);
+        assert_eq!(
+            font.glyphs[65],
+            glyph(4, &[".#..", "#.#.", "###.", "#.#.", "...."])
+        );
+        assert_eq!(
+            font.glyphs[0x82],
+            glyph(3, &["...", "...", "...", ".##", ".#."])
+        );
+        assert_eq!(font.glyphs[0], Glyph::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_metrics() {
+        let original = Font {
+            line_height: 2,
+            glyphs: vec![glyph(1, &["#", "#"]), glyph(2, &["##", ".."])],
+        };
+        let font = Font {
+            line_height: 2,
+            glyphs: vec![
+                glyph(1, &["#", "#"]),
+                glyph(3, &["###", "...", "#.."]),
+                Glyph::default(),
+                glyph(1, &["#", "."]),
+            ],
+        };
+        let check = font.check_metrics(&original);
+        assert_eq!(check.widths_changed, [(1, 2, 3)]);
+        assert_eq!(check.added, [3]);
+        assert_eq!(check.too_tall, [1]);
+        assert_eq!(check.removed, 0);
+        assert_eq!(original.check_metrics(&font).removed, 2);
+    }
+}
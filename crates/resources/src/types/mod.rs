@@ -1,2 +1,11 @@
+#[cfg(feature = "audio")]
 pub mod audio36;
+pub mod cursor;
+pub mod font;
 pub mod msg;
+pub mod palette;
+pub mod pic;
+pub mod quantize;
+#[cfg(feature = "audio")]
+pub mod sync36;
+pub mod view;
@@ -3,11 +3,15 @@ use std::collections::BTreeMap;
 use sci_utils::{
     block::{BlockReader, MemBlock},
     buffer::BufferExt,
-    data_reader::DataReader,
+    data_layout::{DataField, DataLayout},
+    data_reader::{DataReader, Endian},
+    encoding::CodePage,
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::ParseOptions;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct MessageId {
     noun: u8,
@@ -49,78 +53,135 @@ struct RawMessageRecord {
     ref_id: MessageId,
     text_offset: u16,
     talker: u8,
+    unknown: u8,
 }
 
 #[derive(Debug)]
 pub struct MessageRecord {
-    _ref_id: MessageId,
+    ref_id: MessageId,
     text: String,
     talker: u8,
+    unknown: u8,
 }
 
 impl MessageRecord {
+    /// A message with no reference.
+    pub fn new(talker: u8, text: impl Into<String>) -> Self {
+        MessageRecord {
+            ref_id: MessageId::new(0, 0, 0, 1),
+            text: text.into(),
+            talker,
+            unknown: 0,
+        }
+    }
+
     pub fn text(&self) -> &str {
         &self.text
     }
 
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+    }
+
     pub fn talker(&self) -> u8 {
         self.talker
     }
-}
 
-fn parse_message_resource_v4(msg_res: MemBlock) -> anyhow::Result<Vec<RawMessageRecord>> {
-    let mut reader = BlockReader::new(msg_res);
-    let _header_data = reader.read_u32_le()?;
-    let message_count = reader.read_u16_le()?;
+    /// The reference fields of the message's entry, as stored. The sequence
+    /// is always 1. See [`MessageRecord::ref_target`].
+    pub fn ref_id(&self) -> &MessageId {
+        &self.ref_id
+    }
 
-    let mut raw_msg_records = Vec::new();
-    for _ in 0..message_count {
-        let id = {
-            let noun = reader.read_u8()?;
-            let verb = reader.read_u8()?;
-            let condition = reader.read_u8()?;
-            let sequence = reader.read_u8()?;
-            MessageId {
-                noun,
-                verb,
-                condition,
-                sequence,
-            }
-        };
+    /// The first message of the conversation this message refers to, if any.
+    ///
+    /// A message with a reference stands in for the whole referenced
+    /// conversation: the game plays that conversation (following any
+    /// references in it), then continues with this message's conversation.
+    /// Such messages usually have no text of their own.
+    pub fn ref_target(&self) -> Option<MessageId> {
+        let ref_id = self.ref_id;
+        (ref_id.noun != 0 || ref_id.verb != 0 || ref_id.condition != 0).then_some(ref_id)
+    }
 
-        let talker = reader.read_u8()?;
-        let text_offset = reader.read_u16_le()?;
-
-        let ref_id = {
-            let noun = reader.read_u8()?;
-            let verb = reader.read_u8()?;
-            let condition = reader.read_u8()?;
-            MessageId {
-                noun,
-                verb,
-                condition,
-                sequence: 1,
-            }
-        };
+    /// The last byte of the message's entry, whose purpose is unknown.
+    pub fn unknown(&self) -> u8 {
+        self.unknown
+    }
+}
 
-        // According to ScummVM, the record size is 11, but I don't know the purpose of
-        // the last byte.
-        let _unknown = reader.read_u8()?;
+sci_utils::data_layout! {
+    struct MessageHeaderV4 {
+        // Appears to point at the end of the text data, relative to some
+        // fixed position.
+        end_offset: u16,
+        unknown: u16,
+        message_count: u16,
+    }
+}
 
-        let raw_record = RawMessageRecord {
-            id,
-            ref_id,
-            text_offset,
-            talker,
-        };
+sci_utils::data_layout! {
+    // According to ScummVM, the record size is 11, but I don't know the purpose of
+    // the last byte.
+    struct MessageEntryV4 {
+        noun: u8,
+        verb: u8,
+        condition: u8,
+        sequence: u8,
+        talker: u8,
+        text_offset: u16,
+        ref_noun: u8,
+        ref_verb: u8,
+        ref_condition: u8,
+        unknown: u8,
+    }
+}
 
-        raw_msg_records.push(raw_record);
+const MESSAGE_HEADER_V4_OFFSET: usize = 4;
+
+fn parse_message_resource_v4(
+    msg_res: MemBlock,
+    endian: Endian,
+) -> anyhow::Result<(MessageHeaderV4, Vec<RawMessageRecord>)> {
+    let mut reader = BlockReader::with_endian(msg_res, endian);
+    let header = MessageHeaderV4::read_from(&mut reader)?;
+
+    let mut raw_msg_records = Vec::new();
+    for _ in 0..header.message_count {
+        let entry = MessageEntryV4::read_from(&mut reader)?;
+        raw_msg_records.push(RawMessageRecord {
+            id: MessageId {
+                noun: entry.noun,
+                verb: entry.verb,
+                condition: entry.condition,
+                sequence: entry.sequence,
+            },
+            ref_id: MessageId {
+                noun: entry.ref_noun,
+                verb: entry.ref_verb,
+                condition: entry.ref_condition,
+                sequence: 1,
+            },
+            text_offset: entry.text_offset,
+            talker: entry.talker,
+            unknown: entry.unknown,
+        });
     }
 
-    Ok(raw_msg_records)
+    Ok((header, raw_msg_records))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RefError {
+    #[error("Message {0:?} not found")]
+    NotFound(MessageId),
+    /// The messages refer to each other in a loop, starting from the first.
+    #[error("Message references form a cycle: {0:?}")]
+    Cycle(Vec<MessageId>),
 }
 
-fn read_string_at_offset(msg_res: &MemBlock, offset: u16) -> anyhow::Result<String> {
+/// Reads the NUL-terminated string at the offset, returning the raw bytes.
+fn read_string_at_offset(msg_res: &MemBlock, offset: u16) -> anyhow::Result<Vec<u8>> {
     let mut reader = BlockReader::new(msg_res.clone().sub_buffer(offset as usize..));
     let mut text = Vec::new();
     loop {
@@ -130,45 +191,433 @@ fn read_string_at_offset(msg_res: &MemBlock, offset: u16) -> anyhow::Result<Stri
         }
         text.push(ch);
     }
-    Ok(String::from_utf8(text)?)
+    Ok(text)
 }
 
-fn resolve_raw_record(
-    msg_res: &MemBlock,
-    raw_record: RawMessageRecord,
-) -> anyhow::Result<MessageRecord> {
-    let text = read_string_at_offset(msg_res, raw_record.text_offset)?;
-    Ok(MessageRecord {
-        _ref_id: raw_record.ref_id,
-        text,
-        talker: raw_record.talker,
-    })
+/// The code page used when none is given. Only the Mac ports are big-endian,
+/// and they use the Mac encoding.
+fn default_code_page(endian: Endian) -> CodePage {
+    match endian {
+        Endian::Little => CodePage::Cp437,
+        Endian::Big => CodePage::MacRoman,
+    }
 }
 
 pub struct RoomMessageSet {
+    version: u32,
+    endian: Endian,
+    header: MessageHeaderV4,
+    /// The distance from the end of the text data to the position the
+    /// header's end offset points at, so it can be updated when the text
+    /// changes size.
+    end_offset_delta: i64,
+    /// Any data after the end of the text.
+    trailer: Vec<u8>,
     messages: BTreeMap<MessageId, MessageRecord>,
 }
 
 impl RoomMessageSet {
+    /// The format version, as stored (e.g. 4000 for SCI1.1 resources).
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    /// Parses a message resource from its bytes, decoding text with the
+    /// platform's default code page.
+    pub fn parse_from_bytes(data: &[u8]) -> anyhow::Result<RoomMessageSet> {
+        parse_message_resource(MemBlock::from_vec(data.to_vec()))
+    }
+
     pub fn messages(&self) -> impl Iterator<Item = (&MessageId, &MessageRecord)> {
         self.messages.iter()
     }
+
+    pub fn messages_mut(&mut self) -> impl Iterator<Item = (&MessageId, &mut MessageRecord)> {
+        self.messages.iter_mut()
+    }
+
+    pub fn get(&self, id: &MessageId) -> Option<&MessageRecord> {
+        self.messages.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &MessageId) -> Option<&mut MessageRecord> {
+        self.messages.get_mut(id)
+    }
+
+    /// Adds a message, returning the one it replaces, if any.
+    pub fn insert(&mut self, id: MessageId, record: MessageRecord) -> Option<MessageRecord> {
+        self.messages.insert(id, record)
+    }
+
+    /// The messages the game shows for a message, following references as
+    /// the interpreter does. A message without a reference resolves to
+    /// itself. A reference to a conversation that doesn't exist resolves to
+    /// nothing, as the interpreter skips it.
+    pub fn resolve(&self, id: &MessageId) -> Result<Vec<(MessageId, &MessageRecord)>, RefError> {
+        let record = self.messages.get(id).ok_or(RefError::NotFound(*id))?;
+        let mut resolved = Vec::new();
+        self.resolve_into(*id, record, &mut vec![*id], &mut resolved)?;
+        Ok(resolved)
+    }
+
+    fn resolve_into<'a>(
+        &'a self,
+        id: MessageId,
+        record: &'a MessageRecord,
+        path: &mut Vec<MessageId>,
+        resolved: &mut Vec<(MessageId, &'a MessageRecord)>,
+    ) -> Result<(), RefError> {
+        let Some(target) = record.ref_target() else {
+            resolved.push((id, record));
+            return Ok(());
+        };
+        let same_conversation = |a: &MessageId, b: &MessageId| {
+            (a.noun, a.verb, a.condition) == (b.noun, b.verb, b.condition)
+        };
+        if path
+            .iter()
+            .any(|visited| same_conversation(visited, &target))
+        {
+            path.push(target);
+            return Err(RefError::Cycle(path.clone()));
+        }
+        path.push(target);
+        // Like the interpreter, play the conversation until a sequence
+        // number is missing.
+        for sequence in 1..=u8::MAX {
+            let line_id = MessageId { sequence, ..target };
+            let Some(line) = self.messages.get(&line_id) else {
+                break;
+            };
+            self.resolve_into(line_id, line, path, resolved)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    /// Serializes the messages back into a message resource, in the same
+    /// byte order it was read with. Text is encoded with the given code page,
+    /// or the platform default if `None`.
+    pub fn to_bytes(&self, code_page: Option<CodePage>) -> anyhow::Result<Vec<u8>> {
+        let code_page = code_page.unwrap_or(default_code_page(self.endian));
+        let u16_bytes = |value: u16| match self.endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        let u32_bytes = |value: u32| match self.endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+
+        // Identical strings are only stored once.
+        let mut texts: Vec<Vec<u8>> = Vec::new();
+        let mut text_indexes = Vec::new();
+        for record in self.messages.values() {
+            let text = code_page.encode(&record.text)?;
+            let index = match texts.iter().position(|t| *t == text) {
+                Some(index) => index,
+                None => {
+                    texts.push(text);
+                    texts.len() - 1
+                }
+            };
+            text_indexes.push(index);
+        }
+
+        let text_start = MESSAGE_HEADER_V4_OFFSET
+            + MessageHeaderV4::SIZE
+            + self.messages.len() * MessageEntryV4::SIZE;
+        let mut text_offsets = Vec::with_capacity(texts.len());
+        let mut text_end = text_start;
+        for text in &texts {
+            text_offsets.push(u16::try_from(text_end)?);
+            text_end += text.len() + 1;
+        }
+        anyhow::ensure!(
+            u16::try_from(text_end).is_ok(),
+            "Message resource is too large ({} bytes)",
+            text_end
+        );
+        let end_offset = u16::try_from(text_end as i64 - self.end_offset_delta)?;
+
+        let mut data = Vec::with_capacity(text_end + self.trailer.len());
+        data.extend_from_slice(&u32_bytes(self.version));
+        data.extend_from_slice(&u16_bytes(end_offset));
+        data.extend_from_slice(&u16_bytes(self.header.unknown));
+        data.extend_from_slice(&u16_bytes(u16::try_from(self.messages.len())?));
+        for ((id, record), &index) in self.messages.iter().zip(&text_indexes) {
+            data.extend_from_slice(&[id.noun, id.verb, id.condition, id.sequence, record.talker]);
+            data.extend_from_slice(&u16_bytes(text_offsets[index]));
+            data.extend_from_slice(&[
+                record.ref_id.noun,
+                record.ref_id.verb,
+                record.ref_id.condition,
+                record.unknown,
+            ]);
+        }
+        for text in &texts {
+            data.extend_from_slice(text);
+            data.push(0);
+        }
+        data.extend_from_slice(&self.trailer);
+        Ok(data)
+    }
 }
 
-pub fn parse_message_resource(msg_res: MemBlock) -> anyhow::Result<RoomMessageSet> {
+/// Reads the version of a message resource, and the byte order of the
+/// resource. The Mac ports store message resources big-endian; since versions
+/// are small numbers (e.g. 4000), only one byte order gives a sensible value.
+fn read_version(msg_res: &MemBlock) -> anyhow::Result<(u32, Endian)> {
     let mut reader = BlockReader::new(msg_res.clone());
-    let version_num = reader.read_u32_le()? / 1000;
-    let raw_records = match version_num {
-        4 => parse_message_resource_v4(reader.into_rest())?,
+    let version_le = reader.read_u32_le()?;
+    reader.seek_to(0)?;
+    let version_be = reader.read_u32_be()?;
+    if version_le / 1000 == 0 || version_le / 1000 >= 10 {
+        let version_num = version_be / 1000;
+        if (1..10).contains(&version_num) {
+            return Ok((version_num, Endian::Big));
+        }
+    }
+    Ok((version_le / 1000, Endian::Little))
+}
+
+/// Parses a message resource, decoding text with the platform's default code
+/// page.
+pub fn parse_message_resource(msg_res: MemBlock) -> anyhow::Result<RoomMessageSet> {
+    parse_message_resource_with_code_page(msg_res, None)
+}
+
+/// Parses a message resource, decoding text with the given code page, or the
+/// platform default if `None`.
+pub fn parse_message_resource_with_code_page(
+    msg_res: MemBlock,
+    code_page: Option<CodePage>,
+) -> anyhow::Result<RoomMessageSet> {
+    parse_message_resource_with_options(msg_res, code_page, &ParseOptions::default())
+}
+
+/// Parses a message resource, as [`parse_message_resource_with_code_page`],
+/// handling malformed messages as the options say.
+pub fn parse_message_resource_with_options(
+    msg_res: MemBlock,
+    code_page: Option<CodePage>,
+    options: &ParseOptions,
+) -> anyhow::Result<RoomMessageSet> {
+    let (version_num, endian) = read_version(&msg_res)?;
+    let (header, raw_records) = match version_num {
+        4 => parse_message_resource_v4(
+            msg_res.clone().sub_buffer(MESSAGE_HEADER_V4_OFFSET..),
+            endian,
+        )?,
         _ => anyhow::bail!("Unsupported message resource version: {}", version_num),
     };
+    let code_page = code_page.unwrap_or(default_code_page(endian));
+
+    let mut text_end =
+        MESSAGE_HEADER_V4_OFFSET + MessageHeaderV4::SIZE + raw_records.len() * MessageEntryV4::SIZE;
+    let mut messages = BTreeMap::new();
+    for raw_record in raw_records {
+        if raw_record.text_offset as usize >= msg_res.size() {
+            options.out_of_bounds(format!(
+                "Message {:?} text offset {} is past the end of the resource ({} bytes)",
+                raw_record.id,
+                raw_record.text_offset,
+                msg_res.size()
+            ))?;
+            continue;
+        }
+        let text = read_string_at_offset(&msg_res, raw_record.text_offset)?;
+        text_end = text_end.max(raw_record.text_offset as usize + text.len() + 1);
+        messages.insert(
+            raw_record.id,
+            MessageRecord {
+                ref_id: raw_record.ref_id,
+                text: code_page.decode(&text),
+                talker: raw_record.talker,
+                unknown: raw_record.unknown,
+            },
+        );
+    }
+    let trailer = msg_res.clone().sub_buffer(text_end..).read_all()?.to_vec();
 
-    let messages = raw_records
-        .into_iter()
-        .map(|raw_record| {
-            let record = resolve_raw_record(&msg_res, raw_record)?;
-            Ok((raw_record.id, record))
-        })
-        .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
-    Ok(RoomMessageSet { messages })
+    Ok(RoomMessageSet {
+        version: BlockReader::with_endian(msg_res, endian).read_u32()?,
+        endian,
+        end_offset_delta: text_end as i64 - header.end_offset as i64,
+        header,
+        trailer,
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a v4 message resource with a single message.
+    fn build_message_resource(endian: Endian, text: &[u8]) -> Vec<u8> {
+        let u16_bytes = |value: u16| match endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        let u32_bytes = |value: u32| match endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        let mut data = Vec::new();
+        data.extend_from_slice(&u32_bytes(4000));
+        data.extend_from_slice(&u32_bytes(0));
+        data.extend_from_slice(&u16_bytes(1));
+        let text_offset = data.len() as u16 + 11;
+        data.extend_from_slice(&[1, 2, 3, 1, 7]);
+        data.extend_from_slice(&u16_bytes(text_offset));
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(text);
+        data.push(0);
+        let end_offset = data.len() as u16 - 6;
+        data[4..6].copy_from_slice(&u16_bytes(end_offset));
+        data
+    }
+
+    #[test]
+    fn test_parse_either_byte_order() -> anyhow::Result<()> {
+        for (endian, text, expected) in [
+            (Endian::Little, &b"Hello"[..], "Hello"),
+            (Endian::Big, &b"Caf\x8E"[..], "Café"),
+        ] {
+            let msg_res = MemBlock::from_vec(build_message_resource(endian, text));
+            let messages = parse_message_resource(msg_res)?;
+            let (id, record) = messages.messages().next().unwrap();
+            assert_eq!(*id, MessageId::new(1, 2, 3, 1));
+            assert_eq!(record.talker(), 7);
+            assert_eq!(record.text(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_with_code_page() -> anyhow::Result<()> {
+        let original = build_message_resource(Endian::Little, b"Fran\x87ais");
+        let mut messages = parse_message_resource_with_code_page(
+            MemBlock::from_vec(original.clone()),
+            Some(CodePage::Cp850),
+        )?;
+        let id = MessageId::new(1, 2, 3, 1);
+        assert_eq!(messages.get_mut(&id).unwrap().text(), "Français");
+        assert_eq!(messages.to_bytes(Some(CodePage::Cp850))?, original);
+
+        messages.get_mut(&id).unwrap().set_text("Déjà vu");
+        let rewritten = messages.to_bytes(Some(CodePage::Cp850))?;
+        let reparsed = parse_message_resource_with_code_page(
+            MemBlock::from_vec(rewritten),
+            Some(CodePage::Cp850),
+        )?;
+        assert_eq!(reparsed.messages().next().unwrap().1.text(), "Déjà vu");
+
+        messages.get_mut(&id).unwrap().set_text("Żółw");
+        assert!(messages.to_bytes(Some(CodePage::Cp850)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_round_trip() -> anyhow::Result<()> {
+        let mut messages =
+            RoomMessageSet::parse_from_bytes(&build_message_resource(Endian::Little, b"Hello"))?;
+        let id = MessageId::new(1, 2, 3, 2);
+        assert!(
+            messages
+                .insert(id, MessageRecord::new(9, "Added"))
+                .is_none()
+        );
+        let reparsed = RoomMessageSet::parse_from_bytes(&messages.to_bytes(None)?)?;
+        assert_eq!(reparsed.messages().count(), 2);
+        let added = reparsed.get(&id).unwrap();
+        assert_eq!(added.text(), "Added");
+        assert_eq!(added.talker(), 9);
+        assert_eq!(added.ref_target(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_resource_is_error() -> anyhow::Result<()> {
+        let original = build_message_resource(Endian::Little, b"Hello");
+        for len in 0..original.len() {
+            assert!(RoomMessageSet::parse_from_bytes(&original[..len]).is_err());
+        }
+        let mut bad_offset = original.clone();
+        bad_offset[15..17].copy_from_slice(&0xFFF0u16.to_le_bytes());
+        assert!(RoomMessageSet::parse_from_bytes(&bad_offset).is_err());
+        let permissive = ParseOptions {
+            log_anomalies: false,
+            ..ParseOptions::permissive()
+        };
+        let messages =
+            parse_message_resource_with_options(MemBlock::from_vec(bad_offset), None, &permissive)?;
+        assert_eq!(messages.messages().count(), 0);
+        Ok(())
+    }
+
+    fn record(text: &str, ref_id: MessageId) -> MessageRecord {
+        MessageRecord {
+            ref_id,
+            text: text.to_string(),
+            talker: 0,
+            unknown: 0,
+        }
+    }
+
+    #[test]
+    fn test_resolve_refs() {
+        let none = MessageId::new(0, 0, 0, 1);
+        let mut messages = parse_message_resource(MemBlock::from_vec(build_message_resource(
+            Endian::Little,
+            b"Hello",
+        )))
+        .unwrap();
+        messages.messages = BTreeMap::from([
+            (MessageId::new(1, 1, 0, 1), record("Look.", none)),
+            (MessageId::new(1, 1, 0, 2), record("Again.", none)),
+            (
+                MessageId::new(2, 1, 0, 1),
+                record("", MessageId::new(1, 1, 0, 1)),
+            ),
+            (MessageId::new(2, 1, 0, 2), record("After.", none)),
+            (
+                MessageId::new(3, 1, 0, 1),
+                record("", MessageId::new(4, 1, 0, 1)),
+            ),
+            (
+                MessageId::new(4, 1, 0, 1),
+                record("", MessageId::new(3, 1, 0, 1)),
+            ),
+            (
+                MessageId::new(5, 1, 0, 1),
+                record("", MessageId::new(9, 1, 0, 1)),
+            ),
+        ]);
+
+        let texts = |id| -> Vec<String> {
+            messages
+                .resolve(&id)
+                .unwrap()
+                .into_iter()
+                .map(|(_, record)| record.text().to_string())
+                .collect()
+        };
+        assert_eq!(texts(MessageId::new(1, 1, 0, 1)), ["Look."]);
+        assert_eq!(texts(MessageId::new(2, 1, 0, 1)), ["Look.", "Again."]);
+        assert!(texts(MessageId::new(5, 1, 0, 1)).is_empty());
+        assert!(matches!(
+            messages.resolve(&MessageId::new(3, 1, 0, 1)),
+            Err(RefError::Cycle(_))
+        ));
+        assert_eq!(
+            messages.resolve(&MessageId::new(7, 1, 0, 1)).unwrap_err(),
+            RefError::NotFound(MessageId::new(7, 1, 0, 1))
+        );
+    }
 }
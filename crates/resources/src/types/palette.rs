@@ -0,0 +1,193 @@
+//! Palettes, as stored in palette resources and embedded in views.
+
+use anyhow::ensure;
+
+/// Palette data in the SCI1.1 layout stores each color as three bytes, all
+/// marked used.
+const FORMAT_CONSTANT: u8 = 1;
+
+/// An RGB color.
+pub type Rgb = [u8; 3];
+
+/// A palette of 256 colors, some of which may be unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: [Option<Rgb>; 256],
+}
+
+impl Palette {
+    /// A palette with no colors set.
+    pub fn empty() -> Self {
+        Palette {
+            colors: [None; 256],
+        }
+    }
+
+    /// Parses palette data, in either the SCI1.1 layout (with a header
+    /// giving the range of colors) or the older one (256 colors, each with a
+    /// used flag).
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let layout = Layout::read(data)?;
+        let entry_size = layout.entry_size();
+        let entries = data
+            .get(layout.offset..layout.offset + layout.count * entry_size)
+            .ok_or_else(|| anyhow::anyhow!("Palette data is truncated"))?;
+        let mut palette = Palette::empty();
+        for (index, entry) in (layout.start..).zip(entries.chunks_exact(entry_size)) {
+            let (used, rgb) = if layout.with_used_flags {
+                (entry[0] != 0, &entry[1..])
+            } else {
+                (true, entry)
+            };
+            if used {
+                palette.colors[index] = Some([rgb[0], rgb[1], rgb[2]]);
+            }
+        }
+        Ok(palette)
+    }
+
+    /// The size of the palette data at the start of `data`, which may be
+    /// followed by other data (as in views).
+    pub fn data_size(data: &[u8]) -> anyhow::Result<usize> {
+        let layout = Layout::read(data)?;
+        let size = layout.offset + layout.count * layout.entry_size();
+        ensure!(size <= data.len(), "Palette data is truncated");
+        Ok(size)
+    }
+
+    pub fn color(&self, index: u8) -> Option<Rgb> {
+        self.colors[usize::from(index)]
+    }
+
+    pub fn set_color(&mut self, index: u8, rgb: Rgb) {
+        self.colors[usize::from(index)] = Some(rgb);
+    }
+
+    /// The set color closest to `rgb`, other than `exclude` (e.g. a cel's
+    /// transparent color). Ties go to the lowest index.
+    pub fn nearest(&self, rgb: Rgb, exclude: Option<u8>) -> Option<u8> {
+        let distance = |color: Rgb| -> u32 {
+            color
+                .iter()
+                .zip(rgb)
+                .map(|(&a, b)| u32::from(a.abs_diff(b)).pow(2))
+                .sum()
+        };
+        (0..=u8::MAX)
+            .filter(|&index| Some(index) != exclude)
+            .filter_map(|index| Some((index, distance(self.color(index)?))))
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(index, _)| index)
+    }
+
+    /// Sets the colors that the other palette sets, as the interpreter does
+    /// when a view with an embedded palette is drawn.
+    pub fn overlay(&mut self, other: &Palette) {
+        for (color, other) in self.colors.iter_mut().zip(&other.colors) {
+            if other.is_some() {
+                *color = *other;
+            }
+        }
+    }
+}
+
+/// Where the colors are in palette data, in either layout.
+struct Layout {
+    with_used_flags: bool,
+    /// The offset of the first color.
+    offset: usize,
+    /// The index of the first color.
+    start: usize,
+    count: usize,
+}
+
+impl Layout {
+    fn read(data: &[u8]) -> anyhow::Result<Self> {
+        ensure!(data.len() >= 37, "Palette data is too short");
+        let color_count_at_29 = u16::from_le_bytes([data[29], data[30]]);
+        let layout = if (data[0] == 0 && data[1] == 1)
+            || (data[0] == 0 && data[1] == 0 && color_count_at_29 == 0)
+        {
+            Layout {
+                with_used_flags: true,
+                offset: 260,
+                start: 0,
+                count: 256,
+            }
+        } else {
+            Layout {
+                with_used_flags: data[32] != FORMAT_CONSTANT,
+                offset: 37,
+                start: usize::from(data[25]),
+                count: usize::from(color_count_at_29),
+            }
+        };
+        ensure!(
+            layout.start + layout.count <= 256,
+            "Palette has colors past index 255"
+        );
+        Ok(layout)
+    }
+
+    fn entry_size(&self) -> usize {
+        if self.with_used_flags { 4 } else { 3 }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds SCI1.1 palette data setting colors from `start`.
+    pub(crate) fn sci11_palette(start: u8, colors: &[Rgb]) -> Vec<u8> {
+        let mut data = vec![0u8; 37];
+        data[0] = 0x0E;
+        data[25] = start;
+        data[29..31].copy_from_slice(&(colors.len() as u16).to_le_bytes());
+        data[32] = FORMAT_CONSTANT;
+        for color in colors {
+            data.extend_from_slice(color);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_sci11_palette() -> anyhow::Result<()> {
+        let palette = Palette::parse(&sci11_palette(10, &[[1, 2, 3], [4, 5, 6]]))?;
+        assert_eq!(palette.color(9), None);
+        assert_eq!(palette.color(10), Some([1, 2, 3]));
+        assert_eq!(palette.color(11), Some([4, 5, 6]));
+
+        let mut base = Palette::parse(&sci11_palette(11, &[[7, 7, 7], [8, 8, 8]]))?;
+        base.overlay(&palette);
+        assert_eq!(base.color(10), Some([1, 2, 3]));
+        assert_eq!(base.color(11), Some([4, 5, 6]));
+        assert_eq!(base.color(12), Some([8, 8, 8]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nearest() -> anyhow::Result<()> {
+        let mut data = sci11_palette(1, &[[0, 0, 0], [250, 0, 0], [0, 0, 0]]);
+        assert_eq!(Palette::data_size(&data)?, data.len());
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(Palette::data_size(&data)?, data.len() - 3);
+        let palette = Palette::parse(&data)?;
+        assert_eq!(palette.nearest([200, 10, 10], None), Some(2));
+        assert_eq!(palette.nearest([5, 5, 5], None), Some(1));
+        assert_eq!(palette.nearest([5, 5, 5], Some(1)), Some(3));
+        assert_eq!(Palette::empty().nearest([5, 5, 5], None), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sci1_palette() -> anyhow::Result<()> {
+        let mut data = vec![0u8; 260 + 256 * 4];
+        data[1] = 1;
+        data[260 + 4 * 3..260 + 4 * 4].copy_from_slice(&[1, 9, 8, 7]);
+        let palette = Palette::parse(&data)?;
+        assert_eq!(palette.color(3), Some([9, 8, 7]));
+        assert_eq!(palette.color(4), None);
+        Ok(())
+    }
+}
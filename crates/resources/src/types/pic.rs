@@ -0,0 +1,207 @@
+//! SCI1.1 pics: a room's background, as a bitmap for the visual layer (the
+//! pic's cel) and vector drawing commands, which draw the priority and
+//! control layers.
+//!
+//! The visual layer can be replaced with [`Pic::with_visual`], e.g. to
+//! translate text painted into a background. The vector data is kept as it
+//! is, so the priority and control layers don't change.
+
+use anyhow::ensure;
+
+use super::{
+    palette::Palette,
+    view::{Cel, encode_runs, read_cel, read_u32, write_cel_header},
+};
+
+/// The size of the header's fixed fields. The priority bands follow them.
+const HEADER_SIZE: usize = 40;
+
+/// The size of a pic's cel header, as in views.
+const CEL_SIZE: usize = 36;
+
+/// A pic resource.
+pub struct Pic<'a> {
+    data: &'a [u8],
+    vector_offset: usize,
+    palette_offset: usize,
+    cel_offset: Option<usize>,
+}
+
+impl<'a> Pic<'a> {
+    pub fn parse(data: &'a [u8]) -> anyhow::Result<Self> {
+        ensure!(data.len() >= HEADER_SIZE, "Pic data is too short");
+        let pic = Pic {
+            data,
+            vector_offset: read_u32(data, 16)? as usize,
+            palette_offset: read_u32(data, 28)? as usize,
+            cel_offset: (data[4] != 0)
+                .then(|| read_u32(data, 32).map(|offset| offset as usize))
+                .transpose()?,
+        };
+        ensure!(
+            pic.vector_offset >= HEADER_SIZE && pic.vector_offset <= data.len(),
+            "Not a SCI1.1 pic"
+        );
+        Ok(pic)
+    }
+
+    /// The pic's palette, if it has one.
+    pub fn palette(&self) -> anyhow::Result<Option<Palette>> {
+        if self.palette_offset == 0 {
+            return Ok(None);
+        }
+        let data = self
+            .data
+            .get(self.palette_offset..)
+            .ok_or_else(|| anyhow::anyhow!("Pic palette is past the end of the data"))?;
+        Palette::parse(data).map(Some)
+    }
+
+    fn cel_header(&self) -> anyhow::Result<Option<&'a [u8]>> {
+        self.cel_offset
+            .map(|offset| {
+                self.data
+                    .get(offset..offset + CEL_SIZE)
+                    .ok_or_else(|| anyhow::anyhow!("Pic cel is past the end of the data"))
+            })
+            .transpose()
+    }
+
+    /// The visual layer, if the pic has one. Pics drawn only with vectors
+    /// don't.
+    pub fn visual(&self) -> anyhow::Result<Option<Cel>> {
+        self.cel_header()?
+            .map(|header| read_cel(self.data, header))
+            .transpose()
+    }
+
+    /// Builds the pic again with a new visual layer, the same size as the
+    /// old one. The header, palette and vector data are kept.
+    pub fn with_visual(&self, cel: &Cel) -> anyhow::Result<Vec<u8>> {
+        let header = self
+            .cel_header()?
+            .ok_or_else(|| anyhow::anyhow!("The pic has no visual layer to replace"))?;
+        let old = read_cel(self.data, header)?;
+        ensure!(
+            (cel.width, cel.height) == (old.width, old.height),
+            "The new visual layer is {}x{}, but the pic's is {}x{}",
+            cel.width,
+            cel.height,
+            old.width,
+            old.height
+        );
+        ensure!(
+            cel.pixels.len() == usize::from(cel.width) * usize::from(cel.height),
+            "The visual layer has {} pixels, not {}x{}",
+            cel.pixels.len(),
+            cel.width,
+            cel.height
+        );
+        // The game's pics keep the cel's runs and colors just before the
+        // vector data. If they're there, they're dropped; otherwise they're
+        // left where they are, unused.
+        let cel_offset = self.cel_offset.expect("pics with cel headers have cels");
+        let old_start = [read_u32(header, 24)?, read_u32(header, 28)?]
+            .into_iter()
+            .filter(|&offset| offset != 0)
+            .min()
+            .map_or(self.vector_offset, |offset| offset as usize);
+        let keep_end = if old_start >= cel_offset + CEL_SIZE
+            && old_start >= self.palette_offset
+            && old_start <= self.vector_offset
+        {
+            old_start
+        } else {
+            self.vector_offset
+        };
+        let (runs, literals) = encode_runs(cel);
+        let mut data = self.data[..keep_end].to_vec();
+        let rle_offset = u32::try_from(data.len())?;
+        data.extend_from_slice(&runs);
+        let literal_offset = u32::try_from(data.len())?;
+        data.extend_from_slice(&literals);
+        let vector_offset = u32::try_from(data.len())?;
+        data.extend_from_slice(&self.data[self.vector_offset..]);
+        data[16..20].copy_from_slice(&vector_offset.to_le_bytes());
+        write_cel_header(
+            &mut data[cel_offset..cel_offset + CEL_SIZE],
+            cel,
+            rle_offset,
+            literal_offset,
+        );
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::palette::tests::sci11_palette;
+
+    /// Builds a pic with a palette, a 4x2 cel and some vector data, laid out
+    /// as the game's are.
+    fn pic_data() -> Vec<u8> {
+        let palette = sci11_palette(1, &[[10, 20, 30], [40, 50, 60]]);
+        let mut data = vec![0u8; HEADER_SIZE + 2];
+        data[0..2].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data[3] = 1;
+        data[4] = 1;
+        data[HEADER_SIZE..].copy_from_slice(&[0x12, 0x34]);
+        let palette_offset = data.len() as u32;
+        data.extend(palette);
+        let cel_offset = data.len();
+        data.extend([0; CEL_SIZE]);
+        data[cel_offset..cel_offset + 2].copy_from_slice(&4u16.to_le_bytes());
+        data[cel_offset + 2..cel_offset + 4].copy_from_slice(&2u16.to_le_bytes());
+        data[cel_offset + 8] = 0xFF;
+        let rle_offset = data.len() as u32;
+        data.extend([0x84, 0x84]);
+        let literal_offset = data.len() as u32;
+        data.extend([1, 2]);
+        let vector_offset = data.len() as u32;
+        data.extend([0xF0, 0x01, 0xFF]);
+        data[16..20].copy_from_slice(&vector_offset.to_le_bytes());
+        data[28..32].copy_from_slice(&palette_offset.to_le_bytes());
+        data[32..36].copy_from_slice(&(cel_offset as u32).to_le_bytes());
+        data[cel_offset + 24..cel_offset + 28].copy_from_slice(&rle_offset.to_le_bytes());
+        data[cel_offset + 28..cel_offset + 32].copy_from_slice(&literal_offset.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decode_pic() -> anyhow::Result<()> {
+        let data = pic_data();
+        let pic = Pic::parse(&data)?;
+        let visual = pic.visual()?.unwrap();
+        assert_eq!((visual.width, visual.height), (4, 2));
+        assert_eq!(visual.pixels, [1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(pic.palette()?.unwrap().color(2), Some([40, 50, 60]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_visual() -> anyhow::Result<()> {
+        let data = pic_data();
+        let pic = Pic::parse(&data)?;
+        let mut visual = pic.visual()?.unwrap();
+        visual.pixels = vec![1, 2, 1, 2, 0xFF, 2, 2, 2];
+        let rebuilt = pic.with_visual(&visual)?;
+        let new_pic = Pic::parse(&rebuilt)?;
+        assert_eq!(new_pic.visual()?.unwrap(), visual);
+        // The header (but for the vector offset), the palette and the vector
+        // data are kept.
+        let cel_offset = pic.cel_offset.unwrap();
+        assert_eq!(rebuilt[..16], data[..16]);
+        assert_eq!(rebuilt[20..cel_offset], data[20..cel_offset]);
+        assert!(rebuilt.ends_with(&[0xF0, 0x01, 0xFF]));
+        assert_eq!(new_pic.vector_offset, rebuilt.len() - 3);
+
+        // Rebuilding with the same pixels gives the same pic.
+        assert_eq!(pic.with_visual(&pic.visual()?.unwrap())?, data);
+
+        visual.width = 2;
+        visual.pixels.truncate(4);
+        assert!(pic.with_visual(&visual).is_err());
+        Ok(())
+    }
+}
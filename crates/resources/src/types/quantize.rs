@@ -0,0 +1,270 @@
+//! Mapping true-color images onto a game's palette, for importing graphics.
+//!
+//! Each opaque pixel becomes the nearest color the palette sets, optionally
+//! dithered so gradients the palette can't hold keep their overall tone.
+//! Mostly transparent pixels become the transparent color. The result comes
+//! with a report of how far the colors moved, so an import can say when the
+//! art doesn't suit the palette.
+
+use std::collections::HashMap;
+
+use anyhow::ensure;
+
+use super::palette::{Palette, Rgb};
+
+/// How colors the palette doesn't have are approximated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Dithering {
+    /// Each pixel takes the nearest color. Best for art drawn in the
+    /// palette, such as edited cels.
+    #[default]
+    None,
+    /// Floyd-Steinberg error diffusion: each pixel's error is passed on to
+    /// its neighbors. Best for photographic input.
+    FloydSteinberg,
+    /// A 4x4 Bayer pattern. Gives a regular texture, which stays stable when
+    /// neighboring cels are similar.
+    Ordered,
+}
+
+/// The 4x4 Bayer matrix, with thresholds from 0 to 15.
+const BAYER_4X4: [[i16; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How far ordered dithering moves a color, at most, in each channel.
+const ORDERED_SPREAD: i16 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizeOptions {
+    pub dithering: Dithering,
+    /// The color index for transparent pixels. It is never chosen for opaque
+    /// ones.
+    pub clear_key: u8,
+    /// Pixels with an alpha below this are transparent.
+    pub alpha_threshold: u8,
+}
+
+impl QuantizeOptions {
+    pub fn new(clear_key: u8) -> Self {
+        QuantizeOptions {
+            dithering: Dithering::None,
+            clear_key,
+            alpha_threshold: 0x80,
+        }
+    }
+}
+
+/// How far the colors moved.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorError {
+    /// The opaque pixels.
+    pub pixels: usize,
+    /// The opaque pixels whose colors are in the palette as they are.
+    pub exact: usize,
+    /// The mean and largest distance (in RGB) from each pixel's color to its
+    /// palette color.
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl ColorError {
+    /// The pixels whose colors aren't in the palette.
+    pub fn approximated(&self) -> usize {
+        self.pixels - self.exact
+    }
+
+    /// Adds another image's error, as if the images were one.
+    pub fn combine(&mut self, other: &ColorError) {
+        let pixels = self.pixels + other.pixels;
+        if pixels > 0 {
+            self.mean =
+                (self.mean * self.pixels as f64 + other.mean * other.pixels as f64) / pixels as f64;
+        }
+        self.pixels = pixels;
+        self.exact += other.exact;
+        self.max = self.max.max(other.max);
+    }
+}
+
+impl std::fmt::Display for ColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} pixels exact, mean error {:.1}, max {:.1}",
+            self.exact, self.pixels, self.mean, self.max
+        )
+    }
+}
+
+/// An image mapped onto a palette.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantized {
+    /// Color indices, row by row.
+    pub pixels: Vec<u8>,
+    pub error: ColorError,
+}
+
+fn distance(a: Rgb, b: Rgb) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| f64::from(a.abs_diff(b)).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Maps RGBA pixels, row by row, onto the palette.
+pub fn quantize(
+    rgba: &[[u8; 4]],
+    width: usize,
+    palette: &Palette,
+    options: &QuantizeOptions,
+) -> anyhow::Result<Quantized> {
+    ensure!(
+        rgba.is_empty() || (width > 0 && rgba.len().is_multiple_of(width)),
+        "{} pixels don't make rows of {width}",
+        rgba.len()
+    );
+    let mut nearest_cache: HashMap<Rgb, (u8, Rgb)> = HashMap::new();
+    let mut nearest = |rgb: Rgb| -> anyhow::Result<(u8, Rgb)> {
+        if let Some(&found) = nearest_cache.get(&rgb) {
+            return Ok(found);
+        }
+        let index = palette
+            .nearest(rgb, Some(options.clear_key))
+            .ok_or_else(|| anyhow::anyhow!("The palette has no colors to match"))?;
+        let found = (index, palette.color(index).expect("nearest colors are set"));
+        nearest_cache.insert(rgb, found);
+        Ok(found)
+    };
+    // The error carried to each pixel, for error diffusion.
+    let mut carried = vec![[0i16; 3]; rgba.len()];
+    let mut pixels = Vec::with_capacity(rgba.len());
+    let mut error = ColorError::default();
+    let mut total_distance = 0.0;
+    for (pos, &[r, g, b, a]) in rgba.iter().enumerate() {
+        if a < options.alpha_threshold {
+            pixels.push(options.clear_key);
+            continue;
+        }
+        let (x, y) = (pos % width, pos / width);
+        let rgb = [r, g, b];
+        let offset = match options.dithering {
+            Dithering::None => [0; 3],
+            Dithering::FloydSteinberg => carried[pos],
+            Dithering::Ordered => {
+                let threshold = BAYER_4X4[y % 4][x % 4];
+                [(threshold * 2 - 15) * ORDERED_SPREAD / 32; 3]
+            }
+        };
+        let target: Rgb =
+            std::array::from_fn(|c| (i16::from(rgb[c]) + offset[c]).clamp(0, 255) as u8);
+        let (index, chosen) = nearest(target)?;
+        pixels.push(index);
+        if options.dithering == Dithering::FloydSteinberg {
+            let diff: [i16; 3] =
+                std::array::from_fn(|c| i16::from(target[c]) - i16::from(chosen[c]));
+            let rows = rgba.len() / width;
+            let mut pass = |dx: isize, dy: usize, weight: i16| {
+                let nx = x as isize + dx;
+                if nx < 0 || nx as usize >= width || y + dy >= rows {
+                    return;
+                }
+                let next = &mut carried[(y + dy) * width + nx as usize];
+                for c in 0..3 {
+                    next[c] += diff[c] * weight / 16;
+                }
+            };
+            pass(1, 0, 7);
+            pass(-1, 1, 3);
+            pass(0, 1, 5);
+            pass(1, 1, 1);
+        }
+        let moved = distance(rgb, chosen);
+        error.pixels += 1;
+        if chosen == rgb {
+            error.exact += 1;
+        }
+        total_distance += moved;
+        error.max = error.max.max(moved);
+    }
+    if error.pixels > 0 {
+        error.mean = total_distance / error.pixels as f64;
+    }
+    Ok(Quantized { pixels, error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::palette::tests::sci11_palette;
+
+    fn gray_palette() -> Palette {
+        Palette::parse(&sci11_palette(
+            0,
+            &[[0, 0, 0], [255, 255, 255], [255, 0, 255]],
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nearest_without_dithering() -> anyhow::Result<()> {
+        let rgba = [
+            [0, 0, 0, 255],
+            [250, 250, 250, 255],
+            [9, 9, 9, 0],
+            [255, 0, 255, 255],
+        ];
+        let quantized = quantize(&rgba, 2, &gray_palette(), &QuantizeOptions::new(2))?;
+        // Magenta is the transparent color, so it can't be used for opaque
+        // pixels.
+        assert_eq!(quantized.pixels, [0, 1, 2, 1]);
+        assert_eq!(quantized.error.pixels, 3);
+        assert_eq!(quantized.error.exact, 1);
+        assert_eq!(quantized.error.approximated(), 2);
+        assert!(quantized.error.max > 200.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dithering_keeps_tone() -> anyhow::Result<()> {
+        // A mid gray, which the palette only has as black and white.
+        let rgba = vec![[128, 128, 128, 255]; 64];
+        for dithering in [Dithering::FloydSteinberg, Dithering::Ordered] {
+            let options = QuantizeOptions {
+                dithering,
+                ..QuantizeOptions::new(2)
+            };
+            let quantized = quantize(&rgba, 8, &gray_palette(), &options)?;
+            let white = quantized.pixels.iter().filter(|&&index| index == 1).count();
+            assert!((24..=40).contains(&white), "{dithering:?}: {white} white");
+        }
+        let flat = quantize(&rgba, 8, &gray_palette(), &QuantizeOptions::new(2))?;
+        assert!(flat.pixels.iter().all(|&index| index == flat.pixels[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_combine_errors() {
+        let mut error = ColorError {
+            pixels: 2,
+            exact: 2,
+            mean: 0.0,
+            max: 0.0,
+        };
+        error.combine(&ColorError {
+            pixels: 2,
+            exact: 0,
+            mean: 10.0,
+            max: 12.0,
+        });
+        assert_eq!(error.pixels, 4);
+        assert_eq!(error.approximated(), 2);
+        assert_eq!(error.mean, 5.0);
+        assert_eq!(error.max, 12.0);
+    }
+
+    #[test]
+    fn test_bad_width() {
+        assert!(quantize(&[[0; 4]; 3], 2, &gray_palette(), &QuantizeOptions::new(2)).is_err());
+        assert!(quantize(&[], 0, &gray_palette(), &QuantizeOptions::new(2)).is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+//! Sync36 resources, which time a talker's mouth animation to a line of
+//! speech.
+//!
+//! The data is a list of cues, each a pair of little-endian words: the time
+//! in ticks (60 per second) from the start of the line, and the cel of the
+//! mouth loop to show from then on. A word of `0xFFFF` ends the list.
+
+use anyhow::ensure;
+
+use crate::{
+    ResourceType,
+    file::{EXTENDED_HEADER_MARKER, EXTENDED_HEADER_SIZE, patch_header},
+};
+
+use super::msg::MessageId;
+
+/// Ends the list of cues.
+const TERMINATOR: u16 = 0xFFFF;
+
+/// Ticks per second, for cue times.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// A point in a line where the mouth changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncCue {
+    /// The time from the start of the line, in ticks.
+    pub ticks: u16,
+    /// The cel of the talker's mouth loop.
+    pub cel: u16,
+}
+
+/// Parses the cues of a Sync36 resource.
+pub fn read_sync36(data: &[u8]) -> anyhow::Result<Vec<SyncCue>> {
+    let mut words = data
+        .chunks_exact(2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]));
+    let mut cues = Vec::new();
+    loop {
+        let Some(ticks) = words.next() else {
+            anyhow::bail!("Sync data ends without a terminator");
+        };
+        if ticks == TERMINATOR {
+            return Ok(cues);
+        }
+        let Some(cel) = words.next() else {
+            anyhow::bail!("Sync data ends in the middle of a cue");
+        };
+        cues.push(SyncCue { ticks, cel });
+    }
+}
+
+/// Writes cues as a Sync36 resource. The cues must be in time order.
+pub fn write_sync36(cues: &[SyncCue]) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        cues.is_sorted_by_key(|cue| cue.ticks),
+        "Sync cues must be in time order"
+    );
+    let mut data = Vec::with_capacity(cues.len() * 4 + 2);
+    for cue in cues {
+        ensure!(
+            cue.ticks != TERMINATOR,
+            "Sync cue at {} ticks is too late for the line",
+            cue.ticks
+        );
+        data.extend_from_slice(&cue.ticks.to_le_bytes());
+        data.extend_from_slice(&cue.cel.to_le_bytes());
+    }
+    data.extend_from_slice(&TERMINATOR.to_le_bytes());
+    Ok(data)
+}
+
+/// Writes cues as a Sync36 patch file, with the header the interpreter
+/// expects.
+pub fn write_sync36_patch(cues: &[SyncCue]) -> anyhow::Result<Vec<u8>> {
+    let mut patch = patch_header(ResourceType::Sync36);
+    patch.extend(write_sync36(cues)?);
+    Ok(patch)
+}
+
+/// Parses the cues of a Sync36 patch file, skipping its header.
+pub fn read_sync36_patch(patch: &[u8]) -> anyhow::Result<Vec<SyncCue>> {
+    ensure!(
+        patch.first() == Some(&u8::from(ResourceType::Sync36)),
+        "Not a Sync36 patch"
+    );
+    let header_size = match patch.get(1) {
+        // An extended header has 24 bytes of its own, the second giving the
+        // size of any further header data.
+        Some(&EXTENDED_HEADER_MARKER) => {
+            2 + EXTENDED_HEADER_SIZE + usize::from(*patch.get(3).unwrap_or(&0))
+        }
+        Some(size) => 2 + usize::from(*size),
+        None => anyhow::bail!("Sync36 patch is too short"),
+    };
+    read_sync36(
+        patch
+            .get(header_size..)
+            .ok_or_else(|| anyhow::anyhow!("Sync36 patch has a truncated header"))?,
+    )
+}
+
+const BASE36_DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+fn base36(value: u32, digits: u32) -> anyhow::Result<String> {
+    ensure!(
+        value < 36u32.pow(digits),
+        "{value} doesn't fit in {digits} base 36 digits"
+    );
+    let text = (0..digits)
+        .rev()
+        .map(|place| char::from(BASE36_DIGITS[(value / 36u32.pow(place) % 36) as usize]))
+        .collect();
+    Ok(text)
+}
+
+/// The name of the patch file for a line's Audio36 or Sync36 resource.
+///
+/// These resources are identified by the room and the whole message ID, so
+/// the name packs them in base 36: a prefix for the type (`@` for audio, `#`
+/// for sync), then the room, noun and verb in the stem, and the condition and
+/// sequence in the extension.
+pub fn patch_file_name(
+    res_type: ResourceType,
+    room: u16,
+    id: &MessageId,
+) -> anyhow::Result<String> {
+    let prefix = match res_type {
+        ResourceType::Audio36 => '@',
+        ResourceType::Sync36 => '#',
+        _ => anyhow::bail!("{res_type:?} resources aren't named by message"),
+    };
+    Ok(format!(
+        "{prefix}{}{}{}.{}{}",
+        base36(room.into(), 3)?,
+        base36(id.noun().into(), 2)?,
+        base36(id.verb().into(), 2)?,
+        base36(id.condition().into(), 2)?,
+        base36(id.sequence().into(), 1)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync36_round_trip() -> anyhow::Result<()> {
+        let cues = [
+            SyncCue { ticks: 0, cel: 0 },
+            SyncCue { ticks: 5, cel: 3 },
+            SyncCue { ticks: 12, cel: 1 },
+        ];
+        let data = write_sync36(&cues)?;
+        assert_eq!(data.len(), 14);
+        assert_eq!(&data[12..], &[0xFF, 0xFF]);
+        assert_eq!(read_sync36(&data)?, cues);
+        assert!(read_sync36(&data[..12]).is_err());
+        assert!(write_sync36(&[cues[1], cues[0]]).is_err());
+
+        let patch = write_sync36_patch(&cues)?;
+        assert_eq!(patch[0], u8::from(ResourceType::Sync36));
+        assert!(patch.ends_with(&data));
+        assert_eq!(read_sync36_patch(&patch)?, cues);
+        assert!(read_sync36_patch(&data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_file_name() -> anyhow::Result<()> {
+        let id = MessageId::new(1, 2, 0, 35);
+        assert_eq!(
+            patch_file_name(ResourceType::Sync36, 100, &id)?,
+            "#02S0102.00Z"
+        );
+        assert_eq!(
+            patch_file_name(ResourceType::Audio36, 100, &id)?,
+            "@02S0102.00Z"
+        );
+        assert!(patch_file_name(ResourceType::Sync36, 100, &MessageId::new(1, 2, 0, 36)).is_err());
+        assert!(patch_file_name(ResourceType::Script, 100, &id).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,554 @@
+//! SCI1.1 views: loops of cels, each a paletted bitmap, optionally with an
+//! embedded palette. Views are decoded with [`View`], and built (e.g. from
+//! edited cels) with [`build_view`].
+
+use anyhow::ensure;
+
+use super::palette::Palette;
+
+/// The loop number that marks a loop as its own, rather than a mirror of
+/// another.
+const NOT_MIRRORED: u8 = 0xFF;
+
+/// A decoded cel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cel {
+    pub width: u16,
+    pub height: u16,
+    /// The offset of the cel from the object's position. Cels are drawn
+    /// centered horizontally and resting on the position.
+    pub displace_x: i16,
+    pub displace_y: i16,
+    /// The color index drawn as transparent.
+    pub clear_key: u8,
+    /// Color indices, row by row.
+    pub pixels: Vec<u8>,
+}
+
+/// The sizes of the parts of the views built here, as in the game's own.
+const HEADER_SIZE: usize = 16;
+const LOOP_SIZE: usize = 16;
+const CEL_SIZE: usize = 36;
+
+/// The longest run a run byte can hold: six bits of length, plus 64 for
+/// copies with the second bit set.
+const MAX_RUN: usize = 0x3F;
+const MAX_COPY: usize = 0x3F + 64;
+
+/// A loop to build into a view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewLoop {
+    /// The loop this one mirrors, if it does. Mirroring loops have no cels
+    /// of their own.
+    pub mirror_of: Option<u8>,
+    pub cels: Vec<Cel>,
+}
+
+/// A view resource.
+pub struct View<'a> {
+    data: &'a [u8],
+    header_size: usize,
+    loop_count: u8,
+    loop_size: usize,
+    cel_size: usize,
+    palette_offset: usize,
+}
+
+pub(super) fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow::anyhow!("Data is truncated at {offset}"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub(super) fn read_u32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow::anyhow!("Data is truncated at {offset}"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'a> View<'a> {
+    pub fn parse(data: &'a [u8]) -> anyhow::Result<Self> {
+        ensure!(data.len() >= 16, "View data is too short");
+        // The stored header size doesn't include its own two bytes.
+        let header_size = usize::from(read_u16(data, 0)?) + 2;
+        let view = View {
+            data,
+            header_size,
+            loop_count: data[2],
+            loop_size: usize::from(data[12]),
+            cel_size: usize::from(data[13]),
+            palette_offset: read_u32(data, 8)? as usize,
+        };
+        ensure!(
+            view.header_size >= 16 && view.loop_size >= 16 && view.cel_size >= 32,
+            "Not a SCI1.1 view"
+        );
+        Ok(view)
+    }
+
+    pub fn loop_count(&self) -> u8 {
+        self.loop_count
+    }
+
+    /// The view's embedded palette, if it has one.
+    pub fn palette(&self) -> anyhow::Result<Option<Palette>> {
+        self.palette_data()?.map(Palette::parse).transpose()
+    }
+
+    /// The view's embedded palette, as stored, if it has one.
+    pub fn palette_data(&self) -> anyhow::Result<Option<&'a [u8]>> {
+        if self.palette_offset == 0 {
+            return Ok(None);
+        }
+        let data = self
+            .data
+            .get(self.palette_offset..)
+            .ok_or_else(|| anyhow::anyhow!("View palette is past the end of the data"))?;
+        Ok(Some(&data[..Palette::data_size(data)?]))
+    }
+
+    /// The loop that a loop mirrors, if it mirrors one.
+    pub fn mirror_of(&self, loop_num: u8) -> anyhow::Result<Option<u8>> {
+        let mirror = self.loop_data(loop_num)?[0];
+        Ok((mirror != NOT_MIRRORED).then_some(mirror))
+    }
+
+    /// Reads every loop, with the cels of those that aren't mirrors, so the
+    /// view can be built again with [`build_view`].
+    pub fn loops(&self) -> anyhow::Result<Vec<ViewLoop>> {
+        (0..self.loop_count)
+            .map(|loop_num| {
+                let mirror_of = self.mirror_of(loop_num)?;
+                let cels = match mirror_of {
+                    Some(_) => Vec::new(),
+                    None => self.cels(loop_num)?,
+                };
+                Ok(ViewLoop { mirror_of, cels })
+            })
+            .collect()
+    }
+
+    fn loop_data(&self, loop_num: u8) -> anyhow::Result<&'a [u8]> {
+        ensure!(
+            loop_num < self.loop_count,
+            "View has no loop {loop_num} (it has {})",
+            self.loop_count
+        );
+        let start = self.header_size + usize::from(loop_num) * self.loop_size;
+        self.data
+            .get(start..start + self.loop_size)
+            .ok_or_else(|| anyhow::anyhow!("Loop {loop_num} is past the end of the view"))
+    }
+
+    /// Decodes the cels of a loop. A loop that mirrors another has that
+    /// loop's cels, flipped horizontally.
+    pub fn cels(&self, loop_num: u8) -> anyhow::Result<Vec<Cel>> {
+        let mut loop_data = self.loop_data(loop_num)?;
+        let mirrored = loop_data[0] != NOT_MIRRORED;
+        if mirrored {
+            loop_data = self.loop_data(loop_data[0])?;
+        }
+        let cel_count = usize::from(loop_data[2]);
+        let cels_start = read_u32(loop_data, 12)? as usize;
+        (0..cel_count)
+            .map(|cel_num| {
+                let mut cel = self.cel(cels_start + cel_num * self.cel_size)?;
+                if mirrored {
+                    for row in cel.pixels.chunks_mut(usize::from(cel.width).max(1)) {
+                        row.reverse();
+                    }
+                    cel.displace_x = -cel.displace_x;
+                }
+                Ok(cel)
+            })
+            .collect()
+    }
+
+    fn cel(&self, offset: usize) -> anyhow::Result<Cel> {
+        let header = self
+            .data
+            .get(offset..offset + self.cel_size)
+            .ok_or_else(|| anyhow::anyhow!("Cel is past the end of the view"))?;
+        read_cel(self.data, header)
+    }
+}
+
+/// Decodes a cel from its header, with its pixel data in `data`. Views and
+/// pics store cels the same way.
+pub(super) fn read_cel(data: &[u8], header: &[u8]) -> anyhow::Result<Cel> {
+    let width = read_u16(header, 0)?;
+    let height = read_u16(header, 2)?;
+    let clear_key = *header
+        .get(8)
+        .ok_or_else(|| anyhow::anyhow!("Cel header is truncated"))?;
+    let rle_offset = read_u32(header, 24)? as usize;
+    let literal_offset = read_u32(header, 28)? as usize;
+    let mut displace_y = read_u16(header, 6)? as i16;
+    // The interpreter treats negative vertical offsets this way.
+    if displace_y < 0 {
+        displace_y += 255;
+    }
+    let pixel_count = usize::from(width) * usize::from(height);
+    let pixels = match (rle_offset, literal_offset) {
+        (0, 0) => anyhow::bail!("Cel has no pixel data"),
+        // Without separate literal data, the run data holds the colors
+        // inline.
+        (rle_offset, 0) => unpack_runs(data, rle_offset, None, pixel_count, clear_key)?,
+        (0, literal_offset) => data
+            .get(literal_offset..literal_offset + pixel_count)
+            .ok_or_else(|| anyhow::anyhow!("Cel pixels are past the end of the data"))?
+            .to_vec(),
+        (rle_offset, literal_offset) => unpack_runs(
+            data,
+            rle_offset,
+            Some(literal_offset),
+            pixel_count,
+            clear_key,
+        )?,
+    };
+    Ok(Cel {
+        width,
+        height,
+        displace_x: read_u16(header, 4)? as i16,
+        displace_y,
+        clear_key,
+        pixels,
+    })
+}
+
+/// Unpacks run-length encoded pixels. Each run byte has the run's kind in its
+/// top two bits: `00` and `01` copy colors (with `01` adding 64 to the
+/// length), `10` repeats one color, and `11` leaves pixels transparent.
+/// Colors come from the literal data if the cel has it, or follow the run
+/// bytes otherwise.
+fn unpack_runs(
+    data: &[u8],
+    rle_offset: usize,
+    literal_offset: Option<usize>,
+    pixel_count: usize,
+    clear_key: u8,
+) -> anyhow::Result<Vec<u8>> {
+    let truncated = || anyhow::anyhow!("Cel data is past the end of the data");
+    let mut pixels = vec![clear_key; pixel_count];
+    let mut run_pos = rle_offset;
+    let mut literal_pos = literal_offset;
+    let mut next_color = |run_pos: &mut usize, count: usize| -> anyhow::Result<&[u8]> {
+        let pos = literal_pos.as_mut().unwrap_or(run_pos);
+        let colors = data.get(*pos..*pos + count).ok_or_else(truncated)?;
+        *pos += count;
+        Ok(colors)
+    };
+    let mut pixel = 0;
+    while pixel < pixel_count {
+        let run = *data.get(run_pos).ok_or_else(truncated)?;
+        run_pos += 1;
+        let mut len = usize::from(run & 0x3F);
+        let end = |len: usize| (pixel + len).min(pixel_count);
+        match run & 0xC0 {
+            0x00 | 0x40 => {
+                if run & 0x40 != 0 {
+                    len += 64;
+                }
+                let colors = next_color(&mut run_pos, len)?;
+                let end = end(len);
+                pixels[pixel..end].copy_from_slice(&colors[..end - pixel]);
+            }
+            0x80 => {
+                let color = next_color(&mut run_pos, 1)?[0];
+                pixels[pixel..end(len)].fill(color);
+            }
+            _ => {}
+        }
+        pixel += len;
+    }
+    Ok(pixels)
+}
+
+/// Run-length encodes a cel's pixels, row by row, into run bytes and the
+/// literal colors they use. Transparent pixels become skips, repeats of three
+/// or more become fills, and the rest are copied.
+pub(super) fn encode_runs(cel: &Cel) -> (Vec<u8>, Vec<u8>) {
+    let mut runs = Vec::new();
+    let mut literals = Vec::new();
+    for row in cel.pixels.chunks(usize::from(cel.width).max(1)) {
+        let mut pos = 0;
+        let mut copy_start = None;
+        let flush_copy = |copy_start: &mut Option<usize>,
+                          end: usize,
+                          runs: &mut Vec<u8>,
+                          literals: &mut Vec<u8>| {
+            let Some(mut start) = copy_start.take() else {
+                return;
+            };
+            while start < end {
+                let len = (end - start).min(MAX_COPY);
+                runs.push(if len > MAX_RUN {
+                    0x40 | (len - 64) as u8
+                } else {
+                    len as u8
+                });
+                literals.extend_from_slice(&row[start..start + len]);
+                start += len;
+            }
+        };
+        while pos < row.len() {
+            let color = row[pos];
+            let same = row[pos..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&pixel| pixel == color)
+                .count();
+            if color == cel.clear_key {
+                flush_copy(&mut copy_start, pos, &mut runs, &mut literals);
+                runs.push(0xC0 | same as u8);
+            } else if same >= 3 {
+                flush_copy(&mut copy_start, pos, &mut runs, &mut literals);
+                runs.push(0x80 | same as u8);
+                literals.push(color);
+            } else {
+                copy_start.get_or_insert(pos);
+                pos += 1;
+                continue;
+            }
+            pos += same;
+        }
+        flush_copy(&mut copy_start, row.len(), &mut runs, &mut literals);
+    }
+    (runs, literals)
+}
+
+/// Builds a SCI1.1 view from its loops, with an embedded palette if given
+/// (as stored, e.g. from [`View::palette_data`]). The header's other fields
+/// are copied from `template`, if given, so a rebuilt view keeps them.
+pub fn build_view(
+    loops: &[ViewLoop],
+    palette_data: Option<&[u8]>,
+    template: Option<&View>,
+) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        !loops.is_empty() && loops.len() < usize::from(NOT_MIRRORED),
+        "A view has 1 to 254 loops, not {}",
+        loops.len()
+    );
+    for (loop_num, view_loop) in loops.iter().enumerate() {
+        if let Some(mirror_of) = view_loop.mirror_of {
+            let mirrored = loops.get(usize::from(mirror_of));
+            ensure!(
+                mirrored.is_some_and(|mirrored| mirrored.mirror_of.is_none()),
+                "Loop {loop_num} mirrors loop {mirror_of}, which isn't a loop with cels"
+            );
+            ensure!(
+                view_loop.cels.is_empty(),
+                "Loop {loop_num} mirrors another, so it can't have cels of its own"
+            );
+        } else {
+            ensure!(
+                !view_loop.cels.is_empty() && view_loop.cels.len() <= 255,
+                "Loop {loop_num} has {} cels; loops have 1 to 255",
+                view_loop.cels.len()
+            );
+        }
+        for (cel_num, cel) in view_loop.cels.iter().enumerate() {
+            ensure!(
+                cel.pixels.len() == usize::from(cel.width) * usize::from(cel.height),
+                "Cel {cel_num} of loop {loop_num} has {} pixels, not {}x{}",
+                cel.pixels.len(),
+                cel.width,
+                cel.height
+            );
+        }
+    }
+    let cel_count: usize = loops.iter().map(|view_loop| view_loop.cels.len()).sum();
+    let cels_start = HEADER_SIZE + loops.len() * LOOP_SIZE;
+    let mut data = vec![0u8; cels_start + cel_count * CEL_SIZE];
+    if let Some(template) = template {
+        data[3..8].copy_from_slice(&template.data[3..8]);
+        data[14..16].copy_from_slice(&template.data[14..16]);
+    }
+    data[0..2].copy_from_slice(&((HEADER_SIZE - 2) as u16).to_le_bytes());
+    data[2] = loops.len() as u8;
+    data[12] = LOOP_SIZE as u8;
+    data[13] = CEL_SIZE as u8;
+    if let Some(palette_data) = palette_data {
+        let offset = u32::try_from(data.len())?;
+        data[8..12].copy_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(palette_data);
+    }
+    // Where each loop's cels start.
+    let mut loop_cels = Vec::with_capacity(loops.len());
+    let mut cel_offset = cels_start;
+    for view_loop in loops {
+        loop_cels.push(cel_offset);
+        cel_offset += view_loop.cels.len() * CEL_SIZE;
+    }
+    for (loop_num, view_loop) in loops.iter().enumerate() {
+        let entry = HEADER_SIZE + loop_num * LOOP_SIZE;
+        // Mirrors repeat their loop's cel count and offset, as the game's
+        // own views do.
+        let source = view_loop.mirror_of.map_or(loop_num, usize::from);
+        data[entry] = view_loop.mirror_of.unwrap_or(NOT_MIRRORED);
+        data[entry + 2] = loops[source].cels.len() as u8;
+        data[entry + 12..entry + 16]
+            .copy_from_slice(&u32::try_from(loop_cels[source])?.to_le_bytes());
+        for (cel_num, cel) in view_loop.cels.iter().enumerate() {
+            let header = loop_cels[loop_num] + cel_num * CEL_SIZE;
+            let (runs, literals) = encode_runs(cel);
+            let rle_offset = u32::try_from(data.len())?;
+            data.extend_from_slice(&runs);
+            let literal_offset = u32::try_from(data.len())?;
+            data.extend_from_slice(&literals);
+            write_cel_header(
+                &mut data[header..header + CEL_SIZE],
+                cel,
+                rle_offset,
+                literal_offset,
+            );
+        }
+    }
+    Ok(data)
+}
+
+/// Writes a cel's header fields, pointing at its encoded runs and literal
+/// colors.
+pub(super) fn write_cel_header(header: &mut [u8], cel: &Cel, rle_offset: u32, literal_offset: u32) {
+    header[0..2].copy_from_slice(&cel.width.to_le_bytes());
+    header[2..4].copy_from_slice(&cel.height.to_le_bytes());
+    header[4..6].copy_from_slice(&cel.displace_x.to_le_bytes());
+    header[6..8].copy_from_slice(&cel.displace_y.to_le_bytes());
+    header[8] = cel.clear_key;
+    header[24..28].copy_from_slice(&rle_offset.to_le_bytes());
+    // A cel with no literal data must still point past its runs, as an
+    // offset of 0 means the colors are inline.
+    header[28..32].copy_from_slice(&literal_offset.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::palette::tests::sci11_palette;
+
+    /// Builds a view with one loop of the given cels (each as run data
+    /// followed by literal data), plus a second loop mirroring it.
+    fn view_data(cels: &[(u16, u16, Vec<u8>, Vec<u8>)], palette: Option<Vec<u8>>) -> Vec<u8> {
+        const HEADER: usize = 16;
+        const LOOP: usize = 16;
+        const CEL: usize = 36;
+        let mut data = vec![0u8; HEADER + 2 * LOOP + cels.len() * CEL];
+        data[0..2].copy_from_slice(&((HEADER - 2) as u16).to_le_bytes());
+        data[2] = 2;
+        data[12] = LOOP as u8;
+        data[13] = CEL as u8;
+        let cels_start = HEADER + 2 * LOOP;
+        data[HEADER] = NOT_MIRRORED;
+        data[HEADER + 2] = cels.len() as u8;
+        data[HEADER + 12..HEADER + 16].copy_from_slice(&(cels_start as u32).to_le_bytes());
+        data[HEADER + LOOP] = 0;
+        for (i, (width, height, runs, literals)) in cels.iter().enumerate() {
+            let cel = cels_start + i * CEL;
+            data[cel..cel + 2].copy_from_slice(&width.to_le_bytes());
+            data[cel + 2..cel + 4].copy_from_slice(&height.to_le_bytes());
+            data[cel + 4..cel + 6].copy_from_slice(&3i16.to_le_bytes());
+            data[cel + 8] = 0xFF;
+            let rle_offset = data.len() as u32;
+            data.extend_from_slice(runs);
+            let literal_offset = if literals.is_empty() {
+                0
+            } else {
+                let offset = data.len() as u32;
+                data.extend_from_slice(literals);
+                offset
+            };
+            data[cel + 24..cel + 28].copy_from_slice(&rle_offset.to_le_bytes());
+            data[cel + 28..cel + 32].copy_from_slice(&literal_offset.to_le_bytes());
+        }
+        if let Some(palette) = palette {
+            let offset = data.len() as u32;
+            data[8..12].copy_from_slice(&offset.to_le_bytes());
+            data.extend(palette);
+        }
+        data
+    }
+
+    #[test]
+    fn test_decode_cels() -> anyhow::Result<()> {
+        let data = view_data(
+            &[
+                // Two copied colors, two of one color, then two transparent.
+                (3, 2, vec![0x02, 0x82, 0xC2], vec![1, 2, 3]),
+                // The same, with the colors inline.
+                (3, 2, vec![0x02, 1, 2, 0x82, 3, 0xC2], Vec::new()),
+            ],
+            Some(sci11_palette(1, &[[10, 20, 30]])),
+        );
+        let view = View::parse(&data)?;
+        assert_eq!(view.loop_count(), 2);
+        let cels = view.cels(0)?;
+        assert_eq!(cels.len(), 2);
+        assert_eq!(cels[0].pixels, [1, 2, 3, 3, 0xFF, 0xFF]);
+        assert_eq!(cels[1].pixels, cels[0].pixels);
+        assert_eq!(cels[0].displace_x, 3);
+
+        let mirrored = view.cels(1)?;
+        assert_eq!(mirrored[0].pixels, [3, 2, 1, 0xFF, 0xFF, 3]);
+        assert_eq!(mirrored[0].displace_x, -3);
+        assert!(view.cels(2).is_err());
+
+        let palette = view.palette()?.unwrap();
+        assert_eq!(palette.color(1), Some([10, 20, 30]));
+        Ok(())
+    }
+
+    fn cel(width: u16, height: u16, pixels: Vec<u8>) -> Cel {
+        Cel {
+            width,
+            height,
+            displace_x: -2,
+            displace_y: 5,
+            clear_key: 0xFF,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn test_build_view() -> anyhow::Result<()> {
+        // Long copies and fills, transparency, and rows that end mid-run.
+        let mut wide: Vec<u8> = (0..150).map(|i| (i % 7) as u8).collect();
+        wide.extend([4; 100]);
+        wide.extend([0xFF; 50]);
+        let loops = vec![
+            ViewLoop {
+                mirror_of: None,
+                cels: vec![
+                    cel(3, 2, vec![1, 2, 3, 3, 0xFF, 0xFF]),
+                    cel(100, 3, wide),
+                    cel(1, 1, vec![0xFF]),
+                ],
+            },
+            ViewLoop {
+                mirror_of: Some(0),
+                cels: Vec::new(),
+            },
+        ];
+        let palette = sci11_palette(1, &[[10, 20, 30]]);
+        let data = build_view(&loops, Some(&palette), None)?;
+        let view = View::parse(&data)?;
+        assert_eq!(view.loops()?, loops);
+        assert_eq!(view.palette_data()?, Some(&palette[..]));
+        assert_eq!(view.cels(1)?[0].pixels, [3, 2, 1, 0xFF, 0xFF, 3]);
+
+        let rebuilt = build_view(&view.loops()?, view.palette_data()?, Some(&view))?;
+        assert_eq!(rebuilt, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_invalid_view() {
+        let loop_of = |mirror_of, cels| ViewLoop { mirror_of, cels };
+        assert!(build_view(&[], None, None).is_err());
+        assert!(build_view(&[loop_of(None, vec![cel(2, 2, vec![1; 3])])], None, None).is_err());
+        assert!(build_view(&[loop_of(Some(0), Vec::new())], None, None).is_err());
+        assert!(build_view(&[loop_of(None, Vec::new())], None, None).is_err());
+    }
+}
@@ -130,7 +130,7 @@ fn parse_dec_num<'a>() -> impl TextParser<'a, i64> {
 }
 
 fn parse_hex_chars<'a>() -> impl TextParser<'a, i64> {
-    let parser = map_res(
+    map_res(
         nom::character::complete::hex_digit1,
         |text: TextInput<'a>| {
             let text = text.content_slice();
@@ -146,8 +146,7 @@ fn parse_hex_chars<'a>() -> impl TextParser<'a, i64> {
             u64_bytes[8 - bytes.len()..].copy_from_slice(&bytes);
             Ok(i64::from_be_bytes(u64_bytes))
         },
-    );
-    parser
+    )
 }
 
 fn parse_num<'a>() -> impl TextParser<'a, Contents> {
@@ -0,0 +1,29 @@
+//! Book building over a synthetic game (see `sci-corpus`).
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use sci_corpus::{Corpus, CorpusConfig};
+use sci_resources::{ResourceType, file::open_game_resources, types::msg::parse_message_resource};
+use scitool_cli::bench::build_book;
+
+fn bench_book_build(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    Corpus::generate(&CorpusConfig::default())
+        .unwrap()
+        .write_to(dir.path())
+        .unwrap();
+    let resources = open_game_resources(dir.path()).unwrap();
+    let rooms: Vec<_> = resources
+        .resources_of_type(ResourceType::Message)
+        .map(|res| {
+            let messages = parse_message_resource(res.load_data().unwrap()).unwrap();
+            (res.id().resource_num(), messages)
+        })
+        .collect();
+
+    c.bench_function("book/build", |b| {
+        b.iter(|| build_book(&rooms).unwrap());
+    });
+}
+
+criterion_group!(benches, bench_book_build);
+criterion_main!(benches);
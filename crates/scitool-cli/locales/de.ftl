@@ -0,0 +1,132 @@
+# Meldungen von scitool auf Deutsch.
+
+## Ressourcen
+
+map-entries-unused = { $count ->
+    [one] { $count } Map-Eintrag wird vom Spiel nicht verwendet
+   *[other] { $count } Map-Einträge werden vom Spiel nicht verwendet
+}
+resource-not-found = Ressource nicht gefunden: { $id }
+no-numbered-patches = { $type }-Ressourcen können nicht als nummerierte Patches extrahiert werden
+writing-patch = Schreibe Ressource { $id } nach { $path }
+patch-staged = { $path } ist gesperrt; der Patch wurde stattdessen nach { $staged } geschrieben. Kopieren Sie ihn hinüber, sobald das Spiel geschlossen ist.
+not-in-volume = Ressource { $id } ist in keinem Ressourcenvolume gespeichert
+writing-raw = Schreibe Rohressource { $id } nach { $data } und { $meta }
+verify-summary = { $checked } Ressourcen geprüft, { $failed } fehlgeschlagen
+verify-failed = { $failed } Ressourcen konnten nicht gelesen werden
+warning-skipping = Warnung: { $id }: { $error }; wird übersprungen
+extract-resuming = Überspringe { $count } Ressourcen, die ein früherer Lauf extrahiert hat
+extract-summary = { $written } Ressourcen extrahiert, { $skipped } übersprungen
+compact-summary = { $volume }: { $old } Bytes → { $new } Bytes, { $dropped } ungenutzte Map-Einträge entfernt
+compact-unchanged = { $volume } ist bereits kompakt
+compact-resumed = { $volume } wurde bereits von einem früheren Lauf kompaktiert
+compact-backup = Original als { $path } aufbewahrt
+compact-stale-backup = { $path } stammt von einem früheren, nicht von einem unterbrochenen Lauf und ist womöglich älter als die Dateien des Spiels. Verschiebe sie, um die aktuellen Dateien zu kompaktieren.
+compact-volumes = In { $count } Volumes gepackt: { $volumes }
+compact-removed = { $path } entfernt, da keine Ressourcen mehr darin liegen
+compact-verified = Alle { $count } Ressourcen lesen sich unverändert
+compact-mismatch = { $id } liest sich nach dem Kompaktieren anders
+compact-too-large = { $volume } braucht auch kompaktiert { $size } Bytes, mehr als das Limit von { $max }. SCI1.1-Spiele lesen alle Ressourcen aus diesem einen Volume, daher kann es nicht aufgeteilt werden.
+rooms-without-audio-map = Räume mit Nachrichten, aber ohne Audio-Map: { $rooms }
+audio-mismatches = { $count } Abweichungen gefunden
+writing-heap-strings = Schreibe { $count } Heap-Strings aus { $scripts } Skripten nach { $path }
+view-exported = { $count } Cels nach { $path } exportiert
+view-colors-approximated = { $count ->
+    [one] { $count } Pixel hat eine Farbe, die nicht in der Palette ist; sie wurde durch die nächste ersetzt (mittlere Abweichung { $mean }, maximal { $max })
+   *[other] { $count } Pixel haben Farben, die nicht in der Palette sind; sie wurden durch die nächsten ersetzt (mittlere Abweichung { $mean }, maximal { $max })
+}
+view-no-palette = Das Spiel hat keine Palette, auf die die Farben der Cels abgebildet werden können
+pic-exported = Die visuelle Ebene von Pic { $pic } wurde nach { $path } geschrieben
+pic-no-visual = Pic { $pic } hat keine visuelle Ebene; es wird nur mit Vektoren gezeichnet
+pic-size-mismatch = { $path } ist { $width }x{ $height } groß, die visuelle Ebene des Pics aber { $expected_width }x{ $expected_height }
+font-exported = { $count } Zeichen nach { $path } exportiert
+font-too-many-glyphs = Die Schrift hat { $count } Zeichen; Schriften haben höchstens 256
+font-glyph-outside-cell = Zeichen { $code } ist { $width }x{ $height } groß, größer als seine Zelle von { $cell_width }x{ $cell_height }
+font-atlas-too-small = Der Atlas ist zu klein für Zeichen { $code }
+font-glyphs-removed = { $count ->
+    [one] Der neuen Schrift fehlt { $count } Zeichen der ursprünglichen; der Text des Spiels kann es verwenden
+   *[other] Der neuen Schrift fehlen { $count } Zeichen der ursprünglichen; der Text des Spiels kann sie verwenden
+}
+font-unmapped = Warnung: { $count ->
+    [one] { $count } Zeichen ist nicht in { $code_page }; ausgelassen: { $chars }
+   *[other] { $count } Zeichen sind nicht in { $code_page }; ausgelassen: { $chars }
+}
+font-line-height-changed = Warnung: Die Zeilenhöhe hat sich von { $old } auf { $new } geändert
+font-widths-changed = { $count ->
+    [one] { $count } Zeichen hat eine andere Breite, was vom Spiel von Hand gesetzten Text verschiebt: { $changes }
+   *[other] { $count } Zeichen haben eine andere Breite, was vom Spiel von Hand gesetzten Text verschiebt: { $changes }
+}
+font-widths-warning = Warnung: { $message }
+font-too-tall = Warnung: { $count ->
+    [one] { $count } Zeichen ist höher als die Zeilen der Schrift und überlappt die nächste: { $chars }
+   *[other] { $count } Zeichen sind höher als die Zeilen der Schrift und überlappen die nächste: { $chars }
+}
+font-added = { $count ->
+    [one] { $count } Zeichen hinzugefügt: { $chars }
+   *[other] { $count } Zeichen hinzugefügt: { $chars }
+}
+cursor-exported = Cursor { $cursor } nach { $path } exportiert
+cursor-size = Warnung: { $path } ist { $width }x{ $height } groß; Cursor sind { $size }x{ $size }, daher wurde es zugeschnitten oder aufgefüllt
+cursor-colors-approximated = Warnung: { $count ->
+    [one] { $count } Pixel ist weder schwarz, weiß, grau noch transparent; es wurde durch die nächste Farbe ersetzt
+   *[other] { $count } Pixel sind weder schwarz, weiß, grau noch transparent; sie wurden durch die nächsten Farben ersetzt
+}
+cursor-hotspot-outside = Warnung: Der Hotspot ({ $x }, { $y }) liegt außerhalb des Cursors; er wurde auf höchstens ({ $max }, { $max }) verschoben
+
+## Nachrichten
+
+writing-messages = Schreibe { $count } Nachrichten nach { $path }
+loaded-config = Konfiguration aus { $path } geladen
+check-rooms = Räume: { $count }
+check-nouns = Substantive: { $count }
+check-conversations = Gespräche: { $count }
+check-multi-line-conversations = Mehrzeilige Gespräche: { $count }
+check-lines = Zeilen: { $count }
+check-empty-lines = Leere Zeilen: { $count }
+check-dangling-reference = Zeile { $line } verweist auf { $target }, das keine Zeilen hat
+check-reference-lines = Verweiszeilen: { $count }
+check-placeholder-line = Zeile { $line } enthält Text, der zur Laufzeit eingesetzt wird ({ $placeholders }); sie kann nicht wörtlich aufgenommen werden: { $text }
+check-placeholder-lines = Zeilen mit Platzhaltern: { $count }
+check-narrator-lines = Erzählerzeilen: { $count }
+check-character-lines = Figurenzeilen: { $count }
+check-room = Raum { $room }:
+check-room-conditions = Bedingungen: { $count }
+replace-summary = { $lines } Zeilen in { $rooms } Räumen geändert
+replace-no-matches = Keine Zeile passt auf das Muster
+
+## Fehlerberichte
+
+record-run-failed = Warnung: Der Lauf konnte nicht in { $path } festgehalten werden: { $error }
+no-earlier-run = Warnung: Kein früherer Lauf festgehalten
+bundle-written = { $path } geschrieben ({ $files }). Bitte prüfen Sie die Datei, bevor Sie sie an einen Fehlerbericht anhängen.
+
+## Arbeitsbereiche
+
+unknown-project = Kein Projekt namens { $name } in { $path }
+no-workspace = Keine Arbeitsbereichsdatei; --project braucht eine { $file } in diesem oder einem übergeordneten Verzeichnis, oder --workspace
+no-game-dir = Kein Spielverzeichnis angegeben und kein Projekt, aus dem es stammen könnte
+no-book-config = Keine Buchkonfiguration angegeben, und das Projekt hat keine
+no-sample-dir = Kein Sample-Verzeichnis angegeben, und das Projekt hat keins
+
+## Projekt anlegen
+
+init-no-messages = Keine Nachrichten in { $path } gefunden; ist das ein Spielverzeichnis?
+init-found = { $rooms } Räume mit { $lines } Zeilen gefunden, gesprochen von { $talkers } Sprechern
+init-has-speech = Das Spiel hat Sprachausgabe (RESOURCE.AUD); Builds können an die Lautstärke des Originaltons angepasst werden
+init-file-exists = Warnung: { $path } existiert; bleibt unverändert
+init-done = Projekt { $name } in { $path } angelegt. Prüfe die Räume, Rollen und Verben in book.yaml und versuche dann `scitool gen master -o script.html`.
+
+## Spielerkennung
+
+detected-game = Erkannt: { $game }
+unknown-game = Keine bekannte Version
+detect-no-map = Keine RESOURCE.MAP für einen Fingerabdruck
+game-quirks = Besonderheiten: { $quirks }
+map-format = Ressourcenkarte: { $format }
+
+## Umbesetzung
+
+recast-locked = { $role } hat gesperrte Zeilen ({ $lines }); entsperre sie oder verwende --force
+recast-nothing = Für { $role } ist noch nichts aufgenommen
+recast-archived = { $takes } Takes von { $lines } Zeilen nach { $path } archiviert
+recast-patches = Neu zu bauende Patches: { $patches }
@@ -0,0 +1,133 @@
+# Messages printed by scitool, in English. Every message must be here; the
+# other catalogs fall back to these.
+
+## Resources
+
+map-entries-unused = { $count ->
+    [one] { $count } map entry is not used by the game
+   *[other] { $count } map entries are not used by the game
+}
+resource-not-found = Resource not found: { $id }
+no-numbered-patches = { $type } resources can't be extracted as numbered patches
+writing-patch = Writing resource { $id } to { $path }
+patch-staged = { $path } is locked; wrote patch to { $staged } instead. Copy it over once the game is closed.
+not-in-volume = Resource { $id } is not stored in a resource volume
+writing-raw = Writing raw resource { $id } to { $data } and { $meta }
+verify-summary = { $checked } resources checked, { $failed } failed
+verify-failed = { $failed } resources failed to read
+warning-skipping = Warning: { $id }: { $error }; skipping
+extract-resuming = Skipping { $count } resources extracted by an earlier run
+extract-summary = { $written } resources extracted, { $skipped } skipped
+compact-summary = { $volume }: { $old } bytes → { $new } bytes, { $dropped } unused map entries dropped
+compact-unchanged = { $volume } is already compact
+compact-resumed = { $volume } was compacted by an earlier run
+compact-backup = Kept the original as { $path }
+compact-stale-backup = { $path } is from an earlier run, not an interrupted one, so it may be older than the game's files. Move it away to compact the current files.
+compact-volumes = Packed into { $count } volumes: { $volumes }
+compact-removed = Removed { $path }, which no resources are in any more
+compact-verified = All { $count } resources read back identically
+compact-mismatch = { $id } reads differently after compacting
+compact-too-large = { $volume } needs { $size } bytes even when compacted, over the limit of { $max }. SCI1.1 games read all of their resources from this one volume, so it can't be split.
+rooms-without-audio-map = Rooms with messages but no audio map: { $rooms }
+audio-mismatches = { $count } mismatches found
+writing-heap-strings = Writing { $count } heap strings from { $scripts } scripts to { $path }
+view-exported = Exported { $count } cels to { $path }
+view-colors-approximated = { $count ->
+    [one] { $count } pixel has a color that isn't in the palette; it was matched to the nearest (mean error { $mean }, max { $max })
+   *[other] { $count } pixels have colors that aren't in the palette; they were matched to the nearest (mean error { $mean }, max { $max })
+}
+view-no-palette = The game has no palette to match the cels' colors to
+pic-exported = Wrote the visual layer of pic { $pic } to { $path }
+pic-no-visual = Pic { $pic } has no visual layer; it's drawn only with vectors
+pic-size-mismatch = { $path } is { $width }x{ $height }, but the pic's visual layer is { $expected_width }x{ $expected_height }
+font-exported = Exported { $count } characters to { $path }
+font-too-many-glyphs = The font has { $count } characters; fonts have at most 256
+font-glyph-outside-cell = Character { $code } is { $width }x{ $height }, larger than its { $cell_width }x{ $cell_height } cell
+font-atlas-too-small = The atlas is too small to hold character { $code }
+font-glyphs-removed = { $count ->
+    [one] The new font lacks { $count } character the original has; the game's text may use it
+   *[other] The new font lacks { $count } characters the original has; the game's text may use them
+}
+font-unmapped = Warning: { $count ->
+    [one] { $count } character isn't in { $code_page }; left out: { $chars }
+   *[other] { $count } characters aren't in { $code_page }; left out: { $chars }
+}
+font-line-height-changed = Warning: the line height changed from { $old } to { $new }
+font-widths-changed = { $count ->
+    [one] { $count } character changed width, which moves text the game laid out by hand: { $changes }
+   *[other] { $count } characters changed width, which moves text the game laid out by hand: { $changes }
+}
+font-widths-warning = Warning: { $message }
+font-too-tall = Warning: { $count ->
+    [one] { $count } character is taller than the font's lines and overlaps the next: { $chars }
+   *[other] { $count } characters are taller than the font's lines and overlap the next: { $chars }
+}
+font-added = { $count ->
+    [one] Added { $count } character: { $chars }
+   *[other] Added { $count } characters: { $chars }
+}
+cursor-exported = Exported cursor { $cursor } to { $path }
+cursor-size = Warning: { $path } is { $width }x{ $height }; cursors are { $size }x{ $size }, so it was cut or padded to fit
+cursor-colors-approximated = Warning: { $count ->
+    [one] { $count } pixel isn't black, white, gray or transparent; it was matched to the nearest
+   *[other] { $count } pixels aren't black, white, gray or transparent; they were matched to the nearest
+}
+cursor-hotspot-outside = Warning: the hotspot ({ $x }, { $y }) is outside the cursor; it was moved to at most ({ $max }, { $max })
+
+## Messages
+
+writing-messages = Writing { $count } messages to { $path }
+loaded-config = Loaded config from { $path }
+check-rooms = Num rooms: { $count }
+check-nouns = Num nouns: { $count }
+check-conversations = Num conversations: { $count }
+check-multi-line-conversations = Num multi-line conversations: { $count }
+check-lines = Num lines: { $count }
+check-empty-lines = Num empty lines: { $count }
+check-dangling-reference = Line { $line } refers to { $target }, which has no lines
+check-reference-lines = Num reference lines: { $count }
+check-placeholder-line = Line { $line } has text filled in at runtime ({ $placeholders }); it can't be recorded verbatim: { $text }
+check-placeholder-lines = Num lines with placeholders: { $count }
+check-narrator-lines = Num narrator lines: { $count }
+check-character-lines = Num character lines: { $count }
+check-room = Room { $room }:
+check-room-conditions = Num Conditions: { $count }
+replace-summary = { $lines } lines changed in { $rooms } rooms
+replace-no-matches = No lines match the pattern
+
+## Bug reports
+
+record-run-failed = Warning: failed to record the run in { $path }: { $error }
+no-earlier-run = Warning: no earlier run recorded
+bundle-written = Wrote { $path } ({ $files }). Look it over before attaching it to a bug report.
+
+## Workspaces
+
+unknown-project = No project named { $name } in { $path }
+no-workspace = No workspace file; --project needs a { $file } in this directory or above it, or --workspace
+no-game-dir = No game directory given, and no project to take it from
+no-book-config = No book config given, and the project doesn't have one
+no-sample-dir = No sample directory given, and the project doesn't have one
+
+## Starting a project
+
+init-no-messages = No messages found in { $path }; is it a game directory?
+init-found = Found { $rooms } rooms with { $lines } lines, spoken by { $talkers } talkers
+init-has-speech = The game has speech (RESOURCE.AUD); builds can match the original audio's loudness
+init-file-exists = Warning: { $path } exists; left as it is
+init-done = Started project { $name } in { $path }. Review the rooms, roles and verbs in book.yaml, then try `scitool gen master -o script.html`.
+
+## Detecting games
+
+detected-game = Detected: { $game }
+unknown-game = Not a known release
+detect-no-map = No RESOURCE.MAP to fingerprint
+game-quirks = Quirks: { $quirks }
+map-format = Resource map: { $format }
+
+## Recasting
+
+recast-locked = { $role } has locked lines ({ $lines }); unlock them or pass --force
+recast-nothing = Nothing recorded for { $role } yet
+recast-archived = Archived { $takes } takes of { $lines } lines to { $path }
+recast-patches = Patches to rebuild: { $patches }
@@ -0,0 +1,132 @@
+# Mensajes de scitool en español.
+
+## Recursos
+
+map-entries-unused = { $count ->
+    [one] { $count } entrada del mapa no se usa en el juego
+   *[other] { $count } entradas del mapa no se usan en el juego
+}
+resource-not-found = Recurso no encontrado: { $id }
+no-numbered-patches = Los recursos { $type } no se pueden extraer como parches numerados
+writing-patch = Escribiendo el recurso { $id } en { $path }
+patch-staged = { $path } está bloqueado; el parche se escribió en { $staged }. Cópielo cuando el juego esté cerrado.
+not-in-volume = El recurso { $id } no está guardado en un volumen de recursos
+writing-raw = Escribiendo el recurso sin procesar { $id } en { $data } y { $meta }
+verify-summary = { $checked } recursos comprobados, { $failed } con errores
+verify-failed = No se pudieron leer { $failed } recursos
+warning-skipping = Aviso: { $id }: { $error }; se omite
+extract-resuming = Se omiten { $count } recursos extraídos en una ejecución anterior
+extract-summary = { $written } recursos extraídos, { $skipped } omitidos
+compact-summary = { $volume }: { $old } bytes → { $new } bytes, { $dropped } entradas del mapa sin usar eliminadas
+compact-unchanged = { $volume } ya está compactado
+compact-resumed = { $volume } ya se compactó en una ejecución anterior
+compact-backup = Se conservó el original como { $path }
+compact-stale-backup = { $path } es de una ejecución anterior, no de una interrumpida, y puede ser más antiguo que los archivos del juego. Muévelo para compactar los archivos actuales.
+compact-volumes = Empaquetado en { $count } volúmenes: { $volumes }
+compact-removed = Se eliminó { $path }, que ya no contiene recursos
+compact-verified = Los { $count } recursos se leen igual que antes
+compact-mismatch = { $id } se lee distinto después de compactar
+compact-too-large = { $volume } ocupa { $size } bytes incluso compactado, más que el límite de { $max }. Los juegos SCI1.1 leen todos sus recursos de este único volumen, así que no se puede dividir.
+rooms-without-audio-map = Salas con mensajes pero sin mapa de audio: { $rooms }
+audio-mismatches = { $count } discrepancias encontradas
+writing-heap-strings = Escribiendo { $count } cadenas del heap de { $scripts } scripts en { $path }
+view-exported = Se exportaron { $count } cels a { $path }
+view-colors-approximated = { $count ->
+    [one] { $count } píxel tiene un color que no está en la paleta; se usó el más cercano (error medio { $mean }, máximo { $max })
+   *[other] { $count } píxeles tienen colores que no están en la paleta; se usaron los más cercanos (error medio { $mean }, máximo { $max })
+}
+view-no-palette = El juego no tiene una paleta con la que comparar los colores de los cels
+pic-exported = Se escribió la capa visual del pic { $pic } en { $path }
+pic-no-visual = El pic { $pic } no tiene capa visual; solo se dibuja con vectores
+pic-size-mismatch = { $path } mide { $width }x{ $height }, pero la capa visual del pic mide { $expected_width }x{ $expected_height }
+font-exported = Se exportaron { $count } caracteres a { $path }
+font-too-many-glyphs = La fuente tiene { $count } caracteres; las fuentes tienen 256 como máximo
+font-glyph-outside-cell = El carácter { $code } mide { $width }x{ $height }, más que su celda de { $cell_width }x{ $cell_height }
+font-atlas-too-small = El atlas es demasiado pequeño para el carácter { $code }
+font-glyphs-removed = { $count ->
+    [one] A la nueva fuente le falta { $count } carácter que tiene la original; el texto del juego puede usarlo
+   *[other] A la nueva fuente le faltan { $count } caracteres que tiene la original; el texto del juego puede usarlos
+}
+font-unmapped = Aviso: { $count ->
+    [one] { $count } carácter no está en { $code_page }; se omitió: { $chars }
+   *[other] { $count } caracteres no están en { $code_page }; se omitieron: { $chars }
+}
+font-line-height-changed = Aviso: la altura de línea cambió de { $old } a { $new }
+font-widths-changed = { $count ->
+    [one] { $count } carácter cambió de ancho, lo que mueve texto que el juego colocó a mano: { $changes }
+   *[other] { $count } caracteres cambiaron de ancho, lo que mueve texto que el juego colocó a mano: { $changes }
+}
+font-widths-warning = Aviso: { $message }
+font-too-tall = Aviso: { $count ->
+    [one] { $count } carácter es más alto que las líneas de la fuente y se solapa con la siguiente: { $chars }
+   *[other] { $count } caracteres son más altos que las líneas de la fuente y se solapan con la siguiente: { $chars }
+}
+font-added = { $count ->
+    [one] Se añadió { $count } carácter: { $chars }
+   *[other] Se añadieron { $count } caracteres: { $chars }
+}
+cursor-exported = Se exportó el cursor { $cursor } a { $path }
+cursor-size = Aviso: { $path } mide { $width }x{ $height }; los cursores miden { $size }x{ $size }, así que se recortó o se amplió
+cursor-colors-approximated = Aviso: { $count ->
+    [one] { $count } píxel no es negro, blanco, gris ni transparente; se usó el más cercano
+   *[other] { $count } píxeles no son negros, blancos, grises ni transparentes; se usaron los más cercanos
+}
+cursor-hotspot-outside = Aviso: el punto activo ({ $x }, { $y }) está fuera del cursor; se movió a ({ $max }, { $max }) como máximo
+
+## Mensajes
+
+writing-messages = Escribiendo { $count } mensajes en { $path }
+loaded-config = Configuración cargada de { $path }
+check-rooms = Salas: { $count }
+check-nouns = Sustantivos: { $count }
+check-conversations = Conversaciones: { $count }
+check-multi-line-conversations = Conversaciones de varias líneas: { $count }
+check-lines = Líneas: { $count }
+check-empty-lines = Líneas vacías: { $count }
+check-dangling-reference = La línea { $line } remite a { $target }, que no tiene líneas
+check-reference-lines = Líneas de referencia: { $count }
+check-placeholder-line = La línea { $line } tiene texto que se rellena durante el juego ({ $placeholders }); no se puede grabar tal cual: { $text }
+check-placeholder-lines = Líneas con marcadores: { $count }
+check-narrator-lines = Líneas de narrador: { $count }
+check-character-lines = Líneas de personajes: { $count }
+check-room = Sala { $room }:
+check-room-conditions = Condiciones: { $count }
+replace-summary = { $lines } líneas cambiadas en { $rooms } salas
+replace-no-matches = Ninguna línea coincide con el patrón
+
+## Informes de errores
+
+record-run-failed = Aviso: no se pudo registrar la ejecución en { $path }: { $error }
+no-earlier-run = Aviso: no hay ninguna ejecución anterior registrada
+bundle-written = Se escribió { $path } ({ $files }). Revíselo antes de adjuntarlo a un informe de error.
+
+## Espacios de trabajo
+
+unknown-project = No hay ningún proyecto llamado { $name } en { $path }
+no-workspace = No hay archivo de espacio de trabajo; --project necesita un { $file } en este directorio o en uno superior, o --workspace
+no-game-dir = No se indicó el directorio del juego y no hay ningún proyecto del que tomarlo
+no-book-config = No se indicó la configuración del libro y el proyecto no tiene ninguna
+no-sample-dir = No se indicó el directorio de muestras y el proyecto no tiene ninguno
+
+## Crear un proyecto
+
+init-no-messages = No se encontraron mensajes en { $path }; ¿es un directorio de juego?
+init-found = Se encontraron { $rooms } salas con { $lines } líneas, dichas por { $talkers } hablantes
+init-has-speech = El juego tiene voces (RESOURCE.AUD); las compilaciones pueden igualar el volumen del audio original
+init-file-exists = Aviso: { $path } ya existe; se deja como está
+init-done = Se creó el proyecto { $name } en { $path }. Revisa las salas, los papeles y los verbos en book.yaml y luego prueba `scitool gen master -o script.html`.
+
+## Detección de juegos
+
+detected-game = Detectado: { $game }
+unknown-game = No es una versión conocida
+detect-no-map = No hay RESOURCE.MAP del que sacar la huella
+game-quirks = Peculiaridades: { $quirks }
+map-format = Mapa de recursos: { $format }
+
+## Cambio de reparto
+
+recast-locked = { $role } tiene líneas bloqueadas ({ $lines }); desbloquéalas o usa --force
+recast-nothing = Aún no hay nada grabado para { $role }
+recast-archived = Se archivaron { $takes } tomas de { $lines } líneas en { $path }
+recast-patches = Parches que recompilar: { $patches }
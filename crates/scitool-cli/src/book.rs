@@ -10,6 +10,8 @@ use sci_utils::validation::{MultiValidator, ValidationError};
 
 pub mod builder;
 pub mod config;
+pub mod placeholder;
+pub mod segment;
 
 // Raw IDs.
 //
@@ -105,6 +107,12 @@ impl std::fmt::Debug for NounId {
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TalkerId(RawTalkerId);
 
+impl TalkerId {
+    pub fn talker_num(&self) -> u8 {
+        self.0.0
+    }
+}
+
 impl std::fmt::Debug for TalkerId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("RoleId").field(&self.0.0).finish()
@@ -161,6 +169,12 @@ impl std::fmt::Debug for ConversationId {
     }
 }
 
+/// Lines whose references lead back to a conversation already being
+/// followed.
+#[derive(Debug, thiserror::Error)]
+#[error("Line references form a cycle: {0:?}")]
+pub struct RefCycleError(Vec<ConversationId>);
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LineId(ConversationId, RawSequenceId);
 
@@ -215,6 +229,10 @@ struct ConditionEntry {
 struct LineEntry {
     text: String,
     talker: RawTalkerId,
+    /// The conversation this line stands in for, in the same room.
+    ref_target: Option<(RawNounId, ConversationKey)>,
+    /// True if the line was added by the config, and isn't in the game.
+    added: bool,
 }
 
 struct ConversationEntry {
@@ -236,10 +254,13 @@ struct RoomEntry {
 struct RoleEntry {
     name: String,
     short_name: String,
+    redact_cue_words: Option<usize>,
+    narrator: bool,
 }
 
 struct TalkerEntry {
     role_id: RawRoleId,
+    view: Option<u16>,
 }
 
 struct VerbEntry {
@@ -271,6 +292,78 @@ impl<'a> Line<'a> {
         &self.entry.text
     }
 
+    /// The conversation this line refers to, if any. Such lines stand in for
+    /// the whole referenced conversation, and usually have no text of their
+    /// own.
+    pub fn ref_target(&self) -> Option<ConversationId> {
+        self.entry
+            .ref_target
+            .map(|(noun, key)| ConversationId(NounId(self.parent.noun().room().id(), noun), key))
+    }
+
+    /// True if the line isn't in the original game, but was added for the
+    /// dub.
+    pub fn is_added(&self) -> bool {
+        self.entry.added
+    }
+
+    /// The lines the game shows for this line, following references. A line
+    /// without a reference resolves to itself; a reference to a conversation
+    /// that isn't in the book resolves to nothing.
+    pub fn resolved_lines(&self) -> Result<Vec<Line<'a>>, RefCycleError> {
+        let mut lines = Vec::new();
+        self.resolve_into(&mut vec![self.parent.id()], &mut lines)?;
+        Ok(lines)
+    }
+
+    /// The text the game shows for this line, following references. The
+    /// texts of multiple lines are separated by newlines.
+    #[expect(dead_code)]
+    pub fn resolved_text(&self) -> Result<String, RefCycleError> {
+        Ok(self
+            .resolved_lines()?
+            .iter()
+            .map(|line| line.text())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn resolve_into(
+        &self,
+        path: &mut Vec<ConversationId>,
+        lines: &mut Vec<Line<'a>>,
+    ) -> Result<(), RefCycleError> {
+        let Some(target_id) = self.ref_target() else {
+            lines.push(self.clone());
+            return Ok(());
+        };
+        path.push(target_id);
+        if path[..path.len() - 1].contains(&target_id) {
+            return Err(RefCycleError(path.clone()));
+        }
+        if let Some(target) = self.book().get_conversation(target_id) {
+            // Like the interpreter, play the conversation until a sequence
+            // number is missing.
+            for line in (1..=u8::MAX).map_while(|seq| target.get_line_inner(RawSequenceId(seq))) {
+                line.resolve_into(path, lines)?;
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+
+    /// The parts of the text to be recorded separately, according to the
+    /// book's split options.
+    pub fn segments(&self) -> Vec<segment::Segment> {
+        segment::split_segments(self.text(), &self.book().segments)
+    }
+
+    /// The format tokens in the text that are filled in by the game at
+    /// runtime. Lines with placeholders can't be recorded verbatim.
+    pub fn placeholders(&self) -> Vec<placeholder::Placeholder> {
+        placeholder::find_placeholders(self.text())
+    }
+
     pub fn talker(&self) -> Talker<'a> {
         self.book()
             .get_talker(TalkerId(self.entry.talker))
@@ -444,7 +537,6 @@ pub struct Talker<'a> {
 }
 
 impl<'a> Talker<'a> {
-    #[expect(dead_code)]
     pub fn id(&self) -> TalkerId {
         TalkerId(self.raw_id)
     }
@@ -455,6 +547,12 @@ impl<'a> Talker<'a> {
             .unwrap()
     }
 
+    /// The view with the talker's portrait, if it's known.
+    #[cfg_attr(not(feature = "graphics"), expect(dead_code))]
+    pub fn view(&self) -> Option<u16> {
+        self.entry.view
+    }
+
     #[expect(dead_code)]
     fn book(&self) -> &Book {
         self.parent
@@ -525,7 +623,13 @@ impl<'a> Room<'a> {
     }
 
     pub fn name(&self) -> &str {
-        self.entry.name.as_deref().unwrap_or("*NO NAME*")
+        self.configured_name().unwrap_or("*NO NAME*")
+    }
+
+    /// The name of the room from the config, or failing that the name of its
+    /// room object in the game's scripts, if it has one.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.entry.name.as_deref()
     }
 
     /// Get an iterator over all the nouns in this room.
@@ -580,14 +684,12 @@ pub struct Role<'a> {
     entry: &'a RoleEntry,
 }
 
-impl Role<'_> {
-    #[expect(dead_code)]
+impl<'a> Role<'a> {
     pub fn id(&self) -> RoleId {
         RoleId(self.raw_id.clone())
     }
 
     /// Get the full name of the role.
-    #[expect(dead_code)]
     pub fn name(&self) -> &str {
         &self.entry.name
     }
@@ -597,6 +699,27 @@ impl Role<'_> {
         &self.entry.short_name
     }
 
+    /// If other roles' lines should be redacted in this role's script
+    /// bundle, the number of words to keep at the end of each line as a cue.
+    pub fn redact_cue_words(&self) -> Option<usize> {
+        self.entry.redact_cue_words
+    }
+
+    /// True if this role is a narrator or system voice, rather than a
+    /// character.
+    pub fn is_narrator(&self) -> bool {
+        self.entry.narrator
+    }
+
+    /// The talkers whose lines this role speaks.
+    #[cfg_attr(not(feature = "graphics"), expect(dead_code))]
+    pub fn talkers(&self) -> impl Iterator<Item = Talker<'a>> + 'a + use<'a> {
+        let raw_id = self.raw_id;
+        self.parent
+            .talkers()
+            .filter(move |talker| &talker.entry.role_id == raw_id)
+    }
+
     #[expect(dead_code)]
     fn book(&self) -> &Book {
         self.parent
@@ -605,6 +728,7 @@ impl Role<'_> {
 
 pub struct Book {
     project_name: String,
+    segments: segment::SplitOptions,
     roles: BTreeMap<RawRoleId, RoleEntry>,
     talkers: BTreeMap<RawTalkerId, TalkerEntry>,
     verbs: BTreeMap<RawVerbId, VerbEntry>,
@@ -617,7 +741,7 @@ impl Book {
         &self.project_name
     }
 
-    pub fn rooms(&self) -> impl Iterator<Item = Room> {
+    pub fn rooms(&self) -> impl Iterator<Item = Room<'_>> {
         self.rooms.iter().map(|(&raw_id, entry)| Room {
             parent: self,
             raw_id,
@@ -625,8 +749,7 @@ impl Book {
         })
     }
 
-    #[expect(dead_code)]
-    pub fn roles(&self) -> impl Iterator<Item = Role> {
+    pub fn roles(&self) -> impl Iterator<Item = Role<'_>> {
         self.roles.iter().map(|(raw_id, entry)| Role {
             parent: self,
             raw_id,
@@ -635,7 +758,7 @@ impl Book {
     }
 
     #[expect(dead_code)]
-    pub fn verbs(&self) -> impl Iterator<Item = Verb> {
+    pub fn verbs(&self) -> impl Iterator<Item = Verb<'_>> {
         self.verbs.iter().map(|(&raw_id, entry)| Verb {
             parent: self,
             raw_id,
@@ -643,8 +766,7 @@ impl Book {
         })
     }
 
-    #[expect(dead_code)]
-    pub fn talkers(&self) -> impl Iterator<Item = Talker> {
+    pub fn talkers(&self) -> impl Iterator<Item = Talker<'_>> {
         self.talkers.iter().map(|(k, v)| Talker {
             parent: self,
             raw_id: *k,
@@ -652,25 +774,25 @@ impl Book {
         })
     }
 
-    pub fn nouns(&self) -> impl Iterator<Item = Noun> {
+    pub fn nouns(&self) -> impl Iterator<Item = Noun<'_>> {
         self.rooms().flat_map(|room| room.nouns())
     }
 
-    pub fn conversations(&self) -> impl Iterator<Item = Conversation> + '_ {
+    pub fn conversations(&self) -> impl Iterator<Item = Conversation<'_>> + '_ {
         self.nouns().flat_map(|noun| noun.conversations())
     }
 
-    pub fn lines(&self) -> impl Iterator<Item = Line> + '_ {
+    pub fn lines(&self) -> impl Iterator<Item = Line<'_>> + '_ {
         self.conversations()
             .flat_map(|conversation| conversation.lines())
     }
 
     #[expect(dead_code)]
-    pub fn conditions(&self) -> impl Iterator<Item = Condition> + '_ {
+    pub fn conditions(&self) -> impl Iterator<Item = Condition<'_>> + '_ {
         self.rooms().flat_map(|room| room.conditions())
     }
 
-    pub fn get_talker(&self, id: TalkerId) -> Option<Talker> {
+    pub fn get_talker(&self, id: TalkerId) -> Option<Talker<'_>> {
         self.talkers.get(&id.0).map(|entry| Talker {
             parent: self,
             raw_id: id.0,
@@ -678,7 +800,7 @@ impl Book {
         })
     }
 
-    pub fn get_role(&self, id: &RoleId) -> Option<Role> {
+    pub fn get_role(&self, id: &RoleId) -> Option<Role<'_>> {
         self.roles.get_key_value(&id.0).map(|(raw_id, entry)| Role {
             parent: self,
             raw_id,
@@ -686,7 +808,7 @@ impl Book {
         })
     }
 
-    pub fn get_verb(&self, id: VerbId) -> Option<Verb> {
+    pub fn get_verb(&self, id: VerbId) -> Option<Verb<'_>> {
         self.verbs.get(&id.0).map(|entry| Verb {
             parent: self,
             raw_id: id.0,
@@ -694,7 +816,7 @@ impl Book {
         })
     }
 
-    pub fn get_room(&self, id: RoomId) -> Option<Room> {
+    pub fn get_room(&self, id: RoomId) -> Option<Room<'_>> {
         self.rooms.get(&id.0).map(|entry| Room {
             parent: self,
             raw_id: id.0,
@@ -703,23 +825,23 @@ impl Book {
     }
 
     #[expect(dead_code)]
-    pub fn get_condition(&self, id: ConditionId) -> Option<Condition> {
+    pub fn get_condition(&self, id: ConditionId) -> Option<Condition<'_>> {
         self.get_room(id.0)
             .and_then(|room| room.get_condition_inner(id.1))
     }
 
-    pub fn get_noun(&self, id: NounId) -> Option<Noun> {
+    pub fn get_noun(&self, id: NounId) -> Option<Noun<'_>> {
         self.get_room(id.0)
             .and_then(|room| room.get_noun_inner(id.1))
     }
 
-    pub fn get_conversation(&self, id: ConversationId) -> Option<Conversation> {
+    pub fn get_conversation(&self, id: ConversationId) -> Option<Conversation<'_>> {
         self.get_noun(id.0)
             .and_then(|noun| noun.get_conversation_inner(id.1))
     }
 
     #[expect(dead_code)]
-    pub fn get_line(&self, id: LineId) -> Option<Line> {
+    pub fn get_line(&self, id: LineId) -> Option<Line<'_>> {
         self.get_conversation(id.0)
             .and_then(|conversation| conversation.get_line_inner(id.1))
     }
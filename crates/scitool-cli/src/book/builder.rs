@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, btree_map};
+use std::collections::{BTreeMap, BTreeSet, btree_map};
 
 use itertools::Itertools;
 
@@ -8,6 +8,7 @@ use sci_utils::validation::{IteratorExt as _, MultiValidator, ValidationError};
 use super::{
     Book, RawConditionId, RawNounId, RawRoleId, RawRoomId, RawSequenceId, RawTalkerId, RawVerbId,
     config::{self, BookConfig},
+    segment::SplitOptions,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -82,16 +83,23 @@ where
         .collect()
 }
 
+/// A conversation in a room, identified by its noun and key.
+type ConversationRef = (RawNounId, ConversationKey);
+
 #[derive(Debug, Clone)]
 pub(super) struct MessageEntry {
     talker: RawTalkerId,
     text: String,
+    ref_target: Option<ConversationRef>,
+    added: bool,
 }
 impl MessageEntry {
     fn build(&self, _ctxt: &Conversation) -> Result<super::LineEntry, BuildError> {
         Ok(super::LineEntry {
             text: self.text.clone(),
             talker: self.talker,
+            ref_target: self.ref_target,
+            added: self.added,
         })
     }
 }
@@ -132,6 +140,16 @@ impl Conversation {
                 vac.insert(MessageEntry {
                     talker: RawTalkerId(record.talker()),
                     text: record.text().to_string(),
+                    ref_target: record.ref_target().map(|target| {
+                        (
+                            RawNounId(target.noun()),
+                            ConversationKey {
+                                verb: RawVerbId(target.verb()),
+                                condition: RawConditionId(target.condition()),
+                            },
+                        )
+                    }),
+                    added: false,
                 });
                 Ok(())
             }
@@ -139,6 +157,18 @@ impl Conversation {
         }
     }
 
+    /// Appends a line after the conversation's last line.
+    fn append(&mut self, entry: MessageEntry) -> BuildResult<RawSequenceId> {
+        let sequence = match self.0.last_key_value() {
+            Some((RawSequenceId(last), _)) => last
+                .checked_add(1)
+                .ok_or_else(|| "Conversation has no sequence numbers left".to_string())?,
+            None => 1,
+        };
+        self.0.insert(RawSequenceId(sequence), entry);
+        Ok(RawSequenceId(sequence))
+    }
+
     fn build(&self, _ctxt: &BookBuilder) -> BuildResult<super::ConversationEntry> {
         Ok(super::ConversationEntry {
             lines: map_values(&self.0, |v| v.build(self))?,
@@ -149,6 +179,8 @@ impl Conversation {
 pub(super) struct RoleEntry {
     name: String,
     short_name: String,
+    redact_cue_words: Option<usize>,
+    narrator: bool,
 }
 
 impl RoleEntry {
@@ -161,6 +193,8 @@ impl RoleEntry {
         Ok(super::RoleEntry {
             name: self.name.clone(),
             short_name: self.short_name.clone(),
+            redact_cue_words: self.redact_cue_words,
+            narrator: self.narrator,
         })
     }
 }
@@ -168,6 +202,7 @@ impl RoleEntry {
 #[derive(Debug, Clone)]
 pub(super) struct TalkerEntry {
     role: RawRoleId,
+    view: Option<u16>,
 }
 
 impl TalkerEntry {
@@ -181,6 +216,7 @@ impl TalkerEntry {
     fn build(&self, _arg: &BookBuilder) -> Result<super::TalkerEntry, BuildError> {
         Ok(super::TalkerEntry {
             role_id: self.role.clone(),
+            view: self.view,
         })
     }
 }
@@ -279,9 +315,24 @@ impl NounEntry {
         Ok(())
     }
 
+    /// The first line of the noun's message for the verb, in its lowest
+    /// condition.
+    fn describe_with(&self, verb: RawVerbId) -> Option<String> {
+        let (_, conversation) = self
+            .conversation_set
+            .iter()
+            .find(|(key, _)| key.verb == verb)?;
+        let (_, first_line) = conversation.0.first_key_value()?;
+        let text = first_line.text.trim();
+        (first_line.ref_target.is_none() && !text.is_empty()).then(|| text.to_string())
+    }
+
     fn build(&self, ctxt: &BookBuilder) -> Result<super::NounEntry, BuildError> {
         Ok(super::NounEntry {
-            desc: self.desc.clone(),
+            desc: self.desc.clone().or_else(|| {
+                ctxt.description_verb
+                    .and_then(|verb| self.describe_with(verb))
+            }),
             is_cutscene: self.is_cutscene,
             conversations: map_values(&self.conversation_set, |v| v.build(ctxt))?,
         })
@@ -307,10 +358,61 @@ impl RoomEntry {
             .validate_ctxt("nouns", || {
                 self.nouns.iter().validate_all_values(|e| e.validate(ctxt))
             })
+            .validate_ctxt("references", || self.validate_refs())
             .build()?;
         Ok(())
     }
 
+    /// Checks that no conversation refers back to itself, which would loop
+    /// forever in the game.
+    fn validate_refs(&self) -> ValidateResult {
+        let mut validator = MultiValidator::new();
+        for (noun_id, noun) in &self.nouns {
+            for key in noun.conversation_set.keys() {
+                let start = (*noun_id, *key);
+                if let Some(cycle) = self.find_ref_cycle(start, &mut vec![start]) {
+                    validator.with_err(ValidationError::from(format!(
+                        "Conversation references form a cycle: {:?}",
+                        cycle
+                    )));
+                }
+            }
+        }
+        validator.build()
+    }
+
+    /// Finds a chain of references from the last conversation in the path
+    /// back to the first.
+    fn find_ref_cycle(
+        &self,
+        conversation: ConversationRef,
+        path: &mut Vec<ConversationRef>,
+    ) -> Option<Vec<ConversationRef>> {
+        let lines = self
+            .nouns
+            .get(&conversation.0)?
+            .conversation_set
+            .get(&conversation.1)?;
+        for target in lines.0.values().filter_map(|line| line.ref_target) {
+            if target == path[0] {
+                let mut cycle = path.clone();
+                cycle.push(target);
+                return Some(cycle);
+            }
+            // Cycles that don't include the start are found from their own
+            // conversations.
+            if path.contains(&target) {
+                continue;
+            }
+            path.push(target);
+            if let Some(cycle) = self.find_ref_cycle(target, path) {
+                return Some(cycle);
+            }
+            path.pop();
+        }
+        None
+    }
+
     fn build(&self, ctxt: &BookBuilder) -> BuildResult<super::RoomEntry> {
         Ok(super::RoomEntry {
             name: self.name.clone(),
@@ -358,35 +460,80 @@ impl RoomEntry {
             .or_default()
             .add_message(message, record)
     }
+
+    fn has_messages(&self) -> bool {
+        self.nouns
+            .values()
+            .any(|noun| !noun.conversation_set.is_empty())
+    }
+
+    fn add_line(&mut self, line: &config::AddedLineEntry) -> BuildResult<RawSequenceId> {
+        if let btree_map::Entry::Vacant(vac) = self.conditions.entry(line.condition) {
+            vac.insert(ConditionEntry { desc: None });
+        }
+        self.nouns
+            .entry(line.noun)
+            .or_default()
+            .conversation_set
+            .entry(ConversationKey {
+                verb: line.verb,
+                condition: line.condition,
+            })
+            .or_insert_with(Conversation::new)
+            .append(MessageEntry {
+                talker: line.talker,
+                text: line.text.clone(),
+                ref_target: None,
+                added: true,
+            })
+    }
 }
 
 pub struct BookBuilder {
     project_name: String,
+    segments: SplitOptions,
     roles: BTreeMap<RawRoleId, RoleEntry>,
     talkers: BTreeMap<RawTalkerId, TalkerEntry>,
     verbs: BTreeMap<RawVerbId, VerbEntry>,
     rooms: BTreeMap<RawRoomId, RoomEntry>,
+    added_lines: Vec<config::AddedLineEntry>,
+    /// The verb whose messages describe nouns without a configured
+    /// description, if any.
+    description_verb: Option<RawVerbId>,
 }
 
 impl BookBuilder {
     pub fn new(config: BookConfig) -> BuildResult<Self> {
+        let description_verb = config.describe_nouns_from_look.then(|| {
+            config
+                .verbs
+                .iter()
+                .find(|verb| verb.name.eq_ignore_ascii_case("look"))
+                .map_or(RawVerbId(1), |verb| verb.id)
+        });
         let builder = Self {
             project_name: config.project_name.clone(),
+            segments: config.segments.clone(),
             roles: group_pairs(config.roles.into_iter().map(|(k, v)| {
                 (
                     k,
                     RoleEntry {
                         name: v.name,
                         short_name: v.short_name,
+                        redact_cue_words: v.bundle.redact_others.then_some(v.bundle.cue_words),
+                        narrator: v.narrator,
+                    },
+                )
+            }))?,
+            talkers: group_pairs(config.talkers.into_iter().map(|talker| {
+                (
+                    talker.id,
+                    TalkerEntry {
+                        role: talker.role,
+                        view: talker.view,
                     },
                 )
             }))?,
-            talkers: group_pairs(
-                config
-                    .talkers
-                    .into_iter()
-                    .map(|talker| (talker.id, TalkerEntry { role: talker.role })),
-            )?,
             verbs: group_pairs(
                 config
                     .verbs
@@ -399,6 +546,8 @@ impl BookBuilder {
                     .into_iter()
                     .map(|room| Ok((room.id, RoomEntry::from_config(room)?))),
             )?,
+            added_lines: config.added_lines,
+            description_verb,
         };
 
         Ok(builder)
@@ -417,10 +566,57 @@ impl BookBuilder {
         Ok(self)
     }
 
-    pub fn build(self) -> BuildResult<Book> {
+    /// Names a room that the config doesn't name. Rooms without messages are
+    /// ignored.
+    #[cfg_attr(not(feature = "analysis"), expect(dead_code))]
+    pub fn add_room_name(&mut self, room: u16, name: String) -> &mut Self {
+        if let Some(entry) = self.rooms.get_mut(&RawRoomId(room))
+            && entry.name.is_none()
+        {
+            entry.name = Some(name);
+        }
+        self
+    }
+
+    /// Names a verb that the config doesn't.
+    #[cfg_attr(not(feature = "analysis"), expect(dead_code))]
+    pub fn add_verb_name(&mut self, verb: u8, name: String) -> &mut Self {
+        self.verbs
+            .entry(RawVerbId(verb))
+            .or_insert(VerbEntry { name });
+        self
+    }
+
+    /// Sets the portrait view of a talker that the config doesn't give one.
+    /// Talkers that aren't in the config are ignored.
+    #[cfg_attr(not(feature = "analysis"), expect(dead_code))]
+    pub fn add_talker_view(&mut self, talker: u8, view: u16) -> &mut Self {
+        if let Some(entry) = self.talkers.get_mut(&RawTalkerId(talker))
+            && entry.view.is_none()
+        {
+            entry.view = Some(view);
+        }
+        self
+    }
+
+    /// The verbs that messages use but that have no name.
+    pub fn unnamed_verbs(&self) -> BTreeSet<u8> {
+        self.rooms
+            .values()
+            .flat_map(|room| room.nouns.values())
+            .flat_map(|noun| noun.conversation_set.keys())
+            .map(|key| key.verb)
+            .filter(|verb| *verb != RawVerbId(0) && !self.verbs.contains_key(verb))
+            .map(|verb| verb.0)
+            .collect()
+    }
+
+    pub fn build(mut self) -> BuildResult<Book> {
+        self.add_configured_lines()?;
         self.validate()?;
         Ok(Book {
             project_name: self.project_name.clone(),
+            segments: self.segments.clone(),
             roles: map_values(&self.roles, |v| v.build(&self))?,
             talkers: map_values(&self.talkers, |v| v.build(&self))?,
             verbs: map_values(&self.verbs, |v| v.build(&self))?,
@@ -435,6 +631,34 @@ impl BookBuilder {
     }
 }
 
+impl BookBuilder {
+    /// Appends the lines added by the config, once all of the game's
+    /// messages are in, so they follow the original lines.
+    fn add_configured_lines(&mut self) -> BuildResult<()> {
+        for line in std::mem::take(&mut self.added_lines) {
+            if !self.talkers.contains_key(&line.talker) {
+                return Err(format!(
+                    "Added line {:?} has unknown talker {}",
+                    line.text, line.talker.0
+                )
+                .into());
+            }
+            let room = self
+                .rooms
+                .get_mut(&line.room)
+                .filter(|room| room.has_messages())
+                .ok_or_else(|| {
+                    format!(
+                        "Added line {:?} is in room {}, which has no messages",
+                        line.text, line.room.0
+                    )
+                })?;
+            room.add_line(&line)?;
+        }
+        Ok(())
+    }
+}
+
 /// Validation helpers. Internal
 impl BookBuilder {
     fn validate(&self) -> ValidateResult {
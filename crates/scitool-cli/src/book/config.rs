@@ -1,13 +1,48 @@
 use std::collections::BTreeMap;
 
+use sci_utils::encoding::CodePage;
 use serde::{Deserialize, Serialize};
 
-use super::{RawConditionId, RawNounId, RawRoleId, RawRoomId, RawTalkerId, RawVerbId};
+use super::{
+    RawConditionId, RawNounId, RawRoleId, RawRoomId, RawTalkerId, RawVerbId, segment::SplitOptions,
+};
+
+fn default_cue_words() -> usize {
+    5
+}
+
+/// Settings for the script bundle given to the actor playing a role.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct BundleConfig {
+    /// If true, other roles' lines are redacted, leaving only the last few
+    /// words of each line as a cue.
+    #[serde(default)]
+    pub redact_others: bool,
+    /// The number of words to keep at the end of redacted lines.
+    #[serde(default = "default_cue_words")]
+    pub cue_words: usize,
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        BundleConfig {
+            redact_others: false,
+            cue_words: default_cue_words(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(super) struct RoleEntry {
     pub name: String,
     pub short_name: String,
+    /// If true, this role is a narrator or system voice rather than a
+    /// character. Its lines are left out of casting statistics, and are
+    /// gathered in their own section of the master script.
+    #[serde(default)]
+    pub narrator: bool,
+    #[serde(default)]
+    pub bundle: BundleConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +50,10 @@ pub(super) struct TalkerEntry {
     pub id: RawTalkerId,
     // A reference to a role entry.
     pub role: RawRoleId,
+    /// The view with the talker's portrait. If not set, it is read from the
+    /// talker's object in the scripts, where it can be found.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view: Option<u16>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +104,18 @@ pub(super) struct RoomEntry {
     pub hidden: bool,
 }
 
+/// A line the dub adds to the game, such as accessibility narration. It is
+/// appended to its conversation, after the game's own lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct AddedLineEntry {
+    pub room: RawRoomId,
+    pub noun: RawNounId,
+    pub verb: RawVerbId,
+    pub condition: RawConditionId,
+    pub talker: RawTalkerId,
+    pub text: String,
+}
+
 /// The top-level script config structure, and embedding in the messages file.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BookConfig {
@@ -73,4 +124,93 @@ pub struct BookConfig {
     pub(super) talkers: Vec<TalkerEntry>,
     pub(super) verbs: Vec<VerbEntry>,
     pub(super) rooms: Vec<RoomEntry>,
+    /// The code page the game's message text is stored in. If not set, the
+    /// platform default is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) code_page: Option<CodePage>,
+    /// How line text is split into separately recorded segments.
+    #[serde(default)]
+    pub(super) segments: SplitOptions,
+    /// Lines that aren't in the original game.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) added_lines: Vec<AddedLineEntry>,
+    /// Describe nouns that have no configured description with the first
+    /// line of their Look message (the verb named "Look", or verb 1 if none
+    /// is).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(super) describe_nouns_from_look: bool,
+}
+
+impl BookConfig {
+    /// An empty config, for starting a project.
+    pub fn new(project_name: String) -> Self {
+        BookConfig {
+            project_name,
+            ..BookConfig::default()
+        }
+    }
+
+    pub fn code_page(&self) -> Option<CodePage> {
+        self.code_page
+    }
+
+    pub fn set_code_page(&mut self, code_page: CodePage) {
+        self.code_page = Some(code_page);
+    }
+
+    /// Adds a role, unless the config has one with the ID already.
+    pub fn add_role(&mut self, id: &str, name: &str, narrator: bool) {
+        self.roles
+            .entry(RawRoleId(id.to_string()))
+            .or_insert_with(|| RoleEntry {
+                name: name.to_string(),
+                short_name: name.to_string(),
+                narrator,
+                bundle: BundleConfig::default(),
+            });
+    }
+
+    /// Maps a talker to a role, unless the config maps it already.
+    pub fn add_talker(&mut self, talker: u8, role: &str) {
+        if !self.has_talker(talker) {
+            self.talkers.push(TalkerEntry {
+                id: RawTalkerId(talker),
+                role: RawRoleId(role.to_string()),
+                view: None,
+            });
+        }
+    }
+
+    /// Names a verb, unless the config names it already.
+    pub fn add_verb(&mut self, verb: u8, name: String) {
+        if !self.verbs.iter().any(|entry| entry.id.0 == verb) {
+            self.verbs.push(VerbEntry {
+                id: RawVerbId(verb),
+                name,
+            });
+        }
+    }
+
+    /// Names a room, unless the config has it already.
+    pub fn add_room(&mut self, room: u16, name: String) {
+        if !self.rooms.iter().any(|entry| entry.id.0 == room) {
+            self.rooms.push(RoomEntry {
+                id: RawRoomId(room),
+                name,
+                conditions: Vec::new(),
+                nouns: Vec::new(),
+                hidden: false,
+            });
+        }
+    }
+
+    /// Whether the config maps the talker to a role.
+    pub fn has_talker(&self, talker: u8) -> bool {
+        self.talkers.iter().any(|entry| entry.id.0 == talker)
+    }
+
+    #[cfg_attr(not(feature = "analysis"), expect(dead_code))]
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains_key(&RawRoleId(role.to_string()))
+    }
 }
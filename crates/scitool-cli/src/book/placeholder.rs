@@ -0,0 +1,88 @@
+//! Detection of interpolation tokens in line text.
+//!
+//! Some messages are used as format strings by the game scripts (via the
+//! `Format` kernel call), so parts of the text are filled in at runtime
+//! (e.g. "You have %d buckazoids."). These lines can't be recorded verbatim.
+
+/// The conversion characters accepted by the SCI `Format` kernel call.
+const CONVERSIONS: &[char] = &['d', 'u', 'x', 'X', 's', 'c'];
+
+/// A format token in a line's text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    token: String,
+}
+
+impl Placeholder {
+    /// The token, including the leading `%` (e.g. `%-3d`).
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+/// Finds all format tokens in the text. `%%` is a literal percent sign, and
+/// a `%` not followed by a valid token is treated as plain text.
+pub fn find_placeholders(text: &str) -> Vec<Placeholder> {
+    let mut placeholders = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        if ch != '%' {
+            continue;
+        }
+        if chars.next_if(|&(_, c)| c == '%').is_some() {
+            continue;
+        }
+        // Optional flags and width, e.g. "%-3d" or "%02x".
+        while chars
+            .next_if(|&(_, c)| c == '-' || c.is_ascii_digit())
+            .is_some()
+        {}
+        if let Some((i, c)) = chars.next_if(|&(_, c)| CONVERSIONS.contains(&c)) {
+            let end = i + c.len_utf8();
+            placeholders.push(Placeholder {
+                token: text[start..end].to_string(),
+            });
+        }
+    }
+    placeholders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<String> {
+        find_placeholders(text)
+            .iter()
+            .map(|placeholder| placeholder.token().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(tokens("You have %d buckazoids."), ["%d"]);
+        assert_eq!(tokens("%s says %s."), ["%s", "%s"]);
+        assert_eq!(tokens("Code %-3d, at %02x%c"), ["%-3d", "%02x", "%c"]);
+        assert!(tokens("No tokens here.").is_empty());
+    }
+
+    #[test]
+    fn test_literal_percent() {
+        assert!(tokens("100%% sure").is_empty());
+        // The escaped percent doesn't start a token, but the next one does.
+        assert_eq!(tokens("%%%d"), ["%d"]);
+        assert_eq!(tokens("%%d"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_malformed_specifiers() {
+        // A percent without a conversion is plain text.
+        assert!(tokens("50% off").is_empty());
+        assert!(tokens("ends with %").is_empty());
+        assert!(tokens("%-3 wide").is_empty());
+        assert!(tokens("%q is not a conversion").is_empty());
+        // Text after a malformed token is still searched.
+        assert_eq!(tokens("% then %u"), ["%u"]);
+        assert_eq!(tokens("caf\u{e9} %\u{e9}%s"), ["%s"]);
+    }
+}
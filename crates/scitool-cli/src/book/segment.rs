@@ -0,0 +1,278 @@
+//! Splitting of line text into segments that are recorded separately.
+//!
+//! Some messages contain stage directions for the actor (e.g. "(sighs)"), or
+//! several sentences that work better as separate audio chunks. Segments
+//! don't change the line IDs; they are only marked in exports.
+
+use serde::{Deserialize, Serialize};
+
+/// Which splits to apply to line text.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, clap::Args)]
+pub struct SplitOptions {
+    /// Split out parenthesized stage directions, e.g. "(sighs)".
+    #[serde(default)]
+    #[clap(long = "split-stage-directions")]
+    pub stage_directions: bool,
+    /// Split lines with several sentences into one segment per sentence.
+    #[serde(default)]
+    #[clap(long = "split-sentences")]
+    pub sentences: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentKind {
+    /// Text to be spoken.
+    Speech,
+    /// A direction for the actor, which is not spoken.
+    StageDirection,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    kind: SegmentKind,
+    text: String,
+}
+
+impl Segment {
+    pub fn kind(&self) -> SegmentKind {
+        self.kind
+    }
+
+    /// The text of the segment. For stage directions, this excludes the
+    /// parentheses.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+fn push_segment(segments: &mut Vec<Segment>, kind: SegmentKind, text: &str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        segments.push(Segment {
+            kind,
+            text: text.to_string(),
+        });
+    }
+}
+
+/// Abbreviations whose periods don't end a sentence.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "st", "jr", "sr", "prof", "capt", "lt", "sgt", "vs", "etc", "e.g",
+    "i.e",
+];
+
+/// True if the period at `period` ends an abbreviation or an initial (e.g.
+/// "Dr." or the "J." of "J. Smith"), rather than a sentence.
+fn is_abbreviation(text: &str, period: usize) -> bool {
+    let word = text[..period]
+        .rsplit(|c: char| c.is_whitespace() || c == '(' || c == '"')
+        .next()
+        .unwrap_or("");
+    let mut letters = word.chars();
+    let is_initial =
+        matches!((letters.next(), letters.next()), (Some(c), None) if c.is_uppercase());
+    is_initial
+        || ABBREVIATIONS
+            .iter()
+            .any(|abbreviation| word.eq_ignore_ascii_case(abbreviation))
+}
+
+/// Splits text after sentence-ending punctuation that is followed by
+/// whitespace. Runs of punctuation (e.g. "?!" or "...") and closing quotes
+/// stay with the sentence they end.
+fn split_sentences(text: &str, segments: &mut Vec<Segment>) {
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if !matches!(ch, '.' | '!' | '?') {
+            continue;
+        }
+        if ch == '.'
+            && chars.peek().is_some_and(|&(_, c)| c.is_whitespace())
+            && is_abbreviation(text, i)
+        {
+            continue;
+        }
+        while chars
+            .next_if(|&(_, c)| matches!(c, '.' | '!' | '?' | '"' | '\'' | '\u{201D}'))
+            .is_some()
+        {}
+        if let Some(&(end, c)) = chars.peek()
+            && c.is_whitespace()
+        {
+            push_segment(segments, SegmentKind::Speech, &text[start..end]);
+            start = end;
+        }
+    }
+    push_segment(segments, SegmentKind::Speech, &text[start..]);
+}
+
+fn split_speech(text: &str, options: &SplitOptions, segments: &mut Vec<Segment>) {
+    if options.sentences {
+        split_sentences(text, segments);
+    } else {
+        push_segment(segments, SegmentKind::Speech, text);
+    }
+}
+
+/// The index of the `)` that closes the `(` at `open`, counting nested
+/// parentheses.
+fn closing_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, ch) in text[open..].char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the text into segments. With no splits enabled, the whole text is
+/// a single speech segment. A stage direction runs to its matching
+/// parenthesis, so directions can contain parentheses of their own; an
+/// unclosed one is left as speech.
+pub fn split_segments(text: &str, options: &SplitOptions) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+    if options.stage_directions {
+        while let Some(open) = rest.find('(') {
+            let Some(close) = closing_paren(rest, open) else {
+                break;
+            };
+            split_speech(&rest[..open], options, &mut segments);
+            push_segment(
+                &mut segments,
+                SegmentKind::StageDirection,
+                &rest[open + 1..close],
+            );
+            rest = &rest[close + 1..];
+        }
+    }
+    split_speech(rest, options, &mut segments);
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(text: &str, stage_directions: bool, sentences: bool) -> Vec<(SegmentKind, String)> {
+        let options = SplitOptions {
+            stage_directions,
+            sentences,
+        };
+        split_segments(text, &options)
+            .into_iter()
+            .map(|segment| (segment.kind(), segment.text().to_string()))
+            .collect()
+    }
+
+    fn speech(text: &str) -> (SegmentKind, String) {
+        (SegmentKind::Speech, text.to_string())
+    }
+
+    fn direction(text: &str) -> (SegmentKind, String) {
+        (SegmentKind::StageDirection, text.to_string())
+    }
+
+    #[test]
+    fn test_no_splits() {
+        assert_eq!(
+            split("(sighs) Fine. Go.", false, false),
+            [speech("(sighs) Fine. Go.")]
+        );
+    }
+
+    #[test]
+    fn test_no_direction() {
+        assert_eq!(
+            split("Just talking here.", true, false),
+            [speech("Just talking here.")]
+        );
+        assert!(split("   ", true, true).is_empty());
+    }
+
+    #[test]
+    fn test_stage_directions() {
+        assert_eq!(
+            split("(sighs) Fine, then. (leaves)", true, false),
+            [
+                direction("sighs"),
+                speech("Fine, then."),
+                direction("leaves")
+            ]
+        );
+        // An unclosed parenthesis is spoken as it is.
+        assert_eq!(
+            split("Wait (no, never mind", true, false),
+            [speech("Wait (no, never mind")]
+        );
+    }
+
+    #[test]
+    fn test_nested_parentheses() {
+        assert_eq!(
+            split("(whispers (barely)) Over here.", true, false),
+            [direction("whispers (barely)"), speech("Over here.")]
+        );
+        assert_eq!(
+            split("Hi (waves (twice) (slowly)) there", true, false),
+            [
+                speech("Hi"),
+                direction("waves (twice) (slowly)"),
+                speech("there")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sentences() {
+        assert_eq!(
+            split("Hello! Is it \"you?\" Yes...  It is.", false, true),
+            [
+                speech("Hello!"),
+                speech("Is it \"you?\""),
+                speech("Yes..."),
+                speech("It is.")
+            ]
+        );
+        // Periods within a word don't split.
+        assert_eq!(
+            split("Version 1.5 is out.", false, true),
+            [speech("Version 1.5 is out.")]
+        );
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        assert_eq!(
+            split("Mr. Wilco met Dr. Beleauxs. She waved.", false, true),
+            [speech("Mr. Wilco met Dr. Beleauxs."), speech("She waved.")]
+        );
+        assert_eq!(
+            split("Ask R. Wilco, e.g. him. Now.", false, true),
+            [speech("Ask R. Wilco, e.g. him."), speech("Now.")]
+        );
+    }
+
+    #[test]
+    fn test_directions_and_sentences() {
+        assert_eq!(
+            split("One. Two. (pause) Three.", true, true),
+            [
+                speech("One."),
+                speech("Two."),
+                direction("pause"),
+                speech("Three.")
+            ]
+        );
+    }
+}
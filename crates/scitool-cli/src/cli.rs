@@ -1,12 +1,47 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use clap::{Parser, Subcommand};
-use sci_resources::{ResourceId, ResourceType, file::open_game_resources};
-use sci_utils::data_writer::{DataWriter, IoDataWriter};
+use itertools::Itertools;
+use sci_resources::{
+    ParseOptions, Quirk, ResourceId, ResourceType,
+    file::{
+        RawResource, ResourceSet, compact::compact_volumes, find_game_file, find_volume_files,
+        map::EntryStatus, open_game_resources_with_options, read_resource_map,
+        read_resources_from_volumes,
+    },
+    types::msg::parse_message_resource_with_options,
+};
+use sci_utils::{checkpoint::Checkpoint, fs, pool::BufferPool};
 
+use crate::i18n::{self, tr};
+
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "graphics")]
+mod cursor;
+mod debug;
+mod detect;
+#[cfg(feature = "graphics")]
+mod font;
 mod generate;
+#[cfg(feature = "gui")]
+mod gui;
+mod init;
 mod msg;
+#[cfg(feature = "graphics")]
+mod pic;
+#[cfg(feature = "recast")]
+mod recast;
+#[cfg(feature = "analysis")]
 mod script;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "graphics")]
+mod view;
+mod workspace;
 
 #[derive(Parser)]
 struct ListResources {
@@ -14,19 +49,61 @@ struct ListResources {
     root_dir: PathBuf,
     #[clap(long = "type", short = 't')]
     res_type: Option<ResourceType>,
+    /// List every entry of the resource maps, including entries marked as
+    /// not in use and entries replaced by a later duplicate.
+    #[clap(long)]
+    map_entries: bool,
 }
 
 impl ListResources {
     fn run(&self) -> anyhow::Result<()> {
-        let resource_dir_files = open_game_resources(&self.root_dir)?;
+        if self.map_entries {
+            return self.list_map_entries();
+        }
+        let resource_dir_files = detect::open_game(&self.root_dir)?;
         for id in resource_dir_files.resource_ids() {
-            if let Some(res_type) = self.res_type {
-                if id.type_id() != res_type {
+            if let Some(res_type) = self.res_type
+                && id.type_id() != res_type
+            {
+                continue;
+            }
+            println!("{:?}", id);
+        }
+        Ok(())
+    }
+
+    fn list_map_entries(&self) -> anyhow::Result<()> {
+        let mut num_dead = 0;
+        for map_name in ["RESOURCE.MAP", "MESSAGE.MAP"] {
+            let Some(map_path) = find_game_file(&self.root_dir, map_name)? else {
+                continue;
+            };
+            let locations = read_resource_map(&map_path)?;
+            // SCI0 and SCI1 maps spread the resources over several volumes.
+            let multi_volume = locations.format().has_volumes();
+            for location in locations.locations() {
+                if let Some(res_type) = self.res_type
+                    && location.id.type_id() != res_type
+                {
                     continue;
                 }
+                let status = match location.status {
+                    EntryStatus::Live => "",
+                    EntryStatus::Unused => " (not in use)",
+                    EntryStatus::Duplicate => " (replaced by a later duplicate)",
+                };
+                if location.status != EntryStatus::Live {
+                    num_dead += 1;
+                }
+                let place = if multi_volume {
+                    format!("{}:{}", location.volume, location.file_offset)
+                } else {
+                    location.file_offset.to_string()
+                };
+                println!("{map_name}: {:?} at {place}{status}", location.id);
             }
-            println!("{:?}", id);
         }
+        eprintln!("{}", tr!("map-entries-unused", count = num_dead));
         Ok(())
     }
 }
@@ -43,56 +120,147 @@ struct ExtractResourceAsPatch {
     dry_run: bool,
     #[clap(short = 'o', long)]
     output_dir: Option<PathBuf>,
+    /// Where to write the patch if the output file is locked (e.g. by a
+    /// running game).
+    #[clap(long)]
+    staging_dir: Option<PathBuf>,
+    /// Write the resource exactly as stored in the volume (including any
+    /// compression), with a JSON sidecar describing its entry header.
+    #[clap(long)]
+    raw: bool,
+}
+
+/// The entry header of a resource extracted with `--raw`.
+#[derive(serde::Serialize)]
+struct RawResourceMetadata {
+    resource_type: String,
+    resource_number: u16,
+    volume: String,
+    offset: u32,
+    packed_size: u64,
+    unpacked_size: u32,
+    compression_type: u16,
+}
+
+impl RawResourceMetadata {
+    fn from_raw(raw: &RawResource) -> Self {
+        let contents = raw.contents();
+        RawResourceMetadata {
+            resource_type: format!("{:?}", raw.id().type_id()),
+            resource_number: raw.id().resource_num(),
+            volume: raw
+                .volume()
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            offset: raw.offset(),
+            packed_size: contents.packed_size(),
+            unpacked_size: contents.unpacked_size(),
+            compression_type: contents.compression_type(),
+        }
+    }
 }
 
 impl ExtractResourceAsPatch {
+    fn out_root(&self) -> &Path {
+        if let Some(output_dir) = &self.output_dir {
+            return output_dir;
+        }
+        // The resources may come from a single Mac resource file, in which
+        // case the patch goes next to it.
+        if self.root_dir.is_file() {
+            return self.root_dir.parent().unwrap_or(Path::new("."));
+        }
+        &self.root_dir
+    }
+
     fn run(&self) -> anyhow::Result<()> {
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let resource_set = detect::open_game(&self.root_dir)?;
         let resource_id = ResourceId::new(self.resource_type, self.resource_id);
-        let contents = resource_set
-            .get_resource(&resource_id)
-            .ok_or_else(|| anyhow::anyhow!("Resource not found: {:?}", resource_id))?;
-        let ext = match self.resource_type {
-            ResourceType::Script => "SCR",
-            ResourceType::Heap => "HEP",
-            _ => {
-                anyhow::bail!("Unsupported resource type");
-            }
+        if self.raw {
+            return self.run_raw(&resource_set, &resource_id);
+        }
+        let contents = resource_set.get_resource(&resource_id).ok_or_else(|| {
+            anyhow::anyhow!(tr!("resource-not-found", id = format!("{resource_id:?}")))
+        })?;
+        let Some(patch_name) = contents.patch_file_name() else {
+            anyhow::bail!(tr!(
+                "no-numbered-patches",
+                type = format!("{:?}", self.resource_type)
+            ));
         };
 
-        let out_root = self.output_dir.as_ref().unwrap_or(&self.root_dir);
+        let out_root = self.out_root();
 
-        let filename = out_root.join(format!("{0}.{1}", self.resource_id, ext));
+        let filename = out_root.join(patch_name);
+        let message = tr!(
+            "writing-patch",
+            id = format!("{resource_id:?}"),
+            path = format!("{filename:?}")
+        );
         if self.dry_run {
-            eprintln!(
-                "DRY_RUN: Writing resource {restype:?}:{resid} to {filename:?}",
-                restype = self.resource_type,
-                resid = self.resource_id,
-                filename = filename
-            );
+            eprintln!("DRY_RUN: {message}");
         } else {
-            eprintln!(
-                "Writing resource {restype:?}:{resid} to {filename:?}",
-                restype = self.resource_type,
-                resid = self.resource_id,
-                filename = filename
-            );
-            {
-                let mut patch_file = IoDataWriter::new(
-                    std::fs::OpenOptions::new()
-                        .write(true)
-                        .create_new(true)
-                        .open(self.root_dir.join(filename))?,
+            eprintln!("{message}");
+            let mut pending = fs::PendingFile::create(&filename)?;
+            contents.write_patch_sync(pending.file())?;
+            let written = pending.commit(&fs::WriteOptions {
+                overwrite: false,
+                staging_dir: self.staging_dir.clone(),
+                on_locked: Some(&fs::ask_retry_locked),
+                ..fs::WriteOptions::default()
+            })?;
+            if let fs::Written::Staged(path) = written {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "patch-staged",
+                        path = format!("{filename:?}"),
+                        staged = format!("{path:?}")
+                    )
                 );
-
-                patch_file.write_u8(self.resource_type.into())?;
-                patch_file.write_u8(0)?; // Header Size
-                patch_file.write_block(&contents.load_data()?)?;
             }
         }
 
         Ok(())
     }
+
+    fn run_raw(&self, resource_set: &ResourceSet, resource_id: &ResourceId) -> anyhow::Result<()> {
+        let raw = resource_set.get_raw_resource(resource_id).ok_or_else(|| {
+            anyhow::anyhow!(tr!("not-in-volume", id = format!("{resource_id:?}")))
+        })?;
+        let contents = raw.contents();
+        let ext = match self.resource_type.to_file_ext() {
+            "" => format!("{:?}", self.resource_type).to_ascii_uppercase(),
+            ext => ext.to_ascii_uppercase(),
+        };
+        let out_root = self.out_root();
+        let data_filename = out_root.join(format!("{}.{}.raw", self.resource_id, ext));
+        let meta_filename = out_root.join(format!("{}.{}.raw.json", self.resource_id, ext));
+        let metadata = RawResourceMetadata::from_raw(&raw);
+        let message = tr!(
+            "writing-raw",
+            id = format!("{resource_id:?}"),
+            data = format!("{data_filename:?}"),
+            meta = format!("{meta_filename:?}")
+        );
+        if self.dry_run {
+            eprintln!("DRY_RUN: {message}");
+            return Ok(());
+        }
+        eprintln!("{message}");
+        let options = fs::WriteOptions {
+            overwrite: false,
+            ..fs::WriteOptions::default()
+        };
+        fs::write_file(&data_filename, &contents.data().open()?, &options)?;
+        fs::write_file(
+            &meta_filename,
+            &serde_json::to_vec_pretty(&metadata)?,
+            &options,
+        )?;
+        Ok(())
+    }
 }
 
 #[derive(Parser)]
@@ -107,23 +275,533 @@ struct DumpResource {
 
 impl DumpResource {
     fn run(&self) -> anyhow::Result<()> {
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let resource_set = detect::open_game(&self.root_dir)?;
         let resource_id = ResourceId::new(self.resource_type, self.resource_id);
-        let res = resource_set
-            .get_resource(&resource_id)
-            .ok_or_else(|| anyhow::anyhow!("Resource not found: {:?}", resource_id))?;
+        let res = resource_set.get_resource(&resource_id).ok_or_else(|| {
+            anyhow::anyhow!(tr!("resource-not-found", id = format!("{resource_id:?}")))
+        })?;
         let data = res.load_data()?;
         sci_utils::debug::hex_dump(&data, 0);
         Ok(())
     }
 }
 
+/// Checks that every resource of a game can be read, failing on anything
+/// malformed.
+#[derive(Parser)]
+struct VerifyResources {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+}
+
+impl VerifyResources {
+    fn run(&self) -> anyhow::Result<()> {
+        let options = ParseOptions::strict();
+        let resource_set = open_game_resources_with_options(
+            &self.root_dir,
+            &detect::with_game_quirks(&self.root_dir, options),
+        )?;
+        let mut num_checked = 0;
+        let mut num_failed = 0;
+        for res in resource_set.resources() {
+            let result = res.load_data().and_then(|data| {
+                if res.id().type_id() == ResourceType::Message {
+                    parse_message_resource_with_options(data, None, &options)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                println!("{:?}: {e}", res.id());
+                num_failed += 1;
+            }
+            num_checked += 1;
+        }
+        eprintln!(
+            "{}",
+            tr!("verify-summary", checked = num_checked, failed = num_failed)
+        );
+        anyhow::ensure!(num_failed == 0, tr!("verify-failed", failed = num_failed));
+        Ok(())
+    }
+}
+
+/// The map and volume files of a PC game, in any case. `RESOURCE.MAP` may
+/// have more volumes after `RESOURCE.000`, numbered by their extensions.
+const VOLUMES: [(&str, &str); 2] = [
+    ("RESOURCE.MAP", "RESOURCE.000"),
+    ("MESSAGE.MAP", "RESOURCE.MSG"),
+];
+
+/// Finds the volume files of a map in [`VOLUMES`], by volume number.
+fn map_volume_files(
+    root_dir: &Path,
+    map_name: &str,
+    volume_name: &str,
+) -> anyhow::Result<BTreeMap<u8, PathBuf>> {
+    if map_name == "RESOURCE.MAP" {
+        return Ok(find_volume_files(root_dir)?);
+    }
+    Ok(find_game_file(root_dir, volume_name)?
+        .map(|path| (0, path))
+        .into_iter()
+        .collect())
+}
+
+/// The file names of volumes, for messages.
+fn volume_names<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> String {
+    paths
+        .into_iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.to_string_lossy())
+        .join(", ")
+}
+
+/// Rewrites a game's volumes with only the entries their maps use, tightly
+/// packed, and regenerates the maps. Afterwards, checks that every resource
+/// reads back identically.
+///
+/// The resources of SCI0 and SCI1 games can be repacked into volumes of a
+/// given size, e.g. to fit on floppy disks, with `--max-volume-size`.
+///
+/// Each map and its volumes that are done are recorded in a journal in the
+/// output directory, so an interrupted run can be picked up with `--resume`.
+#[derive(Parser)]
+struct CompactResources {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    /// Write the compacted files here, instead of replacing the game's. When
+    /// replacing them, the originals are kept with a `.bak` extension.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+    /// Report how much space would be saved without writing anything.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+    /// Repack the resources of SCI0 and SCI1 games into `RESOURCE.000`,
+    /// `RESOURCE.001`, ..., each at most this many bytes, e.g. to fit on
+    /// floppy disks. SCI1.1 maps have no volume numbers, so the interpreter
+    /// only reads `RESOURCE.000`; for those games, this fails if the
+    /// compacted volume is larger.
+    #[clap(long)]
+    max_volume_size: Option<usize>,
+    /// Resume an interrupted run, skipping the maps and volumes it finished.
+    /// When replacing the game's files, any `.bak` copies left by the
+    /// interrupted run are taken as the originals. Without the interrupted
+    /// run's journal, `.bak` copies are from an earlier run, and this fails
+    /// rather than undo the changes made since.
+    #[clap(long)]
+    resume: bool,
+}
+
+impl CompactResources {
+    /// The journal of the map and volume pairs compacted so far, in the
+    /// output directory.
+    const CHECKPOINT_FILE: &str = ".compact-checkpoint";
+
+    /// Fails if any of the game's maps or volumes has a `.bak` copy, which
+    /// can only be from an earlier run when there's no journal to resume.
+    fn check_no_backups(&self) -> anyhow::Result<()> {
+        for (map_name, volume_name) in VOLUMES {
+            let Some(map_path) = find_game_file(&self.root_dir, map_name)? else {
+                continue;
+            };
+            let volume_paths = map_volume_files(&self.root_dir, map_name, volume_name)?;
+            for path in std::iter::once(&map_path).chain(volume_paths.values()) {
+                let backup = backup_path(path);
+                anyhow::ensure!(
+                    !backup.exists(),
+                    tr!("compact-stale-backup", path = backup.display().to_string())
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&self) -> anyhow::Result<()> {
+        let output_dir = self.output_dir.as_deref().unwrap_or(&self.root_dir);
+        let checkpoint_path = output_dir.join(Self::CHECKPOINT_FILE);
+        // Only a journal left by an interrupted run means that `.bak` files
+        // are its originals; older ones may predate later changes.
+        let resuming = self.resume && checkpoint_path.exists();
+        if self.resume && !resuming && self.output_dir.is_none() {
+            self.check_no_backups()?;
+        }
+        let mut checkpoint = if self.dry_run {
+            None
+        } else {
+            std::fs::create_dir_all(output_dir)?;
+            Some(Checkpoint::open(&checkpoint_path, self.resume)?)
+        };
+        for (map_name, volume_name) in VOLUMES {
+            let key = format!("{map_name}/{volume_name}");
+            if checkpoint
+                .as_ref()
+                .is_some_and(|checkpoint| checkpoint.is_complete(&key))
+            {
+                eprintln!("{}", tr!("compact-resumed", volume = volume_name));
+                continue;
+            }
+            let Some(map_path) = find_game_file(&self.root_dir, map_name)? else {
+                continue;
+            };
+            let volume_paths = map_volume_files(&self.root_dir, map_name, volume_name)?;
+            let Some(first_volume) = volume_paths.values().next().cloned() else {
+                continue;
+            };
+            // An interrupted run may have replaced the game's files already,
+            // but only after backing them up.
+            let resumed_backup = |path: &Path| {
+                let backup = backup_path(path);
+                (resuming && self.output_dir.is_none() && backup.exists()).then_some(backup)
+            };
+            let map_backup = resumed_backup(&map_path);
+            let volume_backups: BTreeMap<u8, PathBuf> = volume_paths
+                .iter()
+                .filter_map(|(&num, path)| Some((num, resumed_backup(path)?)))
+                .collect();
+            let map = std::fs::read(map_backup.as_deref().unwrap_or(&map_path))?;
+            let mut volumes = BTreeMap::new();
+            for (&num, path) in &volume_paths {
+                let source = volume_backups.get(&num).unwrap_or(path);
+                volumes.insert(num, std::fs::read(source)?);
+            }
+            let old_size: usize = volumes.values().map(Vec::len).sum();
+            let label = volume_names(volume_paths.values());
+
+            let compacted = compact_volumes(&map, &volumes, self.max_volume_size)?;
+            if let Some(max_size) = self.max_volume_size
+                && !compacted.format.has_volumes()
+            {
+                anyhow::ensure!(
+                    compacted.size() <= max_size,
+                    tr!(
+                        "compact-too-large",
+                        volume = label.as_str(),
+                        size = compacted.size(),
+                        max = max_size
+                    )
+                );
+            }
+            // Entries may be reordered, but that's not worth rewriting for,
+            // unless the volumes need splitting.
+            let fits = |len: usize| self.max_volume_size.is_none_or(|max_size| len <= max_size);
+            if compacted.dropped_entries == 0
+                && compacted.size() >= old_size
+                && compacted.volumes.keys().eq(volumes.keys())
+                && volumes.values().all(|volume| fits(volume.len()))
+                && map_backup.is_none()
+                && volume_backups.is_empty()
+            {
+                eprintln!("{}", tr!("compact-unchanged", volume = label.as_str()));
+                if let Some(checkpoint) = &mut checkpoint {
+                    checkpoint.mark_complete(&key)?;
+                }
+                continue;
+            }
+
+            // The new volumes are named as the first of the old ones is.
+            let new_volume_paths: BTreeMap<u8, PathBuf> = compacted
+                .volumes
+                .keys()
+                .map(|&num| {
+                    let path = if compacted.format.has_volumes() {
+                        first_volume.with_extension(format!("{num:03}"))
+                    } else {
+                        first_volume.clone()
+                    };
+                    (num, output_dir.join(path.file_name().unwrap_or_default()))
+                })
+                .collect();
+            eprintln!(
+                "{}",
+                tr!(
+                    "compact-summary",
+                    volume = label.as_str(),
+                    old = old_size,
+                    new = compacted.size(),
+                    dropped = compacted.dropped_entries
+                )
+            );
+            if !compacted.volumes.keys().eq(volumes.keys()) {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "compact-volumes",
+                        count = compacted.volumes.len(),
+                        volumes = volume_names(new_volume_paths.values())
+                    )
+                );
+            }
+            if self.dry_run {
+                continue;
+            }
+
+            // The files to check the compacted ones against afterwards. The
+            // map is backed up last, so if its backup exists, so do the
+            // volumes'.
+            let (original_map, original_volumes) = if self.output_dir.is_none() {
+                let mut original_volumes = BTreeMap::new();
+                for (&num, path) in &volume_paths {
+                    let backup = match volume_backups.get(&num) {
+                        Some(backup) => backup.clone(),
+                        None => back_up(path, &volumes[&num])?,
+                    };
+                    original_volumes.insert(num, backup);
+                }
+                let original_map = match map_backup {
+                    Some(backup) => backup,
+                    None => back_up(&map_path, &map)?,
+                };
+                (original_map, original_volumes)
+            } else {
+                (map_path.clone(), volume_paths.clone())
+            };
+            let new_map_path = output_dir.join(map_path.file_name().unwrap_or_default());
+            let options = fs::WriteOptions {
+                on_locked: Some(&fs::ask_retry_locked),
+                ..fs::WriteOptions::default()
+            };
+            for (num, path) in &new_volume_paths {
+                fs::write_file(path, &compacted.volumes[num], &options)?;
+            }
+            fs::write_file(&new_map_path, &compacted.map, &options)?;
+            // Temporary files are private; the game's files usually aren't.
+            let new_files = new_volume_paths
+                .iter()
+                .map(|(num, path)| (path, original_volumes.get(num).unwrap_or(&original_map)))
+                .chain([(&new_map_path, &original_map)]);
+            for (new_path, original_path) in new_files {
+                std::fs::set_permissions(
+                    new_path,
+                    std::fs::metadata(original_path)?.permissions(),
+                )?;
+            }
+
+            let original = read_resources_from_volumes(&original_map, &original_volumes, &[])?;
+            let rewritten = read_resources_from_volumes(&new_map_path, &new_volume_paths, &[])?;
+            let mut num_checked = 0;
+            for res in original.resources() {
+                let same = match rewritten.get_resource(res.id()) {
+                    Some(new_res) => *res.load_data()? == *new_res.load_data()?,
+                    None => false,
+                };
+                anyhow::ensure!(
+                    same,
+                    tr!("compact-mismatch", id = format!("{:?}", res.id()))
+                );
+                num_checked += 1;
+            }
+            anyhow::ensure!(
+                rewritten.resource_ids().count() == num_checked,
+                tr!("compact-mismatch", id = label.as_str())
+            );
+            eprintln!("{}", tr!("compact-verified", count = num_checked));
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.mark_complete(&key)?;
+            }
+            // Volumes that no resources are in any more would only confuse,
+            // and their backups are kept.
+            if self.output_dir.is_none() {
+                for (num, path) in &volume_paths {
+                    if !compacted.volumes.contains_key(num) {
+                        std::fs::remove_file(path)?;
+                        eprintln!(
+                            "{}",
+                            tr!("compact-removed", path = path.display().to_string())
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// The path of a file's backup, with a `.bak` extension added.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// Writes a copy of a file's contents next to it, with a `.bak` extension,
+/// without overwriting an earlier backup.
+fn back_up(path: &Path, contents: &[u8]) -> anyhow::Result<PathBuf> {
+    let backup = backup_path(path);
+    fs::write_file(
+        &backup,
+        contents,
+        &fs::WriteOptions {
+            overwrite: false,
+            ..fs::WriteOptions::default()
+        },
+    )?;
+    eprintln!(
+        "{}",
+        tr!("compact-backup", path = backup.display().to_string())
+    );
+    Ok(backup)
+}
+
+/// Extracts every resource of a game as a patch file, skipping any that
+/// can't be read.
+#[derive(Parser)]
+struct ExtractAllResources {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(short = 'o', long)]
+    output_dir: PathBuf,
+    #[clap(long = "type", short = 't')]
+    res_type: Option<ResourceType>,
+    #[clap(short = 'n', long, default_value = "false")]
+    dry_run: bool,
+    /// Fail on malformed data instead of skipping it.
+    #[clap(long)]
+    strict: bool,
+    /// Resume an interrupted extraction, skipping the resources it already
+    /// wrote. Progress is kept in a journal in the output directory until
+    /// the extraction finishes.
+    #[clap(long)]
+    resume: bool,
+}
+
+impl ExtractAllResources {
+    /// The journal of the resources extracted so far, in the output
+    /// directory.
+    const CHECKPOINT_FILE: &str = ".extract-checkpoint";
+
+    fn run(&self) -> anyhow::Result<()> {
+        let options = if self.strict {
+            ParseOptions::strict()
+        } else {
+            ParseOptions::permissive()
+        };
+        let resource_set = open_game_resources_with_options(
+            &self.root_dir,
+            &detect::with_game_quirks(&self.root_dir, options),
+        )?;
+        let mut checkpoint = if self.dry_run {
+            None
+        } else {
+            std::fs::create_dir_all(&self.output_dir)?;
+            let checkpoint =
+                Checkpoint::open(&self.output_dir.join(Self::CHECKPOINT_FILE), self.resume)?;
+            if checkpoint.num_complete() > 0 {
+                eprintln!(
+                    "{}",
+                    tr!("extract-resuming", count = checkpoint.num_complete())
+                );
+            }
+            Some(checkpoint)
+        };
+        let mut num_written = 0;
+        let mut num_skipped = 0;
+        for res in resource_set.resources() {
+            if let Some(res_type) = self.res_type
+                && res.id().type_id() != res_type
+            {
+                continue;
+            }
+            let Some(patch_name) = res.patch_file_name() else {
+                continue;
+            };
+            let key = format!("{:?}", res.id());
+            if checkpoint
+                .as_ref()
+                .is_some_and(|checkpoint| checkpoint.is_complete(&key))
+            {
+                continue;
+            }
+            let filename = self.output_dir.join(patch_name);
+            if self.dry_run {
+                eprintln!(
+                    "DRY_RUN: {}",
+                    tr!(
+                        "writing-patch",
+                        id = format!("{:?}", res.id()),
+                        path = format!("{filename:?}")
+                    )
+                );
+                num_written += 1;
+                continue;
+            }
+            let result = fs::PendingFile::create(&filename)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut pending| {
+                    res.write_patch_sync(pending.file())?;
+                    pending.commit(&fs::WriteOptions {
+                        overwrite: false,
+                        ..fs::WriteOptions::default()
+                    })?;
+                    Ok(())
+                });
+            match result {
+                Ok(()) => {
+                    if let Some(checkpoint) = &mut checkpoint {
+                        checkpoint.mark_complete(&key)?;
+                    }
+                    num_written += 1;
+                }
+                Err(e) if self.strict => return Err(e.context(key)),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        tr!(
+                            "warning-skipping",
+                            id = format!("{:?}", res.id()),
+                            error = e.to_string()
+                        )
+                    );
+                    num_skipped += 1;
+                }
+            }
+        }
+        eprintln!(
+            "{}",
+            tr!(
+                "extract-summary",
+                written = num_written,
+                skipped = num_skipped
+            )
+        );
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.finish()?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Subcommand)]
 enum ResourceCommand {
     #[clap(name = "list")]
     List(ListResources),
     ExtractAsPatch(ExtractResourceAsPatch),
+    ExtractAll(ExtractAllResources),
     Dump(DumpResource),
+    #[cfg(feature = "audio")]
+    CheckAudioMap(audio::CheckAudioMap),
+    Verify(VerifyResources),
+    Compact(CompactResources),
+    #[cfg(feature = "graphics")]
+    ExportView(view::ExportView),
+    #[cfg(feature = "graphics")]
+    ImportView(view::ImportView),
+    #[cfg(feature = "graphics")]
+    ExportPic(pic::ExportPic),
+    #[cfg(feature = "graphics")]
+    ImportPic(pic::ImportPic),
+    #[cfg(feature = "graphics")]
+    ExportFont(font::ExportFont),
+    #[cfg(feature = "graphics")]
+    ImportFont(font::ImportFont),
+    #[cfg(feature = "graphics")]
+    ExportCursor(cursor::ExportCursor),
+    #[cfg(feature = "graphics")]
+    ImportCursor(cursor::ImportCursor),
+    #[cfg(feature = "analysis")]
+    ExportHeapStrings(script::ExportHeapStrings),
 }
 
 impl ResourceCommand {
@@ -131,7 +809,30 @@ impl ResourceCommand {
         match self {
             ResourceCommand::List(list) => list.run()?,
             ResourceCommand::ExtractAsPatch(extract) => extract.run()?,
+            ResourceCommand::ExtractAll(extract) => extract.run()?,
             ResourceCommand::Dump(dump) => dump.run()?,
+            #[cfg(feature = "audio")]
+            ResourceCommand::CheckAudioMap(check) => check.run()?,
+            ResourceCommand::Verify(verify) => verify.run()?,
+            ResourceCommand::Compact(compact) => compact.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ExportView(export) => export.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ImportView(import) => import.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ExportPic(export) => export.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ImportPic(import) => import.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ExportFont(export) => export.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ImportFont(import) => import.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ExportCursor(export) => export.run()?,
+            #[cfg(feature = "graphics")]
+            ResourceCommand::ImportCursor(import) => import.run()?,
+            #[cfg(feature = "analysis")]
+            ResourceCommand::ExportHeapStrings(export) => export.run()?,
         }
         Ok(())
     }
@@ -157,8 +858,26 @@ enum Category {
     Message(msg::Messages),
     #[clap(name = "gen")]
     Generate(generate::Generate),
+    #[clap(name = "debug-bundle")]
+    DebugBundle(debug::DebugBundle),
+    #[cfg(feature = "analysis")]
     #[clap(name = "script")]
     Script(script::Script),
+    #[cfg(feature = "gui")]
+    #[clap(name = "gui")]
+    Gui(gui::Gui),
+    #[cfg(feature = "serve")]
+    #[clap(name = "serve")]
+    Serve(serve::Serve),
+    #[clap(name = "workspace")]
+    Workspace(workspace::WorkspaceCmd),
+    #[clap(name = "init")]
+    Init(init::Init),
+    #[clap(name = "detect")]
+    Detect(detect::Detect),
+    #[cfg(feature = "recast")]
+    #[clap(name = "recast")]
+    Recast(recast::Recast),
 }
 
 impl Category {
@@ -167,7 +886,18 @@ impl Category {
             Category::Resource(res) => res.run(),
             Category::Message(msg) => msg.run(),
             Category::Generate(generate) => generate.run(),
+            Category::DebugBundle(debug_bundle) => debug_bundle.run(),
+            #[cfg(feature = "analysis")]
             Category::Script(script) => script.run(),
+            #[cfg(feature = "gui")]
+            Category::Gui(gui) => gui.run(),
+            #[cfg(feature = "serve")]
+            Category::Serve(serve) => serve.run(),
+            Category::Workspace(workspace) => workspace.run(),
+            Category::Init(init) => init.run(),
+            Category::Detect(detect) => detect.run(),
+            #[cfg(feature = "recast")]
+            Category::Recast(recast) => recast.run(),
         }
     }
 }
@@ -176,10 +906,56 @@ impl Category {
 pub struct Cli {
     #[clap(subcommand)]
     category: Category,
+    /// Print the decompression buffer pool's statistics when done, for
+    /// tuning.
+    #[clap(long, global = true)]
+    pool_stats: bool,
+    /// The language of messages. Defaults to the locale's, if there is a
+    /// translation for it, and English otherwise.
+    #[clap(long, global = true, value_enum)]
+    lang: Option<i18n::Lang>,
+    /// The workspace project to use for paths that aren't given. Defaults to
+    /// the workspace's default project.
+    #[clap(long, global = true)]
+    project: Option<String>,
+    /// The workspace file. Defaults to `scitool-workspace.yaml` in the
+    /// current directory or the nearest one above it.
+    #[clap(long, global = true)]
+    workspace: Option<PathBuf>,
+    /// The quirks to expect in the game's resource files, as a
+    /// comma-separated list, instead of those of the detected release.
+    /// `--quirks` alone expects none.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    quirks: Option<Vec<Quirk>>,
 }
 
 impl Cli {
     pub fn run(&self) -> anyhow::Result<()> {
-        self.category.run()
+        if let Some(lang) = self.lang {
+            i18n::set_lang(lang);
+        }
+        if let Some(quirks) = &self.quirks {
+            detect::force_quirks(quirks.iter().copied().collect());
+        }
+        workspace::select(workspace::Selection {
+            workspace: self.workspace.clone(),
+            project: self.project.clone(),
+        });
+        let result = self.category.run();
+        if self.pool_stats {
+            eprintln!("Buffer pool: {}", BufferPool::global().stats());
+        }
+        // Keep the run being reported on, rather than the bundle's own.
+        if !matches!(self.category, Category::DebugBundle(_)) {
+            debug::record_last_run(&result);
+        }
+        result
     }
 }
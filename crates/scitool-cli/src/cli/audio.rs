@@ -0,0 +1,74 @@
+//! Commands for the game's speech audio.
+
+use std::{collections::BTreeSet, path::PathBuf};
+
+use clap::Parser;
+use sci_resources::{
+    ResourceId, ResourceType,
+    types::{
+        audio36::{Audio36Map, check_audio_map},
+        msg::parse_message_resource,
+    },
+};
+
+use super::detect;
+use crate::i18n::tr;
+
+/// Compares each room's messages with its audio map, reporting messages with
+/// no audio, audio with no message, and gaps in conversation sequences.
+#[derive(Parser)]
+pub(super) struct CheckAudioMap {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    /// Only check this room.
+    #[clap(short = 'r', long)]
+    room: Option<u16>,
+}
+
+impl CheckAudioMap {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let mut rooms = BTreeSet::new();
+        for id in resource_set.resource_ids() {
+            // The map numbered 65535 is the game's own audio map, not a room's.
+            if matches!(id.type_id(), ResourceType::Message | ResourceType::Map)
+                && id.resource_num() != 65535
+                && self.room.is_none_or(|room| room == id.resource_num())
+            {
+                rooms.insert(id.resource_num());
+            }
+        }
+        let mut num_mismatches = 0;
+        let mut rooms_without_map = Vec::new();
+        for room in rooms {
+            let messages =
+                match resource_set.get_resource(&ResourceId::new(ResourceType::Message, room)) {
+                    Some(res) => parse_message_resource(res.load_data()?)?
+                        .messages()
+                        .map(|(id, _)| *id)
+                        .collect(),
+                    None => Vec::new(),
+                };
+            let Some(map) = resource_set.get_resource(&ResourceId::new(ResourceType::Map, room))
+            else {
+                rooms_without_map.push(room);
+                continue;
+            };
+            let map = Audio36Map::from_block(&map.load_data()?)?;
+            for mismatch in check_audio_map(messages, &map) {
+                println!("Room {room}: {mismatch}");
+                num_mismatches += 1;
+            }
+        }
+        if !rooms_without_map.is_empty() {
+            let rooms = rooms_without_map
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            eprintln!("{}", tr!("rooms-without-audio-map", rooms = rooms));
+        }
+        eprintln!("{}", tr!("audio-mismatches", count = num_mismatches));
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Exporting a cursor as a PNG, and building a cursor patch from an edited
+//! one.
+//!
+//! An export holds `cursor.png` and `cursor.json`, giving the hotspot.
+//! Cursors are 16x16 and have only black, white, gray and transparent
+//! pixels, so imports warn about (and fit) edits that go past that, and
+//! keep the hotspot within the cursor.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use sci_resources::{
+    ResourceId, ResourceType,
+    types::{
+        cursor::{CURSOR_SIZE, Cursor, TRANSPARENT, build_cursor},
+        quantize::{QuantizeOptions, quantize},
+        view::Cel,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    detect,
+    view::{load_resource, read_png, write_patch, write_png},
+};
+use crate::i18n::tr;
+
+const IMAGE_FILE: &str = "cursor.png";
+const METADATA_FILE: &str = "cursor.json";
+
+#[derive(Serialize, Deserialize)]
+struct CursorMetadata {
+    cursor: u16,
+    /// The point the mouse position refers to. The original's is kept if
+    /// these are left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hotspot_x: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hotspot_y: Option<u16>,
+}
+
+/// Writes a cursor as a PNG, with `cursor.json` giving its hotspot, for
+/// editing and `import-cursor`.
+#[derive(Parser)]
+pub(super) struct ExportCursor {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    cursor: u16,
+    #[clap(short = 'o', long)]
+    output_dir: PathBuf,
+}
+
+impl ExportCursor {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let cursor = Cursor::parse(&load_resource(
+            &resource_set,
+            &ResourceId::new(ResourceType::Cursor, self.cursor),
+        )?)?;
+        std::fs::create_dir_all(&self.output_dir)?;
+        let cel = Cel {
+            width: CURSOR_SIZE,
+            height: CURSOR_SIZE,
+            displace_x: 0,
+            displace_y: 0,
+            clear_key: TRANSPARENT,
+            pixels: cursor.pixels,
+        };
+        write_png(&self.output_dir.join(IMAGE_FILE), &cel, &Cursor::palette())?;
+        let metadata = CursorMetadata {
+            cursor: self.cursor,
+            hotspot_x: Some(cursor.hotspot_x),
+            hotspot_y: Some(cursor.hotspot_y),
+        };
+        std::fs::write(
+            self.output_dir.join(METADATA_FILE),
+            serde_json::to_vec_pretty(&metadata)?,
+        )?;
+        eprintln!(
+            "{}",
+            tr!(
+                "cursor-exported",
+                cursor = self.cursor,
+                path = format!("{:?}", self.output_dir)
+            )
+        );
+        Ok(())
+    }
+}
+
+/// Builds a cursor patch from a directory written by `export-cursor`, after
+/// its PNG has been edited.
+#[derive(Parser)]
+pub(super) struct ImportCursor {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    /// The directory written by `export-cursor`.
+    #[clap(index = 2)]
+    input_dir: PathBuf,
+    /// Where to write the patch. Defaults to the game directory.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+}
+
+impl ImportCursor {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let metadata: CursorMetadata =
+            serde_json::from_slice(&std::fs::read(self.input_dir.join(METADATA_FILE))?)?;
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let id = ResourceId::new(ResourceType::Cursor, metadata.cursor);
+        let original = Cursor::parse(&load_resource(&resource_set, &id)?)?;
+
+        let path = self.input_dir.join(IMAGE_FILE);
+        let (width, height, rgba) = read_png(&path)?;
+        let size = usize::from(CURSOR_SIZE);
+        if (width, height) != (CURSOR_SIZE, CURSOR_SIZE) {
+            eprintln!(
+                "{}",
+                tr!(
+                    "cursor-size",
+                    path = format!("{path:?}"),
+                    width = width,
+                    height = height,
+                    size = CURSOR_SIZE
+                )
+            );
+        }
+        // Cut or pad the image to the cursor's size, with transparency.
+        let fitted: Vec<[u8; 4]> = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                if x < usize::from(width) && y < usize::from(height) {
+                    rgba[y * usize::from(width) + x]
+                } else {
+                    [0; 4]
+                }
+            })
+            .collect();
+        let quantized = quantize(
+            &fitted,
+            size,
+            &Cursor::palette(),
+            &QuantizeOptions::new(TRANSPARENT),
+        )?;
+        if quantized.error.approximated() > 0 {
+            eprintln!(
+                "{}",
+                tr!(
+                    "cursor-colors-approximated",
+                    count = quantized.error.approximated()
+                )
+            );
+        }
+
+        let mut cursor = Cursor {
+            hotspot_x: metadata.hotspot_x.unwrap_or(original.hotspot_x),
+            hotspot_y: metadata.hotspot_y.unwrap_or(original.hotspot_y),
+            pixels: quantized.pixels,
+        };
+        // SCI0 hotspots hold a flag, which may be outside the cursor; those
+        // are kept as they are.
+        let hotspot = (cursor.hotspot_x, cursor.hotspot_y);
+        if !cursor.hotspot_inside() && hotspot != (original.hotspot_x, original.hotspot_y) {
+            let max = CURSOR_SIZE - 1;
+            eprintln!(
+                "{}",
+                tr!(
+                    "cursor-hotspot-outside",
+                    x = cursor.hotspot_x,
+                    y = cursor.hotspot_y,
+                    max = max
+                )
+            );
+            cursor.hotspot_x = cursor.hotspot_x.min(max);
+            cursor.hotspot_y = cursor.hotspot_y.min(max);
+        }
+        write_patch(
+            self.output_dir.as_deref().unwrap_or(&self.root_dir),
+            id,
+            build_cursor(&cursor)?,
+        )
+    }
+}
@@ -0,0 +1,248 @@
+//! Bundles of diagnostic information for bug reports.
+//!
+//! Every command records how it went in a file in the temporary directory.
+//! `scitool debug-bundle` zips that record up with a description of the
+//! environment and, if given a game, the headers of its resources, for users
+//! to attach to bug reports. Nothing is sent anywhere, and the bundle never
+//! contains resource data, only where each resource is and how big it is.
+
+use std::{
+    io::{Cursor, Write as _},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::Parser;
+use sci_resources::{
+    ParseOptions,
+    file::{find_game_file, map::EntryStatus, open_game_resources_with_options, read_resource_map},
+};
+use sci_utils::fs;
+use serde::Serialize;
+
+use super::{RawResourceMetadata, detect, workspace};
+use crate::i18n::tr;
+
+const LAST_RUN_FILE: &str = "last-run.json";
+
+fn last_run_path() -> PathBuf {
+    std::env::temp_dir().join("scitool").join(LAST_RUN_FILE)
+}
+
+/// How the last command went.
+#[derive(Serialize)]
+struct LastRun {
+    version: &'static str,
+    args: Vec<String>,
+    /// Seconds since the Unix epoch.
+    finished_at: u64,
+    /// The error the command failed with, including its causes.
+    error: Option<String>,
+}
+
+/// Records the outcome of a command, for the next debug bundle.
+pub(super) fn record_last_run(result: &anyhow::Result<()>) {
+    let last_run = LastRun {
+        version: env!("CARGO_PKG_VERSION"),
+        args: std::env::args().collect(),
+        finished_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default(),
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    };
+    let path = last_run_path();
+    let written = serde_json::to_vec_pretty(&last_run)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            fs::write_file(&path, &contents, &fs::WriteOptions::default())?;
+            Ok(())
+        });
+    if let Err(e) = written {
+        eprintln!(
+            "{}",
+            tr!(
+                "record-run-failed",
+                path = format!("{path:?}"),
+                error = e.to_string()
+            )
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct Environment {
+    version: &'static str,
+    os: &'static str,
+    os_family: &'static str,
+    arch: &'static str,
+    features: Vec<&'static str>,
+}
+
+impl Environment {
+    fn current() -> Self {
+        let features = [
+            ("audio", cfg!(feature = "audio")),
+            ("analysis", cfg!(feature = "analysis")),
+            ("graphics", cfg!(feature = "graphics")),
+            ("gui", cfg!(feature = "gui")),
+            ("serve", cfg!(feature = "serve")),
+        ];
+        Environment {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            os_family: std::env::consts::FAMILY,
+            arch: std::env::consts::ARCH,
+            features: features
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+struct MapSummary {
+    file: String,
+    live: usize,
+    unused: usize,
+    duplicate: usize,
+}
+
+#[derive(Serialize)]
+struct ResourceSummary {
+    id: String,
+    /// Where the resource is stored, unless it is only a patch file.
+    stored: Option<RawResourceMetadata>,
+}
+
+/// The headers of a game's resources.
+#[derive(Serialize, Default)]
+struct GameSummary {
+    files: Vec<String>,
+    maps: Vec<MapSummary>,
+    resources: Vec<ResourceSummary>,
+    /// Why the resources couldn't be read, if they couldn't.
+    error: Option<String>,
+}
+
+impl GameSummary {
+    fn read(game_dir: &Path) -> anyhow::Result<Self> {
+        let mut summary = GameSummary::default();
+        for entry in std::fs::read_dir(game_dir)? {
+            summary
+                .files
+                .push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        summary.files.sort();
+        for map_name in ["RESOURCE.MAP", "MESSAGE.MAP"] {
+            let Some(map_path) = find_game_file(game_dir, map_name)? else {
+                continue;
+            };
+            let mut map = MapSummary {
+                file: map_name.to_string(),
+                ..MapSummary::default()
+            };
+            match read_resource_map(&map_path) {
+                Ok(locations) => {
+                    for location in locations.locations() {
+                        match location.status {
+                            EntryStatus::Live => map.live += 1,
+                            EntryStatus::Unused => map.unused += 1,
+                            EntryStatus::Duplicate => map.duplicate += 1,
+                        }
+                    }
+                }
+                Err(e) => summary.error = Some(format!("{map_name}: {e}")),
+            }
+            summary.maps.push(map);
+        }
+        match open_game_resources_with_options(
+            game_dir,
+            &detect::with_game_quirks(game_dir, ParseOptions::permissive()),
+        ) {
+            Ok(resource_set) => {
+                for id in resource_set.resource_ids() {
+                    summary.resources.push(ResourceSummary {
+                        id: format!("{id:?}"),
+                        stored: resource_set
+                            .get_raw_resource(&id)
+                            .map(|raw| RawResourceMetadata::from_raw(&raw)),
+                    });
+                }
+            }
+            Err(e) => summary.error = Some(format!("{e:#}")),
+        }
+        Ok(summary)
+    }
+}
+
+/// Writes a zip of diagnostic information to attach to a bug report: the
+/// outcome of the last command, the environment, and optionally the headers
+/// of a game's resources (but none of their data).
+#[derive(Parser)]
+pub(super) struct DebugBundle {
+    /// The game the problem is with. Defaults to the project's, if there
+    /// is one.
+    #[clap(long)]
+    game_dir: Option<PathBuf>,
+    #[clap(short = 'o', long, default_value = "scitool-debug.zip")]
+    output: PathBuf,
+}
+
+impl DebugBundle {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let mut entries: Vec<(&str, Vec<u8>)> = vec![(
+            "environment.json",
+            serde_json::to_vec_pretty(&Environment::current())?,
+        )];
+        match std::fs::read(last_run_path()) {
+            Ok(contents) => entries.push((LAST_RUN_FILE, contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("{}", tr!("no-earlier-run"));
+            }
+            Err(e) => return Err(e.into()),
+        }
+        // A broken workspace is worth reporting on too, so it doesn't stop
+        // the bundle.
+        let game_dir = match &self.game_dir {
+            Some(game_dir) => Some(game_dir.clone()),
+            None => workspace::project()
+                .ok()
+                .flatten()
+                .map(|project| project.game_dir),
+        };
+        if let Some(game_dir) = &game_dir {
+            entries.push((
+                "game.json",
+                serde_json::to_vec_pretty(&GameSummary::read(game_dir)?)?,
+            ));
+        }
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        for (name, contents) in &entries {
+            zip.start_file(*name, options)?;
+            zip.write_all(contents)?;
+        }
+        let contents = zip.finish()?.into_inner();
+        fs::write_file(&self.output, &contents, &fs::WriteOptions::default())?;
+        let files = entries
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        eprintln!(
+            "{}",
+            tr!(
+                "bundle-written",
+                path = self.output.display().to_string(),
+                files = files
+            )
+        );
+        Ok(())
+    }
+}
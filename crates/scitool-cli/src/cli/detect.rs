@@ -0,0 +1,238 @@
+//! Recognizing which release of a game a directory holds, by fingerprinting
+//! its resource map and interpreter and looking the fingerprint up in a
+//! built-in table of known releases (`known_games.yaml`).
+//!
+//! A known release can carry settings it needs, such as its code page, which
+//! are used when a book config doesn't give them, and the quirks of its
+//! resource files, which the parsers are told to expect. `--quirks` overrides
+//! the detected quirks.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use clap::Parser;
+use regex::bytes::Regex;
+use sci_resources::{
+    ParseOptions, Quirk, Quirks,
+    file::{ResourceSet, find_game_file, open_game_resources_with_options},
+};
+use sci_utils::encoding::CodePage;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::workspace;
+use crate::i18n::tr;
+
+/// What identifies a release of a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Fingerprint {
+    /// The SHA-256 of `RESOURCE.MAP`, in hex.
+    map_sha256: String,
+    /// The version string of the interpreter, if one was found.
+    interpreter: Option<String>,
+}
+
+/// Finds the interpreter's version string (e.g. `1.001.099`) in the game's
+/// executables. DOS and Windows interpreters embed it; other ports may not
+/// have an executable in the game directory at all.
+fn interpreter_version(game_dir: &Path) -> anyhow::Result<Option<String>> {
+    static VERSION: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\b[0-9]\.[0-9]{3}\.[0-9]{3}\b").expect("Valid regex"));
+    let mut executables = Vec::new();
+    for entry in std::fs::read_dir(game_dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exe"))
+        {
+            executables.push(path);
+        }
+    }
+    executables.sort();
+    for path in executables {
+        let contents = std::fs::read(&path)?;
+        if let Some(version) = VERSION.find(&contents) {
+            return Ok(Some(
+                String::from_utf8_lossy(version.as_bytes()).into_owned(),
+            ));
+        }
+    }
+    Ok(None)
+}
+
+/// The SHA-256 of a resource map, in hex.
+pub(super) fn map_sha256(map: &[u8]) -> String {
+    Sha256::digest(map)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl Fingerprint {
+    /// Fingerprints the game in the directory, or returns `None` if it has no
+    /// `RESOURCE.MAP` (e.g. a Mac release).
+    pub(super) fn read(game_dir: &Path) -> anyhow::Result<Option<Self>> {
+        // Mac releases may be a single resource file.
+        if !game_dir.is_dir() {
+            return Ok(None);
+        }
+        let Some(map_path) = find_game_file(game_dir, "RESOURCE.MAP")? else {
+            return Ok(None);
+        };
+        let map = std::fs::read(map_path)?;
+        Ok(Some(Fingerprint {
+            map_sha256: map_sha256(&map),
+            interpreter: interpreter_version(game_dir)?,
+        }))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RESOURCE.MAP {}", self.map_sha256)?;
+        if let Some(interpreter) = &self.interpreter {
+            write!(f, ", interpreter {interpreter}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A release in the table of known games.
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct KnownGame {
+    name: String,
+    version: String,
+    map_sha256: String,
+    #[serde(default)]
+    interpreter: Option<String>,
+    /// The code page of the release's text.
+    #[serde(default)]
+    pub(super) code_page: Option<CodePage>,
+    /// How the release's resource files deviate from the usual formats.
+    #[serde(default)]
+    quirks: Vec<Quirk>,
+}
+
+impl KnownGame {
+    fn matches(&self, fingerprint: &Fingerprint) -> bool {
+        self.map_sha256
+            .eq_ignore_ascii_case(&fingerprint.map_sha256)
+            && self
+                .interpreter
+                .as_ref()
+                .is_none_or(|interpreter| fingerprint.interpreter.as_ref() == Some(interpreter))
+    }
+}
+
+impl fmt::Display for KnownGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} v{}", self.name, self.version)
+    }
+}
+
+fn known_games() -> &'static [KnownGame] {
+    static KNOWN_GAMES: OnceLock<Vec<KnownGame>> = OnceLock::new();
+    KNOWN_GAMES.get_or_init(|| {
+        serde_yml::from_str(include_str!("known_games.yaml")).expect("Valid known_games.yaml")
+    })
+}
+
+/// The known release with the fingerprint, if there is one.
+pub(super) fn detect(fingerprint: &Fingerprint) -> Option<&'static KnownGame> {
+    known_games().iter().find(|game| game.matches(fingerprint))
+}
+
+/// A known release with the resource map, whatever its interpreter.
+#[cfg_attr(not(feature = "serve"), expect(dead_code))]
+pub(super) fn detect_map(map_sha256: &str) -> Option<&'static KnownGame> {
+    known_games()
+        .iter()
+        .find(|game| game.map_sha256.eq_ignore_ascii_case(map_sha256))
+}
+
+/// Detects the release in the game directory. Errors reading it are
+/// reported as warnings, as detection is only ever a convenience.
+pub(super) fn detect_game(game_dir: &Path) -> Option<&'static KnownGame> {
+    match Fingerprint::read(game_dir) {
+        Ok(fingerprint) => fingerprint.as_ref().and_then(detect),
+        Err(e) => {
+            eprintln!("Warning: couldn't fingerprint the game: {e}");
+            None
+        }
+    }
+}
+
+static FORCED_QUIRKS: OnceLock<Quirks> = OnceLock::new();
+
+/// Uses the quirks for every game, instead of the detected ones.
+pub(super) fn force_quirks(quirks: Quirks) {
+    let _ = FORCED_QUIRKS.set(quirks);
+}
+
+/// The quirks to expect in the game's resources: those given with
+/// `--quirks`, or the detected release's.
+fn game_quirks(game_dir: &Path) -> Quirks {
+    if let Some(quirks) = FORCED_QUIRKS.get() {
+        return *quirks;
+    }
+    detect_game(game_dir)
+        .map(|game| game.quirks.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// The options, expecting the game's quirks.
+pub(super) fn with_game_quirks(game_dir: &Path, options: ParseOptions) -> ParseOptions {
+    options.with_quirks(game_quirks(game_dir))
+}
+
+/// Opens the game's resources, strictly, expecting its quirks.
+pub(super) fn open_game(game_dir: &Path) -> anyhow::Result<ResourceSet> {
+    open_game_resources_with_options(
+        game_dir,
+        &with_game_quirks(game_dir, ParseOptions::strict()),
+    )
+}
+
+/// Prints which known release the game is, or its fingerprint, for adding
+/// it to the table.
+#[derive(Parser)]
+pub(super) struct Detect {
+    /// The game directory. Defaults to the project's (see `--project`).
+    game_dir: Option<PathBuf>,
+}
+
+impl Detect {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let game_dir = workspace::game_dir(self.game_dir.as_deref())?;
+        let Some(fingerprint) = Fingerprint::read(&game_dir)? else {
+            anyhow::bail!(tr!("detect-no-map"));
+        };
+        match detect(&fingerprint) {
+            Some(game) => println!("{}", tr!("detected-game", game = game.to_string())),
+            None => println!("{}", tr!("unknown-game")),
+        }
+        println!("{fingerprint}");
+        // Read leniently: the layout is worth showing even for a release
+        // whose quirks aren't known yet.
+        if let Ok(resources) = open_game_resources_with_options(
+            &game_dir,
+            &with_game_quirks(&game_dir, ParseOptions::default()),
+        ) && let Some(format) = resources.map_format()
+        {
+            println!("{}", tr!("map-format", format = format.to_string()));
+        }
+        let quirks = game_quirks(&game_dir);
+        if !quirks.is_empty() {
+            let names = quirks
+                .iter()
+                .filter_map(|quirk| clap::ValueEnum::to_possible_value(&quirk))
+                .map(|value| value.get_name().to_string())
+                .collect::<Vec<_>>();
+            println!("{}", tr!("game-quirks", quirks = names.join(", ")));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+//! Exporting a font as a glyph atlas, and building a font patch from an
+//! edited atlas or a BDF font, e.g. to add the accented characters a
+//! translation's subtitles need.
+//!
+//! An export holds `font.png`, with a cell for each of the 256 character
+//! codes (16 to a row), and `font.json`, giving the size of each glyph. Ink
+//! is black, the glyph's box white, and the rest of its cell gray. Adding a
+//! character means drawing it in its cell and listing it in `font.json`.
+//!
+//! Imports are checked against the original font's metrics: characters
+//! whose widths change move text the game laid out by hand.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use sci_resources::{
+    ResourceId, ResourceType,
+    types::font::{Font, Glyph, build_font},
+};
+use sci_utils::encoding::CodePage;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    detect,
+    view::{load_resource, read_png},
+};
+use crate::i18n::tr;
+
+const ATLAS_FILE: &str = "font.png";
+const METADATA_FILE: &str = "font.json";
+
+/// The atlas has a cell for every character code, this many to a row.
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_CELLS: usize = 256;
+
+const INK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const BOX: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const CELL: [u8; 4] = [0xC0, 0xC0, 0xC0, 0xFF];
+
+#[derive(Serialize, Deserialize)]
+struct GlyphMetadata {
+    code: u8,
+    /// The character, in the font's code page. Only for reading; it's
+    /// ignored on import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    char: Option<String>,
+    width: u8,
+    height: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FontMetadata {
+    font: u16,
+    line_height: u16,
+    cell_width: u8,
+    cell_height: u8,
+    glyphs: Vec<GlyphMetadata>,
+}
+
+fn char_name(code: u8, code_page: CodePage) -> String {
+    let text = code_page.decode(&[code]);
+    if text.chars().all(char::is_control) {
+        format!("{code}")
+    } else {
+        format!("{code} ({text})")
+    }
+}
+
+fn char_names(codes: &[u8], code_page: CodePage) -> String {
+    codes
+        .iter()
+        .map(|&code| char_name(code, code_page))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn code_page(root_dir: &Path, code_page: Option<CodePage>) -> CodePage {
+    code_page
+        .or(detect::detect_game(root_dir).and_then(|game| game.code_page))
+        .unwrap_or_default()
+}
+
+/// Writes a font as a glyph atlas, with `font.json` describing it, for
+/// editing and `import-font`.
+#[derive(Parser)]
+pub(super) struct ExportFont {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    font: u16,
+    /// The code page of the game's text, for naming the characters.
+    /// Defaults to the detected game's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+    #[clap(short = 'o', long)]
+    output_dir: PathBuf,
+}
+
+impl ExportFont {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let font = Font::parse(&load_resource(
+            &resource_set,
+            &ResourceId::new(ResourceType::Font, self.font),
+        )?)?;
+        anyhow::ensure!(
+            font.glyphs.len() <= ATLAS_CELLS,
+            tr!("font-too-many-glyphs", count = font.glyphs.len())
+        );
+        let code_page = code_page(&self.root_dir, self.code_page);
+        let max_width = font.glyphs.iter().map(|glyph| glyph.width).max();
+        let max_height = font.glyphs.iter().map(|glyph| glyph.height).max();
+        // Room to widen glyphs, and to draw new ones.
+        let cell_width = max_width.unwrap_or(0).max(8).saturating_add(4);
+        let cell_height = max_height
+            .unwrap_or(0)
+            .max(u8::try_from(font.line_height).unwrap_or(u8::MAX))
+            .saturating_add(2);
+        let (cell_w, cell_h) = (usize::from(cell_width), usize::from(cell_height));
+        let atlas_width = cell_w * ATLAS_COLUMNS;
+        let mut atlas = vec![CELL; atlas_width * cell_h * ATLAS_CELLS / ATLAS_COLUMNS];
+        for (code, glyph) in font.glyphs.iter().enumerate() {
+            let (left, top) = (
+                (code % ATLAS_COLUMNS) * cell_w,
+                (code / ATLAS_COLUMNS) * cell_h,
+            );
+            for (y, row) in glyph
+                .pixels
+                .chunks(usize::from(glyph.width).max(1))
+                .enumerate()
+            {
+                for (x, &set) in row.iter().enumerate() {
+                    atlas[(top + y) * atlas_width + left + x] = if set { INK } else { BOX };
+                }
+            }
+        }
+        let metadata = FontMetadata {
+            font: self.font,
+            line_height: font.line_height,
+            cell_width,
+            cell_height,
+            glyphs: font
+                .glyphs
+                .iter()
+                .enumerate()
+                .map(|(code, glyph)| {
+                    let text = code_page.decode(&[code as u8]);
+                    GlyphMetadata {
+                        code: code as u8,
+                        char: (!text.chars().all(char::is_control)).then_some(text),
+                        width: glyph.width,
+                        height: glyph.height,
+                    }
+                })
+                .collect(),
+        };
+        std::fs::create_dir_all(&self.output_dir)?;
+        write_atlas(
+            &self.output_dir.join(ATLAS_FILE),
+            atlas_width,
+            cell_h * ATLAS_CELLS / ATLAS_COLUMNS,
+            &atlas,
+        )?;
+        std::fs::write(
+            self.output_dir.join(METADATA_FILE),
+            serde_json::to_vec_pretty(&metadata)?,
+        )?;
+        eprintln!(
+            "{}",
+            tr!(
+                "font-exported",
+                count = font.glyphs.len(),
+                path = format!("{:?}", self.output_dir)
+            )
+        );
+        Ok(())
+    }
+}
+
+fn write_atlas(path: &Path, width: usize, height: usize, pixels: &[[u8; 4]]) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(std::fs::File::create(path)?),
+        u32::try_from(width)?,
+        u32::try_from(height)?,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels.as_flattened())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Builds a font patch from a directory written by `export-font`, after
+/// its atlas has been edited, or from a BDF font. A BDF font's characters
+/// replace the original's, which are kept for characters it lacks.
+#[derive(Parser)]
+pub(super) struct ImportFont {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    font: u16,
+    /// The directory written by `export-font`, or a `.bdf` file.
+    #[clap(index = 3)]
+    input: PathBuf,
+    /// The code page of the game's text, for placing a BDF font's
+    /// characters. Defaults to the detected game's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+    /// Fail instead of warning when characters change width.
+    #[clap(long)]
+    keep_widths: bool,
+    /// Where to write the patch. Defaults to the game directory.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+}
+
+impl ImportFont {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let id = ResourceId::new(ResourceType::Font, self.font);
+        let original = Font::parse(&load_resource(&resource_set, &id)?)?;
+        let code_page = code_page(&self.root_dir, self.code_page);
+        let font = if self
+            .input
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("bdf"))
+        {
+            self.read_bdf(&original, code_page)?
+        } else {
+            self.read_atlas()?
+        };
+        self.check(&font, &original, code_page)?;
+        super::view::write_patch(
+            self.output_dir.as_deref().unwrap_or(&self.root_dir),
+            id,
+            build_font(&font)?,
+        )
+    }
+
+    fn read_bdf(&self, original: &Font, code_page: CodePage) -> anyhow::Result<Font> {
+        let (bdf, unmapped) = Font::from_bdf(&std::fs::read_to_string(&self.input)?, code_page)?;
+        if !unmapped.is_empty() {
+            eprintln!(
+                "{}",
+                tr!(
+                    "font-unmapped",
+                    count = unmapped.len(),
+                    code_page = code_page.name(),
+                    chars = unmapped.iter().collect::<String>()
+                )
+            );
+        }
+        let mut glyphs = original.glyphs.clone();
+        if glyphs.len() < bdf.glyphs.len() {
+            glyphs.resize(bdf.glyphs.len(), Glyph::default());
+        }
+        for (glyph, new) in glyphs.iter_mut().zip(bdf.glyphs) {
+            // Characters the BDF font lacks have no width.
+            if new.width > 0 {
+                *glyph = new;
+            }
+        }
+        Ok(Font {
+            line_height: bdf.line_height,
+            glyphs,
+        })
+    }
+
+    fn read_atlas(&self) -> anyhow::Result<Font> {
+        let metadata: FontMetadata =
+            serde_json::from_slice(&std::fs::read(self.input.join(METADATA_FILE))?)?;
+        let (width, _, atlas) = read_png(&self.input.join(ATLAS_FILE))?;
+        let (cell_w, cell_h) = (
+            usize::from(metadata.cell_width),
+            usize::from(metadata.cell_height),
+        );
+        let atlas_width = usize::from(width);
+        let count = metadata
+            .glyphs
+            .iter()
+            .map(|glyph| usize::from(glyph.code) + 1)
+            .max()
+            .unwrap_or(0);
+        let mut glyphs = vec![Glyph::default(); count];
+        for entry in &metadata.glyphs {
+            anyhow::ensure!(
+                entry.width <= metadata.cell_width && entry.height <= metadata.cell_height,
+                tr!(
+                    "font-glyph-outside-cell",
+                    code = entry.code,
+                    width = entry.width,
+                    height = entry.height,
+                    cell_width = metadata.cell_width,
+                    cell_height = metadata.cell_height
+                )
+            );
+            let code = usize::from(entry.code);
+            let (left, top) = (
+                (code % ATLAS_COLUMNS) * cell_w,
+                (code / ATLAS_COLUMNS) * cell_h,
+            );
+            let pixels = (0..usize::from(entry.height))
+                .flat_map(|y| (0..usize::from(entry.width)).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let pos = (top + y) * atlas_width + left + x;
+                    let [r, g, b, a] = *atlas.get(pos).ok_or_else(|| {
+                        anyhow::anyhow!(tr!("font-atlas-too-small", code = entry.code))
+                    })?;
+                    let luma = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+                    Ok(a >= 0x80 && luma < 0x80)
+                })
+                .collect::<anyhow::Result<_>>()?;
+            glyphs[code] = Glyph {
+                width: entry.width,
+                height: entry.height,
+                pixels,
+            };
+        }
+        Ok(Font {
+            line_height: metadata.line_height,
+            glyphs,
+        })
+    }
+
+    /// Checks the new font's metrics against the original's.
+    fn check(&self, font: &Font, original: &Font, code_page: CodePage) -> anyhow::Result<()> {
+        let check = font.check_metrics(original);
+        anyhow::ensure!(
+            check.removed == 0,
+            tr!("font-glyphs-removed", count = check.removed)
+        );
+        if font.line_height != original.line_height {
+            eprintln!(
+                "{}",
+                tr!(
+                    "font-line-height-changed",
+                    old = original.line_height,
+                    new = font.line_height
+                )
+            );
+        }
+        if !check.widths_changed.is_empty() {
+            let changes = check
+                .widths_changed
+                .iter()
+                .map(|&(code, old, new)| format!("{}: {old} → {new}", char_name(code, code_page)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = tr!(
+                "font-widths-changed",
+                count = check.widths_changed.len(),
+                changes = changes
+            );
+            anyhow::ensure!(!self.keep_widths, message);
+            eprintln!("{}", tr!("font-widths-warning", message = message));
+        }
+        if !check.too_tall.is_empty() {
+            eprintln!(
+                "{}",
+                tr!(
+                    "font-too-tall",
+                    count = check.too_tall.len(),
+                    chars = char_names(&check.too_tall, code_page)
+                )
+            );
+        }
+        if !check.added.is_empty() {
+            eprintln!(
+                "{}",
+                tr!(
+                    "font-added",
+                    count = check.added.len(),
+                    chars = char_names(&check.added, code_page)
+                )
+            );
+        }
+        Ok(())
+    }
+}
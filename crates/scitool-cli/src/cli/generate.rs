@@ -1,24 +1,170 @@
 use itertools::Itertools;
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use clap::{Parser, Subcommand};
-use sci_resources::{ResourceType, file::open_game_resources, types::msg::parse_message_resource};
+use sci_resources::{
+    ResourceType, file::ResourceSet, types::msg::parse_message_resource_with_code_page,
+};
 
+#[cfg(feature = "coverage")]
+mod coverage;
+mod labels;
+
+use super::{detect, workspace};
+#[cfg(feature = "graphics")]
+use super::{pic::pic_thumbnail, view::view_strip};
 use crate::{
-    book::{Book, builder::BookBuilder, config::BookConfig},
+    book::{
+        Book, Conversation, Line, Noun, Role, RoleId, Room, builder::BookBuilder,
+        config::BookConfig, segment::SegmentKind,
+    },
     generate::{
         doc::{
-            Document, DocumentBuilder, SectionBuilder,
+            Document, DocumentBuilder, Portrait, SectionBuilder, SubSectionBuilder, Thumbnail,
             text::{RichText, TextStyle},
         },
         html::generate_html,
+        pdf::{PageSize, PdfOptions, generate_pdf},
     },
+    i18n::tr,
 };
 
 #[derive(Parser)]
-struct CommonArgs {
-    root_dir: PathBuf,
-    config_path: PathBuf,
+pub(super) struct CommonArgs {
+    /// The game directory. Defaults to the project's (see `--project`).
+    root_dir: Option<PathBuf>,
+    /// The book config. Defaults to the project's.
+    config_path: Option<PathBuf>,
+}
+
+impl CommonArgs {
+    pub(super) fn root_dir(&self) -> anyhow::Result<PathBuf> {
+        workspace::game_dir(self.root_dir.as_deref())
+    }
+
+    pub(super) fn config_path(&self) -> anyhow::Result<PathBuf> {
+        workspace::config(self.config_path.as_deref())
+    }
+}
+
+/// How sections of the script are titled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum HeadingStyle {
+    /// Use the room names and noun descriptions from the config, falling back
+    /// to numbers for anything that isn't named.
+    #[default]
+    Names,
+    /// Use room and noun numbers, as they appear in the game resources.
+    Ids,
+}
+
+/// The order of the conversations within a noun.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum ConversationOrder {
+    /// Sort by verb name, with conversations that apply to any verb first.
+    #[default]
+    VerbName,
+    /// Sort by verb and condition number.
+    Id,
+}
+
+#[derive(Parser, Debug)]
+struct ExportOptions {
+    #[clap(long, value_enum, default_value_t)]
+    headings: HeadingStyle,
+    #[clap(long, value_enum, default_value_t)]
+    conversation_order: ConversationOrder,
+    /// Show a thumbnail of each room's background under its heading, from
+    /// the pic with the room's number.
+    #[cfg(feature = "graphics")]
+    #[clap(long)]
+    thumbnails: bool,
+    /// Show the portraits of the roles' talkers under the title: every
+    /// role's in the master script, and the actor's own in a bundle.
+    #[cfg(feature = "graphics")]
+    #[clap(long)]
+    portraits: bool,
+}
+
+/// The widest a room thumbnail is, in the game's pixels.
+#[cfg(feature = "graphics")]
+const THUMBNAIL_MAX_WIDTH: u16 = 160;
+
+/// The pictures drawn from the game for a script.
+#[derive(Default)]
+pub(super) struct Pictures {
+    /// Room thumbnails, by the IDs of the rooms' sections.
+    thumbnails: BTreeMap<String, Thumbnail>,
+    /// The first portrait of each role's talkers, by role.
+    portraits: BTreeMap<RoleId, Portrait>,
+}
+
+impl Pictures {
+    /// Adds the pictures to a script, with the portraits of the focused role
+    /// or, if there isn't one, of every role.
+    fn add_to(&self, doc: Document, focus: Option<&RoleFocus>) -> Document {
+        let portraits = self
+            .portraits
+            .iter()
+            .filter(|(role, _)| focus.is_none_or(|focus| focus.role.id() == **role))
+            .map(|(_, portrait)| portrait.clone())
+            .collect();
+        doc.with_thumbnails(&self.thumbnails)
+            .with_portraits(portraits)
+    }
+}
+
+impl ExportOptions {
+    /// Draws the pictures that were asked for. Pics and views that can't be
+    /// read are left out, with a warning.
+    #[cfg(feature = "graphics")]
+    fn pictures(&self, root_dir: &Path, book: &Book) -> anyhow::Result<Pictures> {
+        let mut pictures = Pictures::default();
+        if !self.thumbnails && !self.portraits {
+            return Ok(pictures);
+        }
+        let resource_set = detect::open_game(root_dir)?;
+        for room in book.rooms().filter(|_| self.thumbnails) {
+            let room_num = room.id().room_num();
+            match pic_thumbnail(&resource_set, room_num, THUMBNAIL_MAX_WIDTH) {
+                Ok(Some(thumbnail)) => {
+                    let id = room_id_to_id_string(room.id());
+                    pictures
+                        .thumbnails
+                        .insert(format!("narration-{id}"), thumbnail.clone());
+                    pictures.thumbnails.insert(id, thumbnail);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Warning: couldn't render pic {room_num}: {e}"),
+            }
+        }
+        for role in book.roles().filter(|_| self.portraits) {
+            for view in role.talkers().filter_map(|talker| talker.view()) {
+                match view_strip(&resource_set, view) {
+                    Ok(Some(image)) => {
+                        let portrait = Portrait {
+                            name: role.name().to_string(),
+                            image,
+                        };
+                        pictures.portraits.insert(role.id(), portrait);
+                        break;
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Warning: couldn't render view {view}: {e}"),
+                }
+            }
+        }
+        Ok(pictures)
+    }
+
+    /// Without graphics, there are no pictures to draw.
+    #[cfg(not(feature = "graphics"))]
+    fn pictures(&self, _root_dir: &Path, _book: &Book) -> anyhow::Result<Pictures> {
+        Ok(Pictures::default())
+    }
 }
 
 enum MessageSegment<'a> {
@@ -32,7 +178,7 @@ fn split_first_char(text: &str) -> Option<(char, &str)> {
     Some((first, chars.as_str()))
 }
 
-fn parse_message_text(mut text: &str) -> Vec<MessageSegment> {
+fn parse_message_text(mut text: &str) -> Vec<MessageSegment<'_>> {
     let mut segments = Vec::new();
     loop {
         let (next_text, control_start_rest) = match text.split_once('|') {
@@ -155,49 +301,239 @@ fn convert_message_text_to_rich_text(ctxt: &str, text: &str) -> RichText {
     builder.build()
 }
 
-fn load_book(args: &CommonArgs) -> anyhow::Result<Book> {
-    let config = if args.config_path.exists() {
-        let config: BookConfig = serde_yml::from_reader(std::fs::File::open(&args.config_path)?)?;
+pub(super) fn load_book(args: &CommonArgs) -> anyhow::Result<Book> {
+    let root_dir = args.root_dir()?;
+    let config_path = args.config_path()?;
+    let config = if config_path.exists() {
+        let config: BookConfig = serde_yml::from_reader(std::fs::File::open(&config_path)?)?;
         config
     } else {
         BookConfig::default()
     };
-    let resource_set = open_game_resources(&args.root_dir)?;
+    let resource_set = detect::open_game(&root_dir)?;
+    let detected = detect::detect_game(&root_dir);
+    if let Some(game) = detected {
+        eprintln!("{}", tr!("detected-game", game = game.to_string()));
+    }
+    let code_page = config
+        .code_page()
+        .or(detected.and_then(|game| game.code_page));
     let mut builder = BookBuilder::new(config)?;
 
     // Extra testing for building a conversation.
 
     for res in resource_set.resources_of_type(ResourceType::Message) {
-        let msg_resources = parse_message_resource(res.load_data()?)?;
+        let msg_resources = parse_message_resource_with_code_page(res.load_data()?, code_page)?;
         for (msg_id, record) in msg_resources.messages() {
             builder.add_message(res.id().resource_num(), msg_id, record)?;
         }
     }
+    name_from_scripts(&mut builder, &resource_set);
     Ok(builder.build()?)
 }
 
-fn generate_conversation(mut section: SectionBuilder, conversation: &crate::book::Conversation) {
+/// Names the rooms and verbs that the config doesn't from the game's
+/// scripts: rooms after their room objects, and verbs after the icons and
+/// inventory items that use them. Talkers without a configured portrait get
+/// their objects' views. Then warns about verbs still without a name.
+/// Reading the scripts needs the `analysis` feature; without it, rooms and
+/// verbs only have their configured names, and talkers their configured
+/// portraits.
+pub(super) fn name_from_scripts(builder: &mut BookBuilder, resource_set: &ResourceSet) {
+    #[cfg(feature = "analysis")]
+    {
+        match scitool_script_loader::find_room_names(resource_set) {
+            Ok(names) => {
+                for (room, name) in names {
+                    builder.add_room_name(room, name);
+                }
+            }
+            Err(e) => eprintln!("Warning: couldn't read room names from the scripts: {e}"),
+        }
+        match scitool_script_loader::find_verb_names(resource_set) {
+            Ok(names) => {
+                for (verb, name) in names {
+                    if let Ok(verb) = u8::try_from(verb) {
+                        builder.add_verb_name(verb, name);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Warning: couldn't read verb names from the scripts: {e}"),
+        }
+        match scitool_script_loader::find_talkers(resource_set) {
+            Ok(talkers) => {
+                for (talker, object) in talkers {
+                    if let (Ok(talker), Some(view)) = (u8::try_from(talker), object.view) {
+                        builder.add_talker_view(talker, view);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Warning: couldn't read talkers from the scripts: {e}"),
+        }
+    }
+    #[cfg(not(feature = "analysis"))]
+    let _ = resource_set;
+
+    let unnamed = builder.unnamed_verbs();
+    if !unnamed.is_empty() {
+        eprintln!(
+            "Warning: verbs without names (add them to the config): {}",
+            unnamed.iter().join(", ")
+        );
+    }
+}
+
+pub(super) fn find_role<'a>(book: &'a Book, name: &str) -> anyhow::Result<Role<'a>> {
+    book.roles()
+        .find(|role| {
+            role.name().eq_ignore_ascii_case(name) || role.short_name().eq_ignore_ascii_case(name)
+        })
+        .ok_or_else(|| anyhow::anyhow!("No role named {:?} in the config", name))
+}
+
+/// Replaces all but the last `cue_words` words of a line with an ellipsis.
+fn redact_line(text: &str, cue_words: usize) -> String {
+    let words: Vec<_> = text.split_whitespace().collect();
+    if words.len() <= cue_words {
+        return text.to_string();
+    }
+    let cue = &words[words.len() - cue_words..];
+    if cue.is_empty() {
+        "\u{2026}".to_string()
+    } else {
+        format!("\u{2026} {}", cue.join(" "))
+    }
+}
+
+/// Restricts a script to the conversations a single role speaks in, for the
+/// bundle given to the actor playing that role.
+struct RoleFocus<'a> {
+    role: Role<'a>,
+}
+
+impl RoleFocus<'_> {
+    fn includes(&self, conversation: &Conversation) -> bool {
+        conversation
+            .lines()
+            .any(|line| line.role().id() == self.role.id())
+    }
+
+    /// If the line should be redacted in the bundle, the redacted text.
+    fn redacted_text(&self, line: &Line) -> Option<String> {
+        match self.role.redact_cue_words() {
+            Some(cue_words) if line.role().id() != self.role.id() => {
+                Some(redact_line(line.text(), cue_words))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Converts the text of a line, marking its segments if it has been split.
+/// Speech segments are numbered, and stage directions are italicized.
+fn line_to_rich_text(ctxt: &str, line: &Line) -> RichText {
+    let segments = line.segments();
+    let num_speech = segments
+        .iter()
+        .filter(|segment| segment.kind() == SegmentKind::Speech)
+        .count();
+    if num_speech <= 1 && segments.len() == num_speech {
+        return convert_message_text_to_rich_text(ctxt, line.text());
+    }
+
+    let mut marker_style = TextStyle::default();
+    marker_style.set_bold(true);
+    let mut builder = RichText::builder();
+    let mut speech_index = 0;
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            builder.add_text(" ", &TextStyle::default());
+        }
+        let text = convert_message_text_to_rich_text(ctxt, segment.text());
+        match segment.kind() {
+            SegmentKind::Speech => {
+                speech_index += 1;
+                if num_speech > 1 {
+                    builder.add_text(format!("[{}] ", speech_index), &marker_style);
+                }
+                for item in text.items() {
+                    builder.add_text(item.text(), item.style());
+                }
+            }
+            SegmentKind::StageDirection => {
+                let mut italic = TextStyle::default();
+                italic.set_italic(true);
+                builder.add_text("(", &italic);
+                for item in text.items() {
+                    let mut style = item.style().clone();
+                    style.set_italic(true);
+                    builder.add_text(item.text(), &style);
+                }
+                builder.add_text(")", &italic);
+            }
+        }
+    }
+    builder.build()
+}
+
+fn generate_conversation(
+    mut section: SectionBuilder,
+    conversation: &Conversation,
+    focus: Option<&RoleFocus>,
+) {
     section.set_id(conversation_id_to_id_string(conversation.id()));
     let mut content = section.add_content();
     let mut dialogue = content.add_dialogue();
     for line in conversation.lines() {
+        if let Some(target) = line.ref_target() {
+            // The referenced conversation is recorded where it appears.
+            let mut italic = TextStyle::default();
+            italic.set_italic(true);
+            let mut text = RichText::builder();
+            text.add_text(
+                format!(
+                    "(Plays the conversation for noun {}, verb {}, condition {})",
+                    target.noun_num(),
+                    target.verb_num(),
+                    target.condition_num()
+                ),
+                &italic,
+            );
+            dialogue.add_line("", text.build(), line_id_to_id_string(line.id()));
+            continue;
+        }
+        let ctxt = format!("{:?}", conversation.id());
+        let text = match focus.and_then(|focus| focus.redacted_text(&line)) {
+            Some(redacted) => convert_message_text_to_rich_text(&ctxt, &redacted),
+            None => line_to_rich_text(&ctxt, &line),
+        };
         dialogue.add_line(
             line.role().short_name(),
-            convert_message_text_to_rich_text(&format!("{:?}", conversation.id()), line.text()),
+            text,
             line_id_to_id_string(line.id()),
         );
+        if line.is_added() {
+            dialogue.add_line_note("Added for the dub; not in the original game.");
+        }
+        let placeholders = line.placeholders();
+        if !placeholders.is_empty() {
+            dialogue.add_line_note(format!(
+                "Filled in by the game at runtime: {}",
+                placeholders.iter().map(|p| p.token()).join(", ")
+            ));
+        }
     }
 }
 
-fn room_id_to_id_string(room_id: crate::book::RoomId) -> String {
+pub(super) fn room_id_to_id_string(room_id: crate::book::RoomId) -> String {
     format!("room-{}", room_id.room_num())
 }
 
-fn noun_id_to_id_string(noun_id: crate::book::NounId) -> String {
+pub(super) fn noun_id_to_id_string(noun_id: crate::book::NounId) -> String {
     format!("noun-{}-{}", noun_id.room_num(), noun_id.noun_num())
 }
 
-fn conversation_id_to_id_string(conversation_id: crate::book::ConversationId) -> String {
+pub(super) fn conversation_id_to_id_string(conversation_id: crate::book::ConversationId) -> String {
     format!(
         "conv-{}-{}-{}-{}",
         conversation_id.room_num(),
@@ -207,7 +543,7 @@ fn conversation_id_to_id_string(conversation_id: crate::book::ConversationId) ->
     )
 }
 
-fn line_id_to_id_string(line_id: crate::book::LineId) -> String {
+pub(super) fn line_id_to_id_string(line_id: crate::book::LineId) -> String {
     format!(
         "line-{}-{}-{}-{}-{}",
         line_id.room_num(),
@@ -218,69 +554,171 @@ fn line_id_to_id_string(line_id: crate::book::LineId) -> String {
     )
 }
 
-fn generate_document(book: &Book) -> anyhow::Result<Document> {
-    let mut doc = DocumentBuilder::new(format!("{} Script", book.project_name()));
-    for room in book.rooms() {
-        let mut room_section = doc.add_chapter(room.name());
-        room_section.set_id(room_id_to_id_string(room.id()));
-        let mut room_section = room_section.into_section_builder();
+fn room_heading(room: &Room, style: HeadingStyle) -> String {
+    match (style, room.configured_name()) {
+        (HeadingStyle::Names, Some(name)) => name.to_string(),
+        _ => format!("Room #{}", room.id().room_num()),
+    }
+}
 
-        for noun in room.nouns() {
-            let num_conversations = noun.conversations().count();
-            if num_conversations == 0 {
-                continue;
-            }
-            let mut noun_desc = noun
-                .desc()
-                .map(ToOwned::to_owned)
-                .unwrap_or_else(|| format!("Noun #{:?}", noun.id().noun_num()));
+fn noun_heading(noun: &Noun, style: HeadingStyle) -> String {
+    let mut heading = match (style, noun.desc()) {
+        (HeadingStyle::Names, Some(desc)) => desc.to_string(),
+        _ => format!("Noun #{}", noun.id().noun_num()),
+    };
+    if noun.is_cutscene() {
+        heading.push_str(" (Cutscene)");
+    }
+    heading
+}
 
-            if noun.is_cutscene() {
-                noun_desc.push_str(" (Cutscene)");
-            }
+/// True if the conversation is spoken only by narrator roles. Reference
+/// lines are ignored, since they are recorded where they point.
+fn is_narration(conversation: &Conversation) -> bool {
+    let mut spoken = conversation
+        .lines()
+        .filter(|line| line.ref_target().is_none())
+        .peekable();
+    spoken.peek().is_some() && spoken.all(|line| line.role().is_narrator())
+}
 
-            let mut noun_section = room_section.add_subsection(noun_desc);
+/// Returns the noun's conversations to include, in the requested order.
+fn ordered_conversations<'a>(
+    noun: &Noun<'a>,
+    order: ConversationOrder,
+    include: impl Fn(&Conversation) -> bool,
+) -> Vec<Conversation<'a>> {
+    let mut conversations: Vec<_> = noun
+        .conversations()
+        .filter(|conversation| include(conversation))
+        .collect();
+    if order == ConversationOrder::VerbName {
+        // The sort is stable, so conversations for the same verb stay in
+        // condition order.
+        conversations.sort_by_cached_key(|conversation| {
+            conversation.verb().map(|verb| verb.name().to_lowercase())
+        });
+    }
+    conversations
+}
 
-            noun_section.set_id(noun_id_to_id_string(noun.id()));
+fn room_includes(room: &Room, include: impl Fn(&Conversation) -> bool) -> bool {
+    room.nouns()
+        .flat_map(|noun| noun.conversations())
+        .any(|conversation| include(&conversation))
+}
 
-            match noun.conversations().exactly_one() {
-                Ok(conversation) => {
-                    if let Some(verb) = conversation.verb() {
-                        noun_section
-                            .add_content()
-                            .add_paragraph(format!("On {}", verb.name()));
-                    }
-                    generate_conversation(noun_section, &conversation);
+/// Adds a subsection for each noun in the room with included conversations.
+fn generate_room(
+    mut room_section: SubSectionBuilder,
+    room: &Room,
+    options: &ExportOptions,
+    focus: Option<&RoleFocus>,
+    include: impl Fn(&Conversation) -> bool + Copy,
+) {
+    for noun in room.nouns() {
+        let conversations = ordered_conversations(&noun, options.conversation_order, include);
+        if conversations.is_empty() {
+            continue;
+        }
+        let mut noun_section = room_section.add_subsection(noun_heading(&noun, options.headings));
+
+        noun_section.set_id(noun_id_to_id_string(noun.id()));
+
+        match conversations.into_iter().exactly_one() {
+            Ok(conversation) => {
+                if let Some(verb) = conversation.verb() {
+                    noun_section
+                        .add_content()
+                        .add_paragraph(format!("On {}", verb.name()));
                 }
-                Err(full_iter) => {
-                    let mut noun_section_builder = noun_section.into_section_builder();
+                generate_conversation(noun_section, &conversation, focus);
+            }
+            Err(full_iter) => {
+                let mut noun_section_builder = noun_section.into_section_builder();
 
-                    for conversation in full_iter {
-                        let title =
-                            match (conversation.verb(), conversation.condition()) {
-                                (Some(verb), Some(cond)) => format!(
+                for conversation in full_iter {
+                    let title =
+                        match (conversation.verb(), conversation.condition()) {
+                            (Some(verb), Some(cond)) => {
+                                format!(
                                     "On {} ({})",
                                     verb.name(),
                                     cond.desc().map(ToString::to_string).unwrap_or_else(
                                         || format!("Condition #{:?}", cond.id().condition_num())
                                     )
-                                ),
-                                (Some(verb), None) => format!("On {}", verb.name()),
-                                (None, Some(cond)) => format!(
+                                )
+                            }
+                            (Some(verb), None) => format!("On {}", verb.name()),
+                            (None, Some(cond)) => {
+                                format!(
                                     "When {}",
                                     cond.desc().map(ToString::to_string).unwrap_or_else(
                                         || format!("Condition #{:?}", cond.id().condition_num())
                                     )
-                                ),
-                                (None, None) => "On Any".to_string(),
-                            };
-                        let conv_section = noun_section_builder.add_subsection(title);
-                        generate_conversation(conv_section, &conversation);
-                    }
+                                )
+                            }
+                            (None, None) => "On Any".to_string(),
+                        };
+                    let conv_section = noun_section_builder.add_subsection(title);
+                    generate_conversation(conv_section, &conversation, focus);
                 }
             }
         }
     }
+}
+
+fn generate_document(
+    book: &Book,
+    options: &ExportOptions,
+    focus: Option<&RoleFocus>,
+) -> anyhow::Result<Document> {
+    let title = match focus {
+        Some(focus) => format!("{} Script: {}", book.project_name(), focus.role.name()),
+        None => format!("{} Script", book.project_name()),
+    };
+    // The master script gathers narration at the end, so it can be recorded
+    // in one session. Role bundles keep everything in place.
+    let separate_narration = focus.is_none();
+    let include = |conversation: &Conversation| match focus {
+        Some(focus) => focus.includes(conversation),
+        None => !is_narration(conversation),
+    };
+    let mut doc = DocumentBuilder::new(title);
+    for room in book.rooms() {
+        if !room_includes(&room, include) {
+            continue;
+        }
+        let mut room_section = doc.add_chapter(room_heading(&room, options.headings));
+        room_section.set_id(room_id_to_id_string(room.id()));
+        generate_room(
+            room_section.into_section_builder(),
+            &room,
+            options,
+            focus,
+            include,
+        );
+    }
+    if separate_narration && book.conversations().any(|c| is_narration(&c)) {
+        let mut narration_section = doc.add_chapter("Narration");
+        narration_section.set_id("narration");
+        let mut narration_section = narration_section.into_section_builder();
+        for room in book.rooms() {
+            if !room_includes(&room, is_narration) {
+                continue;
+            }
+            let mut room_section =
+                narration_section.add_subsection(room_heading(&room, options.headings));
+            room_section.set_id(format!("narration-{}", room_id_to_id_string(room.id())));
+            generate_room(
+                room_section.into_section_builder(),
+                &room,
+                options,
+                None,
+                is_narration,
+            );
+        }
+    }
     Ok(doc.build())
 }
 
@@ -288,23 +726,254 @@ fn generate_document(book: &Book) -> anyhow::Result<Document> {
 struct GenerateMaster {
     #[clap(flatten)]
     ctxt: CommonArgs,
+    #[clap(flatten)]
+    options: ExportOptions,
     #[clap(short, long)]
     output: PathBuf,
+    /// Show how much of each room and conversation is dubbed and has been
+    /// heard in playtests, from this sample directory, or the project's if
+    /// none is given.
+    #[cfg(feature = "coverage")]
+    #[clap(long, num_args = 0..=1)]
+    coverage: Option<Option<PathBuf>>,
 }
 
 impl GenerateMaster {
     fn run(&self) -> anyhow::Result<()> {
         let book = load_book(&self.ctxt)?;
-        let doc = generate_document(&book)?;
+        let pictures = self.options.pictures(&self.ctxt.root_dir()?, &book)?;
+        let doc = pictures.add_to(generate_document(&book, &self.options, None)?, None);
+        #[cfg(feature = "coverage")]
+        let doc = match &self.coverage {
+            Some(sample_dir) => {
+                let sample_dir = workspace::sample_dir(sample_dir.as_deref())?;
+                doc.with_coverage(&coverage::book_coverage(&book, &sample_dir)?)
+            }
+            None => doc,
+        };
         let html = generate_html(&doc)?;
         std::fs::write(&self.output, html)?;
         Ok(())
     }
 }
 
+/// Generates a PDF script for use in a recording booth.
+#[derive(Parser)]
+struct GeneratePdf {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+    #[clap(flatten)]
+    options: ExportOptions,
+    #[clap(short, long)]
+    output: PathBuf,
+    /// Highlight the lines of this role (by name or short name).
+    #[clap(long)]
+    highlight_role: Option<String>,
+    #[clap(flatten)]
+    pdf: PdfArgs,
+}
+
+#[derive(Parser)]
+struct PdfArgs {
+    #[clap(long, value_enum, default_value_t)]
+    page_size: PageSize,
+    /// The size of the dialogue text, in points.
+    #[clap(long, default_value_t = 14.0)]
+    font_size: f32,
+}
+
+impl PdfArgs {
+    fn to_options(&self, highlight_role: Option<&Role>) -> PdfOptions {
+        PdfOptions {
+            page_size: self.page_size,
+            font_size: self.font_size,
+            highlight_speaker: highlight_role.map(|role| role.short_name().to_string()),
+        }
+    }
+}
+
+impl GeneratePdf {
+    fn run(&self) -> anyhow::Result<()> {
+        let book = load_book(&self.ctxt)?;
+        let highlight_role = self
+            .highlight_role
+            .as_deref()
+            .map(|name| find_role(&book, name))
+            .transpose()?;
+        let pictures = self.options.pictures(&self.ctxt.root_dir()?, &book)?;
+        let doc = pictures.add_to(generate_document(&book, &self.options, None)?, None);
+        let pdf = generate_pdf(&doc, &self.pdf.to_options(highlight_role.as_ref()))?;
+        std::fs::write(&self.output, pdf)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum BundleFormat {
+    #[default]
+    Pdf,
+    Html,
+}
+
+/// How role bundles are written.
+#[derive(Parser)]
+pub(super) struct BundleArgs {
+    #[clap(flatten)]
+    options: ExportOptions,
+    #[clap(long, value_enum, default_value_t)]
+    format: BundleFormat,
+    #[clap(flatten)]
+    pdf: PdfArgs,
+}
+
+impl BundleArgs {
+    /// The pictures to show in bundles, for [`Self::write_bundle`].
+    pub(super) fn pictures(&self, root_dir: &Path, book: &Book) -> anyhow::Result<Pictures> {
+        self.options.pictures(root_dir, book)
+    }
+
+    /// Writes the role's bundle to the directory, unless the role has no
+    /// lines.
+    pub(super) fn write_bundle(
+        &self,
+        book: &Book,
+        role: Role,
+        pictures: &Pictures,
+        output_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let path = self.bundle_path(&role, output_dir);
+        let focus = RoleFocus { role };
+        let doc = pictures.add_to(
+            generate_document(book, &self.options, Some(&focus))?,
+            Some(&focus),
+        );
+        if doc.chapters().is_empty() {
+            eprintln!("Skipping {}: no lines", focus.role.name());
+            return Ok(());
+        }
+        let contents = match self.format {
+            BundleFormat::Pdf => generate_pdf(&doc, &self.pdf.to_options(Some(&focus.role)))?,
+            BundleFormat::Html => generate_html(&doc)?.into_bytes(),
+        };
+        std::fs::write(&path, contents)?;
+        eprintln!("Wrote {} bundle to {}", focus.role.name(), path.display());
+        Ok(())
+    }
+
+    fn bundle_path(&self, role: &Role, output_dir: &Path) -> PathBuf {
+        let name: String = role
+            .short_name()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let ext = match self.format {
+            BundleFormat::Pdf => "pdf",
+            BundleFormat::Html => "html",
+        };
+        output_dir.join(format!("{}.{}", name, ext))
+    }
+}
+
+/// Generates a script for each role, containing only the conversations that
+/// role speaks in.
+///
+/// Roles can be configured to have other roles' lines redacted down to their
+/// cue words, for actors who want to avoid spoilers.
+#[derive(Parser)]
+struct GenerateBundles {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+    #[clap(short, long)]
+    output_dir: PathBuf,
+    /// Only generate bundles for these roles (by name or short name).
+    #[clap(long = "role")]
+    roles: Vec<String>,
+    #[clap(flatten)]
+    bundle: BundleArgs,
+}
+
+impl GenerateBundles {
+    fn run(&self) -> anyhow::Result<()> {
+        let book = load_book(&self.ctxt)?;
+        let roles = if self.roles.is_empty() {
+            book.roles().collect()
+        } else {
+            self.roles
+                .iter()
+                .map(|name| find_role(&book, name))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        let pictures = self.bundle.pictures(&self.ctxt.root_dir()?, &book)?;
+        std::fs::create_dir_all(&self.output_dir)?;
+        for role in roles {
+            self.bundle
+                .write_bundle(&book, role, &pictures, &self.output_dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum ExportFormat {
+    /// Audacity label tracks, one per conversation.
+    #[default]
+    AudacityLabels,
+}
+
+/// Exports the book for recording tools.
+///
+/// Audacity label tracks mark where each line of a conversation falls, so a
+/// conversation recorded in one take can be split into a file per line.
+/// Lines are placed by estimated lengths, to be adjusted on the recording.
+#[derive(Parser)]
+struct GenerateExport {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+    #[clap(short, long)]
+    output_dir: PathBuf,
+    #[clap(long, value_enum, default_value_t)]
+    format: ExportFormat,
+    /// The speaking rate lines' lengths are estimated from.
+    #[clap(long, default_value_t = 150.0)]
+    words_per_minute: f64,
+    /// The pause between lines, in seconds.
+    #[clap(long, default_value_t = 1.0)]
+    gap: f64,
+}
+
+impl GenerateExport {
+    fn run(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.words_per_minute > 0.0,
+            "The speaking rate must be above zero"
+        );
+        let book = load_book(&self.ctxt)?;
+        match self.format {
+            ExportFormat::AudacityLabels => {
+                let timing = labels::LabelTiming {
+                    words_per_minute: self.words_per_minute,
+                    gap: std::time::Duration::try_from_secs_f64(self.gap)?,
+                    min_line: std::time::Duration::from_secs(1),
+                };
+                let written =
+                    labels::write_labels(book.conversations(), &timing, &self.output_dir)?;
+                eprintln!(
+                    "Wrote {} label tracks to {}",
+                    written,
+                    self.output_dir.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Subcommand)]
 enum GenerateCommand {
     Master(GenerateMaster),
+    Pdf(GeneratePdf),
+    Bundles(GenerateBundles),
+    Export(GenerateExport),
 }
 
 #[derive(Parser)]
@@ -317,6 +986,9 @@ impl Generate {
     pub fn run(&self) -> anyhow::Result<()> {
         match &self.msg_cmd {
             GenerateCommand::Master(cmd) => cmd.run(),
+            GenerateCommand::Pdf(cmd) => cmd.run(),
+            GenerateCommand::Bundles(cmd) => cmd.run(),
+            GenerateCommand::Export(cmd) => cmd.run(),
         }
     }
 }
@@ -0,0 +1,95 @@
+//! Dub and playtest coverage of the book's rooms and conversations, for
+//! coloring the master script.
+
+use std::{collections::BTreeMap, path::Path};
+
+use sci_resources::types::msg::MessageId;
+use scitool_fan_dub_cli::{
+    playtest::PlaytestLog,
+    resources::{SampleDir, sample_key},
+};
+
+use super::{
+    conversation_id_to_id_string, is_narration, noun_id_to_id_string, room_id_to_id_string,
+};
+use crate::{
+    book::{Book, Conversation},
+    generate::doc::Coverage,
+};
+
+fn add_conversation(
+    coverage: &mut Coverage,
+    conversation: &Conversation,
+    samples: &SampleDir,
+    playtest: &PlaytestLog,
+) {
+    // Reference lines are covered where they point.
+    for line in conversation
+        .lines()
+        .filter(|line| line.ref_target().is_none())
+    {
+        let id = line.id();
+        let message_id = MessageId::new(
+            id.noun_num(),
+            id.verb_num(),
+            id.condition_num(),
+            id.sequence_num(),
+        );
+        coverage.lines += 1;
+        coverage.dubbed += usize::from(samples.sample(id.room_num(), &message_id).is_some());
+        coverage.heard += usize::from(playtest.is_heard(&sample_key(id.room_num(), &message_id)));
+    }
+}
+
+/// The coverage of each room, noun and conversation, by the IDs of their
+/// sections in the master script. Rooms count the lines outside of
+/// narration, which the script gathers in its own sections.
+pub(super) fn book_coverage(
+    book: &Book,
+    sample_dir: &Path,
+) -> anyhow::Result<BTreeMap<String, Coverage>> {
+    let samples = smol::block_on(SampleDir::load_dir(sample_dir))?;
+    let playtest = match PlaytestLog::load(sample_dir)? {
+        Some(playtest) => playtest,
+        None => {
+            eprintln!(
+                "Warning: no playtest logs have been imported into {}",
+                sample_dir.display()
+            );
+            PlaytestLog::default()
+        }
+    };
+    let mut coverage = BTreeMap::new();
+    for room in book.rooms() {
+        let mut room_coverage = Coverage::default();
+        let mut narration_coverage = Coverage::default();
+        for noun in room.nouns() {
+            let mut noun_coverage = Coverage::default();
+            for conversation in noun.conversations() {
+                let mut conversation_coverage = Coverage::default();
+                add_conversation(
+                    &mut conversation_coverage,
+                    &conversation,
+                    &samples,
+                    &playtest,
+                );
+                add_conversation(&mut noun_coverage, &conversation, &samples, &playtest);
+                let room_total = if is_narration(&conversation) {
+                    &mut narration_coverage
+                } else {
+                    &mut room_coverage
+                };
+                add_conversation(room_total, &conversation, &samples, &playtest);
+                coverage.insert(
+                    conversation_id_to_id_string(conversation.id()),
+                    conversation_coverage,
+                );
+            }
+            coverage.insert(noun_id_to_id_string(noun.id()), noun_coverage);
+        }
+        let room_id = room_id_to_id_string(room.id());
+        coverage.insert(format!("narration-{room_id}"), narration_coverage);
+        coverage.insert(room_id, room_coverage);
+    }
+    Ok(coverage)
+}
@@ -0,0 +1,109 @@
+//! Audacity label tracks, one per conversation, for actors recording a
+//! whole conversation in one take.
+//!
+//! Each line gets a region label named with its line key (e.g.
+//! `10-1-2-0-1`) and its text, placed one after another. The lines haven't
+//! been recorded yet, so their lengths are estimated from their word counts;
+//! the labels are a starting point to drag into place on the recording,
+//! after which Audacity's "Export Multiple" splits the take into one file
+//! per line, named so `scitool-fan-dub` picks them up as takes.
+
+use std::{fmt::Write as _, path::Path, time::Duration};
+
+use super::{MessageSegment, parse_message_text};
+use crate::book::{Conversation, Line, segment::SegmentKind};
+
+/// How lines are spaced on the label track.
+pub(super) struct LabelTiming {
+    pub(super) words_per_minute: f64,
+    /// The pause left between lines.
+    pub(super) gap: Duration,
+    /// The shortest a line's label is made, for lines of a word or two.
+    pub(super) min_line: Duration,
+}
+
+/// The file a conversation's labels are written to, named like
+/// `room-10-n1-v2-c0.txt`.
+fn labels_file_name(conversation: &Conversation) -> String {
+    let id = conversation.id();
+    format!(
+        "room-{}-n{}-v{}-c{}.txt",
+        id.room_num(),
+        id.noun_num(),
+        id.verb_num(),
+        id.condition_num()
+    )
+}
+
+/// The text of a line without its control codes, on a single line.
+fn label_text(line: &Line) -> String {
+    let text: String = parse_message_text(line.text())
+        .into_iter()
+        .filter_map(|segment| match segment {
+            MessageSegment::Text(text) => Some(text),
+            MessageSegment::Control(..) => None,
+        })
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Estimates how long a line takes to say, from the words in its speech
+/// (leaving out stage directions).
+fn estimated_length(line: &Line, timing: &LabelTiming) -> Duration {
+    let words: usize = line
+        .segments()
+        .iter()
+        .filter(|segment| segment.kind() == SegmentKind::Speech)
+        .map(|segment| segment.text().split_whitespace().count())
+        .sum();
+    Duration::from_secs_f64(words as f64 * 60.0 / timing.words_per_minute).max(timing.min_line)
+}
+
+/// Writes the label track of a conversation, in Audacity's format: a line per
+/// label, with its start and end in seconds and its name, separated by tabs.
+/// Reference lines, which reuse other conversations' audio, are left out.
+/// Returns `None` if the conversation has only reference lines.
+fn conversation_labels(conversation: &Conversation, timing: &LabelTiming) -> Option<String> {
+    let mut labels = String::new();
+    let mut offset = Duration::ZERO;
+    for line in conversation
+        .lines()
+        .filter(|line| line.ref_target().is_none())
+    {
+        let id = line.id();
+        let end = offset + estimated_length(&line, timing);
+        writeln!(
+            labels,
+            "{:.6}\t{:.6}\t{}-{}-{}-{}-{} {}",
+            offset.as_secs_f64(),
+            end.as_secs_f64(),
+            id.room_num(),
+            id.noun_num(),
+            id.verb_num(),
+            id.condition_num(),
+            id.sequence_num(),
+            label_text(&line)
+        )
+        .unwrap();
+        offset = end + timing.gap;
+    }
+    (!labels.is_empty()).then_some(labels)
+}
+
+/// Writes the label tracks of the conversations to the directory, returning
+/// how many were written.
+pub(super) fn write_labels<'a>(
+    conversations: impl IntoIterator<Item = Conversation<'a>>,
+    timing: &LabelTiming,
+    output_dir: &Path,
+) -> anyhow::Result<usize> {
+    std::fs::create_dir_all(output_dir)?;
+    let mut written = 0;
+    for conversation in conversations {
+        if let Some(labels) = conversation_labels(&conversation, timing) {
+            std::fs::write(output_dir.join(labels_file_name(&conversation)), labels)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
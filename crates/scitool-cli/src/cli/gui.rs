@@ -0,0 +1,515 @@
+//! A graphical front-end for the dub workflow, for project members who would
+//! rather not use a terminal.
+//!
+//! The book is shown as a tree of rooms, nouns and conversations, with the
+//! recording status of each line. Selecting a line shows a thumbnail of its
+//! room's background and lists its takes, which can be played and chosen; choices are saved to the sample directory's
+//! `samples.json`. Builds run the same code as `scitool-fan-dub compile-audio`.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+};
+
+use clap::Parser;
+use eframe::egui;
+use sci_resources::types::msg::MessageId;
+use scitool_fan_dub_cli::{
+    added::AddedLine,
+    build::{BuildSettings, build_audio},
+    cancel::CancellationToken,
+    path::LookupPath,
+    resources::SampleDir,
+};
+
+use super::{
+    detect,
+    generate::{CommonArgs, load_book},
+    pic::pic_thumbnail,
+    workspace,
+};
+use crate::{
+    book::{Book, Line, LineId},
+    generate::doc::Thumbnail,
+};
+
+/// The widest a room thumbnail is, in the game's pixels.
+const THUMBNAIL_MAX_WIDTH: u16 = 160;
+
+/// How many screen pixels wide a thumbnail is drawn.
+const THUMBNAIL_DISPLAY_WIDTH: f32 = 320.0;
+
+/// Opens the dub workflow GUI.
+#[derive(Parser)]
+pub(super) struct Gui {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+
+    /// The sample directory, with the takes and `samples.json`. Defaults to
+    /// the project's.
+    #[clap(short = 's', long)]
+    sample_dir: Option<PathBuf>,
+
+    /// Where builds are written.
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl Gui {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let book = load_book(&self.ctxt)?;
+        let sample_dir_path = workspace::sample_dir(self.sample_dir.as_deref())?;
+        let sample_dir = smol::block_on(SampleDir::load_dir(&sample_dir_path))?;
+        let system_path = LookupPath::from_env();
+        let mut build_settings = BuildSettings::new(sample_dir_path, self.output.clone());
+        // The original audio is only needed by samples that are matched to
+        // it, so builds work without it.
+        let root_dir = self.ctxt.root_dir()?;
+        let resource_set = detect::open_game(&root_dir)?;
+        let mut thumbnails = BTreeMap::new();
+        for room in book.rooms() {
+            let room_num = room.id().room_num();
+            match pic_thumbnail(&resource_set, room_num, THUMBNAIL_MAX_WIDTH) {
+                Ok(Some(thumbnail)) => {
+                    thumbnails.insert(room_num, thumbnail);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Warning: couldn't render pic {room_num}: {e}"),
+            }
+        }
+        if root_dir.join("RESOURCE.AUD").exists() {
+            build_settings.game_dir = Some(root_dir);
+        }
+        build_settings.added_lines = book
+            .lines()
+            .filter(|line| line.is_added())
+            .map(|line| AddedLine {
+                room: line.id().room_num(),
+                message_id: message_id(&line.id()),
+                talker: line.talker().id().talker_num(),
+                text: line.text().to_string(),
+            })
+            .collect();
+        let app = GuiApp {
+            book,
+            sample_dir,
+            build_settings,
+            ffmpeg_path: system_path
+                .find_binary("ffmpeg")
+                .map(|path| path.to_path_buf()),
+            ffplay_path: system_path
+                .find_binary("ffplay")
+                .map(|path| path.to_path_buf()),
+            thumbnails,
+            textures: BTreeMap::new(),
+            selected: None,
+            takes: Vec::new(),
+            player: None,
+            build: None,
+            log: Vec::new(),
+        };
+        eframe::run_native(
+            "scitool",
+            eframe::NativeOptions::default(),
+            Box::new(|_cc| Ok(Box::new(app))),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to start the GUI: {e}"))
+    }
+}
+
+enum BuildMessage {
+    Log(String),
+    Finished(Result<(), String>),
+}
+
+struct RunningBuild {
+    cancel: CancellationToken,
+    messages: mpsc::Receiver<BuildMessage>,
+}
+
+fn message_id(line_id: &LineId) -> MessageId {
+    MessageId::new(
+        line_id.noun_num(),
+        line_id.verb_num(),
+        line_id.condition_num(),
+        line_id.sequence_num(),
+    )
+}
+
+struct GuiApp {
+    book: Book,
+    sample_dir: SampleDir,
+    build_settings: BuildSettings,
+    ffmpeg_path: Option<PathBuf>,
+    ffplay_path: Option<PathBuf>,
+    /// Thumbnails of the rooms' backgrounds, by room number.
+    thumbnails: BTreeMap<u16, Thumbnail>,
+    /// The thumbnails uploaded so far, loaded as rooms are first shown.
+    textures: BTreeMap<u16, egui::TextureHandle>,
+    selected: Option<LineId>,
+    /// The takes of the selected line.
+    takes: Vec<PathBuf>,
+    player: Option<Child>,
+    build: Option<RunningBuild>,
+    log: Vec<String>,
+}
+
+impl GuiApp {
+    fn is_recorded(&self, line: &Line) -> bool {
+        let id = line.id();
+        self.sample_dir
+            .sample(id.room_num(), &message_id(&id))
+            .is_some()
+    }
+
+    fn select_line(&mut self, line_id: LineId) {
+        self.selected = Some(line_id);
+        self.takes = match self
+            .sample_dir
+            .find_takes(line_id.room_num(), &message_id(&line_id))
+        {
+            Ok(takes) => takes,
+            Err(e) => {
+                self.log.push(format!("Failed to list takes: {e}"));
+                Vec::new()
+            }
+        };
+    }
+
+    fn choose_take(&mut self, line_id: LineId, take: PathBuf) {
+        if let Err(e) = self
+            .sample_dir
+            .ensure_unlocked(line_id.room_num(), &message_id(&line_id))
+        {
+            self.log.push(format!("{e}; unlock it to change its take"));
+            return;
+        }
+        self.sample_dir
+            .select_take(line_id.room_num(), message_id(&line_id), take);
+        if let Err(e) = smol::block_on(self.sample_dir.save()) {
+            self.log.push(format!("Failed to save samples.json: {e}"));
+        }
+    }
+
+    fn play(&mut self, take: &PathBuf) {
+        self.stop();
+        let Some(ffplay_path) = &self.ffplay_path else {
+            return;
+        };
+        match Command::new(ffplay_path)
+            .args(["-nodisp", "-autoexit", "-loglevel", "quiet"])
+            .arg(self.sample_dir.base_path().join(take))
+            .stdin(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => self.player = Some(child),
+            Err(e) => self
+                .log
+                .push(format!("Failed to play {}: {e}", take.display())),
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut child) = self.player.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    /// Starts a build on its own thread, reporting back over a channel.
+    fn start_build(&mut self, ctx: &egui::Context) {
+        let Some(ffmpeg_path) = self.ffmpeg_path.clone() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let cancel = CancellationToken::new();
+        let settings = self.build_settings.clone();
+        let ctx = ctx.clone();
+        let build_cancel = cancel.clone();
+        std::thread::spawn(move || {
+            let log = |message: String| {
+                let _ = sender.send(BuildMessage::Log(message));
+                ctx.request_repaint();
+            };
+            let exec = smol::LocalExecutor::new();
+            let result =
+                smol::block_on(exec.run(build_audio(&settings, ffmpeg_path, build_cancel, &log)));
+            let _ = sender.send(BuildMessage::Finished(result.map_err(|e| e.to_string())));
+            ctx.request_repaint();
+        });
+        self.log.push("Build started".to_string());
+        self.build = Some(RunningBuild {
+            cancel,
+            messages: receiver,
+        });
+    }
+
+    fn poll_build(&mut self) {
+        let Some(build) = &self.build else {
+            return;
+        };
+        let mut finished = false;
+        while let Ok(message) = build.messages.try_recv() {
+            match message {
+                BuildMessage::Log(message) => self.log.push(message),
+                BuildMessage::Finished(Ok(())) => {
+                    self.log.push(format!(
+                        "Build finished; written to {}",
+                        self.build_settings.output.display()
+                    ));
+                    finished = true;
+                }
+                BuildMessage::Finished(Err(e)) => {
+                    self.log.push(format!("Build failed: {e}"));
+                    finished = true;
+                }
+            }
+        }
+        if finished {
+            self.build = None;
+        }
+    }
+
+    fn toolbar_ui(&mut self, ui: &mut egui::Ui) {
+        let total = self.book.lines().count();
+        let recorded = self
+            .book
+            .lines()
+            .filter(|line| self.is_recorded(line))
+            .count();
+        ui.horizontal(|ui| {
+            ui.heading(self.book.project_name());
+            ui.label(format!("{recorded} of {total} lines recorded"));
+            ui.separator();
+            match &self.build {
+                Some(build) => {
+                    ui.spinner();
+                    if ui.button("Cancel build").clicked() {
+                        build.cancel.cancel();
+                    }
+                }
+                None => {
+                    let build_button =
+                        ui.add_enabled(self.ffmpeg_path.is_some(), egui::Button::new("Build"));
+                    if build_button
+                        .on_disabled_hover_text("ffmpeg was not found in PATH")
+                        .clicked()
+                    {
+                        self.start_build(ui.ctx());
+                    }
+                    ui.checkbox(&mut self.build_settings.resume, "Resume");
+                }
+            }
+        });
+    }
+
+    fn tree_ui(&mut self, ui: &mut egui::Ui) {
+        let mut clicked = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for room in self.book.rooms() {
+                let lines: Vec<Line> = room
+                    .nouns()
+                    .flat_map(|noun| noun.conversations())
+                    .flat_map(|conversation| conversation.lines())
+                    .collect();
+                let recorded = lines.iter().filter(|line| self.is_recorded(line)).count();
+                let room_num = room.id().room_num();
+                egui::CollapsingHeader::new(format!(
+                    "Room {room_num}: {} ({recorded}/{})",
+                    room.name(),
+                    lines.len()
+                ))
+                .id_salt(("room", room_num))
+                .show(ui, |ui| {
+                    for noun in room.nouns() {
+                        let noun_num = noun.id().noun_num();
+                        let title = match noun.desc() {
+                            Some(desc) => format!("Noun {noun_num}: {desc}"),
+                            None => format!("Noun {noun_num}"),
+                        };
+                        egui::CollapsingHeader::new(title)
+                            .id_salt(("noun", room_num, noun_num))
+                            .show(ui, |ui| {
+                                for conversation in noun.conversations() {
+                                    let mut title = match conversation.verb() {
+                                        Some(verb) => verb.name().to_string(),
+                                        None => "No verb".to_string(),
+                                    };
+                                    if let Some(condition) = conversation.condition() {
+                                        title.push_str(&format!(
+                                            ", condition {}",
+                                            condition.id().condition_num()
+                                        ));
+                                    }
+                                    ui.label(egui::RichText::new(title).strong());
+                                    for line in conversation.lines() {
+                                        let id = line.id();
+                                        let mark = if self.is_recorded(&line) {
+                                            "●"
+                                        } else {
+                                            "○"
+                                        };
+                                        let text: String = line.text().chars().take(60).collect();
+                                        let label = format!(
+                                            "{mark} {} {}: {text}",
+                                            id.sequence_num(),
+                                            line.role().short_name()
+                                        );
+                                        if ui
+                                            .selectable_label(self.selected == Some(id), label)
+                                            .clicked()
+                                        {
+                                            clicked = Some(id);
+                                        }
+                                    }
+                                }
+                            });
+                    }
+                });
+            }
+        });
+        if let Some(id) = clicked {
+            self.select_line(id);
+        }
+    }
+
+    /// Shows the thumbnail of the room's background, if it has one.
+    fn thumbnail_ui(
+        ui: &mut egui::Ui,
+        thumbnails: &BTreeMap<u16, Thumbnail>,
+        textures: &mut BTreeMap<u16, egui::TextureHandle>,
+        room_num: u16,
+    ) {
+        let Some(thumbnail) = thumbnails.get(&room_num) else {
+            return;
+        };
+        let texture = textures.entry(room_num).or_insert_with(|| {
+            ui.ctx().load_texture(
+                format!("room-{room_num}"),
+                egui::ColorImage::from_rgb(
+                    [usize::from(thumbnail.width), usize::from(thumbnail.height)],
+                    &thumbnail.rgb,
+                ),
+                egui::TextureOptions::NEAREST,
+            )
+        });
+        let size = egui::vec2(
+            THUMBNAIL_DISPLAY_WIDTH,
+            THUMBNAIL_DISPLAY_WIDTH / thumbnail.aspect_ratio(),
+        );
+        ui.add(egui::Image::new(&*texture).fit_to_exact_size(size));
+    }
+
+    fn line_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(line_id) = self.selected else {
+            ui.label("Select a line to see its takes.");
+            return;
+        };
+        let Some(line) = self.book.lines().find(|line| line.id() == line_id) else {
+            return;
+        };
+        let msg_id = message_id(&line_id);
+        ui.heading(format!(
+            "Room {}, line {}-{}-{}-{}",
+            line_id.room_num(),
+            line_id.noun_num(),
+            line_id.verb_num(),
+            line_id.condition_num(),
+            line_id.sequence_num()
+        ));
+        Self::thumbnail_ui(ui, &self.thumbnails, &mut self.textures, line_id.room_num());
+        ui.label(egui::RichText::new(line.role().name()).strong());
+        ui.label(line.text());
+        ui.separator();
+
+        let current = self
+            .sample_dir
+            .sample(line_id.room_num(), &msg_id)
+            .map(|sample| sample.clip.path.clone());
+        if self.takes.is_empty() {
+            ui.label(format!(
+                "No takes found. Takes are audio files in the sample directory named {}-{}-{}-{}-{}, optionally followed by a suffix (e.g. _take2).",
+                line_id.room_num(),
+                line_id.noun_num(),
+                line_id.verb_num(),
+                line_id.condition_num(),
+                line_id.sequence_num()
+            ));
+        }
+        let locked = self.sample_dir.is_locked(line_id.room_num(), &msg_id);
+        if locked {
+            ui.label("Locked: the take has been approved.");
+        }
+        let mut chosen = None;
+        let mut play = None;
+        for take in &self.takes {
+            ui.horizontal(|ui| {
+                let is_current = current.as_ref() == Some(take);
+                let radio = ui.add_enabled(
+                    !locked || is_current,
+                    egui::RadioButton::new(is_current, take.display().to_string()),
+                );
+                if radio.clicked() && !is_current {
+                    chosen = Some(take.clone());
+                }
+                let play_button =
+                    ui.add_enabled(self.ffplay_path.is_some(), egui::Button::new("Play"));
+                if play_button
+                    .on_disabled_hover_text("ffplay was not found in PATH")
+                    .clicked()
+                {
+                    play = Some(take.clone());
+                }
+            });
+        }
+        if let Some(current) = &current
+            && !self.takes.contains(current)
+        {
+            ui.label(format!("Current take: {}", current.display()));
+        }
+        if self.player.is_some() && ui.button("Stop").clicked() {
+            self.stop();
+        }
+        if let Some(take) = chosen {
+            self.choose_take(line_id, take);
+        }
+        if let Some(take) = play {
+            self.play(&take);
+        }
+    }
+}
+
+impl eframe::App for GuiApp {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.poll_build();
+        egui::Panel::top("toolbar").show(ui, |ui| self.toolbar_ui(ui));
+        egui::Panel::bottom("log")
+            .resizable(true)
+            .default_size(120.0)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for message in &self.log {
+                            ui.label(message);
+                        }
+                    });
+            });
+        egui::Panel::left("book")
+            .resizable(true)
+            .default_size(420.0)
+            .show(ui, |ui| self.tree_ui(ui));
+        egui::CentralPanel::default().show(ui, |ui| self.line_ui(ui));
+    }
+}
+
+impl Drop for GuiApp {
+    fn drop(&mut self) {
+        self.stop();
+        if let Some(build) = &self.build {
+            build.cancel.cancel();
+        }
+    }
+}
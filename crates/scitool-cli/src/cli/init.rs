@@ -0,0 +1,300 @@
+//! `scitool init`: starts a dub project for a game in one step.
+//!
+//! The project directory gets:
+//!
+//! - `book.yaml`, a book config with the game's rooms, talkers and verbs,
+//!   named from the scripts where they can be (with the `analysis` feature).
+//! - `samples/`, the sample directory, with an empty `samples.json`, an empty
+//!   `fan-dub.json` for the build pipeline's settings, and `takes/` for the
+//!   recordings.
+//! - `build/`, for built audio.
+//! - `scitool-workspace.yaml`, naming the project, so other commands find
+//!   these paths without being given them.
+//! - `.gitignore`, leaving out the builds and the game's own files.
+//!
+//! Files that exist already are left as they are, so running it again only
+//! fills in what is missing.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use sci_resources::{ResourceType, file::ResourceSet, types::msg::parse_message_resource};
+
+use super::{detect, workspace};
+use crate::{book::config::BookConfig, i18n::tr};
+
+const CONFIG_FILE: &str = "book.yaml";
+const SAMPLE_DIR: &str = "samples";
+const TAKES_DIR: &str = "takes";
+const BUILD_DIR: &str = "build";
+
+/// The rooms, talkers and verbs that the game's messages use.
+#[derive(Default)]
+struct GameMessages {
+    rooms: BTreeSet<u16>,
+    talkers: BTreeSet<u8>,
+    verbs: BTreeSet<u8>,
+    lines: usize,
+}
+
+impl GameMessages {
+    fn read(resource_set: &ResourceSet) -> anyhow::Result<Self> {
+        let mut messages = GameMessages::default();
+        for res in resource_set.resources_of_type(ResourceType::Message) {
+            let msg_resources = parse_message_resource(res.load_data()?)?;
+            messages.rooms.insert(res.id().resource_num());
+            for (id, record) in msg_resources.messages() {
+                messages.talkers.insert(record.talker());
+                if id.verb() != 0 {
+                    messages.verbs.insert(id.verb());
+                }
+                messages.lines += 1;
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// The names the scripts give rooms and verbs, and the characters that speak
+/// for talkers, as far as they can be found. Without the `analysis` feature,
+/// nothing is named.
+#[derive(Default)]
+struct ScriptNames {
+    rooms: BTreeMap<u16, String>,
+    verbs: BTreeMap<u16, String>,
+    /// The character's name, and whether it is a narrator.
+    talkers: BTreeMap<u16, (String, bool)>,
+}
+
+impl ScriptNames {
+    #[cfg(feature = "analysis")]
+    fn read(resource_set: &ResourceSet) -> Self {
+        let mut names = ScriptNames::default();
+        match scitool_script_loader::find_room_names(resource_set) {
+            Ok(rooms) => names.rooms = rooms,
+            Err(e) => eprintln!("Warning: couldn't read room names from the scripts: {e}"),
+        }
+        match scitool_script_loader::find_verb_names(resource_set) {
+            Ok(verbs) => names.verbs = verbs,
+            Err(e) => eprintln!("Warning: couldn't read verb names from the scripts: {e}"),
+        }
+        match scitool_script_loader::find_talkers(resource_set) {
+            Ok(talkers) => {
+                names.talkers = talkers
+                    .into_iter()
+                    .map(|(talker, object)| (talker, (object.character_name, object.is_narrator)))
+                    .collect();
+            }
+            Err(e) => eprintln!("Warning: couldn't read talkers from the scripts: {e}"),
+        }
+        names
+    }
+
+    #[cfg(not(feature = "analysis"))]
+    fn read(_resource_set: &ResourceSet) -> Self {
+        ScriptNames::default()
+    }
+}
+
+/// The role ID for a character's name.
+#[cfg(feature = "analysis")]
+fn role_id(character_name: &str) -> String {
+    super::script::role_id(character_name)
+}
+
+#[cfg(not(feature = "analysis"))]
+fn role_id(character_name: &str) -> String {
+    character_name
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn starter_config(name: &str, messages: &GameMessages, names: &ScriptNames) -> BookConfig {
+    let mut config = BookConfig::new(name.to_string());
+    for talker in &messages.talkers {
+        // Talkers the scripts don't name get a role each, to be merged into
+        // the right characters' roles by hand.
+        let (role, role_name, narrator) = match names.talkers.get(&u16::from(*talker)) {
+            Some((character_name, narrator)) => {
+                (role_id(character_name), character_name.clone(), *narrator)
+            }
+            None => (
+                format!("talker-{talker}"),
+                format!("Talker {talker}"),
+                false,
+            ),
+        };
+        config.add_role(&role, &role_name, narrator);
+        config.add_talker(*talker, &role);
+    }
+    // Every verb needs a name for the book to build.
+    for verb in &messages.verbs {
+        let verb_name = names
+            .verbs
+            .get(&u16::from(*verb))
+            .cloned()
+            .unwrap_or_else(|| format!("Verb {verb}"));
+        config.add_verb(*verb, verb_name);
+    }
+    for room in &messages.rooms {
+        let room_name = names
+            .rooms
+            .get(room)
+            .cloned()
+            .unwrap_or_else(|| format!("Room {room}"));
+        config.add_room(*room, room_name);
+    }
+    config
+}
+
+fn warn_exists(path: &Path) {
+    eprintln!(
+        "{}",
+        tr!("init-file-exists", path = path.display().to_string())
+    );
+}
+
+/// Writes a file, unless it exists.
+fn write_new(path: &Path, contents: &str) -> anyhow::Result<()> {
+    if path.exists() {
+        warn_exists(path);
+        return Ok(());
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// The game directory as the workspace should refer to it: relative to the
+/// project directory if it is inside it, and absolute otherwise.
+fn game_dir_for_workspace(project_dir: &Path, game_dir: &Path) -> anyhow::Result<PathBuf> {
+    let project_dir = std::fs::canonicalize(project_dir)?;
+    let game_dir = std::fs::canonicalize(game_dir)?;
+    Ok(match game_dir.strip_prefix(&project_dir) {
+        Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => game_dir,
+    })
+}
+
+fn gitignore(game_dir: &Path) -> String {
+    let mut gitignore = format!(
+        "# Built audio, and the build pipeline's checkpoints.\n/{BUILD_DIR}/\n/{SAMPLE_DIR}/.*/\n"
+    );
+    // The game's files aren't the project's to share.
+    if game_dir.is_relative() && game_dir != Path::new(".") {
+        gitignore.push_str(&format!(
+            "# The game.\n/{}/\n",
+            game_dir.to_string_lossy().replace('\\', "/")
+        ));
+    }
+    gitignore
+}
+
+/// Starts a dub project for a game: a book config listing the game's rooms,
+/// talkers and verbs, a sample directory for the recordings, and a workspace
+/// file naming the project.
+#[derive(Parser)]
+pub(super) struct Init {
+    /// The game directory.
+    game_dir: PathBuf,
+    /// The project directory. It is created if it doesn't exist.
+    #[clap(short = 'o', long, default_value = ".")]
+    project_dir: PathBuf,
+    /// The project's name. Defaults to the game directory's name.
+    #[clap(long)]
+    name: Option<String>,
+}
+
+impl Init {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.game_dir)?;
+        let messages = GameMessages::read(&resource_set)?;
+        if messages.rooms.is_empty() {
+            anyhow::bail!(tr!(
+                "init-no-messages",
+                path = self.game_dir.display().to_string()
+            ));
+        }
+        println!(
+            "{}",
+            tr!(
+                "init-found",
+                rooms = messages.rooms.len(),
+                lines = messages.lines,
+                talkers = messages.talkers.len()
+            )
+        );
+        if self.game_dir.join("RESOURCE.AUD").exists() {
+            println!("{}", tr!("init-has-speech"));
+        }
+        let detected = detect::detect_game(&self.game_dir);
+        if let Some(game) = detected {
+            println!("{}", tr!("detected-game", game = game.to_string()));
+        }
+
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => std::fs::canonicalize(&self.game_dir)?
+                .file_name()
+                .map_or_else(
+                    || "dub".to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                ),
+        };
+        let names = ScriptNames::read(&resource_set);
+        let mut config = starter_config(&name, &messages, &names);
+        if let Some(code_page) = detected.and_then(|game| game.code_page) {
+            config.set_code_page(code_page);
+        }
+        let unnamed_verbs: Vec<_> = messages
+            .verbs
+            .iter()
+            .filter(|verb| !names.verbs.contains_key(&u16::from(**verb)))
+            .map(ToString::to_string)
+            .collect();
+        if !unnamed_verbs.is_empty() {
+            eprintln!(
+                "Warning: verbs the scripts don't name (rename them in the config): {}",
+                unnamed_verbs.join(", ")
+            );
+        }
+
+        let sample_dir = self.project_dir.join(SAMPLE_DIR);
+        std::fs::create_dir_all(sample_dir.join(TAKES_DIR))?;
+        std::fs::create_dir_all(self.project_dir.join(BUILD_DIR))?;
+        write_new(
+            &self.project_dir.join(CONFIG_FILE),
+            &serde_yml::to_string(&config)?,
+        )?;
+        write_new(&sample_dir.join("samples.json"), "[]\n")?;
+        write_new(&sample_dir.join("fan-dub.json"), "{}\n")?;
+        let game_dir = game_dir_for_workspace(&self.project_dir, &self.game_dir)?;
+        write_new(&self.project_dir.join(".gitignore"), &gitignore(&game_dir))?;
+        let workspace_path = self.project_dir.join(workspace::WORKSPACE_FILE_NAME);
+        if workspace_path.exists() {
+            warn_exists(&workspace_path);
+        } else {
+            workspace::write_workspace(
+                &self.project_dir,
+                &name,
+                &game_dir,
+                Path::new(CONFIG_FILE),
+                Path::new(SAMPLE_DIR),
+            )?;
+        }
+        println!(
+            "{}",
+            tr!(
+                "init-done",
+                name = name.as_str(),
+                path = self.project_dir.display().to_string()
+            )
+        );
+        Ok(())
+    }
+}
@@ -1,60 +1,155 @@
 use std::collections::BTreeSet;
 use std::path::PathBuf;
 
+use crate::book::Role;
 use crate::book::builder::BookBuilder;
 use crate::book::config::BookConfig;
+use crate::book::placeholder::find_placeholders;
+use crate::book::segment::{SegmentKind, SplitOptions, split_segments};
+use crate::i18n::tr;
+
+use super::{detect, generate::name_from_scripts, workspace};
 use crate::output::msg as msg_out;
 use clap::{Parser, Subcommand};
-use sci_resources::{ResourceType, file::open_game_resources, types::msg::parse_message_resource};
+use itertools::Itertools;
+use sci_resources::{
+    ResourceId, ResourceType, file::Resource, types::msg::parse_message_resource_with_code_page,
+};
+use sci_utils::{
+    block::{LazyBlock, MemBlock},
+    encoding::CodePage,
+    fs,
+};
 
 // My current theory is that messages are separatable into a few categories:
 
 #[derive(Parser)]
 struct ExportMessages {
+    /// The game directory. Defaults to the project's (see `--project`).
     #[clap(index = 1)]
-    root_dir: PathBuf,
+    root_dir: Option<PathBuf>,
+    /// The code page of the message text. Defaults to the platform's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+    /// The book config. Lines it adds to the game are exported too, marked
+    /// as added.
+    #[clap(long = "config")]
+    config_path: Option<PathBuf>,
     #[clap(short = 'o', long)]
     output: PathBuf,
+    #[clap(flatten)]
+    split: SplitOptions,
 }
 
 impl ExportMessages {
     fn run(&self) -> anyhow::Result<()> {
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let config = workspace::optional_config(self.config_path.as_deref())?
+            .map(|path| -> anyhow::Result<BookConfig> {
+                Ok(serde_yml::from_reader(std::fs::File::open(path)?)?)
+            })
+            .transpose()?;
+        let code_page = self
+            .code_page
+            .or(config.as_ref().and_then(BookConfig::code_page));
+        let mut builder = config.map(BookBuilder::new).transpose()?;
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
         let mut messages = Vec::new();
         for res in resource_set.resources_of_type(ResourceType::Message) {
-            let msg_resources = parse_message_resource(res.load_data()?)?;
+            let msg_resources = parse_message_resource_with_code_page(res.load_data()?, code_page)?;
             for (msg_id, record) in msg_resources.messages() {
-                let message_id = {
+                if let Some(builder) = &mut builder {
+                    builder.add_message(res.id().resource_num(), msg_id, record)?;
+                }
+                messages.push(self.to_output_message(
                     msg_out::MessageId {
                         room: res.id().resource_num(),
                         noun: msg_id.noun(),
                         verb: msg_id.verb(),
                         condition: msg_id.condition(),
                         sequence: msg_id.sequence(),
-                    }
-                };
-                let message = msg_out::Message {
-                    id: message_id,
-                    talker: record.talker(),
-                    text: record.text().to_string(),
-                };
+                    },
+                    record.talker(),
+                    record.text(),
+                ));
+            }
+        }
+        if let Some(builder) = builder {
+            let book = builder.build()?;
+            for line in book.lines().filter(|line| line.is_added()) {
+                let id = line.id();
+                let mut message = self.to_output_message(
+                    msg_out::MessageId {
+                        room: id.room_num(),
+                        noun: id.noun_num(),
+                        verb: id.verb_num(),
+                        condition: id.condition_num(),
+                        sequence: id.sequence_num(),
+                    },
+                    line.talker().id().talker_num(),
+                    line.text(),
+                );
+                message.added = true;
                 messages.push(message);
             }
         }
 
-        eprintln!("Writing {:?} messages to {:?}", messages.len(), self.output);
+        eprintln!(
+            "{}",
+            tr!(
+                "writing-messages",
+                count = messages.len(),
+                path = format!("{:?}", self.output)
+            )
+        );
 
         let msg_file = msg_out::MessageFile { messages };
         let writer = std::fs::File::create(&self.output)?;
         serde_json::to_writer_pretty(writer, &msg_file)?;
         Ok(())
     }
+
+    fn to_output_message(
+        &self,
+        id: msg_out::MessageId,
+        talker: u8,
+        text: &str,
+    ) -> msg_out::Message {
+        let segments = split_segments(text, &self.split);
+        msg_out::Message {
+            id,
+            talker,
+            text: text.to_string(),
+            placeholders: find_placeholders(text)
+                .iter()
+                .map(|p| p.token().to_string())
+                .collect(),
+            // A single speech segment is just the text.
+            segments: if segments.len() > 1
+                || segments.iter().any(|s| s.kind() != SegmentKind::Speech)
+            {
+                segments
+                    .iter()
+                    .map(|s| msg_out::Segment {
+                        kind: s.kind(),
+                        text: s.text().to_string(),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            added: false,
+        }
+    }
 }
 
 #[derive(Parser)]
 struct PrintMessages {
+    /// The game directory. Defaults to the project's (see `--project`).
     #[clap(index = 1)]
-    root_dir: PathBuf,
+    root_dir: Option<PathBuf>,
+    /// The code page of the message text. Defaults to the platform's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
     #[clap(long = "config")]
     config_path: Option<PathBuf>,
     #[clap(short = 't', long, required = false)]
@@ -73,46 +168,47 @@ struct PrintMessages {
 
 impl PrintMessages {
     fn run(&self) -> anyhow::Result<()> {
-        if let Some(config_path) = &self.config_path {
-            let config: BookConfig = serde_yml::from_reader(std::fs::File::open(config_path)?)?;
+        if let Some(config_path) = workspace::optional_config(self.config_path.as_deref())? {
+            let config: BookConfig = serde_yml::from_reader(std::fs::File::open(&config_path)?)?;
             eprintln!("Loaded config from {:?}: {:?}", config_path, config);
         }
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
 
         // Extra testing for building a conversation.
 
         for res in resource_set.resources_of_type(ResourceType::Message) {
-            let msg_resources = parse_message_resource(res.load_data()?)?;
+            let msg_resources =
+                parse_message_resource_with_code_page(res.load_data()?, self.code_page)?;
             for (msg_id, record) in msg_resources.messages() {
-                if let Some(room) = self.room {
-                    if res.id().resource_num() != room {
-                        continue;
-                    }
+                if let Some(room) = self.room
+                    && res.id().resource_num() != room
+                {
+                    continue;
                 }
-                if let Some(talker) = self.talker {
-                    if record.talker() != talker {
-                        continue;
-                    }
+                if let Some(talker) = self.talker
+                    && record.talker() != talker
+                {
+                    continue;
                 }
-                if let Some(verb) = self.verb {
-                    if msg_id.verb() != verb {
-                        continue;
-                    }
+                if let Some(verb) = self.verb
+                    && msg_id.verb() != verb
+                {
+                    continue;
                 }
-                if let Some(noun) = self.noun {
-                    if msg_id.noun() != noun {
-                        continue;
-                    }
+                if let Some(noun) = self.noun
+                    && msg_id.noun() != noun
+                {
+                    continue;
                 }
-                if let Some(condition) = self.condition {
-                    if msg_id.condition() != condition {
-                        continue;
-                    }
+                if let Some(condition) = self.condition
+                    && msg_id.condition() != condition
+                {
+                    continue;
                 }
-                if let Some(sequence) = self.sequence {
-                    if msg_id.sequence() != sequence {
-                        continue;
-                    }
+                if let Some(sequence) = self.sequence
+                    && msg_id.sequence() != sequence
+                {
+                    continue;
                 }
                 println!(
                     "(room: {:?}, n: {:?}, v: {:?}, c: {:?}, s: {:?}, t: {:?}):",
@@ -133,48 +229,84 @@ impl PrintMessages {
 
 #[derive(Parser)]
 struct CheckMessages {
+    /// The game directory. Defaults to the project's (see `--project`).
     #[clap(index = 1)]
-    root_dir: PathBuf,
+    root_dir: Option<PathBuf>,
+    /// The code page of the message text. Overrides the config.
+    #[clap(long)]
+    code_page: Option<CodePage>,
     #[clap(long = "config")]
     config_path: Option<PathBuf>,
 }
 
 impl CheckMessages {
     fn run(&self) -> anyhow::Result<()> {
-        let config = if let Some(config_path) = &self.config_path {
-            let config: BookConfig = serde_yml::from_reader(std::fs::File::open(config_path)?)?;
-            eprintln!("Loaded config from {:?}", config_path);
+        let config = if let Some(config_path) =
+            workspace::optional_config(self.config_path.as_deref())?
+        {
+            let config: BookConfig = serde_yml::from_reader(std::fs::File::open(&config_path)?)?;
+            eprintln!(
+                "{}",
+                tr!("loaded-config", path = format!("{config_path:?}"))
+            );
             config
         } else {
             BookConfig::default()
         };
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
+        let code_page = self.code_page.or(config.code_page());
         let mut builder = BookBuilder::new(config)?;
 
         // Extra testing for building a conversation.
 
         for res in resource_set.resources_of_type(ResourceType::Message) {
-            let msg_resources = parse_message_resource(res.load_data()?)?;
+            let msg_resources = parse_message_resource_with_code_page(res.load_data()?, code_page)?;
             for (msg_id, record) in msg_resources.messages() {
                 builder.add_message(res.id().resource_num(), msg_id, record)?;
             }
         }
+        name_from_scripts(&mut builder, &resource_set);
         let book = builder.build()?;
 
-        eprintln!("Num rooms: {}", book.rooms().count());
-        eprintln!("Num nouns: {}", book.nouns().count());
-        eprintln!("Num conversations: {}", book.conversations().count());
+        eprintln!("{}", tr!("check-rooms", count = book.rooms().count()));
+        eprintln!("{}", tr!("check-nouns", count = book.nouns().count()));
         eprintln!(
-            "Num multi-line conversations: {}",
-            book.conversations()
-                .filter(|c| c.lines().count() > 1)
-                .count()
+            "{}",
+            tr!("check-conversations", count = book.conversations().count())
         );
-        eprintln!("Num lines: {}", book.lines().count());
+        let num_multi_line = book
+            .conversations()
+            .filter(|c| c.lines().count() > 1)
+            .count();
         eprintln!(
-            "Num empty lines: {}",
-            book.lines().filter(|line| line.text().is_empty()).count()
+            "{}",
+            tr!("check-multi-line-conversations", count = num_multi_line)
         );
+        eprintln!("{}", tr!("check-lines", count = book.lines().count()));
+        let num_empty = book
+            .lines()
+            .filter(|line| line.text().is_empty() && line.ref_target().is_none())
+            .count();
+        eprintln!("{}", tr!("check-empty-lines", count = num_empty));
+
+        let mut num_ref_lines = 0;
+        for line in book.lines() {
+            if line.ref_target().is_none() {
+                continue;
+            }
+            num_ref_lines += 1;
+            if line.resolved_lines()?.is_empty() {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "check-dangling-reference",
+                        line = format!("{:?}", line.id()),
+                        target = format!("{:?}", line.ref_target())
+                    )
+                );
+            }
+        }
+        eprintln!("{}", tr!("check-reference-lines", count = num_ref_lines));
 
         for conversation in book.conversations() {
             if let Err(e) = conversation.validate_complete() {
@@ -182,9 +314,65 @@ impl CheckMessages {
             }
         }
 
+        let mut num_placeholder_lines = 0;
+        for line in book.lines() {
+            let placeholders = line.placeholders();
+            if placeholders.is_empty() {
+                continue;
+            }
+            num_placeholder_lines += 1;
+            eprintln!(
+                "{}",
+                tr!(
+                    "check-placeholder-line",
+                    line = format!("{:?}", line.id()),
+                    placeholders = placeholders.iter().map(|p| p.token()).join(", "),
+                    text = format!("{:?}", line.text())
+                )
+            );
+        }
+        eprintln!(
+            "{}",
+            tr!("check-placeholder-lines", count = num_placeholder_lines)
+        );
+
+        // Narrators usually speak far more than anyone else, so they're
+        // counted apart from the cast.
+        let spoken_lines: Vec<_> = book
+            .lines()
+            .filter(|line| line.ref_target().is_none())
+            .collect();
+        let (narrators, cast): (Vec<_>, Vec<_>) = book.roles().partition(|role| role.is_narrator());
+        let count_lines = |role: &Role| {
+            spoken_lines
+                .iter()
+                .filter(|line| line.role().id() == role.id())
+                .count()
+        };
+        eprintln!(
+            "{}",
+            tr!(
+                "check-narrator-lines",
+                count = narrators.iter().map(count_lines).sum::<usize>()
+            )
+        );
+        eprintln!(
+            "{}",
+            tr!(
+                "check-character-lines",
+                count = cast.iter().map(count_lines).sum::<usize>()
+            )
+        );
+        for role in &cast {
+            eprintln!("  {}: {}", role.name(), count_lines(role));
+        }
+
         for room in book.rooms() {
-            eprintln!("Room {:?}:", room.name(),);
-            eprintln!("  Num Conditions: {}", room.conditions().count());
+            eprintln!("{}", tr!("check-room", room = format!("{:?}", room.name())));
+            eprintln!(
+                "  {}",
+                tr!("check-room-conditions", count = room.conditions().count())
+            );
         }
         Ok(())
     }
@@ -192,16 +380,21 @@ impl CheckMessages {
 
 #[derive(Parser)]
 struct PrintTalkers {
+    /// The game directory. Defaults to the project's (see `--project`).
     #[clap(index = 1)]
-    root_dir: PathBuf,
+    root_dir: Option<PathBuf>,
+    /// The code page of the message text. Defaults to the platform's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
 }
 
 impl PrintTalkers {
     fn run(&self) -> anyhow::Result<()> {
-        let resource_set = open_game_resources(&self.root_dir)?;
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
         let mut talkers = BTreeSet::new();
         for res in resource_set.resources_of_type(ResourceType::Message) {
-            let msg_resources = parse_message_resource(res.load_data()?)?;
+            let msg_resources =
+                parse_message_resource_with_code_page(res.load_data()?, self.code_page)?;
             for (_, record) in msg_resources.messages() {
                 talkers.insert(record.talker());
             }
@@ -219,12 +412,248 @@ impl PrintTalkers {
     }
 }
 
+/// The type byte that starts a message patch file.
+const MESSAGE_PATCH_TYPE: u8 = ResourceType::Message as u8;
+
+/// Prints every record of a single message resource as stored, without
+/// building a book. Useful for debugging the format and checking edited
+/// patches.
+#[derive(Parser)]
+struct DumpMessages {
+    /// A message patch file (e.g. `100.msg`), or a game directory, with
+    /// `--room`.
+    #[clap(index = 1)]
+    path: PathBuf,
+    /// The room whose messages to dump, when the path is a game directory.
+    #[clap(short = 'r', long)]
+    room: Option<u16>,
+    /// The code page of the message text. Defaults to the platform's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+}
+
+impl DumpMessages {
+    fn load(&self) -> anyhow::Result<MemBlock> {
+        if self.path.is_dir() {
+            let Some(room) = self.room else {
+                anyhow::bail!("--room is required when dumping from a game directory");
+            };
+            let resource_set = detect::open_game(&self.path)?;
+            let res = resource_set
+                .get_resource(&ResourceId::new(ResourceType::Message, room))
+                .ok_or_else(|| anyhow::anyhow!("Room {room} has no message resource"))?;
+            return res.load_data();
+        }
+        let data = std::fs::read(&self.path)?;
+        // Patch files start with the resource type and the size of any
+        // extra header; a bare resource starts with its version.
+        match data.as_slice() {
+            [MESSAGE_PATCH_TYPE, header_size, ..] => {
+                let start = 2 + *header_size as usize;
+                anyhow::ensure!(start <= data.len(), "Patch header is truncated");
+                Ok(MemBlock::from_vec(data[start..].to_vec()))
+            }
+            _ => Ok(MemBlock::from_vec(data)),
+        }
+    }
+
+    fn run(&self) -> anyhow::Result<()> {
+        let messages = parse_message_resource_with_code_page(self.load()?, self.code_page)?;
+        println!(
+            "Version {} ({:?} endian), {} messages",
+            messages.version(),
+            messages.endian(),
+            messages.messages().count()
+        );
+        for (id, record) in messages.messages() {
+            let ref_target = match record.ref_target() {
+                Some(target) => format!(
+                    "({}, {}, {})",
+                    target.noun(),
+                    target.verb(),
+                    target.condition()
+                ),
+                None => "-".to_string(),
+            };
+            println!(
+                "({}, {}, {}, {}) talker: {} ref: {} unknown: {} {:?}",
+                id.noun(),
+                id.verb(),
+                id.condition(),
+                id.sequence(),
+                record.talker(),
+                ref_target,
+                record.unknown(),
+                record.text(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A range of room numbers, written like a Rust range: `90..150` (not
+/// including 150), `90..=150`, `90..` or just `90`.
+#[derive(Debug, Clone, Copy)]
+struct RoomRange {
+    start: u16,
+    /// The last room in the range, if it has one.
+    last: Option<u16>,
+}
+
+impl RoomRange {
+    fn contains(&self, room: u16) -> bool {
+        room >= self.start && self.last.is_none_or(|last| room <= last)
+    }
+}
+
+impl std::str::FromStr for RoomRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((start, end)) = s.split_once("..") else {
+            let room = s.parse()?;
+            return Ok(RoomRange {
+                start: room,
+                last: Some(room),
+            });
+        };
+        let start = if start.is_empty() { 0 } else { start.parse()? };
+        let last = if let Some(last) = end.strip_prefix('=') {
+            Some(last.parse()?)
+        } else if end.is_empty() {
+            None
+        } else {
+            let end: u16 = end.parse()?;
+            Some(
+                end.checked_sub(1)
+                    .ok_or_else(|| anyhow::anyhow!("The range {s:?} is empty"))?,
+            )
+        };
+        Ok(RoomRange { start, last })
+    }
+}
+
+/// Replaces text in every message line matching a regular expression, and
+/// writes a patch for each room with changed lines. Prints each change as a
+/// diff, so the replacement can be checked before the patches are used.
+#[derive(Parser)]
+struct ReplaceMessages {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[clap(index = 1)]
+    root_dir: Option<PathBuf>,
+    /// The regular expression to find.
+    #[clap(long)]
+    pattern: regex::Regex,
+    /// What to replace each match with. `$1`, `$name` and so on are replaced
+    /// by the match's groups.
+    #[clap(long)]
+    replacement: String,
+    /// Only change the messages of these rooms (e.g. `90..150`).
+    #[clap(long)]
+    rooms: Option<RoomRange>,
+    /// The code page of the message text. Defaults to the platform's.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+    /// Where to write the patches.
+    #[clap(short = 'o', long)]
+    output_dir: PathBuf,
+    /// Print the changes without writing any patches.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+}
+
+impl ReplaceMessages {
+    fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
+        let mut patches = Vec::new();
+        let mut num_changed = 0;
+        for res in resource_set.resources_of_type(ResourceType::Message) {
+            let room = res.id().resource_num();
+            if self.rooms.is_some_and(|rooms| !rooms.contains(room)) {
+                continue;
+            }
+            let mut messages =
+                parse_message_resource_with_code_page(res.load_data()?, self.code_page)?;
+            let mut changed = false;
+            for (id, record) in messages.messages_mut() {
+                let text = self
+                    .pattern
+                    .replace_all(record.text(), self.replacement.as_str());
+                if text == record.text() {
+                    continue;
+                }
+                println!(
+                    "Room {room} ({}, {}, {}, {}):",
+                    id.noun(),
+                    id.verb(),
+                    id.condition(),
+                    id.sequence()
+                );
+                println!("- {}", record.text());
+                println!("+ {text}");
+                let text = text.into_owned();
+                record.set_text(text);
+                changed = true;
+                num_changed += 1;
+            }
+            if changed {
+                patches.push(Resource::new(
+                    *res.id(),
+                    LazyBlock::from_mem_block(MemBlock::from_vec(
+                        messages.to_bytes(self.code_page)?,
+                    )),
+                ));
+            }
+        }
+        if patches.is_empty() {
+            eprintln!("{}", tr!("replace-no-matches"));
+            return Ok(());
+        }
+        eprintln!(
+            "{}",
+            tr!(
+                "replace-summary",
+                lines = num_changed,
+                rooms = patches.len()
+            )
+        );
+        if self.dry_run {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.output_dir)?;
+        for patch in &patches {
+            let Some(patch_name) = patch.patch_file_name() else {
+                continue;
+            };
+            let filename = self.output_dir.join(patch_name);
+            eprintln!(
+                "{}",
+                tr!(
+                    "writing-patch",
+                    id = format!("{:?}", patch.id()),
+                    path = format!("{filename:?}")
+                )
+            );
+            let mut pending = fs::PendingFile::create(&filename)?;
+            patch.write_patch_sync(pending.file())?;
+            pending.commit(&fs::WriteOptions {
+                on_locked: Some(&fs::ask_retry_locked),
+                ..fs::WriteOptions::default()
+            })?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Subcommand)]
 enum MessageCommand {
     Export(ExportMessages),
     Print(PrintMessages),
     Check(CheckMessages),
     PrintTalkers(PrintTalkers),
+    Dump(DumpMessages),
+    Replace(ReplaceMessages),
 }
 
 #[derive(Parser)]
@@ -240,6 +669,8 @@ impl Messages {
             MessageCommand::Print(cmd) => cmd.run()?,
             MessageCommand::Check(cmd) => cmd.run()?,
             MessageCommand::PrintTalkers(cmd) => cmd.run()?,
+            MessageCommand::Dump(cmd) => cmd.run()?,
+            MessageCommand::Replace(cmd) => cmd.run()?,
         }
         Ok(())
     }
@@ -0,0 +1,185 @@
+//! Exporting a pic's visual layer as a PNG, and replacing it with an edited
+//! one, e.g. to translate text painted into a background. The pic's vector
+//! data, which draws its priority and control layers, is kept as it is.
+//!
+//! Pics are also shrunk into thumbnails, to show each room's background in
+//! generated scripts.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::ResourceSet,
+    types::{
+        palette::Palette,
+        pic::Pic,
+        quantize::{Dithering, QuantizeOptions, quantize},
+        view::Cel,
+    },
+};
+use sci_utils::block::MemBlock;
+
+use super::{
+    detect,
+    view::{global_palette, load_resource, read_png, write_patch, write_png},
+};
+use crate::{generate::doc::Thumbnail, i18n::tr};
+
+/// Loads a pic from the game, with the palette it's drawn in.
+fn load_pic(resource_set: &ResourceSet, pic_num: u16) -> anyhow::Result<(MemBlock, Palette)> {
+    let data = load_resource(resource_set, &ResourceId::new(ResourceType::Pic, pic_num))?;
+    let mut palette = global_palette(resource_set)?;
+    if let Some(pic_palette) = Pic::parse(&data)?.palette()? {
+        palette.overlay(&pic_palette);
+    }
+    Ok((data, palette))
+}
+
+fn visual(pic: &Pic, pic_num: u16) -> anyhow::Result<Cel> {
+    pic.visual()?
+        .ok_or_else(|| anyhow::anyhow!(tr!("pic-no-visual", pic = pic_num)))
+}
+
+/// Renders a pic's visual layer as a thumbnail no wider than `max_width`,
+/// averaging each square of pixels it shrinks into one. Pics the game
+/// doesn't have, or that have no visual layer, have no thumbnail.
+pub(super) fn pic_thumbnail(
+    resource_set: &ResourceSet,
+    pic_num: u16,
+    max_width: u16,
+) -> anyhow::Result<Option<Thumbnail>> {
+    if resource_set
+        .get_resource(&ResourceId::new(ResourceType::Pic, pic_num))
+        .is_none()
+    {
+        return Ok(None);
+    }
+    let (data, palette) = load_pic(resource_set, pic_num)?;
+    let Some(cel) = Pic::parse(&data)?.visual()? else {
+        return Ok(None);
+    };
+    let scale = cel.width.div_ceil(max_width.max(1)).max(1);
+    let width = cel.width.div_ceil(scale);
+    let height = cel.height.div_ceil(scale);
+    let mut rgb = Vec::with_capacity(usize::from(width) * usize::from(height) * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for cel_y in y * scale..((y + 1) * scale).min(cel.height) {
+                for cel_x in x * scale..((x + 1) * scale).min(cel.width) {
+                    let index = cel.pixels
+                        [usize::from(cel_y) * usize::from(cel.width) + usize::from(cel_x)];
+                    // Colors missing from the palette are drawn black.
+                    let color = palette.color(index).unwrap_or_default();
+                    for (total, component) in sum.iter_mut().zip(color) {
+                        *total += u32::from(component);
+                    }
+                    count += 1;
+                }
+            }
+            rgb.extend(sum.map(|total| (total / count) as u8));
+        }
+    }
+    Ok(Some(Thumbnail { width, height, rgb }))
+}
+
+/// Writes the visual layer of a pic as a PNG, for editing and `import-pic`.
+#[derive(Parser)]
+pub(super) struct ExportPic {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    pic: u16,
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl ExportPic {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let (data, palette) = load_pic(&resource_set, self.pic)?;
+        let cel = visual(&Pic::parse(&data)?, self.pic)?;
+        write_png(&self.output, &cel, &palette)?;
+        eprintln!(
+            "{}",
+            tr!(
+                "pic-exported",
+                pic = self.pic,
+                path = format!("{:?}", self.output)
+            )
+        );
+        Ok(())
+    }
+}
+
+/// Builds a pic patch with its visual layer replaced by an edited PNG, the
+/// same size as the one `export-pic` wrote. The pic's priority and control
+/// layers are kept.
+#[derive(Parser)]
+pub(super) struct ImportPic {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    pic: u16,
+    #[clap(index = 3)]
+    input: PathBuf,
+    /// Where to write the patch. Defaults to the game directory.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+    /// How to approximate colors that aren't in the palette.
+    #[clap(long, value_enum, default_value_t = Dithering::None)]
+    dither: Dithering,
+}
+
+impl ImportPic {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let (data, palette) = load_pic(&resource_set, self.pic)?;
+        let pic = Pic::parse(&data)?;
+        let original = visual(&pic, self.pic)?;
+        anyhow::ensure!(
+            palette.nearest([0; 3], None).is_some(),
+            tr!("view-no-palette")
+        );
+        let (width, height, rgba) = read_png(&self.input)?;
+        anyhow::ensure!(
+            (width, height) == (original.width, original.height),
+            tr!(
+                "pic-size-mismatch",
+                path = format!("{:?}", self.input),
+                width = width,
+                height = height,
+                expected_width = original.width,
+                expected_height = original.height
+            )
+        );
+        let options = QuantizeOptions {
+            dithering: self.dither,
+            ..QuantizeOptions::new(original.clear_key)
+        };
+        let quantized = quantize(&rgba, usize::from(width), &palette, &options)?;
+        let error = quantized.error;
+        if error.approximated() > 0 {
+            eprintln!(
+                "{}",
+                tr!(
+                    "view-colors-approximated",
+                    count = error.approximated(),
+                    mean = format!("{:.1}", error.mean),
+                    max = format!("{:.1}", error.max)
+                )
+            );
+        }
+        let pic_data = pic.with_visual(&Cel {
+            pixels: quantized.pixels,
+            ..original
+        })?;
+        write_patch(
+            self.output_dir.as_deref().unwrap_or(&self.root_dir),
+            ResourceId::new(ResourceType::Pic, self.pic),
+            pic_data,
+        )
+    }
+}
@@ -0,0 +1,208 @@
+//! `scitool recast`: starts a role over with a new actor.
+//!
+//! Every take of the role's lines is moved out of the way, into
+//! `.recast/<role>-<n>/` in the sample directory, along with the lines'
+//! entries from `samples.json` (their selections, trim points and locks).
+//! Hidden directories aren't searched for takes, so the lines are left
+//! unrecorded. The role's script bundle is written again for the new actor,
+//! and the patches that need rebuilding are listed.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use sci_resources::{ResourceType, types::msg::MessageId};
+use scitool_fan_dub_cli::{
+    report::BuildReport,
+    resources::{SampleDir, sample_key},
+};
+
+use super::{
+    generate::{BundleArgs, CommonArgs, find_role, load_book},
+    workspace,
+};
+use crate::{book::LineId, i18n::tr};
+
+/// The directory of archived takes, in the sample directory.
+const ARCHIVE_DIR: &str = ".recast";
+
+fn message_id(line_id: &LineId) -> MessageId {
+    MessageId::new(
+        line_id.noun_num(),
+        line_id.verb_num(),
+        line_id.condition_num(),
+        line_id.sequence_num(),
+    )
+}
+
+/// An unused archive directory for the role, relative to the sample
+/// directory.
+fn archive_dir(sample_dir: &Path, short_name: &str) -> PathBuf {
+    let name: String = short_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    (1..)
+        .map(|n| Path::new(ARCHIVE_DIR).join(format!("{name}-{n}")))
+        .find(|dir| !sample_dir.join(dir).exists())
+        .expect("Archive numbers are unbounded")
+}
+
+/// The patch files holding the lines, from the last build's report if there
+/// is one, or by room otherwise. The audio volume holds them all.
+fn affected_patches(
+    output_dir: Option<&Path>,
+    lines: &BTreeSet<(u16, MessageId)>,
+) -> anyhow::Result<BTreeSet<String>> {
+    let report = match output_dir {
+        Some(output_dir) => BuildReport::load(output_dir)?,
+        None => None,
+    };
+    Ok(match report {
+        Some(report) => {
+            let mut patches: BTreeSet<_> = report
+                .lines
+                .iter()
+                .filter(|line| lines.contains(&(line.room, line.message_id)))
+                .map(|line| line.patch.clone())
+                .collect();
+            if !patches.is_empty() {
+                patches.insert(report.audio_volume);
+            }
+            patches
+        }
+        None => lines
+            .iter()
+            .map(|(room, _)| format!("{room}.{}", ResourceType::Map.to_file_ext()))
+            .collect(),
+    })
+}
+
+/// Archives all takes of a role's lines, so it can be recorded again by a
+/// new actor, and writes the role's script bundle for them.
+#[derive(Parser)]
+pub(super) struct Recast {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+
+    /// The role, by name or short name.
+    #[clap(long)]
+    role: String,
+
+    /// The sample directory. Defaults to the project's.
+    #[clap(short = 's', long)]
+    sample_dir: Option<PathBuf>,
+
+    /// The build output directory, to find the patches holding the role's
+    /// lines from the last build's report.
+    #[clap(short = 'o', long)]
+    output: Option<PathBuf>,
+
+    /// Where to write the role's script bundle.
+    #[clap(long, default_value = "bundles")]
+    bundle_dir: PathBuf,
+
+    #[clap(flatten)]
+    bundle: BundleArgs,
+
+    /// Recast lines that are locked, too.
+    #[clap(long)]
+    force: bool,
+}
+
+impl Recast {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let book = load_book(&self.ctxt)?;
+        let role = find_role(&book, &self.role)?;
+        let sample_dir_path = workspace::sample_dir(self.sample_dir.as_deref())?;
+        let mut samples = smol::block_on(SampleDir::load_dir(&sample_dir_path))?;
+
+        let lines: BTreeSet<(u16, MessageId)> = book
+            .lines()
+            .filter(|line| line.role().id() == role.id())
+            .map(|line| {
+                let id = line.id();
+                (id.room_num(), message_id(&id))
+            })
+            .collect();
+        let locked: Vec<_> = lines
+            .iter()
+            .filter(|(room, message_id)| samples.is_locked(*room, message_id))
+            .map(|(room, message_id)| sample_key(*room, message_id))
+            .collect();
+        if !locked.is_empty() && !self.force {
+            anyhow::bail!(tr!(
+                "recast-locked",
+                role = role.name(),
+                lines = locked.join(", ")
+            ));
+        }
+
+        let mut takes = BTreeSet::new();
+        let mut recorded = BTreeSet::new();
+        for (room, message_id) in &lines {
+            let line_takes = samples.find_takes(*room, message_id)?;
+            let sample = samples.sample(*room, message_id);
+            if !line_takes.is_empty() || sample.is_some() {
+                recorded.insert((*room, *message_id));
+            }
+            takes.extend(line_takes);
+            if let Some(sample) = sample {
+                let path = sample.clip_path(&sample_dir_path)?;
+                if path.is_file() {
+                    takes.insert(path.strip_prefix(&sample_dir_path)?.to_path_buf());
+                }
+            }
+        }
+
+        if recorded.is_empty() {
+            println!("{}", tr!("recast-nothing", role = role.name()));
+        } else {
+            let archive = archive_dir(&sample_dir_path, role.short_name());
+            let abs_archive = sample_dir_path.join(&archive);
+            std::fs::create_dir_all(&abs_archive)?;
+            let archived_samples: Vec<_> = recorded
+                .iter()
+                .filter_map(|(room, message_id)| samples.remove_sample(*room, message_id))
+                .collect();
+            std::fs::write(
+                abs_archive.join("samples.json"),
+                serde_json::to_vec_pretty(&archived_samples)?,
+            )?;
+            for take in &takes {
+                let to = abs_archive.join(take);
+                if let Some(parent) = to.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(sample_dir_path.join(take), to)?;
+            }
+            smol::block_on(samples.save())?;
+            println!(
+                "{}",
+                tr!(
+                    "recast-archived",
+                    takes = takes.len(),
+                    lines = recorded.len(),
+                    path = abs_archive.display().to_string()
+                )
+            );
+            let patches = affected_patches(self.output.as_deref(), &recorded)?;
+            if !patches.is_empty() {
+                println!(
+                    "{}",
+                    tr!(
+                        "recast-patches",
+                        patches = patches.into_iter().collect::<Vec<_>>().join(", ")
+                    )
+                );
+            }
+        }
+
+        let pictures = self.bundle.pictures(&self.ctxt.root_dir()?, &book)?;
+        std::fs::create_dir_all(&self.bundle_dir)?;
+        self.bundle
+            .write_bundle(&book, role, &pictures, &self.bundle_dir)
+    }
+}
@@ -1,11 +1,23 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
+use sci_resources::{ResourceId, ResourceType, file::Resource};
+use sci_utils::{
+    block::{LazyBlock, MemBlock},
+    encoding::CodePage,
+    fs,
+};
+use scitool_script_loader::{ScummVmSave, SymbolFile};
+use serde::Serialize;
+
+use super::{detect, workspace};
+use crate::{book::config::BookConfig, i18n::tr};
 
 #[derive(Parser)]
 struct GenerateHeaders {
+    /// The game directory. Defaults to the project's (see `--project`).
     #[arg(short = 'd')]
-    game_dir: PathBuf,
+    game_dir: Option<PathBuf>,
     #[arg(short = 'o', long, default_value = ".")]
     out_dir: PathBuf,
     #[arg(short = 's', long, default_value = "selectors.sh")]
@@ -16,7 +28,9 @@ struct GenerateHeaders {
 
 impl GenerateHeaders {
     pub fn run(&self) -> anyhow::Result<()> {
-        let exports = sci_header_gen::SciScriptExports::read_from_resources(&self.game_dir)?;
+        let exports = sci_header_gen::SciScriptExports::read_from_resources(&workspace::game_dir(
+            self.game_dir.as_deref(),
+        )?)?;
 
         let selectors_file = std::fs::File::create(self.out_dir.join(&self.selectors_path))?;
         exports.write_selector_header_to(std::io::BufWriter::new(selectors_file))?;
@@ -28,16 +42,531 @@ impl GenerateHeaders {
     }
 }
 
+#[derive(Serialize)]
+struct RoleFragment {
+    name: String,
+    short_name: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    narrator: bool,
+}
+
+#[derive(Serialize)]
+struct TalkerFragment {
+    id: u8,
+    role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    view: Option<u16>,
+}
+
+/// The roles and talkers to add to a book config.
+#[derive(Serialize, Default)]
+struct ConfigFragment {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    roles: BTreeMap<String, RoleFragment>,
+    talkers: Vec<TalkerFragment>,
+}
+
+/// The role ID for a character's name, e.g. `roger-wilco` for "Roger
+/// Wilco".
+pub(super) fn role_id(character_name: &str) -> String {
+    character_name
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Guesses the role of each talker from the objects that speak for it in the
+/// game's scripts, and prints them as a book config fragment to review and
+/// merge into the config.
+#[derive(Parser)]
+struct GuessTalkers {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[arg(short = 'd')]
+    game_dir: Option<PathBuf>,
+    /// An existing config. Talkers it maps are left out, and roles it has
+    /// are reused rather than added.
+    #[arg(short = 'c', long)]
+    config_path: Option<PathBuf>,
+    /// Where to write the fragment, instead of standard output.
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+impl GuessTalkers {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let config: BookConfig = match &self.config_path {
+            Some(path) => serde_yml::from_reader(std::fs::File::open(path)?)?,
+            None => BookConfig::default(),
+        };
+        let resource_set = detect::open_game(&workspace::game_dir(self.game_dir.as_deref())?)?;
+        let talkers = scitool_script_loader::find_talkers(&resource_set)?;
+
+        let mut fragment = ConfigFragment::default();
+        for (talker, object) in talkers {
+            let Ok(talker) = u8::try_from(talker) else {
+                continue;
+            };
+            if config.has_talker(talker) {
+                continue;
+            }
+            let role = role_id(&object.character_name);
+            if !config.has_role(&role) {
+                fragment
+                    .roles
+                    .entry(role.clone())
+                    .or_insert_with(|| RoleFragment {
+                        name: object.character_name.clone(),
+                        short_name: object.character_name.clone(),
+                        narrator: object.is_narrator,
+                    });
+            }
+            eprintln!(
+                "Talker {talker}: {} ({})",
+                object.character_name, object.object_name
+            );
+            fragment.talkers.push(TalkerFragment {
+                id: talker,
+                role,
+                view: object.view,
+            });
+        }
+        if fragment.talkers.is_empty() {
+            eprintln!("No unmapped talkers found in the scripts");
+            return Ok(());
+        }
+
+        let yaml = format!(
+            "# Guessed from the scripts' findTalker methods. Review before adding to the config.\n{}",
+            serde_yml::to_string(&fragment)?
+        );
+        match &self.output {
+            Some(path) => std::fs::write(path, yaml)?,
+            None => print!("{yaml}"),
+        }
+        Ok(())
+    }
+}
+
+/// Lists the strings in the scripts' heaps, with their offsets, for
+/// `edit-string`. These include interface text that isn't in the message
+/// resources.
+#[derive(Parser)]
+struct ListStrings {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[arg(short = 'd')]
+    game_dir: Option<PathBuf>,
+    /// Only list the strings of this script.
+    #[arg(short = 's', long)]
+    script: Option<u16>,
+    /// The code page of the text. Defaults to DOS US English.
+    #[arg(long)]
+    code_page: Option<CodePage>,
+}
+
+impl ListStrings {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.game_dir.as_deref())?)?;
+        let code_page = self.code_page.unwrap_or_default();
+        for heap in resource_set.resources_of_type(ResourceType::Heap) {
+            let script = heap.id().resource_num();
+            if self.script.is_some_and(|num| num != script) {
+                continue;
+            }
+            let strings = match scitool_script_loader::heap_strings(&heap.load_data()?, code_page) {
+                Ok(strings) => strings,
+                Err(e) => {
+                    eprintln!("Warning: skipping the strings of script {script}: {e}");
+                    continue;
+                }
+            };
+            for string in strings {
+                println!(
+                    "{script}\t{}\t{}\t{:?}",
+                    string.offset, string.len, string.text
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedHeapString {
+    script: u16,
+    offset: usize,
+    /// The object whose property points at the string, if any. Strings
+    /// without an owner are used by the script's code.
+    owner: Option<String>,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct HeapStringFile {
+    strings: Vec<ExportedHeapString>,
+}
+
+/// Exports the strings of every script's heap, with the objects that own
+/// them, so translators can check that no visible text hides outside the
+/// message and text resources.
+#[derive(Parser)]
+pub(super) struct ExportHeapStrings {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[clap(index = 1)]
+    root_dir: Option<PathBuf>,
+    /// The code page of the text. Defaults to DOS US English.
+    #[clap(long)]
+    code_page: Option<CodePage>,
+    #[clap(short = 'o', long)]
+    output: PathBuf,
+}
+
+impl ExportHeapStrings {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.root_dir.as_deref())?)?;
+        let code_page = self.code_page.unwrap_or_default();
+        let mut strings = Vec::new();
+        let mut num_scripts = 0;
+        for heap in resource_set.resources_of_type(ResourceType::Heap) {
+            let script = heap.id().resource_num();
+            let owned =
+                match scitool_script_loader::owned_heap_strings(&heap.load_data()?, code_page) {
+                    Ok(owned) => owned,
+                    Err(e) => {
+                        eprintln!(
+                            "{}",
+                            tr!(
+                                "warning-skipping",
+                                id = format!("{:?}", heap.id()),
+                                error = e.to_string()
+                            )
+                        );
+                        continue;
+                    }
+                };
+            num_scripts += 1;
+            strings.extend(owned.into_iter().map(|owned| ExportedHeapString {
+                script,
+                offset: owned.string.offset,
+                owner: owned.owner,
+                text: owned.string.text,
+            }));
+        }
+        eprintln!(
+            "{}",
+            tr!(
+                "writing-heap-strings",
+                count = strings.len(),
+                scripts = num_scripts,
+                path = format!("{:?}", self.output)
+            )
+        );
+        let writer = std::fs::File::create(&self.output)?;
+        serde_json::to_writer_pretty(writer, &HeapStringFile { strings })?;
+        Ok(())
+    }
+}
+
+/// Replaces a string in a script's heap, and writes the heap as a patch.
+/// Code refers to strings by their offsets, so the new text can't be longer
+/// than the original.
+#[derive(Parser)]
+struct EditString {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[arg(short = 'd')]
+    game_dir: Option<PathBuf>,
+    #[arg(short = 's', long)]
+    script: u16,
+    /// The offset of the string in the heap, as listed by `strings`.
+    #[arg(long)]
+    offset: usize,
+    /// The new text.
+    #[arg(long)]
+    text: String,
+    /// The code page of the text. Defaults to DOS US English.
+    #[arg(long)]
+    code_page: Option<CodePage>,
+    /// Where to write the patch.
+    #[arg(short = 'o', long)]
+    output_dir: PathBuf,
+}
+
+impl EditString {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.game_dir.as_deref())?)?;
+        let id = ResourceId::new(ResourceType::Heap, self.script);
+        let heap = resource_set
+            .get_resource(&id)
+            .ok_or_else(|| anyhow::anyhow!("Script {} has no heap", self.script))?;
+        let mut data = heap.load_data()?.to_vec();
+        scitool_script_loader::replace_heap_string(
+            &mut data,
+            self.offset,
+            &self.text,
+            self.code_page.unwrap_or_default(),
+        )?;
+
+        let patch = Resource::new(id, LazyBlock::from_mem_block(MemBlock::from_vec(data)));
+        let patch_name = patch
+            .patch_file_name()
+            .ok_or_else(|| anyhow::anyhow!("Heap resources can't be written as patches"))?;
+        std::fs::create_dir_all(&self.output_dir)?;
+        let filename = self.output_dir.join(patch_name);
+        let mut pending = fs::PendingFile::create(&filename)?;
+        patch.write_patch_sync(pending.file())?;
+        pending.commit(&fs::WriteOptions {
+            on_locked: Some(&fs::ask_retry_locked),
+            ..fs::WriteOptions::default()
+        })?;
+        eprintln!("Wrote {}", filename.display());
+        Ok(())
+    }
+}
+
+/// Lists the kernel functions that the scripts call, with the number of
+/// call sites and scripts calling each, to check which ones script patches
+/// rely on.
+#[derive(Parser)]
+struct KernelCalls {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[arg(short = 'd')]
+    game_dir: Option<PathBuf>,
+    /// List the calls of each script, rather than the totals for the game.
+    #[arg(long)]
+    by_script: bool,
+    /// A symbol file to name the scripts from, in an extra column when
+    /// listing by script.
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+}
+
+impl KernelCalls {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.game_dir.as_deref())?)?;
+        let calls = scitool_script_loader::find_kernel_calls(&resource_set)?;
+        let names = scitool_script_loader::kernel_names(&resource_set)?;
+        if names.is_none() {
+            eprintln!("Warning: the game has no kernel names (vocab 999); only numbers are listed");
+        }
+        let name = |func: u16| {
+            names
+                .as_ref()
+                .and_then(|names| names.get(usize::from(func)))
+                .map_or("?", String::as_str)
+        };
+        if self.by_script {
+            let symbols = self.symbols.as_deref().map(SymbolFile::load).transpose()?;
+            for (script, counts) in &calls {
+                let script_name = symbols.as_ref().map(|symbols| {
+                    symbols
+                        .script(*script)
+                        .and_then(|symbols| symbols.name.as_deref())
+                        .unwrap_or_default()
+                });
+                for (func, count) in counts {
+                    match script_name {
+                        Some(script_name) => {
+                            println!("{script}\t{script_name}\t{func}\t{}\t{count}", name(*func))
+                        }
+                        None => println!("{script}\t{func}\t{}\t{count}", name(*func)),
+                    }
+                }
+            }
+            return Ok(());
+        }
+        // The call sites and the number of scripts calling each function.
+        let mut totals: BTreeMap<u16, (usize, usize)> = BTreeMap::new();
+        for counts in calls.values() {
+            for (func, count) in counts {
+                let total = totals.entry(*func).or_default();
+                total.0 += count;
+                total.1 += 1;
+            }
+        }
+        for (func, (count, scripts)) in totals {
+            println!("{func}\t{}\t{count}\t{scripts}", name(func));
+        }
+        Ok(())
+    }
+}
+
+/// Prints pseudo-code for a script's methods and procedures, with loops and
+/// conditionals reconstructed from the branches.
+#[derive(Parser)]
+struct Decompile {
+    /// The game directory. Defaults to the project's (see `--project`).
+    #[arg(short = 'd')]
+    game_dir: Option<PathBuf>,
+    #[arg(short = 's', long)]
+    script: u16,
+    /// List the instructions as they are, without structuring them.
+    #[arg(long)]
+    flat: bool,
+    /// A symbol file with names for the script's labels, procedures and
+    /// locals, and notes on its objects.
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+    /// Where to write the pseudo-code, instead of standard output.
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+impl Decompile {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&workspace::game_dir(self.game_dir.as_deref())?)?;
+        let symbols = match &self.symbols {
+            Some(path) => SymbolFile::load(path)?,
+            None => SymbolFile::default(),
+        };
+        let code = scitool_script_loader::decompile_script(
+            &resource_set,
+            self.script,
+            &symbols,
+            self.flat,
+        )?;
+        match &self.output {
+            Some(path) => std::fs::write(path, code)?,
+            None => print!("{code}"),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a code offset, in hex with a `0x` prefix (as the disassembly shows
+/// them) or in decimal.
+fn parse_offset(text: &str) -> Result<u16, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|e| format!("Invalid offset {text:?}: {e}"))
+}
+
+/// Names part of a script in a symbol file, for `decompile` and
+/// `kernel-calls` to show. The file is created if it doesn't exist.
+#[derive(Parser)]
+#[command(group(
+    ArgGroup::new("target")
+        .required(true)
+        .args(["script_name", "local", "label", "object"])
+))]
+struct NameSymbol {
+    /// The symbol file.
+    #[arg(long)]
+    symbols: PathBuf,
+    #[arg(short = 's', long)]
+    script: u16,
+    /// Name the script itself.
+    #[arg(long)]
+    script_name: bool,
+    /// Name a local variable, by index.
+    #[arg(long)]
+    local: Option<u16>,
+    /// Label the code at an offset, in hex with a `0x` prefix or in decimal.
+    /// A label at the start of a procedure names it.
+    #[arg(long, value_parser = parse_offset)]
+    label: Option<u16>,
+    /// Add a note to an object, by its name.
+    #[arg(long)]
+    object: Option<String>,
+    /// The name or note. Removes the existing one if left out.
+    value: Option<String>,
+}
+
+impl NameSymbol {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let mut symbols = SymbolFile::load(&self.symbols)?;
+        let value = self.value.clone();
+        symbols.update_script(self.script, |script| {
+            fn set<K: Ord>(map: &mut BTreeMap<K, String>, key: K, value: Option<String>) {
+                match value {
+                    Some(value) => map.insert(key, value),
+                    None => map.remove(&key),
+                };
+            }
+            if self.script_name {
+                script.name = value;
+            } else if let Some(local) = self.local {
+                set(&mut script.locals, local, value);
+            } else if let Some(label) = self.label {
+                set(&mut script.labels, label, value);
+            } else if let Some(object) = &self.object {
+                set(&mut script.objects, object.clone(), value);
+            }
+        });
+        symbols.save(&self.symbols)
+    }
+}
+
+/// Prints the globals stored in a ScummVM save, one per line with its index
+/// and value, to check the state a conversation's conditions depend on.
+///
+/// The reader hasn't been checked against saves written by ScummVM yet, only
+/// against saves built to the same layout.
+#[derive(Parser)]
+struct SaveGlobals {
+    /// The save file.
+    save: PathBuf,
+    /// A symbol file whose names for script 0's locals name the globals.
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+    /// Only print these globals, by index.
+    #[arg(short = 'g', long = "global")]
+    globals: Vec<u16>,
+}
+
+impl SaveGlobals {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let save = ScummVmSave::read(&std::fs::read(&self.save)?)?;
+        let symbols = match &self.symbols {
+            Some(path) => SymbolFile::load(path)?,
+            None => SymbolFile::default(),
+        };
+        let names = symbols.script(0).map(|script| &script.locals);
+        for (index, value) in (0u16..).zip(save.globals()?) {
+            if !self.globals.is_empty() && !self.globals.contains(&index) {
+                continue;
+            }
+            let name = names
+                .and_then(|names| names.get(&index))
+                .map_or("", String::as_str);
+            println!("{index}\t{value}\t{name}");
+        }
+        Ok(())
+    }
+}
+
 #[derive(Subcommand)]
 enum ScriptCommand {
     #[clap(name = "gen-headers")]
     GenerateHeaders(GenerateHeaders),
+    #[clap(name = "guess-talkers")]
+    GuessTalkers(GuessTalkers),
+    #[clap(name = "strings")]
+    ListStrings(ListStrings),
+    #[clap(name = "edit-string")]
+    EditString(EditString),
+    #[clap(name = "kernel-calls")]
+    KernelCalls(KernelCalls),
+    #[clap(name = "decompile")]
+    Decompile(Decompile),
+    #[clap(name = "name")]
+    NameSymbol(NameSymbol),
+    #[clap(name = "save-globals")]
+    SaveGlobals(SaveGlobals),
 }
 
 impl ScriptCommand {
     fn run(&self) -> anyhow::Result<()> {
         match self {
             ScriptCommand::GenerateHeaders(gen_headers) => gen_headers.run()?,
+            ScriptCommand::GuessTalkers(guess_talkers) => guess_talkers.run()?,
+            ScriptCommand::ListStrings(list_strings) => list_strings.run()?,
+            ScriptCommand::EditString(edit_string) => edit_string.run()?,
+            ScriptCommand::KernelCalls(kernel_calls) => kernel_calls.run()?,
+            ScriptCommand::Decompile(decompile) => decompile.run()?,
+            ScriptCommand::NameSymbol(name_symbol) => name_symbol.run()?,
+            ScriptCommand::SaveGlobals(save_globals) => save_globals.run()?,
         }
         Ok(())
     }
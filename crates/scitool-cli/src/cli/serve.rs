@@ -0,0 +1,514 @@
+//! An HTTP API over the book and the recording progress, so other tools (the
+//! project's web dashboard, the Discord bot) can query them without parsing
+//! game resources or sample directories themselves.
+//!
+//! The book is compiled once at startup. Recording status is read from the
+//! sample directory on each request, so it is always current.
+//!
+//! Routes:
+//!
+//! - `GET /api/book`: the rooms, nouns, conversations and lines of the book,
+//!   with whether each line is recorded.
+//! - `GET /api/lines/{line_id}`: a line, with its takes.
+//! - `GET /api/lines/{line_id}/audio`: the line's selected take.
+//! - `GET /api/status`: recording progress per room and role, and a summary
+//!   of the last build.
+//! - `POST /api/lines/{line_id}/takes`: uploads a take for a line, with the
+//!   audio file as the body. See [`upload`].
+//!
+//! Line IDs are the ones used in generated scripts, e.g. `line-10-1-2-0-1`.
+//!
+//! - `POST /api/verify`, `GET /api/verify?map_sha256=<hex>`: checks a
+//!   player's copy of the game against this one. See [`verify`].
+//!
+//! With `--tokens`, every request must be authenticated (see [`auth`]), and
+//! uploads are accepted. Without it, the API is read-only. Verification
+//! never needs a token.
+//!
+//! With `--verify-only`, only the verification routes are served, and no
+//! book or sample directory is needed, for community support channels to
+//! run against their copy of the game.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    Json, Router,
+    extract::DefaultBodyLimit,
+    extract::{Path as UrlPath, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use clap::Parser;
+use sci_resources::types::msg::MessageId;
+use scitool_fan_dub_cli::{
+    path::LookupPath, report::BuildReport, resources::SampleDir, tools::ffprobe::FfprobeTool,
+    transcript::Transcriber,
+};
+use serde::Serialize;
+
+use super::{
+    generate::{
+        CommonArgs, conversation_id_to_id_string, line_id_to_id_string, load_book,
+        noun_id_to_id_string, room_id_to_id_string,
+    },
+    workspace,
+};
+use crate::book::{Book, Line, LineId};
+
+mod auth;
+mod upload;
+mod verify;
+
+use auth::{Auth, Tokens};
+use upload::{UploadChecks, upload_take};
+use verify::{ExpectedGame, MAX_MAP_BYTES, verify_hash, verify_map};
+
+/// The largest take that can be uploaded.
+const MAX_UPLOAD_BYTES: usize = 200 * 1024 * 1024;
+
+/// Serves the book and recording progress over HTTP.
+#[derive(Parser)]
+pub(super) struct Serve {
+    #[clap(flatten)]
+    ctxt: CommonArgs,
+
+    /// The sample directory, with the takes and `samples.json`. Defaults to
+    /// the project's.
+    #[clap(short = 's', long, conflicts_with = "verify_only")]
+    sample_dir: Option<PathBuf>,
+
+    /// The build output directory, for reporting on the last build.
+    #[clap(short = 'o', long, conflicts_with = "verify_only")]
+    output: Option<PathBuf>,
+
+    /// Serves only the routes for verifying players' copies of the game
+    /// against this one. No book config or sample directory is needed.
+    #[clap(long, conflicts_with = "tokens")]
+    verify_only: bool,
+
+    /// The address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    addr: SocketAddr,
+
+    /// A YAML file listing the actors' API tokens. Requires authentication
+    /// on every request, and enables uploads.
+    #[clap(long)]
+    tokens: Option<PathBuf>,
+
+    /// A speech-to-text command used to check that uploaded takes say their
+    /// line, e.g. "whisper-cli -nt -np -f {input}". It must print the
+    /// transcript to stdout.
+    #[clap(long)]
+    transcriber: Option<String>,
+
+    /// How closely an uploaded take's transcript must match the line's text,
+    /// from 0 to 1.
+    #[clap(long, default_value_t = 0.6)]
+    min_transcript_similarity: f64,
+}
+
+impl Serve {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let expected_game = ExpectedGame::read(&self.ctxt.root_dir()?);
+        if self.verify_only {
+            return self.listen(verify_routes(expected_game?));
+        }
+        let tokens = self.tokens.as_deref().map(Tokens::load).transpose()?;
+        let upload_checks = if tokens.is_some() {
+            let transcriber = self
+                .transcriber
+                .as_deref()
+                .map(|command| {
+                    Transcriber::new(
+                        &command
+                            .split_whitespace()
+                            .map(str::to_string)
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .transpose()?;
+            Some(UploadChecks {
+                ffprobe: FfprobeTool::find(&LookupPath::from_env()).ok_or_else(|| {
+                    anyhow::anyhow!("ffprobe not found in PATH; it is needed to check uploads")
+                })?,
+                transcriber,
+                min_similarity: self.min_transcript_similarity,
+            })
+        } else {
+            eprintln!("No --tokens given; serving read-only, without authentication.");
+            None
+        };
+        let state = Arc::new(ServerState {
+            book: load_book(&self.ctxt)?,
+            sample_dir: workspace::sample_dir(self.sample_dir.as_deref())?,
+            output_dir: self.output.clone(),
+            tokens,
+            upload_checks,
+            samples_lock: tokio::sync::Mutex::new(()),
+        });
+        let app = Router::new()
+            .route("/api/book", get(get_book))
+            .route("/api/lines/{line_id}", get(get_line))
+            .route("/api/lines/{line_id}/audio", get(get_line_audio))
+            .route(
+                "/api/lines/{line_id}/takes",
+                post(upload_take).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+            )
+            .route("/api/status", get(get_status))
+            .with_state(state);
+        let app = match expected_game {
+            Ok(expected_game) => app.merge(verify_routes(expected_game)),
+            Err(e) => {
+                eprintln!("Warning: not serving /api/verify: {e}");
+                app
+            }
+        };
+        self.listen(app)
+    }
+
+    fn listen(&self, app: Router) -> anyhow::Result<()> {
+        tokio::runtime::Runtime::new()?.block_on(async {
+            let listener = tokio::net::TcpListener::bind(self.addr).await?;
+            eprintln!("Listening on http://{}", listener.local_addr()?);
+            axum::serve(listener, app).await?;
+            Ok(())
+        })
+    }
+}
+
+fn verify_routes(expected_game: ExpectedGame) -> Router {
+    Router::new()
+        .route(
+            "/api/verify",
+            get(verify_hash)
+                .post(verify_map)
+                .layer(DefaultBodyLimit::max(MAX_MAP_BYTES)),
+        )
+        .with_state(Arc::new(expected_game))
+}
+
+struct ServerState {
+    book: Book,
+    sample_dir: PathBuf,
+    output_dir: Option<PathBuf>,
+    tokens: Option<Tokens>,
+    upload_checks: Option<UploadChecks>,
+    /// Held while changing the sample directory.
+    samples_lock: tokio::sync::Mutex<()>,
+}
+
+impl ServerState {
+    async fn load_samples(&self) -> Result<SampleDir, ApiError> {
+        SampleDir::load_dir(&self.sample_dir)
+            .await
+            .map_err(ApiError::internal)
+    }
+
+    fn find_line(&self, line_id: &str) -> Result<Line<'_>, ApiError> {
+        self.book
+            .lines()
+            .find(|line| line_id_to_id_string(line.id()) == line_id)
+            .ok_or_else(|| ApiError::not_found(format!("No line with ID {line_id}")))
+    }
+}
+
+/// An error response, sent as `{"error": "..."}`.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: String) -> Self {
+        ApiError { status, message }
+    }
+
+    fn not_found(message: String) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, message)
+    }
+
+    fn unauthorized(message: String) -> Self {
+        ApiError::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    fn forbidden(message: String) -> Self {
+        ApiError::new(StatusCode::FORBIDDEN, message)
+    }
+
+    fn conflict(message: String) -> Self {
+        ApiError::new(StatusCode::CONFLICT, message)
+    }
+
+    fn unprocessable(message: String) -> Self {
+        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    fn internal(error: anyhow::Error) -> Self {
+        ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        (
+            self.status,
+            Json(ErrorBody {
+                error: self.message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+fn message_id(line_id: &LineId) -> MessageId {
+    MessageId::new(
+        line_id.noun_num(),
+        line_id.verb_num(),
+        line_id.condition_num(),
+        line_id.sequence_num(),
+    )
+}
+
+fn is_recorded(samples: &SampleDir, line: &Line) -> bool {
+    let id = line.id();
+    samples.sample(id.room_num(), &message_id(&id)).is_some()
+}
+
+#[derive(Serialize)]
+struct LineJson {
+    id: String,
+    role: String,
+    text: String,
+    recorded: bool,
+}
+
+impl LineJson {
+    fn new(line: &Line, samples: &SampleDir) -> Self {
+        LineJson {
+            id: line_id_to_id_string(line.id()),
+            role: line.role().name().to_string(),
+            text: line.text().to_string(),
+            recorded: is_recorded(samples, line),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConversationJson {
+    id: String,
+    verb: Option<String>,
+    condition: Option<u8>,
+    lines: Vec<LineJson>,
+}
+
+#[derive(Serialize)]
+struct NounJson {
+    id: String,
+    desc: Option<String>,
+    cutscene: bool,
+    conversations: Vec<ConversationJson>,
+}
+
+#[derive(Serialize)]
+struct RoomJson {
+    id: String,
+    name: Option<String>,
+    nouns: Vec<NounJson>,
+}
+
+#[derive(Serialize)]
+struct BookJson {
+    project_name: String,
+    rooms: Vec<RoomJson>,
+}
+
+async fn get_book(
+    State(state): State<Arc<ServerState>>,
+    _auth: Auth,
+) -> Result<Json<BookJson>, ApiError> {
+    let samples = state.load_samples().await?;
+    let book = &state.book;
+    Ok(Json(BookJson {
+        project_name: book.project_name().to_string(),
+        rooms: book
+            .rooms()
+            .map(|room| RoomJson {
+                id: room_id_to_id_string(room.id()),
+                name: room.configured_name().map(str::to_string),
+                nouns: room
+                    .nouns()
+                    .map(|noun| NounJson {
+                        id: noun_id_to_id_string(noun.id()),
+                        desc: noun.desc().map(str::to_string),
+                        cutscene: noun.is_cutscene(),
+                        conversations: noun
+                            .conversations()
+                            .map(|conversation| ConversationJson {
+                                id: conversation_id_to_id_string(conversation.id()),
+                                verb: conversation.verb().map(|verb| verb.name().to_string()),
+                                condition: conversation
+                                    .condition()
+                                    .map(|condition| condition.id().condition_num()),
+                                lines: conversation
+                                    .lines()
+                                    .map(|line| LineJson::new(&line, &samples))
+                                    .collect(),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Serialize)]
+struct LineDetailJson {
+    #[serde(flatten)]
+    line: LineJson,
+    /// The selected take, relative to the sample directory.
+    selected_take: Option<PathBuf>,
+    /// Whether the selected take is approved, so no takes can be uploaded.
+    locked: bool,
+    takes: Vec<PathBuf>,
+}
+
+async fn get_line(
+    State(state): State<Arc<ServerState>>,
+    _auth: Auth,
+    UrlPath(line_id): UrlPath<String>,
+) -> Result<Json<LineDetailJson>, ApiError> {
+    let samples = state.load_samples().await?;
+    let line = state.find_line(&line_id)?;
+    let id = line.id();
+    let takes = samples
+        .find_takes(id.room_num(), &message_id(&id))
+        .map_err(ApiError::internal)?;
+    Ok(Json(LineDetailJson {
+        line: LineJson::new(&line, &samples),
+        selected_take: samples
+            .sample(id.room_num(), &message_id(&id))
+            .map(|sample| sample.clip.path.clone()),
+        locked: samples.is_locked(id.room_num(), &message_id(&id)),
+        takes,
+    }))
+}
+
+fn audio_content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("wav") => "audio/wav",
+        Some("flac") => "audio/flac",
+        Some("ogg") => "audio/ogg",
+        Some("mp3") => "audio/mpeg",
+        Some("aif" | "aiff") => "audio/aiff",
+        Some("m4a") => "audio/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn get_line_audio(
+    State(state): State<Arc<ServerState>>,
+    _auth: Auth,
+    UrlPath(line_id): UrlPath<String>,
+) -> Result<Response, ApiError> {
+    let samples = state.load_samples().await?;
+    let id = state.find_line(&line_id)?.id();
+    let sample = samples
+        .sample(id.room_num(), &message_id(&id))
+        .ok_or_else(|| ApiError::not_found(format!("Line {line_id} has not been recorded")))?;
+    let path = sample
+        .clip_path(samples.base_path())
+        .map_err(ApiError::internal)?;
+    let contents = tokio::fs::read(&path)
+        .await
+        .map_err(|e| ApiError::internal(e.into()))?;
+    Ok((
+        [(header::CONTENT_TYPE, audio_content_type(&path))],
+        contents,
+    )
+        .into_response())
+}
+
+#[derive(Serialize, Default)]
+struct Progress {
+    total: usize,
+    recorded: usize,
+}
+
+#[derive(Serialize)]
+struct LastBuild {
+    profile: String,
+    lines: usize,
+    warnings: usize,
+}
+
+#[derive(Serialize)]
+struct StatusJson {
+    #[serde(flatten)]
+    overall: Progress,
+    rooms: BTreeMap<String, Progress>,
+    roles: BTreeMap<String, Progress>,
+    last_build: Option<LastBuild>,
+}
+
+async fn get_status(
+    State(state): State<Arc<ServerState>>,
+    _auth: Auth,
+) -> Result<Json<StatusJson>, ApiError> {
+    let samples = state.load_samples().await?;
+    let mut status = StatusJson {
+        overall: Progress::default(),
+        rooms: BTreeMap::new(),
+        roles: BTreeMap::new(),
+        last_build: None,
+    };
+    for room in state.book.rooms() {
+        let room_progress = status
+            .rooms
+            .entry(room_id_to_id_string(room.id()))
+            .or_default();
+        for line in room
+            .nouns()
+            .flat_map(|noun| noun.conversations())
+            .flat_map(|conversation| conversation.lines())
+        {
+            let recorded = usize::from(is_recorded(&samples, &line));
+            room_progress.total += 1;
+            room_progress.recorded += recorded;
+            let role = status
+                .roles
+                .entry(line.role().name().to_string())
+                .or_default();
+            role.total += 1;
+            role.recorded += recorded;
+            status.overall.total += 1;
+            status.overall.recorded += recorded;
+        }
+    }
+    if let Some(output_dir) = &state.output_dir {
+        status.last_build = BuildReport::load(output_dir)
+            .map_err(ApiError::internal)?
+            .map(|report| LastBuild {
+                lines: report.lines.len(),
+                warnings: report.num_warnings(),
+                profile: report.profile,
+            });
+    }
+    Ok(Json(status))
+}
@@ -0,0 +1,117 @@
+//! Token-based authentication. Tokens are listed in a YAML file, one per
+//! actor:
+//!
+//! ```yaml
+//! - token: 6f1c0b2e9a7d4c55
+//!   actor: alice
+//!   roles: [Narrator, Guard]
+//! ```
+//!
+//! Clients send their token as `Authorization: Bearer <token>`. An actor
+//! with roles listed may only upload takes for those roles' lines.
+
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use serde::Deserialize;
+
+use super::{ApiError, ServerState};
+use crate::book::Line;
+
+#[derive(Deserialize, Debug, Clone)]
+pub(super) struct Actor {
+    #[serde(rename = "actor")]
+    pub(super) name: String,
+    /// The roles the actor records, by name or short name. Empty if the
+    /// actor may record any role.
+    #[serde(default)]
+    pub(super) roles: Vec<String>,
+}
+
+impl Actor {
+    pub(super) fn may_record(&self, line: &Line) -> bool {
+        let role = line.role();
+        self.roles.is_empty()
+            || self
+                .roles
+                .iter()
+                .any(|name| name == role.name() || name == role.short_name())
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenEntry {
+    token: String,
+    #[serde(flatten)]
+    actor: Actor,
+}
+
+pub(super) struct Tokens(Vec<TokenEntry>);
+
+/// Compares in time independent of where the strings differ, so tokens
+/// can't be guessed a character at a time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl Tokens {
+    pub(super) fn load(path: &Path) -> anyhow::Result<Self> {
+        let entries: Vec<TokenEntry> = serde_yml::from_reader(std::fs::File::open(path)?)?;
+        for entry in &entries {
+            anyhow::ensure!(
+                entry.token.len() >= 16,
+                "The token for {} is too short; use at least 16 characters",
+                entry.actor.name
+            );
+            // Actor names are used in the paths of uploaded takes.
+            anyhow::ensure!(
+                !entry.actor.name.is_empty()
+                    && entry
+                        .actor
+                        .name
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_'),
+                "Invalid actor name {:?}; use letters, digits, '-' and '_'",
+                entry.actor.name
+            );
+        }
+        Ok(Tokens(entries))
+    }
+
+    fn find(&self, token: &str) -> Option<&Actor> {
+        self.0
+            .iter()
+            .find(|entry| constant_time_eq(entry.token.as_bytes(), token.as_bytes()))
+            .map(|entry| &entry.actor)
+    }
+}
+
+/// The actor making a request. If the server was started without tokens,
+/// requests are anonymous.
+pub(super) struct Auth(pub(super) Option<Actor>);
+
+impl FromRequestParts<Arc<ServerState>> for Auth {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(tokens) = &state.tokens else {
+            return Ok(Auth(None));
+        };
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::unauthorized("Missing bearer token".to_string()))?;
+        let actor = tokens
+            .find(token.trim())
+            .ok_or_else(|| ApiError::unauthorized("Invalid token".to_string()))?;
+        Ok(Auth(Some(actor.clone())))
+    }
+}
@@ -0,0 +1,222 @@
+//! Uploading takes. A recording for a line is checked before it is filed:
+//! ffprobe must be able to read it, its length must be plausible, and (if a
+//! transcriber is configured) it must say roughly the line's text.
+//!
+//! Accepted takes are filed under `uploads/<actor>/` in the sample
+//! directory. A take for a line that had none yet is also selected. Lines
+//! whose take is locked don't accept uploads.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path as UrlPath, Query, State},
+    http::{HeaderMap, StatusCode, header},
+};
+use scitool_fan_dub_cli::{
+    cancel::CancellationToken,
+    resources::{SampleDir, is_take_extension},
+    tools::ffprobe::{AudioInfo, FfprobeTool},
+    transcript::{Transcriber, transcript_similarity},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{ApiError, ServerState, auth::Auth, message_id};
+
+/// Takes shorter than this are assumed to be mistakes.
+const MIN_DURATION: Duration = Duration::from_millis(200);
+
+/// Takes longer than this are assumed to be mistakes (e.g. a whole session).
+const MAX_DURATION: Duration = Duration::from_secs(120);
+
+/// The checks run on uploaded takes.
+pub(super) struct UploadChecks {
+    pub(super) ffprobe: FfprobeTool,
+    pub(super) transcriber: Option<Transcriber>,
+    /// The lowest accepted similarity between the transcript and the line.
+    pub(super) min_similarity: f64,
+}
+
+#[derive(Serialize)]
+pub(super) struct UploadResult {
+    /// The take's path, relative to the sample directory.
+    take: PathBuf,
+    /// Whether the take was selected for the line.
+    selected: bool,
+    duration_secs: f64,
+    sample_rate: u32,
+    channels: u32,
+    transcript: Option<String>,
+    transcript_similarity: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct UploadQuery {
+    /// The file type (e.g. `wav`), if the content type doesn't give it.
+    format: Option<String>,
+}
+
+fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    match mime.to_ascii_lowercase().as_str() {
+        "audio/wav" | "audio/x-wav" | "audio/wave" | "audio/vnd.wave" => Some("wav"),
+        "audio/flac" | "audio/x-flac" => Some("flac"),
+        "audio/ogg" => Some("ogg"),
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/aiff" | "audio/x-aiff" => Some("aiff"),
+        "audio/mp4" | "audio/x-m4a" => Some("m4a"),
+        _ => None,
+    }
+}
+
+/// Runs the checks on the take. Returns the take's properties, and its
+/// transcript and similarity if it was transcribed.
+fn check_take(
+    checks: &UploadChecks,
+    text: &str,
+    path: &Path,
+) -> Result<(AudioInfo, Option<(String, f64)>), ApiError> {
+    // The tool runner's futures aren't Send, so they are run to completion
+    // here, on a blocking thread.
+    smol::block_on(async {
+        let cancel = CancellationToken::new();
+        let info = checks
+            .ffprobe
+            .probe_audio(path, &cancel)
+            .await
+            .map_err(|e| ApiError::unprocessable(format!("Not a readable audio file: {e}")))?;
+        if info.duration < MIN_DURATION || info.duration > MAX_DURATION {
+            return Err(ApiError::unprocessable(format!(
+                "The take is {:.1} seconds long; takes must be between {:.1} and {:.0} seconds",
+                info.duration.as_secs_f64(),
+                MIN_DURATION.as_secs_f64(),
+                MAX_DURATION.as_secs_f64()
+            )));
+        }
+        let Some(transcriber) = &checks.transcriber else {
+            return Ok((info, None));
+        };
+        let transcript = transcriber
+            .transcribe(path, &cancel)
+            .await
+            .map_err(ApiError::internal)?;
+        let similarity = transcript_similarity(text, &transcript);
+        if similarity < checks.min_similarity {
+            return Err(ApiError::unprocessable(format!(
+                "The take doesn't match the line's text (similarity {similarity:.2}). Heard: {transcript:?}"
+            )));
+        }
+        Ok((info, Some((transcript, similarity))))
+    })
+}
+
+pub(super) async fn upload_take(
+    State(state): State<Arc<ServerState>>,
+    Auth(actor): Auth,
+    UrlPath(line_id): UrlPath<String>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<UploadResult>), ApiError> {
+    let (Some(actor), Some(_)) = (actor, &state.upload_checks) else {
+        return Err(ApiError::forbidden(
+            "Uploads are only accepted by servers started with --tokens".to_string(),
+        ));
+    };
+    let line = state.find_line(&line_id)?;
+    if !actor.may_record(&line) {
+        return Err(ApiError::forbidden(format!(
+            "{} doesn't record the role {}",
+            actor.name,
+            line.role().name()
+        )));
+    }
+    let id = line.id();
+    let text = line.text().to_string();
+    let extension = match &query.format {
+        Some(format) => Some(format.to_ascii_lowercase()),
+        None => headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(extension_for_content_type)
+            .map(str::to_string),
+    }
+    .filter(|extension| is_take_extension(extension))
+    .ok_or_else(|| {
+        ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Send an audio content type, or give the file type with ?format=".to_string(),
+        )
+    })?;
+
+    let upload_dir = Path::new("uploads").join(&actor.name);
+    let abs_upload_dir = state.sample_dir.join(&upload_dir);
+    tokio::fs::create_dir_all(&abs_upload_dir)
+        .await
+        .map_err(|e| ApiError::internal(e.into()))?;
+    let upload = tempfile::Builder::new()
+        .prefix(".upload-")
+        .suffix(&format!(".{extension}"))
+        .tempfile_in(&abs_upload_dir)
+        .map_err(|e| ApiError::internal(e.into()))?;
+    tokio::fs::write(upload.path(), &body)
+        .await
+        .map_err(|e| ApiError::internal(e.into()))?;
+
+    let (upload, (info, transcript)) = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            let checks = state
+                .upload_checks
+                .as_ref()
+                .expect("Checked before the upload was accepted");
+            let result = check_take(checks, &text, upload.path());
+            result.map(|result| (upload, result))
+        })
+        .await
+        .map_err(|e| ApiError::internal(e.into()))??
+    };
+
+    // Uploads for the same line could otherwise pick the same take name, or
+    // overwrite each other's changes to samples.json.
+    let _guard = state.samples_lock.lock().await;
+    let mut samples = SampleDir::load_dir(&state.sample_dir)
+        .await
+        .map_err(ApiError::internal)?;
+    if samples.is_locked(id.room_num(), &message_id(&id)) {
+        return Err(ApiError::conflict(format!(
+            "Line {line_id} is locked; its take has been approved"
+        )));
+    }
+    let take = samples
+        .new_take_path(&upload_dir, id.room_num(), &message_id(&id), &extension)
+        .map_err(ApiError::internal)?;
+    upload
+        .persist(state.sample_dir.join(&take))
+        .map_err(|e| ApiError::internal(e.error.into()))?;
+    let selected = samples.sample(id.room_num(), &message_id(&id)).is_none();
+    if selected {
+        samples.select_take(id.room_num(), message_id(&id), take.clone());
+        samples.save().await.map_err(ApiError::internal)?;
+    }
+    eprintln!("{} uploaded {}", actor.name, take.display());
+
+    let (transcript, similarity) = transcript.unzip();
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadResult {
+            take,
+            selected,
+            duration_secs: info.duration.as_secs_f64(),
+            sample_rate: info.sample_rate,
+            channels: info.channels,
+            transcript,
+            transcript_similarity: similarity,
+        }),
+    ))
+}
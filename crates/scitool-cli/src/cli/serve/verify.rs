@@ -0,0 +1,175 @@
+//! Checking a player's copy of the game against the one the dub is made for,
+//! so support channels can tell "wrong version" problems apart without
+//! anyone sharing game files. Only `RESOURCE.MAP` is compared: it lists where
+//! each resource is stored, but none of the game's content.
+//!
+//! - `POST /api/verify`: with the player's `RESOURCE.MAP` as the body.
+//!   Reports whether it is the expected map, which known release it is from,
+//!   and which resources it has that the expected map doesn't, or the other
+//!   way around.
+//! - `GET /api/verify?map_sha256=<hex>`: the same, from the map's SHA-256
+//!   alone, without the differences.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Query, State},
+};
+use sci_resources::{
+    ParseOptions, ResourceId,
+    file::{
+        find_game_file,
+        map::{EntryStatus, ResourceLocations},
+    },
+};
+use sci_utils::block::{BlockReader, MemBlock};
+use serde::{Deserialize, Serialize};
+
+use super::{super::detect, ApiError};
+
+/// The largest resource map that can be uploaded. Maps list a few thousand
+/// resources at five bytes each.
+pub(super) const MAX_MAP_BYTES: usize = 1024 * 1024;
+
+/// The game the dub is made for.
+pub(super) struct ExpectedGame {
+    map_sha256: String,
+    release: Option<String>,
+    /// Where each resource the game uses is stored.
+    resources: BTreeMap<ResourceId, u32>,
+    options: ParseOptions,
+}
+
+fn live_resources(locations: &ResourceLocations) -> BTreeMap<ResourceId, u32> {
+    locations
+        .locations()
+        .filter(|location| location.status == EntryStatus::Live)
+        .map(|location| (location.id, location.file_offset))
+        .collect()
+}
+
+impl ExpectedGame {
+    pub(super) fn read(game_dir: &Path) -> anyhow::Result<Self> {
+        let map_path = find_game_file(game_dir, "RESOURCE.MAP")
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| game_dir.join("RESOURCE.MAP"));
+        let map = std::fs::read(&map_path).map_err(|e| {
+            anyhow::anyhow!(
+                "Can't read {}: {e}; only games with a RESOURCE.MAP can be verified",
+                map_path.display()
+            )
+        })?;
+        // Players' maps are read the same way as the game's, but without
+        // logging their anomalies to the server's output.
+        let options = detect::with_game_quirks(
+            game_dir,
+            ParseOptions {
+                log_anomalies: false,
+                ..ParseOptions::permissive()
+            },
+        );
+        let locations = read_map(&map, &options)?;
+        Ok(ExpectedGame {
+            map_sha256: detect::map_sha256(&map),
+            release: detect::detect_game(game_dir).map(ToString::to_string),
+            resources: live_resources(&locations),
+            options,
+        })
+    }
+}
+
+fn read_map(map: &[u8], options: &ParseOptions) -> std::io::Result<ResourceLocations> {
+    ResourceLocations::read_from_with_options(
+        BlockReader::new(MemBlock::from_vec(map.to_vec())),
+        options,
+    )
+}
+
+/// How a player's map differs from the expected one.
+#[derive(Serialize)]
+pub(super) struct MapDifferences {
+    /// Resources the expected game has, but the player's doesn't.
+    missing: Vec<String>,
+    /// Resources the player's game has, but the expected one doesn't.
+    extra: Vec<String>,
+    /// The number of resources both have, stored in different places.
+    moved: usize,
+}
+
+impl MapDifferences {
+    fn new(expected: &BTreeMap<ResourceId, u32>, actual: &BTreeMap<ResourceId, u32>) -> Self {
+        let names = |ids: BTreeSet<&ResourceId>| ids.iter().map(|id| format!("{id:?}")).collect();
+        let expected_ids: BTreeSet<_> = expected.keys().collect();
+        let actual_ids: BTreeSet<_> = actual.keys().collect();
+        MapDifferences {
+            missing: names(&expected_ids - &actual_ids),
+            extra: names(&actual_ids - &expected_ids),
+            moved: expected
+                .iter()
+                .filter(|(id, offset)| actual.get(id).is_some_and(|actual| actual != *offset))
+                .count(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(super) struct VerifyResult {
+    map_sha256: String,
+    /// Whether the map is the one the dub is made for.
+    matches: bool,
+    /// The known release the map is from, if it is one.
+    release: Option<String>,
+    /// The known release the dub is made for, if it is one.
+    expected_release: Option<String>,
+    /// How the map differs from the expected one, if it was uploaded and
+    /// isn't the same.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    differences: Option<MapDifferences>,
+}
+
+impl VerifyResult {
+    fn new(expected: &ExpectedGame, map_sha256: String) -> Self {
+        VerifyResult {
+            matches: map_sha256.eq_ignore_ascii_case(&expected.map_sha256),
+            release: detect::detect_map(&map_sha256).map(ToString::to_string),
+            expected_release: expected.release.clone(),
+            map_sha256,
+            differences: None,
+        }
+    }
+}
+
+pub(super) async fn verify_map(
+    State(expected): State<Arc<ExpectedGame>>,
+    body: Bytes,
+) -> Result<Json<VerifyResult>, ApiError> {
+    let locations = read_map(&body, &expected.options)
+        .map_err(|e| ApiError::unprocessable(format!("Not a resource map: {e}")))?;
+    let mut result = VerifyResult::new(&expected, detect::map_sha256(&body));
+    if !result.matches {
+        result.differences = Some(MapDifferences::new(
+            &expected.resources,
+            &live_resources(&locations),
+        ));
+    }
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+pub(super) struct VerifyQuery {
+    map_sha256: String,
+}
+
+pub(super) async fn verify_hash(
+    State(expected): State<Arc<ExpectedGame>>,
+    Query(query): Query<VerifyQuery>,
+) -> Json<VerifyResult> {
+    Json(VerifyResult::new(&expected, query.map_sha256))
+}
@@ -0,0 +1,388 @@
+//! Exporting a view's cels as PNGs, and building a view patch from edited
+//! ones, so artists can fix sprites (e.g. signage text) for a release.
+//!
+//! An export holds a PNG per cel and `view.json`, giving each loop's cels
+//! with their origins and transparent colors, and which loops mirror others.
+//! Importing quantizes the PNGs against the game's palette (plus the view's
+//! own), optionally dithered, and encodes them into a patch that keeps the
+//! view's palette.
+//!
+//! A loop's cels can also be drawn side by side into a strip, to show a
+//! talker's portrait in generated scripts.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use sci_resources::{
+    ResourceId, ResourceType,
+    file::{Resource, ResourceSet},
+    types::{
+        palette::Palette,
+        quantize::{ColorError, Dithering, QuantizeOptions, quantize},
+        view::{Cel, View, ViewLoop, build_view},
+    },
+};
+use sci_utils::{
+    block::{LazyBlock, MemBlock},
+    fs,
+};
+use serde::{Deserialize, Serialize};
+
+use super::detect;
+use crate::{generate::doc::Thumbnail, i18n::tr};
+
+/// The game's global palette, which views' embedded palettes add to.
+const GLOBAL_PALETTE: u16 = 999;
+
+const METADATA_FILE: &str = "view.json";
+
+#[derive(Serialize, Deserialize)]
+struct CelMetadata {
+    /// The PNG, relative to the export directory.
+    file: String,
+    displace_x: i16,
+    displace_y: i16,
+    /// The color index drawn as transparent. Transparent pixels in the PNG
+    /// become this color.
+    clear_key: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LoopMetadata {
+    /// The loop this one mirrors, if it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mirror_of: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cels: Vec<CelMetadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ViewMetadata {
+    view: u16,
+    loops: Vec<LoopMetadata>,
+}
+
+/// Loads a resource's data from the game.
+pub(super) fn load_resource(
+    resource_set: &ResourceSet,
+    id: &ResourceId,
+) -> anyhow::Result<MemBlock> {
+    resource_set
+        .get_resource(id)
+        .ok_or_else(|| anyhow::anyhow!(tr!("resource-not-found", id = format!("{id:?}"))))?
+        .load_data()
+}
+
+/// The game's global palette, or an empty one if it has none.
+pub(super) fn global_palette(resource_set: &ResourceSet) -> anyhow::Result<Palette> {
+    Ok(
+        match resource_set.get_resource(&ResourceId::new(ResourceType::Palette, GLOBAL_PALETTE)) {
+            Some(resource) => Palette::parse(&resource.load_data()?)?,
+            None => Palette::empty(),
+        },
+    )
+}
+
+/// Loads a view from the game, with the palette its cels are drawn in.
+fn load_view(resource_set: &ResourceSet, view_num: u16) -> anyhow::Result<(MemBlock, Palette)> {
+    let data = load_resource(resource_set, &ResourceId::new(ResourceType::View, view_num))?;
+    let mut palette = global_palette(resource_set)?;
+    if let Some(view_palette) = View::parse(&data)?.palette()? {
+        palette.overlay(&view_palette);
+    }
+    Ok((data, palette))
+}
+
+/// The most cels drawn in a strip.
+const MAX_STRIP_CELS: usize = 8;
+
+/// The space between cels in a strip, in the game's pixels.
+const STRIP_GAP: u16 = 2;
+
+/// Draws the first cels of a view's first loop side by side, lined up at
+/// their bottoms, on white. Views the game doesn't have, or whose first loop
+/// has no cels, have no strip.
+pub(super) fn view_strip(
+    resource_set: &ResourceSet,
+    view_num: u16,
+) -> anyhow::Result<Option<Thumbnail>> {
+    if resource_set
+        .get_resource(&ResourceId::new(ResourceType::View, view_num))
+        .is_none()
+    {
+        return Ok(None);
+    }
+    let (data, palette) = load_view(resource_set, view_num)?;
+    let view = View::parse(&data)?;
+    if view.loop_count() == 0 {
+        return Ok(None);
+    }
+    let cels = view.cels(0)?;
+    let cels = &cels[..cels.len().min(MAX_STRIP_CELS)];
+    if cels.is_empty() {
+        return Ok(None);
+    }
+    let width = cels.iter().map(|cel| cel.width).sum::<u16>() + STRIP_GAP * (cels.len() as u16 - 1);
+    let height = cels.iter().map(|cel| cel.height).max().unwrap_or(0);
+    let mut rgb = vec![0xFF; usize::from(width) * usize::from(height) * 3];
+    let mut left = 0;
+    for cel in cels {
+        let top = height - cel.height;
+        for (i, &index) in cel.pixels.iter().enumerate() {
+            if index == cel.clear_key {
+                continue;
+            }
+            let x = usize::from(left) + i % usize::from(cel.width);
+            let y = usize::from(top) + i / usize::from(cel.width);
+            let start = (y * usize::from(width) + x) * 3;
+            // Colors missing from the palette are drawn black.
+            rgb[start..start + 3].copy_from_slice(&palette.color(index).unwrap_or_default());
+        }
+        left += cel.width + STRIP_GAP;
+    }
+    Ok(Some(Thumbnail { width, height, rgb }))
+}
+
+/// Writes the cels of a view as PNGs, with `view.json` describing them, for
+/// editing and `import-view`.
+#[derive(Parser)]
+pub(super) struct ExportView {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    #[clap(index = 2)]
+    view: u16,
+    #[clap(short = 'o', long)]
+    output_dir: PathBuf,
+}
+
+impl ExportView {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let (data, palette) = load_view(&resource_set, self.view)?;
+        let view = View::parse(&data)?;
+        std::fs::create_dir_all(&self.output_dir)?;
+        let mut loops = Vec::new();
+        let mut num_cels = 0;
+        for (loop_num, view_loop) in view.loops()?.into_iter().enumerate() {
+            let mut cels = Vec::new();
+            for (cel_num, cel) in view_loop.cels.iter().enumerate() {
+                let file = format!("{loop_num}-{cel_num}.png");
+                write_png(&self.output_dir.join(&file), cel, &palette)?;
+                cels.push(CelMetadata {
+                    file,
+                    displace_x: cel.displace_x,
+                    displace_y: cel.displace_y,
+                    clear_key: cel.clear_key,
+                });
+                num_cels += 1;
+            }
+            loops.push(LoopMetadata {
+                mirror_of: view_loop.mirror_of,
+                cels,
+            });
+        }
+        let metadata = ViewMetadata {
+            view: self.view,
+            loops,
+        };
+        std::fs::write(
+            self.output_dir.join(METADATA_FILE),
+            serde_json::to_vec_pretty(&metadata)?,
+        )?;
+        eprintln!(
+            "{}",
+            tr!(
+                "view-exported",
+                count = num_cels,
+                path = format!("{:?}", self.output_dir)
+            )
+        );
+        Ok(())
+    }
+}
+
+pub(super) fn write_png(path: &Path, cel: &Cel, palette: &Palette) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(std::fs::File::create(path)?),
+        u32::from(cel.width),
+        u32::from(cel.height),
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let pixels: Vec<u8> = cel
+        .pixels
+        .iter()
+        .flat_map(|&index| {
+            if index == cel.clear_key {
+                return [0; 4];
+            }
+            // Colors missing from the palette are drawn black.
+            let [r, g, b] = palette.color(index).unwrap_or_default();
+            [r, g, b, 0xFF]
+        })
+        .collect();
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads a PNG as 8-bit RGBA.
+pub(super) fn read_png(path: &Path) -> anyhow::Result<(u16, u16, Vec<[u8; 4]>)> {
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![
+        0;
+        reader
+            .output_buffer_size()
+            .ok_or_else(|| anyhow::anyhow!("{path:?} is too large"))?
+    ];
+    let info = reader.next_frame(&mut buffer)?;
+    let bytes = &buffer[..info.buffer_size()];
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => bytes
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect(),
+        png::ColorType::Rgb => bytes
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => bytes
+            .chunks_exact(2)
+            .map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => bytes.iter().map(|&v| [v, v, v, 0xFF]).collect(),
+        png::ColorType::Indexed => anyhow::bail!("{path:?} wasn't expanded from its palette"),
+    };
+    let too_large = || anyhow::anyhow!("{path:?} is larger than a cel can be");
+    Ok((
+        u16::try_from(info.width).map_err(|_| too_large())?,
+        u16::try_from(info.height).map_err(|_| too_large())?,
+        pixels,
+    ))
+}
+
+/// Writes a patch for a resource into a directory, creating it if needed.
+pub(super) fn write_patch(output_dir: &Path, id: ResourceId, data: Vec<u8>) -> anyhow::Result<()> {
+    let patch = Resource::new(id, LazyBlock::from_mem_block(MemBlock::from_vec(data)));
+    let patch_name = patch.patch_file_name().ok_or_else(|| {
+        anyhow::anyhow!(tr!("no-numbered-patches", type = format!("{:?}", id.type_id())))
+    })?;
+    std::fs::create_dir_all(output_dir)?;
+    let filename = output_dir.join(patch_name);
+    eprintln!(
+        "{}",
+        tr!(
+            "writing-patch",
+            id = format!("{id:?}"),
+            path = format!("{filename:?}")
+        )
+    );
+    let mut pending = fs::PendingFile::create(&filename)?;
+    patch.write_patch_sync(pending.file())?;
+    pending.commit(&fs::WriteOptions {
+        on_locked: Some(&fs::ask_retry_locked),
+        ..fs::WriteOptions::default()
+    })?;
+    Ok(())
+}
+
+/// Builds a view patch from a directory written by `export-view`, after its
+/// PNGs have been edited. Colors are matched to the nearest in the game's
+/// palette, and pixels that are mostly transparent become transparent.
+#[derive(Parser)]
+pub(super) struct ImportView {
+    #[clap(index = 1)]
+    root_dir: PathBuf,
+    /// The directory written by `export-view`.
+    #[clap(index = 2)]
+    input_dir: PathBuf,
+    /// Where to write the patch. Defaults to the game directory.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+    /// How to approximate colors that aren't in the palette.
+    #[clap(long, value_enum, default_value_t = Dithering::None)]
+    dither: Dithering,
+}
+
+impl ImportView {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        let metadata: ViewMetadata =
+            serde_json::from_slice(&std::fs::read(self.input_dir.join(METADATA_FILE))?)?;
+        let resource_set = detect::open_game(&self.root_dir)?;
+        let (data, palette) = load_view(&resource_set, metadata.view)?;
+        let original = View::parse(&data)?;
+        anyhow::ensure!(
+            palette.nearest([0; 3], None).is_some(),
+            tr!("view-no-palette")
+        );
+        let mut error = ColorError::default();
+        let loops = metadata
+            .loops
+            .iter()
+            .map(|loop_metadata| {
+                let cels = loop_metadata
+                    .cels
+                    .iter()
+                    .map(|cel| {
+                        let (cel, cel_error) = self.read_cel(cel, &palette)?;
+                        error.combine(&cel_error);
+                        Ok(cel)
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(ViewLoop {
+                    mirror_of: loop_metadata.mirror_of,
+                    cels,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if error.approximated() > 0 {
+            eprintln!(
+                "{}",
+                tr!(
+                    "view-colors-approximated",
+                    count = error.approximated(),
+                    mean = format!("{:.1}", error.mean),
+                    max = format!("{:.1}", error.max)
+                )
+            );
+        }
+        let view_data = build_view(&loops, original.palette_data()?, Some(&original))?;
+
+        write_patch(
+            self.output_dir.as_deref().unwrap_or(&self.root_dir),
+            ResourceId::new(ResourceType::View, metadata.view),
+            view_data,
+        )?;
+        Ok(())
+    }
+
+    /// Reads and quantizes a cel's PNG, returning it with how far its colors
+    /// moved.
+    fn read_cel(
+        &self,
+        metadata: &CelMetadata,
+        palette: &Palette,
+    ) -> anyhow::Result<(Cel, ColorError)> {
+        let path = self.input_dir.join(&metadata.file);
+        let (width, height, rgba) = read_png(&path)?;
+        let options = QuantizeOptions {
+            dithering: self.dither,
+            ..QuantizeOptions::new(metadata.clear_key)
+        };
+        let quantized = quantize(&rgba, usize::from(width), palette, &options)?;
+        Ok((
+            Cel {
+                width,
+                height,
+                displace_x: metadata.displace_x,
+                displace_y: metadata.displace_y,
+                clear_key: metadata.clear_key,
+                pixels: quantized.pixels,
+            },
+            quantized.error,
+        ))
+    }
+}
@@ -0,0 +1,265 @@
+//! Workspaces: a file naming several projects (e.g. the English and Spanish
+//! dubs of a game), each with its game directory, book config and sample
+//! directory, so commands can be pointed at a project with `--project`
+//! instead of repeating the paths.
+//!
+//! The workspace file is `scitool-workspace.yaml`, found in the current
+//! directory or the nearest one above it, or given with `--workspace`:
+//!
+//! ```yaml
+//! default: en
+//! projects:
+//!   en:
+//!     game_dir: games/en
+//!     config: book-en.yaml
+//!     sample_dir: samples/en
+//!   es:
+//!     game_dir: games/es
+//!     config: book-es.yaml
+//! ```
+//!
+//! Paths are relative to the workspace file. Paths given on the command line
+//! take precedence over the project's. Without `--project`, the `default`
+//! project is used, if the workspace has one.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::tr;
+
+pub(super) const WORKSPACE_FILE_NAME: &str = "scitool-workspace.yaml";
+
+#[derive(Serialize, Deserialize)]
+struct ProjectEntry {
+    game_dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    config: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sample_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkspaceFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<String>,
+    projects: BTreeMap<String, ProjectEntry>,
+}
+
+/// A project of the workspace, with its paths resolved.
+pub(super) struct Project {
+    pub(super) name: String,
+    pub(super) game_dir: PathBuf,
+    pub(super) config: Option<PathBuf>,
+    pub(super) sample_dir: Option<PathBuf>,
+}
+
+struct Workspace {
+    path: PathBuf,
+    file: WorkspaceFile,
+}
+
+impl Workspace {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = serde_yml::from_reader(std::fs::File::open(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid workspace file {}: {e}", path.display()))?;
+        Ok(Workspace {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Finds the workspace file in the current directory or above it.
+    fn find() -> anyhow::Result<Option<Self>> {
+        let current_dir = std::env::current_dir()?;
+        current_dir
+            .ancestors()
+            .map(|dir| dir.join(WORKSPACE_FILE_NAME))
+            .find(|path| path.is_file())
+            .map(|path| Workspace::load(&path))
+            .transpose()
+    }
+
+    fn project(&self, name: &str) -> Option<Project> {
+        let entry = self.file.projects.get(name)?;
+        let base = self.path.parent().unwrap_or(Path::new("."));
+        Some(Project {
+            name: name.to_string(),
+            game_dir: base.join(&entry.game_dir),
+            config: entry.config.as_ref().map(|path| base.join(path)),
+            sample_dir: entry.sample_dir.as_ref().map(|path| base.join(path)),
+        })
+    }
+}
+
+/// Writes a workspace file in `dir` with a single project, as the default.
+/// The paths are taken as relative to `dir`.
+pub(super) fn write_workspace(
+    dir: &Path,
+    name: &str,
+    game_dir: &Path,
+    config: &Path,
+    sample_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let file = WorkspaceFile {
+        default: Some(name.to_string()),
+        projects: BTreeMap::from([(
+            name.to_string(),
+            ProjectEntry {
+                game_dir: game_dir.to_path_buf(),
+                config: Some(config.to_path_buf()),
+                sample_dir: Some(sample_dir.to_path_buf()),
+            },
+        )]),
+    };
+    let path = dir.join(WORKSPACE_FILE_NAME);
+    std::fs::write(&path, serde_yml::to_string(&file)?)?;
+    Ok(path)
+}
+
+/// The workspace and project chosen on the command line.
+#[derive(Default)]
+pub(super) struct Selection {
+    pub(super) workspace: Option<PathBuf>,
+    pub(super) project: Option<String>,
+}
+
+static SELECTION: OnceLock<Selection> = OnceLock::new();
+
+/// Chooses the workspace and project for the rest of the run.
+pub(super) fn select(selection: Selection) {
+    let _ = SELECTION.set(selection);
+}
+
+fn load_workspace(selection: &Selection) -> anyhow::Result<Option<Workspace>> {
+    match &selection.workspace {
+        Some(path) => Ok(Some(Workspace::load(path)?)),
+        None => Workspace::find(),
+    }
+}
+
+/// The selected project, or the workspace's default if none was selected.
+pub(super) fn project() -> anyhow::Result<Option<Project>> {
+    let selection = SELECTION.get_or_init(Selection::default);
+    let workspace = load_workspace(selection)?;
+    match (&selection.project, workspace) {
+        (Some(name), Some(workspace)) => workspace.project(name).map(Some).ok_or_else(|| {
+            anyhow::anyhow!(tr!(
+                "unknown-project",
+                name = name.as_str(),
+                path = workspace.path.display().to_string()
+            ))
+        }),
+        (Some(_), None) => Err(anyhow::anyhow!(tr!(
+            "no-workspace",
+            file = WORKSPACE_FILE_NAME
+        ))),
+        (None, Some(workspace)) => Ok(workspace
+            .file
+            .default
+            .as_deref()
+            .and_then(|name| workspace.project(name))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// The game directory given on the command line, or the project's.
+pub(super) fn game_dir(given: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(path) = given {
+        return Ok(path.to_path_buf());
+    }
+    project()?
+        .map(|project| project.game_dir)
+        .ok_or_else(|| anyhow::anyhow!(tr!("no-game-dir")))
+}
+
+/// The book config given on the command line, or the project's, if it has
+/// one.
+pub(super) fn optional_config(given: Option<&Path>) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(path) = given {
+        return Ok(Some(path.to_path_buf()));
+    }
+    Ok(project()?.and_then(|project| project.config))
+}
+
+/// The book config given on the command line, or the project's.
+pub(super) fn config(given: Option<&Path>) -> anyhow::Result<PathBuf> {
+    optional_config(given)?.ok_or_else(|| anyhow::anyhow!(tr!("no-book-config")))
+}
+
+/// The sample directory given on the command line, or the project's.
+#[cfg_attr(
+    not(any(
+        feature = "gui",
+        feature = "serve",
+        feature = "coverage",
+        feature = "recast"
+    )),
+    expect(dead_code)
+)]
+pub(super) fn sample_dir(given: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(path) = given {
+        return Ok(path.to_path_buf());
+    }
+    project()?
+        .and_then(|project| project.sample_dir)
+        .ok_or_else(|| anyhow::anyhow!(tr!("no-sample-dir")))
+}
+
+/// Lists the workspace's projects, with their paths.
+#[derive(Parser)]
+struct ListProjects {}
+
+impl ListProjects {
+    fn run(&self) -> anyhow::Result<()> {
+        let selection = SELECTION.get_or_init(Selection::default);
+        let workspace = load_workspace(selection)?
+            .ok_or_else(|| anyhow::anyhow!(tr!("no-workspace", file = WORKSPACE_FILE_NAME)))?;
+        let default = workspace.file.default.as_deref();
+        for name in workspace.file.projects.keys() {
+            let project = workspace.project(name).expect("Listed by the workspace");
+            let marker = if default == Some(name.as_str()) {
+                "*"
+            } else {
+                ""
+            };
+            let optional = |path: &Option<PathBuf>| {
+                path.as_ref()
+                    .map_or_else(|| "-".to_string(), |path| path.display().to_string())
+            };
+            println!(
+                "{}{marker}\t{}\t{}\t{}",
+                project.name,
+                project.game_dir.display(),
+                optional(&project.config),
+                optional(&project.sample_dir)
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand)]
+enum WorkspaceCommand {
+    #[clap(name = "list")]
+    ListProjects(ListProjects),
+}
+
+#[derive(Parser)]
+pub(super) struct WorkspaceCmd {
+    #[clap(subcommand)]
+    command: WorkspaceCommand,
+}
+
+impl WorkspaceCmd {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        match &self.command {
+            WorkspaceCommand::ListProjects(list) => list.run(),
+        }
+    }
+}
@@ -3,3 +3,4 @@
 pub mod doc;
 pub mod html;
 mod markdown;
+pub mod pdf;
@@ -1,5 +1,7 @@
 //! Traits and implementations used to generate documents, including VO scripts.
 
+use std::collections::BTreeMap;
+
 use text::RichText;
 
 pub mod text;
@@ -11,6 +13,7 @@ fn push_last_mut<T>(vec: &mut Vec<T>, value: T) -> &mut T {
 
 pub struct Document {
     title: RichText,
+    portraits: Vec<Portrait>,
     chapters: Vec<Section>,
 }
 
@@ -19,9 +22,85 @@ impl Document {
         &self.title
     }
 
+    /// The characters shown under the title.
+    pub fn portraits(&self) -> &[Portrait] {
+        &self.portraits
+    }
+
+    /// Sets the characters shown under the title.
+    pub fn with_portraits(mut self, portraits: Vec<Portrait>) -> Self {
+        self.portraits = portraits;
+        self
+    }
+
     pub fn chapters(&self) -> &[Section] {
         &self.chapters
     }
+
+    /// Sets the coverage of the sections with the given IDs.
+    #[cfg_attr(not(feature = "coverage"), expect(dead_code))]
+    pub fn with_coverage(mut self, coverage: &BTreeMap<String, Coverage>) -> Self {
+        fn annotate(sections: &mut [Section], coverage: &BTreeMap<String, Coverage>) {
+            for section in sections {
+                section.coverage = section.id.as_ref().and_then(|id| coverage.get(id)).copied();
+                annotate(&mut section.subsections, coverage);
+            }
+        }
+        annotate(&mut self.chapters, coverage);
+        self
+    }
+
+    /// Sets the thumbnails of the sections with the given IDs.
+    pub fn with_thumbnails(mut self, thumbnails: &BTreeMap<String, Thumbnail>) -> Self {
+        fn annotate(sections: &mut [Section], thumbnails: &BTreeMap<String, Thumbnail>) {
+            for section in sections {
+                section.thumbnail = section
+                    .id
+                    .as_ref()
+                    .and_then(|id| thumbnails.get(id))
+                    .cloned();
+                annotate(&mut section.subsections, thumbnails);
+            }
+        }
+        annotate(&mut self.chapters, thumbnails);
+        self
+    }
+}
+
+/// How many of a section's lines are dubbed, and how many have been heard
+/// in playtests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub lines: usize,
+    pub dubbed: usize,
+    pub heard: usize,
+}
+
+/// A small picture, such as a room's background shown under its section's
+/// title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u16,
+    pub height: u16,
+    /// 8-bit RGB pixels, row by row.
+    pub rgb: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// The width of the thumbnail over its height, as shown. The game's
+    /// pixels are 1.2 times taller than they are wide, so a 320x200 screen
+    /// fills a 4:3 display.
+    pub fn aspect_ratio(&self) -> f32 {
+        f32::from(self.width) / (f32::from(self.height) * 1.2)
+    }
+}
+
+/// A picture of a character, such as their talker's portrait, with their
+/// name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Portrait {
+    pub name: String,
+    pub image: Thumbnail,
 }
 
 pub struct Section {
@@ -29,6 +108,8 @@ pub struct Section {
     id: Option<String>,
     content: Content,
     subsections: Vec<Section>,
+    coverage: Option<Coverage>,
+    thumbnail: Option<Thumbnail>,
 }
 
 impl Section {
@@ -47,6 +128,14 @@ impl Section {
     pub fn subsections(&self) -> &[Section] {
         &self.subsections
     }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn thumbnail(&self) -> Option<&Thumbnail> {
+        self.thumbnail.as_ref()
+    }
 }
 
 impl Section {
@@ -56,6 +145,8 @@ impl Section {
             id: None,
             content: Content::new(),
             subsections: Vec::new(),
+            coverage: None,
+            thumbnail: None,
         }
     }
 }
@@ -80,6 +171,7 @@ pub struct Line {
     speaker: RichText,
     id: String,
     line: RichText,
+    notes: Vec<String>,
 }
 
 impl Line {
@@ -94,6 +186,11 @@ impl Line {
     pub fn id(&self) -> &str {
         &self.id
     }
+
+    /// Notes for the recording of this line.
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
 }
 
 pub struct Dialogue {
@@ -164,12 +261,13 @@ impl DocumentBuilder {
         Self {
             document: Document {
                 title: title.into(),
+                portraits: Vec::new(),
                 chapters: Vec::new(),
             },
         }
     }
 
-    pub fn add_chapter(&mut self, title: impl Into<RichText>) -> SectionBuilder {
+    pub fn add_chapter(&mut self, title: impl Into<RichText>) -> SectionBuilder<'_> {
         SectionBuilder {
             section: push_last_mut(
                 &mut self.document.chapters,
@@ -189,7 +287,7 @@ pub struct ListBuilder<'a> {
 
 impl ListBuilder<'_> {
     #[expect(dead_code)]
-    fn add_item(&mut self) -> ContentBuilder {
+    fn add_item(&mut self) -> ContentBuilder<'_> {
         ContentBuilder {
             content: push_last_mut(self.list, Content::new()),
         }
@@ -205,7 +303,7 @@ impl<'a> SectionBuilder<'a> {
         self.section.id = Some(id.into());
     }
 
-    pub fn add_content(&mut self) -> ContentBuilder {
+    pub fn add_content(&mut self) -> ContentBuilder<'_> {
         ContentBuilder {
             content: &mut self.section.content,
         }
@@ -223,7 +321,7 @@ pub struct SubSectionBuilder<'a> {
 }
 
 impl SubSectionBuilder<'_> {
-    pub fn add_subsection(&mut self, title: impl Into<RichText>) -> SectionBuilder {
+    pub fn add_subsection(&mut self, title: impl Into<RichText>) -> SectionBuilder<'_> {
         SectionBuilder {
             section: push_last_mut(
                 &mut self.section.subsections,
@@ -243,13 +341,13 @@ impl ContentBuilder<'_> {
     }
 
     #[expect(dead_code)]
-    pub fn add_list(&mut self) -> ListBuilder {
+    pub fn add_list(&mut self) -> ListBuilder<'_> {
         ListBuilder {
             list: self.content.push_list_mut(),
         }
     }
 
-    pub fn add_dialogue(&mut self) -> DialogueBuilder {
+    pub fn add_dialogue(&mut self) -> DialogueBuilder<'_> {
         DialogueBuilder {
             dialogue: self.content.push_dialogue_mut(),
         }
@@ -271,6 +369,16 @@ impl DialogueBuilder<'_> {
             speaker: speaker.into(),
             id,
             line: line.into(),
+            notes: Vec::new(),
         });
     }
+
+    /// Adds a note to the most recently added line.
+    pub fn add_line_note(&mut self, note: impl Into<String>) {
+        self.dialogue
+            .last_mut()
+            .expect("A line must be added before its notes")
+            .notes
+            .push(note.into());
+    }
 }
@@ -50,6 +50,11 @@ impl RichText {
         &self.items
     }
 
+    /// The text without any styling.
+    pub fn plain_text(&self) -> String {
+        self.items.iter().map(TextItem::text).collect()
+    }
+
     pub fn builder() -> RichTextBuilder {
         RichTextBuilder {
             output: RichText::default(),
@@ -1,4 +1,4 @@
-use super::doc::{ContentItem, Document, Section, text::RichText};
+use super::doc::{ContentItem, Document, Portrait, Section, Thumbnail, text::RichText};
 
 const GOOGLE_ICONS_LINK: maud::PreEscaped<&str> = maud::PreEscaped(
     r#"<link rel="stylesheet" href="https://fonts.googleapis.com/css2?family=Material+Symbols+Outlined:opsz,wght,FILL,GRAD@20..48,100..700,0..1,-50..200" />"#,
@@ -63,7 +63,10 @@ fn generate_content(content: &super::doc::Content) -> maud::Markup {
                             .line id=(line.id()){
                                 .speaker { (generate_rich_text(line.speaker())) ":" }
                                 ."line-text" { (generate_rich_text(line.line()))
-                                (generate_copy_button(line.id()))}
+                                (generate_copy_button(line.id()))
+                                @for note in line.notes() {
+                                    ."line-note" { (note) }
+                                }}
                             }
                         }
                     }
@@ -73,28 +76,136 @@ fn generate_content(content: &super::doc::Content) -> maud::Markup {
     }
 }
 
-fn generate_section(_level: usize, section: &Section) -> maud::Markup {
+/// A count of covered lines, colored from red when none are covered to
+/// green when all are.
+fn generate_coverage_badge(label: &str, count: usize, total: usize) -> maud::Markup {
+    let hue = (count * 120).checked_div(total).unwrap_or(120);
     maud::html! {
+        span."coverage-badge" style=(format!("background-color: hsl({hue}, 70%, 85%)")) {
+            (label) " " (count) "/" (total)
+        }
+    }
+}
+
+/// Encodes bytes as base64, for data URLs.
+#[cfg(feature = "graphics")]
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The thumbnail as a PNG data URL, so the page stays a single file.
+#[cfg(feature = "graphics")]
+fn thumbnail_data_url(thumbnail: &Thumbnail) -> anyhow::Result<String> {
+    let mut png_data = Vec::new();
+    let mut encoder = png::Encoder::new(
+        &mut png_data,
+        u32::from(thumbnail.width),
+        u32::from(thumbnail.height),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&thumbnail.rgb)?;
+    writer.finish()?;
+    Ok(format!("data:image/png;base64,{}", base64(&png_data)))
+}
+
+/// Only documents built with graphics have thumbnails to encode.
+#[cfg(not(feature = "graphics"))]
+fn thumbnail_data_url(_thumbnail: &Thumbnail) -> anyhow::Result<String> {
+    anyhow::bail!("Images in HTML need the `graphics` feature")
+}
+
+/// An image of the thumbnail at twice the game's resolution, in the given
+/// class.
+fn generate_image(thumbnail: &Thumbnail, class: &str) -> anyhow::Result<maud::Markup> {
+    let width = u32::from(thumbnail.width) * 2;
+    let height = (width as f32 / thumbnail.aspect_ratio()).round() as u32;
+    Ok(maud::html! {
+        img class=(class) src=(thumbnail_data_url(thumbnail)?) width=(width) height=(height) alt="";
+    })
+}
+
+fn generate_portraits(portraits: &[Portrait]) -> anyhow::Result<maud::Markup> {
+    let images = portraits
+        .iter()
+        .map(|portrait| generate_image(&portrait.image, "portrait-image"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(maud::html! {
+        @if !portraits.is_empty() {
+            .portraits {
+                @for (portrait, image) in portraits.iter().zip(images) {
+                    figure.portrait {
+                        (image)
+                        figcaption { (portrait.name) }
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn generate_section(_level: usize, section: &Section) -> anyhow::Result<maud::Markup> {
+    let thumbnail = section
+        .thumbnail()
+        .map(|thumbnail| generate_image(thumbnail, "room-thumbnail"))
+        .transpose()?;
+    let subsections = section
+        .subsections()
+        .iter()
+        .map(|subsection| generate_section(_level + 1, subsection))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(maud::html! {
         .section id=[section.id()] {
             ."section-title" {
-                (generate_rich_text(&format!("{} - {}", section.title(), section.id().unwrap_or(""))))
+                (generate_rich_text(section.title()))
+                @if let Some(coverage) = section.coverage() {
+                    (generate_coverage_badge("dubbed", coverage.dubbed, coverage.lines))
+                    (generate_coverage_badge("heard", coverage.heard, coverage.lines))
+                }
                 @if let Some(id) = section.id() {
                     (generate_copy_button(id))
                 }
             }
             ."section-body" {
+                @if let Some(thumbnail) = thumbnail {
+                    (thumbnail)
+                }
 
                 (generate_content(section.content()))
 
-                @for subsection in section.subsections() {
-                    (generate_section(_level + 1, subsection))
+                @for subsection in subsections {
+                    (subsection)
                 }
             }
         }
-    }
+    })
 }
 
 pub fn generate_html(doc: &Document) -> anyhow::Result<String> {
+    let chapters = doc
+        .chapters()
+        .iter()
+        .map(|chapter| generate_section(0, chapter))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let portraits = generate_portraits(doc.portraits())?;
     Ok(maud::html! {
         (maud::DOCTYPE)
         html {
@@ -105,8 +216,9 @@ pub fn generate_html(doc: &Document) -> anyhow::Result<String> {
             }
             body lang="en-US"{
                 h1 { (generate_rich_text(doc.title())) }
-                @for chapter in doc.chapters() {
-                    (generate_section(0, chapter))
+                (portraits)
+                @for chapter in chapters {
+                    (chapter)
                 }
                 script { (SCRIPT_JS) }
             }
@@ -101,7 +101,7 @@ impl<'a> StringBuilder<'a> {
         }
     }
 
-    fn borrow(&mut self) -> StringBuilder {
+    fn borrow(&mut self) -> StringBuilder<'_> {
         StringBuilder {
             state: self.state,
             output: self.output,
@@ -127,7 +127,7 @@ struct Section<'a> {
 }
 
 impl<'a> Section<'a> {
-    fn add_content(&mut self) -> Content {
+    fn add_content(&mut self) -> Content<'_> {
         Content {
             next_separator: "",
             writer: StringBuilder::with_mut_string(self.output),
@@ -178,7 +178,7 @@ struct Content<'a> {
 }
 
 impl Content<'_> {
-    fn add_paragraph(&mut self) -> Text {
+    fn add_paragraph(&mut self) -> Text<'_> {
         self.writer.add_raw_text(self.next_separator);
         self.next_separator = "\n";
         Text {
@@ -187,7 +187,7 @@ impl Content<'_> {
         }
     }
 
-    fn block_quote(&mut self) -> Text {
+    fn block_quote(&mut self) -> Text<'_> {
         self.writer.add_raw_text(self.next_separator);
         self.next_separator = "";
         Text {
@@ -196,7 +196,7 @@ impl Content<'_> {
         }
     }
 
-    fn add_list(&mut self) -> List {
+    fn add_list(&mut self) -> List<'_> {
         self.writer.add_raw_text(self.next_separator);
         self.next_separator = "";
         List {
@@ -291,7 +291,7 @@ struct List<'a> {
 }
 
 impl List<'_> {
-    fn add_item(&mut self) -> Content {
+    fn add_item(&mut self) -> Content<'_> {
         todo!()
         // Content {
         //     writer: self.writer.indent("- ", "  "),
@@ -305,7 +305,7 @@ struct SubSectionList<'a> {
 }
 
 impl SubSectionList<'_> {
-    fn add_subsection(&mut self) -> Section {
+    fn add_subsection(&mut self) -> Section<'_> {
         self.output
             .push_str(&format!("{} ", "#".repeat(self.level)));
         Section {
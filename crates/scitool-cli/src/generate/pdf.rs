@@ -0,0 +1,615 @@
+//! Generates PDF scripts laid out for use in a recording booth.
+//!
+//! Pages use a large font, a gutter on the left with each line's ID (so
+//! takes can be slated), and a wide right margin for notes. Lines spoken by
+//! a chosen speaker can be highlighted.
+//!
+//! Only the standard PDF fonts are used, so no font data needs to be
+//! embedded. Text is encoded with WinAnsiEncoding; characters outside of it
+//! are replaced with `?`. Portraits and section thumbnails are embedded as
+//! uncompressed RGB images.
+
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+use super::doc::{
+    Content as DocContent, ContentItem, Document, Portrait, Section, Thumbnail,
+    text::{RichText, TextStyle},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PageSize {
+    #[default]
+    Letter,
+    A4,
+}
+
+impl PageSize {
+    /// The width and height of the page, in points.
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::A4 => (595.0, 842.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PdfOptions {
+    pub page_size: PageSize,
+    /// The size of dialogue text, in points.
+    pub font_size: f32,
+    /// Lines whose speaker matches this name are highlighted.
+    pub highlight_speaker: Option<String>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            page_size: PageSize::default(),
+            font_size: 14.0,
+            highlight_speaker: None,
+        }
+    }
+}
+
+// Margins, in points.
+const TOP_MARGIN: f32 = 72.0;
+const BOTTOM_MARGIN: f32 = 72.0;
+const GUTTER_WIDTH: f32 = 108.0;
+const NOTES_MARGIN: f32 = 180.0;
+const GUTTER_LABEL_X: f32 = 36.0;
+
+/// The width thumbnails are drawn at, in points.
+const THUMBNAIL_WIDTH: f32 = 216.0;
+
+/// The size of one of the game's pixels in a portrait, in points.
+const PORTRAIT_SCALE: f32 = 1.5;
+
+const GUTTER_FONT_SIZE: f32 = 8.0;
+const LINE_SPACING: f32 = 1.3;
+const HIGHLIGHT_RGB: (f32, f32, f32) = (1.0, 0.95, 0.6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 5] = [
+        Font::Regular,
+        Font::Bold,
+        Font::Italic,
+        Font::BoldItalic,
+        Font::Mono,
+    ];
+
+    fn for_style(style: &TextStyle) -> Self {
+        match (style.bold(), style.italic()) {
+            (false, false) => Font::Regular,
+            (true, false) => Font::Bold,
+            (false, true) => Font::Italic,
+            (true, true) => Font::BoldItalic,
+        }
+    }
+
+    fn bold(self) -> Self {
+        match self {
+            Font::Regular => Font::Bold,
+            Font::Italic => Font::BoldItalic,
+            other => other,
+        }
+    }
+
+    fn resource_name(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"F1",
+            Font::Bold => b"F2",
+            Font::Italic => b"F3",
+            Font::BoldItalic => b"F4",
+            Font::Mono => b"F5",
+        })
+    }
+
+    fn base_font(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"Helvetica",
+            Font::Bold => b"Helvetica-Bold",
+            Font::Italic => b"Helvetica-Oblique",
+            Font::BoldItalic => b"Helvetica-BoldOblique",
+            Font::Mono => b"Courier",
+        })
+    }
+
+    /// The width of an encoded character, in thousandths of the font size.
+    fn char_width(self, ch: u8) -> u16 {
+        let table = match self {
+            Font::Mono => return 600,
+            Font::Regular | Font::Italic => &HELVETICA_WIDTHS,
+            Font::Bold | Font::BoldItalic => &HELVETICA_BOLD_WIDTHS,
+        };
+        match ch {
+            32..=126 => table[(ch - 32) as usize],
+            _ => 556,
+        }
+    }
+
+    fn text_width(self, text: &[u8], size: f32) -> f32 {
+        let units: u32 = text.iter().map(|&ch| self.char_width(ch) as u32).sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+// Advance widths for the printable ASCII range, from the standard AFM
+// metrics.
+#[rustfmt::skip]
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[rustfmt::skip]
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// Encodes text as WinAnsiEncoding.
+fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|ch| match ch {
+            '\u{20}'..='\u{7E}' | '\u{A0}'..='\u{FF}' => ch as u8,
+            '\u{2026}' => 0x85,
+            '\u{2018}' => 0x91,
+            '\u{2019}' => 0x92,
+            '\u{201C}' => 0x93,
+            '\u{201D}' => 0x94,
+            '\u{2013}' => 0x96,
+            '\u{2014}' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// A run of text in a single font.
+#[derive(Debug, Clone)]
+struct Run {
+    font: Font,
+    text: Vec<u8>,
+}
+
+/// A single line of laid out text.
+type TextLine = Vec<Run>;
+
+/// Adds a run to the end of a line, merging it with the previous run if they
+/// use the same font.
+fn push_run(line: &mut TextLine, run: Run) {
+    match line.last_mut() {
+        Some(last) if last.font == run.font => last.text.extend(run.text),
+        _ => line.push(run),
+    }
+}
+
+/// Splits rich text into words, each of which is a list of runs (a word can
+/// change style part way through).
+fn split_words(text: &[(Font, String)]) -> Vec<Vec<Run>> {
+    let mut words = Vec::new();
+    let mut current: Vec<Run> = Vec::new();
+    for (font, text) in text {
+        for (i, part) in text.split(char::is_whitespace).enumerate() {
+            if i > 0 && !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            if !part.is_empty() {
+                current.push(Run {
+                    font: *font,
+                    text: encode_win_ansi(part),
+                });
+            }
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Wraps text into lines no wider than `width`. Words that are too long for a
+/// line on their own are left to overflow.
+fn wrap_text(text: &[(Font, String)], size: f32, width: f32) -> Vec<TextLine> {
+    let space_width = Font::Regular.text_width(b" ", size);
+    let mut lines = Vec::new();
+    let mut line: TextLine = Vec::new();
+    let mut line_width = 0.0;
+    for word in split_words(text) {
+        let word_width: f32 = word
+            .iter()
+            .map(|run| run.font.text_width(&run.text, size))
+            .sum();
+        if !line.is_empty() && line_width + space_width + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+        }
+        if !line.is_empty() {
+            push_run(
+                &mut line,
+                Run {
+                    font: Font::Regular,
+                    text: b" ".to_vec(),
+                },
+            );
+            line_width += space_width;
+        }
+        for run in word {
+            push_run(&mut line, run);
+        }
+        line_width += word_width;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn rich_text_runs(text: &RichText, base: Font) -> Vec<(Font, String)> {
+    text.items()
+        .iter()
+        .map(|item| {
+            let font = match base {
+                Font::Regular => Font::for_style(item.style()),
+                Font::Bold => Font::for_style(item.style()).bold(),
+                other => other,
+            };
+            (font, item.text().to_string())
+        })
+        .collect()
+}
+
+/// A block of content that is kept together on one page where possible.
+struct Block {
+    lines: Vec<TextLine>,
+    size: f32,
+    /// Space before the block, in points.
+    space_before: f32,
+    /// A label to write in the gutter next to the first line.
+    gutter_label: Option<String>,
+    highlighted: bool,
+}
+
+impl Block {
+    fn line_height(&self) -> f32 {
+        self.size * LINE_SPACING
+    }
+
+    fn height(&self) -> f32 {
+        self.lines.len() as f32 * self.line_height()
+    }
+}
+
+struct Layout<'a> {
+    options: &'a PdfOptions,
+    width: f32,
+    height: f32,
+    pages: Vec<Content>,
+    /// The top of the next line, measured from the top of the page.
+    cursor: f32,
+    /// True if nothing has been written to the current page yet.
+    at_page_top: bool,
+    /// The thumbnails drawn so far. Each is named by its index.
+    images: Vec<&'a Thumbnail>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(options: &'a PdfOptions) -> Self {
+        let (width, height) = options.page_size.dimensions();
+        Layout {
+            options,
+            width,
+            height,
+            pages: Vec::new(),
+            cursor: TOP_MARGIN,
+            at_page_top: false,
+            images: Vec::new(),
+        }
+    }
+
+    fn text_width(&self) -> f32 {
+        self.width - GUTTER_WIDTH - NOTES_MARGIN
+    }
+
+    fn new_page(&mut self) {
+        let mut page = Content::new();
+        let number = (self.pages.len() + 1).to_string();
+        write_text(
+            &mut page,
+            Font::Regular,
+            10.0,
+            self.width - NOTES_MARGIN,
+            BOTTOM_MARGIN / 2.0,
+            &encode_win_ansi(&number),
+        );
+        self.pages.push(page);
+        self.cursor = TOP_MARGIN;
+        self.at_page_top = true;
+    }
+
+    fn add_block(&mut self, block: Block) {
+        let available = self.height - TOP_MARGIN - BOTTOM_MARGIN;
+        let space_before = if self.at_page_top {
+            0.0
+        } else {
+            block.space_before
+        };
+        // Keep the block on one page, unless it wouldn't fit on any page.
+        if self.cursor + space_before + block.height() > self.height - BOTTOM_MARGIN
+            && (block.height() <= available || self.pages.is_empty())
+        {
+            self.new_page();
+        } else {
+            self.cursor += space_before;
+        }
+        self.at_page_top = false;
+
+        let line_height = block.line_height();
+        if block.highlighted {
+            let padding = block.size * 0.2;
+            let top = self.height - self.cursor;
+            let bottom = (top - block.height()).max(BOTTOM_MARGIN);
+            let width = self.text_width();
+            let content = self.pages.last_mut().unwrap();
+            let (r, g, b) = HIGHLIGHT_RGB;
+            content.save_state();
+            content.set_fill_rgb(r, g, b);
+            content.rect(
+                GUTTER_WIDTH - padding,
+                bottom - padding,
+                width + 2.0 * padding,
+                top - bottom + 2.0 * padding,
+            );
+            content.fill_nonzero();
+            content.restore_state();
+        }
+
+        for (i, line) in block.lines.iter().enumerate() {
+            if self.cursor + line_height > self.height - BOTTOM_MARGIN {
+                self.new_page();
+            }
+            let baseline = self.height - self.cursor - block.size;
+            let content = self.pages.last_mut().unwrap();
+            if i == 0
+                && let Some(label) = &block.gutter_label
+            {
+                write_text(
+                    content,
+                    Font::Mono,
+                    GUTTER_FONT_SIZE,
+                    GUTTER_LABEL_X,
+                    baseline,
+                    &encode_win_ansi(label),
+                );
+            }
+            let mut x = GUTTER_WIDTH;
+            for run in line {
+                write_text(content, run.font, block.size, x, baseline, &run.text);
+                x += run.font.text_width(&run.text, block.size);
+            }
+            self.cursor += line_height;
+        }
+    }
+
+    fn add_heading(&mut self, text: &RichText, size: f32, new_page: bool) {
+        if new_page {
+            self.new_page();
+        }
+        let lines = wrap_text(&rich_text_runs(text, Font::Bold), size, self.text_width());
+        self.add_block(Block {
+            lines,
+            size,
+            space_before: size,
+            gutter_label: None,
+            highlighted: false,
+        });
+    }
+
+    /// Draws an image at the given width, below what's been drawn so far.
+    fn add_image(&mut self, thumbnail: &'a Thumbnail, width: f32) {
+        let height = width / thumbnail.aspect_ratio();
+        let space_before = self.options.font_size * 0.5;
+        if self.cursor + space_before + height > self.height - BOTTOM_MARGIN {
+            self.new_page();
+        } else {
+            self.cursor += space_before;
+        }
+        self.at_page_top = false;
+        let name = image_name(self.images.len());
+        self.images.push(thumbnail);
+        let bottom = self.height - self.cursor - height;
+        let content = self.pages.last_mut().unwrap();
+        content.save_state();
+        content.transform([width, 0.0, 0.0, height, GUTTER_WIDTH, bottom]);
+        content.x_object(Name(name.as_bytes()));
+        content.restore_state();
+        self.cursor += height;
+    }
+
+    fn add_portrait(&mut self, portrait: &'a Portrait) {
+        let size = self.options.font_size;
+        let width = (f32::from(portrait.image.width) * PORTRAIT_SCALE).min(self.text_width());
+        self.add_block(Block {
+            lines: wrap_text(
+                &[(Font::Bold, portrait.name.clone())],
+                size,
+                self.text_width(),
+            ),
+            size,
+            space_before: size,
+            gutter_label: None,
+            highlighted: false,
+        });
+        self.add_image(&portrait.image, width);
+    }
+
+    fn add_content(&mut self, content: &DocContent) {
+        let size = self.options.font_size;
+        for item in content.items() {
+            match item {
+                ContentItem::Paragraph(text) => {
+                    let lines =
+                        wrap_text(&rich_text_runs(text, Font::Italic), size, self.text_width());
+                    self.add_block(Block {
+                        lines,
+                        size,
+                        space_before: size * 0.5,
+                        gutter_label: None,
+                        highlighted: false,
+                    });
+                }
+                ContentItem::List(list) => {
+                    for item in list.items() {
+                        self.add_content(item);
+                    }
+                }
+                ContentItem::Dialogue(dialogue) => {
+                    for line in dialogue.lines() {
+                        let speaker = line.speaker().plain_text();
+                        let highlighted = self
+                            .options
+                            .highlight_speaker
+                            .as_ref()
+                            .is_some_and(|name| name.eq_ignore_ascii_case(&speaker));
+                        let mut text = vec![(Font::Bold, format!("{}: ", speaker))];
+                        text.extend(rich_text_runs(line.line(), Font::Regular));
+                        self.add_block(Block {
+                            lines: wrap_text(&text, size, self.text_width()),
+                            size,
+                            space_before: size * 0.6,
+                            gutter_label: Some(line.id().to_string()),
+                            highlighted,
+                        });
+                        for note in line.notes() {
+                            let note_size = size * 0.8;
+                            let text = [(Font::Italic, format!("Note: {}", note))];
+                            self.add_block(Block {
+                                lines: wrap_text(&text, note_size, self.text_width()),
+                                size: note_size,
+                                space_before: note_size * 0.3,
+                                gutter_label: None,
+                                highlighted: false,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_section(&mut self, level: usize, section: &'a Section) {
+        let size = match level {
+            0 => self.options.font_size + 8.0,
+            1 => self.options.font_size + 4.0,
+            _ => self.options.font_size + 2.0,
+        };
+        self.add_heading(section.title(), size, level == 0);
+        if let Some(thumbnail) = section.thumbnail() {
+            self.add_image(thumbnail, THUMBNAIL_WIDTH);
+        }
+        self.add_content(section.content());
+        for subsection in section.subsections() {
+            self.add_section(level + 1, subsection);
+        }
+    }
+}
+
+/// The resource name of the image at the index.
+fn image_name(index: usize) -> String {
+    format!("Im{}", index + 1)
+}
+
+fn write_text(content: &mut Content, font: Font, size: f32, x: f32, y: f32, text: &[u8]) {
+    content.begin_text();
+    content.set_font(font.resource_name(), size);
+    content.next_line(x, y);
+    content.show(Str(text));
+    content.end_text();
+}
+
+pub fn generate_pdf(doc: &Document, options: &PdfOptions) -> anyhow::Result<Vec<u8>> {
+    let mut layout = Layout::new(options);
+    layout.new_page();
+    layout.add_heading(doc.title(), options.font_size + 14.0, false);
+    for portrait in doc.portraits() {
+        layout.add_portrait(portrait);
+    }
+    for chapter in doc.chapters() {
+        layout.add_section(0, chapter);
+    }
+
+    let mut pdf = Pdf::new();
+    let mut next_ref = Ref::new(1);
+    let catalog_id = next_ref.bump();
+    let page_tree_id = next_ref.bump();
+    let info_id = next_ref.bump();
+    let font_ids: Vec<_> = Font::ALL.iter().map(|_| next_ref.bump()).collect();
+    let image_ids: Vec<_> = layout.images.iter().map(|_| next_ref.bump()).collect();
+    let page_ids: Vec<_> = layout
+        .pages
+        .iter()
+        .map(|_| (next_ref.bump(), next_ref.bump()))
+        .collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.document_info(info_id)
+        .title(TextStr(&doc.title().plain_text()));
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().map(|&(page_id, _)| page_id))
+        .count(page_ids.len() as i32);
+
+    for (font, &font_id) in Font::ALL.iter().zip(&font_ids) {
+        pdf.type1_font(font_id)
+            .base_font(font.base_font())
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (thumbnail, &image_id) in layout.images.iter().zip(&image_ids) {
+        let mut image = pdf.image_xobject(image_id, &thumbnail.rgb);
+        image.width(i32::from(thumbnail.width));
+        image.height(i32::from(thumbnail.height));
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.finish();
+    }
+
+    for (content, &(page_id, content_id)) in layout.pages.into_iter().zip(&page_ids) {
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, layout.width, layout.height));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        for (font, &font_id) in Font::ALL.iter().zip(&font_ids) {
+            fonts.pair(font.resource_name(), font_id);
+        }
+        fonts.finish();
+        let mut x_objects = resources.x_objects();
+        for (i, &image_id) in image_ids.iter().enumerate() {
+            x_objects.pair(Name(image_name(i).as_bytes()), image_id);
+        }
+        x_objects.finish();
+        resources.finish();
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+
+    Ok(pdf.finish())
+}
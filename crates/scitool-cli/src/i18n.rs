@@ -0,0 +1,131 @@
+//! Translations of the messages the commands print.
+//!
+//! Messages are looked up by ID in a [Fluent](https://projectfluent.org)
+//! catalog, one per language in `locales/`. A message missing from a catalog
+//! falls back to English, so catalogs can be filled in a bit at a time.
+//! Output meant to be read by other programs (listings, dumps, exports) is not
+//! translated.
+
+use std::sync::OnceLock;
+
+use fluent_bundle::{FluentArgs, FluentResource, concurrent::FluentBundle};
+use unic_langid::LanguageIdentifier;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Lang {
+    En,
+    Es,
+    De,
+}
+
+impl Lang {
+    fn catalog(self) -> &'static str {
+        match self {
+            Lang::En => include_str!("../locales/en.ftl"),
+            Lang::Es => include_str!("../locales/es.ftl"),
+            Lang::De => include_str!("../locales/de.ftl"),
+        }
+    }
+
+    fn id(self) -> LanguageIdentifier {
+        let id = match self {
+            Lang::En => "en",
+            Lang::Es => "es",
+            Lang::De => "de",
+        };
+        id.parse().unwrap()
+    }
+
+    /// The language of a POSIX locale name (e.g. `es_ES.UTF-8`), if there is
+    /// a catalog for it.
+    fn from_locale(locale: &str) -> Option<Self> {
+        let language = locale.split(['_', '-', '.', '@']).next()?;
+        match language {
+            "en" => Some(Lang::En),
+            "es" => Some(Lang::Es),
+            "de" => Some(Lang::De),
+            _ => None,
+        }
+    }
+
+    /// The language of the user's locale, from the environment.
+    fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+    }
+}
+
+struct Catalog {
+    bundle: FluentBundle<FluentResource>,
+    english: FluentBundle<FluentResource>,
+}
+
+impl Catalog {
+    fn bundle(lang: Lang) -> FluentBundle<FluentResource> {
+        let resource = FluentResource::try_new(lang.catalog().to_string())
+            .unwrap_or_else(|(_, errors)| panic!("Invalid {lang:?} catalog: {errors:?}"));
+        let mut bundle = FluentBundle::new_concurrent(vec![lang.id()]);
+        // Isolation marks show up as garbage in most terminals.
+        bundle.set_use_isolating(false);
+        bundle
+            .add_resource(resource)
+            .unwrap_or_else(|errors| panic!("Duplicate messages in {lang:?} catalog: {errors:?}"));
+        bundle
+    }
+
+    fn new(lang: Lang) -> Self {
+        Catalog {
+            bundle: Catalog::bundle(lang),
+            english: Catalog::bundle(Lang::En),
+        }
+    }
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| Catalog::new(Lang::from_env().unwrap_or(Lang::En)))
+}
+
+/// Chooses the language of messages, instead of the locale's. Must be called
+/// before any message is translated.
+pub fn set_lang(lang: Lang) {
+    if CATALOG.set(Catalog::new(lang)).is_err() {
+        eprintln!("Warning: the language was chosen after messages were translated");
+    }
+}
+
+/// Translates a message. Use [`tr!`] rather than calling this directly.
+pub fn translate(id: &str, args: Option<&FluentArgs>) -> String {
+    let catalog = catalog();
+    let Some((bundle, message)) = [&catalog.bundle, &catalog.english]
+        .into_iter()
+        .find_map(|bundle| Some((bundle, bundle.get_message(id)?)))
+    else {
+        return id.to_string();
+    };
+    let Some(pattern) = message.value() else {
+        return id.to_string();
+    };
+    let mut errors = Vec::new();
+    bundle
+        .format_pattern(pattern, args, &mut errors)
+        .into_owned()
+}
+
+/// Translates a message, with named arguments: `tr!("id", count = 3)`.
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::translate($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::translate($id, Some(&args))
+    }};
+}
+
+pub(crate) use tr;
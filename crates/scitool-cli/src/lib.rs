@@ -1,4 +1,25 @@
 mod book;
 pub mod cli;
 mod generate;
+mod i18n;
 mod output;
+
+/// Entry points for the benchmarks, which can't reach the private modules.
+#[doc(hidden)]
+pub mod bench {
+    use sci_resources::types::msg::RoomMessageSet;
+
+    use crate::book::{builder::BookBuilder, config::BookConfig};
+
+    /// Builds a book from parsed message resources, with the default config.
+    pub fn build_book(rooms: &[(u16, RoomMessageSet)]) -> anyhow::Result<()> {
+        let mut builder = BookBuilder::new(BookConfig::default())?;
+        for (room, messages) in rooms {
+            for (id, record) in messages.messages() {
+                builder.add_message(*room, id, record)?;
+            }
+        }
+        builder.build()?;
+        Ok(())
+    }
+}
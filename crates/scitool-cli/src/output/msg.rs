@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::book::segment::SegmentKind;
+
 /// A message identifier.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageId {
@@ -17,6 +19,22 @@ pub struct Message {
     pub id: MessageId,
     pub talker: u8,
     pub text: String,
+    /// Format tokens in the text that are filled in at runtime.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub placeholders: Vec<String>,
+    /// The parts of the text to record separately, if the text was split.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    /// True if the line isn't in the original game, but was added for the
+    /// dub.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub added: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub text: String,
 }
 
 /// The top level structure for a message output file.
@@ -129,7 +129,7 @@ impl ScriptBuilder {
         StringRef(string_sym)
     }
 
-    pub fn add_export(&mut self, value: Value) -> (ExportRef, ExportBuilder) {
+    pub fn add_export(&mut self, value: Value) -> (ExportRef, ExportBuilder<'_>) {
         let export_sym = Symbol::new();
         let export = Export {
             index: None,
@@ -0,0 +1,274 @@
+//! Just enough of the PMachine instruction set to walk through a method's
+//! code, for analyses that look for simple patterns in it.
+//!
+//! Each opcode byte is the instruction kind shifted left by one, with the low
+//! bit set if the instruction's variable-width arguments are a byte wide
+//! rather than a word.
+
+/// An instruction that an analysis cares about. The rest are `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inst {
+    /// `ldi` or `pushi`: loads an immediate value.
+    Immediate(u16),
+    /// `eq?`: compares the top of the stack with the accumulator.
+    Eq,
+    /// `lofsa` or `lofss`: loads the address of an object or string.
+    LoadOffset(u16),
+    /// `callk`: calls a kernel function with the given number of arguments.
+    CallKernel {
+        func: u16,
+        argc: u16,
+    },
+    /// `call`: calls a procedure in the same script, at the given offset.
+    Call(usize),
+    Other,
+}
+
+/// The arguments of an instruction kind.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// A byte or a word, depending on the opcode's low bit.
+    Var,
+    Byte,
+}
+
+const BT: u8 = 0x17;
+const BNT: u8 = 0x18;
+const JMP: u8 = 0x19;
+const LDI: u8 = 0x1A;
+const PUSHI: u8 = 0x1C;
+const EQ: u8 = 0x0D;
+const CALL: u8 = 0x20;
+const CALLK: u8 = 0x21;
+const RET: u8 = 0x24;
+const LOFSA: u8 = 0x39;
+const LOFSS: u8 = 0x3A;
+
+/// The arguments of each instruction kind, or `None` for kinds that aren't
+/// defined.
+fn args_of(kind: u8) -> Option<&'static [Arg]> {
+    use Arg::{Byte, Var};
+    Some(match kind {
+        0x00..=0x16 | 0x1B | 0x1D | 0x1E | 0x24 | 0x2E | 0x30 | 0x3B..=0x3E => &[],
+        0x17..=0x1A | 0x1C | 0x1F | 0x28 | 0x2C | 0x31..=0x3A | 0x40..=0x7F => &[Var],
+        0x20..=0x22 | 0x2B => &[Var, Byte],
+        0x23 => &[Var, Var, Byte],
+        0x25 | 0x2A => &[Byte],
+        0x2D => &[Var, Var],
+        _ => return None,
+    })
+}
+
+/// Names of the instruction kinds below `0x40`, or `None` for kinds that
+/// aren't defined.
+const MNEMONICS: [Option<&str>; 0x40] = [
+    Some("bnot"),
+    Some("add"),
+    Some("sub"),
+    Some("mul"),
+    Some("div"),
+    Some("mod"),
+    Some("shr"),
+    Some("shl"),
+    Some("xor"),
+    Some("and"),
+    Some("or"),
+    Some("neg"),
+    Some("not"),
+    Some("eq?"),
+    Some("ne?"),
+    Some("gt?"),
+    Some("ge?"),
+    Some("lt?"),
+    Some("le?"),
+    Some("ugt?"),
+    Some("uge?"),
+    Some("ult?"),
+    Some("ule?"),
+    Some("bt"),
+    Some("bnt"),
+    Some("jmp"),
+    Some("ldi"),
+    Some("push"),
+    Some("pushi"),
+    Some("toss"),
+    Some("dup"),
+    Some("link"),
+    Some("call"),
+    Some("callk"),
+    Some("callb"),
+    Some("calle"),
+    Some("ret"),
+    Some("send"),
+    None,
+    None,
+    Some("class"),
+    None,
+    Some("self"),
+    Some("super"),
+    Some("&rest"),
+    Some("lea"),
+    Some("selfID"),
+    None,
+    Some("pprev"),
+    Some("pToa"),
+    Some("aTop"),
+    Some("pTos"),
+    Some("sTop"),
+    Some("ipToa"),
+    Some("dpToa"),
+    Some("ipTos"),
+    Some("dpTos"),
+    Some("lofsa"),
+    Some("lofss"),
+    Some("push0"),
+    Some("push1"),
+    Some("push2"),
+    Some("pushSelf"),
+    None,
+];
+
+/// A decoded instruction, with its raw operands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RawInst {
+    pub offset: usize,
+    /// The offset of the following instruction.
+    pub next: usize,
+    pub kind: u8,
+    pub args: Vec<u16>,
+    byte_args: bool,
+}
+
+impl RawInst {
+    /// Decodes the instruction at `pc`, if it is complete and defined.
+    pub fn decode(data: &[u8], pc: usize) -> Option<Self> {
+        let opcode = *data.get(pc)?;
+        let kind = opcode >> 1;
+        let byte_args = opcode & 1 != 0;
+        let arg_kinds = args_of(kind)?;
+        let mut args = Vec::with_capacity(arg_kinds.len());
+        let mut next = pc + 1;
+        for arg in arg_kinds {
+            let (value, size) = match (arg, byte_args) {
+                (Arg::Byte, _) | (Arg::Var, true) => (u16::from(*data.get(next)?), 1),
+                (Arg::Var, false) => {
+                    let bytes = data.get(next..next + 2)?;
+                    (u16::from_le_bytes([bytes[0], bytes[1]]), 2)
+                }
+            };
+            args.push(value);
+            next += size;
+        }
+        Some(RawInst {
+            offset: pc,
+            next,
+            kind,
+            args,
+            byte_args,
+        })
+    }
+
+    pub fn mnemonic(&self) -> String {
+        if let Some(mnemonic) = MNEMONICS.get(usize::from(self.kind)) {
+            return mnemonic.unwrap_or("?").to_string();
+        }
+        // The variable instructions: the operation, whether the value goes to
+        // the accumulator or the stack, the kind of variable, and whether the
+        // accumulator indexes it.
+        let index = self.kind - 0x40;
+        let operation = ["l", "s", "+", "-"][usize::from(index >> 4)];
+        let destination = if index & 0x04 != 0 { "s" } else { "a" };
+        let variable = ["g", "l", "t", "p"][usize::from(index & 0x03)];
+        let indexed = if index & 0x08 != 0 { "i" } else { "" };
+        format!("{operation}{destination}{variable}{indexed}")
+    }
+
+    /// The index of the local variable that a variable instruction uses.
+    pub fn local_var(&self) -> Option<u16> {
+        (self.kind >= 0x40 && (self.kind - 0x40) & 0x03 == 1).then(|| self.args[0])
+    }
+
+    /// The offset a branch or `call` goes to. These are relative to the
+    /// next instruction.
+    pub fn target(&self) -> Option<usize> {
+        if !matches!(self.kind, BT | BNT | JMP | CALL) {
+            return None;
+        }
+        let offset = if self.byte_args {
+            i32::from(self.args[0] as u8 as i8)
+        } else {
+            i32::from(self.args[0] as i16)
+        };
+        usize::try_from(self.next as i32 + offset).ok()
+    }
+
+    pub fn is_conditional_branch(&self) -> bool {
+        matches!(self.kind, BT | BNT)
+    }
+
+    /// Whether a conditional branch is taken when the accumulator is zero.
+    pub fn branches_if_false(&self) -> bool {
+        self.kind == BNT
+    }
+
+    pub fn is_jump(&self) -> bool {
+        self.kind == JMP
+    }
+
+    pub fn is_kernel_call(&self) -> bool {
+        self.kind == CALLK
+    }
+
+    fn inst(&self) -> Inst {
+        match self.kind {
+            LDI | PUSHI => {
+                // Byte immediates are sign extended.
+                let value = if self.byte_args {
+                    self.args[0] as u8 as i8 as i16 as u16
+                } else {
+                    self.args[0]
+                };
+                Inst::Immediate(value)
+            }
+            EQ => Inst::Eq,
+            LOFSA | LOFSS => Inst::LoadOffset(self.args[0]),
+            // The second argument is the size of the arguments in bytes.
+            CALLK => Inst::CallKernel {
+                func: self.args[0],
+                argc: self.args[1] / 2,
+            },
+            CALL => self.target().map_or(Inst::Other, Inst::Call),
+            _ => Inst::Other,
+        }
+    }
+}
+
+/// Decodes the instructions of the method starting at `start`, stopping at
+/// the `ret` that no branch jumps past. Stops early at anything that can't be
+/// decoded, as the analyses using this are best-effort.
+pub(crate) fn method_code(data: &[u8], start: usize) -> Vec<RawInst> {
+    let mut code = Vec::new();
+    let mut pc = start;
+    // The furthest offset that a branch seen so far can jump to.
+    let mut furthest_target = start;
+    while let Some(inst) = RawInst::decode(data, pc) {
+        if matches!(inst.kind, BT | BNT | JMP)
+            && let Some(target) = inst.target()
+        {
+            furthest_target = furthest_target.max(target);
+        }
+        let done = inst.kind == RET && inst.next > furthest_target;
+        pc = inst.next;
+        code.push(inst);
+        if done {
+            break;
+        }
+    }
+    code
+}
+
+/// The instructions of the method starting at `start`, as
+/// [`method_code`] decodes them, classified for analyses.
+pub(crate) fn method_insts(data: &[u8], start: usize) -> Vec<Inst> {
+    method_code(data, start).iter().map(RawInst::inst).collect()
+}
@@ -0,0 +1,587 @@
+//! Pseudo-code for a script's methods and procedures, in the style of SCI
+//! Studio source.
+//!
+//! Control flow is reconstructed from the branch targets: a forward
+//! conditional branch becomes an `if` (with an `else` when the skipped code
+//! ends by jumping past the rest), a backward jump closes a `while` loop, a
+//! backward conditional branch closes a `do` loop, and branches out of or to
+//! the top of the innermost loop become `break` and `continue`. Anything else
+//! is left as a `goto` to a label. Expressions aren't rebuilt: the
+//! instructions computing values are listed as they are, and conditions test
+//! the accumulator (`ACC`).
+//!
+//! Names from a [`SymbolFile`] replace the generated ones for labels,
+//! procedures and local variables, and its notes are added as comments.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use sci_resources::file::ResourceSet;
+
+use crate::{
+    ClassHierarchy, ScriptId, ScriptLoader,
+    code::{self, RawInst},
+    kernel_names,
+    mem_loader::LoadedScript,
+    symbols::{ScriptSymbols, SymbolFile},
+};
+
+/// A line of output, with the offset of the code it starts at.
+struct Line {
+    depth: usize,
+    offset: Option<usize>,
+    text: String,
+}
+
+/// The loop that `break` and `continue` refer to.
+#[derive(Clone, Copy)]
+struct Loop {
+    head: usize,
+    exit: usize,
+}
+
+/// Names the code's entry points, and formats its instructions.
+struct Names<'a> {
+    kernel: Option<&'a [String]>,
+    procedures: &'a BTreeMap<usize, String>,
+    symbols: Option<&'a ScriptSymbols>,
+}
+
+impl Names<'_> {
+    /// The label the user gave the code at `offset`, if any.
+    fn user_label(&self, offset: usize) -> Option<&str> {
+        let offset = u16::try_from(offset).ok()?;
+        self.symbols?.labels.get(&offset).map(String::as_str)
+    }
+
+    fn label(&self, offset: usize) -> String {
+        self.user_label(offset)
+            .map_or_else(|| format!("code_{offset:04x}"), str::to_string)
+    }
+
+    fn inst(&self, inst: &RawInst) -> String {
+        let mnemonic = inst.mnemonic();
+        let args = inst.args.iter().map(u16::to_string).collect::<Vec<_>>();
+        match (inst.target(), inst.is_kernel_call()) {
+            (Some(target), _) if !inst.is_conditional_branch() && !inst.is_jump() => {
+                let name = self
+                    .procedures
+                    .get(&target)
+                    .cloned()
+                    .unwrap_or_else(|| self.label(target));
+                format!("{mnemonic} {name} {}", args[1..].join(" "))
+            }
+            (Some(target), _) => format!("{mnemonic} {}", self.label(target)),
+            (None, true) => {
+                let name = self
+                    .kernel
+                    .and_then(|names| names.get(usize::from(inst.args[0])))
+                    .cloned()
+                    .unwrap_or_else(|| format!("kernel_{}", inst.args[0]));
+                format!("{mnemonic} {name} {}", args[1..].join(" "))
+            }
+            (None, false)
+                if let Some(name) = inst
+                    .local_var()
+                    .and_then(|index| self.symbols?.locals.get(&index)) =>
+            {
+                format!("{mnemonic} {name}")
+            }
+            (None, false) if args.is_empty() => mnemonic,
+            (None, false) => format!("{mnemonic} {}", args.join(" ")),
+        }
+    }
+}
+
+/// Reconstructs the control flow of one method or procedure.
+struct Structurer<'a> {
+    code: &'a [RawInst],
+    names: &'a Names<'a>,
+    lines: Vec<Line>,
+    /// The targets of the `goto`s emitted, which need labels.
+    goto_targets: BTreeSet<usize>,
+}
+
+impl Structurer<'_> {
+    /// The offset that the instruction at `index` starts at, or the end of
+    /// the code.
+    fn offset_at(&self, index: usize) -> usize {
+        self.code.get(index).map_or_else(
+            || self.code.last().map_or(0, |inst| inst.next),
+            |inst| inst.offset,
+        )
+    }
+
+    /// The index of the instruction at `offset`, if one starts there.
+    fn index_of(&self, offset: usize) -> Option<usize> {
+        if offset == self.offset_at(self.code.len()) {
+            return Some(self.code.len());
+        }
+        self.code
+            .binary_search_by_key(&offset, |inst| inst.offset)
+            .ok()
+    }
+
+    fn push(&mut self, depth: usize, offset: Option<usize>, text: impl Into<String>) {
+        self.lines.push(Line {
+            depth,
+            offset,
+            text: text.into(),
+        });
+    }
+
+    fn goto(&mut self, target: usize) -> String {
+        self.goto_targets.insert(target);
+        format!("(goto {})", self.names.label(target))
+    }
+
+    /// The instruction closing a loop headed at `index`: the last backward
+    /// branch before `end` that goes to it.
+    fn loop_end(&self, index: usize, end: usize) -> Option<usize> {
+        let head = self.code[index].offset;
+        (index..end).rev().find(|&j| {
+            let inst = &self.code[j];
+            (inst.is_jump() || inst.is_conditional_branch()) && inst.target() == Some(head)
+        })
+    }
+
+    /// Emits the instructions from `start` up to `end`, by index.
+    fn emit(&mut self, start: usize, end: usize, depth: usize, current_loop: Option<Loop>) {
+        let range_end = self.offset_at(end);
+        let mut i = start;
+        while i < end {
+            let inst = &self.code[i];
+            let offset = inst.offset;
+            // Other branches to the head of the current loop continue it,
+            // rather than starting a loop of their own.
+            let at_head = current_loop.is_some_and(|current| current.head == offset);
+            if !at_head && let Some(j) = self.loop_end(i, end) {
+                let closing = &self.code[j];
+                let inner = Loop {
+                    head: offset,
+                    exit: closing.next,
+                };
+                if closing.is_jump() {
+                    self.push(depth, Some(offset), "(while TRUE");
+                    self.emit(i, j, depth + 1, Some(inner));
+                    self.push(depth, None, ")");
+                } else {
+                    let condition = if closing.branches_if_false() {
+                        "(not ACC)"
+                    } else {
+                        "ACC"
+                    };
+                    self.push(depth, Some(offset), "(do");
+                    self.emit(i, j, depth + 1, Some(inner));
+                    self.push(depth, None, format!(") (while {condition})"));
+                }
+                i = j + 1;
+                continue;
+            }
+            let Some(target) = inst
+                .target()
+                .filter(|_| inst.is_jump() || inst.is_conditional_branch())
+            else {
+                let text = self.names.inst(inst);
+                self.push(depth, Some(offset), text);
+                i += 1;
+                continue;
+            };
+            // The condition under which the branch is taken.
+            let taken = if inst.branches_if_false() {
+                "(not ACC)"
+            } else {
+                "ACC"
+            };
+            let escape = match current_loop {
+                Some(current) if target == current.exit => Some("break"),
+                Some(current) if target == current.head => Some("continue"),
+                _ => None,
+            };
+            if inst.is_jump() {
+                let text = match escape {
+                    Some(escape) => format!("({escape})"),
+                    None => self.goto(target),
+                };
+                self.push(depth, Some(offset), text);
+                i += 1;
+                continue;
+            }
+            if let Some(escape) = escape {
+                let escape = if escape == "break" {
+                    "breakif"
+                } else {
+                    "contif"
+                };
+                self.push(depth, Some(offset), format!("({escape} {taken})"));
+                i += 1;
+                continue;
+            }
+            match self.index_of(target) {
+                Some(then_end) if target > offset && target <= range_end && then_end <= end => {
+                    // The code skipped by the branch runs when it isn't taken.
+                    let condition = if inst.branches_if_false() {
+                        "ACC"
+                    } else {
+                        "(not ACC)"
+                    };
+                    self.push(depth, Some(offset), format!("(if {condition}"));
+                    let else_end = self.code[i + 1..then_end]
+                        .last()
+                        .filter(|last| last.is_jump())
+                        .and_then(|last| last.target())
+                        .filter(|&after| after > target && after <= range_end)
+                        .and_then(|after| self.index_of(after));
+                    match else_end {
+                        Some(else_end) => {
+                            self.emit(i + 1, then_end - 1, depth + 1, current_loop);
+                            self.push(depth, None, "else");
+                            self.emit(then_end, else_end, depth + 1, current_loop);
+                            i = else_end;
+                        }
+                        None => {
+                            self.emit(i + 1, then_end, depth + 1, current_loop);
+                            i = then_end;
+                        }
+                    }
+                    self.push(depth, None, ")");
+                }
+                _ => {
+                    let goto = self.goto(target);
+                    self.push(depth, Some(offset), format!("(if {taken} {goto})"));
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Writes the lines, with labels before the `goto` targets and the code
+    /// the user labelled.
+    fn write_to(&self, out: &mut String, base_depth: usize) {
+        let mut labelled = BTreeSet::new();
+        for line in &self.lines {
+            if let Some(offset) = line.offset
+                && (self.goto_targets.contains(&offset) || self.names.user_label(offset).is_some())
+                && labelled.insert(offset)
+            {
+                let _ = writeln!(
+                    out,
+                    "{}{}:",
+                    "\t".repeat(base_depth),
+                    self.names.label(offset)
+                );
+            }
+            let _ = writeln!(out, "{}{}", "\t".repeat(base_depth + line.depth), line.text);
+        }
+        // Targets that no line starts at, e.g. in the middle of an
+        // instruction, are noted so the gotos aren't left dangling.
+        for target in self.goto_targets.difference(&labelled) {
+            let _ = writeln!(
+                out,
+                "{}; {} is not at an instruction",
+                "\t".repeat(base_depth),
+                self.names.label(*target)
+            );
+        }
+    }
+}
+
+/// Writes the code starting at `start`, structured or as a flat listing.
+fn write_code(
+    out: &mut String,
+    data: &[u8],
+    start: usize,
+    names: &Names,
+    flat: bool,
+    depth: usize,
+) {
+    let code = code::method_code(data, start);
+    if flat {
+        let targets: BTreeSet<usize> = code
+            .iter()
+            .filter(|inst| inst.is_jump() || inst.is_conditional_branch())
+            .filter_map(RawInst::target)
+            .collect();
+        for inst in &code {
+            if targets.contains(&inst.offset) || names.user_label(inst.offset).is_some() {
+                let _ = writeln!(out, "{}{}:", "\t".repeat(depth), names.label(inst.offset));
+            }
+            let _ = writeln!(
+                out,
+                "{}{:04x}: {}",
+                "\t".repeat(depth),
+                inst.offset,
+                names.inst(inst)
+            );
+        }
+        return;
+    }
+    let mut structurer = Structurer {
+        code: &code,
+        names,
+        lines: Vec::new(),
+        goto_targets: BTreeSet::new(),
+    };
+    structurer.emit(0, code.len(), 0, None);
+    structurer.write_to(out, depth);
+}
+
+/// Names the script's procedures: the exported ones as SCI Studio does, and
+/// the ones only called from within the script by their offsets, unless the
+/// user labelled them.
+fn procedure_names(
+    script_num: u16,
+    script: &LoadedScript,
+    symbols: Option<&ScriptSymbols>,
+) -> BTreeMap<usize, String> {
+    let heap_offset = script.heap_offset();
+    let is_object = |offset: u16| {
+        script.objects().any(|object| {
+            object.address() == offset || object.address() == offset.wrapping_add(heap_offset)
+        })
+    };
+    let mut names = BTreeMap::new();
+    for (index, &offset) in script.exports().iter().enumerate() {
+        if offset != 0 && offset < heap_offset && !is_object(offset) {
+            names
+                .entry(usize::from(offset))
+                .or_insert_with(|| format!("proc{script_num}_{index}"));
+        }
+    }
+    let mut pending: Vec<usize> = script
+        .objects()
+        .flat_map(|object| object.method_offsets().map(|(_, offset)| offset.into()))
+        .chain(names.keys().copied())
+        .collect();
+    let mut visited = BTreeSet::new();
+    while let Some(start) = pending.pop() {
+        if !visited.insert(start) {
+            continue;
+        }
+        for inst in code::method_insts(script.data(), start) {
+            if let code::Inst::Call(target) = inst
+                && target < usize::from(heap_offset)
+            {
+                names
+                    .entry(target)
+                    .or_insert_with(|| format!("localproc_{target:04x}"));
+                pending.push(target);
+            }
+        }
+    }
+    if let Some(symbols) = symbols {
+        for (offset, name) in &mut names {
+            if let Some(label) = u16::try_from(*offset)
+                .ok()
+                .and_then(|offset| symbols.labels.get(&offset))
+            {
+                name.clone_from(label);
+            }
+        }
+    }
+    names
+}
+
+/// Writes pseudo-code for the objects' methods and the procedures of a
+/// script, with the control flow structured, or as a flat listing of the
+/// instructions if `flat` is set.
+pub fn decompile_script(
+    resources: &ResourceSet,
+    script_num: u16,
+    symbols: &SymbolFile,
+    flat: bool,
+) -> anyhow::Result<String> {
+    let loader = ScriptLoader::load_from(resources)?;
+    let script = loader
+        .loaded_scripts
+        .get(&ScriptId(script_num))
+        .ok_or_else(|| anyhow::anyhow!("The game has no script {script_num}"))?;
+    let kernel = kernel_names(resources)?;
+    let script_symbols = symbols.script(script_num);
+    let procedures = procedure_names(script_num, script, script_symbols);
+    let names = Names {
+        kernel: kernel.as_deref(),
+        procedures: &procedures,
+        symbols: script_symbols,
+    };
+    let hierarchy = ClassHierarchy::new(&loader);
+    let class_name = |species: u16| {
+        hierarchy
+            .classes
+            .get(&species)
+            .and_then(|(name, _)| *name)
+            .map_or_else(|| format!("class_{species}"), str::to_string)
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "(script# {script_num})");
+    if let Some(name) = script_symbols.and_then(|symbols| symbols.name.as_ref()) {
+        let _ = writeln!(out, "; {name}");
+    }
+    for (offset, name) in &procedures {
+        let _ = writeln!(out, "\n(procedure ({name})");
+        write_code(&mut out, script.data(), *offset, &names, flat, 1);
+        let _ = writeln!(out, ")");
+    }
+    for object in script.objects() {
+        let kind = if object.is_class() {
+            "class"
+        } else {
+            "instance"
+        };
+        let name = object
+            .name()
+            .map_or_else(|| format!("obj_{:04x}", object.address()), str::to_string);
+        out.push('\n');
+        if let Some(note) = script_symbols.and_then(|symbols| symbols.objects.get(&name)) {
+            for line in note.lines() {
+                let _ = writeln!(out, "; {line}");
+            }
+        }
+        let _ = writeln!(
+            out,
+            "({kind} {name} of {}",
+            class_name(object.super_class())
+        );
+        let mut methods: Vec<_> = object.method_offsets().collect();
+        methods.sort_by_key(|(_, offset)| *offset);
+        for (selector, offset) in methods {
+            let _ = writeln!(out, "\t(method ({})", selector.name());
+            write_code(&mut out, script.data(), offset.into(), &names, flat, 2);
+            let _ = writeln!(out, "\t)");
+        }
+        let _ = writeln!(out, ")");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Structures the code in `data`, with no names besides the generated
+    /// ones. Branches and immediates use byte arguments.
+    fn structure(data: &[u8]) -> String {
+        let procedures = BTreeMap::new();
+        let names = Names {
+            kernel: None,
+            procedures: &procedures,
+            symbols: None,
+        };
+        let mut out = String::new();
+        write_code(&mut out, data, 0, &names, false, 0);
+        out
+    }
+
+    const LDI: u8 = 0x35;
+    const BT: u8 = 0x2F;
+    const BNT: u8 = 0x31;
+    const JMP: u8 = 0x33;
+    const PUSH0: u8 = 0x76;
+    const PUSH1: u8 = 0x78;
+    const RET: u8 = 0x48;
+
+    #[test]
+    fn test_if_else() {
+        let code = [
+            LDI, 1, // 0
+            BNT, 3,     // 2: to 7
+            PUSH0, // 4
+            JMP, 1,     // 5: to 8
+            PUSH1, // 7
+            RET,   // 8
+        ];
+        assert_eq!(
+            structure(&code),
+            "ldi 1\n(if ACC\n\tpush0\nelse\n\tpush1\n)\nret\n"
+        );
+    }
+
+    #[test]
+    fn test_if() {
+        let code = [
+            LDI, 1, // 0
+            BT, 1,     // 2: to 5
+            PUSH0, // 4
+            RET,   // 5
+        ];
+        assert_eq!(structure(&code), "ldi 1\n(if (not ACC)\n\tpush0\n)\nret\n");
+    }
+
+    #[test]
+    fn test_while() {
+        let code = [
+            PUSH0, // 0
+            LDI, 1, // 1
+            BNT, 3,     // 3: to 8
+            PUSH1, // 5
+            JMP, 0xF8, // 6: to 0
+            RET,  // 8
+        ];
+        assert_eq!(
+            structure(&code),
+            "(while TRUE\n\tpush0\n\tldi 1\n\t(breakif (not ACC))\n\tpush1\n)\nret\n"
+        );
+    }
+
+    #[test]
+    fn test_do() {
+        let code = [
+            PUSH0, // 0
+            LDI, 1, // 1
+            BT, 0xFB, // 3: to 0
+            RET,  // 5
+        ];
+        assert_eq!(
+            structure(&code),
+            "(do\n\tpush0\n\tldi 1\n) (while ACC)\nret\n"
+        );
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let code = [
+            PUSH0, // 0
+            LDI, 1, // 1
+            BT, 0xFB, // 3: to 0
+            LDI, 2, // 5
+            BNT, 4, // 7: to 13
+            JMP, 2, // 9: to 13
+            JMP, 0xF3, // 11: to 0
+            RET,  // 13
+        ];
+        assert_eq!(
+            structure(&code),
+            "(while TRUE\n\tpush0\n\tldi 1\n\t(contif ACC)\n\tldi 2\n\
+             \t(breakif (not ACC))\n\t(break)\n)\nret\n"
+        );
+    }
+
+    #[test]
+    fn test_goto() {
+        let code = [
+            JMP, 1,     // 0: to 3
+            PUSH0, // 2
+            PUSH1, // 3
+            RET,   // 4
+        ];
+        assert_eq!(
+            structure(&code),
+            "(goto code_0003)\npush0\ncode_0003:\npush1\nret\n"
+        );
+    }
+
+    #[test]
+    fn test_goto_into_instruction() {
+        let code = [
+            LDI, 1, // 0
+            BT, 1, // 2: to 5, inside the next instruction
+            LDI, 2,   // 4
+            RET, // 6
+        ];
+        assert_eq!(
+            structure(&code),
+            "ldi 1\n(if ACC (goto code_0005))\nldi 2\nret\n; code_0005 is not at an instruction\n"
+        );
+    }
+}
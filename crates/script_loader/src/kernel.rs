@@ -0,0 +1,93 @@
+//! Which kernel functions the game's scripts call.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use sci_resources::{ResourceId, ResourceType, file::ResourceSet};
+
+use crate::{ScriptLoader, code};
+
+/// The vocab resource with the names of the kernel functions.
+const KERNEL_NAMES_VOCAB_NUM: u16 = 999;
+
+fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow::anyhow!("Kernel names are truncated at {offset}"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Parses the kernel names vocab: a count, a table of offsets, and at each
+/// offset a length-prefixed name.
+fn parse_kernel_names(data: &[u8]) -> anyhow::Result<Vec<String>> {
+    let count = read_u16(data, 0)?;
+    (0..usize::from(count))
+        .map(|index| {
+            let offset = usize::from(read_u16(data, 2 + index * 2)?);
+            let len = usize::from(read_u16(data, offset)?);
+            let name = data
+                .get(offset + 2..offset + 2 + len)
+                .ok_or_else(|| anyhow::anyhow!("Kernel name {index} is truncated"))?;
+            Ok(String::from_utf8(name.to_vec())?)
+        })
+        .collect()
+}
+
+/// Reads the names of the kernel functions, by number, if the game has them.
+pub fn kernel_names(resources: &ResourceSet) -> anyhow::Result<Option<Vec<String>>> {
+    let Some(resource) = resources.get_resource(&ResourceId::new(
+        ResourceType::Vocab,
+        KERNEL_NAMES_VOCAB_NUM,
+    )) else {
+        return Ok(None);
+    };
+    Ok(Some(parse_kernel_names(&resource.load_data()?)?))
+}
+
+/// Counts the call sites of each kernel function in each script, by walking
+/// the code of every method and exported procedure, and of the procedures in
+/// the same script that they call. Code only reached in other ways (e.g.
+/// through a jump table) is missed.
+pub fn find_kernel_calls(
+    resources: &ResourceSet,
+) -> anyhow::Result<BTreeMap<u16, BTreeMap<u16, usize>>> {
+    let loader = ScriptLoader::load_from(resources)?;
+    let mut calls = BTreeMap::new();
+    for (script_id, script) in loader.loaded_scripts() {
+        let heap_offset = script.heap_offset();
+        let is_object = |offset: u16| {
+            script.objects().any(|object| {
+                object.address() == offset || object.address() == offset.wrapping_add(heap_offset)
+            })
+        };
+        let mut pending: Vec<usize> = script
+            .objects()
+            .flat_map(|object| object.method_offsets().map(|(_, offset)| offset.into()))
+            .collect();
+        // Exports that aren't objects are procedures.
+        pending.extend(
+            script
+                .exports()
+                .iter()
+                .filter(|&&offset| offset != 0 && offset < heap_offset && !is_object(offset))
+                .map(|&offset| usize::from(offset)),
+        );
+        let mut visited = BTreeSet::new();
+        let mut counts: BTreeMap<u16, usize> = BTreeMap::new();
+        while let Some(start) = pending.pop() {
+            if !visited.insert(start) {
+                continue;
+            }
+            for inst in code::method_insts(script.data(), start) {
+                match inst {
+                    code::Inst::CallKernel { func, .. } => *counts.entry(func).or_default() += 1,
+                    code::Inst::Call(target) if target < usize::from(heap_offset) => {
+                        pending.push(target);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        calls.insert(script_id.num(), counts);
+    }
+    Ok(calls)
+}
@@ -1,12 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use mem_loader::LoadedScript;
 use sci_resources::{ResourceType, file::ResourceSet};
 
+mod code;
+mod decompile;
+mod kernel;
 mod mem_loader;
+mod savegame;
 mod selectors;
+mod strings;
+mod symbols;
 
+pub use decompile::decompile_script;
+pub use kernel::{find_kernel_calls, kernel_names};
 pub use mem_loader::Object;
+pub use savegame::{Reg, SaveMetadata, ScummVmSave, Segment};
+pub use strings::{
+    HeapString, OwnedHeapString, heap_strings, owned_heap_strings, replace_heap_string,
+};
+pub use symbols::{ScriptSymbols, SymbolFile};
 
 const SELECTOR_TABLE_VOCAB_NUM: u16 = 997;
 
@@ -91,6 +104,283 @@ impl ScriptLoader {
     }
 }
 
+/// The names of the classes that rooms are instances of.
+const ROOM_CLASS_NAMES: &[&str] = &["Room", "Rm"];
+
+/// The names of the classes of icon bar icons and inventory items, whose
+/// `message` property is the verb they use.
+const VERB_CLASS_NAMES: &[&str] = &["IconI", "IconItem", "InvI", "InvItem"];
+
+/// Words in the names of icons and inventory items that don't describe the
+/// verb.
+const VERB_NAME_NOISE: &[&str] = &["icon", "inv", "item"];
+
+/// The superclasses of every class in the game's scripts.
+struct ClassHierarchy<'a> {
+    classes: HashMap<u16, (Option<&'a str>, u16)>,
+}
+
+impl<'a> ClassHierarchy<'a> {
+    fn new(loader: &'a ScriptLoader) -> Self {
+        let mut classes = HashMap::new();
+        for (_, loaded_script) in loader.loaded_scripts() {
+            for object in loaded_script.objects().filter(|object| object.is_class()) {
+                classes.insert(object.species(), (object.name(), object.super_class()));
+            }
+        }
+        Self { classes }
+    }
+
+    /// Whether the class, or one of its superclasses, has one of the names.
+    fn descends_from(&self, mut species: u16, class_names: &[&str]) -> bool {
+        // Bound the walk up the hierarchy, in case it has a cycle.
+        for _ in 0..self.classes.len() {
+            let Some((name, super_class)) = self.classes.get(&species) else {
+                return false;
+            };
+            if name.is_some_and(|name| class_names.contains(&name)) {
+                return true;
+            }
+            species = *super_class;
+        }
+        false
+    }
+}
+
+/// Finds the name of each room's object: the instance of a room class in the
+/// script with the room's number. Rooms whose script has no such instance,
+/// or whose instance has no name, are left out.
+pub fn find_room_names(resources: &ResourceSet) -> anyhow::Result<BTreeMap<u16, String>> {
+    let loader = ScriptLoader::load_from(resources)?;
+    let hierarchy = ClassHierarchy::new(&loader);
+
+    let mut names = BTreeMap::new();
+    for (script_id, loaded_script) in loader.loaded_scripts() {
+        let name = loaded_script
+            .objects()
+            .find(|object| {
+                !object.is_class()
+                    && hierarchy.descends_from(object.super_class(), ROOM_CLASS_NAMES)
+            })
+            .and_then(|object| object.name());
+        if let Some(name) = name {
+            names.insert(script_id.num(), name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Finds names for verbs from the icons and inventory items that use them,
+/// e.g. `iconLook` names its verb "Look" and `invRayGun` names its verb
+/// "Ray Gun". Where several objects use a verb, the one in the
+/// lowest-numbered script names it. Verbs whose objects have no usable name
+/// are left out.
+pub fn find_verb_names(resources: &ResourceSet) -> anyhow::Result<BTreeMap<u16, String>> {
+    let loader = ScriptLoader::load_from(resources)?;
+    let hierarchy = ClassHierarchy::new(&loader);
+
+    let mut scripts: Vec<_> = loader.loaded_scripts().collect();
+    scripts.sort_by_key(|(script_id, _)| *script_id);
+    let mut names = BTreeMap::new();
+    for (_, loaded_script) in scripts {
+        for object in loaded_script.objects() {
+            if object.is_class() || !hierarchy.descends_from(object.super_class(), VERB_CLASS_NAMES)
+            {
+                continue;
+            }
+            let Some(verb) = object
+                .get_property_by_name("message")
+                .filter(|&verb| verb != 0)
+            else {
+                continue;
+            };
+            if let Some(name) = object
+                .name()
+                .and_then(|name| readable_object_name(name, VERB_NAME_NOISE))
+            {
+                names.entry(verb).or_insert(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The names of the classes of objects that speak messages. Talkers, which
+/// have portraits, are a kind of narrator.
+const NARRATOR_CLASS_NAME: &str = "Narrator";
+const TALKER_CLASS_NAME: &str = "Talker";
+
+/// Words in the names of talkers that don't name the character.
+const TALKER_NAME_NOISE: &[&str] = &["talker", "tlkr"];
+
+/// The selector of the property holding a talker's portrait view.
+const VIEW_SELECTOR: &str = "view";
+
+/// The selector of the method that picks the object to speak for a talker
+/// number.
+const FIND_TALKER_SELECTOR: &str = "findTalker";
+
+/// The number of the `ScriptID` kernel function, which gets an object that
+/// another script exports.
+const KERNEL_SCRIPT_ID: u16 = 2;
+
+/// An object that speaks the messages of a talker number.
+#[derive(Debug, Clone)]
+pub struct TalkerObject {
+    /// The name of the object, e.g. `rogerTalker`.
+    pub object_name: String,
+    /// A readable name for its character, e.g. "Roger".
+    pub character_name: String,
+    /// Whether the object is a plain narrator, rather than a talker with a
+    /// portrait.
+    pub is_narrator: bool,
+    /// The view with the talker's portrait. Narrators have none.
+    pub view: Option<u16>,
+}
+
+/// Guesses which object speaks for each talker number, by scanning the
+/// `findTalker` methods of the game's scripts for the `switch` that picks
+/// the object: a comparison with the number, followed by a load of the
+/// object, either from the same script or through `ScriptID`. Talker
+/// numbers whose objects can't be found this way are left out.
+pub fn find_talkers(resources: &ResourceSet) -> anyhow::Result<BTreeMap<u16, TalkerObject>> {
+    let loader = ScriptLoader::load_from(resources)?;
+    let hierarchy = ClassHierarchy::new(&loader);
+    let object_at = |script_id: ScriptId, offset: u16| {
+        let script = loader.loaded_scripts.get(&script_id)?;
+        script.objects().find(|object| {
+            object.address() == offset
+                || object.address() == offset.wrapping_add(script.heap_offset())
+        })
+    };
+    let exported_object = |script_num: u16, export: u16| {
+        let script_id = ScriptId(script_num);
+        let offset = *loader
+            .loaded_scripts
+            .get(&script_id)?
+            .exports()
+            .get(usize::from(export))?;
+        object_at(script_id, offset)
+    };
+
+    let mut scripts: Vec<_> = loader.loaded_scripts().collect();
+    scripts.sort_by_key(|(script_id, _)| *script_id);
+    let mut talkers = BTreeMap::new();
+    for (script_id, loaded_script) in scripts {
+        for object in loaded_script.objects() {
+            let Some((_, method_offset)) = object
+                .method_offsets()
+                .find(|(selector, _)| selector.name() == FIND_TALKER_SELECTOR)
+            else {
+                continue;
+            };
+            let insts = code::method_insts(loaded_script.data(), method_offset.into());
+            // The number being matched, and the immediates loaded since.
+            let mut case = None;
+            let mut immediates = Vec::new();
+            for (i, inst) in insts.iter().enumerate() {
+                let found = match *inst {
+                    code::Inst::Immediate(value) => {
+                        immediates.push(value);
+                        None
+                    }
+                    code::Inst::Eq => {
+                        case = match i.checked_sub(1).map(|prev| insts[prev]) {
+                            Some(code::Inst::Immediate(value)) => Some(value),
+                            _ => None,
+                        };
+                        immediates.clear();
+                        None
+                    }
+                    code::Inst::LoadOffset(offset) => object_at(script_id, offset),
+                    code::Inst::CallKernel { func, argc } if func == KERNEL_SCRIPT_ID => {
+                        let args = immediates
+                            .len()
+                            .checked_sub(argc.into())
+                            .map(|start| &immediates[start..]);
+                        match args {
+                            Some(&[script_num]) => exported_object(script_num, 0),
+                            Some(&[script_num, export]) => exported_object(script_num, export),
+                            _ => None,
+                        }
+                    }
+                    code::Inst::CallKernel { .. } | code::Inst::Call(_) | code::Inst::Other => None,
+                };
+                let Some(found) = found else {
+                    continue;
+                };
+                if !hierarchy.descends_from(found.super_class(), &[NARRATOR_CLASS_NAME]) {
+                    continue;
+                }
+                let (Some(talker), Some(object_name)) = (case.take(), found.name()) else {
+                    continue;
+                };
+                let is_narrator =
+                    !hierarchy.descends_from(found.super_class(), &[TALKER_CLASS_NAME]);
+                talkers.entry(talker).or_insert_with(|| TalkerObject {
+                    object_name: object_name.to_string(),
+                    character_name: readable_object_name(object_name, TALKER_NAME_NOISE)
+                        .unwrap_or_else(|| object_name.to_string()),
+                    is_narrator,
+                    // Unset views are -1.
+                    view: found
+                        .get_property_by_name(VIEW_SELECTOR)
+                        .filter(|&view| !is_narrator && view != 0xFFFF),
+                });
+            }
+        }
+    }
+    Ok(talkers)
+}
+
+/// Turns an object name like `iconLook` or `Ray_Gun` into readable words, if
+/// anything is left of it once the noise words, any prefix before them, and
+/// numbers are dropped.
+fn readable_object_name(object_name: &str, noise: &[&str]) -> Option<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut prev: Option<char> = None;
+    for ch in object_name.chars() {
+        // Words break at punctuation, before capitals, and between letters
+        // and numbers.
+        let starts_word = prev.is_some_and(|prev| {
+            (ch.is_uppercase() && prev.is_lowercase())
+                || ch.is_ascii_digit() != prev.is_ascii_digit()
+        });
+        if !ch.is_alphanumeric() || starts_word {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        }
+        if ch.is_alphanumeric() {
+            word.push(ch);
+        }
+        prev = Some(ch);
+    }
+    words.extend((!word.is_empty()).then_some(word));
+
+    // The words after a noise word name the verb (`sq5IconWalk`), unless it
+    // ends the name (`rayGunItem`).
+    let is_noise = |word: &String| noise.contains(&word.to_lowercase().as_str());
+    if let Some(last_noise) = words.iter().rposition(is_noise) {
+        if last_noise + 1 < words.len() {
+            words.drain(..=last_noise);
+        } else {
+            words.truncate(last_noise);
+        }
+    }
+    let words: Vec<String> = words
+        .into_iter()
+        .filter(|word| !is_noise(word) && !word.chars().all(|ch| ch.is_ascii_digit()))
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
 pub struct ClassDeclSet {
     classes: HashMap<Species, ClassData>,
 }
@@ -113,7 +403,7 @@ impl ClassDeclSet {
         Ok(Self { classes })
     }
 
-    pub fn classes(&self) -> impl Iterator<Item = Class> {
+    pub fn classes(&self) -> impl Iterator<Item = Class<'_>> {
         self.classes.values().map(|data| Class { root: self, data })
     }
 }
@@ -114,7 +114,6 @@ pub struct Script {
     data: MemBlock,
     #[expect(dead_code)]
     relocations: MemBlock,
-    #[expect(dead_code)]
     exports: Vec<u16>,
 }
 
@@ -146,11 +145,8 @@ where
 }
 
 pub struct LoadedScript {
-    #[expect(dead_code)]
     heap_offset: u16,
-    #[expect(dead_code)]
     full_buffer: MemBlock,
-    #[expect(dead_code)]
     script: Script,
     heap: Heap,
 }
@@ -202,4 +198,21 @@ impl LoadedScript {
     pub fn objects(&self) -> impl Iterator<Item = &Object> {
         self.heap.objects.iter()
     }
+
+    /// The script followed by its heap, with relocations applied. Object
+    /// addresses and method offsets are offsets into this.
+    pub fn data(&self) -> &[u8] {
+        &self.full_buffer
+    }
+
+    /// The offsets that the script exports, in [`LoadedScript::data`] or its
+    /// heap.
+    pub fn exports(&self) -> &[u16] {
+        &self.script.exports
+    }
+
+    /// The offset of the heap in [`LoadedScript::data`].
+    pub fn heap_offset(&self) -> u16 {
+        self.heap_offset
+    }
 }
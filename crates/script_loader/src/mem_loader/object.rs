@@ -7,7 +7,6 @@ use crate::selectors::{Selector, SelectorTable};
 
 struct MethodRecord {
     selector_id: u16,
-    #[expect(dead_code)]
     method_offset: u16,
 }
 
@@ -86,15 +85,23 @@ impl ObjectData {
     }
 
     pub fn get_method_selectors(&self) -> impl Iterator<Item = &Selector> {
+        self.get_methods().map(|(selector, _)| selector)
+    }
+
+    /// The methods, with the offsets of their code in the loaded script.
+    pub fn get_methods(&self) -> impl Iterator<Item = (&Selector, u16)> {
         self.method_records
             .clone()
             .split_values::<MethodRecord>()
             .unwrap()
             .into_iter()
             .map(|record| {
-                self.selector_table
-                    .get_selector_by_id(record.selector_id)
-                    .unwrap()
+                (
+                    self.selector_table
+                        .get_selector_by_id(record.selector_id)
+                        .unwrap(),
+                    record.method_offset,
+                )
             })
     }
 
@@ -111,6 +118,8 @@ impl ObjectData {
 
 pub struct Object {
     object_data: ObjectData,
+    /// The offset of the object in the loaded script.
+    address: u16,
 
     // Standard property values
     class_script: u16,
@@ -126,6 +135,7 @@ impl Object {
         loaded_data: &MemBlock,
         obj_data: MemBlock,
     ) -> anyhow::Result<Object> {
+        let address = loaded_data.offset_in(&obj_data).try_into()?;
         let object_data = ObjectData::from_block(selector_table, loaded_data, obj_data)?;
 
         // Read the standard properties.
@@ -175,6 +185,7 @@ impl Object {
 
         Ok(Self {
             object_data,
+            address,
             class_script,
             script,
             super_class,
@@ -199,6 +210,15 @@ impl Object {
         self.object_data.get_method_selectors()
     }
 
+    /// The methods, with the offsets of their code in the loaded script.
+    pub fn method_offsets(&self) -> impl Iterator<Item = (&Selector, u16)> {
+        self.object_data.get_methods()
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
     pub fn properties(&self) -> impl Iterator<Item = (&Selector, u16)> {
         assert!(self.is_class());
         self.object_data.properties()
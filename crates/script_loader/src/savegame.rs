@@ -0,0 +1,594 @@
+//! Reading ScummVM's save files, for the state of a game's globals at the
+//! point it was saved.
+//!
+//! A save starts with metadata (the save's name, the save format version,
+//! the game version, the date and the play time), then a thumbnail, then the
+//! engine state, whose main part is the serialized segment table. The
+//! globals are the locals of script 0, kept in their own locals segment.
+//!
+//! Only saves of SCI0 to SCI1.1 games are read: SCI32 games save other
+//! state before the segment table and have segment types of their own.
+//! Nothing past the segment table is read.
+//!
+//! The layout follows ScummVM's save code. The tests use saves built by hand
+//! to that layout; no save written by ScummVM has been checked against it.
+
+// The save format versions whose layout the reader follows.
+const MIN_VERSION: u32 = 14;
+const MAX_VERSION: u32 = 46;
+
+const THUMBNAIL_TAGS: [&[u8; 4]; 2] = [b"THMB", b"BMHT"];
+
+/// The metadata at the start of a save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveMetadata {
+    /// The name the player gave the save.
+    pub name: String,
+    /// The version of ScummVM's save format.
+    pub version: u32,
+    /// The game's version string, as the game reported it.
+    pub game_version: String,
+    /// The date of the save, packed as day << 24 | month << 16 | year.
+    pub date: u32,
+    /// The time of the save, packed as hour << 16 | minute << 8 | second.
+    pub time: u32,
+    /// The offset of the game object in script 0's heap. Zero before
+    /// version 22.
+    pub game_object_offset: u16,
+    /// The size of script 0, to check that the save matches the game. Zero
+    /// before version 22.
+    pub script0_size: u16,
+    /// The play time in seconds. Zero before version 26.
+    pub play_time: u32,
+}
+
+impl SaveMetadata {
+    /// The date of the save, as (year, month, day).
+    pub fn ymd(&self) -> (u16, u8, u8) {
+        (
+            self.date as u16,
+            (self.date >> 16) as u8,
+            (self.date >> 24) as u8,
+        )
+    }
+
+    /// The time of the save, as (hour, minute, second).
+    pub fn hms(&self) -> (u8, u8, u8) {
+        (
+            (self.time >> 16) as u8,
+            (self.time >> 8) as u8,
+            self.time as u8,
+        )
+    }
+}
+
+/// A value in the interpreter's memory: a segment and an offset. Numbers
+/// have segment 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Reg {
+    pub segment: u16,
+    pub offset: u16,
+}
+
+impl Reg {
+    /// The value as a number, if it isn't a pointer.
+    pub fn as_number(self) -> Option<u16> {
+        (self.segment == 0).then_some(self.offset)
+    }
+}
+
+impl std::fmt::Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.as_number() {
+            Some(value) => write!(f, "{value}"),
+            None => write!(f, "{:04x}:{:04x}", self.segment, self.offset),
+        }
+    }
+}
+
+/// A segment in a save's segment table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// A loaded script, with the segment that holds its locals (0 if it has
+    /// none).
+    Script {
+        number: u16,
+        locals_segment: u16,
+    },
+    /// The clones of objects.
+    Clones,
+    /// A script's local variables.
+    Locals {
+        script: u16,
+        values: Vec<Reg>,
+    },
+    /// The stack, whose contents aren't saved.
+    Stack,
+    Lists,
+    Nodes,
+    /// A block of dynamic memory, with ScummVM's description of it.
+    DynMem {
+        description: String,
+    },
+}
+
+/// A save written by ScummVM.
+#[derive(Debug, Clone)]
+pub struct ScummVmSave {
+    pub metadata: SaveMetadata,
+    /// The segments, by segment number. Free and hunk segments are `None`.
+    pub segments: Vec<Option<Segment>>,
+}
+
+impl ScummVmSave {
+    /// Reads a save. See the module docs for the saves that can be read.
+    pub fn read(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = SaveReader { data, pos: 0 };
+        let metadata = reader.metadata()?;
+        reader.skip_thumbnail()?;
+        let segments = reader.engine_state(metadata.version)?;
+        Ok(ScummVmSave { metadata, segments })
+    }
+
+    /// The values of the game's globals: the locals of script 0.
+    pub fn globals(&self) -> anyhow::Result<&[Reg]> {
+        let locals_segment = self
+            .segments
+            .iter()
+            .find_map(|segment| match segment {
+                Some(Segment::Script {
+                    number: 0,
+                    locals_segment,
+                }) => Some(*locals_segment),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("The save has no script 0"))?;
+        match self.segments.get(usize::from(locals_segment)) {
+            Some(Some(Segment::Locals { script: 0, values })) => Ok(values),
+            _ => anyhow::bail!(
+                "Script 0's locals segment {locals_segment} isn't a locals segment for script 0"
+            ),
+        }
+    }
+}
+
+struct SaveReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl SaveReader<'_> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow::anyhow!("Save is truncated at offset {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_le(&mut self) -> anyhow::Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_le(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32_be(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a number that ScummVM stores as a signed 32-bit value, but which
+    /// must fit in 16 bits (a script or segment number).
+    fn u16_from_i32(&mut self, what: &str) -> anyhow::Result<u16> {
+        let value = self.u32_le()? as i32;
+        u16::try_from(value).map_err(|_| anyhow::anyhow!("Bad {what} {value} in save"))
+    }
+
+    /// Reads a null-terminated string, as Latin-1.
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.data[self.pos.min(self.data.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow::anyhow!("Unterminated string at offset {}", self.pos))?;
+        let text = self.bytes(len)?.iter().map(|&b| char::from(b)).collect();
+        self.pos += 1;
+        Ok(text)
+    }
+
+    fn reg(&mut self) -> anyhow::Result<Reg> {
+        Ok(Reg {
+            segment: self.u16_le()?,
+            offset: self.u16_le()?,
+        })
+    }
+
+    fn regs(&mut self) -> anyhow::Result<Vec<Reg>> {
+        let len = self.u32_le()? as usize;
+        anyhow::ensure!(
+            len * 4 <= self.data.len() - self.pos,
+            "Array of {len} values at offset {} runs past the end of the save",
+            self.pos
+        );
+        (0..len).map(|_| self.reg()).collect()
+    }
+
+    fn metadata(&mut self) -> anyhow::Result<SaveMetadata> {
+        let name = self.string()?;
+        let version = self.u32_be()?;
+        anyhow::ensure!(
+            (MIN_VERSION..=MAX_VERSION).contains(&version),
+            "Unsupported save format version {version}"
+        );
+        let game_version = self.string()?;
+        let date = self.u32_le()?;
+        let time = self.u32_le()?;
+        let (game_object_offset, script0_size) = if version >= 22 {
+            (self.u16_le()?, self.u16_le()?)
+        } else {
+            (0, 0)
+        };
+        let play_time = if version >= 26 { self.u32_le()? } else { 0 };
+        Ok(SaveMetadata {
+            name,
+            version,
+            game_version,
+            date,
+            time,
+            game_object_offset,
+            script0_size,
+            play_time,
+        })
+    }
+
+    /// Skips the thumbnail, if there is one. Its header gives its size,
+    /// counting the header.
+    fn skip_thumbnail(&mut self) -> anyhow::Result<()> {
+        let Some(tag) = self.data.get(self.pos..self.pos + 4) else {
+            return Ok(());
+        };
+        if !THUMBNAIL_TAGS.iter().any(|t| t.as_slice() == tag) {
+            return Ok(());
+        }
+        let start = self.pos;
+        self.skip(4)?;
+        let size = self.u32_be()? as usize;
+        anyhow::ensure!(size >= 8, "Bad thumbnail size {size}");
+        self.pos = start;
+        self.skip(size)
+    }
+
+    fn engine_state(&mut self, version: u32) -> anyhow::Result<Vec<Option<Segment>>> {
+        if version <= 23 {
+            // The game version, again.
+            self.string()?;
+        }
+        // The picture window: its rectangle and its top and left.
+        self.skip(12)?;
+        if version <= 18 {
+            // Whether exports are wide.
+            self.skip(4)?;
+        }
+        let num_segments = self.u32_le()?;
+        let mut segments = Vec::new();
+        for _ in 0..num_segments {
+            segments.push(self.segment(version)?);
+        }
+        Ok(segments)
+    }
+
+    fn segment(&mut self, version: u32) -> anyhow::Result<Option<Segment>> {
+        let segment = match self.u32_le()? {
+            0 | 8 => None,
+            1 => Some(self.script(version)?),
+            2 => {
+                self.table(version, |reader| reader.object())?;
+                Some(Segment::Clones)
+            }
+            3 => Some(Segment::Locals {
+                script: self.u16_from_i32("script number")?,
+                values: self.regs()?,
+            }),
+            4 => {
+                // Only the stack's capacity is saved.
+                self.skip(4)?;
+                Some(Segment::Stack)
+            }
+            5 => {
+                // Old system strings: four names, sizes and values.
+                for _ in 0..4 {
+                    self.string()?;
+                    self.skip(4)?;
+                    self.string()?;
+                }
+                None
+            }
+            6 => {
+                self.table(version, |reader| reader.skip(8))?;
+                Some(Segment::Lists)
+            }
+            7 => {
+                self.table(version, |reader| reader.skip(16))?;
+                Some(Segment::Nodes)
+            }
+            9 => {
+                let size = self.u32_le()? as usize;
+                let description = self.string()?;
+                self.skip(size)?;
+                Some(Segment::DynMem { description })
+            }
+            kind => anyhow::bail!(
+                "Unsupported segment type {kind} at offset {} (saves of SCI32 games can't be read)",
+                self.pos - 4
+            ),
+        };
+        Ok(segment)
+    }
+
+    fn script(&mut self, version: u32) -> anyhow::Result<Segment> {
+        let number = self.u16_from_i32("script number")?;
+        if version <= 22 {
+            // The buffer, script and heap sizes.
+            self.skip(12)?;
+        }
+        if version <= 19 {
+            // The numbers of exports and synonyms.
+            self.skip(8)?;
+        }
+        // Lockers.
+        self.skip(4)?;
+        let num_objects = self.u32_le()?;
+        for _ in 0..num_objects {
+            self.object()?;
+        }
+        if version <= 20 {
+            // The offset of the locals.
+            self.skip(4)?;
+        }
+        let locals_segment = self.u16_from_i32("segment number")?;
+        // Whether the script is marked as deleted.
+        self.skip(4)?;
+        Ok(Segment::Script {
+            number,
+            locals_segment,
+        })
+    }
+
+    /// Skips an object: whether it's freed, its position, its method count
+    /// and its variables.
+    fn object(&mut self) -> anyhow::Result<()> {
+        self.skip(12)?;
+        self.regs()?;
+        Ok(())
+    }
+
+    /// Skips a table of clones, lists or nodes. From version 37, each entry
+    /// says whether it has data; before then every entry has.
+    fn table(
+        &mut self,
+        version: u32,
+        mut entry: impl FnMut(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        // The first free entry and the number used.
+        self.skip(8)?;
+        let len = self.u32_le()?;
+        for _ in 0..len {
+            // The next free entry.
+            self.skip(4)?;
+            let has_data = version < 37 || self.u8()? != 0;
+            if has_data {
+                entry(self)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes saves in ScummVM's layout, for the tests.
+    #[derive(Default)]
+    struct SaveWriter(Vec<u8>);
+
+    impl SaveWriter {
+        fn string(&mut self, text: &str) -> &mut Self {
+            self.0.extend_from_slice(text.as_bytes());
+            self.0.push(0);
+            self
+        }
+
+        fn u8(&mut self, value: u8) -> &mut Self {
+            self.0.push(value);
+            self
+        }
+
+        fn u16(&mut self, value: u16) -> &mut Self {
+            self.0.extend_from_slice(&value.to_le_bytes());
+            self
+        }
+
+        fn u32(&mut self, value: u32) -> &mut Self {
+            self.0.extend_from_slice(&value.to_le_bytes());
+            self
+        }
+
+        fn regs(&mut self, values: &[(u16, u16)]) -> &mut Self {
+            self.u32(values.len() as u32);
+            for &(segment, offset) in values {
+                self.u16(segment).u16(offset);
+            }
+            self
+        }
+
+        fn object(&mut self, offset: u16, variables: &[(u16, u16)]) -> &mut Self {
+            self.u32(0).u16(1).u16(offset).u32(2).regs(variables)
+        }
+    }
+
+    const GLOBALS: [(u16, u16); 4] = [(0, 0), (0, 110), (2, 0x0010), (0, 3)];
+
+    /// A version 46 save of an SCI1.1 game with a thumbnail, with script 0
+    /// in segment 2 and its globals in segment 3, followed by a list, a node,
+    /// a hunk and a dynamic memory segment.
+    fn fixture() -> Vec<u8> {
+        let mut save = SaveWriter::default();
+        save.string("In the kitchen")
+            .0
+            .extend_from_slice(&46u32.to_be_bytes());
+        save.string("1.000.000")
+            .u32(15 << 24 | 7 << 16 | 2024)
+            .u32(21 << 16 | 5 << 8 | 9)
+            .u16(0x0032)
+            .u16(0x1a2b)
+            .u32(3725);
+        // A 2x1 thumbnail with a version 2 header.
+        save.0.extend_from_slice(b"THMB");
+        save.0.extend_from_slice(&(8 + 14 + 4u32).to_be_bytes());
+        save.u8(2).u16(2).u16(1).u8(2);
+        save.0.extend_from_slice(&[0; 8]);
+        save.0.extend_from_slice(&[0xff; 4]);
+        // The picture window.
+        save.u16(10).u16(0).u16(200).u16(320).u16(10).u16(0);
+        save.u32(8);
+        // Segment 0 is always free.
+        save.u32(0);
+        // The stack.
+        save.u32(4).u32(0x1000);
+        // Script 0, with one object.
+        save.u32(1).u32(0).u32(1).u32(1);
+        save.object(0x0032, &[(0, 0x1234), (0, 5)]);
+        save.u32(3).u32(0);
+        // Script 0's locals.
+        save.u32(3).u32(0).regs(&GLOBALS);
+        // A list with one entry and one free entry.
+        save.u32(6).u32(1).u32(1).u32(2);
+        save.u32(0).u8(1).u16(5).u16(0).u16(5).u16(0);
+        save.u32(-1i32 as u32).u8(0);
+        // A node.
+        save.u32(7).u32(1).u32(1).u32(1);
+        save.u32(0).u8(1);
+        for _ in 0..4 {
+            save.u16(0).u16(0);
+        }
+        // A hunk segment, which isn't saved.
+        save.u32(8);
+        // A little dynamic memory.
+        save.u32(9).u32(3).string("savegame dir").u8(1).u8(2).u8(3);
+        save.0
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let save = ScummVmSave::read(&fixture()).unwrap();
+        let metadata = &save.metadata;
+        assert_eq!(metadata.name, "In the kitchen");
+        assert_eq!(metadata.version, 46);
+        assert_eq!(metadata.game_version, "1.000.000");
+        assert_eq!(metadata.ymd(), (2024, 7, 15));
+        assert_eq!(metadata.hms(), (21, 5, 9));
+        assert_eq!(metadata.game_object_offset, 0x0032);
+        assert_eq!(metadata.script0_size, 0x1a2b);
+        assert_eq!(metadata.play_time, 3725);
+    }
+
+    #[test]
+    fn test_read_segments() {
+        let save = ScummVmSave::read(&fixture()).unwrap();
+        assert_eq!(
+            save.segments,
+            vec![
+                None,
+                Some(Segment::Stack),
+                Some(Segment::Script {
+                    number: 0,
+                    locals_segment: 3
+                }),
+                Some(Segment::Locals {
+                    script: 0,
+                    values: GLOBALS
+                        .iter()
+                        .map(|&(segment, offset)| Reg { segment, offset })
+                        .collect()
+                }),
+                Some(Segment::Lists),
+                Some(Segment::Nodes),
+                None,
+                Some(Segment::DynMem {
+                    description: "savegame dir".into()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_globals() {
+        let save = ScummVmSave::read(&fixture()).unwrap();
+        let globals = save.globals().unwrap();
+        assert_eq!(globals.len(), 4);
+        assert_eq!(globals[1].as_number(), Some(110));
+        assert_eq!(globals[2].as_number(), None);
+        assert_eq!(globals[2].to_string(), "0002:0010");
+        assert_eq!(globals[3].to_string(), "3");
+    }
+
+    #[test]
+    fn test_read_without_thumbnail() {
+        let mut save = SaveWriter::default();
+        save.string("Old").0.extend_from_slice(&25u32.to_be_bytes());
+        save.string("1.0").u32(0).u32(0).u16(0).u16(0);
+        save.u16(0).u16(0).u16(0).u16(0).u16(0).u16(0);
+        save.u32(3).u32(0);
+        save.u32(1).u32(0).u32(1).u32(0).u32(2).u32(0);
+        save.u32(3).u32(0).regs(&[(0, 42)]);
+        let save = ScummVmSave::read(&save.0).unwrap();
+        assert_eq!(save.metadata.play_time, 0);
+        assert_eq!(
+            save.globals().unwrap(),
+            [Reg {
+                segment: 0,
+                offset: 42
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reject_unsupported() {
+        let mut save = SaveWriter::default();
+        save.string("New").0.extend_from_slice(&99u32.to_be_bytes());
+        let err = ScummVmSave::read(&save.0).unwrap_err();
+        assert!(err.to_string().contains("version 99"), "{err}");
+
+        let mut data = fixture();
+        data.truncate(data.len() - 40);
+        let err = ScummVmSave::read(&data).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+    }
+
+    #[test]
+    fn test_missing_globals() {
+        let mut save = SaveWriter::default();
+        save.string("Empty")
+            .0
+            .extend_from_slice(&46u32.to_be_bytes());
+        save.string("1.0").u32(0).u32(0).u16(0).u16(0).u32(0);
+        save.u16(0).u16(0).u16(0).u16(0).u16(0).u16(0);
+        save.u32(1).u32(0);
+        let save = ScummVmSave::read(&save.0).unwrap();
+        let err = save.globals().unwrap_err();
+        assert!(err.to_string().contains("no script 0"), "{err}");
+    }
+}
@@ -0,0 +1,297 @@
+//! The strings in a script's heap, which hold much of a game's interface text
+//! (menus, prompts, error messages) that isn't in its message resources.
+//!
+//! Strings are read straight from the heap resource, without loading the
+//! script, so no selector table is needed. Code and objects refer to strings
+//! by their offsets, so an edited string must fit in the space of the
+//! original; the rest of that space is filled with nulls.
+
+use sci_utils::encoding::CodePage;
+
+const OBJECT_MAGIC: u16 = 0x1234;
+
+/// A string in a heap resource.
+#[derive(Debug, Clone)]
+pub struct HeapString {
+    /// The offset of the string in the heap resource.
+    pub offset: usize,
+    /// The number of bytes the string takes up, not counting its null
+    /// terminator.
+    pub len: usize,
+    pub text: String,
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow::anyhow!("Heap is truncated at offset {offset}"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Where things are in a heap resource.
+struct HeapLayout {
+    /// The offset and size (in words) of each object.
+    objects: Vec<(usize, usize)>,
+    /// The range that holds strings: from the end of the objects to the
+    /// relocation table.
+    strings: std::ops::Range<usize>,
+}
+
+fn heap_layout(heap: &[u8]) -> anyhow::Result<HeapLayout> {
+    let relocations_offset = usize::from(read_u16_le(heap, 0)?);
+    anyhow::ensure!(
+        relocations_offset <= heap.len(),
+        "Heap relocation table is past the end of the heap"
+    );
+    let num_locals = usize::from(read_u16_le(heap, 2)?);
+    let mut offset = 4 + num_locals * 2;
+    let mut objects = Vec::new();
+    loop {
+        match read_u16_le(heap, offset)? {
+            0 => break,
+            OBJECT_MAGIC => {
+                let object_size = usize::from(read_u16_le(heap, offset + 2)?);
+                anyhow::ensure!(object_size > 0, "Object at offset {offset} is empty");
+                objects.push((offset, object_size));
+                offset += object_size * 2;
+            }
+            magic => anyhow::bail!("Bad object magic {magic:#06x} at offset {offset}"),
+        }
+    }
+    // Skip the terminator of the object list.
+    let start = offset + 2;
+    anyhow::ensure!(
+        start <= relocations_offset,
+        "Heap objects overlap the relocation table"
+    );
+    Ok(HeapLayout {
+        objects,
+        strings: start..relocations_offset,
+    })
+}
+
+/// Lists the non-empty strings in a heap resource.
+pub fn heap_strings(heap: &[u8], code_page: CodePage) -> anyhow::Result<Vec<HeapString>> {
+    let area = heap_layout(heap)?.strings;
+    let mut strings = Vec::new();
+    let mut offset = area.start;
+    while offset < area.end {
+        let len = heap[offset..area.end]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow::anyhow!("String at offset {offset} has no null terminator"))?;
+        if len > 0 {
+            strings.push(HeapString {
+                offset,
+                len,
+                text: code_page.decode(&heap[offset..offset + len]),
+            });
+        }
+        offset += len + 1;
+    }
+    Ok(strings)
+}
+
+/// A string in a heap resource, with the object that refers to it.
+#[derive(Debug, Clone)]
+pub struct OwnedHeapString {
+    pub string: HeapString,
+    /// The name of the first object with a property pointing at the string
+    /// (including its own name), if any. Strings that no object points at
+    /// are used by the script's code.
+    pub owner: Option<String>,
+}
+
+/// Lists the non-empty strings in a heap resource, with the objects that
+/// refer to them.
+pub fn owned_heap_strings(
+    heap: &[u8],
+    code_page: CodePage,
+) -> anyhow::Result<Vec<OwnedHeapString>> {
+    // The name is usually the property at index 8 (after the built-in
+    // "-objID-" through "-info-"), as in the script loader.
+    const NAME_INDEX: usize = 8;
+    let objects = heap_layout(heap)?.objects;
+    let strings = heap_strings(heap, code_page)?;
+    let read_string = |offset: usize| -> Option<String> {
+        let string = strings.iter().find(|string| string.offset == offset)?;
+        Some(string.text.clone())
+    };
+    // The properties of each object, with its name.
+    let objects = objects
+        .into_iter()
+        .map(|(offset, size)| {
+            let properties = (0..size)
+                .map(|index| read_u16_le(heap, offset + index * 2))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let name = properties
+                .get(NAME_INDEX)
+                .and_then(|&name| read_string(usize::from(name)))
+                .unwrap_or_else(|| format!("obj@{offset:04x}"));
+            Ok((name, properties))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(strings
+        .iter()
+        .map(|string| {
+            // Offsets past 0xFFFF can't be pointed at by a property.
+            let owner = u16::try_from(string.offset).ok().and_then(|offset| {
+                objects
+                    .iter()
+                    // Skip the magic and the size.
+                    .find(|(_, properties)| properties.iter().skip(2).any(|&value| value == offset))
+                    .map(|(name, _)| name.clone())
+            });
+            OwnedHeapString {
+                string: string.clone(),
+                owner,
+            }
+        })
+        .collect())
+}
+
+/// Replaces the string at `offset` in a heap resource with `text`, which must
+/// fit in the original's bytes once encoded.
+pub fn replace_heap_string(
+    heap: &mut [u8],
+    offset: usize,
+    text: &str,
+    code_page: CodePage,
+) -> anyhow::Result<()> {
+    let string = heap_strings(heap, code_page)?
+        .into_iter()
+        .find(|string| string.offset == offset)
+        .ok_or_else(|| anyhow::anyhow!("No string starts at offset {offset}"))?;
+    let encoded = code_page.encode(text)?;
+    anyhow::ensure!(
+        !encoded.contains(&0),
+        "The new text can't contain null characters"
+    );
+    anyhow::ensure!(
+        encoded.len() <= string.len,
+        "The new text is {} bytes, but the string at offset {offset} only has room for {}",
+        encoded.len(),
+        string.len
+    );
+    let space = &mut heap[offset..offset + string.len];
+    space.fill(0);
+    space[..encoded.len()].copy_from_slice(&encoded);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: usize = 28;
+    const HELLO: usize = 32;
+    const BYE: usize = 39;
+
+    /// A heap with one local, one ten-word object named "Ego" whose last
+    /// property points at "Hello", and the strings "Ego", "Hello", "" and
+    /// "Bye" before an empty relocation table.
+    fn test_heap() -> Vec<u8> {
+        let mut properties = [0u16; 10];
+        properties[0] = OBJECT_MAGIC;
+        properties[1] = 10;
+        properties[8] = NAME as u16;
+        properties[9] = HELLO as u16;
+        let strings = b"Ego\0Hello\0\0Bye\0";
+        let relocations_offset = NAME + strings.len();
+
+        let mut heap = Vec::new();
+        heap.extend_from_slice(&(relocations_offset as u16).to_le_bytes());
+        // One local.
+        heap.extend_from_slice(&1u16.to_le_bytes());
+        heap.extend_from_slice(&0x5678u16.to_le_bytes());
+        for property in properties {
+            heap.extend_from_slice(&property.to_le_bytes());
+        }
+        // The end of the object list.
+        heap.extend_from_slice(&0u16.to_le_bytes());
+        assert_eq!(heap.len(), NAME);
+        heap.extend_from_slice(strings);
+        // No relocations.
+        heap.extend_from_slice(&0u16.to_le_bytes());
+        heap
+    }
+
+    fn texts(heap: &[u8]) -> Vec<(usize, usize, String)> {
+        heap_strings(heap, CodePage::Cp437)
+            .unwrap()
+            .into_iter()
+            .map(|string| (string.offset, string.len, string.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_string_area_follows_objects() {
+        let heap = test_heap();
+        assert_eq!(
+            texts(&heap),
+            [
+                (NAME, 3, "Ego".to_string()),
+                (HELLO, 5, "Hello".to_string()),
+                (BYE, 3, "Bye".to_string()),
+            ]
+        );
+        let owners: Vec<_> = owned_heap_strings(&heap, CodePage::Cp437)
+            .unwrap()
+            .into_iter()
+            .map(|string| string.owner)
+            .collect();
+        assert_eq!(
+            owners,
+            [Some("Ego".to_string()), Some("Ego".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn test_replace_equal_length() {
+        let mut heap = test_heap();
+        replace_heap_string(&mut heap, HELLO, "World", CodePage::Cp437).unwrap();
+        assert_eq!(&heap[HELLO..HELLO + 6], b"World\0");
+        assert_eq!(texts(&heap)[1], (HELLO, 5, "World".to_string()));
+    }
+
+    #[test]
+    fn test_replace_shorter_pads_with_nulls() {
+        let mut heap = test_heap();
+        let len = heap.len();
+        replace_heap_string(&mut heap, HELLO, "Hi", CodePage::Cp437).unwrap();
+        assert_eq!(heap.len(), len);
+        assert_eq!(&heap[HELLO..HELLO + 6], b"Hi\0\0\0\0");
+        // The strings after it don't move.
+        assert_eq!(texts(&heap)[2], (BYE, 3, "Bye".to_string()));
+    }
+
+    #[test]
+    fn test_replace_too_long() {
+        let mut heap = test_heap();
+        let original = heap.clone();
+        let err = replace_heap_string(&mut heap, HELLO, "Hello!", CodePage::Cp437).unwrap_err();
+        assert!(err.to_string().contains("only has room for 5"), "{err}");
+        assert_eq!(heap, original);
+    }
+
+    #[test]
+    fn test_replace_not_at_string_start() {
+        let mut heap = test_heap();
+        let err = replace_heap_string(&mut heap, HELLO + 1, "x", CodePage::Cp437).unwrap_err();
+        assert!(
+            err.to_string().contains("No string starts at offset"),
+            "{err}"
+        );
+        // The empty string between "Hello" and "Bye" isn't listed either.
+        assert!(replace_heap_string(&mut heap, BYE - 1, "", CodePage::Cp437).is_err());
+    }
+
+    #[test]
+    fn test_bad_object_magic() {
+        let mut heap = test_heap();
+        heap[6..8].copy_from_slice(&0x4321u16.to_le_bytes());
+        let err = heap_strings(&heap, CodePage::Cp437).unwrap_err();
+        assert!(err.to_string().contains("Bad object magic 0x4321"), "{err}");
+        assert!(replace_heap_string(&mut heap, HELLO, "World", CodePage::Cp437).is_err());
+    }
+}
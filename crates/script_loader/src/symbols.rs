@@ -0,0 +1,69 @@
+//! Names that users give to parts of the scripts, kept in a JSON file next to
+//! the game so they can be shared, and shown in disassembly and reports.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// The names given to one script.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptSymbols {
+    /// A name for the script itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Names of local variables, by index.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locals: BTreeMap<u16, String>,
+    /// Labels for code, by offset in the script. A label at the start of a
+    /// procedure names it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<u16, String>,
+    /// Notes on objects, by object name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub objects: BTreeMap<String, String>,
+}
+
+impl ScriptSymbols {
+    fn is_empty(&self) -> bool {
+        *self == ScriptSymbols::default()
+    }
+}
+
+/// A symbol file: the names given to each script, by script number.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SymbolFile {
+    #[serde(default)]
+    scripts: BTreeMap<u16, ScriptSymbols>,
+}
+
+impl SymbolFile {
+    /// Reads a symbol file, or starts an empty one if it doesn't exist yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Invalid symbol file {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SymbolFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn script(&self, script_num: u16) -> Option<&ScriptSymbols> {
+        self.scripts.get(&script_num)
+    }
+
+    /// Changes the names of a script, dropping its entry if none are left.
+    pub fn update_script(&mut self, script_num: u16, update: impl FnOnce(&mut ScriptSymbols)) {
+        let symbols = self.scripts.entry(script_num).or_default();
+        update(symbols);
+        if symbols.is_empty() {
+            self.scripts.remove(&script_num);
+        }
+    }
+}
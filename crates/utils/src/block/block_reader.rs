@@ -1,6 +1,9 @@
 use std::io;
 
-use crate::{buffer::BufferExt, data_reader::DataReader};
+use crate::{
+    buffer::BufferExt,
+    data_reader::{DataReader, Endian},
+};
 
 use super::{MemBlock, ReadError};
 
@@ -9,12 +12,23 @@ use super::{MemBlock, ReadError};
 pub struct BlockReader {
     curr_pos: usize,
     block: MemBlock,
+    endian: Endian,
 }
 
 impl BlockReader {
     /// Creates a new reader from the block.
     pub fn new(block: MemBlock) -> Self {
-        Self { curr_pos: 0, block }
+        Self::with_endian(block, Endian::Little)
+    }
+
+    /// Creates a new reader from the block, which reads values of unspecified
+    /// byte order (e.g. with [`DataReader::read_u16`]) in the given order.
+    pub fn with_endian(block: MemBlock, endian: Endian) -> Self {
+        Self {
+            curr_pos: 0,
+            block,
+            endian,
+        }
     }
 
     /// Returns the portion of the block that has not yet been read.
@@ -25,6 +39,10 @@ impl BlockReader {
 }
 
 impl DataReader for BlockReader {
+    fn endian(&self) -> Endian {
+        self.endian
+    }
+
     fn read_u8(&mut self) -> io::Result<u8> {
         let mut buf = [0; 1];
         self.block.read_at(self.curr_pos, &mut buf)?;
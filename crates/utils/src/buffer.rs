@@ -191,7 +191,7 @@ pub trait Buffer: Sized + Clone {
     fn split_values<T: FromFixedBytes>(self) -> anyhow::Result<Vec<T>> {
         let buf_size = self.size();
         let item_size: u64 = T::SIZE.try_into().unwrap();
-        assert!((buf_size % item_size) == 0);
+        assert!(buf_size.is_multiple_of(item_size));
         let (values, rest) = self.read_values::<T>((buf_size / item_size).try_into().unwrap())?;
         assert!(rest.is_empty());
         Ok(values)
@@ -0,0 +1,172 @@
+//! Checkpoint journals for long-running operations.
+//!
+//! A checkpoint records the keys of the work items that have completed, one
+//! per line. Each entry is flushed as soon as it is recorded, so an
+//! interrupted run can be resumed by skipping any item already in the
+//! journal.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+pub struct Checkpoint {
+    path: PathBuf,
+    completed: BTreeSet<String>,
+    journal: File,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint journal at the given path.
+    ///
+    /// If `resume` is true, any entries already in the journal are loaded and
+    /// new entries are appended. Otherwise the journal is started fresh.
+    pub fn open(path: &Path, resume: bool) -> io::Result<Self> {
+        let mut completed = BTreeSet::new();
+        let mut valid_len = 0;
+        if resume {
+            match std::fs::read(path) {
+                Ok(contents) => (completed, valid_len) = read_entries(&contents),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut journal = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(!resume)
+            .open(path)?;
+        if resume {
+            // If the previous run was interrupted mid-write, the last line may
+            // be partial. Drop it so the next entry is not merged into it.
+            journal.set_len(valid_len)?;
+        }
+        journal.seek(io::SeekFrom::End(0))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            completed,
+            journal,
+        })
+    }
+
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the item with the given key was already completed.
+    pub fn is_complete(&self, key: &str) -> bool {
+        self.completed.contains(key)
+    }
+
+    /// Returns the number of completed items.
+    pub fn num_complete(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Returns the keys of all completed items.
+    pub fn completed(&self) -> &BTreeSet<String> {
+        &self.completed
+    }
+
+    /// Records that the item with the given key has completed. The entry is
+    /// flushed to disk before returning.
+    pub fn mark_complete(&mut self, key: &str) -> io::Result<()> {
+        assert!(
+            !key.contains(['\n', '\r']),
+            "Checkpoint keys cannot contain newlines: {:?}",
+            key
+        );
+        if !self.completed.insert(key.to_string()) {
+            return Ok(());
+        }
+        writeln!(self.journal, "{}", key)?;
+        self.journal.flush()?;
+        self.journal.sync_data()?;
+        Ok(())
+    }
+
+    /// Marks the operation as finished, removing the journal.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.journal);
+        std::fs::remove_file(&self.path)
+    }
+}
+
+/// Parses the entries of a journal. Returns the set of entries, and the
+/// length of the journal up to the end of the last complete entry.
+fn read_entries(contents: &[u8]) -> (BTreeSet<String>, u64) {
+    let mut entries = BTreeSet::new();
+    // The final line is only complete if it was followed by a newline. If it
+    // wasn't, the write was interrupted, and the entry must be ignored.
+    let valid_len = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |pos| pos + 1);
+    for line in contents[..valid_len].split(|&b| b == b'\n') {
+        let Ok(line) = std::str::from_utf8(line) else {
+            continue;
+        };
+        let line = line.trim_end_matches('\r');
+        if !line.is_empty() {
+            entries.insert(line.to_string());
+        }
+    }
+    (entries, valid_len as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_keeps_entries() -> io::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("checkpoint");
+        {
+            let mut checkpoint = Checkpoint::open(&path, false)?;
+            checkpoint.mark_complete("a")?;
+            checkpoint.mark_complete("b")?;
+        }
+        let checkpoint = Checkpoint::open(&path, true)?;
+        assert!(checkpoint.is_complete("a"));
+        assert!(checkpoint.is_complete("b"));
+        assert!(!checkpoint.is_complete("c"));
+
+        let checkpoint = Checkpoint::open(&path, false)?;
+        assert_eq!(checkpoint.num_complete(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_line_ignored() -> io::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("checkpoint");
+        std::fs::write(&path, b"a\nb\npart")?;
+        {
+            let mut checkpoint = Checkpoint::open(&path, true)?;
+            assert_eq!(
+                checkpoint.completed(),
+                &BTreeSet::from(["a".to_string(), "b".to_string()])
+            );
+            checkpoint.mark_complete("c")?;
+        }
+        assert_eq!(std::fs::read(&path)?, b"a\nb\nc\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_finish_removes_journal() -> io::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("checkpoint");
+        let mut checkpoint = Checkpoint::open(&path, false)?;
+        checkpoint.mark_complete("a")?;
+        checkpoint.finish()?;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -1,17 +1,16 @@
-use std::io;
+use std::{io, sync::LazyLock};
 
 use bitter::BitReader;
 
-use crate::block::MemBlock;
+use crate::{block::MemBlock, pool::BufferPool};
 
-use super::huffman::{ASCII_TREE, DISTANCE_TREE, LENGTH_TREE};
+use super::huffman::{ASCII_TREE, DISTANCE_TREE, HuffmanCode, LENGTH_TREE};
 
 pub fn decompress_dcl(input: &MemBlock) -> io::Result<MemBlock> {
     // This follows the implementation from ScummVM, in DecompressorDCL::unpack()
-    let input_data = input.read_all()?;
-    let input_size = input_data.len();
-    let mut reader = bitter::LittleEndianReader::new(&input_data);
-    let mut output = Vec::with_capacity(input_size.checked_mul(2).unwrap());
+    let input_size = input.len();
+    let mut reader = bitter::LittleEndianReader::new(input);
+    let mut output = BufferPool::global().take(input_size.checked_mul(2).unwrap());
     let Some(mode) = reader.read_u8() else {
         return Err(io::Error::other("Failed to read DCL mode"));
     };
@@ -114,5 +113,206 @@ pub fn decompress_dcl(input: &MemBlock) -> io::Result<MemBlock> {
         }
     }
 
-    Ok(MemBlock::from_vec(output))
+    Ok(output.into_block())
+}
+
+/// The dictionary size code used when compressing: a 4096-byte dictionary.
+const COMPRESS_DICT_TYPE: u32 = 6;
+const COMPRESS_DICT_SIZE: usize = 1 << (COMPRESS_DICT_TYPE + 6);
+/// The longest match a token can copy. A length of 519 ends the stream.
+const MAX_MATCH_LEN: usize = 518;
+/// How many earlier positions with the same prefix are searched for a match.
+const MAX_CHAIN: usize = 32;
+
+static LENGTH_CODES: LazyLock<Vec<Option<HuffmanCode>>> = LazyLock::new(|| LENGTH_TREE.codes());
+static DISTANCE_CODES: LazyLock<Vec<Option<HuffmanCode>>> = LazyLock::new(|| DISTANCE_TREE.codes());
+
+/// Writes bits in the order `bitter::LittleEndianReader` reads them.
+struct BitWriter {
+    output: Vec<u8>,
+    acc: u64,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, bits: u32, len: u32) {
+        self.acc |= u64::from(bits & ((1u64 << len) - 1) as u32) << self.num_bits;
+        self.num_bits += len;
+        while self.num_bits >= 8 {
+            self.output.push(self.acc as u8);
+            self.acc >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn write_code(&mut self, code: HuffmanCode) {
+        self.write_bits(code.bits, code.len);
+    }
+
+    fn write_length(&mut self, length: usize) {
+        let length = length as u32;
+        let (length_code, extra) = if length < 10 {
+            (length - 2, None)
+        } else {
+            let num_bits = (length - 8).ilog2();
+            (num_bits + 7, Some((length - 8 - (1 << num_bits), num_bits)))
+        };
+        self.write_code(
+            LENGTH_CODES[length_code as usize].expect("All length codes are in the tree"),
+        );
+        if let Some((extra, num_bits)) = extra {
+            self.write_bits(extra, num_bits);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.output.push(self.acc as u8);
+        }
+        self.output
+    }
+}
+
+fn prefix_hash(data: &[u8]) -> usize {
+    ((usize::from(data[0]) << 8) ^ (usize::from(data[1]) << 4) ^ usize::from(data[2])) & 0xFFF
+}
+
+/// Finds earlier occurrences of the data at a position, through chains of
+/// positions with the same 3-byte prefix hash.
+struct MatchFinder<'a> {
+    input: &'a [u8],
+    /// The most recent position with each hash.
+    head: Vec<usize>,
+    /// For each position, the previous one with the same hash.
+    prev: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        MatchFinder {
+            input,
+            head: vec![usize::MAX; 0x1000],
+            prev: vec![usize::MAX; input.len()],
+        }
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + 3 <= self.input.len() {
+            let hash = prefix_hash(&self.input[pos..]);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// The longest match for the data at `pos`, as its length and offset.
+    fn longest_match(&self, pos: usize) -> (usize, usize) {
+        let max_len = MAX_MATCH_LEN.min(self.input.len() - pos);
+        let mut best = (0, 0);
+        if pos + 3 > self.input.len() {
+            return best;
+        }
+        let mut candidate = self.head[prefix_hash(&self.input[pos..])];
+        let mut chain = 0;
+        while candidate != usize::MAX && chain < MAX_CHAIN {
+            let offset = pos - candidate;
+            if offset >= COMPRESS_DICT_SIZE {
+                break;
+            }
+            let len = self.input[candidate..]
+                .iter()
+                .zip(&self.input[pos..pos + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best.0 {
+                best = (len, offset);
+            }
+            candidate = self.prev[candidate];
+            chain += 1;
+        }
+        best
+    }
+}
+
+/// Compresses data with DCL (PKWARE implode) in binary mode, so that
+/// [`decompress_dcl`] restores it.
+pub fn compress_dcl(input: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        output: Vec::with_capacity(input.len() / 2 + 4),
+        acc: 0,
+        num_bits: 0,
+    };
+    // Binary mode, so literals are stored as plain bytes.
+    writer.write_bits(0, 8);
+    writer.write_bits(COMPRESS_DICT_TYPE, 8);
+
+    let mut matches = MatchFinder::new(input);
+    let mut pos = 0;
+    while pos < input.len() {
+        let (len, offset) = matches.longest_match(pos);
+        // Two-byte matches only have room for short distances.
+        if len >= 3 || (len == 2 && offset <= 256) {
+            writer.write_bits(1, 1);
+            writer.write_length(len);
+            let distance = (offset - 1) as u32;
+            let (distance_code, extra_bits) = if len == 2 {
+                (distance >> 2, 2)
+            } else {
+                (distance >> COMPRESS_DICT_TYPE, COMPRESS_DICT_TYPE)
+            };
+            writer.write_code(
+                DISTANCE_CODES[distance_code as usize].expect("All distance codes are in the tree"),
+            );
+            writer.write_bits(distance, extra_bits);
+            for p in pos..pos + len {
+                matches.insert(p);
+            }
+            pos += len;
+        } else {
+            writer.write_bits(0, 1);
+            writer.write_bits(u32::from(input[pos]), 8);
+            matches.insert(pos);
+            pos += 1;
+        }
+    }
+    // The end of the stream is a token of length 519.
+    writer.write_bits(1, 1);
+    writer.write_length(519);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> io::Result<()> {
+        let compressed = compress_dcl(data);
+        let decompressed = decompress_dcl(&MemBlock::from_vec(compressed))?;
+        assert_eq!(&decompressed[..], data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> io::Result<()> {
+        round_trip(b"")?;
+        round_trip(b"a")?;
+        round_trip(b"abababababababab")?;
+        round_trip(b"xyQxy")?;
+        round_trip(&b"The quick brown fox. ".repeat(200))?;
+        round_trip(&vec![7; 5000])?;
+        // Pseudo-random data, with few matches.
+        let mut state = 12345u32;
+        let noise: Vec<u8> = (0..10000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        round_trip(&noise)
+    }
+
+    #[test]
+    fn test_compresses_repetitive_data() {
+        let data = b"Hello there. ".repeat(100);
+        assert!(compress_dcl(&data).len() < data.len() / 4);
+    }
 }
@@ -11,6 +11,44 @@ pub struct HuffmanTable<T> {
     entries: Vec<HuffmanTableEntry<T>>,
 }
 
+/// The code of a value in a Huffman table: its bits, in the order they are
+/// read from the root, with the first in the lowest bit.
+#[derive(Debug, Clone, Copy)]
+pub struct HuffmanCode {
+    pub bits: u32,
+    pub len: u32,
+}
+
+impl HuffmanTable<u8> {
+    /// The code for each value in the table, indexed by value.
+    pub fn codes(&self) -> Vec<Option<HuffmanCode>> {
+        let mut codes = vec![None; 256];
+        let mut stack = vec![(0, HuffmanCode { bits: 0, len: 0 })];
+        while let Some((pos, code)) = stack.pop() {
+            match &self.entries[pos] {
+                HuffmanTableEntry::Leaf(value) => codes[*value as usize] = Some(code),
+                HuffmanTableEntry::Branch(left, right) => {
+                    stack.push((
+                        *left,
+                        HuffmanCode {
+                            len: code.len + 1,
+                            ..code
+                        },
+                    ));
+                    stack.push((
+                        *right,
+                        HuffmanCode {
+                            bits: code.bits | (1 << code.len),
+                            len: code.len + 1,
+                        },
+                    ));
+                }
+            }
+        }
+        codes
+    }
+}
+
 impl<T> HuffmanTable<T> {
     pub fn lookup(&self, reader: &mut bitter::LittleEndianReader) -> io::Result<&T> {
         let mut pos = 0;
@@ -0,0 +1,200 @@
+//! The compression methods of SCI0 volumes: LZW (method 1) and Huffman
+//! (method 2).
+
+use std::io;
+
+use bitter::BitReader;
+
+use crate::{block::MemBlock, pool::BufferPool};
+
+/// Resets the LZW dictionary.
+const LZW_RESET: u64 = 0x100;
+/// Ends LZW data.
+const LZW_END: u64 = 0x101;
+/// The first code for a dictionary entry.
+const LZW_FIRST_ENTRY: usize = 0x102;
+/// The widest LZW code, in bits.
+const LZW_MAX_BITS: u32 = 12;
+
+fn truncated(method: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("{method} data ends early"),
+    )
+}
+
+pub fn decompress_lzw(input: &MemBlock, unpacked_size: usize) -> io::Result<MemBlock> {
+    // This follows the implementation from ScummVM, in
+    // DecompressorLZW::unpackLZW(). Codes are read from the lowest bit up,
+    // and widen as the dictionary fills.
+    let mut reader = bitter::LittleEndianReader::new(input);
+    let mut output = BufferPool::global().take(unpacked_size);
+    let mut num_bits = 9;
+    let mut next_entry = LZW_FIRST_ENTRY;
+    let mut last_entry = 0x1FF;
+    // Each entry's start in the output and its length, less the byte that
+    // followed it.
+    let mut entries = vec![(0usize, 0usize); 1 << LZW_MAX_BITS];
+    while output.len() < unpacked_size {
+        let code = reader.read_bits(num_bits).ok_or_else(|| truncated("LZW"))?;
+        match code {
+            LZW_END => break,
+            LZW_RESET => {
+                num_bits = 9;
+                next_entry = LZW_FIRST_ENTRY;
+                last_entry = 0x1FF;
+                continue;
+            }
+            _ => {}
+        }
+        let code = code as usize;
+        let start = output.len();
+        if code < 0x100 {
+            output.push(code as u8);
+        } else {
+            if code >= next_entry {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("LZW code {code:#x} isn't in the dictionary yet"),
+                ));
+            }
+            let (entry_start, entry_len) = entries[code];
+            // An entry may end with the byte being written, so copy a byte at
+            // a time.
+            for i in 0..=entry_len {
+                let byte = output[entry_start + i];
+                output.push(byte);
+            }
+        }
+        if next_entry > last_entry && num_bits < LZW_MAX_BITS {
+            num_bits += 1;
+            last_entry = (last_entry << 1) + 1;
+        }
+        if next_entry <= last_entry {
+            entries[next_entry] = (start, output.len() - start);
+            next_entry += 1;
+        }
+    }
+    if output.len() != unpacked_size {
+        return Err(truncated("LZW"));
+    }
+    Ok(output.into_block())
+}
+
+pub fn decompress_huffman(input: &MemBlock, unpacked_size: usize) -> io::Result<MemBlock> {
+    // This follows the implementation from ScummVM, in
+    // DecompressorHuffman::unpack(). The data starts with the node count and
+    // the terminator, then the tree: a pair of bytes per node, either a
+    // value and a zero, or the steps to the node's children (zero bits in
+    // the high nibble, one bits in the low). A one bit where the low nibble
+    // is zero is followed by a literal byte. Bits are read from the highest
+    // down.
+    let Some((&[num_nodes, terminator], rest)) = input.split_first_chunk::<2>() else {
+        return Err(truncated("Huffman"));
+    };
+    let terminator = u16::from(terminator) | 0x100;
+    let tree_size = usize::from(num_nodes) * 2;
+    if rest.len() < tree_size {
+        return Err(truncated("Huffman"));
+    }
+    let (nodes, data) = rest.split_at(tree_size);
+    let mut reader = bitter::BigEndianReader::new(data);
+    let mut output = BufferPool::global().take(unpacked_size);
+    while output.len() < unpacked_size {
+        let mut node = 0;
+        let value = loop {
+            let (Some(&value), Some(&children)) = (nodes.get(node * 2), nodes.get(node * 2 + 1))
+            else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Huffman node {node} is outside the tree"),
+                ));
+            };
+            if children == 0 {
+                break u16::from(value);
+            }
+            let step = if reader.read_bit().ok_or_else(|| truncated("Huffman"))? {
+                match children & 0x0F {
+                    0 => {
+                        let literal = reader.read_u8().ok_or_else(|| truncated("Huffman"))?;
+                        break u16::from(literal) | 0x100;
+                    }
+                    step => step,
+                }
+            } else {
+                children >> 4
+            };
+            node += usize::from(step);
+        };
+        if value == terminator {
+            break;
+        }
+        output.push(value as u8);
+    }
+    if output.len() != unpacked_size {
+        return Err(truncated("Huffman"));
+    }
+    Ok(output.into_block())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs 9-bit LZW codes, lowest bit first.
+    fn pack_codes(codes: &[u16]) -> MemBlock {
+        let mut bytes = Vec::new();
+        let (mut acc, mut len) = (0u32, 0);
+        for &code in codes {
+            acc |= u32::from(code) << len;
+            len += 9;
+            while len >= 8 {
+                bytes.push(acc as u8);
+                acc >>= 8;
+                len -= 8;
+            }
+        }
+        if len > 0 {
+            bytes.push(acc as u8);
+        }
+        MemBlock::from_vec(bytes)
+    }
+
+    #[test]
+    fn test_lzw() -> io::Result<()> {
+        // "a", "b", then the entry for "a" and the byte after it.
+        let data = pack_codes(&[0x61, 0x62, 0x102, 0x101]);
+        assert_eq!(&decompress_lzw(&data, 4)?[..], b"abab");
+        // An entry used as soon as it's made.
+        let data = pack_codes(&[0x61, 0x102, 0x101]);
+        assert_eq!(&decompress_lzw(&data, 3)?[..], b"aaa");
+        // A reset, then codes from the start of the dictionary again.
+        let data = pack_codes(&[0x61, 0x100, 0x62, 0x102, 0x101]);
+        assert_eq!(&decompress_lzw(&data, 4)?[..], b"abbb");
+
+        assert!(decompress_lzw(&pack_codes(&[0x61, 0x103]), 3).is_err());
+        assert!(decompress_lzw(&pack_codes(&[0x61, 0x101]), 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_huffman() -> io::Result<()> {
+        // Node 0: a zero bit leads to node 1 ('a'), a one bit to a literal.
+        // The bits are 0, 0, 1 + 'b', then 1 + the terminator.
+        let data = MemBlock::from_vec(vec![
+            2,
+            0xFF,
+            0x00,
+            0x10,
+            b'a',
+            0x00,
+            0b0010_1100,
+            0b0101_1111,
+            0b1111_0000,
+        ]);
+        assert_eq!(&decompress_huffman(&data, 3)?[..], b"aab");
+        assert!(decompress_huffman(&data, 4).is_err());
+        assert!(decompress_huffman(&MemBlock::from_vec(vec![2, 0xFF, 0x00]), 1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Declarative layouts for fixed-size binary structures.
+//!
+//! The [`data_layout!`](crate::data_layout!) macro declares a struct along with
+//! the code to read it field by field, in declaration order. The size of the
+//! struct is computed from its fields, and reading checks that enough data
+//! remains before reading anything, so parsers don't need to do their own
+//! offset arithmetic or bounds checks.
+//!
+//! Integer fields are read in the reader's byte order (see
+//! [`DataReader::endian`]), so the same layout can be used for ports that
+//! store values big-endian. Fields whose order is fixed by the format can be
+//! wrapped in [`Le`] or [`Be`].
+//!
+//! ```
+//! use sci_utils::{
+//!     block::{BlockReader, MemBlock},
+//!     data_layout::{DataField, DataLayout},
+//! };
+//!
+//! sci_utils::data_layout! {
+//!     #[derive(Debug)]
+//!     pub struct Header {
+//!         pub kind: u8,
+//!         pub length: u16,
+//!     }
+//! }
+//!
+//! let block = MemBlock::from_vec(vec![1, 0x34, 0x12]);
+//! let header = Header::read_from(BlockReader::new(block)).unwrap();
+//! assert_eq!(header.length, 0x1234);
+//! assert_eq!(Header::SIZE, 3);
+//! ```
+
+use std::io;
+
+use crate::data_reader::DataReader;
+
+/// A value with a fixed-size binary encoding.
+pub trait DataField: Sized {
+    /// The size of the encoded value in bytes.
+    const SIZE: usize;
+
+    /// Reads the value from the reader. Callers are expected to have checked
+    /// that at least [`Self::SIZE`] bytes remain.
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self>;
+}
+
+/// A struct declared with [`data_layout!`](crate::data_layout!).
+pub trait DataLayout: DataField {
+    /// The name of the struct, used in error messages.
+    const NAME: &'static str;
+
+    /// Reads the struct from the reader, returning an error if there isn't
+    /// enough data left for the whole struct.
+    fn read_from<R: DataReader>(mut reader: R) -> io::Result<Self> {
+        let remaining = reader.file_size()?.saturating_sub(reader.tell()?) as usize;
+        if remaining < Self::SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{} needs {} bytes, but only {} remain",
+                    Self::NAME,
+                    Self::SIZE,
+                    remaining
+                ),
+            ));
+        }
+        Self::read_field(&mut reader)
+    }
+}
+
+impl DataField for u8 {
+    const SIZE: usize = 1;
+
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+        reader.read_u8()
+    }
+}
+
+impl DataField for u16 {
+    const SIZE: usize = 2;
+
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+        reader.read_u16()
+    }
+}
+
+impl DataField for u32 {
+    const SIZE: usize = 4;
+
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+        reader.read_u32()
+    }
+}
+
+impl<const N: usize> DataField for [u8; N] {
+    const SIZE: usize = N;
+
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+        let mut buf = [0; N];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// A 24-bit unsigned integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct U24(u32);
+
+impl U24 {
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl DataField for U24 {
+    const SIZE: usize = 3;
+
+    fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+        Ok(U24(reader.read_u24()?))
+    }
+}
+
+/// A value that is always stored little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Le<T>(pub T);
+
+/// A value that is always stored big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Be<T>(pub T);
+
+macro_rules! impl_fixed_endian {
+    ($ty:ty, $size:expr, $wrap:expr, $read_le:ident, $read_be:ident) => {
+        impl DataField for Le<$ty> {
+            const SIZE: usize = $size;
+
+            fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+                Ok(Le($wrap(reader.$read_le()?)))
+            }
+        }
+
+        impl DataField for Be<$ty> {
+            const SIZE: usize = $size;
+
+            fn read_field<R: DataReader>(reader: &mut R) -> io::Result<Self> {
+                Ok(Be($wrap(reader.$read_be()?)))
+            }
+        }
+    };
+}
+
+impl_fixed_endian!(u16, 2, std::convert::identity, read_u16_le, read_u16_be);
+impl_fixed_endian!(U24, 3, U24, read_u24_le, read_u24_be);
+impl_fixed_endian!(u32, 4, std::convert::identity, read_u32_le, read_u32_be);
+
+/// Declares a struct with a fixed binary layout. Fields are read in
+/// declaration order, and each field type must implement
+/// [`DataField`](crate::data_layout::DataField). The struct implements
+/// [`DataLayout`](crate::data_layout::DataLayout), so it can be read with
+/// bounds checking, and can itself be used as a field of another layout.
+#[macro_export]
+macro_rules! data_layout {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::data_layout::DataField for $name {
+            const SIZE: usize = 0 $(+ <$ty as $crate::data_layout::DataField>::SIZE)*;
+
+            fn read_field<R: $crate::data_reader::DataReader>(
+                reader: &mut R,
+            ) -> ::std::io::Result<Self> {
+                $(
+                    let $field = <$ty as $crate::data_layout::DataField>::read_field(reader)?;
+                )*
+                Ok($name { $($field,)* })
+            }
+        }
+
+        impl $crate::data_layout::DataLayout for $name {
+            const NAME: &'static str = stringify!($name);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{BlockReader, MemBlock},
+        data_reader::Endian,
+    };
+
+    crate::data_layout! {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Inner {
+            a: u8,
+            b: U24,
+        }
+    }
+
+    crate::data_layout! {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Outer {
+            inner: Inner,
+            c: u16,
+            d: u32,
+            reserved: [u8; 2],
+        }
+    }
+
+    #[test]
+    fn test_size_is_sum_of_fields() {
+        assert_eq!(Inner::SIZE, 4);
+        assert_eq!(Outer::SIZE, 12);
+    }
+
+    #[test]
+    fn test_read_nested() -> io::Result<()> {
+        let block = MemBlock::from_vec(vec![
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C,
+        ]);
+        let mut reader = BlockReader::new(block);
+        let outer = Outer::read_from(&mut reader)?;
+        assert_eq!(
+            outer,
+            Outer {
+                inner: Inner {
+                    a: 0x01,
+                    b: U24(0x040302),
+                },
+                c: 0x0605,
+                d: 0x0A090807,
+                reserved: [0x0B, 0x0C],
+            }
+        );
+        assert_eq!(reader.tell()?, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_endian() -> io::Result<()> {
+        crate::data_layout! {
+            struct Mixed {
+                native: u16,
+                little: Le<u16>,
+                big: Be<u32>,
+            }
+        }
+
+        let data = vec![0x12, 0x34, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04];
+        let le = Mixed::read_from(BlockReader::new(MemBlock::from_vec(data.clone())))?;
+        assert_eq!(le.native, 0x3412);
+        assert_eq!(le.little, Le(0x3412));
+        assert_eq!(le.big, Be(0x01020304));
+
+        let be = Mixed::read_from(BlockReader::with_endian(
+            MemBlock::from_vec(data),
+            Endian::Big,
+        ))?;
+        assert_eq!(be.native, 0x1234);
+        assert_eq!(be.little, Le(0x3412));
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_data_is_error() {
+        let block = MemBlock::from_vec(vec![0x01, 0x02, 0x03]);
+        let err = Inner::read_from(BlockReader::new(block)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "Inner needs 4 bytes, but only 3 remain");
+    }
+}
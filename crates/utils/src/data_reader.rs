@@ -1,6 +1,20 @@
 use std::io::{self, Read, Seek};
 
-use super::block::{BlockReader, BlockSource};
+use super::{
+    block::{BlockReader, BlockSource},
+    data_layout::DataLayout,
+};
+
+/// The byte order of multi-byte values.
+///
+/// Most SCI games are little-endian, but some ports (e.g. Mac and Amiga)
+/// store some values big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
 
 pub trait DataReader {
     fn read_u8(&mut self) -> io::Result<u8>;
@@ -11,6 +25,53 @@ pub trait DataReader {
     fn seek_to(&mut self, offset: u32) -> io::Result<()>;
     fn tell(&mut self) -> io::Result<u32>;
     fn file_size(&mut self) -> io::Result<u32>;
+
+    /// The byte order used by [`DataReader::read_u16`] and friends.
+    fn endian(&self) -> Endian {
+        Endian::Little
+    }
+
+    fn read_u16_be(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn read_u24_be(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 3];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes([0, buf[0], buf[1], buf[2]]))
+    }
+
+    fn read_u32_be(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    /// Reads a u16 in the reader's byte order.
+    fn read_u16(&mut self) -> io::Result<u16> {
+        match self.endian() {
+            Endian::Little => self.read_u16_le(),
+            Endian::Big => self.read_u16_be(),
+        }
+    }
+
+    /// Reads a 24-bit value in the reader's byte order.
+    fn read_u24(&mut self) -> io::Result<u32> {
+        match self.endian() {
+            Endian::Little => self.read_u24_le(),
+            Endian::Big => self.read_u24_be(),
+        }
+    }
+
+    /// Reads a u32 in the reader's byte order.
+    fn read_u32(&mut self) -> io::Result<u32> {
+        match self.endian() {
+            Endian::Little => self.read_u32_le(),
+            Endian::Big => self.read_u32_be(),
+        }
+    }
 }
 
 impl<R> DataReader for &mut R
@@ -48,6 +109,10 @@ where
     fn file_size(&mut self) -> io::Result<u32> {
         (**self).file_size()
     }
+
+    fn endian(&self) -> Endian {
+        (**self).endian()
+    }
 }
 
 impl<T> DataReader for Box<T>
@@ -85,6 +150,10 @@ where
     fn file_size(&mut self) -> io::Result<u32> {
         (**self).file_size()
     }
+
+    fn endian(&self) -> Endian {
+        (**self).endian()
+    }
 }
 pub struct IoDataReader<R>(R);
 
@@ -136,6 +205,16 @@ impl<R: Read + Seek> DataReader for IoDataReader<R> {
 
 pub trait FromBlockSource: Sized {
     fn from_block_source(source: &BlockSource) -> io::Result<(Self, BlockSource)> {
+        if source.size() < Self::read_size() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Needed {} bytes, but only {} remain",
+                    Self::read_size(),
+                    source.size()
+                ),
+            ));
+        }
         let block = source.subblock(..Self::read_size() as u64).open()?;
         let header = Self::parse(BlockReader::new(block))?;
         let rest = source.subblock(Self::read_size() as u64..);
@@ -148,3 +227,16 @@ pub trait FromBlockSource: Sized {
     where
         R: DataReader;
 }
+
+impl<T: DataLayout> FromBlockSource for T {
+    fn read_size() -> usize {
+        T::SIZE
+    }
+
+    fn parse<R>(reader: R) -> io::Result<Self>
+    where
+        R: DataReader,
+    {
+        T::read_from(reader)
+    }
+}
@@ -101,7 +101,7 @@ pub fn hex_dump_to<W: std::io::Write>(
 
     let num_visible_bits = (max_offset.next_power_of_two() - 1).trailing_ones();
 
-    let num_offset_hex_chars = ((num_visible_bits + 3) / 4) as usize;
+    let num_offset_hex_chars = num_visible_bits.div_ceil(4) as usize;
 
     let mut remaining_data = data;
     let mut curr_offset = 0;
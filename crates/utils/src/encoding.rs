@@ -0,0 +1,195 @@
+//! Decoding of the legacy text encodings used by SCI games.
+//!
+//! The DOS versions store text in the code page of the language they were
+//! released in (CP437 for English, CP850 for most Western European
+//! languages, and so on), while the Mac ports use Mac OS Roman.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The characters for bytes 0x80 to 0xFF in Mac OS Roman.
+const MAC_ROMAN_HIGH: [char; 128] = [
+    'Ä', 'Å', 'Ç', 'É', 'Ñ', 'Ö', 'Ü', 'á', 'à', 'â', 'ä', 'ã', 'å', 'ç', 'é', 'è', //
+    'ê', 'ë', 'í', 'ì', 'î', 'ï', 'ñ', 'ó', 'ò', 'ô', 'ö', 'õ', 'ú', 'ù', 'û', 'ü', //
+    '†', '°', '¢', '£', '§', '•', '¶', 'ß', '®', '©', '™', '´', '¨', '≠', 'Æ', 'Ø', //
+    '∞', '±', '≤', '≥', '¥', 'µ', '∂', '∑', '∏', 'π', '∫', 'ª', 'º', 'Ω', 'æ', 'ø', //
+    '¿', '¡', '¬', '√', 'ƒ', '≈', '∆', '«', '»', '…', '\u{A0}', 'À', 'Ã', 'Õ', 'Œ', 'œ', //
+    '–', '—', '“', '”', '‘', '’', '÷', '◊', 'ÿ', 'Ÿ', '⁄', '€', '‹', '›', 'ﬁ', 'ﬂ', //
+    '‡', '·', '‚', '„', '‰', 'Â', 'Ê', 'Á', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', 'Ó', 'Ô', //
+    '\u{F8FF}', 'Ò', 'Ú', 'Û', 'Ù', 'ı', 'ˆ', '˜', '¯', '˘', '˙', '˚', '¸', '˝', '˛', 'ˇ', //
+];
+
+/// Decodes text in the Mac OS Roman encoding, used by the Mac ports.
+pub fn decode_mac_roman(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b < 0x80 {
+                b as char
+            } else {
+                MAC_ROMAN_HIGH[(b - 0x80) as usize]
+            }
+        })
+        .collect()
+}
+
+fn encode_mac_roman_char(ch: char) -> Option<u8> {
+    if ch.is_ascii() {
+        return Some(ch as u8);
+    }
+    MAC_ROMAN_HIGH
+        .iter()
+        .position(|&c| c == ch)
+        .map(|i| 0x80 + i as u8)
+}
+
+/// A text encoding used by a game's resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodePage {
+    /// DOS US English.
+    #[default]
+    Cp437,
+    /// DOS Western European.
+    Cp850,
+    /// DOS Central European.
+    Cp852,
+    /// DOS Cyrillic.
+    Cp866,
+    /// Mac OS Roman, used by the Mac ports.
+    MacRoman,
+}
+
+impl CodePage {
+    const ALL: [CodePage; 5] = [
+        CodePage::Cp437,
+        CodePage::Cp850,
+        CodePage::Cp852,
+        CodePage::Cp866,
+        CodePage::MacRoman,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CodePage::Cp437 => "cp437",
+            CodePage::Cp850 => "cp850",
+            CodePage::Cp852 => "cp852",
+            CodePage::Cp866 => "cp866",
+            CodePage::MacRoman => "mac-roman",
+        }
+    }
+
+    fn dos_tables(
+        self,
+    ) -> Option<(
+        &'static [char; 128],
+        &'static oem_cp::OEMCPHashMap<char, u8>,
+    )> {
+        use oem_cp::code_table::*;
+        match self {
+            CodePage::Cp437 => Some((&DECODING_TABLE_CP437, &ENCODING_TABLE_CP437)),
+            CodePage::Cp850 => Some((&DECODING_TABLE_CP850, &ENCODING_TABLE_CP850)),
+            CodePage::Cp852 => Some((&DECODING_TABLE_CP852, &ENCODING_TABLE_CP852)),
+            CodePage::Cp866 => Some((&DECODING_TABLE_CP866, &ENCODING_TABLE_CP866)),
+            CodePage::MacRoman => None,
+        }
+    }
+
+    /// Decodes text in this code page. Every byte maps to some character, so
+    /// this can't fail.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self.dos_tables() {
+            Some((decoding, _)) => oem_cp::decode_string_complete_table(bytes, decoding),
+            None => decode_mac_roman(bytes),
+        }
+    }
+
+    /// Encodes text in this code page, failing if any character can't be
+    /// represented.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, EncodeError> {
+        text.chars()
+            .map(|ch| {
+                let byte = match self.dos_tables() {
+                    Some((_, encoding)) => oem_cp::encode_char_checked(ch, encoding),
+                    None => encode_mac_roman_char(ch),
+                };
+                byte.ok_or(EncodeError {
+                    ch,
+                    code_page: self,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for CodePage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown code page {0:?}")]
+pub struct UnknownCodePage(String);
+
+impl FromStr for CodePage {
+    type Err = UnknownCodePage;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_ascii_lowercase().replace('_', "-");
+        CodePage::ALL
+            .into_iter()
+            .find(|code_page| {
+                let name = code_page.name();
+                normalized == name || name.strip_prefix("cp") == Some(normalized.as_str())
+            })
+            .ok_or_else(|| UnknownCodePage(s.to_string()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Character {ch:?} can't be represented in {code_page}")]
+pub struct EncodeError {
+    ch: char,
+    code_page: CodePage,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_mac_roman() {
+        assert_eq!(decode_mac_roman(b"plain"), "plain");
+        assert_eq!(decode_mac_roman(&[0xD2, b'H', b'i', 0xD3, 0xC9]), "“Hi”…");
+        assert_eq!(decode_mac_roman(&[0x8E, 0xFF]), "éˇ");
+    }
+
+    #[test]
+    fn test_code_page_round_trip() -> anyhow::Result<()> {
+        for (code_page, bytes, text) in [
+            (CodePage::Cp437, &b"Caf\x82 \x9B"[..], "Café ¢"),
+            (CodePage::Cp850, &b"\xB5 \x99"[..], "Á Ö"),
+            (CodePage::Cp852, &b"\xA5\x88"[..], "ął"),
+            (CodePage::Cp866, &b"\x8F\xE0\xA8"[..], "При"),
+            (CodePage::MacRoman, &b"\xD2\x8E\xD3"[..], "“é”"),
+        ] {
+            assert_eq!(code_page.decode(bytes), text);
+            assert_eq!(code_page.encode(text)?, bytes);
+        }
+        assert!(CodePage::Cp437.encode("ł").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_code_page() {
+        assert_eq!("cp850".parse::<CodePage>().ok(), Some(CodePage::Cp850));
+        assert_eq!("852".parse::<CodePage>().ok(), Some(CodePage::Cp852));
+        assert_eq!(
+            "Mac_Roman".parse::<CodePage>().ok(),
+            Some(CodePage::MacRoman)
+        );
+        assert!("latin1".parse::<CodePage>().is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! Filesystem helpers for writing output files robustly.
+//!
+//! Output files often go into a game directory that an emulator (DOSBox,
+//! ScummVM) has open, and on Windows those files are locked while in use.
+//! Files are first written to a temporary file next to the target, then moved
+//! into place, retrying if the target is locked. If it stays locked, the file
+//! can be written to a staging directory instead, to be copied over later.
+
+use std::{
+    fs::File,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The maximum length of a path on Windows, unless the extended-length
+/// (`\\?\`) form is used.
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriteError {
+    #[error("File {0:?} is locked by another process")]
+    Locked(PathBuf),
+    #[error("File {0:?} already exists")]
+    AlreadyExists(PathBuf),
+    #[error("Failed to write {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl WriteError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        WriteError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+/// Returns true if the error indicates that the file is in use by another
+/// process.
+pub fn is_file_locked(err: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION.
+    if cfg!(windows) && matches!(err.raw_os_error(), Some(32 | 33)) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::ResourceBusy
+}
+
+/// Converts a path to a form that is not subject to the Windows path length
+/// limit. On other platforms, the path is returned unchanged.
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    if !cfg!(windows) {
+        return Ok(path.to_path_buf());
+    }
+    let path = std::path::absolute(path)?;
+    let Some(path_str) = path.to_str() else {
+        return Ok(path);
+    };
+    Ok(PathBuf::from(verbatim_path(path_str)))
+}
+
+/// Converts an absolute Windows path string to its extended-length form, if
+/// it is too long to be used otherwise.
+fn verbatim_path(path: &str) -> String {
+    if path.len() < WINDOWS_MAX_PATH || path.starts_with(VERBATIM_PREFIX) {
+        return path.to_string();
+    }
+    // Extended-length paths are not normalized, so they must use backslashes.
+    let path = path.replace('/', r"\");
+    match path.strip_prefix(r"\\") {
+        Some(unc) => format!("{}{}", VERBATIM_UNC_PREFIX, unc),
+        None => format!("{}{}", VERBATIM_PREFIX, path),
+    }
+}
+
+/// Asks the user whether to retry writing a locked file. Returns false without
+/// asking if stdin is not a terminal.
+pub fn ask_retry_locked(path: &Path) -> bool {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return false;
+    }
+    eprint!(
+        "{} is in use by another program (is the game running?). Close it and retry? [y/N] ",
+        path.display()
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if stdin.read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+pub struct WriteOptions<'a> {
+    /// Replace the target if it already exists.
+    pub overwrite: bool,
+    /// How many times to retry when the target is locked.
+    pub lock_retries: u32,
+    /// The delay between retries.
+    pub retry_delay: Duration,
+    /// Where to write the file if the target is still locked after retrying.
+    pub staging_dir: Option<PathBuf>,
+    /// Called when the target is still locked after retrying. If it returns
+    /// true, the write is attempted again.
+    pub on_locked: Option<&'a (dyn Fn(&Path) -> bool + Sync)>,
+    /// The modification time to give the file, instead of the time it was
+    /// written. See [`reproducible_mtime`].
+    pub modified: Option<SystemTime>,
+}
+
+impl Default for WriteOptions<'_> {
+    fn default() -> Self {
+        WriteOptions {
+            overwrite: true,
+            lock_retries: 5,
+            retry_delay: Duration::from_millis(200),
+            staging_dir: None,
+            on_locked: None,
+            modified: None,
+        }
+    }
+}
+
+/// Where a file ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Written {
+    /// The file was written to the requested path.
+    Target(PathBuf),
+    /// The target was locked, so the file was written to the staging
+    /// directory instead.
+    Staged(PathBuf),
+}
+
+impl Written {
+    pub fn path(&self) -> &Path {
+        match self {
+            Written::Target(path) | Written::Staged(path) => path,
+        }
+    }
+}
+
+/// A file that is being written. The contents are not visible at the target
+/// path until [`PendingFile::commit`] is called. If dropped without being
+/// committed, the partial file is removed.
+pub struct PendingFile {
+    target: PathBuf,
+    temp: tempfile::NamedTempFile,
+}
+
+impl PendingFile {
+    pub fn create(target: &Path) -> Result<Self, WriteError> {
+        let target = long_path(target).map_err(|e| WriteError::io(target, e))?;
+        let parent = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let temp = tempfile::Builder::new()
+            .prefix(".partial-")
+            .tempfile_in(parent)
+            .map_err(|e| WriteError::io(&target, e))?;
+        Ok(Self { target, temp })
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        self.temp.as_file_mut()
+    }
+
+    /// Moves the file into place.
+    pub fn commit(mut self, options: &WriteOptions) -> Result<Written, WriteError> {
+        if let Some(modified) = options.modified {
+            self.temp
+                .as_file()
+                .set_modified(modified)
+                .map_err(|e| WriteError::io(&self.target, e))?;
+        }
+        self.temp
+            .as_file_mut()
+            .sync_all()
+            .map_err(|e| WriteError::io(&self.target, e))?;
+        let mut temp = self.temp;
+        loop {
+            let mut retries_left = options.lock_retries;
+            loop {
+                let result = if options.overwrite {
+                    temp.persist(&self.target)
+                } else {
+                    temp.persist_noclobber(&self.target)
+                };
+                let err = match result {
+                    Ok(_) => return Ok(Written::Target(self.target)),
+                    Err(err) => err,
+                };
+                temp = err.file;
+                if !options.overwrite && err.error.kind() == io::ErrorKind::AlreadyExists {
+                    return Err(WriteError::AlreadyExists(self.target));
+                }
+                if !is_file_locked(&err.error) {
+                    return Err(WriteError::io(&self.target, err.error));
+                }
+                if retries_left == 0 {
+                    break;
+                }
+                retries_left -= 1;
+                std::thread::sleep(options.retry_delay);
+            }
+
+            if let Some(on_locked) = options.on_locked
+                && on_locked(&self.target)
+            {
+                continue;
+            }
+            break;
+        }
+
+        let Some(staging_dir) = &options.staging_dir else {
+            return Err(WriteError::Locked(self.target));
+        };
+        let file_name = self
+            .target
+            .file_name()
+            .expect("Target path must have a file name");
+        let staged =
+            long_path(&staging_dir.join(file_name)).map_err(|e| WriteError::io(&self.target, e))?;
+        std::fs::create_dir_all(staging_dir).map_err(|e| WriteError::io(staging_dir, e))?;
+        // The staging directory may be on another filesystem, so copy rather
+        // than rename.
+        std::fs::copy(temp.path(), &staged).map_err(|e| WriteError::io(&staged, e))?;
+        if let Some(modified) = options.modified {
+            set_modified(&staged, modified).map_err(|e| WriteError::io(&staged, e))?;
+        }
+        Ok(Written::Staged(staged))
+    }
+}
+
+/// The modification time to give output files so that builds are
+/// reproducible: `SOURCE_DATE_EPOCH` if it is set, and the Unix epoch
+/// otherwise.
+pub fn reproducible_mtime() -> SystemTime {
+    parse_source_date_epoch(std::env::var("SOURCE_DATE_EPOCH").ok().as_deref())
+}
+
+fn parse_source_date_epoch(value: Option<&str>) -> SystemTime {
+    let secs = value.and_then(|value| value.trim().parse::<u64>().ok());
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs.unwrap_or(0))
+}
+
+/// Sets the modification time of an existing file.
+pub fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(modified)
+}
+
+/// Sets the modification time of every file under a directory, skipping
+/// files that are locked by another process.
+pub fn set_modified_recursive(dir: &Path, modified: SystemTime) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            set_modified_recursive(&path, modified)?;
+            continue;
+        }
+        match set_modified(&path, modified) {
+            Err(err) if is_file_locked(&err) => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file with the given contents.
+pub fn write_file(
+    path: &Path,
+    contents: &[u8],
+    options: &WriteOptions,
+) -> Result<Written, WriteError> {
+    let mut file = PendingFile::create(path)?;
+    file.file()
+        .write_all(contents)
+        .map_err(|e| WriteError::io(path, e))?;
+    file.commit(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbatim_path() {
+        assert_eq!(verbatim_path(r"C:\short\path"), r"C:\short\path");
+
+        let long_dir = "a".repeat(WINDOWS_MAX_PATH);
+        assert_eq!(
+            verbatim_path(&format!(r"C:\{}/file", long_dir)),
+            format!(r"\\?\C:\{}\file", long_dir)
+        );
+        assert_eq!(
+            verbatim_path(&format!(r"\\server\share\{}", long_dir)),
+            format!(r"\\?\UNC\server\share\{}", long_dir)
+        );
+        let already = format!(r"\\?\C:\{}", long_dir);
+        assert_eq!(verbatim_path(&already), already);
+    }
+
+    #[test]
+    fn test_write_file_no_overwrite() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("100.SCR");
+        let options = WriteOptions {
+            overwrite: false,
+            ..WriteOptions::default()
+        };
+        assert_eq!(
+            write_file(&path, b"first", &options)?,
+            Written::Target(long_path(&path)?)
+        );
+        assert!(matches!(
+            write_file(&path, b"second", &options),
+            Err(WriteError::AlreadyExists(_))
+        ));
+        assert_eq!(std::fs::read(&path)?, b"first");
+        // The temporary file must not be left behind.
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_file_overwrite() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("100.SCR");
+        write_file(&path, b"first", &WriteOptions::default())?;
+        write_file(&path, b"second", &WriteOptions::default())?;
+        assert_eq!(std::fs::read(&path)?, b"second");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_file_modified() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("100.SCR");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let options = WriteOptions {
+            modified: Some(modified),
+            ..WriteOptions::default()
+        };
+        write_file(&path, b"contents", &options)?;
+        assert_eq!(std::fs::metadata(&path)?.modified()?, modified);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_source_date_epoch() {
+        assert_eq!(parse_source_date_epoch(None), SystemTime::UNIX_EPOCH);
+        assert_eq!(
+            parse_source_date_epoch(Some("1700000000")),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(
+            parse_source_date_epoch(Some("not a number")),
+            SystemTime::UNIX_EPOCH
+        );
+    }
+}
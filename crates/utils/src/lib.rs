@@ -1,10 +1,16 @@
 pub mod block;
 pub mod buffer;
+pub mod checkpoint;
 pub mod compression;
+pub mod data_layout;
 pub mod data_reader;
 pub mod data_writer;
 pub mod debug;
+pub mod encoding;
+pub mod fs;
 pub mod numbers;
+pub mod pool;
 pub mod reloc_buffer;
+pub mod rng;
 pub mod symbol;
 pub mod validation;
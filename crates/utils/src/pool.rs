@@ -0,0 +1,184 @@
+//! A pool of byte buffers, so that decompressing many resources reuses
+//! buffers instead of allocating a new one for each.
+//!
+//! A buffer goes back to its pool when it is dropped. Turned into a
+//! [`MemBlock`], that is once the last clone of the block is dropped.
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::block::MemBlock;
+
+/// How many free buffers the global pool keeps.
+const GLOBAL_MAX_FREE: usize = 32;
+
+/// Counters for tuning a pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers taken from the pool.
+    pub taken: u64,
+    /// Buffers taken that reused a free buffer, rather than allocating.
+    pub reused: u64,
+    /// Buffers given back and kept for reuse.
+    pub returned: u64,
+    /// Buffers given back while the pool was full, and freed.
+    pub discarded: u64,
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} buffers taken ({} reused), {} returned, {} discarded",
+            self.taken, self.reused, self.returned, self.discarded
+        )
+    }
+}
+
+struct PoolState {
+    free: Vec<Vec<u8>>,
+    max_free: usize,
+    stats: PoolStats,
+}
+
+#[derive(Clone)]
+pub struct BufferPool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl BufferPool {
+    /// Creates a pool that keeps at most `max_free` buffers for reuse.
+    pub fn new(max_free: usize) -> Self {
+        BufferPool {
+            state: Arc::new(Mutex::new(PoolState {
+                free: Vec::new(),
+                max_free,
+                stats: PoolStats::default(),
+            })),
+        }
+    }
+
+    /// The pool shared by the decompressors.
+    pub fn global() -> &'static BufferPool {
+        static GLOBAL: OnceLock<BufferPool> = OnceLock::new();
+        GLOBAL.get_or_init(|| BufferPool::new(GLOBAL_MAX_FREE))
+    }
+
+    /// Takes an empty buffer with at least the given capacity.
+    pub fn take(&self, capacity: usize) -> PooledBuffer {
+        let mut state = self.state.lock().unwrap();
+        state.stats.taken += 1;
+        // Prefer the largest free buffer, as it is the least likely to grow.
+        let buf = match state.free.pop() {
+            Some(mut buf) => {
+                state.stats.reused += 1;
+                buf.reserve(capacity);
+                buf
+            }
+            None => Vec::with_capacity(capacity),
+        };
+        PooledBuffer {
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.state.lock().unwrap().stats
+    }
+
+    fn give_back(&self, mut buf: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        if state.free.len() < state.max_free {
+            buf.clear();
+            let pos = state
+                .free
+                .partition_point(|free| free.capacity() < buf.capacity());
+            state.free.insert(pos, buf);
+            state.stats.returned += 1;
+        } else {
+            state.stats.discarded += 1;
+        }
+    }
+}
+
+/// A buffer that goes back to its pool when dropped.
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl PooledBuffer {
+    /// Turns the buffer into a block without copying it. The buffer goes
+    /// back to the pool once the block and all its clones are dropped.
+    pub fn into_block(self) -> MemBlock {
+        MemBlock::from_slice_owner(self)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::BufferExt;
+
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::new(1);
+        let mut buf = pool.take(16);
+        buf.extend_from_slice(b"hello");
+        let block = buf.into_block();
+        assert_eq!(&block[..], b"hello");
+        assert_eq!(pool.stats().returned, 0);
+        let sub_block = block.clone().sub_buffer(1..3);
+        drop(block);
+        assert_eq!(&sub_block[..], b"el");
+        drop(sub_block);
+        assert_eq!(pool.stats().returned, 1);
+
+        let buf = pool.take(8);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 16);
+        let other = pool.take(8);
+        drop(buf);
+        drop(other);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                taken: 3,
+                reused: 1,
+                returned: 2,
+                discarded: 1,
+            }
+        );
+    }
+}